@@ -0,0 +1,7 @@
+-- Add up migration script here
+create table references_hash (
+  integration text not null,
+  repository text not null,
+  hash blob not null,
+  primary key (integration, repository)
+);
@@ -0,0 +1,2 @@
+-- Add down migration script here
+drop table scan_history;
@@ -0,0 +1,12 @@
+-- Add up migration script here
+create table scan_history (
+  id integer primary key autoincrement,
+  integration text not null,
+  repository text not null,
+  revision text not null,
+  scan_id text not null,
+  clone_ms integer not null,
+  analyze_ms integer not null,
+  recorded_at integer not null
+);
+create index scan_history_repository on scan_history (integration, repository, id);
@@ -0,0 +1,11 @@
+-- Add up migration script here
+create table pending_upload (
+  scan_id text primary key not null,
+  integration text not null,
+  repository text not null,
+  revision text not null,
+  attempts integer not null,
+  first_failed_at integer not null,
+  next_attempt_at integer not null
+);
+create index pending_upload_due on pending_upload (integration, repository, next_attempt_at);
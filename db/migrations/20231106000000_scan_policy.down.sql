@@ -0,0 +1,2 @@
+-- Add down migration script here
+alter table scan_history drop column policy_status;
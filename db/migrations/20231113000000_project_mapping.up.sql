@@ -0,0 +1,8 @@
+-- Add up migration script here
+create table project_mapping (
+  integration text not null,
+  repository text not null,
+  project text not null,
+  title text,
+  primary key (integration, repository)
+);
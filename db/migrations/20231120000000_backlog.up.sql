@@ -0,0 +1,10 @@
+-- Add up migration script here
+create table backlog (
+  integration text not null,
+  repository text not null,
+  total integer not null,
+  completed integer not null,
+  started_at integer not null,
+  updated_at integer not null,
+  primary key (integration, repository)
+);
@@ -0,0 +1,12 @@
+-- Add up migration script here
+create table queued_job (
+  scan_id text primary key not null,
+  stage text not null,
+  integration text not null,
+  repository text not null,
+  revision text not null,
+  reference text not null,
+  repo_state blob not null,
+  is_branch integer not null,
+  enqueued_at integer not null
+);
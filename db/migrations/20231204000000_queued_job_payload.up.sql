@@ -0,0 +1,2 @@
+-- Add up migration script here
+alter table queued_job add column payload blob;
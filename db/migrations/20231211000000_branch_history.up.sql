@@ -0,0 +1,10 @@
+-- Add up migration script here
+create table branch_history (
+  id integer primary key autoincrement,
+  integration text not null,
+  repository text not null,
+  branch text not null,
+  repo_state blob not null,
+  recorded_at integer not null
+);
+create index branch_history_branch on branch_history (integration, repository, branch, id);
@@ -0,0 +1,7 @@
+-- Add up migration script here
+create table integration_poll (
+  integration text not null,
+  repository text not null,
+  polled_at integer not null,
+  primary key (integration, repository)
+);
@@ -0,0 +1,9 @@
+-- Add up migration script here
+create table scan_lease (
+  integration text not null,
+  repository text not null,
+  revision text not null,
+  holder text not null,
+  expires_at integer not null,
+  primary key (integration, repository, revision)
+);
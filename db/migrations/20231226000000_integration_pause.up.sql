@@ -0,0 +1,8 @@
+-- Add up migration script here
+-- Pausing every integration is recorded with an empty integration and repository.
+create table integration_pause (
+  integration text not null,
+  repository text not null,
+  paused_at integer not null,
+  primary key (integration, repository)
+);
@@ -0,0 +1,2 @@
+-- Add up migration script here
+alter table scan_history add column locator text;
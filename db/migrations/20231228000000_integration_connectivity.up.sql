@@ -0,0 +1,8 @@
+-- Add up migration script here
+create table integration_connectivity (
+  integration text not null,
+  repository text not null,
+  reachable integer not null,
+  checked_at integer not null,
+  primary key (integration, repository)
+);
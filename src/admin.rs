@@ -0,0 +1,398 @@
+//! The admin API, which lets a central dashboard manage Broker without access to the host it runs on.
+//!
+//! It only implements as much of HTTP as the API needs:
+//! each connection carries a single request, parameters are provided in the query,
+//! and every response is JSON.
+//! Every request must provide the configured token as a bearer token in the `Authorization` header.
+//!
+//! The routes themselves are provided by `broker run`, which serves the API while it runs.
+
+use std::{fmt::Display, future::Future, io, net::SocketAddr, time::Duration};
+
+use derive_new::new;
+use error_stack::{report, Report, ResultExt};
+use futures::{stream::FuturesUnordered, StreamExt};
+use getset::{CopyGetters, Getters};
+use serde_json::Value;
+use strum::Display;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    doc::code::{ErrorCode, HasErrorCode},
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        result::WrapErr,
+        secrecy::ComparableSecretString,
+        tokio::CancellationToken,
+    },
+};
+
+/// Tokens shorter than this are rejected, since they'd be easy to guess.
+const MIN_TOKEN_LENGTH: usize = 16;
+
+/// The largest request line and headers accepted; requests never need more than this.
+const MAX_REQUEST_SIZE: u64 = 16 * 1024;
+
+/// How long a client has to send its request before the connection is closed.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Errors encountered serving the admin API.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The admin API couldn't listen on its configured address.
+    #[error("listen on {0}")]
+    Bind(SocketAddr),
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Bind(..) => ErrorCode::new(4501),
+        }
+    }
+}
+
+/// Errors that are possibly surfaced during validation of config values.
+#[derive(Debug, thiserror::Error)]
+pub enum ValidationError {
+    /// The address isn't a valid socket address.
+    #[error("validate admin API address")]
+    Address,
+
+    /// The token is too short.
+    #[error("validate admin API token")]
+    Token,
+}
+
+/// Validated config values for the admin API.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct Config {
+    /// The address on which the API listens.
+    #[getset(get_copy = "pub")]
+    address: SocketAddr,
+
+    /// The token with which requests must be authenticated.
+    #[getset(get = "pub")]
+    token: ComparableSecretString,
+}
+
+impl Config {
+    /// Validate the admin API settings.
+    pub fn validate(address: String, token: String) -> Result<Self, Report<ValidationError>> {
+        let address = address
+            .parse::<SocketAddr>()
+            .context(ValidationError::Address)
+            .describe_lazy(|| format!("provided value: '{address}'"))
+            .help("provide an IP address and port, like '127.0.0.1:8321'")?;
+
+        // Tokens read from the keyring or the environment often end in a newline,
+        // and the tokens of requests are trimmed before they're compared.
+        let token = token.trim();
+        if token.len() < MIN_TOKEN_LENGTH {
+            return report!(ValidationError::Token)
+                .wrap_err()
+                .describe_lazy(|| format!("token is {} characters long", token.len()))
+                .help_lazy(|| {
+                    format!("provide a random token at least {MIN_TOKEN_LENGTH} characters long")
+                });
+        }
+        Ok(Self {
+            address,
+            token: ComparableSecretString::from(token),
+        })
+    }
+}
+
+/// A request received by the admin API.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct Request {
+    /// The HTTP method of the request.
+    method: String,
+
+    /// The path of the request, without its query.
+    path: String,
+
+    /// The query parameters of the request, in the order they were provided.
+    query: Vec<(String, String)>,
+}
+
+impl Request {
+    /// The value of the first query parameter with the provided name, if any.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(candidate, _)| candidate == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The status of a response from the admin API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum Status {
+    /// The request succeeded.
+    #[strum(serialize = "200 OK")]
+    Ok,
+
+    /// The request was accepted, and will be acted on later.
+    #[strum(serialize = "202 Accepted")]
+    Accepted,
+
+    /// The request is missing a parameter or has an invalid one.
+    #[strum(serialize = "400 Bad Request")]
+    BadRequest,
+
+    /// The request didn't provide the configured token.
+    #[strum(serialize = "401 Unauthorized")]
+    Unauthorized,
+
+    /// The route, or the integration it refers to, doesn't exist.
+    #[strum(serialize = "404 Not Found")]
+    NotFound,
+
+    /// The route exists, but not for the method of the request.
+    #[strum(serialize = "405 Method Not Allowed")]
+    MethodNotAllowed,
+
+    /// Broker failed to handle the request.
+    #[strum(serialize = "500 Internal Server Error")]
+    InternalServerError,
+}
+
+/// A response from the admin API.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, new)]
+pub struct Response {
+    /// The status of the response.
+    #[getset(get_copy = "pub")]
+    status: Status,
+
+    /// The body of the response.
+    #[getset(get = "pub")]
+    body: Value,
+}
+
+impl Response {
+    /// A successful response with the provided body.
+    pub fn ok(body: Value) -> Self {
+        Self::new(Status::Ok, body)
+    }
+
+    /// A failed response, describing why it failed.
+    pub fn error(status: Status, message: impl Display) -> Self {
+        Self::new(status, serde_json::json!({ "error": message.to_string() }))
+    }
+}
+
+/// The admin API, listening for connections.
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+    token: ComparableSecretString,
+}
+
+impl Server {
+    /// Listen on the configured address.
+    pub async fn bind(config: &Config) -> Result<Self, Report<Error>> {
+        let listener = TcpListener::bind(config.address())
+            .await
+            .context(Error::Bind(config.address()))
+            .help("ensure that the address is available and that nothing else is listening on the port")?;
+        Ok(Self {
+            listener,
+            token: config.token().clone(),
+        })
+    }
+
+    /// The address on which the server is listening.
+    pub fn address(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Handle requests with `handle` until `cancel` is cancelled.
+    ///
+    /// Requests are handled concurrently; failing to handle one is logged and doesn't stop the server.
+    pub async fn serve<F, Fut>(self, cancel: &CancellationToken, handle: F)
+    where
+        F: Fn(Request) -> Fut,
+        Fut: Future<Output = Response>,
+    {
+        if let Ok(address) = self.listener.local_addr() {
+            info!("Admin API listening on {address}");
+        }
+
+        let mut connections = FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => return,
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, peer)) => connections.push(self.connection(stream, peer, &handle)),
+                    Err(err) => warn!("Admin API: unable to accept connection: {err}"),
+                },
+                Some(()) = connections.next(), if !connections.is_empty() => {}
+            }
+        }
+    }
+
+    async fn connection<F, Fut>(&self, stream: TcpStream, peer: SocketAddr, handle: &F)
+    where
+        F: Fn(Request) -> Fut,
+        Fut: Future<Output = Response>,
+    {
+        if let Err(err) = self.exchange(stream, peer, handle).await {
+            debug!("Admin API: unable to handle request from {peer}: {err}");
+        }
+    }
+
+    async fn exchange<F, Fut>(
+        &self,
+        stream: TcpStream,
+        peer: SocketAddr,
+        handle: &F,
+    ) -> io::Result<()>
+    where
+        F: Fn(Request) -> Fut,
+        Fut: Future<Output = Response>,
+    {
+        let mut reader = BufReader::new(stream.take(MAX_REQUEST_SIZE));
+        let read = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut reader));
+        let Ok(read) = read.await else {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request timed out"));
+        };
+        let Some((request, token)) = read? else {
+            return Ok(());
+        };
+
+        let response = if token.map(ComparableSecretString::from).as_ref() == Some(&self.token) {
+            handle(request.clone()).await
+        } else {
+            Response::error(Status::Unauthorized, "provide the configured token")
+        };
+        info!(
+            "Admin API: {} {} from {peer} -> {}",
+            request.method,
+            request.path,
+            response.status()
+        );
+
+        let body = response.body().to_string();
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            response.status(),
+            body.len()
+        );
+        let mut stream = reader.into_inner().into_inner();
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(body.as_bytes()).await?;
+        stream.shutdown().await
+    }
+}
+
+/// Read the request and the bearer token it provided, if any.
+/// Returns `None` if the connection closed without a request.
+async fn read_request<R>(reader: &mut R) -> io::Result<Option<(Request, Option<String>)>>
+where
+    R: AsyncBufReadExt + Unpin,
+{
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+    let (method, target) = (method.to_string(), target.to_string());
+
+    let mut token = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            break;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("authorization") {
+                token = value
+                    .trim()
+                    .strip_prefix("Bearer ")
+                    .map(|token| token.trim().to_string());
+            }
+        }
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let request = Request {
+        method,
+        path: path.to_string(),
+        query: url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect(),
+    };
+    Ok(Some((request, token)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    #[test]
+    fn validates_config() {
+        let config = Config::validate(String::from("127.0.0.1:8321"), TOKEN.to_string())
+            .expect("must validate");
+        assert_eq!(config.address(), SocketAddr::from(([127, 0, 0, 1], 8321)));
+
+        assert!(Config::validate(String::from("localhost"), TOKEN.to_string()).is_err());
+        assert!(Config::validate(String::from("127.0.0.1:8321"), String::from("short")).is_err());
+    }
+
+    #[test]
+    fn trims_token() {
+        let config = Config::validate(String::from("127.0.0.1:8321"), format!("  {TOKEN}\n"))
+            .expect("must validate");
+        assert_eq!(config.token(), &ComparableSecretString::from(TOKEN));
+
+        let padded = format!("  {}\n", &TOKEN[..MIN_TOKEN_LENGTH - 1]);
+        assert!(Config::validate(String::from("127.0.0.1:8321"), padded).is_err());
+    }
+
+    #[tokio::test]
+    async fn requires_token() {
+        let config = Config::validate(String::from("127.0.0.1:0"), TOKEN.to_string())
+            .expect("must validate");
+        let server = Server::bind(&config).await.expect("must bind");
+        let url = format!("http://{}/v1/echo", server.address().expect("must listen"));
+
+        let cancel = CancellationToken::new();
+        let serving = server.serve(&cancel, |request| async move {
+            Response::ok(serde_json::json!({ "name": request.param("name") }))
+        });
+        let requests = async {
+            let client = reqwest::Client::new();
+            let unauthorized = client
+                .get(&url)
+                .bearer_auth("wrong")
+                .send()
+                .await
+                .expect("must send request");
+            let authorized = client
+                .get(format!("{url}?name=broker"))
+                .bearer_auth(TOKEN)
+                .send()
+                .await
+                .expect("must send request");
+            cancel.cancel();
+            (unauthorized.status(), authorized.text().await)
+        };
+        let ((unauthorized, authorized), ()) = tokio::join!(requests, serving);
+
+        assert_eq!(unauthorized, reqwest::StatusCode::UNAUTHORIZED);
+        assert_eq!(authorized.expect("must read body"), r#"{"name":"broker"}"#);
+    }
+}
@@ -1,31 +1,47 @@
 //! Interactions and data types for the FOSSA API live here.
 
-use std::fmt::Display;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    future::Future,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use delegate::delegate;
 use derive_more::{AsRef, Display, From};
 use derive_new::new;
 use error_stack::{report, Report, Result, ResultExt};
-use getset::Getters;
+use getset::{CopyGetters, Getters};
 use indoc::formatdoc;
-use reqwest::{header::CONTENT_TYPE, Client, ClientBuilder, RequestBuilder};
+use itertools::Itertools;
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE},
+    Body, RequestBuilder, StatusCode,
+};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use srclib::{Fetcher, Locator};
 use thiserror::Error;
+use tracing::{info, warn};
 use url::Url;
 
 use crate::{
-    api::remote::git,
+    debug::api_calls,
     ext::{
         error_stack::{DescribeContext, ErrorHelper, IntoContext},
-        result::{WrapErr, WrapOk},
+        result::{DiscardResult, WrapErr, WrapOk},
         secrecy::ComparableSecretString,
         tracing::span_record,
     },
     fossa_cli::{SourceUnits, Version},
 };
 
-use super::remote::{Integration, Reference};
+use super::{
+    http::client::Client,
+    remote::{BranchMapping, Contributors, Integration, Reference},
+};
+use crate::doc::code::{ErrorCode, HasErrorCode};
 
 /// Specify that this upload came from Broker.
 ///
@@ -52,10 +68,6 @@ pub enum Error {
         route: String,
     },
 
-    /// If initializing the client fails, this error occurs.
-    #[error("construct HTTP client")]
-    ConstructClient,
-
     /// If running a request fails, this error occurs.
     #[error("run HTTP request")]
     Request,
@@ -88,6 +100,30 @@ pub enum Error {
         error: String,
     },
 
+    /// Reading the debug bundle from disk failed.
+    #[error("read debug bundle at '{0}'")]
+    ReadDebugBundle(String),
+
+    /// Uploading the debug bundle failed.
+    #[error("upload debug bundle at '{0}'")]
+    UploadDebugBundle(String),
+
+    /// Uploading the recent contributors for a scan failed.
+    #[error("upload contributors for '{0}'")]
+    UploadContributors(String),
+
+    /// FOSSA failed to process an uploaded scan, so it couldn't be checked for issues.
+    #[error("FOSSA failed to process the uploaded scan: {0}")]
+    BuildFailed(String),
+
+    /// FOSSA didn't finish checking an uploaded scan for issues in time.
+    #[error("wait for FOSSA to check the uploaded scan for issues")]
+    CheckIssuesTimeout,
+
+    /// FOSSA didn't finish processing an uploaded scan in time to export its SBOM.
+    #[error("wait for FOSSA to export an SBOM of the uploaded scan")]
+    ExportSbomTimeout,
+
     /// If the FOSSA API rejects the request, report it.
     #[error(r#"the FOSSA API rejected the request\n{error}"#)]
     FossaApi {
@@ -96,6 +132,28 @@ pub enum Error {
     },
 }
 
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::LookupOrgId => ErrorCode::new(1001),
+            Self::ConstructUrl { .. } => ErrorCode::new(1002),
+            Self::Request => ErrorCode::new(1003),
+            Self::ReadResponse => ErrorCode::new(1004),
+            Self::ParseResponseBody(..) => ErrorCode::new(1005),
+            Self::EncodeRequestBody => ErrorCode::new(1006),
+            Self::UploadScan { .. } => ErrorCode::new(1007),
+            Self::ValidateUploadedScan { .. } => ErrorCode::new(1008),
+            Self::ReadDebugBundle(..) => ErrorCode::new(1009),
+            Self::UploadDebugBundle(..) => ErrorCode::new(1010),
+            Self::UploadContributors(..) => ErrorCode::new(1011),
+            Self::BuildFailed(..) => ErrorCode::new(1012),
+            Self::CheckIssuesTimeout => ErrorCode::new(1013),
+            Self::ExportSbomTimeout => ErrorCode::new(1014),
+            Self::FossaApi { .. } => ErrorCode::new(1015),
+        }
+    }
+}
+
 impl Error {
     fn construct_url(base: &Endpoint, route: &str) -> Self {
         Self::ConstructUrl {
@@ -116,6 +174,14 @@ impl Error {
         Self::UploadScan { metadata }
     }
 
+    fn read_debug_bundle(path: &Path) -> Self {
+        Self::ReadDebugBundle(path.display().to_string())
+    }
+
+    fn upload_debug_bundle(path: &Path) -> Self {
+        Self::UploadDebugBundle(path.display().to_string())
+    }
+
     fn fossa_api(err: ApiError) -> Self {
         let ApiError { name, message, .. } = err;
         let ApiError { code, uuid, .. } = err;
@@ -155,17 +221,568 @@ pub enum ValidationError {
     /// The value provided to parse is empty.
     #[error("provided value is empty")]
     ValueEmpty,
+
+    /// The maximum age for retrying failed uploads is not a valid duration.
+    #[error("validate upload retry max age")]
+    UploadRetryMaxAge,
+
+    /// The route to which scans are uploaded is not valid.
+    #[error("validate upload path")]
+    UploadPath,
+
+    /// An extra query parameter for uploads would replace one Broker sets itself.
+    #[error("validate upload query parameter '{0}'")]
+    UploadQuery(String),
+
+    /// The timeout for checking uploaded scans for issues is not a valid duration.
+    #[error("validate policy check timeout")]
+    PolicyCheckTimeout,
+
+    /// The directory from which CI build metadata is read must be written as an absolute path.
+    #[error("CI metadata location must be absolute")]
+    CiMetadataLocation,
+
+    /// The timeout for exporting SBOMs of uploaded scans is not a valid duration.
+    #[error("validate SBOM export timeout")]
+    SbomExportTimeout,
+
+    /// The name of an additional target is empty, reserved, or used by another target.
+    #[error("validate target name '{0}'")]
+    TargetName(String),
 }
 
-/// Validated config values for the FOSSA API.
-#[derive(Debug, Clone, PartialEq, Eq, Getters, new)]
+impl HasErrorCode for ValidationError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Endpoint => ErrorCode::new(1051),
+            Self::ApiKey => ErrorCode::new(1052),
+            Self::ValueEmpty => ErrorCode::new(1053),
+            Self::UploadRetryMaxAge => ErrorCode::new(1054),
+            Self::UploadPath => ErrorCode::new(1055),
+            Self::UploadQuery(..) => ErrorCode::new(1056),
+            Self::PolicyCheckTimeout => ErrorCode::new(1057),
+            Self::CiMetadataLocation => ErrorCode::new(1058),
+            Self::SbomExportTimeout => ErrorCode::new(1059),
+            Self::TargetName(..) => ErrorCode::new(1060),
+        }
+    }
+}
+
+/// Validated config values for retrying uploads which failed.
+///
+/// Scans which fail to upload are saved and retried on a schedule that backs off from minutes to hours,
+/// until they're older than the maximum age.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters, new)]
+#[getset(get_copy = "pub")]
+pub struct UploadRetry {
+    /// Uploads which first failed longer ago than this are no longer retried.
+    /// A maximum age of zero disables retrying.
+    max_age: Duration,
+}
+
+impl UploadRetry {
+    /// The default maximum age.
+    pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+    /// Whether failed uploads are retried.
+    pub fn enabled(&self) -> bool {
+        !self.max_age.is_zero()
+    }
+}
+
+impl Default for UploadRetry {
+    fn default() -> Self {
+        Self {
+            max_age: Self::DEFAULT_MAX_AGE,
+        }
+    }
+}
+
+impl TryFrom<String> for UploadRetry {
+    type Error = Report<ValidationError>;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        humantime::parse_duration(&value)
+            .context(ValidationError::UploadRetryMaxAge)
+            .describe_lazy(|| format!("provided value: '{value}'"))
+            .help("provide a duration like '3d' or '12h', or '0s' to disable retrying")
+            .map(Self::new)
+    }
+}
+
+/// Validated config values for checking uploaded scans for issues.
+///
+/// FOSSA checks uploaded scans for issues (like policy violations and vulnerabilities) in the background.
+/// When enabled, Broker waits for that check to complete after each upload, like `fossa test`,
+/// and reports scans in which issues were found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters, new)]
+#[getset(get_copy = "pub")]
+pub struct PolicyCheck {
+    /// Whether uploaded scans are checked.
+    enabled: bool,
+
+    /// How long to wait for FOSSA to finish checking a scan before giving up.
+    timeout: Duration,
+}
+
+impl PolicyCheck {
+    /// The default timeout.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+    /// Validate the config values for checking uploaded scans.
+    pub fn validate(
+        enabled: bool,
+        timeout: Option<String>,
+    ) -> std::result::Result<Self, Report<ValidationError>> {
+        let timeout = match timeout {
+            None => Self::DEFAULT_TIMEOUT,
+            Some(timeout) => humantime::parse_duration(&timeout)
+                .context(ValidationError::PolicyCheckTimeout)
+                .describe_lazy(|| format!("provided value: '{timeout}'"))
+                .help("provide a duration like '1h' or '30m'")?,
+        };
+        Self::new(enabled, timeout).wrap_ok()
+    }
+}
+
+impl Default for PolicyCheck {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+/// Validated config values for exporting SBOMs of uploaded scans.
+///
+/// Once FOSSA finishes processing each uploaded scan, Broker downloads an SBOM of it in each configured format,
+/// so that tooling on the Broker host can consume SBOMs directly.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, new)]
+pub struct SbomExport {
+    /// The formats in which SBOMs are exported; if empty, SBOMs aren't exported.
+    #[getset(get = "pub")]
+    formats: Vec<SbomFormat>,
+
+    /// How long to wait for FOSSA to process a scan before giving up on exporting its SBOMs.
+    #[getset(get_copy = "pub")]
+    timeout: Duration,
+}
+
+impl SbomExport {
+    /// The default timeout.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+    /// Validate the config values for exporting SBOMs.
+    pub fn validate(
+        formats: Vec<SbomFormat>,
+        timeout: Option<String>,
+    ) -> std::result::Result<Self, Report<ValidationError>> {
+        let timeout = match timeout {
+            None => Self::DEFAULT_TIMEOUT,
+            Some(timeout) => humantime::parse_duration(&timeout)
+                .context(ValidationError::SbomExportTimeout)
+                .describe_lazy(|| format!("provided value: '{timeout}'"))
+                .help("provide a duration like '1h' or '30m'")?,
+        };
+        let formats = formats.into_iter().unique().collect();
+        Self::new(formats, timeout).wrap_ok()
+    }
+
+    /// Whether SBOMs are exported.
+    pub fn enabled(&self) -> bool {
+        !self.formats.is_empty()
+    }
+}
+
+impl Default for SbomExport {
+    fn default() -> Self {
+        Self {
+            formats: Vec::new(),
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+/// The formats in which SBOMs can be exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, strum::Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum SbomFormat {
+    /// CycloneDX, encoded as JSON.
+    CyclonedxJson,
+
+    /// CycloneDX, encoded as XML.
+    CyclonedxXml,
+
+    /// SPDX, encoded as JSON.
+    SpdxJson,
+
+    /// SPDX, encoded as tag-value.
+    Spdx,
+}
+
+impl SbomFormat {
+    /// The extension of files containing SBOMs in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SbomFormat::CyclonedxJson => "cdx.json",
+            SbomFormat::CyclonedxXml => "cdx.xml",
+            SbomFormat::SpdxJson => "spdx.json",
+            SbomFormat::Spdx => "spdx",
+        }
+    }
+}
+
+/// Validated config values for reading metadata about CI builds.
+///
+/// CI systems deposit a JSON file describing the build of each commit into a directory,
+/// named after the commit (like `0e0a8d6b4f1d2a0ad11a4e6e3c3b64c2f62f4d14.json`).
+/// When Broker uploads a scan of that commit, it attaches the build's link to the uploaded revision,
+/// giving traceability from FOSSA back to the CI build.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Getters, new)]
+#[getset(get = "pub")]
+pub struct CiMetadata {
+    /// The directory from which metadata is read; if not set, no metadata is read.
+    location: Option<PathBuf>,
+}
+
+impl CiMetadata {
+    /// Validate the config values for reading CI build metadata.
+    pub fn validate(
+        location: Option<PathBuf>,
+    ) -> std::result::Result<Self, Report<ValidationError>> {
+        if let Some(location) = &location {
+            if !location.is_absolute() {
+                return report!(ValidationError::CiMetadataLocation)
+                    .wrap_err()
+                    .help("provide the absolute path to the directory")
+                    .describe_lazy(|| format!("provided path: '{}'", location.display()));
+            }
+        }
+        Self::new(location).wrap_ok()
+    }
+
+    /// Read the metadata CI deposited for the revision, if any.
+    ///
+    /// Metadata is informational, so metadata which can't be read or parsed is logged and otherwise ignored.
+    pub async fn read(&self, revision: &str) -> Option<BuildInfo> {
+        let location = self.location.as_ref()?;
+
+        // Revisions which couldn't name a file in the directory never have metadata.
+        let is_file_name = revision
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+        if revision.is_empty() || revision.starts_with('.') || !is_file_name {
+            return None;
+        }
+
+        let path = location.join(format!("{revision}.json"));
+        let content = match tokio::fs::read(&path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                warn!(path = %path.display(), %err, "Unable to read CI metadata, ignoring it");
+                return None;
+            }
+        };
+
+        serde_json::from_slice(&content)
+            .map_err(|err| {
+                warn!(path = %path.display(), %err, "Unable to parse CI metadata, ignoring it");
+            })
+            .ok()
+    }
+}
+
+/// Metadata about the CI build of a revision, deposited by CI.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Getters, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 #[getset(get = "pub")]
+pub struct BuildInfo {
+    /// A link to the build, attached to the uploaded revision in FOSSA.
+    link: Option<String>,
+
+    /// The identifier of the build, like its build number.
+    build: Option<String>,
+
+    /// The identifiers of the artifacts the build produced.
+    artifacts: Vec<String>,
+}
+
+/// The issues FOSSA found in an uploaded scan.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Issues {
+    /// The number of issues of each type, like `policy_conflict` or `vulnerability`.
+    counts: BTreeMap<String, usize>,
+
+    /// The number of issues of each severity, like `critical` or `high`.
+    /// Issues FOSSA doesn't assign a severity are counted as `unknown`.
+    severities: BTreeMap<String, usize>,
+}
+
+impl Issues {
+    /// The number of issues of each severity, like `critical` or `high`.
+    pub fn severities(&self) -> &BTreeMap<String, usize> {
+        &self.severities
+    }
+
+    /// The total number of issues.
+    pub fn count(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    /// Whether the scan passed, meaning that no issues were found.
+    pub fn passed(&self) -> bool {
+        self.count() == 0
+    }
+}
+
+impl Display for Issues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.passed() {
+            return write!(f, "no issues");
+        }
+
+        let counts = self
+            .counts
+            .iter()
+            .map(|(kind, count)| format!("{count} {kind}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "{} issue(s): {counts}", self.count())
+    }
+}
+
+/// Validated config values for the FOSSA API.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, new)]
 pub struct Config {
     /// The endpoint for the FOSSA backend.
+    #[getset(get = "pub")]
     endpoint: Endpoint,
 
     /// The key used when interacting with the FOSSA backend.
+    #[getset(get = "pub")]
     key: Key,
+
+    /// Where and how scans are uploaded.
+    #[getset(get = "pub")]
+    upload: Upload,
+
+    /// Whether to look for an existing project in FOSSA for each integration before creating one.
+    #[getset(get_copy = "pub")]
+    match_existing_projects: bool,
+
+    /// Whether to upload the people who recently committed to the code alongside each scan.
+    #[getset(get_copy = "pub")]
+    upload_contributors: bool,
+
+    /// The most scans uploaded to the endpoint per minute, across all integrations.
+    /// If not set, uploads to the endpoint aren't limited beyond the limit per integration.
+    #[getset(get_copy = "pub")]
+    max_uploads_per_minute: Option<NonZeroU32>,
+
+    /// Additional endpoints to which scans are uploaded, in the order they're tried.
+    #[getset(get = "pub")]
+    targets: Vec<Target>,
+
+    /// How scans are uploaded when additional endpoints are configured.
+    #[getset(get_copy = "pub")]
+    fan_out: FanOut,
+
+    /// How requests to the FOSSA API which fail transiently are retried.
+    #[getset(get_copy = "pub")]
+    retries: Retries,
+}
+
+impl Config {
+    /// The config for uploading to the additional target.
+    ///
+    /// Uploads to the target use its endpoint and key, and otherwise the same settings as the primary endpoint.
+    pub fn for_target(&self, target: &Target) -> Self {
+        Self {
+            endpoint: target.endpoint.clone(),
+            key: target.key.clone(),
+            max_uploads_per_minute: target.max_uploads_per_minute,
+            targets: Vec::new(),
+            ..self.clone()
+        }
+    }
+}
+
+/// How scans are uploaded when additional FOSSA endpoints are configured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FanOut {
+    /// Scans are uploaded to every endpoint.
+    ///
+    /// Additional endpoints receive each scan once the primary endpoint accepted it;
+    /// uploads to them which fail are reported, but not retried.
+    #[default]
+    All,
+
+    /// Scans are uploaded to the primary endpoint.
+    /// If that fails, the additional endpoints are tried in order until one accepts the scan.
+    PrimaryWithFallback,
+}
+
+/// How requests to the FOSSA API are retried when they fail transiently,
+/// like when the connection is reset or FOSSA responds that it's temporarily unavailable.
+///
+/// Retries back off exponentially, starting at one second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[getset(get_copy = "pub")]
+pub struct Retries {
+    /// How many times requests which are safe to repeat, like reading from FOSSA, are retried.
+    idempotent: u32,
+
+    /// How many times uploads of scans are retried.
+    ///
+    /// Before each retry, FOSSA is asked whether it already received the scan,
+    /// so that a scan whose upload succeeded but whose response was lost isn't uploaded twice.
+    uploads: u32,
+}
+
+impl Retries {
+    /// Retry idempotent requests and uploads up to the provided number of times.
+    pub fn new(idempotent: u32, uploads: u32) -> Self {
+        Self {
+            idempotent,
+            uploads,
+        }
+    }
+}
+
+impl Default for Retries {
+    fn default() -> Self {
+        Self::new(3, 2)
+    }
+}
+
+/// Validated config values for an additional FOSSA endpoint to which scans are uploaded.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, new)]
+pub struct Target {
+    /// The name of the target, used in logs, the audit log, and notifications.
+    #[getset(get = "pub")]
+    name: String,
+
+    /// The endpoint for the FOSSA backend.
+    #[getset(get = "pub")]
+    endpoint: Endpoint,
+
+    /// The key used when interacting with the FOSSA backend.
+    #[getset(get = "pub")]
+    key: Key,
+
+    /// The most scans uploaded to the endpoint per minute, across all integrations.
+    #[getset(get_copy = "pub")]
+    max_uploads_per_minute: Option<NonZeroU32>,
+}
+
+impl Target {
+    /// The name by which the endpoint set in `fossa_endpoint` is referred to.
+    pub const PRIMARY: &'static str = "primary";
+
+    /// Validate the names of the additional targets.
+    ///
+    /// Names must be set, and distinct from each other and from the primary endpoint.
+    pub fn validate_names(targets: &[Self]) -> std::result::Result<(), Report<ValidationError>> {
+        let mut seen = BTreeSet::new();
+        for target in targets {
+            let name = target.name.trim();
+            if name.is_empty() || name == Self::PRIMARY || !seen.insert(name) {
+                return report!(ValidationError::TargetName(target.name.clone()))
+                    .wrap_err()
+                    .help_lazy(|| {
+                        format!(
+                            "give each target a unique name other than '{}'",
+                            Self::PRIMARY
+                        )
+                    });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Validated config values for uploading scans.
+///
+/// The defaults work for FOSSA itself; these only need to change when FOSSA is
+/// reached through a reverse proxy which changes its routes or requires extra parameters.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, new)]
+#[getset(get = "pub")]
+pub struct Upload {
+    /// The route, relative to the endpoint, to which scans are uploaded.
+    path: String,
+
+    /// Extra query parameters added to each upload.
+    query: BTreeMap<String, String>,
+}
+
+impl Upload {
+    /// The default route to which scans are uploaded.
+    pub const DEFAULT_PATH: &'static str = "api/builds/custom";
+
+    /// The query parameters Broker sets on each upload itself, which can't be overridden.
+    pub const RESERVED_QUERY: [&'static str; 8] = [
+        "title",
+        "locator",
+        "cliVersion",
+        "managedBuild",
+        ANALYSIS_SOURCE_KEY,
+        "branch",
+        "team",
+        "link",
+    ];
+
+    /// Validate the route and extra query parameters for uploads.
+    pub fn validate(
+        path: Option<String>,
+        query: BTreeMap<String, String>,
+    ) -> std::result::Result<Self, Report<ValidationError>> {
+        let path = match path {
+            None => Self::DEFAULT_PATH.to_string(),
+            Some(path) if path.trim().is_empty() => {
+                return report!(ValidationError::ValueEmpty)
+                    .wrap_err()
+                    .help("remove the value to use the default path")
+                    .change_context(ValidationError::UploadPath);
+            }
+            Some(path) if Url::parse(&path).is_ok() => {
+                return report!(ValidationError::UploadPath)
+                    .wrap_err()
+                    .describe_lazy(|| format!("provided path: '{path}'"))
+                    .help("the path is relative to 'fossa_endpoint'; set the scheme and host there instead");
+            }
+            Some(path) => path,
+        };
+
+        if let Some(name) = query
+            .keys()
+            .find(|name| Self::RESERVED_QUERY.contains(&name.as_str()))
+        {
+            return report!(ValidationError::UploadQuery(name.clone()))
+                .wrap_err()
+                .help_lazy(|| {
+                    format!(
+                        "Broker sets these parameters itself: {}",
+                        Self::RESERVED_QUERY.join(", ")
+                    )
+                });
+        }
+
+        Self { path, query }.wrap_ok()
+    }
+}
+
+impl Default for Upload {
+    fn default() -> Self {
+        Self {
+            path: Self::DEFAULT_PATH.to_string(),
+            query: BTreeMap::new(),
+        }
+    }
 }
 
 /// The URL to the FOSSA endpoint.
@@ -231,25 +848,67 @@ pub struct OrgConfig {
 
     /// The ID of the organization to which the API key is registered.
     organization_id: usize,
+
+    /// How requests to the FOSSA API which fail transiently are retried.
+    retries: Retries,
 }
 
 impl OrgConfig {
     /// Lookup the organization for the provided config.
-    #[tracing::instrument]
-    pub async fn lookup(config: &Config) -> Result<Self, Error> {
+    #[tracing::instrument(skip(client))]
+    pub async fn lookup(client: &Client, config: &Config) -> Result<Self, Error> {
         let OrganizationInfo { organization_id } = config
             .endpoint()
-            .get::<OrganizationInfo>("/api/cli/organization", config.key())
+            .get::<OrganizationInfo>(
+                client,
+                "/api/cli/organization",
+                config.key(),
+                config.retries().idempotent(),
+            )
             .await
             .change_context(Error::LookupOrgId)?;
 
-        let Config { endpoint, key } = config.clone();
+        let Config {
+            endpoint,
+            key,
+            retries,
+            ..
+        } = config.clone();
         Ok(Self {
             endpoint,
             key,
             organization_id,
+            retries,
         })
     }
+
+    /// Look up the custom project with the provided name in the organization, if it exists.
+    #[tracing::instrument(skip(client))]
+    pub async fn find_project(
+        &self,
+        client: &Client,
+        name: &str,
+    ) -> Result<Option<ExistingProject>, Error> {
+        let locator = format!("custom+{}/{name}", self.organization_id);
+        let route = format!("api/cli/{}/project", encode_locator(&locator));
+        let url = self.endpoint.join(&route)?;
+        let req = client.get(url).bearer_auth(self.key.expose_secret());
+
+        run_optional_request::<ProjectResponse>(client, req, self.retries.idempotent())
+            .await
+            .map(|found| found.map(|project| ExistingProject::new(name.to_string(), project.title)))
+    }
+}
+
+/// A project which already exists in FOSSA.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, new)]
+#[getset(get = "pub")]
+pub struct ExistingProject {
+    /// The name of the project. Used to build the locator.
+    name: String,
+
+    /// The title of the project in the UI, if FOSSA reported one.
+    title: Option<String>,
 }
 
 /// The metadata for a project to upload.
@@ -271,29 +930,71 @@ pub struct ProjectMetadata {
 
     /// The team to which the project should be assigned, if any.
     team: Option<String>,
+
+    /// A link attached to the uploaded revision, like the CI build of the revision.
+    #[serde(default)]
+    link: Option<String>,
+
+    /// The commit the branch pointed at before it was rewritten, if it was rewritten since it was last scanned.
+    #[serde(default)]
+    rewritten_from: Option<String>,
 }
 
 impl ProjectMetadata {
     /// Create metadata from the project information.
     pub fn new(integration: &Integration, reference: &Reference) -> Self {
-        let name = integration.endpoint().to_string();
-        match reference {
-            Reference::Git(reference) => match reference {
-                git::Reference::Branch { name: branch, head } => Self {
-                    name,
-                    revision: head.to_string(),
-                    title: integration.title().to_owned(),
-                    branch: Some(branch.to_string()),
-                    team: integration.team().to_owned(),
-                },
-                git::Reference::Tag { name: tag, .. } => Self {
-                    name,
-                    revision: tag.to_string(),
-                    title: integration.title().to_owned(),
-                    branch: None,
-                    team: integration.team().to_owned(),
-                },
-            },
+        Self {
+            name: integration.protocol().project(),
+            revision: reference.revision().to_string(),
+            title: integration.title().to_owned(),
+            branch: reference.branch().map(ToString::to_string),
+            team: integration.team().to_owned(),
+            link: None,
+            rewritten_from: None,
+        }
+    }
+
+    /// Note that the branch was rewritten since it was last scanned, when it pointed at the provided commit.
+    pub fn with_rewritten_from(self, previous: String) -> Self {
+        Self {
+            rewritten_from: Some(previous),
+            ..self
+        }
+    }
+
+    /// Attach the provided link to the uploaded revision.
+    pub fn with_link(self, link: String) -> Self {
+        Self {
+            link: Some(link),
+            ..self
+        }
+    }
+
+    /// Upload to the provided project instead of the one named by the integration.
+    ///
+    /// The project's title, if known, replaces the title from the integration.
+    pub fn with_project(self, name: String, title: Option<String>) -> Self {
+        Self {
+            name,
+            title: title.or(self.title),
+            ..self
+        }
+    }
+
+    /// Upload to the project and branch named by the mapping, where it names them.
+    ///
+    /// If the mapping names a project, the title from the integration is dropped,
+    /// since it describes the integration's project rather than the mapped one.
+    pub fn with_mapping(self, mapping: &BranchMapping) -> Self {
+        let (name, title) = match mapping.project() {
+            Some(project) => (project.clone(), None),
+            None => (self.name, self.title),
+        };
+        Self {
+            name,
+            title,
+            branch: mapping.branch().clone().or(self.branch),
+            ..self
         }
     }
 }
@@ -303,8 +1004,12 @@ impl Display for ProjectMetadata {
         let name = &self.name;
         let revision = &self.revision;
         match &self.branch {
-            Some(branch) => write!(f, "{name}@{revision} ({branch})"),
-            None => write!(f, "{name}@{revision}"),
+            Some(branch) => write!(f, "{name}@{revision} ({branch})")?,
+            None => write!(f, "{name}@{revision}")?,
+        }
+        match &self.rewritten_from {
+            Some(previous) => write!(f, " (rewritten from {previous})"),
+            None => Ok(()),
         }
     }
 }
@@ -319,14 +1024,16 @@ pub struct CliMetadata {
 ///
 /// In the future we'd like to have this method be made available via trait so we can test.
 /// I ran out of time this time around though.
-#[tracing::instrument(skip(source_units))]
+#[tracing::instrument(skip(client, source_units))]
 pub async fn upload_scan(
+    client: &Client,
     opts: &Config,
     project: &ProjectMetadata,
     cli: &CliMetadata,
-    source_units: SourceUnits,
+    source_units: &SourceUnits,
 ) -> Result<Locator, Error> {
-    let url = opts.endpoint().join("api/builds/custom")?;
+    let upload = opts.upload();
+    let url = opts.endpoint().join(upload.path())?;
 
     let locator = Locator::builder()
         .fetcher(Fetcher::Custom)
@@ -358,31 +1065,272 @@ pub async fn upload_scan(
     if let Some(team) = &project.team {
         query.push(("team", team.to_string()));
     }
+    if let Some(link) = &project.link {
+        query.push(("link", link.to_string()));
+    }
+    let extra = upload
+        .query()
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.to_string()));
+    query.extend(extra);
 
-    let req = new_client()?
+    let req = client
         .post(url)
         .bearer_auth(opts.key().expose_secret())
         .query(&query)
         .header(CONTENT_TYPE, "application/json")
         .body(source_units.to_string());
 
-    run_request::<UploadResponse>(req)
+    let upload = |req: RequestBuilder, attempt: u32| {
+        let locator = &locator;
+        async move {
+            if attempt > 0 && already_uploaded(client, opts, locator).await {
+                info!(%locator, "FOSSA already received the scan, not uploading it again");
+                return Ok(locator.clone());
+            }
+            let (status, body) = execute_request(client, req).await?;
+            parse_response::<UploadResponse>(status, &body)?.into()
+        }
+    };
+    with_retries(req, opts.retries().uploads(), upload)
+        .await
+        .change_context_lazy(|| Error::upload_scan(&locator, source_units))
+}
+
+/// Whether FOSSA already received a scan for the locator.
+///
+/// If this can't be determined, the scan is assumed not to have been received, so it's uploaded again.
+async fn already_uploaded(client: &Client, opts: &Config, locator: &Locator) -> bool {
+    let encoded = encode_locator(&locator.to_string());
+    let Ok(url) = opts
+        .endpoint()
+        .join(&format!("api/cli/{encoded}/latest_build"))
+    else {
+        return false;
+    };
+    let req = client.get(url).bearer_auth(opts.key().expose_secret());
+    matches!(
+        run_optional_request::<BuildResponse>(client, req, 0).await,
+        Ok(Some(_))
+    )
+}
+
+/// Upload the people who recently committed to the code of an uploaded scan.
+///
+/// FOSSA counts contributors for licensing; this reports them the same way FOSSA CLI does
+/// for the projects it uploads itself.
+#[tracing::instrument(skip(client, contributors), fields(contributors = contributors.len()))]
+pub async fn upload_contributors(
+    client: &Client,
+    opts: &Config,
+    locator: &str,
+    contributors: &Contributors,
+) -> Result<(), Error> {
+    let url = opts.endpoint().join("api/contributors")?;
+    let body = ContributorsRequest {
+        default: contributors,
+    };
+    let body = serde_json::to_vec(&body).context(Error::EncodeRequestBody)?;
+
+    let req = client
+        .post(url)
+        .bearer_auth(opts.key().expose_secret())
+        .query(&[("locator", locator)])
+        .header(CONTENT_TYPE, "application/json")
+        .body(body);
+
+    run_request_discarding_body(client, req, opts.retries().idempotent())
         .await
-        .change_context_lazy(|| Error::upload_scan(&locator, &source_units))?
-        .into()
+        .change_context_lazy(|| Error::UploadContributors(locator.to_string()))
 }
 
+/// Upload a debug bundle to FOSSA Support.
+///
+/// Debug bundles can be very large, so the file is streamed from disk rather than read into memory.
+/// The returned reference ID identifies the bundle to FOSSA Support;
+/// users include it in their support request instead of attaching the bundle itself.
+#[tracing::instrument(skip(client))]
+pub async fn upload_debug_bundle(
+    client: &Client,
+    opts: &Config,
+    bundle: &Path,
+) -> Result<String, Error> {
+    let url = opts.endpoint().join(DEBUG_BUNDLE_ROUTE)?;
+
+    let file = tokio::fs::File::open(bundle)
+        .await
+        .context_lazy(|| Error::read_debug_bundle(bundle))?;
+    let size = file
+        .metadata()
+        .await
+        .context_lazy(|| Error::read_debug_bundle(bundle))?
+        .len();
+
+    let file_name = bundle
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let query = vec![
+        ("filename", file_name),
+        (ANALYSIS_SOURCE_KEY, ANALYSIS_SOURCE.to_string()),
+    ];
+
+    let req = client
+        .post(url)
+        .bearer_auth(opts.key().expose_secret())
+        .query(&query)
+        .header(CONTENT_TYPE, "application/gzip")
+        .header(CONTENT_LENGTH, size)
+        .body(Body::from(file));
+
+    // Uploading a bundle isn't idempotent: if the upload succeeded but its response was lost,
+    // retrying would upload the bundle again under a new reference ID. So it's never retried,
+    // and users run the command again instead.
+    run_request::<DebugBundleUploadResponse>(client, req, 0)
+        .await
+        .change_context_lazy(|| Error::upload_debug_bundle(bundle))
+        .map(|res| res.reference_id)
+}
+
+/// Wait for FOSSA to process the uploaded scan at the locator and check it for issues, like `fossa test`.
+///
+/// The locator is the one returned when the scan was uploaded, rendered to a string.
+/// Fails if FOSSA fails to process the scan, or doesn't finish checking it within the timeout.
+#[tracing::instrument(skip(client))]
+pub async fn check_issues(
+    client: &Client,
+    opts: &Config,
+    locator: &str,
+    timeout: Duration,
+) -> Result<Issues, Error> {
+    let encoded = encode_locator(locator);
+    let issues_route = format!("api/cli/{encoded}/issues");
+
+    let check = async {
+        wait_for_build(client, opts, &encoded).await?;
+        loop {
+            let issues = opts
+                .endpoint()
+                .get::<IssuesResponse>(
+                    client,
+                    &issues_route,
+                    opts.key(),
+                    opts.retries().idempotent(),
+                )
+                .await?;
+            if issues.status != IssuesStatus::Waiting {
+                return Issues::from(issues).wrap_ok();
+            }
+            tokio::time::sleep(BUILD_STATUS_PERIOD).await;
+        }
+    };
+
+    tokio::time::timeout(timeout, check)
+        .await
+        .context(Error::CheckIssuesTimeout)
+        .describe_lazy(|| format!("gave up after {}", humantime::format_duration(timeout)))
+        .help("FOSSA may be busy; increase 'policy_check.timeout' if this happens often")?
+}
+
+/// Look up the issues FOSSA found in the uploaded scan at the locator, without waiting for it to be checked.
+///
+/// The locator is the one returned when the scan was uploaded, rendered to a string.
+/// Returns `None` if FOSSA hasn't finished checking the scan for issues.
+#[tracing::instrument(skip(client))]
+pub async fn find_issues(
+    client: &Client,
+    opts: &Config,
+    locator: &str,
+) -> Result<Option<Issues>, Error> {
+    let route = format!("api/cli/{}/issues", encode_locator(locator));
+    let issues = opts
+        .endpoint()
+        .get::<IssuesResponse>(client, &route, opts.key(), opts.retries().idempotent())
+        .await?;
+    if issues.status == IssuesStatus::Waiting {
+        return Ok(None);
+    }
+    Ok(Some(Issues::from(issues)))
+}
+
+/// Wait for FOSSA to process the uploaded scan at the locator, then export an SBOM of it in the format.
+///
+/// The locator is the one returned when the scan was uploaded, rendered to a string.
+/// Fails if FOSSA fails to process the scan, or doesn't finish processing it within the timeout.
+#[tracing::instrument(skip(client))]
+pub async fn export_sbom(
+    client: &Client,
+    opts: &Config,
+    locator: &str,
+    format: SbomFormat,
+    timeout: Duration,
+) -> Result<Vec<u8>, Error> {
+    let encoded = encode_locator(locator);
+    let route = format!("api/revisions/{encoded}/attribution/{format}");
+
+    let export = async {
+        wait_for_build(client, opts, &encoded).await?;
+        let url = opts.endpoint().join(&route)?;
+        let req = client
+            .get(url)
+            .bearer_auth(opts.key().expose_secret())
+            .query(&[
+                ("includeDirectDependencies", "true"),
+                ("includeDeepDependencies", "true"),
+                ("includeHashAndVersionData", "true"),
+            ]);
+        run_request_raw(client, req, opts.retries().idempotent()).await
+    };
+
+    tokio::time::timeout(timeout, export)
+        .await
+        .context(Error::ExportSbomTimeout)
+        .describe_lazy(|| format!("gave up after {}", humantime::format_duration(timeout)))
+        .help("FOSSA may be busy; increase 'sbom_export.timeout' if this happens often")?
+}
+
+/// Wait for FOSSA to finish processing the uploaded scan at the encoded locator.
+async fn wait_for_build(client: &Client, opts: &Config, encoded: &str) -> Result<(), Error> {
+    let route = format!("api/cli/{encoded}/latest_build");
+    loop {
+        let build = opts
+            .endpoint()
+            .get::<BuildResponse>(client, &route, opts.key(), opts.retries().idempotent())
+            .await?;
+        match build.task.status {
+            BuildStatus::Succeeded => return Ok(()),
+            BuildStatus::Failed => {
+                let error = build.error.unwrap_or_else(|| String::from("unknown error"));
+                return report!(Error::BuildFailed(error)).wrap_err();
+            }
+            _ => tokio::time::sleep(BUILD_STATUS_PERIOD).await,
+        }
+    }
+}
+
+/// Encode a locator for use as a single segment of a route.
+fn encode_locator(locator: &str) -> String {
+    url::form_urlencoded::byte_serialize(locator.as_bytes()).collect()
+}
+
+/// How often to ask FOSSA whether it has finished processing or checking an uploaded scan.
+const BUILD_STATUS_PERIOD: Duration = Duration::from_secs(10);
+
 impl Endpoint {
     /// Make a GET request against the FOSSA server with the provided route,
-    /// which is joined to the base.
-    #[tracing::instrument]
-    async fn get<T: DeserializeOwned>(&self, route: &str, token: &Key) -> Result<T, Error> {
+    /// which is joined to the base, retrying it up to `retries` times if it fails transiently.
+    #[tracing::instrument(skip(client))]
+    async fn get<T: DeserializeOwned>(
+        &self,
+        client: &Client,
+        route: &str,
+        token: &Key,
+        retries: u32,
+    ) -> Result<T, Error> {
         let full_url = self.join(route)?;
-        let req = new_client()?
-            .get(full_url)
-            .bearer_auth(token.expose_secret());
+        let req = client.get(full_url).bearer_auth(token.expose_secret());
 
-        run_request(req).await
+        run_request(client, req, retries).await
     }
 
     /// Parse a string as an URL, with this URL as the base URL.
@@ -397,30 +1345,192 @@ impl Endpoint {
     }
 }
 
-fn new_client() -> Result<Client, Error> {
-    static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
-    ClientBuilder::new()
-        .user_agent(APP_USER_AGENT)
-        .build()
-        .context(Error::ConstructClient)
+/// Run the request and parse its response, retrying up to `retries` times if it fails transiently.
+#[tracing::instrument(skip_all, fields(url))]
+async fn run_request<T: DeserializeOwned>(
+    client: &Client,
+    req: RequestBuilder,
+    retries: u32,
+) -> Result<T, Error> {
+    with_retries(req, retries, |req, _| async move {
+        let (status, body) = execute_request(client, req).await?;
+        parse_response(status, &body)
+    })
+    .await
+}
+
+/// Like [`run_request`], but a response reporting that the resource doesn't exist is returned as `None`.
+#[tracing::instrument(skip_all, fields(url))]
+async fn run_optional_request<T: DeserializeOwned>(
+    client: &Client,
+    req: RequestBuilder,
+    retries: u32,
+) -> Result<Option<T>, Error> {
+    with_retries(req, retries, |req, _| async move {
+        let (status, body) = execute_request(client, req).await?;
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        parse_response(status, &body).map(Some)
+    })
+    .await
+}
+
+/// Like [`run_request`], for routes whose successful response isn't needed.
+#[tracing::instrument(skip_all, fields(url))]
+async fn run_request_discarding_body(
+    client: &Client,
+    req: RequestBuilder,
+    retries: u32,
+) -> Result<(), Error> {
+    with_retries(req, retries, |req, _| async move {
+        let (status, body) = execute_request(client, req).await?;
+        if status.is_success() {
+            return Ok(());
+        }
+        parse_response::<serde::de::IgnoredAny>(status, &body).discard_ok()
+    })
+    .await
 }
 
+/// Like [`run_request`], for routes whose successful response isn't JSON.
 #[tracing::instrument(skip_all, fields(url))]
-async fn run_request<T: DeserializeOwned>(req: RequestBuilder) -> Result<T, Error> {
-    let (client, req) = req.build_split();
+async fn run_request_raw(
+    client: &Client,
+    req: RequestBuilder,
+    retries: u32,
+) -> Result<Vec<u8>, Error> {
+    with_retries(req, retries, |req, _| async move {
+        let (status, body) = execute_request(client, req).await?;
+        if status.is_success() {
+            return Ok(body);
+        }
+        parse_response::<serde::de::IgnoredAny>(status, &body).map(|_| body)
+    })
+    .await
+}
+
+/// Attached to errors from requests which may succeed if they're retried,
+/// because the connection failed or FOSSA was temporarily unavailable.
+#[derive(Debug)]
+struct Transient;
+
+/// The delay before the first retry of a request; each later retry waits twice as long as the one before it.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// The longest delay between retries of a request.
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Run `attempt` with the request, retrying it with a copy of the request up to `retries` times
+/// while it fails transiently. `attempt` is also given the number of the attempt, starting at zero.
+///
+/// Requests whose body can't be copied, like files streamed from disk, are never retried.
+async fn with_retries<T, F, Fut>(
+    mut req: RequestBuilder,
+    retries: u32,
+    mut attempt: F,
+) -> Result<T, Error>
+where
+    F: FnMut(RequestBuilder, u32) -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let mut count = 0;
+    loop {
+        let retry = if count < retries {
+            req.try_clone()
+        } else {
+            None
+        };
+        match (attempt(req, count).await, retry) {
+            (Err(err), Some(next)) if err.contains::<Transient>() => {
+                count += 1;
+                let delay = RETRY_BASE_DELAY
+                    .saturating_mul(2u32.saturating_pow(count - 1))
+                    .min(RETRY_MAX_DELAY);
+                warn!(
+                    "Request to FOSSA failed, retrying in {} ({count} of {retries}): {err:#}",
+                    humantime::format_duration(delay)
+                );
+                tokio::time::sleep(delay).await;
+                req = next;
+            }
+            (result, _) => return result,
+        }
+    }
+}
+
+/// Run the request with the client, recording its URL in the current span, and download the response body.
+///
+/// If enabled in the debugging config, the call is also recorded into the debug artifacts.
+async fn execute_request(
+    client: &Client,
+    req: RequestBuilder,
+) -> Result<(StatusCode, Vec<u8>), Error> {
+    let (_, req) = req.build_split();
     let req = req.context(Error::Request)?;
     span_record!(url, display req.url());
 
-    let res = client.execute(req).await.context(Error::Request)?;
-    let status = res.status();
+    let key = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let call = api_calls::start(
+        req.method().as_str(),
+        req.url().as_str(),
+        key,
+        req.body().and_then(Body::as_bytes),
+    );
+
+    let response: Result<(StatusCode, Vec<u8>), Error> = async {
+        let res = match client.execute(req).await {
+            Ok(res) => res,
+            Err(err) => {
+                // Other errors, like a malformed request, fail the same way every time they're retried.
+                let transient = err.is_connect() || err.is_timeout() || err.is_request();
+                let err = Report::new(err).change_context(Error::Request);
+                let err = if transient {
+                    err.attach(Transient)
+                } else {
+                    err
+                };
+                return err.wrap_err();
+            }
+        };
+        let status = res.status();
+        let body = res
+            .bytes()
+            .await
+            .context(Error::ReadResponse)
+            .map_err(|err| err.attach(Transient))?;
+        Ok((status, body.to_vec()))
+    }
+    .await;
+
+    if let Some(call) = call {
+        match &response {
+            Ok((status, body)) => call.responded(status.as_u16(), body),
+            Err(err) => call.failed(format!("{err:#}")),
+        }
+    }
+    response
+}
 
-    let body = res.bytes().await.context(Error::ReadResponse)?;
+fn parse_response<T: DeserializeOwned>(status: StatusCode, body: &[u8]) -> Result<T, Error> {
     if !status.is_success() {
-        let err = serde_json::from_slice::<ApiError>(&body)
-            .context_lazy(|| Error::parse_response_body(&body))?;
-        report!(Error::fossa_api(err)).wrap_err()
+        let transient = status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS;
+        let err = match serde_json::from_slice::<ApiError>(body) {
+            Ok(err) => report!(Error::fossa_api(err)),
+            Err(err) => Report::new(err).change_context(Error::parse_response_body(body)),
+        };
+        let err = if transient {
+            err.attach(Transient)
+        } else {
+            err
+        };
+        err.wrap_err()
     } else {
-        serde_json::from_slice(&body).context_lazy(|| Error::parse_response_body(&body))
+        serde_json::from_slice(body).context_lazy(|| Error::parse_response_body(body))
     }
 }
 
@@ -439,6 +1549,97 @@ struct UploadResponse {
     error: Option<String>,
 }
 
+/// The body FOSSA expects when contributors are uploaded.
+#[derive(Debug, Serialize)]
+struct ContributorsRequest<'a> {
+    default: &'a Contributors,
+}
+
+/// The FOSSA API's information about a project. There's more here, but we don't care about it.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectResponse {
+    title: Option<String>,
+}
+
+/// The FOSSA API's information about the latest build of a revision.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BuildResponse {
+    error: Option<String>,
+    task: BuildTask,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BuildTask {
+    status: BuildStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum BuildStatus {
+    Succeeded,
+    Failed,
+    #[serde(other)]
+    Pending,
+}
+
+/// The FOSSA API's issues for a revision. There's more here, but we don't care about it.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IssuesResponse {
+    #[serde(default)]
+    issues: Vec<Issue>,
+    status: IssuesStatus,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Issue {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    severity: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum IssuesStatus {
+    Waiting,
+    #[serde(other)]
+    Scanned,
+}
+
+impl From<IssuesResponse> for Issues {
+    fn from(response: IssuesResponse) -> Self {
+        let mut counts = BTreeMap::new();
+        let mut severities = BTreeMap::new();
+        for issue in response.issues {
+            *counts.entry(issue.kind).or_insert(0) += 1;
+            let severity = issue
+                .severity
+                .map(|severity| severity.to_lowercase())
+                .unwrap_or_else(|| String::from("unknown"));
+            *severities.entry(severity).or_insert(0) += 1;
+        }
+        Self { counts, severities }
+    }
+}
+
+/// The route on the FOSSA API to which debug bundles are uploaded.
+///
+/// This route is served by FOSSA's support API rather than the CLI API used for scans;
+/// it's described for users in `docs/subcommands/fix.md`, and mocked by `broker simulate` in `cmd/simulate/mock.rs`.
+const DEBUG_BUNDLE_ROUTE: &str = "api/support/broker/debug-bundles";
+
+/// The FOSSA API's response to an uploaded debug bundle.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DebugBundleUploadResponse {
+    reference_id: String,
+}
+
 /// FOSSA API reports errors in this formatted form.
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -458,3 +1659,181 @@ impl From<UploadResponse> for Result<Locator, Error> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn counts_issues_by_type() {
+        let response = serde_json::from_str::<IssuesResponse>(
+            r#"{
+                "count": 3,
+                "status": "SCANNED",
+                "issues": [
+                    { "type": "policy_conflict", "revisionId": "npm+left-pad$1.0.0" },
+                    { "type": "vulnerability", "revisionId": "npm+lodash$4.17.0", "severity": "HIGH" },
+                    { "type": "policy_conflict", "revisionId": "npm+lodash$4.17.0" }
+                ]
+            }"#,
+        )
+        .expect("must parse response");
+        assert_eq!(response.status, IssuesStatus::Scanned);
+
+        let issues = Issues::from(response);
+        assert!(!issues.passed());
+        assert_eq!(
+            issues.to_string(),
+            "3 issue(s): 2 policy_conflict, 1 vulnerability"
+        );
+        assert_eq!(
+            issues.severities(),
+            &BTreeMap::from([(String::from("high"), 1), (String::from("unknown"), 2)])
+        );
+
+        let response = serde_json::from_str::<IssuesResponse>(r#"{ "status": "WAITING" }"#)
+            .expect("must parse response");
+        assert_eq!(response.status, IssuesStatus::Waiting);
+        assert!(Issues::from(response).passed());
+    }
+
+    #[test]
+    fn sbom_formats_match_config_names() {
+        for (name, format) in [
+            ("cyclonedx-json", SbomFormat::CyclonedxJson),
+            ("cyclonedx-xml", SbomFormat::CyclonedxXml),
+            ("spdx-json", SbomFormat::SpdxJson),
+            ("spdx", SbomFormat::Spdx),
+        ] {
+            let parsed = serde_json::from_str::<SbomFormat>(&format!("\"{name}\""))
+                .expect("must parse format");
+            assert_eq!(parsed, format);
+            assert_eq!(format.to_string(), name);
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_ci_metadata() {
+        let root = tempfile::tempdir().expect("must create temp dir");
+        let metadata = CiMetadata::new(Some(root.path().to_path_buf()));
+        assert_eq!(metadata.read("abcd1234").await, None);
+
+        let content = r#"{ "link": "https://ci.example.com/builds/42", "build": "42", "artifacts": ["app.tar.gz"] }"#;
+        std::fs::write(root.path().join("abcd1234.json"), content).expect("must write metadata");
+        let build = metadata.read("abcd1234").await.expect("must read metadata");
+        assert_eq!(
+            build.link().as_deref(),
+            Some("https://ci.example.com/builds/42")
+        );
+        assert_eq!(build.build().as_deref(), Some("42"));
+        assert_eq!(build.artifacts(), &vec![String::from("app.tar.gz")]);
+
+        std::fs::write(root.path().join("invalid.json"), "{").expect("must write metadata");
+        assert_eq!(metadata.read("invalid").await, None);
+        assert_eq!(metadata.read("../abcd1234").await, None);
+        assert_eq!(CiMetadata::default().read("abcd1234").await, None);
+    }
+
+    #[tokio::test]
+    async fn uploads_debug_bundle() {
+        let endpoint = crate::cmd::simulate::mock::Endpoint::start()
+            .await
+            .expect("must start mock endpoint");
+        let config = |url: String| {
+            Config::new(
+                Endpoint::try_from(url).expect("must parse endpoint"),
+                Key::try_from(String::from("some-key")).expect("must parse key"),
+                Upload::default(),
+                false,
+                false,
+                None,
+                Vec::new(),
+                FanOut::default(),
+                Retries::new(0, 0),
+            )
+        };
+        let clients = crate::api::http::client::Clients::new(Default::default())
+            .expect("must construct clients");
+        let client = clients.get(crate::api::http::client::Purpose::Fossa);
+
+        let root = tempfile::tempdir().expect("must create temp dir");
+        let bundle = root.path().join("broker-debug.tar.gz");
+        std::fs::write(&bundle, b"some bundle").expect("must write bundle");
+
+        let opts = config(endpoint.url().to_string());
+        let reference = upload_debug_bundle(client, &opts, &bundle)
+            .await
+            .expect("must upload bundle");
+        assert_eq!(reference, "mock-debug-bundle");
+
+        let requests = endpoint.requests();
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.path(), "/api/support/broker/debug-bundles");
+        assert!(request.query().contains(&(
+            String::from("filename"),
+            String::from("broker-debug.tar.gz")
+        )));
+        let header = |name: &str| {
+            request
+                .headers()
+                .iter()
+                .find(|(header, _)| header == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(header("authorization"), Some("Bearer some-key"));
+        assert_eq!(header("content-type"), Some("application/gzip"));
+        assert_eq!(request.body(), b"some bundle");
+
+        let opts = config(format!("{}missing/", endpoint.url()));
+        let err = upload_debug_bundle(client, &opts, &bundle)
+            .await
+            .expect_err("must fail when FOSSA rejects the upload");
+        assert!(matches!(err.current_context(), Error::UploadDebugBundle(_)));
+        assert_eq!(endpoint.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        let unavailable = parse_response::<serde::de::IgnoredAny>(
+            StatusCode::SERVICE_UNAVAILABLE,
+            b"<html>Service Unavailable</html>",
+        )
+        .expect_err("must fail");
+        assert!(unavailable.contains::<Transient>());
+        let rejected = parse_response::<serde::de::IgnoredAny>(
+            StatusCode::BAD_REQUEST,
+            br#"{"uuid":"1","code":2,"httpStatusCode":400,"message":"bad"}"#,
+        )
+        .expect_err("must fail");
+        assert!(!rejected.contains::<Transient>());
+
+        let req = || reqwest::Client::new().get("http://localhost/");
+        let attempts = AtomicU32::new(0);
+        let result = with_retries(req(), 1, |_, attempt| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if attempt == 0 {
+                    report!(Error::Request).attach(Transient).wrap_err()
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result.expect("must succeed when retried"), 1);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+        attempts.store(0, Ordering::SeqCst);
+        with_retries(req(), 3, |_, _| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            async { Err::<(), _>(report!(Error::Request)) }
+        })
+        .await
+        .expect_err("must fail without retrying");
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
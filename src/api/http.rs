@@ -1,10 +1,66 @@
 //! Interact with remote services over HTTP!
 
+use std::{
+    fmt::{Debug, Display},
+    time::Duration,
+};
+
 use derive_more::From;
 use derive_new::new;
+use error_stack::{bail, report, Report};
+use getset::Getters;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    api::remote::git::codecommit::CodeCommit,
+    ext::{
+        command::{Command, OutputProvider, Value},
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        result::WrapErr,
+        secrecy::{ComparableSecretString, REDACTION_LITERAL},
+    },
+};
+
+pub mod client;
 
-use crate::ext::secrecy::ComparableSecretString;
+/// Errors encountered minting credentials.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The credential command could not be run.
+    #[error("run credential command '{0}'")]
+    RunCommand(String),
+
+    /// The credential command exited unsuccessfully.
+    #[error("credential command '{command}' exited with status {status}")]
+    CommandFailed {
+        /// The command that failed.
+        command: String,
+
+        /// The exit status of the command.
+        status: i32,
+    },
+
+    /// The credential command didn't print a credential.
+    #[error("credential command '{0}' did not print a credential")]
+    EmptyCredential(String),
+
+    /// AWS credentials couldn't be loaded from the described source.
+    #[error("load AWS credentials from {0}")]
+    AwsCredentials(String),
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::RunCommand(..) => ErrorCode::new(1101),
+            Self::CommandFailed { .. } => ErrorCode::new(1102),
+            Self::EmptyCredential(..) => ErrorCode::new(1103),
+            Self::AwsCredentials(..) => ErrorCode::new(1104),
+        }
+    }
+}
 
 /// HTTP authentication can be performed either with a header or via 'HTTP Basic'.
 #[derive(Debug, Clone, PartialEq, Eq, From, Deserialize, Serialize, new)]
@@ -15,9 +71,228 @@ pub enum Auth {
     /// Uses HTTP Basic to perform authentication.
     Basic {
         /// The username for authentication.
-        username: String,
+        username: Username,
 
         /// The password for authentication.
         password: ComparableSecretString,
     },
+
+    /// Uses a short-lived credential, minted by running a command, to perform authentication.
+    Command(CredentialCommand),
+
+    /// Uses a short-lived password for an AWS CodeCommit repository, computed from AWS credentials.
+    AwsCodeCommit(CodeCommit),
+}
+
+impl Auth {
+    /// Resolve the auth into a static credential, minting a fresh credential if required.
+    pub async fn resolve(&self) -> Result<Auth, Report<Error>> {
+        match self {
+            Auth::Command(command) => command.mint().await,
+            Auth::AwsCodeCommit(repository) => repository.mint().await,
+            other => Ok(other.clone()),
+        }
+    }
+}
+
+/// The username for HTTP Basic authentication.
+///
+/// Usernames are usually not secret, but some credentials carry a secret in the username:
+/// for example AWS CodeCommit expects the session token of temporary credentials to be appended to the access key ID.
+/// That part is kept secret, and redacted when the username is displayed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Username {
+    /// The part of the username which isn't secret.
+    name: String,
+
+    /// The secret appended to the name, if any.
+    secret: Option<ComparableSecretString>,
+}
+
+impl Username {
+    /// A username made of the name followed by the secret.
+    pub fn with_secret(name: String, secret: ComparableSecretString) -> Self {
+        Self {
+            name,
+            secret: Some(secret),
+        }
+    }
+
+    /// The part of the username which isn't secret.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Expose the secret, viewing the full username as a standard string.
+    pub fn expose_secret(&self) -> String {
+        match &self.secret {
+            Some(secret) => format!("{}{}", self.name, secret.expose_secret()),
+            None => self.name.clone(),
+        }
+    }
+}
+
+impl From<String> for Username {
+    fn from(name: String) -> Self {
+        Self { name, secret: None }
+    }
+}
+
+impl Debug for Username {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Username").field(&self.to_string()).finish()
+    }
+}
+
+impl Display for Username {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.secret {
+            Some(_) => write!(f, "{}{REDACTION_LITERAL}", self.name),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+/// When serializing, we have to expose the secret.
+impl Serialize for Username {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.expose_secret())
+    }
+}
+
+impl<'de> Deserialize<'de> for Username {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(Self::from)
+    }
+}
+
+/// A user-provided command which prints a short-lived credential (such as a GitHub App installation token
+/// or an Azure AD token) to stdout.
+///
+/// Since such credentials expire, the command is run to mint a fresh credential before each git operation.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Getters, new)]
+#[getset(get = "pub")]
+pub struct CredentialCommand {
+    /// The command to run.
+    command: String,
+
+    /// Arguments to provide to the command.
+    args: Vec<String>,
+
+    /// If provided, the credential is used as the password for HTTP Basic authentication with this username.
+    /// Otherwise, the credential is used as a bearer token.
+    username: Option<String>,
+}
+
+impl CredentialCommand {
+    /// If the credential command runs longer than this, it is killed.
+    pub const TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Run the command, returning the static auth using the credential it printed.
+    ///
+    /// The output of the command is never included in errors, since it may contain the credential.
+    #[tracing::instrument(skip(self), fields(command = %self.command))]
+    pub async fn mint(&self) -> Result<Auth, Report<Error>> {
+        let output = Command::new(&self.command)
+            .args(self.args.iter().map(Value::new_plain))
+            .timeout(Self::TIMEOUT)
+            .output()
+            .await
+            .context_lazy(|| Error::RunCommand(self.command.clone()))
+            .help("ensure the credential command exists and is executable")?;
+
+        if !output.status().success() {
+            let stderr = output.stderr_string_lossy();
+            return report!(Error::CommandFailed {
+                command: self.command.clone(),
+                status: output.exit_code(),
+            })
+            .wrap_err()
+            .describe_lazy(|| format!("stderr: {}", stderr.trim()));
+        }
+
+        let credential = output.stdout_string_lossy().trim().to_string();
+        if credential.is_empty() {
+            bail!(Error::EmptyCredential(self.command.clone()));
+        }
+
+        let credential = ComparableSecretString::from(credential);
+        match &self.username {
+            Some(username) => Ok(Auth::new_basic(username.clone().into(), credential)),
+            None => {
+                let header = format!("Authorization: Bearer {}", credential.expose_secret());
+                Ok(Auth::new_header(ComparableSecretString::from(header)))
+            }
+        }
+    }
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn mints_bearer_header() {
+        let command = CredentialCommand::new(
+            String::from("sh"),
+            vec![String::from("-c"), String::from("echo abcd1234")],
+            None,
+        );
+
+        let auth = command.mint().await.expect("must mint credential");
+        assert_eq!(
+            auth,
+            Auth::new_header(ComparableSecretString::from(
+                "Authorization: Bearer abcd1234"
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn mints_basic_auth() {
+        let command = CredentialCommand::new(
+            String::from("sh"),
+            vec![String::from("-c"), String::from("echo abcd1234")],
+            Some(String::from("x-access-token")),
+        );
+
+        let auth = command.mint().await.expect("must mint credential");
+        assert_eq!(
+            auth,
+            Auth::new_basic(
+                Username::from(String::from("x-access-token")),
+                ComparableSecretString::from("abcd1234")
+            )
+        );
+    }
+
+    #[test]
+    fn redacts_secret_in_username() {
+        let username = Username::with_secret(
+            String::from("AKIDEXAMPLE"),
+            ComparableSecretString::from("%session-token"),
+        );
+        assert_eq!(username.name(), "AKIDEXAMPLE");
+        assert_eq!(username.expose_secret(), "AKIDEXAMPLE%session-token");
+        assert_eq!(username.to_string(), "AKIDEXAMPLE<REDACTED>");
+        assert!(!format!("{username:?}").contains("session-token"));
+    }
+
+    #[tokio::test]
+    async fn rejects_empty_credential() {
+        let command = CredentialCommand::new(
+            String::from("sh"),
+            vec![String::from("-c"), String::from("true")],
+            None,
+        );
+
+        let err = command.mint().await.expect_err("must fail");
+        assert!(matches!(err.current_context(), Error::EmptyCredential(_)));
+    }
 }
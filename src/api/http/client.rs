@@ -0,0 +1,474 @@
+//! Shared HTTP clients for outbound requests.
+//!
+//! Broker keeps one [`Client`] for each [`Purpose`] in its [`AppContext`](crate::AppContext),
+//! so that connections to each host are pooled and reused instead of being opened for every request.
+//! Code which doesn't have the context, like remote providers, uses the same clients through [`Clients::current`].
+//! Requests sent through these clients are counted and timed for each purpose and host; see [`Metrics`].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    num::NonZeroUsize,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use derive_new::new;
+use error_stack::{report, Report};
+use getset::CopyGetters;
+use once_cell::sync::OnceCell;
+use reqwest::{redirect, Method, Request, RequestBuilder, Response, Url};
+use strum::{Display, EnumIter, IntoEnumIterator};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::crypto;
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::ext::{
+    error_stack::{DescribeContext, ErrorHelper, IntoContext},
+    result::{WrapErr, WrapOk},
+};
+
+/// The clients shared by the process; see [`Clients::shared`].
+static SHARED: OnceCell<Clients> = OnceCell::new();
+
+/// Errors encountered setting up HTTP clients.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The HTTP client for a purpose couldn't be constructed.
+    #[error("construct HTTP client for {0}")]
+    Construct(Purpose),
+
+    /// The clients shared by the process are already in use with different connection pool settings.
+    #[error("share HTTP clients with different connection pool settings")]
+    Shared,
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Construct(..) => ErrorCode::new(1121),
+            Self::Shared => ErrorCode::new(1122),
+        }
+    }
+}
+
+/// Errors that are possibly surfaced during validation of config values.
+#[derive(Debug, thiserror::Error)]
+pub enum ValidationError {
+    /// A duration in the connection pool settings couldn't be parsed.
+    #[error("parse duration for '{0}'")]
+    Duration(&'static str),
+}
+
+impl HasErrorCode for ValidationError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Duration(..) => ErrorCode::new(1131),
+        }
+    }
+}
+
+/// What a client is used for.
+///
+/// Each purpose has its own client, so that settings like redirects differ between them
+/// and a burst of requests for one purpose doesn't exhaust the pooled connections of another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Display, EnumIter)]
+#[strum(serialize_all = "snake_case")]
+pub enum Purpose {
+    /// Requests to the FOSSA API.
+    Fossa,
+
+    /// Downloads of FOSSA CLI and lookups of its releases.
+    FossaCli,
+
+    /// Downloads of portable git, when the system git is missing or too old.
+    PortableGit,
+
+    /// Requests made by integrations to read their remotes, like archive index pages, buckets, and Gerrit servers,
+    /// along with requests for the credentials they use.
+    Remotes,
+
+    /// Notifications delivered to webhooks.
+    Notifications,
+
+    /// Lookups and downloads of Broker releases by `broker update`.
+    Updates,
+
+    /// Diagnostic requests made by `broker fix` and `broker doctor`, which don't follow redirects and connect with a short timeout
+    /// so that problems with the network are reported rather than worked around.
+    Diagnostics,
+}
+
+/// Validated config values for the connection pools of the HTTP clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters, new)]
+#[getset(get_copy = "pub")]
+pub struct Config {
+    /// The most idle connections kept open to each host, for each client.
+    max_idle_per_host: usize,
+
+    /// How long idle connections are kept open before they're closed.
+    idle_timeout: Duration,
+
+    /// How often TCP keep-alive probes are sent on open connections.
+    tcp_keepalive: Duration,
+
+    /// The most requests awaiting a response from each host at a time, for each client.
+    /// If not set, requests aren't limited beyond the limits Broker otherwise applies.
+    max_connections_per_host: Option<NonZeroUsize>,
+}
+
+impl Config {
+    /// Validate the connection pool settings, using the defaults for any which aren't provided.
+    pub fn validate(
+        max_idle_per_host: Option<usize>,
+        idle_timeout: Option<String>,
+        tcp_keepalive: Option<String>,
+        max_connections_per_host: Option<NonZeroUsize>,
+    ) -> Result<Self, Report<ValidationError>> {
+        let default = Self::default();
+        let idle_timeout = match idle_timeout {
+            Some(value) => parse_duration("idle_timeout", &value)?,
+            None => default.idle_timeout,
+        };
+        let tcp_keepalive = match tcp_keepalive {
+            Some(value) => parse_duration("tcp_keepalive", &value)?,
+            None => default.tcp_keepalive,
+        };
+        Self::new(
+            max_idle_per_host.unwrap_or(default.max_idle_per_host),
+            idle_timeout,
+            tcp_keepalive,
+            max_connections_per_host,
+        )
+        .wrap_ok()
+    }
+}
+
+fn parse_duration(name: &'static str, value: &str) -> Result<Duration, Report<ValidationError>> {
+    humantime::parse_duration(value)
+        .context(ValidationError::Duration(name))
+        .describe_lazy(|| format!("provided value: '{value}'"))
+        .help("provide a duration like '90s' or '5m'")
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: 8,
+            idle_timeout: Duration::from_secs(90),
+            tcp_keepalive: Duration::from_secs(60),
+            max_connections_per_host: None,
+        }
+    }
+}
+
+/// The shared HTTP clients, one for each [`Purpose`].
+///
+/// Clones share the same clients, connection pools, and metrics.
+#[derive(Debug, Clone)]
+pub struct Clients {
+    inner: Arc<ClientsInner>,
+}
+
+#[derive(Debug)]
+struct ClientsInner {
+    config: Config,
+    clients: BTreeMap<Purpose, Client>,
+    metrics: Metrics,
+}
+
+impl Clients {
+    /// Construct the clients with the provided connection pool settings.
+    ///
+    /// There's no fallback if they can't be constructed: every client must connect with [`crypto::TLS_PROVIDER`]
+    /// and the settings of the crypto mode, which a client constructed some other way wouldn't.
+    pub fn new(config: Config) -> Result<Self, Report<Error>> {
+        let metrics = Metrics::default();
+        let clients = Purpose::iter()
+            .map(|purpose| Client::new(purpose, &config, metrics.clone()).map(|c| (purpose, c)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            inner: Arc::new(ClientsInner {
+                config,
+                clients,
+                metrics,
+            }),
+        })
+    }
+
+    /// The clients shared by the process, constructed with the provided settings the first time this is called.
+    ///
+    /// [`crate::config::apply`] puts these in the context, so that they're also the clients used by [`Clients::current`].
+    /// Requesting them with settings other than those they were constructed with is an error.
+    pub fn shared(config: Config) -> Result<Self, Report<Error>> {
+        let clients = SHARED.get_or_try_init(|| Self::new(config))?;
+        if clients.config() != config {
+            return report!(Error::Shared)
+                .wrap_err()
+                .help("every instance of Broker in a process must use the same connection pool settings")
+                .describe_lazy(|| format!("in use: {:?}, provided: {config:?}", clients.config()));
+        }
+        Ok(clients.clone())
+    }
+
+    /// The clients shared by the process, for code which doesn't have an [`AppContext`](crate::AppContext).
+    ///
+    /// If they haven't been constructed yet, they're constructed with the default settings.
+    /// Like any other clients, they connect with the settings of the crypto mode, and there's no fallback
+    /// if they can't be constructed.
+    pub fn current() -> Result<Self, Report<Error>> {
+        match SHARED.get() {
+            Some(clients) => Ok(clients.clone()),
+            None => Self::shared(Config::default()),
+        }
+    }
+
+    /// The client for the purpose.
+    pub fn get(&self, purpose: Purpose) -> &Client {
+        // Every purpose has a client, since they're all constructed in `new`.
+        &self.inner.clients[&purpose]
+    }
+
+    /// The connection pool settings of the clients.
+    pub fn config(&self) -> Config {
+        self.inner.config
+    }
+
+    /// The metrics of requests sent through the clients.
+    pub fn metrics(&self) -> &Metrics {
+        &self.inner.metrics
+    }
+}
+
+impl PartialEq for Clients {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for Clients {}
+
+/// An HTTP client for a [`Purpose`], which records metrics for each request it sends
+/// and limits the requests awaiting a response from each host.
+#[derive(Debug, Clone)]
+pub struct Client {
+    purpose: Purpose,
+    inner: reqwest::Client,
+    limits: Arc<HostLimits>,
+    metrics: Metrics,
+}
+
+impl Client {
+    fn new(purpose: Purpose, config: &Config, metrics: Metrics) -> Result<Self, Report<Error>> {
+        static APP_USER_AGENT: &str =
+            concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+        let builder = crypto::http_client()
+            .user_agent(APP_USER_AGENT)
+            .pool_max_idle_per_host(config.max_idle_per_host)
+            .pool_idle_timeout(config.idle_timeout)
+            .tcp_keepalive(config.tcp_keepalive);
+        let builder = match purpose {
+            Purpose::Fossa
+            | Purpose::FossaCli
+            | Purpose::PortableGit
+            | Purpose::Remotes
+            | Purpose::Notifications
+            | Purpose::Updates => builder,
+            Purpose::Diagnostics => builder
+                .redirect(redirect::Policy::none())
+                .connect_timeout(Duration::from_secs(30)),
+        };
+
+        let inner = builder
+            .build()
+            .context(Error::Construct(purpose))
+            .describe("this may be caused by a problem with the system's TLS configuration")?;
+        Ok(Self {
+            purpose,
+            inner,
+            limits: Arc::new(HostLimits::new(config.max_connections_per_host)),
+            metrics,
+        })
+    }
+
+    /// Start building a request with the method and URL.
+    ///
+    /// Send the request with [`Client::send`] or [`Client::execute`] so that it's limited and measured.
+    pub fn request(&self, method: Method, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.inner.request(method, url)
+    }
+
+    /// Start building a GET request to the URL.
+    pub fn get(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    /// Start building a POST request to the URL.
+    pub fn post(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.request(Method::POST, url)
+    }
+
+    /// Build and send the request.
+    pub async fn send(&self, req: RequestBuilder) -> reqwest::Result<Response> {
+        let (_, req) = req.build_split();
+        self.execute(req?).await
+    }
+
+    /// Send the request, waiting for the response headers.
+    ///
+    /// The request counts against the limit for its host until the response headers are received;
+    /// its latency is recorded as the time until then.
+    pub async fn execute(&self, req: Request) -> reqwest::Result<Response> {
+        let host = host_of(req.url());
+        let _permit = self.limits.acquire(&host).await;
+
+        let start = Instant::now();
+        let response = self.inner.execute(req).await;
+        let succeeded = matches!(&response, Ok(res) if !res.status().is_server_error());
+        self.metrics
+            .record(self.purpose, host, start.elapsed(), succeeded);
+        response
+    }
+}
+
+/// The host of the URL, used to group limits and metrics.
+fn host_of(url: &Url) -> String {
+    match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => String::from("unknown"),
+    }
+}
+
+/// Limits the requests awaiting a response from each host.
+#[derive(Debug)]
+struct HostLimits {
+    max: Option<NonZeroUsize>,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimits {
+    fn new(max: Option<NonZeroUsize>) -> Self {
+        Self {
+            max,
+            hosts: Mutex::default(),
+        }
+    }
+
+    /// Wait until a request may be sent to the host.
+    async fn acquire(&self, host: &str) -> Option<OwnedSemaphorePermit> {
+        let max = self.max?;
+        let semaphore = self
+            .hosts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(max.get())))
+            .clone();
+        // The semaphore is never closed, so this only fails if that changes.
+        semaphore.acquire_owned().await.ok()
+    }
+}
+
+/// Counts and latencies of the requests sent through the clients, for each purpose and host.
+///
+/// Clones share the same metrics.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    requests: Arc<Mutex<BTreeMap<(Purpose, String), RequestStats>>>,
+}
+
+impl Metrics {
+    fn record(&self, purpose: Purpose, host: String, latency: Duration, succeeded: bool) {
+        let mut requests = self.lock();
+        let stats = requests.entry((purpose, host)).or_default();
+        stats.requests += 1;
+        if !succeeded {
+            stats.failures += 1;
+        }
+        stats.total_latency += latency;
+        stats.max_latency = stats.max_latency.max(latency);
+    }
+
+    /// The metrics recorded so far, for each purpose and host.
+    pub fn snapshot(&self) -> BTreeMap<(Purpose, String), RequestStats> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<(Purpose, String), RequestStats>> {
+        self.requests.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The count and latency of requests sent to a host for a purpose.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct RequestStats {
+    /// How many requests were sent.
+    requests: u64,
+
+    /// How many requests failed without a response, or received a server error response.
+    failures: u64,
+
+    /// The total time spent waiting for responses.
+    total_latency: Duration,
+
+    /// The longest time spent waiting for a response.
+    max_latency: Duration,
+}
+
+impl RequestStats {
+    /// The average time spent waiting for a response, if any requests were sent.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        u32::try_from(self.requests)
+            .ok()
+            .filter(|requests| *requests > 0)
+            .map(|requests| self.total_latency / requests)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn records_metrics_per_host() {
+        let clients = Clients::new(Config::default()).expect("must construct clients");
+        let client = clients.get(Purpose::Fossa);
+        client
+            .send(client.get("http://127.0.0.1:1/api/cli/organization"))
+            .await
+            .expect_err("must fail to connect");
+
+        let snapshot = clients.metrics().snapshot();
+        let stats = snapshot
+            .get(&(Purpose::Fossa, String::from("127.0.0.1:1")))
+            .expect("must record request");
+        assert_eq!(stats.requests(), 1);
+        assert_eq!(stats.failures(), 1);
+        assert!(stats.mean_latency().is_some());
+        assert!(!snapshot
+            .keys()
+            .any(|(purpose, _)| *purpose == Purpose::Diagnostics));
+    }
+
+    #[tokio::test]
+    async fn limits_requests_per_host() {
+        let limits = HostLimits::new(NonZeroUsize::new(1));
+        let first = limits.acquire("fossa.example.com").await;
+        assert!(first.is_some());
+        let other_host = limits.acquire("github.com").await;
+        assert!(other_host.is_some());
+
+        let waiting = tokio::time::timeout(
+            Duration::from_millis(50),
+            limits.acquire("fossa.example.com"),
+        )
+        .await;
+        assert!(waiting.is_err(), "must wait for the first request");
+
+        drop(first);
+        let second = limits.acquire("fossa.example.com").await;
+        assert!(second.is_some());
+    }
+}
@@ -9,9 +9,17 @@
 //! [`Protocol`], which is usually wrapped inside an [`Integration`], forming the primary interaction
 //! point for this module.
 
-use std::{fmt::Display, time::Duration};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    future::Future,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
 
 use async_trait::async_trait;
+use bytesize::ByteSize;
 use delegate::delegate;
 use derive_more::{AsRef, Display, From};
 use derive_new::new;
@@ -19,20 +27,41 @@ use error_stack::{ensure, report, Report, ResultExt};
 use getset::{CopyGetters, Getters};
 use glob::Pattern;
 use humantime::parse_duration;
+use itertools::Itertools;
+use nonzero_ext::nonzero;
 use serde::{Deserialize, Serialize};
 use tempfile::TempDir;
 use typed_builder::TypedBuilder;
 
+use crate::doc::code::{ErrorCode, HasErrorCode};
 use crate::{
-    db,
+    bandwidth, db,
     ext::{
+        command,
         error_stack::{DescribeContext, ErrorHelper, IntoContext},
         result::{WrapErr, WrapOk},
+        secrecy::ComparableSecretString,
     },
 };
 
+/// Integrations for versioned archives
+pub mod archive;
+/// Integrations for object storage buckets
+pub mod bucket;
+/// A fake remote for tests
+#[cfg(any(test, feature = "test-fixtures"))]
+pub mod fake;
+/// Integrations for Gerrit Code Review projects
+pub mod gerrit;
 /// Integrations for git repositories
 pub mod git;
+/// Integrations for directories on the Broker host
+pub mod local;
+/// Integrations for Perforce depots
+pub mod perforce;
+mod provider;
+
+pub use provider::{Provider, ProviderReference};
 
 /// Errors that are possibly surfaced during validation of config values.
 #[derive(Debug, thiserror::Error)]
@@ -45,10 +74,66 @@ pub enum ValidationError {
     #[error("poll interval must be a minimum of {}", humantime::format_duration(MIN_POLL_INTERVAL).to_string())]
     MinPollInterval,
 
+    /// Job timeouts are parsed from a user-provided string.
+    #[error("validate job timeout")]
+    JobTimeout,
+
+    /// Job timeouts must be greater than zero.
+    #[error("job timeout must be greater than zero")]
+    JobTimeoutZero,
+
+    /// Poll windows are parsed from a user-provided string.
+    #[error("validate poll window")]
+    PollWindow,
+
+    /// Poll windows must have a distinct start and end.
+    #[error("poll window must not be empty")]
+    PollWindowEmpty,
+
+    /// Slow scan multiples must be a finite number greater than 1.
+    #[error("slow scan multiple must be greater than 1")]
+    SlowScanMultiple,
+
+    /// Integrations may only reference groups defined in the config file.
+    #[error("group '{0}' is not defined")]
+    UnknownGroup(String),
+
+    /// The poll interval must be set on the integration or its group.
+    #[error("poll interval is not set")]
+    PollIntervalMissing,
+
     /// The provided remote is not valid.
     #[error("validate remote location")]
     Remote,
 
+    /// Directories for `local` integrations must be written as absolute paths.
+    #[error("local integration path must be absolute")]
+    LocalPath,
+
+    /// Archives for `archive` integrations must be listed from an absolute path or an HTTP URL.
+    #[error("archive integration location must be an absolute path or an http(s) URL")]
+    ArchiveLocation,
+
+    /// Buckets must be written as `s3://` or `gs://` URLs.
+    #[error("bucket location must be an s3:// or gs:// URL")]
+    BucketLocation,
+
+    /// Depots for `perforce` integrations must be written like `//depot`.
+    #[error("perforce depot must be written like '//depot'")]
+    PerforceDepot,
+
+    /// Servers for `gerrit` integrations must be written as HTTP URLs.
+    #[error("gerrit url must be an http(s) URL")]
+    GerritUrl,
+
+    /// Remotes authenticated with AWS CodeCommit credentials must be CodeCommit HTTPS URLs.
+    #[error("aws codecommit remote must be a CodeCommit https URL")]
+    CodeCommitRemote,
+
+    /// Environment variable names for FOSSA CLI must be nonempty and may not contain `=`.
+    #[error("invalid environment variable name for FOSSA CLI")]
+    CliEnvName,
+
     /// The provided value is empty.
     #[error("value is empty")]
     ValueEmpty,
@@ -57,9 +142,66 @@ pub enum ValidationError {
     #[error("validate import branches and watched branches")]
     ImportBranches,
 
+    /// Mirrors hold every file of the repository, so there's nothing to save by scanning only manifests.
+    #[error("scan mode 'manifests-only' can't be used with a mirror cache")]
+    ManifestsOnlyMirror,
+
+    /// Excluded branches must be valid glob patterns.
+    #[error("excluded branch '{0}' is not a valid pattern")]
+    ExcludedBranch(String),
+
+    /// Paths provided to FOSSA CLI must be relative to the root of the project.
+    #[error("path '{0}' for FOSSA CLI must be relative to the project")]
+    CliOptionPath(String),
+
     /// Unable to infer primary branch
     #[error("primary branch could not be inferred")]
     PrimaryBranch,
+
+    /// Integrations reading their remotes from a file must be git integrations configured without a single remote.
+    #[error("validate remotes file")]
+    RemotesFrom,
+
+    /// Branch mappings must have a valid glob pattern, and change the project or the branch.
+    #[error("branch mapping for '{0}' is invalid")]
+    BranchMapping(String),
+
+    /// No branch may match more than one branch mapping.
+    #[error("branch mappings for '{0}' and '{1}' overlap")]
+    BranchMappingOverlap(String, String),
+}
+
+impl HasErrorCode for ValidationError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::PollInterval => ErrorCode::new(1201),
+            Self::MinPollInterval => ErrorCode::new(1202),
+            Self::JobTimeout => ErrorCode::new(1203),
+            Self::JobTimeoutZero => ErrorCode::new(1204),
+            Self::PollWindow => ErrorCode::new(1205),
+            Self::PollWindowEmpty => ErrorCode::new(1206),
+            Self::SlowScanMultiple => ErrorCode::new(1207),
+            Self::UnknownGroup(..) => ErrorCode::new(1208),
+            Self::PollIntervalMissing => ErrorCode::new(1209),
+            Self::Remote => ErrorCode::new(1210),
+            Self::LocalPath => ErrorCode::new(1211),
+            Self::ArchiveLocation => ErrorCode::new(1212),
+            Self::BucketLocation => ErrorCode::new(1213),
+            Self::PerforceDepot => ErrorCode::new(1214),
+            Self::GerritUrl => ErrorCode::new(1215),
+            Self::CodeCommitRemote => ErrorCode::new(1216),
+            Self::CliEnvName => ErrorCode::new(1217),
+            Self::ValueEmpty => ErrorCode::new(1218),
+            Self::ImportBranches => ErrorCode::new(1219),
+            Self::ManifestsOnlyMirror => ErrorCode::new(1220),
+            Self::ExcludedBranch(..) => ErrorCode::new(1221),
+            Self::CliOptionPath(..) => ErrorCode::new(1222),
+            Self::PrimaryBranch => ErrorCode::new(1223),
+            Self::RemotesFrom => ErrorCode::new(1224),
+            Self::BranchMapping(..) => ErrorCode::new(1225),
+            Self::BranchMappingOverlap(..) => ErrorCode::new(1226),
+        }
+    }
 }
 
 /// Validated config values for external code host integrations.
@@ -149,6 +291,105 @@ pub struct Integration {
     /// The name of the branches we want to scan
     #[getset(get = "pub")]
     watched_branches: Vec<WatchedBranch>,
+
+    /// Branches which aren't scanned even if they match a watched branch.
+    #[getset(get = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    excluded_branches: Vec<ExcludedBranch>,
+
+    /// Specifies how Broker obtains the code for references it scans.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    clone_strategy: CloneStrategy,
+
+    /// Specifies how much of each reference Broker obtains to scan it.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    scan_mode: ScanMode,
+
+    /// The maximum amount of time Broker waits for a reference to be cloned.
+    #[getset(get_copy = "pub")]
+    #[builder(default = JobTimeout::DEFAULT_CLONE)]
+    #[serde(default = "JobTimeout::default_clone")]
+    clone_timeout: JobTimeout,
+
+    /// The maximum amount of time Broker waits for a reference to be analyzed.
+    #[getset(get_copy = "pub")]
+    #[builder(default = JobTimeout::DEFAULT_SCAN)]
+    #[serde(default = "JobTimeout::default_scan")]
+    scan_timeout: JobTimeout,
+
+    /// The share of scan workers this integration receives relative to other integrations.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    scan_weight: ScanWeight,
+
+    /// Which references Broker scans the first time it polls the remote code host after starting.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    scan_on_startup: ScanOnStartup,
+
+    /// The time of day during which Broker may poll the remote code host, if restricted.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    poll_window: Option<PollWindow>,
+
+    /// How many times slower than its recent average a clone or analysis must be to be reported as slow.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    slow_scan_multiple: SlowScanMultiple,
+
+    /// The most bytes per second this integration's clones and downloads use, instead of the global limit.
+    /// A limit of zero means they aren't limited.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    bandwidth_limit: Option<ByteSize>,
+
+    /// Remotes this integration was previously configured with, for example before its repository was renamed.
+    /// State stored for them is moved to the integration's current remote, so that it isn't scanned again.
+    #[getset(get = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    aliases: Vec<Remote>,
+
+    /// The group to which this integration belongs, if any.
+    #[getset(get = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    group: Option<String>,
+
+    /// Environment variables set for FOSSA CLI when it analyzes this integration's code.
+    #[getset(get = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    cli_env: Vec<CliEnv>,
+
+    /// Options for FOSSA CLI when it analyzes this integration's code.
+    #[getset(get = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    cli_options: CliOptions,
+
+    /// The file listing this integration's remotes, if it reads them from a file instead of having a single remote.
+    /// Broker runs an integration for each remote in the file, with the rest of its settings taken from this one.
+    #[getset(get = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    remotes_from: Option<PathBuf>,
+
+    /// Rules which upload scans of matching branches to a different FOSSA project or branch.
+    #[getset(get = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    branch_mappings: Vec<BranchMapping>,
 }
 
 impl Display for Integration {
@@ -158,14 +399,45 @@ impl Display for Integration {
 }
 
 impl Integration {
+    /// Whether the integration belongs to the named group.
+    pub fn in_group(&self, group: &str) -> bool {
+        self.group.as_deref() == Some(group)
+    }
+
+    /// The branch mapping which applies to the reference, if any.
+    /// Only branches are mapped; tags and other references are uploaded as usual.
+    pub fn branch_mapping(&self, reference: &Reference) -> Option<&BranchMapping> {
+        let branch = reference.branch()?;
+        self.branch_mappings
+            .iter()
+            .find(|mapping| mapping.matches(branch))
+    }
+
     /// Get the configured remote for the integration, regardless of variant.
     pub fn remote(&self) -> &Remote {
-        match &self.protocol {
-            Protocol::Git(transport) => match transport {
-                git::transport::Transport::Ssh { endpoint, .. } => endpoint,
-                git::transport::Transport::Http { endpoint, .. } => endpoint,
-            },
-        }
+        self.protocol.endpoint()
+    }
+
+    /// The database namespace for the integration's coordinates.
+    pub fn namespace(&self) -> db::Namespace {
+        self.protocol.namespace()
+    }
+
+    /// The representation of the integration's remote used for its database coordinates.
+    ///
+    /// Integrations for the same repository share a representation even if their remotes are written differently,
+    /// so that the repository is only scanned once.
+    pub fn repository(&self) -> String {
+        self.protocol.repository()
+    }
+
+    /// The representations of the integration's aliases used for database coordinates,
+    /// as they would be if they were the integration's remote.
+    pub fn alias_repositories(&self) -> Vec<String> {
+        self.aliases
+            .iter()
+            .map(|alias| self.protocol.repository_of(alias))
+            .collect()
     }
 
     /// The endpoint for the integration.
@@ -173,14 +445,15 @@ impl Integration {
         self.protocol().endpoint()
     }
 
-    /// Checks if the reference branch should be scanned by comparing it to our watched branches
+    /// Checks if the reference branch should be scanned by comparing it to our watched branches,
+    /// then to our excluded branches
     pub fn should_scan_reference(&self, reference: &str) -> bool {
         let branches = self.watched_branches();
         for branch in branches {
             match Pattern::new(branch.name()) {
                 Ok(p) => {
                     if p.matches(reference) {
-                        return true;
+                        return !self.is_excluded_branch(reference);
                     }
                 }
                 // In the case of error continue on and have the function return false if there are no matches
@@ -190,41 +463,431 @@ impl Integration {
         false
     }
 
+    /// Whether the integration scans a reference, given the branch it tracks (or `None` if it isn't a branch).
+    pub fn scans_reference(&self, branch: Option<&str>) -> bool {
+        match branch {
+            // Skipping because integration is not configured to scan branches or branch was not in the integration's watched branches
+            Some(branch) => {
+                !self.import_branches().should_skip_branches() && self.should_scan_reference(branch)
+            }
+            // Skipping because integration was not configured to scan tags
+            None => !self.import_tags().should_skip_tags(),
+        }
+    }
+
+    /// List the references on the code host which the integration scans.
+    ///
+    /// Unlike [`RemoteProvider::references`], references the integration doesn't scan are skipped while listing,
+    /// so they're never collected.
+    pub async fn scanned_references(&self) -> Result<Vec<Reference>, Report<RemoteProviderError>> {
+        self.protocol
+            .references_matching(&|branch| self.scans_reference(branch))
+            .await
+    }
+
+    /// Checks if the reference branch matches any of our excluded branches
+    pub fn is_excluded_branch(&self, reference: &str) -> bool {
+        self.excluded_branches
+            .iter()
+            .any(|branch| branch.matches(reference))
+    }
+
     /// Mutable reference for watched branches
     pub fn add_watched_branch(&mut self, watched_branch: WatchedBranch) {
         self.watched_branches.push(watched_branch)
     }
-}
 
-/// Code is stored in many kinds of locations, from git repos to
-/// random FTP sites to DevOps hosts like GitHub.
-///
-/// To handle this variety, Broker uses a predefined list
-/// of supported protocols (this type),
-/// which are specialized with configuration unique to those integrations.
-#[derive(Debug, Clone, PartialEq, Eq, From, Deserialize, Serialize, new)]
-pub enum Protocol {
-    /// Integration with a code host using the git protocol.
-    Git(git::transport::Transport),
-}
+    /// The integrations for the remotes listed in the contents of this integration's `remotes_from` file.
+    ///
+    /// The file lists one remote per line. Blank lines and lines starting with `#` are ignored,
+    /// as are remotes listed more than once.
+    pub fn expand_remotes(&self, content: &str) -> Vec<Integration> {
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .unique()
+            .map(|line| self.with_remote(Remote::new(line.to_string())))
+            .collect()
+    }
 
-impl Display for Protocol {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Protocol::Git(transport) => write!(f, "git::{transport}"),
+    /// This integration with its remote replaced, as expanded from its `remotes_from` file.
+    fn with_remote(&self, remote: Remote) -> Integration {
+        let mut integration = self.clone();
+        integration.remotes_from = None;
+        if let Protocol::Git(transport) = &mut integration.protocol {
+            transport.set_endpoint(remote);
         }
+        integration
     }
-}
 
-impl Protocol {
-    /// The endpoint for the protocol.
-    pub fn endpoint(&self) -> &Remote {
-        match self {
-            Protocol::Git(transport) => transport.endpoint(),
+    /// The location of the persistent mirror for this integration inside the provided cache root.
+    ///
+    /// Mirrors are named after the remote, so integrations sharing a remote also share a mirror.
+    pub fn mirror_location(&self, cache_root: &Path) -> PathBuf {
+        let name = self
+            .remote()
+            .to_string()
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '.' => c,
+                _ => '_',
+            })
+            .collect::<String>();
+        cache_root.join(name)
+    }
+
+    /// Fetch the latest state of the remote into the persistent mirror for this integration.
+    ///
+    /// Integrations that do not use [`CloneStrategy::Mirror`] have no mirror, so this does nothing.
+    pub async fn update_mirror(
+        &self,
+        cache_root: &Path,
+    ) -> Result<(), Report<RemoteProviderError>> {
+        if self.clone_strategy != CloneStrategy::Mirror {
+            return Ok(());
         }
+
+        let mirror = self.mirror_location(cache_root);
+        self.with_bandwidth_limit(self.protocol.update_mirror(&mirror))
+            .await
+    }
+
+    /// Check out a [`Reference`] into a temporary directory, according to the integration's [`CloneStrategy`]
+    /// and [`ScanMode`].
+    pub async fn checkout_reference(
+        &self,
+        cache_root: &Path,
+        reference: &Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        let checkout = async {
+            match self.clone_strategy {
+                CloneStrategy::Blobless if self.scan_mode == ScanMode::ManifestsOnly => {
+                    self.protocol.clone_manifests(reference).await
+                }
+                CloneStrategy::Blobless => self.clone_reference(reference).await,
+                CloneStrategy::Mirror => {
+                    let mirror = self.mirror_location(cache_root);
+                    self.protocol.checkout_from_mirror(&mirror, reference).await
+                }
+            }
+        };
+        self.with_bandwidth_limit(checkout).await
+    }
+
+    /// Run the integration's transfers with its own bandwidth limit, if it has one.
+    async fn with_bandwidth_limit<F: Future>(&self, work: F) -> F::Output {
+        bandwidth::with_limit(&self.remote().to_string(), self.bandwidth_limit, work).await
     }
 }
 
+/// Generates [`Protocol`] and [`Reference`] from the registered providers,
+/// along with the methods that dispatch to the provider for each variant.
+///
+/// Each provider is registered with the name of its variant, the type implementing [`Provider`],
+/// and the type implementing [`ProviderReference`] for its references.
+macro_rules! providers {
+    ($($(#[$meta:meta])* $variant:ident($provider:ty, $reference:ty)),+ $(,)?) => {
+        /// Code is stored in many kinds of locations, from git repos to
+        /// random FTP sites to DevOps hosts like GitHub.
+        ///
+        /// To handle this variety, Broker uses a predefined list
+        /// of supported protocols (this type),
+        /// which are specialized with configuration unique to those integrations.
+        ///
+        /// Variants are generated from the providers registered with `providers!`.
+        #[derive(Debug, Clone, PartialEq, Eq, From, Deserialize, Serialize, new)]
+        pub enum Protocol {
+            $($(#[$meta])* $variant($provider),)+
+        }
+
+        impl Display for Protocol {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $(Protocol::$variant(provider) => {
+                        write!(f, "{}::{provider}", <$provider as Provider>::NAME)
+                    })+
+                }
+            }
+        }
+
+        impl Protocol {
+            /// The endpoint for the protocol.
+            pub fn endpoint(&self) -> &Remote {
+                match self {
+                    $(Protocol::$variant(provider) => provider.endpoint(),)+
+                }
+            }
+
+            /// The name of the project in FOSSA.
+            pub fn project(&self) -> String {
+                match self {
+                    $(Protocol::$variant(provider) => provider.project(),)+
+                }
+            }
+
+            /// The names under which a project for this protocol may already exist in FOSSA, most preferred first.
+            pub fn existing_projects(&self) -> Vec<String> {
+                match self {
+                    $(Protocol::$variant(provider) => provider.existing_projects(),)+
+                }
+            }
+
+            /// The database namespace for the protocol's coordinates.
+            pub fn namespace(&self) -> db::Namespace {
+                match self {
+                    $(Protocol::$variant(_) => <$provider as Provider>::NAMESPACE,)+
+                }
+            }
+
+            /// The representation of the protocol's endpoint used for its database coordinates.
+            pub fn repository(&self) -> String {
+                match self {
+                    $(Protocol::$variant(provider) => <$provider as Provider>::repository(provider.endpoint()),)+
+                }
+            }
+
+            /// The representation of another remote used for database coordinates, as if it were the protocol's endpoint.
+            pub fn repository_of(&self, remote: &Remote) -> String {
+                match self {
+                    $(Protocol::$variant(_) => <$provider as Provider>::repository(remote),)+
+                }
+            }
+
+            /// The people who recently committed to the code checked out at the provided location,
+            /// if the provider can tell.
+            pub async fn contributors(&self, checkout: &Path) -> Result<Option<Contributors>, Report<RemoteProviderError>> {
+                match self {
+                    $(Protocol::$variant(provider) => provider.contributors(checkout).await,)+
+                }
+            }
+
+            /// The hash of the tree checked out at the provided location, if the provider can identify its content.
+            pub async fn tree_hash(&self, checkout: &Path) -> Result<Option<String>, Report<RemoteProviderError>> {
+                match self {
+                    $(Protocol::$variant(provider) => provider.tree_hash(checkout).await,)+
+                }
+            }
+
+            /// Whether the code checked out at the provided location descends from a previous state of the same reference,
+            /// if the provider has history.
+            pub async fn descends_from(&self, checkout: &Path, previous: &[u8]) -> Result<Option<bool>, Report<RemoteProviderError>> {
+                match self {
+                    $(Protocol::$variant(provider) => provider.descends_from(checkout, previous).await,)+
+                }
+            }
+
+            /// Check that the code host can be reached with the configured authentication.
+            pub async fn check_connection(&self) -> Result<(), Report<RemoteProviderError>> {
+                match self {
+                    $(Protocol::$variant(provider) => provider.check_connection().await,)+
+                }
+            }
+
+            /// List all references on the code host.
+            pub async fn references(&self) -> Result<Vec<Reference>, Report<RemoteProviderError>> {
+                match self {
+                    $(Protocol::$variant(provider) => provider
+                        .references()
+                        .await
+                        .map(|references| references.into_iter().map(Reference::$variant).collect()),)+
+                }
+            }
+
+            /// List the references on the code host which are included by the filter.
+            pub async fn references_matching(&self, include: &ReferenceFilter) -> Result<Vec<Reference>, Report<RemoteProviderError>> {
+                match self {
+                    $(Protocol::$variant(provider) => provider
+                        .references_matching(&|reference: &$reference| include(ProviderReference::branch(reference)))
+                        .await
+                        .map(|references| references.into_iter().map(Reference::$variant).collect()),)+
+                }
+            }
+
+            /// Clone a [`Reference`] into a temporary directory.
+            pub async fn clone_reference(
+                &self,
+                reference: &Reference,
+            ) -> Result<TempDir, Report<RemoteProviderError>> {
+                match (self, reference) {
+                    $((Protocol::$variant(provider), Reference::$variant(reference)) => {
+                        provider.clone_reference(reference).await
+                    })+
+                    // Only reachable once more than one provider is registered.
+                    #[allow(unreachable_patterns)]
+                    _ => mismatched_reference(self, reference),
+                }
+            }
+
+            /// Clone only the files listed in [`ScanMode::MANIFESTS`] for a [`Reference`] into a temporary directory.
+            pub async fn clone_manifests(
+                &self,
+                reference: &Reference,
+            ) -> Result<TempDir, Report<RemoteProviderError>> {
+                match (self, reference) {
+                    $((Protocol::$variant(provider), Reference::$variant(reference)) => {
+                        provider.clone_manifests(reference).await
+                    })+
+                    // Only reachable once more than one provider is registered.
+                    #[allow(unreachable_patterns)]
+                    _ => mismatched_reference(self, reference),
+                }
+            }
+
+            /// Fetch the latest state of the remote into a persistent mirror at the provided location,
+            /// creating the mirror if it doesn't yet exist.
+            pub async fn update_mirror(&self, mirror: &Path) -> Result<(), Report<RemoteProviderError>> {
+                match self {
+                    $(Protocol::$variant(provider) => provider.update_mirror(mirror).await,)+
+                }
+            }
+
+            /// Check out a [`Reference`] from the persistent mirror at the provided location
+            /// into a temporary directory.
+            pub async fn checkout_from_mirror(
+                &self,
+                mirror: &Path,
+                reference: &Reference,
+            ) -> Result<TempDir, Report<RemoteProviderError>> {
+                match (self, reference) {
+                    $((Protocol::$variant(provider), Reference::$variant(reference)) => {
+                        provider.checkout_from_mirror(mirror, reference).await
+                    })+
+                    // Only reachable once more than one provider is registered.
+                    #[allow(unreachable_patterns)]
+                    _ => mismatched_reference(self, reference),
+                }
+            }
+        }
+
+        /// Remotes can reference specific points in time on a remote unit of code.
+        ///
+        /// Variants are generated from the providers registered with `providers!`.
+        #[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+        pub enum Reference {
+            $(
+                #[doc = concat!("A reference on a remote using [`Protocol::", stringify!($variant), "`].")]
+                $variant($reference),
+            )+
+        }
+
+        impl Reference {
+            /// Given a remote, create a database coordinate from this reference.
+            pub fn as_coordinate(&self, remote: &Remote) -> db::Coordinate {
+                match self {
+                    $(Reference::$variant(reference) => db::Coordinate::new(
+                        <$provider as Provider>::NAMESPACE,
+                        <$provider as Provider>::repository(remote),
+                        format!("{}:{}", <$provider as Provider>::NAME, reference.for_coordinate()),
+                    ),)+
+                }
+            }
+
+            /// The database coordinate and state with which earlier versions of Broker recorded this reference,
+            /// if they differ from [`Reference::as_coordinate`] and [`Reference::as_state`].
+            pub fn legacy_coordinate(&self, remote: &Remote) -> Option<(db::Coordinate, &[u8])> {
+                match self {
+                    $(Reference::$variant(reference) => reference.legacy_coordinate().map(|(revision, state)| {
+                        let coordinate = db::Coordinate::new(
+                            <$provider as Provider>::NAMESPACE,
+                            <$provider as Provider>::repository(remote),
+                            format!("{}:{revision}", <$provider as Provider>::NAME),
+                        );
+                        (coordinate, state)
+                    }),)+
+                }
+            }
+
+            /// Generate a canonical state for the reference.
+            pub fn as_state(&self) -> &[u8] {
+                match self {
+                    $(Reference::$variant(reference) => reference.as_state(),)+
+                }
+            }
+
+            /// The name of the reference's branch or tag
+            pub fn name(&self) -> &str {
+                match self {
+                    $(Reference::$variant(reference) => ProviderReference::name(reference),)+
+                }
+            }
+
+            /// The branch this reference tracks, if it is a branch.
+            pub fn branch(&self) -> Option<&str> {
+                match self {
+                    $(Reference::$variant(reference) => reference.branch(),)+
+                }
+            }
+
+            /// Whether the reference is a branch (as opposed to a tag or other kind of reference).
+            pub fn is_branch(&self) -> bool {
+                self.branch().is_some()
+            }
+
+            /// The revision reported to FOSSA for the reference.
+            pub fn revision(&self) -> &str {
+                match self {
+                    $(Reference::$variant(reference) => reference.revision(),)+
+                }
+            }
+
+            /// The commit at which the reference points, if references at the same commit always have the same code.
+            pub fn commit(&self) -> Option<&str> {
+                match self {
+                    $(Reference::$variant(reference) => ProviderReference::commit(reference),)+
+                }
+            }
+
+            /// The prefixes of the revisions in database coordinates (see [`Reference::as_coordinate`])
+            /// for any reference with the provided name, regardless of its state.
+            pub fn coordinate_prefixes(name: &str) -> Vec<String> {
+                let mut prefixes = Vec::new();
+                $(prefixes.extend(
+                    <$reference as ProviderReference>::coordinate_prefixes(name)
+                        .into_iter()
+                        .map(|prefix| format!("{}:{prefix}", <$provider as Provider>::NAME)),
+                );)+
+                prefixes
+            }
+        }
+
+        impl Display for Reference {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $(Reference::$variant(reference) => {
+                        write!(f, "{}::{reference}", <$provider as Provider>::NAME)
+                    })+
+                }
+            }
+        }
+    };
+}
+
+providers! {
+    /// Integration with a code host using the git protocol.
+    Git(git::transport::Transport, git::Reference),
+    /// Integration with a directory on the Broker host.
+    Local(local::Directory, local::Snapshot),
+    /// Integration with versioned archives in a directory or on an HTTP index page.
+    Archive(archive::Archives, archive::Release),
+    /// Integration with archives stored in an S3 or Google Cloud Storage bucket.
+    Bucket(bucket::Bucket, bucket::Object),
+    /// Integration with a stream depot on a Perforce server.
+    Perforce(perforce::Depot, perforce::Reference),
+    /// Integration with a project on a Gerrit Code Review server.
+    Gerrit(gerrit::Project, gerrit::Reference),
+}
+
+/// Report that a reference was used with a protocol from a different provider.
+fn mismatched_reference<T>(
+    protocol: &Protocol,
+    reference: &Reference,
+) -> Result<T, Report<RemoteProviderError>> {
+    report!(RemoteProviderError::MismatchedReference)
+        .wrap_err()
+        .describe_lazy(|| format!("protocol: '{protocol}', reference: '{reference}'"))
+}
+
 /// Specifies the maximum age for an observability artifact.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, AsRef, From, Deserialize, Serialize, new)]
 pub struct PollInterval(Duration);
@@ -236,6 +899,182 @@ impl PollInterval {
     }
 }
 
+/// Specifies the maximum amount of time a job (such as cloning or analyzing a reference) may run.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, AsRef, From, Deserialize, Serialize, new)]
+pub struct JobTimeout(Duration);
+
+impl JobTimeout {
+    /// The default timeout for cloning a reference.
+    pub const DEFAULT_CLONE: JobTimeout = JobTimeout(Duration::from_secs(60 * 60));
+
+    /// The default timeout for analyzing a reference.
+    pub const DEFAULT_SCAN: JobTimeout = JobTimeout(Duration::from_secs(4 * 60 * 60));
+
+    /// The timeout expressed as a [`Duration`].
+    pub fn as_duration(&self) -> Duration {
+        self.0
+    }
+
+    fn default_clone() -> Self {
+        Self::DEFAULT_CLONE
+    }
+
+    fn default_scan() -> Self {
+        Self::DEFAULT_SCAN
+    }
+}
+
+impl TryFrom<String> for JobTimeout {
+    type Error = Report<ValidationError>;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let parsed = parse_duration(&value)
+            .context(ValidationError::JobTimeout)
+            .describe_lazy(|| format!("provided value: {value}"))?;
+
+        ensure!(!parsed.is_zero(), ValidationError::JobTimeoutZero);
+        JobTimeout(parsed).wrap_ok()
+    }
+}
+
+/// How many times slower than the recent average a clone or analysis must be before it is reported as slow.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SlowScanMultiple(f64);
+
+// The multiple is validated to be finite, so it is never NaN.
+impl Eq for SlowScanMultiple {}
+
+impl SlowScanMultiple {
+    /// The default multiple.
+    pub const DEFAULT: SlowScanMultiple = SlowScanMultiple(3.0);
+
+    /// The multiple as a number.
+    pub fn as_f64(&self) -> f64 {
+        self.0
+    }
+}
+
+impl Default for SlowScanMultiple {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl TryFrom<f64> for SlowScanMultiple {
+    type Error = Report<ValidationError>;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        ensure!(
+            value.is_finite() && value > 1.0,
+            ValidationError::SlowScanMultiple
+        );
+        SlowScanMultiple(value).wrap_ok()
+    }
+}
+
+/// A daily window of time, in UTC, during which an integration may be polled.
+///
+/// The window may wrap past midnight; for example `22:00-04:00 UTC` is a six hour window.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PollWindow {
+    /// The start of the window, in seconds since midnight UTC.
+    start: u32,
+
+    /// The end of the window, in seconds since midnight UTC.
+    end: u32,
+}
+
+impl PollWindow {
+    const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+
+    /// The length of the window.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(Self::seconds_until(self.start, self.end).into())
+    }
+
+    /// How long to wait from `now` until the window is open.
+    /// If the window is open at `now`, this is zero.
+    pub fn wait_from(&self, now: SystemTime) -> Duration {
+        let now = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            % u64::from(Self::SECONDS_PER_DAY);
+
+        // The remainder is less than the number of seconds in a day, so it always fits.
+        let now = u32::try_from(now).unwrap_or_default();
+        if Self::seconds_until(self.start, now) < Self::seconds_until(self.start, self.end) {
+            Duration::ZERO
+        } else {
+            Duration::from_secs(Self::seconds_until(now, self.start).into())
+        }
+    }
+
+    /// The number of seconds from `from` until the next time it's `to`, wrapping past midnight.
+    fn seconds_until(from: u32, to: u32) -> u32 {
+        (to + Self::SECONDS_PER_DAY - from) % Self::SECONDS_PER_DAY
+    }
+
+    /// Parse a time of day in the form `HH:MM` into seconds since midnight.
+    fn parse_time(value: &str) -> Option<u32> {
+        let (hours, minutes) = value.trim().split_once(':')?;
+        let hours = hours.parse::<u32>().ok().filter(|hours| *hours < 24)?;
+        let minutes = minutes
+            .parse::<u32>()
+            .ok()
+            .filter(|minutes| *minutes < 60)?;
+        Some(hours * 60 * 60 + minutes * 60)
+    }
+}
+
+impl Display for PollWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format = |seconds: u32| format!("{:02}:{:02}", seconds / 3600, seconds % 3600 / 60);
+        write!(f, "{}-{} UTC", format(self.start), format(self.end))
+    }
+}
+
+impl TryFrom<String> for PollWindow {
+    type Error = Report<ValidationError>;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let window = value
+            .trim()
+            .strip_suffix("UTC")
+            .ok_or_else(|| report!(ValidationError::PollWindow))
+            .help("poll windows are specified in UTC, like '01:00-05:00 UTC'")
+            .describe_lazy(|| format!("provided value: {value}"))?;
+
+        let (start, end) = window
+            .split_once('-')
+            .and_then(|(start, end)| Some((Self::parse_time(start)?, Self::parse_time(end)?)))
+            .ok_or_else(|| report!(ValidationError::PollWindow))
+            .help("poll windows are a start and end time of day, like '01:00-05:00 UTC'")
+            .describe_lazy(|| format!("provided value: {value}"))?;
+
+        ensure!(start != end, ValidationError::PollWindowEmpty);
+        PollWindow { start, end }.wrap_ok()
+    }
+}
+
+/// When several integrations have scans pending, scan workers take turns between them.
+/// Each turn, an integration may scan up to its weight in references before the next integration's turn.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, AsRef, From, Deserialize, Serialize, new)]
+pub struct ScanWeight(NonZeroU32);
+
+impl ScanWeight {
+    /// The scan weight expressed as a [`NonZeroU32`].
+    pub fn as_nonzero(&self) -> NonZeroU32 {
+        self.0
+    }
+}
+
+impl Default for ScanWeight {
+    fn default() -> Self {
+        Self(nonzero!(1u32))
+    }
+}
+
 /// This is set because Broker is intended to bring eventual observability;
 /// if users want faster polling than this it's probably because they want to make sure they don't miss revisions,
 /// in such a case we recommend CI integration.
@@ -333,6 +1172,150 @@ impl TagImportStrategy {
     }
 }
 
+/// Specifies how Broker obtains the code for references it scans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display, Deserialize, Serialize, new)]
+pub enum CloneStrategy {
+    /// Each reference is cloned from the remote into a fresh blobless clone.
+    #[default]
+    Blobless,
+
+    /// A persistent mirror of the remote is kept in the data root.
+    /// The mirror is fetched on poll, and references are checked out from it.
+    Mirror,
+}
+
+impl From<Option<bool>> for CloneStrategy {
+    fn from(val: Option<bool>) -> CloneStrategy {
+        match val {
+            Some(true) => CloneStrategy::Mirror,
+            // False case maps to blobless, and if it is None we default to blobless
+            _ => CloneStrategy::Blobless,
+        }
+    }
+}
+
+/// Specifies how much of a reference's code Broker obtains to scan it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScanMode {
+    /// Every file in the reference is obtained and analyzed.
+    #[default]
+    Full,
+
+    /// Only dependency manifests and lockfiles are obtained and analyzed,
+    /// which greatly reduces the data downloaded and written to disk for large repositories.
+    ///
+    /// Analyses which need the rest of the code (for example, to run a build tool) may find fewer dependencies.
+    ManifestsOnly,
+}
+
+impl ScanMode {
+    /// The files obtained in [`ScanMode::ManifestsOnly`], as gitignore-style patterns matched in any directory.
+    pub const MANIFESTS: &'static [&'static str] = &[
+        // Broker's import marker, and configuration for FOSSA CLI.
+        ".broker-import",
+        ".fossa.yml",
+        ".fossa.yaml",
+        "fossa-deps.yml",
+        "fossa-deps.yaml",
+        "fossa-deps.json",
+        // JavaScript
+        "package.json",
+        "package-lock.json",
+        "npm-shrinkwrap.json",
+        "yarn.lock",
+        "pnpm-lock.yaml",
+        "bower.json",
+        // Go
+        "go.mod",
+        "go.sum",
+        "Gopkg.toml",
+        "Gopkg.lock",
+        "glide.yaml",
+        "glide.lock",
+        // Python
+        "requirements*.txt",
+        "setup.py",
+        "setup.cfg",
+        "pyproject.toml",
+        "poetry.lock",
+        "Pipfile",
+        "Pipfile.lock",
+        "environment.yml",
+        // Ruby
+        "Gemfile",
+        "Gemfile.lock",
+        "*.gemspec",
+        // JVM
+        "pom.xml",
+        "build.gradle",
+        "build.gradle.kts",
+        "settings.gradle",
+        "settings.gradle.kts",
+        "gradle.lockfile",
+        "build.sbt",
+        "project.clj",
+        "deps.edn",
+        // .NET
+        "*.csproj",
+        "*.vbproj",
+        "*.fsproj",
+        "*.nuspec",
+        "packages.config",
+        "packages.lock.json",
+        "project.assets.json",
+        "paket.lock",
+        // Rust
+        "Cargo.toml",
+        "Cargo.lock",
+        // PHP
+        "composer.json",
+        "composer.lock",
+        // Swift and Objective-C
+        "Package.swift",
+        "Package.resolved",
+        "Podfile",
+        "Podfile.lock",
+        "Cartfile",
+        "Cartfile.resolved",
+        // Elixir and Erlang
+        "mix.exs",
+        "mix.lock",
+        "rebar.config",
+        "rebar.lock",
+        // Dart
+        "pubspec.yaml",
+        "pubspec.lock",
+        // Haskell
+        "*.cabal",
+        "cabal.project",
+        "cabal.project.freeze",
+        "stack.yaml",
+        "stack.yaml.lock",
+        // C and C++
+        "conanfile.txt",
+        "conanfile.py",
+        "conan.lock",
+    ];
+}
+
+/// Specifies which references Broker scans the first time it polls an integration after starting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanOnStartup {
+    /// Every reference the integration is configured to scan is scanned,
+    /// regardless of whether it has changed since it was last scanned.
+    All,
+
+    /// Only references which have changed since they were last scanned are scanned.
+    #[default]
+    Changed,
+
+    /// No references are scanned; instead their current state is recorded as scanned,
+    /// so that only references which change afterwards are scanned.
+    None,
+}
+
 /// The integration's branch that you intend to scan
 #[derive(Debug, Clone, PartialEq, Eq, AsRef, Display, Deserialize, Serialize, new)]
 pub struct WatchedBranch(String);
@@ -344,52 +1327,318 @@ impl WatchedBranch {
     }
 }
 
-/// Errors encountered while working with remotes
-#[derive(Debug, thiserror::Error)]
-pub enum RemoteProviderError {
-    /// We encountered an error while shelling out to an external command
-    #[error("run external command")]
-    RunCommand,
+/// A pattern for branches the integration doesn't scan, even if they match a watched branch.
+#[derive(Debug, Clone, PartialEq, Eq, AsRef, Display, Deserialize, Serialize)]
+pub struct ExcludedBranch(String);
+
+impl ExcludedBranch {
+    /// The pattern for the excluded branches
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether the branch is excluded by this pattern
+    pub fn matches(&self, reference: &str) -> bool {
+        Pattern::new(&self.0)
+            .map(|pattern| pattern.matches(reference))
+            .unwrap_or(false)
+    }
 }
 
-/// Remotes can reference specific points in time on a remote unit of code.
-#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
-pub enum Reference {
-    /// Git remotes have their own reference format.
-    Git(git::Reference),
+impl TryFrom<String> for ExcludedBranch {
+    type Error = Report<ValidationError>;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Pattern::new(&value)
+            .context_lazy(|| ValidationError::ExcludedBranch(value.clone()))
+            .help("excluded branches are glob patterns, like 'release/*-rc*'")?;
+        Ok(Self(value))
+    }
 }
 
-impl Reference {
-    /// Given a remote, create a database coordinate from this reference.
-    pub fn as_coordinate(&self, remote: &Remote) -> db::Coordinate {
-        db::Coordinate::new(
-            db::Namespace::Git,
-            remote.for_coordinate(),
-            match self {
-                Reference::Git(reference) => format!("git:{}", reference.for_coordinate()),
-            },
-        )
+/// Uploads scans of the branches matching a pattern to a different FOSSA project or branch than the integration's,
+/// for example to keep release branches in their own project.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, Deserialize, Serialize)]
+#[getset(get = "pub")]
+pub struct BranchMapping {
+    /// The pattern for the branches to which the mapping applies.
+    branches: String,
+
+    /// The FOSSA project to which matching branches are uploaded, instead of the integration's project.
+    project: Option<String>,
+
+    /// The FOSSA branch under which matching branches are uploaded, instead of the branch that was scanned.
+    branch: Option<String>,
+}
+
+impl BranchMapping {
+    /// Validate the mapping.
+    pub fn validate(
+        branches: String,
+        project: Option<String>,
+        branch: Option<String>,
+    ) -> Result<Self, Report<ValidationError>> {
+        Pattern::new(&branches)
+            .context_lazy(|| ValidationError::BranchMapping(branches.clone()))
+            .help("the branches of branch mappings are glob patterns, like 'release/*'")?;
+        let empty = |value: &Option<String>| value.as_deref().map(str::trim) == Some("");
+        if (project.is_none() && branch.is_none()) || empty(&project) || empty(&branch) {
+            return report!(ValidationError::BranchMapping(branches))
+                .wrap_err()
+                .help("provide the 'project' or the 'branch' to which the branches are uploaded");
+        }
+        Ok(Self {
+            branches,
+            project,
+            branch,
+        })
     }
 
-    /// Generate a canonical state for the reference.
-    pub fn as_state(&self) -> &[u8] {
+    /// Whether the mapping applies to the branch.
+    pub fn matches(&self, branch: &str) -> bool {
+        Pattern::new(&self.branches)
+            .map(|pattern| pattern.matches(branch))
+            .unwrap_or(false)
+    }
+
+    /// Whether the patterns of the mappings overlap, as far as can be told without enumerating branches:
+    /// they overlap if either pattern matches the other, such as `release/*` and `release/1.*`.
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.matches(&other.branches) || other.matches(&self.branches)
+    }
+}
+
+/// An environment variable set for FOSSA CLI when it analyzes the integration's code,
+/// for example `GOFLAGS` or credentials for a private package registry.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, Deserialize, Serialize, new)]
+pub struct CliEnv {
+    /// The name of the variable.
+    #[getset(get = "pub")]
+    name: String,
+
+    /// The value of the variable.
+    #[getset(get = "pub")]
+    value: CliEnvValue,
+}
+
+/// The value of an environment variable set for FOSSA CLI.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CliEnvValue {
+    /// The value is shown as-is in logs and debugging output.
+    Plain(String),
+
+    /// The value is redacted from logs, debugging output, and the output of FOSSA CLI.
+    Secret(ComparableSecretString),
+}
+
+impl CliEnvValue {
+    /// Convert into a value for a [`Command`](crate::ext::command::Command),
+    /// preserving whether it is secret.
+    pub fn to_command_value(&self) -> command::Value {
         match self {
-            Reference::Git(git) => git.as_state(),
+            CliEnvValue::Plain(value) => command::Value::new_plain(value),
+            CliEnvValue::Secret(value) => command::Value::new_secret(value.clone()),
         }
     }
+}
 
-    /// The name of the reference's branch or tag
-    pub fn name(&self) -> &str {
+/// Options for FOSSA CLI which Broker translates into arguments when it analyzes the integration's code.
+///
+/// Each option corresponds to a flag of `fossa analyze`; options left unset don't add any arguments,
+/// so FOSSA CLI uses its own default.
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, Getters, CopyGetters, Deserialize, Serialize, TypedBuilder,
+)]
+pub struct CliOptions {
+    /// Whether FOSSA CLI extracts archives in the project and analyzes their contents.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    unpack_archives: bool,
+
+    /// Whether FOSSA CLI analyzes paths it skips by default, like `node_modules` or test directories.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    without_default_filters: bool,
+
+    /// Only these paths, relative to the root of the project, are analyzed.
+    #[getset(get = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    only_paths: Vec<String>,
+
+    /// These paths, relative to the root of the project, are not analyzed.
+    #[getset(get = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    exclude_paths: Vec<String>,
+
+    /// Whether FOSSA CLI looks for vendored C and C++ code.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    detect_vendored: bool,
+
+    /// How FOSSA CLI scans vendored dependencies for licenses, if overridden.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    vendored_scan_method: Option<VendoredScanMethod>,
+
+    /// Whether FOSSA CLI rescans vendored dependencies which FOSSA has already scanned.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    force_vendored_rescans: bool,
+
+    /// Whether FOSSA CLI scans first-party code for licenses, if overridden.
+    #[getset(get_copy = "pub")]
+    #[builder(default)]
+    #[serde(default)]
+    first_party_scans: Option<FirstPartyScans>,
+}
+
+impl CliOptions {
+    /// The arguments for `fossa analyze` which apply these options.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.unpack_archives {
+            args.push(String::from("--unpack-archives"));
+        }
+        if self.without_default_filters {
+            args.push(String::from("--without-default-filters"));
+        }
+        for path in &self.only_paths {
+            args.extend([String::from("--only-path"), path.clone()]);
+        }
+        for path in &self.exclude_paths {
+            args.extend([String::from("--exclude-path"), path.clone()]);
+        }
+        if self.detect_vendored {
+            args.push(String::from("--detect-vendored"));
+        }
+        if let Some(method) = self.vendored_scan_method {
+            args.push(String::from("--force-vendored-dependency-scan-method"));
+            args.push(method.cli_value().to_string());
+        }
+        if self.force_vendored_rescans {
+            args.push(String::from("--force-vendored-dependency-rescans"));
+        }
+        match self.first_party_scans {
+            Some(FirstPartyScans::Force) => {
+                args.push(String::from("--experimental-force-first-party-scans"))
+            }
+            Some(FirstPartyScans::Block) => {
+                args.push(String::from("--experimental-block-first-party-scans"))
+            }
+            None => {}
+        }
+        args
+    }
+}
+
+/// Validate a path provided to FOSSA CLI in [`CliOptions`].
+///
+/// FOSSA CLI interprets these paths relative to the root of the project,
+/// so they must be relative and must not leave the project.
+pub fn validate_cli_option_path(path: String) -> Result<String, Report<ValidationError>> {
+    let escapes = Path::new(&path).components().any(|component| {
+        matches!(
+            component,
+            std::path::Component::ParentDir
+                | std::path::Component::RootDir
+                | std::path::Component::Prefix(_)
+        )
+    });
+    if path.trim().is_empty() || escapes {
+        return report!(ValidationError::CliOptionPath(path))
+            .wrap_err()
+            .help(
+                "provide a path relative to the root of the project, like 'vendor' or 'src/lib'",
+            );
+    }
+    Ok(path)
+}
+
+/// How FOSSA CLI scans vendored dependencies for licenses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VendoredScanMethod {
+    /// FOSSA CLI scans vendored dependencies for licenses locally, and uploads only the results.
+    LicenseScan,
+
+    /// FOSSA CLI uploads vendored dependencies to FOSSA, which scans them for licenses.
+    ArchiveUpload,
+}
+
+impl VendoredScanMethod {
+    /// The value FOSSA CLI accepts for this method.
+    pub fn cli_value(self) -> &'static str {
         match self {
-            Reference::Git(git) => git.name().as_str(),
+            VendoredScanMethod::LicenseScan => "CLILicenseScan",
+            VendoredScanMethod::ArchiveUpload => "ArchiveUpload",
         }
     }
 }
 
-impl Display for Reference {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// Whether FOSSA CLI scans first-party code for licenses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FirstPartyScans {
+    /// First-party code is scanned, even if the organization's FOSSA settings disable it.
+    Force,
+
+    /// First-party code is not scanned, even if the organization's FOSSA settings enable it.
+    Block,
+}
+
+/// The people who recently committed to the code of an integration,
+/// keyed by email address with the date (`YYYY-MM-DD`) of their latest commit.
+///
+/// FOSSA uses these to count contributors for licensing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, AsRef, From, Deserialize, Serialize, new)]
+pub struct Contributors(BTreeMap<String, String>);
+
+impl Contributors {
+    /// Whether nobody committed recently.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The number of contributors.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Errors encountered while working with remotes
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteProviderError {
+    /// We encountered an error while shelling out to an external command
+    #[error("run external command")]
+    RunCommand,
+
+    /// A reference was used with a protocol from a different provider.
+    #[error("reference does not belong to the protocol")]
+    MismatchedReference,
+
+    /// We couldn't read from the location of the code.
+    #[error("read from remote location")]
+    ReadLocation,
+
+    /// We couldn't extract an archive.
+    #[error("extract archive")]
+    Extract,
+}
+
+impl HasErrorCode for RemoteProviderError {
+    fn code(&self) -> ErrorCode {
         match self {
-            Reference::Git(reference) => write!(f, "git::{reference}"),
+            Self::RunCommand => ErrorCode::new(1251),
+            Self::MismatchedReference => ErrorCode::new(1252),
+            Self::ReadLocation => ErrorCode::new(1253),
+            Self::Extract => ErrorCode::new(1254),
         }
     }
 }
@@ -408,8 +1657,27 @@ pub trait RemoteProvider {
 
     /// List all references
     async fn references(&self) -> Result<Vec<Self::Reference>, Report<RemoteProviderError>>;
+
+    /// List the references for which `include` returns true.
+    ///
+    /// By default every reference is listed and then filtered;
+    /// providers which may list a very large number of references override this to filter while listing,
+    /// so that references which aren't included are never collected.
+    async fn references_matching(
+        &self,
+        include: &(dyn Fn(&Self::Reference) -> bool + Send + Sync),
+    ) -> Result<Vec<Self::Reference>, Report<RemoteProviderError>> {
+        let references = self.references().await?;
+        Ok(references
+            .into_iter()
+            .filter(|reference| include(reference))
+            .collect())
+    }
 }
 
+/// Decides whether a listed reference is included, given the branch it tracks (or `None` if it isn't a branch).
+pub type ReferenceFilter = dyn Fn(Option<&str>) -> bool + Send + Sync;
+
 #[async_trait]
 impl RemoteProvider for Integration {
     type Reference = Reference;
@@ -418,23 +1686,94 @@ impl RemoteProvider for Integration {
         &self,
         reference: &Self::Reference,
     ) -> Result<TempDir, Report<RemoteProviderError>> {
-        match self.protocol() {
-            // This is a little awkward because these two types are _semantically related_,
-            // but are not related in the code.
-            // Right now we're considering this not worth fixing,
-            // but as we add more protocols/references it's probably worth revisiting.
-            Protocol::Git(transport) => match reference {
-                Reference::Git(reference) => transport.clone_reference(reference).await,
-            },
-        }
+        self.protocol.clone_reference(reference).await
     }
 
     async fn references(&self) -> Result<Vec<Self::Reference>, Report<RemoteProviderError>> {
-        match self.protocol() {
-            Protocol::Git(proto) => proto
-                .references()
-                .await
-                .map(|refs| refs.into_iter().map(Reference::Git).collect()),
+        self.protocol.references().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hours: u64, minutes: u64) -> SystemTime {
+        // An arbitrary day, so that the tests aren't only exercising the epoch.
+        let day = 19_000 * 24 * 60 * 60;
+        SystemTime::UNIX_EPOCH + Duration::from_secs(day + hours * 60 * 60 + minutes * 60)
+    }
+
+    #[test]
+    fn parses_poll_window() {
+        let window = PollWindow::try_from(String::from("01:00-05:30 UTC")).expect("must parse");
+        assert_eq!(window.to_string(), "01:00-05:30 UTC");
+        assert_eq!(
+            window.duration(),
+            Duration::from_secs(4 * 60 * 60 + 30 * 60)
+        );
+
+        for invalid in [
+            "01:00-05:00",
+            "01:00 UTC",
+            "25:00-05:00 UTC",
+            "01:00-01:00 UTC",
+        ] {
+            let parsed = PollWindow::try_from(String::from(invalid));
+            assert!(parsed.is_err(), "must not parse '{invalid}'");
         }
     }
+
+    #[test]
+    fn waits_for_poll_window() {
+        let window = PollWindow::try_from(String::from("01:00-05:00 UTC")).expect("must parse");
+        assert_eq!(window.wait_from(at(1, 0)), Duration::ZERO);
+        assert_eq!(window.wait_from(at(4, 59)), Duration::ZERO);
+        assert_eq!(window.wait_from(at(0, 30)), Duration::from_secs(30 * 60));
+        assert_eq!(
+            window.wait_from(at(5, 0)),
+            Duration::from_secs(20 * 60 * 60)
+        );
+    }
+
+    #[test]
+    fn waits_for_poll_window_past_midnight() {
+        let window = PollWindow::try_from(String::from("22:00-04:00 UTC")).expect("must parse");
+        assert_eq!(window.duration(), Duration::from_secs(6 * 60 * 60));
+        assert_eq!(window.wait_from(at(23, 0)), Duration::ZERO);
+        assert_eq!(window.wait_from(at(2, 0)), Duration::ZERO);
+        assert_eq!(
+            window.wait_from(at(12, 0)),
+            Duration::from_secs(10 * 60 * 60)
+        );
+    }
+
+    #[test]
+    fn dispatches_to_provider() {
+        let remote = Remote::new(String::from("git@github.com:fossas/broker.git"));
+        let reference = Reference::Git(git::Reference::new_branch(
+            String::from("main"),
+            String::from("abcd1234"),
+        ));
+
+        assert_eq!(reference.to_string(), "git::branch::main@abcd1234");
+        assert_eq!(reference.name(), "main");
+        assert_eq!(reference.branch(), Some("main"));
+        assert_eq!(reference.revision(), "abcd1234");
+        assert_eq!(
+            reference.as_coordinate(&remote),
+            db::Coordinate::new(
+                db::Namespace::Git,
+                String::from("github.com/fossas/broker"),
+                String::from("git:branch:main@abcd1234"),
+            )
+        );
+        assert_eq!(
+            Reference::coordinate_prefixes("main"),
+            vec![
+                String::from("git:branch:main@"),
+                String::from("git:tag:main@")
+            ]
+        );
+    }
 }
@@ -0,0 +1,452 @@
+//! Powers integration with code delivered as versioned archives, like `myapp-1.2.3.tar.gz`.
+//!
+//! Archives are listed from either a directory on the Broker host or an HTTP index page,
+//! such as a web server's directory listing, which links to them.
+//! Each archive is a [`Release`]; when a new one appears it is extracted
+//! and scanned with the version from its file name as the revision.
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use derive_new::new;
+use error_stack::{Report, ResultExt};
+use getset::Getters;
+use itertools::Itertools;
+use libflate::gzip;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tempfile::{NamedTempFile, TempDir};
+use url::Url;
+
+use crate::{
+    api::http::client::{Clients, Purpose},
+    bandwidth, db,
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        io::spawn_blocking_wrap,
+        result::DiscardResult,
+        tempfile::{named_tempfile_with_suffix, tempdir},
+    },
+};
+
+use super::{Provider, ProviderReference, Remote, RemoteProvider, RemoteProviderError};
+
+/// Archive file extensions Broker knows how to extract, and the format of each.
+const EXTENSIONS: &[(&str, Format)] = &[
+    (".tar.gz", Format::TarGz),
+    (".tgz", Format::TarGz),
+    (".tar", Format::Tar),
+    (".zip", Format::Zip),
+];
+
+/// Matches the file name of an archive (without its extension),
+/// capturing the version after the last `-` that is followed by a digit.
+static VERSION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^.+?-(?P<version>\d[^/\\]*)$").expect("invariant: version pattern must be valid")
+});
+
+/// Matches links on an HTTP index page.
+static LINK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)href\s*=\s*["']([^"']+)["']"#).expect("invariant: link pattern must be valid")
+});
+
+/// The formats of archive Broker can extract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub(super) enum Format {
+    TarGz,
+    Tar,
+    Zip,
+}
+
+/// A location from which versioned archives are listed.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, Deserialize, Serialize, new)]
+pub struct Archives {
+    /// The directory or HTTP index page, as written in the config file.
+    #[getset(get = "pub")]
+    endpoint: Remote,
+
+    /// The name of the project in FOSSA, if it differs from the endpoint.
+    #[getset(get = "pub")]
+    project: Option<String>,
+}
+
+impl Display for Archives {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.endpoint)
+    }
+}
+
+impl Archives {
+    /// Where the archives are listed.
+    pub fn location(&self) -> Location {
+        let endpoint = self.endpoint.as_ref();
+        match Url::parse(endpoint) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Location::Index(url),
+            _ => Location::Directory(PathBuf::from(endpoint)),
+        }
+    }
+}
+
+/// Where the archives for an integration are listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    /// Archives are linked from an HTTP index page.
+    Index(Url),
+
+    /// Archives are files in a directory on the Broker host.
+    Directory(PathBuf),
+}
+
+/// An archive containing a single version of the code.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Getters, Serialize, Deserialize, new)]
+pub struct Release {
+    /// The file name of the archive.
+    #[getset(get = "pub")]
+    file: String,
+
+    /// The version parsed from the file name.
+    #[getset(get = "pub")]
+    version: String,
+
+    /// The path or URL from which the archive is read.
+    #[getset(get = "pub")]
+    location: String,
+
+    /// Changes when the archive is replaced.
+    state: String,
+
+    /// The format of the archive.
+    format: Format,
+}
+
+impl Display for Release {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file)
+    }
+}
+
+impl ProviderReference for Release {
+    fn name(&self) -> &str {
+        &self.version
+    }
+
+    fn branch(&self) -> Option<&str> {
+        None
+    }
+
+    fn revision(&self) -> &str {
+        &self.version
+    }
+
+    fn as_state(&self) -> &[u8] {
+        self.state.as_bytes()
+    }
+
+    fn for_coordinate(&self) -> String {
+        format!("release:{}@{}", self.version, self.state)
+    }
+
+    fn coordinate_prefixes(name: &str) -> Vec<String> {
+        vec![format!("release:{name}@")]
+    }
+}
+
+#[async_trait]
+impl RemoteProvider for Archives {
+    type Reference = Release;
+
+    async fn clone_reference(
+        &self,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        let format = reference.format;
+        match self.location() {
+            Location::Index(_) => {
+                let archive = download(&reference.location).await?;
+                spawn_blocking_wrap(move || extract(archive.path(), format)).await
+            }
+            Location::Directory(_) => {
+                let archive = PathBuf::from(&reference.location);
+                spawn_blocking_wrap(move || extract(&archive, format)).await
+            }
+        }
+        .change_context(RemoteProviderError::Extract)
+        .describe_lazy(|| format!("extract archive '{}'", reference.location))
+        .help("ensure the archive is complete and is a valid tar, gzipped tar, or zip file")
+    }
+
+    async fn references(&self) -> Result<Vec<Self::Reference>, Report<RemoteProviderError>> {
+        let releases = match self.location() {
+            Location::Index(index) => list_index(&index).await?,
+            Location::Directory(directory) => {
+                spawn_blocking_wrap(move || list_directory(&directory))
+                    .await
+                    .change_context(RemoteProviderError::ReadLocation)
+                    .describe_lazy(|| format!("list archives in '{}'", self.endpoint))
+                    .help("ensure the directory exists and that Broker has permission to read it")?
+            }
+        };
+
+        // If the same version is delivered in more than one format, only scan one of them.
+        let releases = releases
+            .into_iter()
+            .sorted_by(|a, b| a.file.cmp(&b.file))
+            .unique_by(|release| release.version.clone())
+            .collect();
+        Ok(releases)
+    }
+}
+
+#[async_trait]
+impl Provider for Archives {
+    const NAME: &'static str = "archive";
+    const NAMESPACE: db::Namespace = db::Namespace::Archive;
+
+    fn endpoint(&self) -> &Remote {
+        &self.endpoint
+    }
+
+    fn project(&self) -> String {
+        self.project
+            .clone()
+            .unwrap_or_else(|| self.endpoint.to_string())
+    }
+
+    async fn check_connection(&self) -> Result<(), Report<RemoteProviderError>> {
+        match self.location() {
+            Location::Index(index) => fetch_index(&index).await.discard_ok(),
+            Location::Directory(directory) => {
+                spawn_blocking_wrap(move || std::fs::read_dir(directory))
+                    .await
+                    .discard_ok()
+                    .change_context(RemoteProviderError::ReadLocation)
+                    .describe_lazy(|| format!("read directory '{}'", self.endpoint))
+            }
+        }
+    }
+
+    /// Archives aren't mirrored; each is read when it is scanned.
+    async fn update_mirror(&self, _mirror: &Path) -> Result<(), Report<RemoteProviderError>> {
+        Ok(())
+    }
+
+    /// Archives aren't mirrored, so this extracts the archive from its location instead.
+    async fn checkout_from_mirror(
+        &self,
+        _mirror: &Path,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        self.clone_reference(reference).await
+    }
+}
+
+/// Parse the format and version of an archive from its file name.
+fn parse_file_name(file: &str) -> Option<(Format, String)> {
+    let (format, stem) = format_of(file)?;
+    Some((format, version_of(stem)?))
+}
+
+/// Parse the format of an archive from its file name, returning the file name without its extension.
+pub(super) fn format_of(file: &str) -> Option<(Format, &str)> {
+    EXTENSIONS
+        .iter()
+        .find_map(|(ext, format)| file.strip_suffix(ext).map(|stem| (*format, stem)))
+}
+
+/// Parse the version from the file name of an archive without its extension.
+pub(super) fn version_of(stem: &str) -> Option<String> {
+    let captures = VERSION.captures(stem)?;
+    Some(captures["version"].to_string())
+}
+
+/// List the archives in a directory.
+///
+/// Files are identified by their size and modification time,
+/// so an archive that is replaced with different content is scanned again.
+fn list_directory(directory: &Path) -> io::Result<Vec<Release>> {
+    let mut releases = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        let file = entry.file_name().to_string_lossy().to_string();
+        let Some((format, version)) = parse_file_name(&file) else {
+            continue;
+        };
+
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+
+        let modified = metadata
+            .modified()?
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let state = format!("{file}:{}:{modified}", metadata.len());
+        let location = entry.path().display().to_string();
+        releases.push(Release::new(file, version, location, state, format));
+    }
+    Ok(releases)
+}
+
+/// Fetch the content of the index page.
+async fn fetch_index(index: &Url) -> Result<String, Report<RemoteProviderError>> {
+    let clients = Clients::current().change_context(RemoteProviderError::ReadLocation)?;
+    let client = clients.get(Purpose::Remotes);
+    client
+        .send(client.get(index.clone()))
+        .await
+        .and_then(|response| response.error_for_status())
+        .context(RemoteProviderError::ReadLocation)
+        .describe_lazy(|| format!("request index page '{index}'"))
+        .help("ensure the index page is reachable from the Broker host without authentication")?
+        .text()
+        .await
+        .context(RemoteProviderError::ReadLocation)
+        .describe_lazy(|| format!("read index page '{index}'"))
+}
+
+/// List the archives linked from an HTTP index page.
+///
+/// Archives published to an index are assumed not to change once published,
+/// so they are identified by their file name.
+async fn list_index(index: &Url) -> Result<Vec<Release>, Report<RemoteProviderError>> {
+    let page = fetch_index(index).await?;
+    let releases = LINK
+        .captures_iter(&page)
+        .filter_map(|captures| index.join(&captures[1]).ok())
+        .filter_map(|url| {
+            let file = url.path_segments()?.last()?.to_string();
+            let (format, version) = parse_file_name(&file)?;
+            let state = file.clone();
+            Some(Release::new(file, version, url.to_string(), state, format))
+        })
+        .collect();
+    Ok(releases)
+}
+
+/// Download an archive into a temporary file.
+async fn download(url: &str) -> Result<NamedTempFile, Report<RemoteProviderError>> {
+    let clients = Clients::current().change_context(RemoteProviderError::ReadLocation)?;
+    let client = clients.get(Purpose::Remotes);
+    let response = client
+        .send(client.get(url))
+        .await
+        .and_then(|response| response.error_for_status())
+        .context(RemoteProviderError::ReadLocation)
+        .describe_lazy(|| format!("download archive '{url}'"))?;
+    save(response, url).await
+}
+
+/// Write the body of a response containing an archive into a temporary file.
+pub(super) async fn save(
+    mut response: reqwest::Response,
+    url: &str,
+) -> Result<NamedTempFile, Report<RemoteProviderError>> {
+    let mut file = named_tempfile_with_suffix(".archive")
+        .context(RemoteProviderError::ReadLocation)
+        .describe("create temporary file for archive")?;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .context(RemoteProviderError::ReadLocation)
+        .describe_lazy(|| format!("download archive '{url}'"))?
+    {
+        bandwidth::consume(chunk.len()).await;
+        file.write_all(&chunk)
+            .context(RemoteProviderError::ReadLocation)
+            .describe("write archive to temporary file")?;
+    }
+
+    file.flush()
+        .context(RemoteProviderError::ReadLocation)
+        .describe("write archive to temporary file")?;
+    Ok(file)
+}
+
+/// Extract an archive into a temporary directory.
+///
+/// Entries with paths that would escape the directory are never written outside of it.
+pub(super) fn extract(archive: &Path, format: Format) -> io::Result<TempDir> {
+    let dest = tempdir()?;
+    let file = File::open(archive)?;
+    match format {
+        Format::TarGz => tar::Archive::new(gzip::Decoder::new(file)?).unpack(dest.path())?,
+        Format::Tar => tar::Archive::new(file).unpack(dest.path())?,
+        Format::Zip => zip::ZipArchive::new(file)
+            .and_then(|mut archive| archive.extract(dest.path()))
+            .map_err(io::Error::from)?,
+    }
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_versions_from_file_names() {
+        for (file, format, version) in [
+            ("myapp-1.2.3.tar.gz", Format::TarGz, "1.2.3"),
+            ("my-app-1.2.3-rc1.tgz", Format::TarGz, "1.2.3-rc1"),
+            ("myapp-20230601.tar", Format::Tar, "20230601"),
+            ("myapp-2.0.zip", Format::Zip, "2.0"),
+        ] {
+            assert_eq!(
+                parse_file_name(file),
+                Some((format, version.to_string())),
+                "file: {file}"
+            );
+        }
+
+        for file in ["myapp.tar.gz", "myapp-1.2.3.rar", "README.md", "-1.2.3.zip"] {
+            assert_eq!(parse_file_name(file), None, "file: {file}");
+        }
+    }
+
+    #[test]
+    fn lists_and_extracts_directory() {
+        let tmp = tempfile::tempdir().expect("must create tempdir");
+        let archive = tmp.path().join("myapp-1.2.3.tar.gz");
+        {
+            let file = File::create(&archive).expect("must create archive");
+            let encoder = gzip::Encoder::new(file).expect("must create encoder");
+            let mut builder = tar::Builder::new(encoder);
+            let content = b"hello";
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, "myapp-1.2.3/README", &content[..])
+                .expect("must append file");
+            builder
+                .into_inner()
+                .expect("must finish archive")
+                .finish()
+                .into_result()
+                .expect("must finish gzip");
+        }
+        std::fs::write(tmp.path().join("notes.txt"), b"ignored").expect("must write file");
+
+        let releases = list_directory(tmp.path()).expect("must list directory");
+        assert_eq!(releases.len(), 1);
+        let release = &releases[0];
+        assert_eq!(release.version(), "1.2.3");
+        assert_eq!(release.revision(), "1.2.3");
+
+        let extracted = extract(archive.as_path(), release.format).expect("must extract");
+        assert_eq!(
+            std::fs::read(extracted.path().join("myapp-1.2.3").join("README"))
+                .expect("must read extracted file"),
+            b"hello"
+        );
+    }
+}
@@ -0,0 +1,429 @@
+//! Powers integration with object storage buckets in S3 or Google Cloud Storage.
+//!
+//! Archives stored under a prefix in the bucket are listed on each poll.
+//! Each object is a reference identified by its ETag, so a new version of an object is scanned again.
+//! Objects are downloaded and extracted the same way as [`super::archive`] integrations.
+
+use std::{fmt::Display, path::Path, time::SystemTime};
+
+use async_trait::async_trait;
+use derive_new::new;
+use error_stack::{Report, ResultExt};
+use getset::{CopyGetters, Getters};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+use url::Url;
+
+use crate::{
+    api::http::{
+        self,
+        client::{Clients, Purpose},
+    },
+    db,
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        io::spawn_blocking_wrap,
+        result::DiscardResult,
+        secrecy::ComparableSecretString,
+    },
+};
+
+use super::{
+    archive::{self, Format},
+    Provider, ProviderReference, Remote, RemoteProvider, RemoteProviderError,
+};
+
+pub(crate) mod sigv4;
+
+/// The region used for S3 buckets if none is configured.
+pub const DEFAULT_S3_REGION: &str = "us-east-1";
+
+/// Matches each object in a bucket listing.
+static CONTENTS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?s)<Contents>(.*?)</Contents>")
+        .expect("invariant: contents pattern must be valid")
+});
+
+/// Matches the key of an object in a bucket listing.
+static KEY: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<Key>(.*?)</Key>").expect("invariant: key pattern must be valid"));
+
+/// Matches the ETag of an object in a bucket listing.
+static ETAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<ETag>(.*?)</ETag>").expect("invariant: etag pattern must be valid"));
+
+/// Matches whether a bucket listing has more pages.
+static TRUNCATED: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"<IsTruncated>\s*true\s*</IsTruncated>")
+        .expect("invariant: truncated pattern must be valid")
+});
+
+/// The object storage services Broker can read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Service {
+    /// Amazon S3, or a service compatible with its API.
+    S3,
+
+    /// Google Cloud Storage, accessed via its XML API.
+    Gcs,
+}
+
+/// Authentication to an object storage service.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, new)]
+pub enum Auth {
+    /// Requests are signed with an access key; for Google Cloud Storage this is an HMAC key.
+    AccessKey {
+        /// The ID of the access key.
+        access_key_id: String,
+
+        /// The secret for the access key.
+        secret_access_key: ComparableSecretString,
+
+        /// The session token, if the access key is temporary.
+        session_token: Option<ComparableSecretString>,
+    },
+
+    /// Requests use a bearer token minted by running a command, like `gcloud auth print-access-token`.
+    Command(http::CredentialCommand),
+
+    /// The bucket is public.
+    None,
+}
+
+/// A prefix in an object storage bucket.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, Deserialize, Serialize, new)]
+pub struct Bucket {
+    /// The location of the prefix, like `s3://bucket/prefix/`, as written in the config file.
+    #[getset(get = "pub")]
+    endpoint: Remote,
+
+    /// The service hosting the bucket.
+    #[getset(get_copy = "pub")]
+    service: Service,
+
+    /// The name of the bucket.
+    #[getset(get = "pub")]
+    bucket: String,
+
+    /// Only objects whose keys start with this prefix are scanned.
+    #[getset(get = "pub")]
+    prefix: String,
+
+    /// The region of the bucket, used to sign requests.
+    #[getset(get = "pub")]
+    region: String,
+
+    /// The base URL of the service, if not the default for the service.
+    #[getset(get = "pub")]
+    service_url: Option<String>,
+
+    /// The name of the project in FOSSA, if it differs from the endpoint.
+    #[getset(get = "pub")]
+    project: Option<String>,
+
+    /// Authentication to the service.
+    #[getset(get = "pub")]
+    auth: Auth,
+}
+
+impl Display for Bucket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.endpoint)
+    }
+}
+
+/// A version of an object in a bucket.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Getters, Serialize, Deserialize, new)]
+pub struct Object {
+    /// The key of the object.
+    #[getset(get = "pub")]
+    key: String,
+
+    /// The ETag of the object, which changes when a new version of the object is written.
+    #[getset(get = "pub")]
+    etag: String,
+
+    /// The revision reported to FOSSA: the version in the object's file name if it has one,
+    /// otherwise its ETag.
+    revision: String,
+
+    /// The format of the archive.
+    format: Format,
+}
+
+impl Display for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.key, self.etag)
+    }
+}
+
+impl ProviderReference for Object {
+    fn name(&self) -> &str {
+        &self.key
+    }
+
+    fn branch(&self) -> Option<&str> {
+        None
+    }
+
+    fn revision(&self) -> &str {
+        &self.revision
+    }
+
+    fn as_state(&self) -> &[u8] {
+        self.etag.as_bytes()
+    }
+
+    fn for_coordinate(&self) -> String {
+        format!("object:{}@{}", self.key, self.etag)
+    }
+
+    fn coordinate_prefixes(name: &str) -> Vec<String> {
+        vec![format!("object:{name}@")]
+    }
+}
+
+impl Bucket {
+    /// The URL of the bucket, or of an object in it.
+    ///
+    /// Custom service URLs and Google Cloud Storage are addressed with the bucket in the path;
+    /// S3 is addressed with the bucket in the host name.
+    fn url(&self, key: &str, query: &[(&str, &str)]) -> Result<Url, url::ParseError> {
+        let key = sigv4::uri_encode(key, true);
+        let base = match (&self.service_url, self.service) {
+            (Some(base), _) => format!("{}/{}", base.trim_end_matches('/'), self.bucket),
+            (None, Service::Gcs) => format!("https://storage.googleapis.com/{}", self.bucket),
+            (None, Service::S3) => {
+                format!("https://{}.s3.{}.amazonaws.com", self.bucket, self.region)
+            }
+        };
+        let query = query
+            .iter()
+            .map(|(key, value)| format!("{key}={}", sigv4::uri_encode(value, false)))
+            .collect::<Vec<_>>()
+            .join("&");
+        match query.as_str() {
+            "" => Url::parse(&format!("{base}/{key}")),
+            query => Url::parse(&format!("{base}/{key}?{query}")),
+        }
+    }
+
+    /// Send an authenticated `GET` request.
+    async fn get(&self, url: Url) -> Result<reqwest::Response, Report<RemoteProviderError>> {
+        let clients = Clients::current().change_context(RemoteProviderError::ReadLocation)?;
+        let client = clients.get(Purpose::Remotes);
+        let mut request = client.get(url.clone());
+        match &self.auth {
+            Auth::AccessKey {
+                access_key_id,
+                secret_access_key,
+                session_token,
+            } => {
+                let credentials = sigv4::Credentials {
+                    access_key_id,
+                    secret_access_key: secret_access_key.expose_secret(),
+                    session_token: session_token.as_ref().map(|token| token.expose_secret()),
+                };
+                for (name, value) in
+                    sigv4::sign_get(credentials, &self.region, &url, SystemTime::now())
+                {
+                    request = request.header(name, value);
+                }
+            }
+            Auth::Command(command) => {
+                let auth = command
+                    .mint()
+                    .await
+                    .change_context(RemoteProviderError::ReadLocation)
+                    .describe("mint credential for bucket")?;
+                if let http::Auth::Header(header) = auth {
+                    if let Some((name, value)) = header.expose_secret().split_once(':') {
+                        request = request.header(name.trim(), value.trim());
+                    }
+                }
+            }
+            Auth::None => {}
+        }
+
+        client
+            .send(request)
+            .await
+            .and_then(|response| response.error_for_status())
+            .context(RemoteProviderError::ReadLocation)
+            .describe_lazy(|| format!("request '{url}'"))
+            .help("ensure the bucket exists and that the configured credentials can read it")
+    }
+
+    /// List every object under the prefix, following pagination.
+    async fn list(&self) -> Result<Vec<(String, String)>, Report<RemoteProviderError>> {
+        let mut objects = Vec::new();
+        let mut marker = String::new();
+        loop {
+            let url = self
+                .url("", &[("marker", &marker), ("prefix", &self.prefix)])
+                .context(RemoteProviderError::ReadLocation)
+                .describe_lazy(|| format!("build listing URL for '{}'", self.endpoint))?;
+            let page = self
+                .get(url)
+                .await?
+                .text()
+                .await
+                .context(RemoteProviderError::ReadLocation)
+                .describe_lazy(|| format!("read listing for '{}'", self.endpoint))?;
+
+            let listed = parse_listing(&page);
+            let Some((last, _)) = listed.last() else {
+                break;
+            };
+            marker = last.clone();
+            objects.extend(listed);
+            if !TRUNCATED.is_match(&page) {
+                break;
+            }
+        }
+        Ok(objects)
+    }
+}
+
+#[async_trait]
+impl RemoteProvider for Bucket {
+    type Reference = Object;
+
+    async fn clone_reference(
+        &self,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        let url = self
+            .url(&reference.key, &[])
+            .context(RemoteProviderError::ReadLocation)
+            .describe_lazy(|| format!("build URL for object '{}'", reference.key))?;
+        let response = self.get(url.clone()).await?;
+        let file = archive::save(response, url.as_str()).await?;
+
+        let format = reference.format;
+        spawn_blocking_wrap(move || archive::extract(file.path(), format))
+            .await
+            .change_context(RemoteProviderError::Extract)
+            .describe_lazy(|| format!("extract object '{}'", reference.key))
+            .help("ensure the object is a valid tar, gzipped tar, or zip file")
+    }
+
+    async fn references(&self) -> Result<Vec<Self::Reference>, Report<RemoteProviderError>> {
+        let objects = self
+            .list()
+            .await?
+            .into_iter()
+            .filter_map(|(key, etag)| {
+                let file = key.rsplit('/').next().unwrap_or(&key);
+                let (format, stem) = archive::format_of(file)?;
+                let revision = archive::version_of(stem).unwrap_or_else(|| etag.clone());
+                Some(Object::new(key, etag, revision, format))
+            })
+            .collect();
+        Ok(objects)
+    }
+}
+
+#[async_trait]
+impl Provider for Bucket {
+    const NAME: &'static str = "bucket";
+    const NAMESPACE: db::Namespace = db::Namespace::Bucket;
+
+    fn endpoint(&self) -> &Remote {
+        &self.endpoint
+    }
+
+    fn project(&self) -> String {
+        self.project
+            .clone()
+            .unwrap_or_else(|| self.endpoint.to_string())
+    }
+
+    async fn check_connection(&self) -> Result<(), Report<RemoteProviderError>> {
+        let url = self
+            .url("", &[("max-keys", "1"), ("prefix", &self.prefix)])
+            .context(RemoteProviderError::ReadLocation)
+            .describe_lazy(|| format!("build listing URL for '{}'", self.endpoint))?;
+        self.get(url).await.discard_ok()
+    }
+
+    /// Buckets aren't mirrored; each object is downloaded when it is scanned.
+    async fn update_mirror(&self, _mirror: &Path) -> Result<(), Report<RemoteProviderError>> {
+        Ok(())
+    }
+
+    /// Buckets aren't mirrored, so this downloads the object instead.
+    async fn checkout_from_mirror(
+        &self,
+        _mirror: &Path,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        self.clone_reference(reference).await
+    }
+}
+
+/// Parse the key and ETag of each object in a page of a bucket listing.
+fn parse_listing(page: &str) -> Vec<(String, String)> {
+    CONTENTS
+        .captures_iter(page)
+        .filter_map(|contents| {
+            let contents = &contents[1];
+            let key = unescape(&KEY.captures(contents)?[1]);
+            let etag = unescape(&ETAG.captures(contents)?[1]);
+            Some((key, etag.trim_matches('"').to_string()))
+        })
+        .collect()
+}
+
+/// Replace the entities XML uses to escape text.
+fn unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_listing() {
+        let page = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+              <Name>vendor-drops</Name>
+              <Prefix>myapp/</Prefix>
+              <IsTruncated>false</IsTruncated>
+              <Contents>
+                <Key>myapp/myapp-1.2.3.tar.gz</Key>
+                <LastModified>2023-06-01T12:00:00.000Z</LastModified>
+                <ETag>&quot;9b2cf535f27731c974343645a3985328&quot;</ETag>
+                <Size>1024</Size>
+              </Contents>
+              <Contents>
+                <Key>myapp/R&amp;D notes.txt</Key>
+                <ETag>"d41d8cd98f00b204e9800998ecf8427e"</ETag>
+              </Contents>
+            </ListBucketResult>"#;
+
+        assert_eq!(
+            parse_listing(page),
+            vec![
+                (
+                    String::from("myapp/myapp-1.2.3.tar.gz"),
+                    String::from("9b2cf535f27731c974343645a3985328")
+                ),
+                (
+                    String::from("myapp/R&D notes.txt"),
+                    String::from("d41d8cd98f00b204e9800998ecf8427e")
+                ),
+            ]
+        );
+        assert!(!TRUNCATED.is_match(page));
+    }
+}
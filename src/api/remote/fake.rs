@@ -0,0 +1,200 @@
+//! A fake [`RemoteProvider`] for tests, which serves references and their contents from memory.
+//!
+//! Tests configure the references the remote lists and the files each one contains,
+//! and can change them between polls to simulate pushes, or make polls and clones fail
+//! to exercise error handling. Every reference the fake is asked to clone is recorded,
+//! so tests can assert on what was cloned without a real code host.
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use async_trait::async_trait;
+use error_stack::{report, Report};
+use tempfile::TempDir;
+
+use crate::ext::{
+    error_stack::{DescribeContext, IntoContext},
+    result::WrapErr,
+    tempfile::tempdir,
+};
+
+use super::{Reference, RemoteProvider, RemoteProviderError};
+
+/// A remote whose references and their contents are configured by the test.
+///
+/// Clones of a [`FakeRemote`] share the same configuration, so a test can keep a handle
+/// to change the remote while the code under test holds another.
+#[derive(Debug, Clone, Default)]
+pub struct FakeRemote {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    references: Vec<(Reference, Vec<(PathBuf, String)>)>,
+    cloned: Vec<Reference>,
+    fail_polls: bool,
+    fail_clones: bool,
+}
+
+impl FakeRemote {
+    /// Create a remote with no references.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a reference to the remote, or replace the reference with the same name.
+    /// When cloned, the reference contains the provided files, with paths relative to the root of the clone.
+    pub fn push(
+        &self,
+        reference: Reference,
+        files: impl IntoIterator<Item = (impl Into<PathBuf>, impl Into<String>)>,
+    ) {
+        let files = files
+            .into_iter()
+            .map(|(path, content)| (path.into(), content.into()))
+            .collect();
+        let mut state = self.state();
+        state
+            .references
+            .retain(|(existing, _)| existing.name() != reference.name());
+        state.references.push((reference, files));
+    }
+
+    /// Remove the reference with the provided name from the remote.
+    pub fn remove(&self, name: &str) {
+        self.state()
+            .references
+            .retain(|(reference, _)| reference.name() != name);
+    }
+
+    /// Make polling the remote fail until set back to `false`.
+    pub fn fail_polls(&self, fail: bool) {
+        self.state().fail_polls = fail;
+    }
+
+    /// Make cloning references fail until set back to `false`.
+    pub fn fail_clones(&self, fail: bool) {
+        self.state().fail_clones = fail;
+    }
+
+    /// The references which were cloned, in the order they were cloned.
+    pub fn cloned(&self) -> Vec<Reference> {
+        self.state().cloned.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl RemoteProvider for FakeRemote {
+    type Reference = Reference;
+
+    async fn clone_reference(
+        &self,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        let files = {
+            let mut state = self.state();
+            state.cloned.push(reference.clone());
+            if state.fail_clones {
+                return report!(RemoteProviderError::ReadLocation)
+                    .wrap_err()
+                    .describe("fake remote configured to fail clones");
+            }
+            state
+                .references
+                .iter()
+                .find(|(existing, _)| existing == reference)
+                .map(|(_, files)| files.clone())
+        };
+        let Some(files) = files else {
+            return report!(RemoteProviderError::ReadLocation)
+                .wrap_err()
+                .describe_lazy(|| format!("fake remote has no reference '{reference}'"));
+        };
+
+        let dir = tempdir()
+            .context(RemoteProviderError::ReadLocation)
+            .describe("create temporary directory")?;
+        for (path, content) in files {
+            let path = dir.path().join(path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .context(RemoteProviderError::ReadLocation)
+                    .describe_lazy(|| format!("create directory '{}'", parent.display()))?;
+            }
+            std::fs::write(&path, content)
+                .context(RemoteProviderError::ReadLocation)
+                .describe_lazy(|| format!("write file '{}'", path.display()))?;
+        }
+        Ok(dir)
+    }
+
+    async fn references(&self) -> Result<Vec<Self::Reference>, Report<RemoteProviderError>> {
+        let state = self.state();
+        if state.fail_polls {
+            return report!(RemoteProviderError::ReadLocation)
+                .wrap_err()
+                .describe("fake remote configured to fail polls");
+        }
+        Ok(state
+            .references
+            .iter()
+            .map(|(reference, _)| reference.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::remote::git;
+
+    use super::*;
+
+    fn branch(name: &str, head: &str) -> Reference {
+        Reference::Git(git::Reference::new_branch(
+            name.to_string(),
+            head.to_string(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn serves_configured_references() {
+        let remote = FakeRemote::new();
+        remote.push(branch("main", "aaaa"), [("src/lib.rs", "fn main() {}")]);
+        remote.push(branch("dev", "bbbb"), Vec::<(PathBuf, String)>::new());
+        remote.push(branch("main", "cccc"), [("Cargo.toml", "[package]")]);
+
+        let references = remote.references().await.expect("must list references");
+        assert_eq!(
+            references,
+            vec![branch("dev", "bbbb"), branch("main", "cccc")]
+        );
+
+        let clone = remote
+            .clone_reference(&branch("main", "cccc"))
+            .await
+            .expect("must clone reference");
+        let content = std::fs::read_to_string(clone.path().join("Cargo.toml"))
+            .expect("must read cloned file");
+        assert_eq!(content, "[package]");
+        assert_eq!(remote.cloned(), vec![branch("main", "cccc")]);
+
+        remote
+            .clone_reference(&branch("main", "aaaa"))
+            .await
+            .expect_err("replaced references must not be cloned");
+
+        remote.fail_polls(true);
+        remote.references().await.expect_err("must fail polls");
+        remote.remove("main");
+        remote.fail_polls(false);
+        let references = remote.references().await.expect("must list references");
+        assert_eq!(references, vec![branch("dev", "bbbb")]);
+    }
+}
@@ -0,0 +1,571 @@
+//! Powers integration with projects on a Gerrit Code Review server, using its REST API.
+//!
+//! Gerrit reviews changes before they're merged into a branch, so each watched branch is polled for two kinds of references:
+//! the branch itself, at its most recently merged change, and each open change targeting it, at its current patch set.
+//! The branch is scanned like a git branch whenever another change is merged,
+//! and open changes are scanned like tags whenever a new patch set is uploaded.
+//!
+//! To scan a reference, Broker downloads an archive of the patch set from the REST API and extracts it,
+//! so git isn't needed on the Broker host.
+
+use std::{collections::BTreeMap, fmt::Display, path::Path};
+
+use async_trait::async_trait;
+use derive_new::new;
+use error_stack::{Report, ResultExt};
+use getset::Getters;
+use reqwest::{header::WWW_AUTHENTICATE, StatusCode};
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+use url::{Position, Url};
+
+use crate::{
+    api::http::client::{Client, Clients, Purpose},
+    db,
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        io::spawn_blocking_wrap,
+        result::DiscardResult,
+        secrecy::ComparableSecretString,
+    },
+};
+
+use super::{
+    archive::{self, Format},
+    Provider, ProviderReference, Remote, RemoteProvider, RemoteProviderError,
+};
+
+mod digest;
+
+/// Gerrit prefixes JSON responses with this line to prevent cross-site script inclusion.
+const XSSI_PREFIX: &str = ")]}'";
+
+/// How many open changes are requested per page.
+const PAGE_SIZE: usize = 500;
+
+/// Authentication to a Gerrit server.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, new)]
+pub enum Auth {
+    /// Requests use HTTP basic authentication with the user's HTTP password.
+    Basic {
+        /// The Gerrit user.
+        username: String,
+
+        /// The HTTP password generated for the user in Gerrit's settings.
+        password: ComparableSecretString,
+    },
+
+    /// Requests use HTTP digest authentication with the user's HTTP password,
+    /// which Gerrit requires unless basic authentication is enabled for its REST API.
+    Digest {
+        /// The Gerrit user.
+        username: String,
+
+        /// The HTTP password generated for the user in Gerrit's settings.
+        password: ComparableSecretString,
+    },
+
+    /// The project can be read anonymously.
+    None,
+}
+
+/// A project on a Gerrit server.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, Deserialize, Serialize, new)]
+pub struct Project {
+    /// The server and project, like `https://gerrit.example.com/platform/build`.
+    #[getset(get = "pub")]
+    endpoint: Remote,
+
+    /// The base URL of the server, like `https://gerrit.example.com` or `https://example.com/gerrit`.
+    #[getset(get = "pub")]
+    url: String,
+
+    /// The name of the project in Gerrit, like `platform/build`.
+    #[getset(get = "pub")]
+    repository: String,
+
+    /// The branches whose merged and open changes are polled.
+    #[getset(get = "pub")]
+    branches: Vec<String>,
+
+    /// The name of the project in FOSSA, if it differs from the endpoint.
+    #[getset(get = "pub")]
+    project: Option<String>,
+
+    /// Authentication to the server.
+    #[getset(get = "pub")]
+    auth: Auth,
+}
+
+impl Display for Project {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.endpoint)
+    }
+}
+
+/// A specific point in time in a Gerrit project.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Reference {
+    /// A watched branch, at its most recently merged change.
+    Branch {
+        /// The name of the branch.
+        name: String,
+
+        /// The number of the most recently merged change.
+        change: String,
+
+        /// The commit of the change as it was merged.
+        commit: String,
+    },
+
+    /// An open change targeting a watched branch, at its current patch set.
+    Change {
+        /// The number of the change.
+        number: String,
+
+        /// The number of the current patch set.
+        patchset: String,
+
+        /// The branch the change targets.
+        branch: String,
+
+        /// The commit of the current patch set.
+        commit: String,
+    },
+}
+
+impl Reference {
+    /// The change and commit whose code the reference contains.
+    fn patchset(&self) -> (&str, &str) {
+        match self {
+            Reference::Branch { change, commit, .. } => (change, commit),
+            Reference::Change { number, commit, .. } => (number, commit),
+        }
+    }
+}
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reference::Branch { name, commit, .. } => write!(f, "branch::{name}@{commit}"),
+            Reference::Change {
+                number, patchset, ..
+            } => write!(f, "change::{number}/{patchset}"),
+        }
+    }
+}
+
+impl ProviderReference for Reference {
+    fn name(&self) -> &str {
+        match self {
+            Reference::Branch { name, .. } => name,
+            Reference::Change { number, .. } => number,
+        }
+    }
+
+    fn branch(&self) -> Option<&str> {
+        // Open changes haven't been merged into the branch they target,
+        // so they're imported according to the integration's tag settings rather than as the branch.
+        match self {
+            Reference::Branch { name, .. } => Some(name),
+            Reference::Change { .. } => None,
+        }
+    }
+
+    fn revision(&self) -> &str {
+        match self {
+            Reference::Branch { commit, .. } | Reference::Change { commit, .. } => commit,
+        }
+    }
+
+    fn commit(&self) -> Option<&str> {
+        Some(ProviderReference::revision(self))
+    }
+
+    fn as_state(&self) -> &[u8] {
+        ProviderReference::revision(self).as_bytes()
+    }
+
+    fn for_coordinate(&self) -> String {
+        match self {
+            Reference::Branch { name, commit, .. } => format!("branch:{name}@{commit}"),
+            Reference::Change { number, commit, .. } => format!("change:{number}@{commit}"),
+        }
+    }
+
+    fn coordinate_prefixes(name: &str) -> Vec<String> {
+        vec![format!("branch:{name}@"), format!("change:{name}@")]
+    }
+}
+
+/// A change, as reported by the REST API.
+#[derive(Debug, Deserialize)]
+struct ChangeInfo {
+    #[serde(rename = "_number")]
+    number: u64,
+    branch: String,
+    status: String,
+    current_revision: Option<String>,
+    #[serde(default)]
+    revisions: BTreeMap<String, RevisionInfo>,
+    #[serde(default, rename = "_more_changes")]
+    more_changes: bool,
+}
+
+/// A patch set of a change, as reported by the REST API.
+#[derive(Debug, Deserialize)]
+struct RevisionInfo {
+    #[serde(rename = "_number")]
+    number: u64,
+}
+
+impl ChangeInfo {
+    /// The reference for the change, if it's merged or open and its current patch set was reported.
+    fn into_reference(self) -> Option<Reference> {
+        let commit = self.current_revision?;
+        match self.status.as_str() {
+            "MERGED" => Some(Reference::Branch {
+                name: self.branch,
+                change: self.number.to_string(),
+                commit,
+            }),
+            "NEW" => {
+                let patchset = self.revisions.get(&commit)?.number;
+                Some(Reference::Change {
+                    number: self.number.to_string(),
+                    patchset: patchset.to_string(),
+                    branch: self.branch,
+                    commit,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Project {
+    /// The URL of a REST API endpoint, built from its path segments and query.
+    ///
+    /// Authenticated requests are made to the `/a/` prefix of the API, which is how Gerrit knows to authenticate them.
+    fn api_url(&self, segments: &[&str], query: &[(&str, &str)]) -> Result<Url, url::ParseError> {
+        let mut url = Url::parse(&self.url)?;
+        {
+            let mut path = url
+                .path_segments_mut()
+                .map_err(|_| url::ParseError::RelativeUrlWithCannotBeABaseBase)?;
+            path.pop_if_empty();
+            if self.auth != Auth::None {
+                path.push("a");
+            }
+            path.extend(segments);
+        }
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        Ok(url)
+    }
+
+    /// Send an authenticated `GET` request.
+    async fn get(&self, url: Url) -> Result<reqwest::Response, Report<RemoteProviderError>> {
+        let clients = Clients::current().change_context(RemoteProviderError::ReadLocation)?;
+        let client = clients.get(Purpose::Remotes);
+        let response = match &self.auth {
+            Auth::Basic { username, password } => {
+                client
+                    .send(
+                        client
+                            .get(url.clone())
+                            .basic_auth(username, Some(password.expose_secret())),
+                    )
+                    .await
+            }
+            Auth::Digest { username, password } => {
+                send_digest(client, &url, username, password.expose_secret()).await
+            }
+            Auth::None => client.send(client.get(url.clone())).await,
+        };
+
+        response
+            .and_then(|response| response.error_for_status())
+            .context(RemoteProviderError::ReadLocation)
+            .describe_lazy(|| format!("request '{url}'"))
+            .help("ensure the project exists and that the configured user can read it with its HTTP password")
+    }
+
+    /// Query the changes of the project, starting at the provided offset.
+    async fn query(
+        &self,
+        query: &str,
+        start: usize,
+        limit: usize,
+    ) -> Result<Vec<ChangeInfo>, Report<RemoteProviderError>> {
+        let (start, limit) = (start.to_string(), limit.to_string());
+        let query = format!("project:\"{}\" {query}", self.repository);
+        let url = self
+            .api_url(
+                &["changes", ""],
+                &[
+                    ("q", query.as_str()),
+                    ("o", "CURRENT_REVISION"),
+                    ("n", limit.as_str()),
+                    ("S", start.as_str()),
+                ],
+            )
+            .context(RemoteProviderError::ReadLocation)
+            .describe_lazy(|| format!("build query URL for '{}'", self.endpoint))?;
+        let body = self
+            .get(url)
+            .await?
+            .text()
+            .await
+            .context(RemoteProviderError::ReadLocation)
+            .describe_lazy(|| format!("read changes of '{}'", self.endpoint))?;
+        parse_changes(&body)
+            .context(RemoteProviderError::ReadLocation)
+            .describe_lazy(|| format!("parse changes of '{}'", self.endpoint))
+    }
+
+    /// The most recently merged change on the branch, if any change was merged.
+    async fn merged(&self, branch: &str) -> Result<Option<Reference>, Report<RemoteProviderError>> {
+        let query = format!("branch:\"{branch}\" status:merged");
+        let changes = self.query(&query, 0, 1).await?;
+        Ok(changes
+            .into_iter()
+            .next()
+            .and_then(ChangeInfo::into_reference))
+    }
+
+    /// The open changes targeting the branch, following pagination.
+    async fn open(&self, branch: &str) -> Result<Vec<Reference>, Report<RemoteProviderError>> {
+        let query = format!("branch:\"{branch}\" status:open");
+        let mut references = Vec::new();
+        let mut start = 0;
+        loop {
+            let page = self.query(&query, start, PAGE_SIZE).await?;
+            let more = page
+                .last()
+                .map(|change| change.more_changes)
+                .unwrap_or(false);
+            start += page.len();
+            references.extend(page.into_iter().filter_map(ChangeInfo::into_reference));
+            if !more {
+                break;
+            }
+        }
+        Ok(references)
+    }
+}
+
+#[async_trait]
+impl RemoteProvider for Project {
+    type Reference = Reference;
+
+    async fn clone_reference(
+        &self,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        let (change, commit) = reference.patchset();
+        let url = self
+            .api_url(
+                &["changes", change, "revisions", commit, "archive"],
+                &[("format", "tgz")],
+            )
+            .context(RemoteProviderError::ReadLocation)
+            .describe_lazy(|| format!("build archive URL for '{reference}'"))?;
+        let response = self.get(url.clone()).await?;
+        let file = archive::save(response, url.as_str()).await?;
+
+        spawn_blocking_wrap(move || archive::extract(file.path(), Format::TarGz))
+            .await
+            .change_context(RemoteProviderError::Extract)
+            .describe_lazy(|| format!("extract archive of '{reference}'"))
+            .help("ensure the 'tgz' archive format is enabled with 'download.archive' in the Gerrit config")
+    }
+
+    async fn references(&self) -> Result<Vec<Self::Reference>, Report<RemoteProviderError>> {
+        let mut references = Vec::new();
+        for branch in &self.branches {
+            references.extend(self.merged(branch).await?);
+            references.extend(self.open(branch).await?);
+        }
+        Ok(references)
+    }
+}
+
+#[async_trait]
+impl Provider for Project {
+    const NAME: &'static str = "gerrit";
+    const NAMESPACE: db::Namespace = db::Namespace::Gerrit;
+
+    fn endpoint(&self) -> &Remote {
+        &self.endpoint
+    }
+
+    fn project(&self) -> String {
+        self.project
+            .clone()
+            .unwrap_or_else(|| self.endpoint.to_string())
+    }
+
+    async fn check_connection(&self) -> Result<(), Report<RemoteProviderError>> {
+        let url = self
+            .api_url(&["projects", self.repository.as_str()], &[])
+            .context(RemoteProviderError::ReadLocation)
+            .describe_lazy(|| format!("build project URL for '{}'", self.endpoint))?;
+        self.get(url).await.discard_ok()
+    }
+
+    /// Projects aren't mirrored; each patch set is downloaded when it is scanned.
+    async fn update_mirror(&self, _mirror: &Path) -> Result<(), Report<RemoteProviderError>> {
+        Ok(())
+    }
+
+    /// Projects aren't mirrored, so this downloads the patch set instead.
+    async fn checkout_from_mirror(
+        &self,
+        _mirror: &Path,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        self.clone_reference(reference).await
+    }
+}
+
+/// Send a `GET` request with HTTP digest authentication.
+///
+/// The request is first sent without credentials to obtain a challenge, then sent again answering it.
+/// If the server doesn't send a digest challenge, its response to the first request is returned.
+async fn send_digest(
+    client: &Client,
+    url: &Url,
+    username: &str,
+    password: &str,
+) -> reqwest::Result<reqwest::Response> {
+    let response = client.send(client.get(url.clone())).await?;
+    if response.status() != StatusCode::UNAUTHORIZED {
+        return Ok(response);
+    }
+
+    let challenge = response
+        .headers()
+        .get_all(WWW_AUTHENTICATE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .find_map(digest::Challenge::parse);
+    let Some(challenge) = challenge else {
+        return Ok(response);
+    };
+
+    let cnonce = format!("{:016x}", rand::random::<u64>());
+    let uri = &url[Position::BeforePath..];
+    let authorization = challenge.authorization(username, password, "GET", uri, &cnonce);
+    client
+        .send(
+            client
+                .get(url.clone())
+                .header(reqwest::header::AUTHORIZATION, authorization),
+        )
+        .await
+}
+
+/// Parse a list of changes from the REST API, which is JSON after the [`XSSI_PREFIX`] line.
+fn parse_changes(body: &str) -> Result<Vec<ChangeInfo>, serde_json::Error> {
+    let body = body.trim_start();
+    serde_json::from_str(body.strip_prefix(XSSI_PREFIX).unwrap_or(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(auth: Auth) -> Project {
+        Project::new(
+            Remote::new(String::from("https://gerrit.example.com/r/platform/build")),
+            String::from("https://gerrit.example.com/r/"),
+            String::from("platform/build"),
+            vec![String::from("master")],
+            None,
+            auth,
+        )
+    }
+
+    #[test]
+    fn builds_api_urls() {
+        let anonymous = project(Auth::None);
+        let url = anonymous
+            .api_url(&["projects", "platform/build"], &[])
+            .expect("must build url");
+        assert_eq!(
+            url.as_str(),
+            "https://gerrit.example.com/r/projects/platform%2Fbuild"
+        );
+
+        let authenticated = project(Auth::new_digest(
+            String::from("broker"),
+            String::from("hunter2").into(),
+        ));
+        let url = authenticated
+            .api_url(&["changes", ""], &[("q", "status:open"), ("n", "1")])
+            .expect("must build url");
+        assert_eq!(
+            url.as_str(),
+            "https://gerrit.example.com/r/a/changes/?q=status%3Aopen&n=1"
+        );
+    }
+
+    #[test]
+    fn parses_changes() {
+        let body = r#")]}'
+            [
+              {
+                "id": "platform%2Fbuild~master~I8473b95934b5732ac55d26311a706c9c2bde9940",
+                "project": "platform/build",
+                "branch": "master",
+                "status": "MERGED",
+                "current_revision": "184ebe53805e102605d11f6b143486d15c23a09c",
+                "revisions": {"184ebe53805e102605d11f6b143486d15c23a09c": {"_number": 2}},
+                "_number": 3965
+              },
+              {
+                "project": "platform/build",
+                "branch": "master",
+                "status": "NEW",
+                "current_revision": "674ac754f91e64a0efb8087e59a176484bd534d1",
+                "revisions": {"674ac754f91e64a0efb8087e59a176484bd534d1": {"_number": 4}},
+                "_number": 4247,
+                "_more_changes": true
+              },
+              {
+                "project": "platform/build",
+                "branch": "master",
+                "status": "ABANDONED",
+                "_number": 4248
+              }
+            ]"#;
+
+        let changes = parse_changes(body).expect("must parse changes");
+        assert!(changes[1].more_changes);
+        let references = changes
+            .into_iter()
+            .filter_map(ChangeInfo::into_reference)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            references,
+            vec![
+                Reference::Branch {
+                    name: String::from("master"),
+                    change: String::from("3965"),
+                    commit: String::from("184ebe53805e102605d11f6b143486d15c23a09c"),
+                },
+                Reference::Change {
+                    number: String::from("4247"),
+                    patchset: String::from("4"),
+                    branch: String::from("master"),
+                    commit: String::from("674ac754f91e64a0efb8087e59a176484bd534d1"),
+                },
+            ]
+        );
+        assert_eq!(references[0].branch(), Some("master"));
+        assert_eq!(references[1].branch(), None);
+        assert_eq!(references[1].to_string(), "change::4247/4");
+    }
+}
@@ -0,0 +1,209 @@
+//! Authenticates requests with HTTP digest authentication, as described in RFC 7616.
+//!
+//! Gerrit servers which haven't enabled basic authentication for their REST API require digest authentication,
+//! using the user's HTTP password.
+//! Only the subset Gerrit uses is implemented: the `MD5`, `MD5-sess`, and `SHA-256` algorithms,
+//! with or without the `auth` quality of protection.
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+/// The hash algorithms a server may request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+}
+
+impl Algorithm {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "MD5" => Some(Self::Md5),
+            "MD5-SESS" => Some(Self::Md5Sess),
+            "SHA-256" => Some(Self::Sha256),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Md5 => "MD5",
+            Self::Md5Sess => "MD5-sess",
+            Self::Sha256 => "SHA-256",
+        }
+    }
+
+    fn hash(self, value: &str) -> String {
+        match self {
+            Self::Md5 | Self::Md5Sess => format!("{:x}", Md5::digest(value.as_bytes())),
+            Self::Sha256 => format!("{:x}", Sha256::digest(value.as_bytes())),
+        }
+    }
+}
+
+/// The challenge a server sends in the `WWW-Authenticate` header of a `401` response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Challenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: Algorithm,
+    /// Whether the server asked for the `auth` quality of protection.
+    qop_auth: bool,
+}
+
+impl Challenge {
+    /// Parse a `WWW-Authenticate` header, returning `None` if it isn't a digest challenge Broker supports.
+    pub(super) fn parse(header: &str) -> Option<Self> {
+        let (scheme, params) = header.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("digest") {
+            return None;
+        }
+
+        let mut realm = None;
+        let mut nonce = None;
+        let mut opaque = None;
+        let mut algorithm = Algorithm::Md5;
+        let mut qop_auth = false;
+        for (name, value) in parse_params(params) {
+            match name.to_ascii_lowercase().as_str() {
+                "realm" => realm = Some(value),
+                "nonce" => nonce = Some(value),
+                "opaque" => opaque = Some(value),
+                "algorithm" => algorithm = Algorithm::parse(&value)?,
+                "qop" => qop_auth = value.split(',').any(|qop| qop.trim() == "auth"),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            realm: realm?,
+            nonce: nonce?,
+            opaque,
+            algorithm,
+            qop_auth,
+        })
+    }
+
+    /// The `Authorization` header answering the challenge for a request.
+    ///
+    /// `uri` is the path and query of the request, and `cnonce` a random value chosen by the client.
+    pub(super) fn authorization(
+        &self,
+        username: &str,
+        password: &str,
+        method: &str,
+        uri: &str,
+        cnonce: &str,
+    ) -> String {
+        // Each challenge answers a single request, so the nonce count is always 1.
+        const NC: &str = "00000001";
+
+        let algorithm = self.algorithm;
+        let Self { realm, nonce, .. } = self;
+        let ha1 = algorithm.hash(&format!("{username}:{realm}:{password}"));
+        let ha1 = match algorithm {
+            Algorithm::Md5Sess => algorithm.hash(&format!("{ha1}:{nonce}:{cnonce}")),
+            _ => ha1,
+        };
+        let ha2 = algorithm.hash(&format!("{method}:{uri}"));
+
+        let mut fields = vec![
+            format!("username=\"{username}\""),
+            format!("realm=\"{realm}\""),
+            format!("nonce=\"{nonce}\""),
+            format!("uri=\"{uri}\""),
+            format!("algorithm={}", algorithm.name()),
+        ];
+        if self.qop_auth {
+            let response = algorithm.hash(&format!("{ha1}:{nonce}:{NC}:{cnonce}:auth:{ha2}"));
+            fields.push(format!("response=\"{response}\""));
+            fields.push(String::from("qop=auth"));
+            fields.push(format!("nc={NC}"));
+            fields.push(format!("cnonce=\"{cnonce}\""));
+        } else {
+            let response = algorithm.hash(&format!("{ha1}:{nonce}:{ha2}"));
+            fields.push(format!("response=\"{response}\""));
+        }
+        if let Some(opaque) = &self.opaque {
+            fields.push(format!("opaque=\"{opaque}\""));
+        }
+        format!("Digest {}", fields.join(", "))
+    }
+}
+
+/// Parse the comma separated `name=value` parameters of a challenge, where values may be quoted.
+fn parse_params(params: &str) -> Vec<(String, String)> {
+    let mut parsed = Vec::new();
+    let mut rest = params.trim();
+    while let Some((name, after)) = rest.split_once('=') {
+        let name = name.trim().trim_start_matches(',').trim().to_string();
+        let after = after.trim_start();
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let mut end = quoted.len();
+                while let Some((index, c)) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next().map(|(_, escaped)| escaped)),
+                        '"' => {
+                            end = index + 1;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                (value, &quoted[end..])
+            }
+            None => match after.split_once(',') {
+                Some((value, remaining)) => (value.trim().to_string(), remaining),
+                None => (after.trim().to_string(), ""),
+            },
+        };
+        parsed.push((name, value));
+        rest = remaining.trim_start().trim_start_matches(',');
+    }
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_rfc_2617_challenge() {
+        let challenge = Challenge::parse(
+            r#"Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#,
+        )
+        .expect("must parse challenge");
+        let authorization = challenge.authorization(
+            "Mufasa",
+            "Circle Of Life",
+            "GET",
+            "/dir/index.html",
+            "0a4f113b",
+        );
+        assert!(
+            authorization.starts_with("Digest username=\"Mufasa\", realm=\"testrealm@host.com\"")
+        );
+        assert!(authorization.contains("response=\"6629fae49393a05397450978507c4ef1\""));
+        assert!(authorization.contains("qop=auth, nc=00000001, cnonce=\"0a4f113b\""));
+        assert!(authorization.ends_with("opaque=\"5ccc069c403ebaf9f0171e9517f40e41\""));
+    }
+
+    #[test]
+    fn rejects_other_schemes() {
+        assert_eq!(
+            Challenge::parse(r#"Basic realm="Gerrit Code Review""#),
+            None
+        );
+        assert_eq!(
+            Challenge::parse(
+                r#"Digest realm="Gerrit Code Review", nonce="abcd", algorithm=SHA-512-256"#
+            ),
+            None
+        );
+    }
+}
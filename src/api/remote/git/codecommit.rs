@@ -0,0 +1,369 @@
+//! Authenticates git over HTTPS to AWS CodeCommit with short-lived passwords derived from AWS credentials.
+//!
+//! Like `git-remote-codecommit`, the password is a SigV4 signature computed from the AWS credentials,
+//! so no static git credentials need to be created in IAM.
+//! The AWS credentials are read from the named profile in the shared credentials file if a profile is configured.
+//! Otherwise they're read from the `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, and `AWS_SESSION_TOKEN` environment variables,
+//! then the `default` profile, and finally the IAM role of the EC2 instance Broker is running on.
+//!
+//! Passwords expire shortly after they're computed, and instance role credentials rotate,
+//! so a fresh password is computed before each git operation.
+
+use std::{path::PathBuf, time::Duration, time::SystemTime};
+
+use error_stack::{report, Report, ResultExt};
+use getset::Getters;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    api::{
+        http::{
+            self,
+            client::{Clients, Purpose},
+        },
+        remote::{bucket::sigv4, Remote, ValidationError},
+    },
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        io,
+        result::{WrapErr, WrapOk},
+        secrecy::ComparableSecretString,
+    },
+};
+
+/// The profile used if none is configured and the credentials aren't in the environment.
+const DEFAULT_PROFILE: &str = "default";
+
+/// The base URL of the EC2 instance metadata service.
+const INSTANCE_METADATA: &str = "http://169.254.169.254/latest";
+
+/// If the instance metadata service doesn't respond within this long, Broker isn't running on EC2.
+const INSTANCE_METADATA_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A CodeCommit repository, and where to find the AWS credentials used to authenticate to it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Getters)]
+#[getset(get = "pub")]
+pub struct CodeCommit {
+    /// The AWS profile in the shared credentials file, if one is configured.
+    profile: Option<String>,
+
+    /// The AWS region of the repository.
+    region: String,
+
+    /// The host of the repository, like `git-codecommit.us-east-1.amazonaws.com`.
+    host: String,
+
+    /// The path of the repository, like `/v1/repos/broker`.
+    path: String,
+}
+
+impl CodeCommit {
+    /// Validate that the remote is the HTTPS URL of a CodeCommit repository,
+    /// like `https://git-codecommit.us-east-1.amazonaws.com/v1/repos/broker`.
+    pub fn validate(
+        remote: &Remote,
+        profile: Option<String>,
+    ) -> Result<Self, Report<ValidationError>> {
+        let parsed = Url::parse(&remote.to_string())
+            .ok()
+            .filter(|url| url.scheme() == "https")
+            .and_then(|url| {
+                let host = url.host_str()?.to_lowercase();
+                let region = parse_region(&host)?;
+                Some((host, region, url.path().to_string()))
+            });
+        match parsed {
+            Some((host, region, path)) => Self {
+                profile,
+                region,
+                host,
+                path,
+            }
+            .wrap_ok(),
+            None => report!(ValidationError::CodeCommitRemote)
+                .wrap_err()
+                .help("use the HTTPS clone URL of the repository, like 'https://git-codecommit.us-east-1.amazonaws.com/v1/repos/broker'")
+                .describe_lazy(|| format!("provided remote: {remote}")),
+        }
+    }
+
+    /// Load the AWS credentials and compute a fresh password, returning HTTP Basic auth for git.
+    ///
+    /// Credentials are never included in errors.
+    #[tracing::instrument(skip(self), fields(host = %self.host, path = %self.path))]
+    pub async fn mint(&self) -> Result<http::Auth, Report<http::Error>> {
+        let key = load_credentials(self.profile.as_deref()).await?;
+        let credentials = sigv4::Credentials {
+            access_key_id: &key.access_key_id,
+            secret_access_key: key.secret_access_key.expose_secret(),
+            session_token: key
+                .session_token
+                .as_ref()
+                .map(|token| token.expose_secret()),
+        };
+        let password = sigv4::codecommit_password(
+            credentials,
+            &self.region,
+            &self.host,
+            &self.path,
+            SystemTime::now(),
+        );
+
+        // Temporary credentials are identified by the access key ID and session token, joined by '%'.
+        let username = match &key.session_token {
+            Some(token) => http::Username::with_secret(
+                key.access_key_id.clone(),
+                ComparableSecretString::from(format!("%{}", token.expose_secret())),
+            ),
+            None => http::Username::from(key.access_key_id.clone()),
+        };
+        Ok(http::Auth::new_basic(
+            username,
+            ComparableSecretString::from(password),
+        ))
+    }
+}
+
+/// Parse the region from a CodeCommit host, like `git-codecommit.us-east-1.amazonaws.com`
+/// or `git-codecommit-fips.us-gov-west-1.amazonaws.com`.
+fn parse_region(host: &str) -> Option<String> {
+    let rest = host
+        .strip_prefix("git-codecommit.")
+        .or_else(|| host.strip_prefix("git-codecommit-fips."))?;
+    let region = rest
+        .strip_suffix(".amazonaws.com")
+        .or_else(|| rest.strip_suffix(".amazonaws.com.cn"))?;
+    if region.is_empty() || region.contains('.') {
+        return None;
+    }
+    Some(region.to_string())
+}
+
+/// AWS credentials.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AccessKey {
+    access_key_id: String,
+    secret_access_key: ComparableSecretString,
+    session_token: Option<ComparableSecretString>,
+}
+
+/// Load the AWS credentials, from the profile if one is provided.
+async fn load_credentials(profile: Option<&str>) -> Result<AccessKey, Report<http::Error>> {
+    if let Some(profile) = profile {
+        return from_profile(profile)
+            .await?
+            .ok_or_else(|| report!(http::Error::AwsCredentials(format!("profile '{profile}'"))))
+            .describe_lazy(|| format!("profile '{profile}' is not in the shared credentials file"))
+            .help("add the profile to the shared credentials file, which is '~/.aws/credentials' unless 'AWS_SHARED_CREDENTIALS_FILE' is set");
+    }
+
+    if let Some(key) = from_env() {
+        return Ok(key);
+    }
+    if let Some(key) = from_profile(DEFAULT_PROFILE).await? {
+        return Ok(key);
+    }
+    from_instance_role()
+        .await
+        .help("configure 'profile', set the 'AWS_ACCESS_KEY_ID' and 'AWS_SECRET_ACCESS_KEY' environment variables, or run Broker on an EC2 instance with an IAM role")
+}
+
+/// Load the credentials from the standard environment variables, if they're set.
+fn from_env() -> Option<AccessKey> {
+    let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    Some(AccessKey {
+        access_key_id: var("AWS_ACCESS_KEY_ID")?,
+        secret_access_key: ComparableSecretString::from(var("AWS_SECRET_ACCESS_KEY")?),
+        session_token: var("AWS_SESSION_TOKEN").map(ComparableSecretString::from),
+    })
+}
+
+/// The location of the shared credentials file.
+async fn credentials_file() -> Result<PathBuf, Report<http::Error>> {
+    if let Some(path) = std::env::var_os("AWS_SHARED_CREDENTIALS_FILE") {
+        return Ok(PathBuf::from(path));
+    }
+    io::home_dir()
+        .await
+        .map(|home| home.join(".aws").join("credentials"))
+        .change_context(http::Error::AwsCredentials(String::from(
+            "the shared credentials file",
+        )))
+}
+
+/// Load the credentials for the profile from the shared credentials file,
+/// or `None` if the file or the profile in it don't exist.
+async fn from_profile(profile: &str) -> Result<Option<AccessKey>, Report<http::Error>> {
+    let path = credentials_file().await?;
+    let content = match tokio::fs::read_to_string(&path).await {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err)
+                .context(http::Error::AwsCredentials(format!("profile '{profile}'")))
+                .describe_lazy(|| format!("read '{}'", path.display()))
+        }
+    };
+    Ok(parse_profile(&content, profile))
+}
+
+/// Parse the credentials for the profile out of the INI formatted shared credentials file.
+fn parse_profile(content: &str, profile: &str) -> Option<AccessKey> {
+    let mut in_profile = false;
+    let mut access_key_id = None;
+    let mut secret_access_key = None;
+    let mut session_token = None;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_profile = section.trim() == profile;
+            continue;
+        }
+        if !in_profile {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match key.trim() {
+            "aws_access_key_id" => access_key_id = Some(value),
+            "aws_secret_access_key" => secret_access_key = Some(value),
+            "aws_session_token" => session_token = Some(value),
+            _ => {}
+        }
+    }
+
+    Some(AccessKey {
+        access_key_id: access_key_id?,
+        secret_access_key: ComparableSecretString::from(secret_access_key?),
+        session_token: session_token.map(ComparableSecretString::from),
+    })
+}
+
+/// The credentials of an instance role, as reported by the instance metadata service.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RoleCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    token: String,
+}
+
+/// Load the credentials of the IAM role attached to the EC2 instance, using IMDSv2.
+async fn from_instance_role() -> Result<AccessKey, Report<http::Error>> {
+    let source = || http::Error::AwsCredentials(String::from("the EC2 instance role"));
+    let clients = Clients::current().change_context_lazy(source)?;
+    let client = clients.get(Purpose::Remotes);
+    let metadata = |url: String, token: &str| {
+        client
+            .get(url)
+            .header("X-aws-ec2-metadata-token", token)
+            .timeout(INSTANCE_METADATA_TIMEOUT)
+    };
+
+    let token = client
+        .send(
+            client
+                .request(Method::PUT, format!("{INSTANCE_METADATA}/api/token"))
+                .header("X-aws-ec2-metadata-token-ttl-seconds", "60")
+                .timeout(INSTANCE_METADATA_TIMEOUT),
+        )
+        .await
+        .and_then(|response| response.error_for_status())
+        .context_lazy(source)
+        .describe("request an instance metadata token")?
+        .text()
+        .await
+        .context_lazy(source)?;
+
+    let roles = format!("{INSTANCE_METADATA}/meta-data/iam/security-credentials/");
+    let role = client
+        .send(metadata(roles.clone(), &token))
+        .await
+        .and_then(|response| response.error_for_status())
+        .context_lazy(source)
+        .describe("find the instance role; ensure the instance has an IAM role attached")?
+        .text()
+        .await
+        .context_lazy(source)?;
+    let role = role.lines().next().unwrap_or_default().trim();
+
+    let credentials = client
+        .send(metadata(format!("{roles}{role}"), &token))
+        .await
+        .and_then(|response| response.error_for_status())
+        .context_lazy(source)
+        .describe_lazy(|| format!("request credentials for instance role '{role}'"))?
+        .json::<RoleCredentials>()
+        .await
+        .context_lazy(source)
+        .describe_lazy(|| format!("parse credentials for instance role '{role}'"))?;
+
+    Ok(AccessKey {
+        access_key_id: credentials.access_key_id,
+        secret_access_key: ComparableSecretString::from(credentials.secret_access_key),
+        session_token: Some(ComparableSecretString::from(credentials.token)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_remote() {
+        let remote = Remote::new(String::from(
+            "https://git-codecommit.us-east-1.amazonaws.com/v1/repos/broker",
+        ));
+        let repo = CodeCommit::validate(&remote, Some(String::from("broker")))
+            .expect("must validate remote");
+        assert_eq!(repo.region(), "us-east-1");
+        assert_eq!(repo.host(), "git-codecommit.us-east-1.amazonaws.com");
+        assert_eq!(repo.path(), "/v1/repos/broker");
+
+        for remote in [
+            "git@github.com:fossas/broker.git",
+            "ssh://git-codecommit.us-east-1.amazonaws.com/v1/repos/broker",
+            "https://github.com/fossas/broker",
+        ] {
+            let remote = Remote::new(remote.to_string());
+            CodeCommit::validate(&remote, None).expect_err("must reject remote");
+        }
+    }
+
+    #[test]
+    fn parses_profiles() {
+        let content = "
+            [default]
+            aws_access_key_id = AKIDDEFAULT
+            aws_secret_access_key = default-secret
+
+            # Used by Broker.
+            [broker]
+            aws_access_key_id=AKIDBROKER
+            aws_secret_access_key=broker-secret
+            aws_session_token=broker-token
+        ";
+
+        let key = parse_profile(content, "broker").expect("must parse profile");
+        assert_eq!(key.access_key_id, "AKIDBROKER");
+        assert_eq!(key.secret_access_key.expose_secret(), "broker-secret");
+        assert_eq!(
+            key.session_token
+                .as_ref()
+                .map(|token| token.expose_secret()),
+            Some("broker-token")
+        );
+
+        let key = parse_profile(content, "default").expect("must parse profile");
+        assert_eq!(key.access_key_id, "AKIDDEFAULT");
+        assert_eq!(key.session_token, None);
+
+        assert_eq!(parse_profile(content, "missing"), None);
+    }
+}
@@ -0,0 +1,354 @@
+//! Finds the `git` executable that Broker runs, and checks that it's recent enough.
+//!
+//! Broker relies on features like blobless clones (`--filter=blob:none`), which older versions of git don't support.
+//! If the system git is missing or too old, Broker can instead download a portable git archive
+//! pinned by its checksum (for example MinGit on Windows), which is extracted into the data root and used from then on.
+
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use error_stack::{bail, report, Report, ResultExt};
+use once_cell::sync::OnceCell;
+use semver::Version;
+use tracing::{debug, info};
+use url::Url;
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    api::http::client::Purpose,
+    ext::{
+        command::{Command, CommandDescriber, OutputProvider},
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        io::spawn_blocking,
+        once_cell::{Conflict, InstallOnce},
+        result::{WrapErr, WrapOk},
+        sha2,
+    },
+    AppContext,
+};
+
+/// The oldest version of git which supports everything Broker does with it.
+///
+/// Blobless clones, with `git clone --filter=blob:none`, were introduced in git 2.19.
+pub const MINIMUM_VERSION: Version = Version::new(2, 19, 0);
+
+/// Errors encountered finding or downloading git.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Running `git --version` failed.
+    #[error("run command: {}", .0.trim())]
+    Execution(String),
+
+    /// The output of `git --version` isn't in the expected format.
+    #[error("parse git version from '{}'", .0.trim())]
+    ParseVersion(String),
+
+    /// The git executable is older than [`MINIMUM_VERSION`].
+    #[error("git {found} is older than the minimum supported version {minimum}")]
+    Outdated {
+        /// The version that was found.
+        found: Version,
+        /// The minimum supported version.
+        minimum: Version,
+    },
+
+    /// Downloading the portable git archive failed.
+    #[error("download portable git from '{0}'")]
+    Download(Url),
+
+    /// The portable git archive doesn't match its configured checksum.
+    #[error("verify portable git download against its checksum")]
+    Verify,
+
+    /// Extracting the portable git archive failed.
+    #[error("extract portable git into '{}'", .0.display())]
+    Extract(PathBuf),
+
+    /// The portable git archive was extracted, but doesn't contain a git executable in a known location.
+    #[error("find git executable in portable git at '{}'", .0.display())]
+    FindExecutable(PathBuf),
+
+    /// Portable git couldn't be used, because a different git executable is already used by this process.
+    #[error("use portable git at '{}'", .0.display())]
+    Install(PathBuf),
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Execution(..) => ErrorCode::new(1351),
+            Self::ParseVersion(..) => ErrorCode::new(1352),
+            Self::Outdated { .. } => ErrorCode::new(1353),
+            Self::Download(..) => ErrorCode::new(1354),
+            Self::Verify => ErrorCode::new(1355),
+            Self::Extract(..) => ErrorCode::new(1356),
+            Self::FindExecutable(..) => ErrorCode::new(1357),
+            Self::Install(..) => ErrorCode::new(1358),
+        }
+    }
+}
+
+impl Error {
+    fn running_git_command<D: CommandDescriber>(describer: D) -> Self {
+        Self::Execution(describer.describe().to_string())
+    }
+}
+
+/// Errors that are possibly surfaced during validation of config values.
+#[derive(Debug, thiserror::Error)]
+pub enum ValidationError {
+    /// The portable git URL must be a valid HTTP or HTTPS URL.
+    #[error("invalid portable git url")]
+    Url,
+
+    /// The portable git checksum must be a SHA-256 checksum.
+    #[error("invalid portable git checksum")]
+    Checksum,
+}
+
+impl HasErrorCode for ValidationError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Url => ErrorCode::new(1371),
+            Self::Checksum => ErrorCode::new(1372),
+        }
+    }
+}
+
+/// Validated config values for downloading a portable git when the system git is missing or too old.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Portable {
+    /// The location of the zip archive.
+    url: Url,
+
+    /// The SHA-256 checksum of the archive, as lowercase hex.
+    sha256: String,
+}
+
+impl Portable {
+    /// Validate the URL and checksum of the archive.
+    pub fn validate(url: String, sha256: String) -> Result<Self, Report<ValidationError>> {
+        let url = match Url::parse(&url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => parsed,
+            _ => {
+                return report!(ValidationError::Url)
+                    .wrap_err()
+                    .help("the url must be a full http or https url, such as 'https://github.com/git-for-windows/git/releases/download/v2.42.0.windows.2/MinGit-2.42.0.2-64-bit.zip'")
+                    .describe_lazy(|| format!("provided value: {url}"))
+            }
+        };
+
+        let sha256 = sha2::parse_sha256(sha256.as_bytes())
+            .change_context(ValidationError::Checksum)
+            .help("provide the SHA-256 checksum of the archive as 64 hexadecimal characters")
+            .describe_lazy(|| format!("provided value: '{sha256}'"))?;
+        Self { url, sha256 }.wrap_ok()
+    }
+
+    /// The location of the zip archive.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The SHA-256 checksum of the archive.
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+}
+
+/// The git executable installed with [`install_program`].
+static PROGRAM: OnceCell<PathBuf> = OnceCell::new();
+
+/// Run the git executable at the provided path for every git command run after this is called.
+///
+/// Installing an executable other than the one already installed is an error.
+pub fn install_program(program: PathBuf) -> Result<(), Report<Conflict>> {
+    PROGRAM.install("git executable", program)
+}
+
+/// The git executable Broker runs: the one installed with [`install_program`], or `git` from `$PATH`.
+pub fn program() -> &'static Path {
+    PROGRAM
+        .get()
+        .map(PathBuf::as_path)
+        .unwrap_or_else(|| Path::new("git"))
+}
+
+/// Ensure that a git executable at least as recent as [`MINIMUM_VERSION`] is available, returning its version.
+///
+/// If the system git is missing or too old and a portable git is configured,
+/// the portable git is downloaded (unless it already was) and installed with [`install_program`].
+#[tracing::instrument(skip(ctx))]
+pub async fn prepare(
+    ctx: &AppContext,
+    portable: Option<&Portable>,
+) -> Result<Version, Report<Error>> {
+    let system = supported_version(Path::new("git")).await;
+    let Some(portable) = portable else {
+        return system.help("install git 2.19 or later, configure 'portable_git' so that Broker downloads it, or set 'git_backend: native'");
+    };
+    let err = match system {
+        Ok(version) => return Ok(version),
+        Err(err) => err,
+    };
+
+    debug!("System git is unavailable, using portable git: {err:#?}");
+    let program = download_portable(ctx, portable).await?;
+    let version = supported_version(&program)
+        .await
+        .help("configure 'portable_git' with an archive of git 2.19 or later")?;
+    info!(%version, program = %program.display(), "Using portable git");
+    install_program(program.clone()).change_context(Error::Install(program))?;
+    Ok(version)
+}
+
+/// The version of the git executable, if it's at least [`MINIMUM_VERSION`].
+async fn supported_version(program: &Path) -> Result<Version, Report<Error>> {
+    let found = version(program).await?;
+    if found < MINIMUM_VERSION {
+        bail!(Error::Outdated {
+            found,
+            minimum: MINIMUM_VERSION,
+        });
+    }
+    Ok(found)
+}
+
+/// The version of the git executable, as reported by `git --version`.
+#[tracing::instrument]
+pub async fn version(program: &Path) -> Result<Version, Report<Error>> {
+    let command = Command::new(program).arg_plain("--version");
+    let output = command
+        .output()
+        .await
+        .context_lazy(|| Error::running_git_command(&command))?;
+    if !output.status().success() {
+        bail!(Error::running_git_command(&output));
+    }
+
+    let output = output.stdout_string_lossy();
+    parse_version(&output).ok_or_else(|| report!(Error::ParseVersion(output)))
+}
+
+/// Parse the output of `git --version`, which looks like one of these depending on the platform:
+///
+/// git version 2.39.2
+/// git version 2.39.3 (Apple Git-145)
+/// git version 2.42.0.windows.2
+fn parse_version(output: &str) -> Option<Version> {
+    let version = output.trim().strip_prefix("git version ")?;
+    let mut parts = version
+        .split(|c: char| c == '.' || c.is_whitespace())
+        .map(|part| part.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().flatten().unwrap_or_default();
+    let patch = parts.next().flatten().unwrap_or_default();
+    Some(Version::new(major, minor, patch))
+}
+
+/// Where the executable is found in a portable git archive, in order of preference.
+///
+/// MinGit puts a wrapper which sets up the environment for git in `cmd`;
+/// other archives put git directly in `bin`.
+const PORTABLE_EXECUTABLES: [&str; 3] = ["cmd/git.exe", "bin/git.exe", "bin/git"];
+
+/// Download and extract the portable git into the data root, returning the path to its executable.
+///
+/// Archives are extracted into a directory named after their checksum,
+/// so an archive which was already extracted is reused, and changing the checksum downloads the new archive.
+#[tracing::instrument(skip(ctx))]
+async fn download_portable(
+    ctx: &AppContext,
+    portable: &Portable,
+) -> Result<PathBuf, Report<Error>> {
+    let root = ctx.data_root().join("git").join(portable.sha256());
+    if let Some(program) = find_executable(&root) {
+        debug!(program = %program.display(), "Reusing extracted portable git");
+        return Ok(program);
+    }
+
+    let url = portable.url().clone();
+    let client = ctx.http().get(Purpose::PortableGit);
+    let content = client
+        .send(client.get(url.clone()))
+        .await
+        .and_then(|response| response.error_for_status())
+        .context_lazy(|| Error::Download(url.clone()))?
+        .bytes()
+        .await
+        .context_lazy(|| Error::Download(url.clone()))?;
+    sha2::verify_sha256(&content, portable.sha256())
+        .change_context(Error::Verify)
+        .help("ensure that 'portable_git.sha256' is the checksum of the archive at 'portable_git.url'")?;
+
+    // Extract next to the final location, then move it into place,
+    // so that an interrupted extraction isn't mistaken for a complete one.
+    let staged = root.with_extension("new");
+    let extract_to = staged.clone();
+    spawn_blocking(move || extract(&content, &extract_to))
+        .await
+        .change_context_lazy(|| Error::Extract(staged.clone()))?;
+    tokio::fs::rename(&staged, &root)
+        .await
+        .context_lazy(|| Error::Extract(root.clone()))?;
+
+    find_executable(&root)
+        .ok_or_else(|| report!(Error::FindExecutable(root.clone())))
+        .describe_lazy(|| format!("looked for: {}", PORTABLE_EXECUTABLES.join(", ")))
+}
+
+fn extract(content: &[u8], destination: &Path) -> Result<(), Report<Error>> {
+    if destination.exists() {
+        std::fs::remove_dir_all(destination)
+            .context_lazy(|| Error::Extract(destination.to_path_buf()))
+            .describe("remove incomplete extraction")?;
+    }
+    zip::ZipArchive::new(Cursor::new(content))
+        .and_then(|mut archive| archive.extract(destination))
+        .context_lazy(|| Error::Extract(destination.to_path_buf()))
+        .describe("portable git must be a zip archive")
+}
+
+fn find_executable(root: &Path) -> Option<PathBuf> {
+    PORTABLE_EXECUTABLES
+        .iter()
+        .map(|candidate| root.join(candidate))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_versions() {
+        for (output, expected) in [
+            ("git version 2.39.2\n", Version::new(2, 39, 2)),
+            ("git version 2.39.3 (Apple Git-145)", Version::new(2, 39, 3)),
+            ("git version 2.42.0.windows.2", Version::new(2, 42, 0)),
+            ("git version 1.8.3.1", Version::new(1, 8, 3)),
+            ("git version 2.20", Version::new(2, 20, 0)),
+        ] {
+            assert_eq!(parse_version(output), Some(expected), "{output}");
+        }
+        assert_eq!(parse_version("fossa-cli version 3.7.2"), None);
+    }
+
+    #[test]
+    fn validates_portable() {
+        let sha256 = "a".repeat(64);
+        let portable = Portable::validate(String::from("https://example.com/git.zip"), sha256)
+            .expect("must validate");
+        assert_eq!(portable.url().as_str(), "https://example.com/git.zip");
+
+        Portable::validate(String::from("ftp://example.com/git.zip"), "a".repeat(64))
+            .expect_err("must reject non-http url");
+        Portable::validate(
+            String::from("https://example.com/git.zip"),
+            String::from("abcd"),
+        )
+        .expect_err("must reject invalid checksum");
+    }
+}
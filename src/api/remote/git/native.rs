@@ -0,0 +1,249 @@
+//! The git client built into Broker, used instead of the `git` executable when the native backend is configured.
+//!
+//! This is powered by gitoxide, and only lists references and clones repositories;
+//! mirrors, manifest-only clones, and contributors always use the `git` executable.
+//! Its operations block, so [`super::repository`] runs them in the background thread pool.
+
+use std::{io::Write, sync::atomic::AtomicBool};
+
+use base64::{engine::general_purpose, Engine as _};
+use error_stack::{bail, Report};
+use gix::{bstr::BString, protocol::handshake, remote::Direction};
+use itertools::Itertools;
+use tempfile::{NamedTempFile, TempDir};
+
+use crate::{
+    api::{http, ssh},
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        tempfile::{secret_tempfile, tempdir},
+    },
+};
+
+use super::{
+    repository::{git_ssh_command, Error},
+    transport::{Auth, Transport},
+    Reference,
+};
+
+/// The references listed by [`ls_remote`]: every branch and tag.
+const REFSPECS: [&str; 2] = ["+refs/heads/*:refs/heads/*", "+refs/tags/*:refs/tags/*"];
+
+/// The config for the client, along with the temporary files it references.
+///
+/// The temporary files are removed when this is dropped,
+/// so it must be kept alive until the operation has finished.
+struct Options {
+    overrides: Vec<BString>,
+    _ssh_key_file: Option<NamedTempFile>,
+}
+
+impl Options {
+    /// Construct the config for the transport's auth.
+    ///
+    /// Short-lived credentials must already be minted, as with [`Transport::with_fresh_credentials`].
+    fn new(transport: &Transport) -> Result<Self, Report<Error>> {
+        if transport.ssh_passphrase().is_some() {
+            bail!(Error::NativeUnsupported(String::from(
+                "SSH keys with passphrases"
+            )));
+        }
+        if let Transport::Http { endpoint, .. } = transport {
+            if !endpoint.starts_with("http") {
+                bail!(Error::HttpRemoteInvalid(endpoint.to_string()));
+            }
+        }
+
+        // Credential helpers can override the configured auth, so they're turned off.
+        let mut overrides = vec![BString::from("credential.helper=")];
+        let mut ssh_key_file = None;
+        match transport.auth() {
+            Auth::Http(Some(http::Auth::Basic { username, password })) => {
+                let secret_header =
+                    format!("{}:{}", username.expose_secret(), password.expose_secret());
+                let secret_header = general_purpose::STANDARD.encode(secret_header);
+                overrides
+                    .push(format!("http.extraHeader=AUTHORIZATION: Basic {secret_header}").into());
+            }
+            Auth::Http(Some(http::Auth::Header(header))) => {
+                overrides.push(format!("http.extraHeader={}", header.expose_secret()).into());
+            }
+            Auth::Http(Some(http::Auth::Command(_))) => {
+                bail!(Error::NativeUnsupported(String::from(
+                    "credential commands which haven't been run"
+                )));
+            }
+            Auth::Http(Some(http::Auth::AwsCodeCommit(_))) => {
+                bail!(Error::NativeUnsupported(String::from(
+                    "AWS CodeCommit passwords which haven't been computed"
+                )));
+            }
+            Auth::Http(None) => {}
+            Auth::Ssh(ssh::Auth::KeyFile(path)) => {
+                overrides.push(format!("core.sshCommand={}", git_ssh_command(&path)?).into());
+            }
+            Auth::Ssh(ssh::Auth::KeyValue(key)) => {
+                // Write the contents of the SSH key to a file so that we can point to it.
+                let mut file = secret_tempfile()
+                    .context(Error::SshKeyFileCreation)
+                    .describe(
+                        "Broker must create a temporary SSH key file to provide the key to ssh",
+                    )?;
+                file.write_all(key.expose_secret().as_bytes())
+                    .context(Error::SshKeyFileCreation)?;
+                overrides.push(format!("core.sshCommand={}", git_ssh_command(file.path())?).into());
+                ssh_key_file = Some(file);
+            }
+        }
+
+        Ok(Self {
+            overrides,
+            _ssh_key_file: ssh_key_file,
+        })
+    }
+
+    /// The options for opening a repository with this config.
+    ///
+    /// Repositories are isolated from the system and user git config, like the `git` executable is
+    /// isolated from the user's ssh config, so that the configured auth is the only auth used.
+    fn open(&self) -> gix::open::Options {
+        gix::open::Options::isolated().config_overrides(self.overrides.iter().cloned())
+    }
+}
+
+/// Credentials are only provided through config, so the client must never ask for them.
+fn no_credentials(_: gix::credentials::helper::Action) -> gix::credentials::protocol::Result {
+    Ok(None)
+}
+
+/// List the references of the remote, in the same format as `git ls-remote`.
+pub fn ls_remote(transport: &Transport) -> Result<String, Report<Error>> {
+    let options = Options::new(transport)?;
+    let endpoint = transport.endpoint().to_string();
+
+    // The client can only talk to remotes through a repository, so it uses an empty one.
+    let dir = tempdir()
+        .context(Error::Native)
+        .describe("create temporary repository for listing references")
+        .help("altering the temporary directory location may resolve this issue")?;
+    let repo = gix::ThreadSafeRepository::init_opts(
+        dir.path(),
+        gix::create::Kind::Bare,
+        gix::create::Options::default(),
+        options.open(),
+    )
+    .context(Error::Native)
+    .describe("create temporary repository for listing references")?
+    .to_thread_local();
+
+    let remote = repo
+        .remote_at(endpoint.as_str())
+        .context(Error::Native)
+        .describe_lazy(|| format!("parse remote '{endpoint}'"))?
+        .with_refspecs(REFSPECS, Direction::Fetch)
+        .context(Error::Native)?;
+    let ref_map = remote
+        .connect(Direction::Fetch)
+        .context(Error::Native)
+        .describe_lazy(|| format!("connect to '{endpoint}'"))?
+        .with_credentials(no_credentials)
+        .ref_map(gix::progress::Discard, Default::default())
+        .context(Error::Native)
+        .describe_lazy(|| format!("list references of '{endpoint}'"))?;
+
+    Ok(format_refs(&ref_map.remote_refs))
+}
+
+/// Format references like `git ls-remote`: peeled tags are listed twice,
+/// once for the tag and again with a `^{}` suffix for the commit to which it points.
+fn format_refs(refs: &[handshake::Ref]) -> String {
+    refs.iter()
+        .flat_map(|reference| {
+            let (name, target, peeled) = reference.unpack();
+            let target = target.map(|id| format!("{id}\t{name}"));
+            let peeled = peeled.map(|id| format!("{id}\t{name}^{{}}"));
+            target.into_iter().chain(peeled)
+        })
+        .join("\n")
+}
+
+/// Clone a [`Reference`] into a temporary directory.
+///
+/// Unlike the `git` executable, the client can't do a blobless clone, so the whole history is downloaded.
+pub fn clone_reference(
+    transport: &Transport,
+    reference: &Reference,
+) -> Result<TempDir, Report<Error>> {
+    let options = Options::new(transport)?;
+    let endpoint = transport.endpoint().to_string();
+    let tmpdir = tempdir()
+        .context(Error::Native)
+        .describe("create temporary directory for clone")
+        .help("altering the temporary directory location may resolve this issue")?;
+
+    let interrupt = AtomicBool::new(false);
+    let mut fetch = gix::clone::PrepareFetch::new(
+        endpoint.as_str(),
+        tmpdir.path(),
+        gix::create::Kind::WithWorktree,
+        gix::create::Options::default(),
+        options.open(),
+    )
+    .context(Error::Native)
+    .describe_lazy(|| format!("prepare to clone '{endpoint}'"))?
+    .with_ref_name(Some(reference.name().as_str()))
+    .context(Error::Native)
+    .describe_lazy(|| format!("select reference '{reference}'"))?
+    .configure_connection(|connection| {
+        connection.set_credentials(no_credentials);
+        Ok(())
+    });
+
+    let (mut checkout, _) = fetch
+        .fetch_then_checkout(gix::progress::Discard, &interrupt)
+        .context(Error::Native)
+        .describe_lazy(|| format!("fetch '{reference}' from '{endpoint}'"))?;
+    checkout
+        .main_worktree(gix::progress::Discard, &interrupt)
+        .context(Error::Native)
+        .describe_lazy(|| format!("check out '{reference}'"))?;
+
+    Ok(tmpdir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::remote::Remote;
+
+    #[test]
+    fn refuses_ssh_passphrases() {
+        let transport = Transport::new_ssh(
+            Remote::new(String::from("git@github.com:fossas/broker.git")),
+            ssh::Auth::KeyFile("/home/me/.ssh/id_rsa".into()),
+            Some(String::from("hunter2").into()),
+        );
+        let err = Options::new(&transport)
+            .err()
+            .expect("must not support passphrases");
+        assert!(matches!(err.current_context(), Error::NativeUnsupported(_)));
+    }
+
+    #[test]
+    fn configures_http_header() {
+        let transport = Transport::new_http(
+            Remote::new(String::from("https://github.com/fossas/broker.git")),
+            Some(http::Auth::new_header(
+                String::from("Authorization: token abcd1234").into(),
+            )),
+        );
+        let options = Options::new(&transport).expect("must configure client");
+        assert_eq!(
+            options.overrides,
+            vec![
+                BString::from("credential.helper="),
+                BString::from("http.extraHeader=Authorization: token abcd1234"),
+            ]
+        );
+    }
+}
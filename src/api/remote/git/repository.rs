@@ -1,19 +1,31 @@
 //! Wrapper for Git
 use base64::{engine::general_purpose, Engine as _};
-use error_stack::{bail, report, Report};
+use error_stack::{bail, report, Report, ResultExt};
+use futures::future::try_join3;
 use itertools::Itertools;
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use tempfile::{tempdir, NamedTempFile, TempDir};
+use std::sync::{Arc, PoisonError};
+use tempfile::{NamedTempFile, TempDir, TempPath};
 use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
 
-use super::Reference;
+use super::{executable, native, Backend, Reference};
+use crate::api::remote::Contributors;
+use crate::doc::code::{ErrorCode, HasErrorCode};
 use crate::ext::command::{Command, CommandDescriber, Output, OutputProvider, Value};
 use crate::ext::error_stack::{ErrorHelper, IntoContext};
-use crate::ext::result::WrapOk;
-use crate::{api::http, api::remote::git, api::ssh, ext::error_stack::DescribeContext};
+use crate::ext::io::spawn_blocking;
+use crate::ext::result::{WrapErr, WrapOk};
+use crate::ext::secrecy::ComparableSecretString;
+use crate::ext::tempfile::{named_tempfile_with_suffix, secret_tempfile, tempdir};
+use crate::{api::http, api::remote::git, api::ssh, bandwidth, ext::error_stack::DescribeContext};
 
 use super::transport::Transport;
 
@@ -32,6 +44,18 @@ pub enum Error {
     #[error("create temporary ssh key file")]
     SshKeyFileCreation,
 
+    /// When the transport uses short-lived credentials, they are minted before running git; that failed.
+    #[error("mint short-lived credentials")]
+    MintCredentials,
+
+    /// When the SSH key has a passphrase, this module needs to create a helper which provides it to ssh.
+    #[error("create temporary ssh askpass helper")]
+    SshAskpassCreation,
+
+    /// Configuring which files are checked out of a sparse clone failed.
+    #[error("configure sparse checkout")]
+    SparseCheckout,
+
     /// Parsing git output failed.
     #[error("parse git output")]
     ParseGitOutput,
@@ -48,11 +72,54 @@ pub enum Error {
     #[error("http remote '{0}' does not begin with 'http'")]
     HttpRemoteInvalid(String),
 
+    /// The git client built into Broker failed.
+    #[error("run built in git client")]
+    Native,
+
+    /// The git client built into Broker doesn't support the transport's configuration.
+    #[error("built in git client doesn't support {0}")]
+    NativeUnsupported(String),
+
     /// It's possible, although unlikely, that a path on the file system is not a valid UTF8 string.
-    /// If this occurs when creating the temporary path to which the directory is cloned,
-    /// this module cannot provide that path as an argument to the git executable and this error is returned.
+    /// If this occurs for the path to an SSH key or the SSH askpass helper,
+    /// this module cannot provide that path to ssh and this error is returned.
     #[error("path on local system is not a valid UTF8 string: {0}")]
     PathNotValidUtf8(PathBuf),
+
+    /// After cloning a reference, the commit checked out isn't the one listed for the reference by the remote.
+    /// This happens when the name of the reference is ambiguous (such as a tag and a branch with the same name),
+    /// or when the reference moved between listing and cloning it;
+    /// either way the checkout isn't the revision that would be scanned, so the clone is rejected.
+    #[error(
+        "cloned '{reference}' at commit '{actual}', but the remote listed commit '{expected}'"
+    )]
+    UnexpectedHead {
+        /// The name of the reference that was cloned.
+        reference: String,
+        /// The commit listed for the reference.
+        expected: String,
+        /// The commit that was checked out.
+        actual: String,
+    },
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Execution(..) => ErrorCode::new(1301),
+            Self::TempDirCreation(..) => ErrorCode::new(1302),
+            Self::SshKeyFileCreation => ErrorCode::new(1303),
+            Self::MintCredentials => ErrorCode::new(1304),
+            Self::SshAskpassCreation => ErrorCode::new(1305),
+            Self::SparseCheckout => ErrorCode::new(1306),
+            Self::ParseGitOutput => ErrorCode::new(1307),
+            Self::HttpRemoteInvalid(..) => ErrorCode::new(1308),
+            Self::Native => ErrorCode::new(1309),
+            Self::NativeUnsupported(..) => ErrorCode::new(1310),
+            Self::PathNotValidUtf8(..) => ErrorCode::new(1311),
+            Self::UnexpectedHead { .. } => ErrorCode::new(1312),
+        }
+    }
 }
 
 impl Error {
@@ -63,16 +130,83 @@ impl Error {
     fn creating_temp_dir() -> Self {
         Error::TempDirCreation(env::temp_dir())
     }
-
-    fn path_invalid_utf(path: &Path) -> Self {
-        Error::PathNotValidUtf8(path.to_path_buf())
-    }
 }
 
 /// List all references
 #[tracing::instrument]
 pub async fn list_references(transport: &Transport) -> Result<Vec<Reference>, Report<Error>> {
-    get_all_references(transport).await
+    list_references_matching(transport, &|_| true).await
+}
+
+/// List the references for which `include` returns true.
+///
+/// The output of `git ls-remote` is parsed as it's read, so references which aren't included are never collected;
+/// this keeps memory bounded even for repositories with millions of references.
+#[tracing::instrument(skip(include))]
+pub async fn list_references_matching(
+    transport: &Transport,
+    include: &(dyn Fn(&Reference) -> bool + Send + Sync),
+) -> Result<Vec<Reference>, Report<Error>> {
+    if git::backend() == Backend::Native {
+        if let Some(output) = run_native(transport, "list references", native::ls_remote).await {
+            return references_from_lines(output.lines(), include).wrap_ok();
+        }
+    }
+
+    let transport = transport
+        .with_fresh_credentials()
+        .await
+        .change_context(Error::MintCredentials)?;
+    let git = construct_git_command_with_askpass(&transport, &ls_remote_args(&transport), None)?;
+    let mut stream = git
+        .command
+        .stream()
+        .context_lazy(|| Error::running_git_command(&git.command))?;
+    let redacter = stream.redacter();
+
+    // Parse references from stdout line by line, keeping only the ones which are included.
+    let mut stdout = BufReader::new(stream.take_stdout()).lines();
+    let stdout_reader = async {
+        let mut references = Vec::new();
+        while let Some(line) = stdout
+            .next_line()
+            .await
+            .context_lazy(|| Error::running_git_command(&git.command))?
+        {
+            references.extend(line_to_git_ref(&line).filter(|(reference, _)| include(reference)));
+        }
+        Ok(references)
+    };
+
+    // Buffer stderr so that it can be reported if the command fails; it's small regardless of the repository.
+    let mut stderr = stream.take_stderr();
+    let stderr_reader = async {
+        let mut buf = String::new();
+        stderr
+            .read_to_string(&mut buf)
+            .await
+            .context_lazy(|| Error::running_git_command(&git.command))?;
+        redacter.redact_str(&buf).wrap_ok()
+    };
+
+    let waiter = async {
+        stream
+            .wait()
+            .await
+            .context_lazy(|| Error::running_git_command(&git.command))
+    };
+    let (references, stderr, status) = try_join3(stdout_reader, stderr_reader, waiter).await?;
+
+    if !status.success() {
+        let description = stream.describe().with_stderr(stderr);
+        let description = match status.code() {
+            Some(code) => description.with_status(code),
+            None => description,
+        };
+        bail!(Error::Execution(description.to_string()));
+    }
+
+    peel_references(references).wrap_ok()
 }
 
 /// Clone a [`Reference`] into a temporary directory.
@@ -81,7 +215,342 @@ pub async fn clone_reference(
     transport: &Transport,
     reference: &Reference,
 ) -> Result<TempDir, Report<Error>> {
-    blobless_clone(transport, Some(reference)).await
+    let transfer = bandwidth::Transfer::begin().await;
+    let cloned = if git::backend() == Backend::Native {
+        let reference = reference.clone();
+        run_native(transport, "clone", move |transport| {
+            native::clone_reference(transport, &reference)
+        })
+        .await
+    } else {
+        None
+    };
+    let tmpdir = match cloned {
+        Some(tmpdir) => tmpdir,
+        None => blobless_clone(transport, Some(reference), true).await?,
+    };
+    finish_clone(transfer, tmpdir.path()).await;
+    verify_head(tmpdir.path(), reference).await?;
+    Ok(tmpdir)
+}
+
+/// Withdraw the size of the git directory of a clone, which approximates what was downloaded,
+/// from the bandwidth budget.
+async fn finish_clone(transfer: bandwidth::Transfer, checkout: &Path) {
+    let used = transfer.usage(&checkout.join(".git")).await;
+    transfer.finish(used);
+}
+
+/// Run an operation with the git client built into Broker, in the background thread pool.
+///
+/// Returns `None` if the operation fails or isn't supported for the transport,
+/// in which case the caller falls back to the `git` executable.
+async fn run_native<T, F>(transport: &Transport, operation: &str, work: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Transport) -> Result<T, Report<Error>> + Send + 'static,
+{
+    let transport = match transport.with_fresh_credentials().await {
+        Ok(transport) => transport.into_owned(),
+        // The git executable mints credentials again, and reports the failure if it persists.
+        Err(err) => {
+            warn!("Unable to mint credentials for built in git client, falling back to git executable: {err:#?}");
+            return None;
+        }
+    };
+
+    match spawn_blocking(move || work(&transport)).await {
+        Ok(value) => Some(value),
+        Err(err) => {
+            match err.downcast_ref::<Error>() {
+                Some(Error::NativeUnsupported(unsupported)) => {
+                    debug!("Built in git client doesn't support {unsupported}, using git executable to {operation}")
+                }
+                _ => warn!(
+                    "Built in git client failed to {operation}, falling back to git executable: {err:#?}"
+                ),
+            }
+            None
+        }
+    }
+}
+
+/// Clone only the files matching `patterns` for a [`Reference`] into a temporary directory.
+///
+/// The clone is blobless and sparse: git downloads the commits and trees of the reference,
+/// but only downloads the contents of the files which match the patterns.
+/// Patterns are written like lines of a `.gitignore` file.
+#[tracing::instrument(skip(transport))]
+pub async fn clone_manifests(
+    transport: &Transport,
+    reference: &Reference,
+    patterns: &[&str],
+) -> Result<TempDir, Report<Error>> {
+    let transfer = bandwidth::Transfer::begin().await;
+    let tmpdir = blobless_clone(transport, Some(reference), false).await?;
+
+    // Sparse checkout is configured directly rather than with `git sparse-checkout`,
+    // since that command's defaults and flags have changed across git versions.
+    let enable = [
+        Value::new_plain("config"),
+        Value::new_plain("core.sparseCheckout"),
+        Value::new_plain("true"),
+    ];
+    run_git(transport, &enable, Some(tmpdir.path())).await?;
+
+    let info = tmpdir.path().join(".git").join("info");
+    tokio::fs::create_dir_all(&info)
+        .await
+        .context(Error::SparseCheckout)
+        .describe_lazy(|| format!("create directory '{}'", info.display()))?;
+    let sparse = info.join("sparse-checkout");
+    tokio::fs::write(&sparse, patterns.join("\n"))
+        .await
+        .context(Error::SparseCheckout)
+        .describe_lazy(|| format!("write patterns to '{}'", sparse.display()))?;
+
+    let checkout = [
+        Value::new_plain("checkout"),
+        Value::new_plain("--quiet"),
+        Value::new_plain(reference.name()),
+    ];
+    // The contents of the matching files are downloaded as they're checked out.
+    run_git(transport, &checkout, Some(tmpdir.path())).await?;
+    finish_clone(transfer, tmpdir.path()).await;
+    verify_head(tmpdir.path(), reference).await?;
+    Ok(tmpdir)
+}
+
+/// Serializes updates to each mirror, so that concurrent fetches don't contend on git's lock files.
+static MIRROR_LOCKS: Lazy<std::sync::Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>> =
+    Lazy::new(Default::default);
+
+fn mirror_lock(mirror: &Path) -> Arc<Mutex<()>> {
+    let mut locks = MIRROR_LOCKS.lock().unwrap_or_else(PoisonError::into_inner);
+    locks.entry(mirror.to_path_buf()).or_default().clone()
+}
+
+/// Fetch the branches and tags of the remote into a bare mirror repository at `mirror`,
+/// creating the mirror if it doesn't yet exist.
+///
+/// Worktrees created by [`checkout_from_mirror`] leave records behind in the mirror
+/// once their temporary directory is dropped; these are pruned on each update.
+#[tracing::instrument(skip(transport))]
+pub async fn update_mirror(transport: &Transport, mirror: &Path) -> Result<(), Report<Error>> {
+    let lock = mirror_lock(mirror);
+    let _guard = lock.lock().await;
+
+    if !mirror.join("HEAD").exists() {
+        debug!(mirror = %mirror.display(), "Creating mirror");
+        let args = [
+            Value::new_plain("init"),
+            Value::new_plain("--bare"),
+            Value::new_path(mirror),
+        ];
+        run_git(transport, &args, None).await?;
+    }
+
+    let prune = [Value::new_plain("worktree"), Value::new_plain("prune")];
+    run_git(transport, &prune, Some(mirror)).await?;
+
+    let endpoint = transport.endpoint().to_string();
+    let fetch = [
+        Value::new_plain("fetch"),
+        Value::new_plain("--progress"),
+        Value::new_plain("--prune"),
+        Value::new_plain("--force"),
+        Value::new_plain(&endpoint),
+        Value::new_plain("+refs/heads/*:refs/heads/*"),
+        Value::new_plain("+refs/tags/*:refs/tags/*"),
+    ];
+    let transfer = bandwidth::Transfer::begin().await;
+    let before = transfer.usage(mirror).await;
+    run_git(transport, &fetch, Some(mirror)).await?;
+    let after = transfer.usage(mirror).await;
+    transfer.finish(after.saturating_sub(before));
+    Ok(())
+}
+
+/// Check out a [`Reference`] from the bare mirror repository at `mirror` into a temporary directory.
+///
+/// If the mirror doesn't yet contain the commit referenced, it is updated first.
+#[tracing::instrument(skip(transport))]
+pub async fn checkout_from_mirror(
+    transport: &Transport,
+    mirror: &Path,
+    reference: &Reference,
+) -> Result<TempDir, Report<Error>> {
+    if !mirror_contains(transport, mirror, reference).await {
+        update_mirror(transport, mirror).await?;
+    }
+
+    let tmpdir = tempdir()
+        .context_lazy(Error::creating_temp_dir)
+        .help("altering the temporary directory location may resolve this issue")
+        .describe("temporary directory location uses $TMPDIR on Linux and macOS; for Windows it uses the 'GetTempPath' system call")?;
+
+    let args = [
+        Value::new_plain("worktree"),
+        Value::new_plain("add"),
+        Value::new_plain("--detach"),
+        Value::new_path(tmpdir.path()),
+        Value::new_plain(reference.commit()),
+    ];
+    run_git(transport, &args, Some(mirror))
+        .await
+        .map(|_| tmpdir)
+}
+
+/// Report whether the mirror contains the commit referenced.
+async fn mirror_contains(transport: &Transport, mirror: &Path, reference: &Reference) -> bool {
+    if !mirror.join("HEAD").exists() {
+        return false;
+    }
+
+    let commit = format!("{}^{{commit}}", reference.commit());
+    let args = [
+        Value::new_plain("cat-file"),
+        Value::new_plain("-e"),
+        Value::new_plain(&commit),
+    ];
+    run_git(transport, &args, Some(mirror)).await.is_ok()
+}
+
+/// How far back in history commits count towards contributors, in a form understood by `git log --since`.
+///
+/// This matches the window FOSSA CLI uses for the projects it analyzes itself.
+const CONTRIBUTOR_WINDOW: &str = "90 days ago";
+
+/// List the people who committed to the repository checked out at `checkout` within [`CONTRIBUTOR_WINDOW`].
+///
+/// This only reads commits, which blobless clones and worktrees of mirrors both have locally,
+/// so it doesn't contact the remote.
+#[tracing::instrument]
+pub async fn contributors(checkout: &Path) -> Result<Contributors, Report<Error>> {
+    let command = Command::new(executable::program())
+        .arg_plain("log")
+        .arg_plain(format!("--since={CONTRIBUTOR_WINDOW}"))
+        .arg_plain("--date=short")
+        .arg_plain("--format=%ae|%cd")
+        .current_dir(checkout);
+    let output = command
+        .output_traced()
+        .await
+        .context_lazy(|| Error::running_git_command(&command))?;
+
+    if !output.status().success() {
+        bail!(Error::running_git_command(&output));
+    }
+
+    let output = String::from_utf8(output.stdout()).context(Error::ParseGitOutput)?;
+    Ok(parse_contributors(&output))
+}
+
+/// The hash of the tree at `HEAD` in the checkout.
+///
+/// Unlike the commit, this only depends on the content that's checked out,
+/// so re-tagged or re-pushed identical trees have the same hash.
+#[tracing::instrument]
+pub async fn tree_hash(checkout: &Path) -> Result<String, Report<Error>> {
+    rev_parse(checkout, "HEAD^{tree}").await
+}
+
+/// Verify that `HEAD` in the checkout is the commit listed for the reference by `git ls-remote`.
+///
+/// References are cloned by name, so if the name is ambiguous (such as a tag and a branch with the same name)
+/// git may check out a different revision than the one listed; scanning it would upload the wrong revision.
+#[tracing::instrument]
+pub async fn verify_head(checkout: &Path, reference: &Reference) -> Result<(), Report<Error>> {
+    let actual = rev_parse(checkout, "HEAD").await?;
+
+    // Annotated tags may be listed with the ID of the tag object rather than the commit it points to,
+    // so peel the listed ID to its commit when the checkout has it.
+    let listed = reference.commit();
+    let expected = rev_parse(checkout, &format!("{listed}^{{commit}}"))
+        .await
+        .unwrap_or_else(|_| listed.to_string());
+    if actual == expected {
+        return Ok(());
+    }
+
+    report!(Error::UnexpectedHead {
+        reference: reference.name().to_string(),
+        expected,
+        actual,
+    })
+    .wrap_err()
+    .help("ensure that no tag and branch in the repository share this name")
+    .describe("the reference may also have been updated on the remote after Broker listed it")
+}
+
+/// Resolve the revision to the ID of an object in the checkout.
+async fn rev_parse(checkout: &Path, revision: &str) -> Result<String, Report<Error>> {
+    let command = Command::new(executable::program())
+        .arg_plain("rev-parse")
+        .arg_plain("--verify")
+        .arg_plain(revision)
+        .current_dir(checkout);
+    let output = command
+        .output_traced()
+        .await
+        .context_lazy(|| Error::running_git_command(&command))?;
+
+    if !output.status().success() {
+        bail!(Error::running_git_command(&output));
+    }
+
+    let output = String::from_utf8(output.stdout()).context(Error::ParseGitOutput)?;
+    Ok(output.trim().to_string())
+}
+
+/// Whether `HEAD` in the checkout descends from the provided commit.
+///
+/// If the commit isn't in the checkout at all, it isn't in the history of `HEAD`
+/// (clones include every ancestor of the reference), so `HEAD` doesn't descend from it.
+#[tracing::instrument]
+pub async fn descends_from(checkout: &Path, commit: &str) -> Result<bool, Report<Error>> {
+    let command = Command::new(executable::program())
+        .arg_plain("merge-base")
+        .arg_plain("--is-ancestor")
+        .arg_plain(commit)
+        .arg_plain("HEAD")
+        .current_dir(checkout);
+    let output = command
+        .output_traced()
+        .await
+        .context_lazy(|| Error::running_git_command(&command))?;
+
+    // `git merge-base --is-ancestor` exits with 1 if the commit isn't an ancestor,
+    // and with 128 if the commit doesn't exist.
+    match output.status().code() {
+        Some(0) => Ok(true),
+        Some(1) | Some(128) => Ok(false),
+        _ => bail!(Error::running_git_command(&output)),
+    }
+}
+
+/// parse the output from `git log --date=short --format=%ae|%cd`
+/// The output lists the author email and commit date of each commit, newest first, like this:
+///
+/// jessica@example.com|2023-11-14
+/// zach@example.com|2023-11-10
+/// jessica@example.com|2023-11-02
+///
+/// Each contributor is reported with the date of their latest commit.
+fn parse_contributors(output: &str) -> Contributors {
+    let mut contributors = BTreeMap::new();
+    for line in output.lines() {
+        let Some((email, date)) = line.trim().split_once('|') else {
+            continue;
+        };
+        if email.is_empty() {
+            continue;
+        }
+        contributors
+            .entry(email.to_lowercase())
+            .or_insert_with(|| date.to_string());
+    }
+    Contributors::new(contributors)
 }
 
 /// The args for the call to ls-remote
@@ -93,26 +562,85 @@ fn ls_remote_args(transport: &Transport) -> Vec<Value> {
     ]
 }
 
-/// ls_remote calls `git ls-remote <endpoint>` on the transport's endpoint
+/// ls_remote calls `git ls-remote <endpoint>` on the transport's endpoint,
+/// or lists the references with the built in git client if the native backend is installed.
 #[tracing::instrument(skip(transport))]
 pub async fn ls_remote(transport: &Transport) -> Result<String, Report<Error>> {
+    if git::backend() == Backend::Native {
+        if let Some(output) = run_native(transport, "list references", native::ls_remote).await {
+            return Ok(output);
+        }
+    }
+
     let output = run_git(transport, &ls_remote_args(transport), None).await?;
     let output = String::from_utf8(output.stdout()).context(Error::ParseGitOutput)?;
     Ok(output)
 }
 
-#[tracing::instrument(skip(transport))]
-async fn get_all_references(transport: &Transport) -> Result<Vec<Reference>, Report<Error>> {
-    let output = ls_remote(transport).await?;
-    let references = parse_ls_remote(output);
+/// The references listed in the output of `git ls-remote`, like the output of [`ls_remote`].
+pub fn references_from_ls_remote(output: String) -> Vec<Reference> {
+    references_from_lines(output.lines(), &|_| true)
+}
+
+/// The references for which `include` returns true, parsed from lines of `git ls-remote` output.
+fn references_from_lines<'a>(
+    lines: impl Iterator<Item = &'a str>,
+    include: &(dyn Fn(&Reference) -> bool + Send + Sync),
+) -> Vec<Reference> {
+    let references = lines
+        .filter_map(line_to_git_ref)
+        .filter(|(reference, _)| include(reference))
+        .collect_vec();
+    peel_references(references)
+}
+
+/// Resolve annotated tags to the commits they point at, and de-dupe the references.
+///
+/// Annotated tags are listed twice in the output from `git ls-remote`, like this:
+/// ```not_rust
+/// b72eb52c09df108c81e755bc3a083ce56d7e4197        refs/tags/v0.0.1
+/// ffb878b5eb456e7e1725606192765dcb6c7e78b8        refs/tags/v0.0.1^{}
+/// ```
+///
+/// The first line is the ID of the tag object, and the second (the "peeled" tag) is the commit it points at.
+/// The commit is what's checked out, and it's used as the state of the tag, so a tag is always stored
+/// and scanned at the same commit regardless of whether it's annotated.
+/// The ID of the tag object is kept too, since earlier versions of Broker stored annotated tags at it;
+/// see [`Reference::legacy_coordinate`].
+fn peel_references(references: Vec<(Reference, bool)>) -> Vec<Reference> {
+    let peeled = references
+        .iter()
+        .filter(|(_, peeled)| *peeled)
+        .map(|(reference, _)| (reference.name().clone(), reference.commit().to_string()))
+        .collect::<HashMap<_, _>>();
+
+    // The peeled line always follows the line for the tag object, so only the latter is needed.
+    references
+        .into_iter()
+        .filter(|(_, peeled)| !peeled)
+        .map(|(reference, _)| match reference {
+            Reference::Tag { name, commit, .. } => match peeled.get(&name) {
+                Some(peeled) => Reference::Tag {
+                    commit: peeled.clone(),
+                    object: Some(commit),
+                    name,
+                },
+                None => Reference::new_tag(name, commit),
+            },
+            branch => branch,
+        })
+        .unique()
+        .collect_vec()
+}
 
-    // Tags sometimes get duplicated in the output from `git ls-remote`, like this:
-    // b72eb52c09df108c81e755bc3a083ce56d7e4197        refs/tags/v0.0.1
-    // ffb878b5eb456e7e1725606192765dcb6c7e78b8        refs/tags/v0.0.1^{}
-    //
-    // We can use either of these (the commit resolves to the ^{} version when we check it out), but we need to
-    // de-dupe it
-    references.into_iter().unique().collect_vec().wrap_ok()
+/// A git command, along with the temporary files referenced by its environment.
+///
+/// The temporary files are removed when this is dropped,
+/// so it must be kept alive until the command has finished running.
+struct GitCommand {
+    command: Command,
+    _ssh_key_file: NamedTempFile,
+    _ssh_askpass: Option<TempPath>,
 }
 
 /// Construct a git command, including the default args and the environment required for the transport's auth
@@ -121,25 +649,48 @@ fn construct_git_command(
     transport: &Transport,
     args: &[Value],
     cwd: Option<&Path>,
-) -> Result<Command, Report<Error>> {
+) -> Result<GitCommand, Report<Error>> {
     let args = default_args(transport)?
         .into_iter()
         .chain(args.iter().cloned().map_into())
         .collect::<Vec<_>>();
 
-    let mut ssh_key_file = NamedTempFile::new()
+    let mut ssh_key_file = secret_tempfile()
         .context(Error::SshKeyFileCreation)
         .describe("Broker must create a temporary SSH key file (even if not using SSH key authentication) to ensure reproducible authentication")?;
     let env = env_vars(transport, &mut ssh_key_file)?;
 
-    let mut command = Command::new("git")
+    let mut command = Command::new(executable::program())
         .args(args)
         .envs(env)
         .env_remove("GIT_ASKPASS");
     if let Some(directory) = cwd {
         command = command.current_dir(directory);
     }
-    Ok(command)
+    Ok(GitCommand {
+        command,
+        _ssh_key_file: ssh_key_file,
+        _ssh_askpass: None,
+    })
+}
+
+/// Construct a git command as [`construct_git_command`], additionally providing the SSH key passphrase
+/// (if the transport has one) to ssh through an askpass helper.
+///
+/// This is kept separate so that pastable commands prompt for the passphrase instead of
+/// referring to a helper which won't exist by the time they're run.
+fn construct_git_command_with_askpass(
+    transport: &Transport,
+    args: &[Value],
+    cwd: Option<&Path>,
+) -> Result<GitCommand, Report<Error>> {
+    let mut git = construct_git_command(transport, args, cwd)?;
+    if let Some(passphrase) = transport.ssh_passphrase() {
+        let (helper, env) = ssh_askpass(passphrase)?;
+        git.command = git.command.envs(env);
+        git._ssh_askpass = Some(helper);
+    }
+    Ok(git)
 }
 
 #[tracing::instrument(skip(transport))]
@@ -148,11 +699,16 @@ async fn run_git(
     args: &[Value],
     cwd: Option<&Path>,
 ) -> Result<Output, Report<Error>> {
-    let command = construct_git_command(transport, args, cwd)?;
-    let output = command
-        .output()
+    let transport = transport
+        .with_fresh_credentials()
         .await
-        .context_lazy(|| Error::running_git_command(&command))?;
+        .change_context(Error::MintCredentials)?;
+    let git = construct_git_command_with_askpass(&transport, args, cwd)?;
+    let output = git
+        .command
+        .output_traced()
+        .await
+        .context_lazy(|| Error::running_git_command(&git.command))?;
 
     if !output.status().success() {
         bail!(Error::running_git_command(&output));
@@ -168,8 +724,8 @@ fn pastable_git_command(
     args: &[Value],
     cwd: Option<&Path>,
 ) -> Result<String, Report<Error>> {
-    let command = construct_git_command(transport, args, cwd)?;
-    command.describe().pastable().wrap_ok()
+    let git = construct_git_command(transport, args, cwd)?;
+    git.command.describe().pastable().wrap_ok()
 }
 
 /// Construct a pastable string containing a `git ls-remote` command, including the default args and the environment required for the transport's auth
@@ -177,17 +733,23 @@ pub fn pastable_ls_remote_command(transport: &Transport) -> Result<String, Repor
     pastable_git_command(transport, &ls_remote_args(transport), None)
 }
 
-/// Do a blobless clone of the repository, checking out the Reference if it exists
+/// Do a blobless clone of the repository, checking out the Reference if it exists and `checkout` is set
 #[tracing::instrument(skip(transport))]
 async fn blobless_clone(
     transport: &Transport,
     reference: Option<&Reference>,
+    checkout: bool,
 ) -> Result<TempDir, Report<Error>> {
     let mut args = vec![
         Value::new_plain("clone"),
+        Value::new_plain("--progress"),
         Value::new_plain("--filter=blob:none"),
     ];
 
+    if !checkout {
+        args.push(Value::new_plain("--no-checkout"));
+    }
+
     if let Some(reference) = reference {
         args.push(Value::new_plain("--branch"));
         args.push(Value::new_plain(reference.name()));
@@ -199,15 +761,8 @@ async fn blobless_clone(
         .help("altering the temporary directory location may resolve this issue")
         .describe("temporary directory location uses $TMPDIR on Linux and macOS; for Windows it uses the 'GetTempPath' system call")?;
 
-    let tmp_path = tmpdir
-        .path()
-        .to_str()
-        .ok_or_else(|| report!(Error::path_invalid_utf(tmpdir.path())))
-        .help("changing the system temporary directory to a path that is valid UTF-8 may resolve this issue")
-        .describe("Broker needs the temporary path to be valid UTF-8 because it's sent as an argument to the git executable")?;
-
     args.push(Value::new_plain(&endpoint));
-    args.push(Value::new_plain(tmp_path));
+    args.push(Value::new_path(tmpdir.path()));
     run_git(transport, args.as_slice(), None)
         .await
         .map(|_| tmpdir)
@@ -227,7 +782,8 @@ fn default_args(transport: &Transport) -> Result<Vec<Value>, Report<Error>> {
         //   -c http.extraHeader="AUTHORIZATION: Basic ${B64_GITHUB_TOKEN}" \
         //   clone https://github.com/spatten/fanopticon
         git::transport::Auth::Http(Some(http::Auth::Basic { username, password })) => {
-            let secret_header = format!("{}:{}", username, password.expose_secret());
+            let secret_header =
+                format!("{}:{}", username.expose_secret(), password.expose_secret());
             let secret_header = general_purpose::STANDARD.encode(secret_header);
 
             vec![
@@ -244,13 +800,24 @@ fn default_args(transport: &Transport) -> Result<Vec<Value>, Report<Error>> {
                 Value::format_secret("http.extraHeader={secret}", header),
             ]
         }
+        // Credentials minted by a command or computed for CodeCommit are resolved into one of the above before running git,
+        // so if they're seen here the command is only being displayed.
         _ => vec![],
     };
 
+    // Git for Windows can't write files whose paths are longer than `MAX_PATH` unless long paths are enabled,
+    // which fails clones of deeply nested repositories; other platforms have no such limit.
+    let long_path_args = if cfg!(windows) {
+        vec!["-c", "core.longpaths=true"]
+    } else {
+        vec![]
+    };
+
     // Credential helpers can override the header provided by http.extraHeader,
     // so we need to get rid of them by setting `credential-helper` to an empty value.
     vec!["-c", "credential.helper="]
         .into_iter()
+        .chain(long_path_args)
         .map(Value::new_plain)
         .chain(header_args.into_iter())
         .collect_vec()
@@ -296,13 +863,68 @@ fn env_vars(
         .wrap_ok()
 }
 
+/// The name of the environment variable through which the SSH key passphrase is provided
+/// to the askpass helper.
+const SSH_PASSPHRASE_VAR: &str = "BROKER_SSH_PASSPHRASE";
+
+/// Create an askpass helper which provides the SSH key passphrase to ssh,
+/// returning the helper along with the environment required for ssh to use it.
+///
+/// The helper itself doesn't contain the passphrase; it reads it from the environment,
+/// which is set as a secret on the git command so that it is redacted from any output.
+#[tracing::instrument(skip_all)]
+fn ssh_askpass(
+    passphrase: &ComparableSecretString,
+) -> Result<(TempPath, Vec<(String, Value)>), Report<Error>> {
+    #[cfg(target_family = "windows")]
+    let (suffix, script) = (".cmd", format!("@echo %{SSH_PASSPHRASE_VAR}%\r\n"));
+    #[cfg(not(target_family = "windows"))]
+    let (suffix, script) = (
+        ".sh",
+        format!("#!/bin/sh\nprintf '%s\\n' \"${SSH_PASSPHRASE_VAR}\"\n"),
+    );
+
+    let mut file = named_tempfile_with_suffix(suffix).context(Error::SshAskpassCreation)?;
+    file.write_all(script.as_bytes())
+        .context(Error::SshAskpassCreation)?;
+
+    // The file must be closed before it is run, otherwise running it may fail with "text file busy".
+    let path = file.into_temp_path();
+
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o700))
+            .context(Error::SshAskpassCreation)?;
+    }
+
+    let helper = path
+        .to_str()
+        .ok_or_else(|| report!(Error::PathNotValidUtf8(path.to_path_buf())))
+        .describe("Broker requires that the path to the SSH askpass helper is valid UTF-8 because it's passed to ssh in an environment variable")?
+        .to_string();
+
+    // ssh only uses the askpass helper without a terminal if `DISPLAY` is set;
+    // newer versions can be told to always use it with `SSH_ASKPASS_REQUIRE`.
+    let display = env::var("DISPLAY").unwrap_or_else(|_| String::from(":0"));
+    let s = String::from;
+    let env = vec![
+        (s("SSH_ASKPASS"), Value::new_plain(helper)),
+        (s("SSH_ASKPASS_REQUIRE"), Value::new_plain("force")),
+        (s("DISPLAY"), Value::new_plain(display)),
+        (s(SSH_PASSPHRASE_VAR), Value::new_secret(passphrase.clone())),
+    ];
+
+    Ok((path, env))
+}
+
 // git_ssh_command is passed into the GIT_SSH_COMMAND env variable. This makes git use this command
 // when it tries to make an SSH connection.
 // "-o IdentitiesOnly=yes" means "only use the identity file pointed to by the -i arg"
 // "-o StrictHostKeyChecking=no" avoids errors when the host is not in ssh's knownHosts file
 // "-F /dev/null" means "start with an empty ssh config"
 #[tracing::instrument]
-fn git_ssh_command(path: &Path) -> Result<String, Report<Error>> {
+pub(super) fn git_ssh_command(path: &Path) -> Result<String, Report<Error>> {
     path.to_str()
         .ok_or_else(|| report!(Error::PathNotValidUtf8(path.to_path_buf())))
         .describe("Broker requires that the path to the SSH key is valid UTF-8 because it's passed as an argument to the git executable")
@@ -311,7 +933,7 @@ fn git_ssh_command(path: &Path) -> Result<String, Report<Error>> {
         })
 }
 
-/// parse the output from `git ls-remote --quiet`
+/// parse a line of the output from `git ls-remote --quiet`
 /// The output will look something like this:
 ///
 /// git ls-remote --quiet
@@ -330,29 +952,22 @@ fn git_ssh_command(path: &Path) -> Result<String, Report<Error>> {
 /// ffb878b5eb456e7e1725606192765dcb6c7e78b8        refs/tags/v0.0.1^{}
 ///
 /// We only want the branches (which start with `refs/head/` and the tags (which start with `refs/tags`))
-/// Tags that end in ^{} should have the ^{} stripped from them. This will usually end up with a duplicate, so we
-/// de-dupe before returning
-#[tracing::instrument(skip_all)]
-fn parse_ls_remote(output: String) -> Vec<Reference> {
-    output
-        .split('\n')
-        .map(line_to_git_ref)
-        .filter(|r| r.is_some())
-        .flatten()
-        .collect()
-}
-
-fn line_to_git_ref(line: &str) -> Option<Reference> {
+/// Tags that end in ^{} should have the ^{} stripped from them, and are reported as peeled (the `bool`).
+/// Annotated tags then appear twice, so callers resolve them with [`peel_references`].
+fn line_to_git_ref(line: &str) -> Option<(Reference, bool)> {
     let mut parsed = line.split_whitespace();
     let commit = parsed.next()?;
     let commit = String::from(commit);
     let reference = parsed.next()?;
     if let Some(tag) = reference.strip_prefix("refs/tags/") {
-        let tag = tag.strip_suffix("^{}").unwrap_or(tag);
-        Some(Reference::new_tag(tag.to_string(), commit))
+        let (tag, peeled) = match tag.strip_suffix("^{}") {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        Some((Reference::new_tag(tag.to_string(), commit), peeled))
     } else {
         reference
             .strip_prefix("refs/heads/")
-            .map(|branch| Reference::new_branch(branch.to_string(), commit))
+            .map(|branch| (Reference::new_branch(branch.to_string(), commit), false))
     }
 }
@@ -1,6 +1,6 @@
 //! Powers integration with code hosts speaking the git protocol.
 
-use std::fmt::Display;
+use std::{borrow::Cow, fmt::Display, path::Path};
 
 use async_trait::async_trait;
 use derive_more::From;
@@ -9,10 +9,14 @@ use error_stack::{Report, ResultExt};
 use serde::{Deserialize, Serialize};
 use tempfile::TempDir;
 
-use crate::api::{
-    http,
-    remote::{RemoteProvider, RemoteProviderError},
-    ssh,
+use crate::{
+    api::{
+        http,
+        remote::{Contributors, Provider, RemoteProvider, RemoteProviderError, ScanMode},
+        ssh,
+    },
+    db,
+    ext::{result::DiscardResult, secrecy::ComparableSecretString},
 };
 
 use super::{super::Remote, repository};
@@ -32,6 +36,9 @@ pub enum Transport {
 
         /// Authentication to that host. This is not an Option<> because ssh without auth never works
         auth: ssh::Auth,
+
+        /// The passphrase for the SSH key, if the key is encrypted.
+        passphrase: Option<ComparableSecretString>,
     },
 
     /// Specifies that the remote code host is configured to use the HTTP protocol.
@@ -75,6 +82,15 @@ impl Transport {
         }
     }
 
+    /// replaces the endpoint of a transport, keeping its auth
+    pub fn set_endpoint(&mut self, remote: Remote) {
+        use Transport::*;
+        match self {
+            Ssh { endpoint, .. } => *endpoint = remote,
+            Http { endpoint, .. } => *endpoint = remote,
+        }
+    }
+
     /// returns the auth info for a transport
     pub fn auth(&self) -> Auth {
         use Transport::*;
@@ -83,6 +99,32 @@ impl Transport {
             Http { auth, .. } => Auth::Http(auth.clone()),
         }
     }
+
+    /// returns the passphrase for the SSH key used by the transport, if any
+    pub fn ssh_passphrase(&self) -> Option<&ComparableSecretString> {
+        match self {
+            Transport::Ssh { passphrase, .. } => passphrase.as_ref(),
+            Transport::Http { .. } => None,
+        }
+    }
+
+    /// returns the transport with any short-lived credentials freshly minted,
+    /// so that the credentials can be used directly by git
+    pub async fn with_fresh_credentials(&self) -> Result<Cow<'_, Self>, Report<http::Error>> {
+        match self {
+            Transport::Http {
+                endpoint,
+                auth: Some(auth @ (http::Auth::Command(_) | http::Auth::AwsCodeCommit(_))),
+            } => {
+                let auth = auth.resolve().await?;
+                Ok(Cow::Owned(Transport::new_http(
+                    endpoint.clone(),
+                    Some(auth),
+                )))
+            }
+            _ => Ok(Cow::Borrowed(self)),
+        }
+    }
 }
 
 #[async_trait]
@@ -114,4 +156,102 @@ impl RemoteProvider for Transport {
             .await
             .change_context(RemoteProviderError::RunCommand)
     }
+
+    async fn references_matching(
+        &self,
+        include: &(dyn Fn(&Self::Reference) -> bool + Send + Sync),
+    ) -> Result<Vec<Self::Reference>, Report<RemoteProviderError>> {
+        // Repositories may have millions of references, so they're filtered as they're listed.
+        let transport = self.to_owned();
+        repository::list_references_matching(&transport, include)
+            .await
+            .change_context(RemoteProviderError::RunCommand)
+    }
+}
+
+#[async_trait]
+impl Provider for Transport {
+    const NAME: &'static str = "git";
+    const NAMESPACE: db::Namespace = db::Namespace::Git;
+
+    fn endpoint(&self) -> &Remote {
+        Transport::endpoint(self)
+    }
+
+    fn repository(remote: &Remote) -> String {
+        super::canonical_remote(remote)
+    }
+
+    fn project(&self) -> String {
+        // The same repository is imported to the same project, however its remote is written.
+        super::canonical_remote(self.endpoint())
+    }
+
+    fn existing_projects(&self) -> Vec<String> {
+        super::project_names(self.endpoint())
+    }
+
+    async fn check_connection(&self) -> Result<(), Report<RemoteProviderError>> {
+        repository::ls_remote(self)
+            .await
+            .discard_ok()
+            .change_context(RemoteProviderError::RunCommand)
+    }
+
+    async fn clone_manifests(
+        &self,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        repository::clone_manifests(self, reference, ScanMode::MANIFESTS)
+            .await
+            .change_context(RemoteProviderError::RunCommand)
+    }
+
+    async fn contributors(
+        &self,
+        checkout: &Path,
+    ) -> Result<Option<Contributors>, Report<RemoteProviderError>> {
+        repository::contributors(checkout)
+            .await
+            .map(Some)
+            .change_context(RemoteProviderError::RunCommand)
+    }
+
+    async fn tree_hash(
+        &self,
+        checkout: &Path,
+    ) -> Result<Option<String>, Report<RemoteProviderError>> {
+        repository::tree_hash(checkout)
+            .await
+            .map(Some)
+            .change_context(RemoteProviderError::RunCommand)
+    }
+
+    async fn descends_from(
+        &self,
+        checkout: &Path,
+        previous: &[u8],
+    ) -> Result<Option<bool>, Report<RemoteProviderError>> {
+        let previous = String::from_utf8_lossy(previous);
+        repository::descends_from(checkout, &previous)
+            .await
+            .map(Some)
+            .change_context(RemoteProviderError::RunCommand)
+    }
+
+    async fn update_mirror(&self, mirror: &Path) -> Result<(), Report<RemoteProviderError>> {
+        repository::update_mirror(self, mirror)
+            .await
+            .change_context(RemoteProviderError::RunCommand)
+    }
+
+    async fn checkout_from_mirror(
+        &self,
+        mirror: &Path,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        repository::checkout_from_mirror(self, mirror, reference)
+            .await
+            .change_context(RemoteProviderError::RunCommand)
+    }
 }
@@ -0,0 +1,269 @@
+//! Powers integration with directories on the Broker host,
+//! for example a network share to which release artifacts are copied.
+//!
+//! Unlike a code host, a directory has no history of its own.
+//! Instead, each poll takes a [`Snapshot`] of the directory's manifest
+//! (the relative path, size, and modification time of every file inside it),
+//! and the directory is scanned whenever the manifest changes.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use derive_new::new;
+use error_stack::{Report, ResultExt};
+use getset::{CopyGetters, Getters};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+use walkdir::WalkDir;
+
+use crate::{
+    db,
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper},
+        io::spawn_blocking_wrap,
+        result::DiscardResult,
+        tempfile::tempdir,
+    },
+};
+
+use super::{Provider, ProviderReference, Remote, RemoteProvider, RemoteProviderError};
+
+/// The name of the only reference a directory has: its current snapshot.
+pub const SNAPSHOT: &str = "snapshot";
+
+/// How the revision reported to FOSSA is chosen for each snapshot of a directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum RevisionScheme {
+    /// The hash of the directory's manifest.
+    #[default]
+    Hash,
+
+    /// The time at which the most recently modified file in the directory was modified, in RFC 3339 format.
+    Mtime,
+}
+
+/// A directory on the Broker host.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, Deserialize, Serialize, new)]
+pub struct Directory {
+    /// The path to the directory, as written in the config file.
+    #[getset(get = "pub")]
+    endpoint: Remote,
+
+    /// The name of the project in FOSSA, if it differs from the path.
+    #[getset(get = "pub")]
+    project: Option<String>,
+
+    /// How the revision reported to FOSSA is chosen.
+    #[getset(get_copy = "pub")]
+    revision: RevisionScheme,
+}
+
+impl Display for Directory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.endpoint)
+    }
+}
+
+impl Directory {
+    /// The path to the directory.
+    pub fn path(&self) -> PathBuf {
+        PathBuf::from(self.endpoint.to_string())
+    }
+}
+
+/// The state of a directory at the time it was polled.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize, new)]
+pub struct Snapshot {
+    /// The hex encoded SHA-256 hash of the directory's manifest.
+    hash: String,
+
+    /// The revision reported to FOSSA, chosen according to the directory's [`RevisionScheme`].
+    revision: String,
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{SNAPSHOT}@{}", self.revision)
+    }
+}
+
+impl ProviderReference for Snapshot {
+    fn name(&self) -> &str {
+        SNAPSHOT
+    }
+
+    fn branch(&self) -> Option<&str> {
+        None
+    }
+
+    fn revision(&self) -> &str {
+        &self.revision
+    }
+
+    fn as_state(&self) -> &[u8] {
+        self.hash.as_bytes()
+    }
+
+    fn for_coordinate(&self) -> String {
+        format!("{SNAPSHOT}:{}", self.hash)
+    }
+
+    fn coordinate_prefixes(name: &str) -> Vec<String> {
+        if name == SNAPSHOT {
+            vec![format!("{SNAPSHOT}:")]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[async_trait]
+impl RemoteProvider for Directory {
+    type Reference = Snapshot;
+
+    async fn clone_reference(
+        &self,
+        _reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        // The directory may change while it's being scanned, so scan a copy of it.
+        // If it changed after it was polled, the copy is newer than the snapshot;
+        // the next poll then sees a different manifest and scans it again.
+        let path = self.path();
+        spawn_blocking_wrap(move || copy(&path))
+            .await
+            .change_context(RemoteProviderError::ReadLocation)
+            .describe_lazy(|| format!("copy directory '{}'", self.endpoint))
+    }
+
+    async fn references(&self) -> Result<Vec<Self::Reference>, Report<RemoteProviderError>> {
+        let path = self.path();
+        let revision = self.revision;
+        let snapshot = spawn_blocking_wrap(move || snapshot(&path, revision))
+            .await
+            .change_context(RemoteProviderError::ReadLocation)
+            .describe_lazy(|| format!("read directory '{}'", self.endpoint))
+            .help("ensure the directory exists and that Broker has permission to read it")?;
+        Ok(vec![snapshot])
+    }
+}
+
+#[async_trait]
+impl Provider for Directory {
+    const NAME: &'static str = "local";
+    const NAMESPACE: db::Namespace = db::Namespace::Local;
+
+    fn endpoint(&self) -> &Remote {
+        &self.endpoint
+    }
+
+    fn project(&self) -> String {
+        self.project
+            .clone()
+            .unwrap_or_else(|| self.endpoint.to_string())
+    }
+
+    async fn check_connection(&self) -> Result<(), Report<RemoteProviderError>> {
+        let path = self.path();
+        spawn_blocking_wrap(move || std::fs::read_dir(path))
+            .await
+            .discard_ok()
+            .change_context(RemoteProviderError::ReadLocation)
+            .describe_lazy(|| format!("read directory '{}'", self.endpoint))
+    }
+
+    /// Directories aren't mirrored; they're copied directly.
+    async fn update_mirror(&self, _mirror: &Path) -> Result<(), Report<RemoteProviderError>> {
+        Ok(())
+    }
+
+    /// Directories aren't mirrored, so this copies the directory instead.
+    async fn checkout_from_mirror(
+        &self,
+        _mirror: &Path,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        self.clone_reference(reference).await
+    }
+}
+
+/// Take a snapshot of the directory by hashing its manifest.
+///
+/// Files are visited in a consistent order, so the hash only changes when the files themselves do.
+fn snapshot(root: &Path, scheme: RevisionScheme) -> std::io::Result<Snapshot> {
+    let mut hasher = Sha256::new();
+    let mut latest = SystemTime::UNIX_EPOCH;
+    for entry in WalkDir::new(root).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let metadata = entry.metadata()?;
+        let modified = metadata.modified()?;
+        let since_epoch = modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update(format!("\0{}\0{}\n", metadata.len(), since_epoch.as_nanos()).as_bytes());
+        latest = latest.max(modified);
+    }
+
+    let hash = format!("{:x}", hasher.finalize());
+    let revision = match scheme {
+        RevisionScheme::Hash => hash.clone(),
+        RevisionScheme::Mtime => humantime::format_rfc3339_seconds(latest).to_string(),
+    };
+    Ok(Snapshot::new(hash, revision))
+}
+
+/// Copy the contents of the directory into a temporary directory.
+fn copy(root: &Path) -> std::io::Result<TempDir> {
+    let dest = tempdir()?;
+    for entry in WalkDir::new(root) {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let target = dest.path().join(relative);
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else if entry.file_type().is_file() {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_changes_with_contents() {
+        let tmp = tempfile::tempdir().expect("must create tempdir");
+        std::fs::create_dir(tmp.path().join("lib")).expect("must create subdir");
+        std::fs::write(tmp.path().join("lib").join("a.jar"), b"a").expect("must write file");
+
+        let first = snapshot(tmp.path(), RevisionScheme::Hash).expect("must snapshot");
+        let again = snapshot(tmp.path(), RevisionScheme::Hash).expect("must snapshot");
+        assert_eq!(first, again);
+        assert_eq!(first.revision(), first.hash);
+
+        std::fs::write(tmp.path().join("b.jar"), b"b").expect("must write file");
+        let changed = snapshot(tmp.path(), RevisionScheme::Hash).expect("must snapshot");
+        assert_ne!(first.as_state(), changed.as_state());
+
+        let copied = copy(tmp.path()).expect("must copy");
+        assert_eq!(
+            std::fs::read(copied.path().join("lib").join("a.jar")).expect("must read copy"),
+            b"a"
+        );
+        assert!(copied.path().join("b.jar").exists());
+    }
+}
@@ -0,0 +1,416 @@
+//! Powers integration with Perforce Helix Core servers, using the `p4` CLI.
+//!
+//! A stream depot plays the part of a git repository: its streams are treated like branches,
+//! and labels containing files in the depot are treated like tags.
+//!
+//! To scan a reference, Broker creates a temporary client workspace rooted in a temporary directory,
+//! syncs the stream at its head changelist (or the files in the label) into it, and then deletes the client.
+
+use std::{fmt::Display, path::Path};
+
+use async_trait::async_trait;
+use derive_new::new;
+use error_stack::{report, Report};
+use getset::Getters;
+use indoc::formatdoc;
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    bandwidth, db,
+    ext::{
+        command::{Command, CommandDescriber, Output, OutputProvider, Value},
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        result::{DiscardResult, WrapErr},
+        secrecy::ComparableSecretString,
+        tempfile::tempdir,
+    },
+};
+
+use super::{Provider, ProviderReference, Remote, RemoteProvider, RemoteProviderError};
+
+/// A stream depot on a Perforce server.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, Deserialize, Serialize, new)]
+pub struct Depot {
+    /// The server and depot, like `ssl:perforce.example.com:1666//game`.
+    #[getset(get = "pub")]
+    endpoint: Remote,
+
+    /// The address of the server, as it would be set in `P4PORT`.
+    #[getset(get = "pub")]
+    port: String,
+
+    /// The depot, like `//game`.
+    #[getset(get = "pub")]
+    depot: String,
+
+    /// The user Broker authenticates as.
+    #[getset(get = "pub")]
+    user: String,
+
+    /// The login ticket for the user, if the server requires authentication.
+    #[getset(get = "pub")]
+    ticket: Option<ComparableSecretString>,
+
+    /// The character set of the server, as it would be set in `P4CHARSET`, for unicode servers.
+    #[getset(get = "pub")]
+    charset: Option<String>,
+
+    /// The name of the project in FOSSA, if it differs from the endpoint.
+    #[getset(get = "pub")]
+    project: Option<String>,
+}
+
+impl Display for Depot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.endpoint)
+    }
+}
+
+/// A specific point in time in a depot.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Reference {
+    /// A stream, at its most recently submitted changelist.
+    Stream {
+        /// The stream, like `//game/main`.
+        name: String,
+
+        /// The most recently submitted changelist in the stream.
+        change: String,
+    },
+
+    /// A label.
+    Label {
+        /// The name of the label.
+        name: String,
+
+        /// When the label was last updated, as reported by the server.
+        update: String,
+    },
+}
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reference::Stream { name, change } => write!(f, "stream::{name}@{change}"),
+            Reference::Label { name, .. } => write!(f, "label::{name}"),
+        }
+    }
+}
+
+impl ProviderReference for Reference {
+    fn name(&self) -> &str {
+        match self {
+            Reference::Stream { name, .. } | Reference::Label { name, .. } => name,
+        }
+    }
+
+    fn branch(&self) -> Option<&str> {
+        match self {
+            Reference::Stream { name, .. } => Some(name),
+            Reference::Label { .. } => None,
+        }
+    }
+
+    fn revision(&self) -> &str {
+        // Like git branches and tags, streams are identified by their changelist and labels by their name.
+        match self {
+            Reference::Stream { change, .. } => change,
+            Reference::Label { name, .. } => name,
+        }
+    }
+
+    fn as_state(&self) -> &[u8] {
+        match self {
+            Reference::Stream { change, .. } => change.as_bytes(),
+            Reference::Label { update, .. } => update.as_bytes(),
+        }
+    }
+
+    fn for_coordinate(&self) -> String {
+        match self {
+            Reference::Stream { name, change } => format!("stream:{name}@{change}"),
+            Reference::Label { name, update } => format!("label:{name}@{update}"),
+        }
+    }
+
+    fn coordinate_prefixes(name: &str) -> Vec<String> {
+        vec![format!("stream:{name}@"), format!("label:{name}@")]
+    }
+}
+
+impl Depot {
+    /// Construct a `p4` command configured to communicate with the server.
+    fn p4<I: IntoIterator<Item = Value>>(&self, args: I) -> Command {
+        let mut command = Command::new("p4")
+            .env_plain("P4PORT", &self.port)
+            .env_plain("P4USER", &self.user)
+            // Settings from the environment of the Broker host shouldn't affect which client is used.
+            .env_remove("P4CLIENT")
+            .env_remove("P4CONFIG")
+            .args(args);
+        if let Some(ticket) = &self.ticket {
+            // Perforce accepts a ticket in place of a password.
+            command = command.env_secret("P4PASSWD", ticket.clone());
+        }
+        if let Some(charset) = &self.charset {
+            command = command.env_plain("P4CHARSET", charset);
+        }
+        command
+    }
+
+    /// Run a `p4` command, failing if it exits unsuccessfully.
+    async fn run(&self, command: Command) -> Result<Output, Report<RemoteProviderError>> {
+        let output = command
+            .output_traced()
+            .await
+            .context(RemoteProviderError::RunCommand)
+            .describe_lazy(|| command.describe().to_string())
+            .help("ensure the 'p4' CLI is installed and on the PATH")?;
+
+        if !output.status().success() {
+            return report!(RemoteProviderError::RunCommand)
+                .wrap_err()
+                .describe_lazy(|| output.describe().to_string())
+                .help("ensure the server is reachable and that the ticket is valid for the configured user");
+        }
+        Ok(output)
+    }
+
+    /// Run a `p4` command with tagged output formatted by `format`, returning each nonempty line of output.
+    async fn lines(
+        &self,
+        format: &str,
+        args: &[&str],
+    ) -> Result<Vec<String>, Report<RemoteProviderError>> {
+        let args = ["-ztag", "-F", format]
+            .into_iter()
+            .chain(args.iter().copied())
+            .map(Value::new_plain);
+        let output = self.run(self.p4(args)).await?;
+        let lines = output
+            .stdout_string_lossy()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect();
+        Ok(lines)
+    }
+
+    /// The path matching every file in the depot.
+    fn depot_files(&self) -> String {
+        format!("{}/...", self.depot.trim_end_matches('/'))
+    }
+
+    /// List the streams in the depot, along with their most recently submitted changelist.
+    async fn streams(&self) -> Result<Vec<Reference>, Report<RemoteProviderError>> {
+        let mut references = Vec::new();
+        for name in self
+            .lines("%Stream%", &["streams", self.depot_files().as_str()])
+            .await?
+        {
+            let files = format!("{name}/...");
+            let change = self
+                .lines(
+                    "%change%",
+                    &["changes", "-m1", "-s", "submitted", files.as_str()],
+                )
+                .await?;
+
+            // Streams without any submitted changes have nothing to scan.
+            if let Some(change) = change.into_iter().next() {
+                references.push(Reference::Stream { name, change });
+            }
+        }
+        Ok(references)
+    }
+
+    /// List the labels which contain files in the depot.
+    async fn labels(&self) -> Result<Vec<Reference>, Report<RemoteProviderError>> {
+        let lines = self
+            .lines("%label% %Update%", &["labels", self.depot_files().as_str()])
+            .await?;
+        let labels = lines
+            .into_iter()
+            .filter_map(|line| {
+                let (name, update) = line.rsplit_once(' ')?;
+                Some(Reference::Label {
+                    name: name.to_string(),
+                    update: update.to_string(),
+                })
+            })
+            .collect();
+        Ok(labels)
+    }
+
+    /// The spec for a temporary client rooted at `root` which can sync the reference.
+    fn client_spec(&self, client: &str, root: &str, reference: &Reference) -> String {
+        let options = "allwrite clobber nocompress unlocked nomodtime rmdir";
+        match reference {
+            Reference::Stream { name, .. } => formatdoc! {"
+                Client: {client}
+                Owner: {user}
+                Root: {root}
+                Options: {options}
+                SubmitOptions: revertunchanged
+                LineEnd: local
+                Stream: {name}
+                ",
+                user = self.user,
+            },
+            Reference::Label { .. } => formatdoc! {"
+                Client: {client}
+                Owner: {user}
+                Root: {root}
+                Options: {options}
+                SubmitOptions: revertunchanged
+                LineEnd: local
+                View:
+                \t{files} //{client}/...
+                ",
+                user = self.user,
+                files = self.depot_files(),
+            },
+        }
+    }
+
+    /// Sync the reference into a temporary client workspace rooted at `root`.
+    async fn sync(
+        &self,
+        root: &str,
+        reference: &Reference,
+    ) -> Result<(), Report<RemoteProviderError>> {
+        let client = format!("fossa-broker-{}", Uuid::new_v4().simple());
+        let spec = self.client_spec(&client, root, reference);
+        self.run(
+            self.p4([Value::new_plain("client"), Value::new_plain("-i")])
+                .stdin(Value::new_plain(spec)),
+        )
+        .await
+        .describe("create temporary client workspace")?;
+
+        let revision = match reference {
+            Reference::Stream { change, .. } => format!("//{client}/...@{change}"),
+            Reference::Label { name, .. } => format!("//{client}/...@{name}"),
+        };
+        let sync = ["-c", client.as_str(), "sync", "-q", revision.as_str()];
+        let synced = self
+            .run(self.p4(sync.map(Value::new_plain)))
+            .await
+            .describe_lazy(|| format!("sync '{reference}'"));
+
+        // The client only exists to sync this reference, so it's always cleaned up;
+        // the files it synced remain in the temporary directory.
+        let deleted = self
+            .run(self.p4(["client", "-d", client.as_str()].map(Value::new_plain)))
+            .await;
+        if let Err(err) = deleted {
+            warn!("failed to delete temporary client workspace '{client}': {err:#}");
+        }
+
+        synced.discard_ok()
+    }
+}
+
+#[async_trait]
+impl RemoteProvider for Depot {
+    type Reference = Reference;
+
+    async fn clone_reference(
+        &self,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        let dir = tempdir()
+            .context(RemoteProviderError::RunCommand)
+            .describe("create temporary directory for client workspace")?;
+        let root = dir.path().display().to_string();
+
+        // p4 can't be paced while it syncs, so the synced files are counted against the bandwidth limit afterwards.
+        let transfer = bandwidth::Transfer::begin().await;
+        self.sync(&root, reference).await?;
+        let used = transfer.usage(dir.path()).await;
+        transfer.finish(used);
+        Ok(dir)
+    }
+
+    async fn references(&self) -> Result<Vec<Self::Reference>, Report<RemoteProviderError>> {
+        let mut references = self.streams().await?;
+        references.extend(self.labels().await?);
+        Ok(references)
+    }
+}
+
+#[async_trait]
+impl Provider for Depot {
+    const NAME: &'static str = "perforce";
+    const NAMESPACE: db::Namespace = db::Namespace::Perforce;
+
+    fn endpoint(&self) -> &Remote {
+        &self.endpoint
+    }
+
+    fn project(&self) -> String {
+        self.project
+            .clone()
+            .unwrap_or_else(|| self.endpoint.to_string())
+    }
+
+    async fn check_connection(&self) -> Result<(), Report<RemoteProviderError>> {
+        self.lines("%Stream%", &["streams", "-m1", self.depot_files().as_str()])
+            .await
+            .discard_ok()
+    }
+
+    /// Depots aren't mirrored; each reference is synced into a new workspace.
+    async fn update_mirror(&self, _mirror: &Path) -> Result<(), Report<RemoteProviderError>> {
+        Ok(())
+    }
+
+    /// Depots aren't mirrored, so this syncs the reference instead.
+    async fn checkout_from_mirror(
+        &self,
+        _mirror: &Path,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        self.clone_reference(reference).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_spec_for_stream() {
+        let depot = Depot::new(
+            Remote::new(String::from("ssl:perforce:1666//game")),
+            String::from("ssl:perforce:1666"),
+            String::from("//game"),
+            String::from("broker"),
+            None,
+            None,
+            None,
+        );
+        let stream = Reference::Stream {
+            name: String::from("//game/main"),
+            change: String::from("1234"),
+        };
+        let spec = depot.client_spec("fossa-broker-abcd", "/tmp/workspace", &stream);
+        assert!(spec.contains("Client: fossa-broker-abcd\n"));
+        assert!(spec.contains("Root: /tmp/workspace\n"));
+        assert!(spec.contains("Stream: //game/main\n"));
+        assert!(!spec.contains("View:"));
+
+        let label = Reference::Label {
+            name: String::from("release-1.0"),
+            update: String::from("1690000000"),
+        };
+        let spec = depot.client_spec("fossa-broker-abcd", "/tmp/workspace", &label);
+        assert!(spec.contains("View:\n\t//game/... //fossa-broker-abcd/...\n"));
+        assert_eq!(label.revision(), "release-1.0");
+        assert_eq!(stream.revision(), "1234");
+    }
+}
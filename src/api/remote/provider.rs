@@ -0,0 +1,154 @@
+//! The interface each kind of code host implements so that Broker can poll and scan it.
+//!
+//! Providers are registered in [`super`] with `providers!`, which generates [`super::Protocol`]
+//! and [`super::Reference`] with a variant per provider and dispatches to the provider for each variant.
+//! Adding a provider is then a matter of implementing these traits and registering it;
+//! the rest of Broker works in terms of [`super::Protocol`] and [`super::Reference`]
+//! and doesn't need to know which providers exist.
+
+use std::{fmt::Display, path::Path};
+
+use async_trait::async_trait;
+use error_stack::Report;
+use tempfile::TempDir;
+
+use crate::db;
+
+use super::{Contributors, Remote, RemoteProvider, RemoteProviderError};
+
+/// A kind of code host, specialized with the configuration needed to communicate with it.
+#[async_trait]
+pub trait Provider: RemoteProvider + Display + Send + Sync
+where
+    Self::Reference: ProviderReference,
+{
+    /// The name of the provider, used to distinguish its protocols and references when they are displayed,
+    /// and to distinguish its references in database coordinates.
+    const NAME: &'static str;
+
+    /// The database namespace for the provider's coordinates.
+    const NAMESPACE: db::Namespace;
+
+    /// The remote location of the code host.
+    fn endpoint(&self) -> &Remote;
+
+    /// The representation of a remote used for the provider's database coordinates.
+    ///
+    /// Providers whose remotes can be written several ways normalize them here,
+    /// so that integrations for the same code written differently share state.
+    fn repository(remote: &Remote) -> String
+    where
+        Self: Sized,
+    {
+        remote.for_coordinate()
+    }
+
+    /// The name of the project in FOSSA.
+    fn project(&self) -> String {
+        self.endpoint().to_string()
+    }
+
+    /// The names under which a project for this remote may already exist in FOSSA, most preferred first.
+    fn existing_projects(&self) -> Vec<String> {
+        vec![self.project()]
+    }
+
+    /// Check that the code host can be reached with the configured authentication.
+    async fn check_connection(&self) -> Result<(), Report<RemoteProviderError>>;
+
+    /// The people who recently committed to the code checked out at the provided location.
+    ///
+    /// Providers without commit history report `None`.
+    async fn contributors(
+        &self,
+        _checkout: &Path,
+    ) -> Result<Option<Contributors>, Report<RemoteProviderError>> {
+        Ok(None)
+    }
+
+    /// The hash of the tree checked out at the provided location, which identifies its content.
+    ///
+    /// Identical trees have the same hash even if they're reached by different references or commits,
+    /// so the results of analyzing a tree are cached by its hash.
+    /// Providers which can't identify content this way report `None`.
+    async fn tree_hash(
+        &self,
+        _checkout: &Path,
+    ) -> Result<Option<String>, Report<RemoteProviderError>> {
+        Ok(None)
+    }
+
+    /// Whether the code checked out at the provided location descends from a previous state of the same reference.
+    ///
+    /// A reference which doesn't descend from its previous state was rewritten, for example by a force push.
+    /// Providers without history report `None`.
+    async fn descends_from(
+        &self,
+        _checkout: &Path,
+        _previous: &[u8],
+    ) -> Result<Option<bool>, Report<RemoteProviderError>> {
+        Ok(None)
+    }
+
+    /// Clone only the files listed in [`super::ScanMode::MANIFESTS`] for a reference into a temporary directory.
+    ///
+    /// Providers which can't obtain files selectively clone the whole reference.
+    async fn clone_manifests(
+        &self,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>> {
+        self.clone_reference(reference).await
+    }
+
+    /// Fetch the latest state of the remote into a persistent mirror at the provided location,
+    /// creating the mirror if it doesn't yet exist.
+    async fn update_mirror(&self, mirror: &Path) -> Result<(), Report<RemoteProviderError>>;
+
+    /// Check out a reference from the persistent mirror at the provided location into a temporary directory.
+    async fn checkout_from_mirror(
+        &self,
+        mirror: &Path,
+        reference: &Self::Reference,
+    ) -> Result<TempDir, Report<RemoteProviderError>>;
+}
+
+/// A specific point in time on a provider's remote.
+pub trait ProviderReference: Display + Clone + Send + Sync {
+    /// The name of the reference, like the name of a branch or tag.
+    fn name(&self) -> &str;
+
+    /// The branch this reference tracks, if it is a branch.
+    ///
+    /// References which aren't branches are imported according to the integration's tag settings.
+    fn branch(&self) -> Option<&str>;
+
+    /// The revision reported to FOSSA for the reference.
+    fn revision(&self) -> &str;
+
+    /// The commit at which the reference points, if references at the same commit always have the same code.
+    ///
+    /// References sharing a commit are scanned once per poll, and the results are uploaded for each of them.
+    fn commit(&self) -> Option<&str> {
+        None
+    }
+
+    /// A canonical state for the reference; when this changes the reference is scanned again.
+    fn as_state(&self) -> &[u8];
+
+    /// A representation of the reference suitable for use in a [`db::Coordinate`].
+    fn for_coordinate(&self) -> String;
+
+    /// The representation in a [`db::Coordinate`] and the state with which earlier versions of Broker recorded the reference,
+    /// if they differ from [`ProviderReference::for_coordinate`] and [`ProviderReference::as_state`].
+    ///
+    /// A reference recorded that way at the same state isn't scanned again just because the way it's recorded changed.
+    fn legacy_coordinate(&self) -> Option<(String, &[u8])> {
+        None
+    }
+
+    /// The prefixes of [`ProviderReference::for_coordinate`] for any reference with the provided name,
+    /// regardless of its state.
+    fn coordinate_prefixes(name: &str) -> Vec<String>
+    where
+        Self: Sized;
+}
@@ -0,0 +1,246 @@
+//! An append-only log of every externally visible action Broker takes.
+//!
+//! Unlike traces, which are intended for debugging and are rotated away,
+//! the audit log exists to answer "what did Broker import, and when?".
+//! Each action is written as a single JSON object on its own line (JSONL)
+//! to `audit/audit.jsonl` inside the debug root.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Serialize;
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+use tracing::warn;
+
+use crate::debug;
+
+/// The kinds of actions recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Broker polled an integration for references.
+    Poll,
+
+    /// Broker cloned a reference.
+    Clone,
+
+    /// Broker ran FOSSA CLI analysis on a reference.
+    Analyze,
+
+    /// Broker uploaded the results of a scan to FOSSA.
+    Upload,
+
+    /// Broker exported an SBOM of an uploaded scan from FOSSA.
+    Export,
+
+    /// Broker checked the results of a scan for secrets before uploading them.
+    SecretScan,
+}
+
+/// The outcome of an action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The action succeeded.
+    Success,
+
+    /// The action failed.
+    Failure,
+}
+
+/// An event to record in the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Event {
+    action: Action,
+    integration: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scan_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    locator: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build: Option<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    artifacts: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    findings: Vec<String>,
+}
+
+impl Event {
+    /// Create an event for an action taken on an integration.
+    pub fn new(action: Action, integration: impl Display) -> Self {
+        Self {
+            action,
+            integration: integration.to_string(),
+            reference: None,
+            scan_id: None,
+            locator: None,
+            build: None,
+            artifacts: Vec::new(),
+            target: None,
+            format: None,
+            findings: Vec::new(),
+        }
+    }
+
+    /// The reference on which the action was taken.
+    pub fn reference(mut self, reference: impl Display) -> Self {
+        self.reference = Some(reference.to_string());
+        self
+    }
+
+    /// The scan to which the action belongs.
+    pub fn scan_id(mut self, scan_id: impl Display) -> Self {
+        self.scan_id = Some(scan_id.to_string());
+        self
+    }
+
+    /// The locator of the project revision created by the action.
+    pub fn locator(mut self, locator: impl Display) -> Self {
+        self.locator = Some(locator.to_string());
+        self
+    }
+
+    /// The CI build of the reference, as reported by CI.
+    pub fn build(mut self, build: impl Display) -> Self {
+        self.build = Some(build.to_string());
+        self
+    }
+
+    /// The artifacts produced by the CI build of the reference, as reported by CI.
+    pub fn artifacts(mut self, artifacts: &[String]) -> Self {
+        self.artifacts = artifacts.to_vec();
+        self
+    }
+
+    /// The additional FOSSA endpoint to which the action was taken.
+    pub fn target(mut self, target: impl Display) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    /// The format of the document produced by the action.
+    pub fn format(mut self, format: impl Display) -> Self {
+        self.format = Some(format.to_string());
+        self
+    }
+
+    /// What the action found, such as the secrets found in a scan.
+    pub fn findings<T: Display>(mut self, findings: &[T]) -> Self {
+        self.findings = findings.iter().map(ToString::to_string).collect();
+        self
+    }
+}
+
+/// A line in the audit log.
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    event: &'a Event,
+    outcome: Outcome,
+    duration_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The audit log.
+///
+/// Writes are serialized so that concurrent workers never interleave lines.
+/// Failing to write to the audit log is logged but otherwise does not interrupt Broker.
+#[derive(Debug)]
+pub struct Log {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl Log {
+    /// Create an audit log stored in the provided debug root.
+    pub fn new(root: &debug::Root) -> Self {
+        Self {
+            path: root.as_path().join("audit").join("audit.jsonl"),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// The location of the audit log on disk.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record the outcome of an action which started at `started`.
+    ///
+    /// For failures, the top level error message is recorded.
+    pub async fn record<T, E: Display>(
+        &self,
+        event: Event,
+        started: Instant,
+        result: &Result<T, E>,
+    ) {
+        let (outcome, error) = match result {
+            Ok(_) => (Outcome::Success, None),
+            Err(err) => (Outcome::Failure, Some(err.to_string())),
+        };
+        self.write(&event, outcome, started.elapsed(), error).await
+    }
+
+    async fn write(
+        &self,
+        event: &Event,
+        outcome: Outcome,
+        duration: Duration,
+        error: Option<String>,
+    ) {
+        let record = Record {
+            timestamp: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            event,
+            outcome,
+            duration_ms: duration.as_millis(),
+            error,
+        };
+
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(err) => {
+                warn!(?event, %err, "Unable to encode audit log record");
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().await;
+        if let Err(err) = self.append(&line).await {
+            warn!(?event, %err, path = %self.path.display(), "Unable to write audit log record");
+        }
+    }
+
+    async fn append(&self, line: &[u8]) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(line).await?;
+        file.flush().await
+    }
+}
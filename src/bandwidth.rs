@@ -0,0 +1,255 @@
+//! Limits on the bandwidth Broker uses to clone code and download files.
+//!
+//! Dozens of clones running at once can saturate the network the Broker host is on.
+//! When a limit is configured, transfers share a budget of bytes per second:
+//! downloads Broker makes itself are paced as they're read,
+//! while transfers run by other programs (like `git`) can't be paced as they run,
+//! so instead they take turns, each waiting until the bytes transferred before it fit within the limit.
+//!
+//! Like the git backend, the limit is process wide.
+//! Integrations may set their own limit, which applies to their transfers instead; see [`with_limit`].
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use bytesize::ByteSize;
+use derive_new::new;
+use error_stack::Report;
+use getset::CopyGetters;
+use once_cell::sync::{Lazy, OnceCell};
+use tokio::sync::OwnedMutexGuard;
+
+use crate::{
+    disk,
+    ext::once_cell::{Conflict, InstallOnce},
+};
+
+/// Validated config values for bandwidth limiting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, CopyGetters, new)]
+#[getset(get_copy = "pub")]
+pub struct Config {
+    /// The most bytes per second transferred by all transfers together.
+    /// If not set, or set to zero, transfers aren't limited.
+    limit: Option<ByteSize>,
+}
+
+/// Shares a budget of bytes per second between transfers.
+///
+/// A transfer may overdraw the budget, in which case the transfers after it wait until it's repaid.
+#[derive(Debug)]
+struct Limiter {
+    /// Bytes added to the budget per second.
+    rate: f64,
+
+    /// The budget as of when it was last updated.
+    balance: Mutex<Balance>,
+
+    /// Held by a transfer which can't be paced while it runs, so that only one runs at a time.
+    turn: Arc<tokio::sync::Mutex<()>>,
+}
+
+#[derive(Debug)]
+struct Balance {
+    bytes: f64,
+    updated: Instant,
+}
+
+impl Limiter {
+    /// Create a limiter with a full budget as of `now`.
+    fn new(limit: ByteSize, now: Instant) -> Self {
+        let rate = limit.as_u64() as f64;
+        Self {
+            rate,
+            balance: Mutex::new(Balance {
+                bytes: rate,
+                updated: now,
+            }),
+            turn: Default::default(),
+        }
+    }
+
+    /// Withdraw bytes from the budget as of `now`, returning how long until the budget is no longer overdrawn.
+    fn withdraw(&self, bytes: u64, now: Instant) -> Duration {
+        let mut balance = self.balance.lock().unwrap_or_else(PoisonError::into_inner);
+        let elapsed = now.saturating_duration_since(balance.updated);
+
+        // At most a second of unused budget accumulates, so that being idle doesn't allow a burst over the limit.
+        balance.bytes = (balance.bytes + elapsed.as_secs_f64() * self.rate).min(self.rate);
+        balance.bytes -= bytes as f64;
+        balance.updated = balance.updated.max(now);
+
+        if balance.bytes >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-balance.bytes / self.rate)
+        }
+    }
+
+    /// Withdraw bytes from the budget, waiting until it's no longer overdrawn.
+    async fn consume(&self, bytes: u64) {
+        let wait = self.withdraw(bytes, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// The config installed with [`install`].
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+/// The limiter for the config installed with [`install`], if the limit is set.
+static GLOBAL: OnceCell<Option<Arc<Limiter>>> = OnceCell::new();
+
+/// The limiters for integrations which set their own limit, by the key provided to [`with_limit`].
+static INTEGRATIONS: Lazy<Mutex<HashMap<String, Option<Arc<Limiter>>>>> =
+    Lazy::new(Default::default);
+
+tokio::task_local! {
+    /// The limiter for the integration whose transfer is running in the current task, if set with [`with_limit`].
+    static CURRENT: Option<Arc<Limiter>>;
+}
+
+/// Limit every transfer run after this is called, other than those run [`with_limit`].
+///
+/// Transfers already running share the budget of the installed limit, so installing a different one is an error;
+/// installing the same one again keeps its budget.
+pub fn install(config: Config) -> Result<(), Report<Conflict>> {
+    CONFIG.install("bandwidth limit", config)?;
+    GLOBAL.get_or_init(|| limiter(config.limit()));
+    Ok(())
+}
+
+/// Run `work` with its own limit instead of the one installed with [`install`], if `limit` is set.
+///
+/// Calls with the same `key` share a budget, so integrations are identified by their remote.
+/// A limit of zero means the transfers in `work` aren't limited at all.
+pub async fn with_limit<F: Future>(key: &str, limit: Option<ByteSize>, work: F) -> F::Output {
+    let Some(limit) = limit else {
+        return work.await;
+    };
+    let limiter = INTEGRATIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(key.to_string())
+        .or_insert_with(|| limiter(Some(limit)))
+        .clone();
+    CURRENT.scope(limiter, work).await
+}
+
+/// Wait until `bytes` more fit within the current limit.
+///
+/// Downloads call this for each chunk they read, so that they're paced as they run.
+pub async fn consume(bytes: usize) {
+    if let Some(limiter) = current() {
+        limiter.consume(bytes as u64).await;
+    }
+}
+
+/// A transfer which can't be paced while it runs, like a clone by the `git` executable.
+///
+/// Only one of these runs at a time under each limit, and each waits to start until the bytes transferred
+/// before it fit within the limit; once it's finished, the bytes it transferred are withdrawn from the budget.
+#[derive(Debug)]
+pub struct Transfer {
+    limiter: Option<Arc<Limiter>>,
+    _turn: Option<OwnedMutexGuard<()>>,
+}
+
+impl Transfer {
+    /// Wait for the transfer's turn under the current limit.
+    pub async fn begin() -> Self {
+        let Some(limiter) = current() else {
+            return Self {
+                limiter: None,
+                _turn: None,
+            };
+        };
+        let turn = limiter.turn.clone().lock_owned().await;
+        limiter.consume(0).await;
+        Self {
+            limiter: Some(limiter),
+            _turn: Some(turn),
+        }
+    }
+
+    /// The size of the files at `path`, which approximates the bytes transferred into it.
+    ///
+    /// This walks the directory, so it isn't measured if the transfer isn't limited.
+    pub async fn usage(&self, path: &Path) -> u64 {
+        if self.limiter.is_none() {
+            return 0;
+        }
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || disk::usage(&path).as_u64())
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Withdraw the bytes the transfer used from the budget, and let the next transfer take its turn.
+    pub fn finish(self, bytes: u64) {
+        if let Some(limiter) = &self.limiter {
+            limiter.withdraw(bytes, Instant::now());
+        }
+    }
+}
+
+/// The limiter which applies to transfers in the current task.
+fn current() -> Option<Arc<Limiter>> {
+    CURRENT
+        .try_with(Clone::clone)
+        .unwrap_or_else(|_| GLOBAL.get().cloned().flatten())
+}
+
+fn limiter(limit: Option<ByteSize>) -> Option<Arc<Limiter>> {
+    limit
+        .filter(|limit| limit.as_u64() > 0)
+        .map(|limit| Arc::new(Limiter::new(limit, Instant::now())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn withdraws_from_budget() {
+        let start = Instant::now();
+        let limiter = Limiter::new(ByteSize::kb(1), start);
+
+        assert_eq!(limiter.withdraw(500, start), Duration::ZERO);
+        assert_eq!(limiter.withdraw(1500, start), Duration::from_secs(1));
+        assert_eq!(
+            limiter.withdraw(0, start + Duration::from_millis(500)),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            limiter.withdraw(0, start + Duration::from_secs(1)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn limits_accumulated_budget() {
+        let start = Instant::now();
+        let limiter = Limiter::new(ByteSize::kb(1), start);
+
+        let idle = start + Duration::from_secs(60);
+        assert_eq!(limiter.withdraw(3000, idle), Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn integration_limits_replace_global() {
+        let limited = with_limit("limited", Some(ByteSize::kb(1)), async {
+            current().map(|limiter| limiter.rate)
+        })
+        .await;
+        assert_eq!(limited, Some(1000.0));
+
+        let unlimited = with_limit("unlimited", Some(ByteSize::b(0)), async { current() }).await;
+        assert!(unlimited.is_none());
+    }
+}
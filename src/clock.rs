@@ -0,0 +1,165 @@
+//! The source of time for long running tasks.
+//!
+//! Workers read the current time and wait through a [`Clock`] instead of the system clock,
+//! so that tests can use a manual clock and fast-forward through poll intervals, poll windows,
+//! retention periods, and retry backoff instead of waiting for them in real time.
+//!
+//! Durations which are measured rather than waited for, like how long a scan took,
+//! still use [`std::time::Instant`]; and rate limits are tracked by `governor` against its own clock.
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use tokio::sync::watch;
+
+/// Tells the time, and waits for time to pass.
+///
+/// Clones share the same time, so advancing a manual clock advances every clone of it.
+#[derive(Debug, Clone, Default)]
+pub struct Clock {
+    /// The current time of a manual clock; `None` for the system clock.
+    manual: Option<Arc<watch::Sender<SystemTime>>>,
+}
+
+impl Clock {
+    /// A clock which follows the system clock.
+    pub fn system() -> Self {
+        Self::default()
+    }
+
+    /// A clock which starts at `start`, and only moves when [`Clock::advance`] is called.
+    pub fn manual(start: SystemTime) -> Self {
+        let (sender, _) = watch::channel(start);
+        Self {
+            manual: Some(Arc::new(sender)),
+        }
+    }
+
+    /// The current time.
+    pub fn now(&self) -> SystemTime {
+        match &self.manual {
+            Some(now) => *now.borrow(),
+            None => SystemTime::now(),
+        }
+    }
+
+    /// Wait until the duration has passed.
+    ///
+    /// For a manual clock, this waits until the clock is advanced to at least the duration from now.
+    pub async fn sleep(&self, duration: Duration) {
+        let Some(now) = &self.manual else {
+            return tokio::time::sleep(duration).await;
+        };
+
+        let until = *now.borrow() + duration;
+        let mut receiver = now.subscribe();
+        // The sender is held by `self`, so this only returns once the clock reaches `until`.
+        let _ = receiver.wait_for(|now| *now >= until).await;
+    }
+
+    /// Run the action, running it again after each delay from the strategy for as long as it fails.
+    ///
+    /// The delays are waited for on this clock. Once the strategy runs out of delays,
+    /// the error from the last attempt is returned.
+    pub async fn retry<T, E, F, Fut>(
+        &self,
+        strategy: impl IntoIterator<Item = Duration>,
+        mut action: F,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut delays = strategy.into_iter();
+        loop {
+            match action().await {
+                Ok(value) => return Ok(value),
+                Err(err) => match delays.next() {
+                    Some(delay) => self.sleep(delay).await,
+                    None => return Err(err),
+                },
+            }
+        }
+    }
+
+    /// Move a manual clock forward by the duration, waking anything whose sleep has elapsed.
+    ///
+    /// The system clock can't be moved, so this has no effect on it.
+    pub fn advance(&self, duration: Duration) {
+        if let Some(now) = &self.manual {
+            now.send_modify(|now| *now += duration);
+        }
+    }
+}
+
+impl PartialEq for Clock {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.manual, &other.manual) {
+            (None, None) => true,
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Clock {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn manual_clock_sleeps_until_advanced() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let clock = Clock::manual(start);
+        let handle = clock.clone();
+        assert_eq!(clock.now(), start);
+
+        let sleeper = tokio::spawn(async move { handle.sleep(Duration::from_secs(60)).await });
+        tokio::task::yield_now().await;
+
+        clock.advance(Duration::from_secs(59));
+        tokio::task::yield_now().await;
+        assert!(!sleeper.is_finished());
+
+        clock.advance(Duration::from_secs(1));
+        sleeper.await.expect("sleep must complete");
+        assert_eq!(clock.now(), start + Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn retry_waits_on_the_clock() {
+        let clock = Clock::manual(SystemTime::UNIX_EPOCH);
+        let handle = clock.clone();
+        let retry = tokio::spawn(async move {
+            let mut attempts = 0;
+            handle
+                .retry([Duration::from_secs(10); 2], || {
+                    attempts += 1;
+                    std::future::ready(Err::<(), _>(attempts))
+                })
+                .await
+        });
+        tokio::task::yield_now().await;
+        assert!(!retry.is_finished());
+
+        clock.advance(Duration::from_secs(10));
+        tokio::task::yield_now().await;
+        assert!(!retry.is_finished());
+
+        clock.advance(Duration::from_secs(10));
+        let attempts = retry.await.expect("retry must complete");
+        assert_eq!(attempts, Err(3));
+    }
+
+    #[test]
+    fn clones_share_time() {
+        let clock = Clock::manual(SystemTime::UNIX_EPOCH);
+        assert_eq!(clock, clock.clone());
+        assert_ne!(clock, Clock::manual(SystemTime::UNIX_EPOCH));
+        assert_ne!(clock, Clock::system());
+    }
+}
@@ -1,5 +1,18 @@
 //! Implementations for the subcommands.
 
+pub mod backfill;
+pub mod config;
+pub mod db;
+pub mod debug;
+pub mod doctor;
 pub mod fix;
 pub mod init;
+pub mod monitor;
+pub mod pause;
+pub mod queue;
+pub mod report;
 pub mod run;
+pub mod scan;
+pub mod simulate;
+pub mod status;
+pub mod update;
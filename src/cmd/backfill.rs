@@ -0,0 +1,179 @@
+//! Implementation for the `backfill` subcommand.
+
+use std::cmp::Ordering;
+
+use error_stack::{report, Report, ResultExt};
+use glob::Pattern;
+use itertools::Itertools;
+use tracing::info;
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    api::remote::{Integration, Reference, RemoteProvider},
+    config::Config,
+    db::Database,
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper},
+        result::WrapErr,
+    },
+    AppContext,
+};
+
+/// Errors encountered backfilling an integration.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The requested integration isn't in the config file.
+    #[error("integration '{0}' is not configured")]
+    IntegrationNotFound(String),
+
+    /// Listing the references of the integration failed.
+    #[error("list references")]
+    ListReferences,
+
+    /// Reading which references were already scanned failed.
+    #[error("read scanned references")]
+    ReadState,
+
+    /// Scanning the selected tags failed.
+    #[error("scan tags")]
+    Scan,
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::IntegrationNotFound(..) => ErrorCode::new(3301),
+            Self::ListReferences => ErrorCode::new(3302),
+            Self::ReadState => ErrorCode::new(3303),
+            Self::Scan => ErrorCode::new(3304),
+        }
+    }
+}
+
+/// Scan the most recent `max` tags of the integration whose names match the pattern, then return.
+///
+/// Unlike polling, this considers every tag the integration currently has,
+/// so that the history of releases made before the integration was configured can be imported.
+/// Tags are ordered by the version in their names, so `v1.10.0` is more recent than `v1.9.0`;
+/// tags which were already scanned at their current state are skipped.
+#[tracing::instrument(skip(ctx, config, db))]
+pub async fn main<D: Database>(
+    ctx: &AppContext,
+    config: Config,
+    db: D,
+    integration: &str,
+    tags_matching: Option<&Pattern>,
+    max: usize,
+) -> Result<(), Report<Error>> {
+    let integration = select(&config, integration)?;
+    let references = integration
+        .references()
+        .await
+        .change_context(Error::ListReferences)
+        .describe_lazy(|| format!("list references for '{integration}'"))?;
+
+    let tags = select_tags(references, tags_matching, max);
+    let total = tags.len();
+
+    let coordinates = tags
+        .iter()
+        .map(|tag| tag.as_coordinate(integration.remote()))
+        .collect_vec();
+    let states = db
+        .states_for(&coordinates)
+        .await
+        .change_context(Error::ReadState)
+        .describe_lazy(|| format!("read scanned tags for '{integration}'"))?;
+    let tags = tags
+        .into_iter()
+        .zip(states)
+        .filter(|(tag, state)| state.as_deref() != Some(tag.as_state()))
+        .map(|(tag, _)| tag)
+        .collect_vec();
+
+    info!(
+        "Backfilling {} of {total} selected tag(s) for '{integration}'; the rest were already scanned",
+        tags.len()
+    );
+    if tags.is_empty() {
+        println!("Nothing to backfill for '{integration}'.");
+        return Ok(());
+    }
+
+    crate::cmd::run::scan_references_once(ctx, config, db, &integration, tags)
+        .await
+        .change_context(Error::Scan)
+}
+
+/// Find the integration with the provided remote.
+fn select(config: &Config, integration: &str) -> Result<Integration, Report<Error>> {
+    let integrations = config.integrations();
+    match integrations
+        .iter()
+        .find(|candidate| candidate.remote().to_string() == integration)
+    {
+        Some(selected) => Ok(selected.clone()),
+        None => {
+            let configured = integrations
+                .iter()
+                .map(|integration| format!("'{}'", integration.remote()))
+                .join(", ");
+            report!(Error::IntegrationNotFound(integration.to_string()))
+                .wrap_err()
+                .help("provide the remote of the integration exactly as it is written in the config file")
+                .describe_lazy(|| format!("configured integrations: {configured}"))
+        }
+    }
+}
+
+/// Select the most recent `max` tags matching the pattern, most recent first.
+fn select_tags(
+    references: Vec<Reference>,
+    pattern: Option<&Pattern>,
+    max: usize,
+) -> Vec<Reference> {
+    references
+        .into_iter()
+        .filter(|reference| reference.branch().is_none())
+        .filter(|tag| pattern.map_or(true, |pattern| pattern.matches(tag.name())))
+        .sorted_by(|a, b| version_order(b.name(), a.name()))
+        .take(max)
+        .collect()
+}
+
+/// Compare names so that runs of digits are ordered numerically, so `v1.10.0` sorts after `v1.9.0`.
+fn version_order(a: &str, b: &str) -> Ordering {
+    let chunks = |name: &str| {
+        name.chars()
+            .group_by(char::is_ascii_digit)
+            .into_iter()
+            .map(|(_, chunk)| chunk.collect::<String>())
+            .collect_vec()
+    };
+
+    for (a, b) in chunks(a).iter().zip(chunks(b).iter()) {
+        let ordering = match (a.parse::<u128>(), b.parse::<u128>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            _ => a.cmp(b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_versions_numerically() {
+        let mut names = vec!["v1.9.0", "v1.10.0", "v1.2.0", "v2.0.0", "v1.10.0-rc1"];
+        names.sort_by(|a, b| version_order(a, b));
+        assert_eq!(
+            names,
+            vec!["v1.2.0", "v1.9.0", "v1.10.0", "v1.10.0-rc1", "v2.0.0"]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Implementation for the `config` subcommands.
+
+use std::path::Path;
+
+use error_stack::{Report, ResultExt};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    config::{self, Config},
+    db,
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        io,
+    },
+};
+
+/// Errors encountered showing the config.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The config file couldn't be read.
+    #[error("read config file")]
+    ReadFile,
+
+    /// The effective config couldn't be rendered.
+    #[error("render effective config as {0}")]
+    Render(Format),
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::ReadFile => ErrorCode::new(3601),
+            Self::Render(..) => ErrorCode::new(3602),
+        }
+    }
+}
+
+/// The format in which the effective config is printed.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, clap::ValueEnum, strum::Display,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum Format {
+    /// Rendered as YAML, like the config file.
+    #[default]
+    Yaml,
+
+    /// Rendered as JSON.
+    Json,
+}
+
+/// Print the config file at `path` exactly as it is written.
+///
+/// The config has already been validated by the time this is called,
+/// so this only shows config files which Broker is able to load.
+#[tracing::instrument]
+pub async fn show(path: &Path) -> Result<(), Report<Error>> {
+    let content = io::read_to_string(path)
+        .await
+        .change_context(Error::ReadFile)
+        .describe_lazy(|| format!("read config file at '{}'", path.display()))
+        .help("ensure you have access to the file and that it exists")?;
+    print!("{content}");
+    Ok(())
+}
+
+/// Print the effective config: the config as Broker resolved it,
+/// with defaults applied, groups resolved, inferred watched branches included, and secrets redacted.
+#[tracing::instrument(skip(config))]
+pub fn show_effective(config: &Config, format: Format) -> Result<(), Report<Error>> {
+    let effective = config.effective();
+    let rendered = match format {
+        Format::Yaml => serde_yaml::to_string(&effective).context(Error::Render(format))?,
+        Format::Json => serde_json::to_string_pretty(&effective)
+            .map(|rendered| format!("{rendered}\n"))
+            .context(Error::Render(format))?,
+    };
+    print!("{rendered}");
+    Ok(())
+}
+
+/// Check the config for settings which are valid, but unused or ineffective, and print them.
+///
+/// The config has already been validated by the time this is called, so this only reports lints.
+/// The database is opened read only, so this can be run while `broker run` is using it;
+/// if it can't be opened, lints which depend on the scans Broker has observed are skipped.
+#[tracing::instrument(skip(config))]
+pub async fn validate(config: &Config, database: &Path) {
+    let db = match db::open_sqlite_read_only(database).await {
+        Ok(db) => Some(db),
+        Err(err) => {
+            debug!("Unable to open database, skipping lints based on scan history: {err:#}");
+            None
+        }
+    };
+
+    let lints = config::lint(config, db.as_ref()).await;
+    if lints.is_empty() {
+        println!("The config file is valid.");
+        return;
+    }
+
+    println!(
+        "The config file is valid, but has {} setting(s) which are likely unintended:",
+        lints.len()
+    );
+    for lint in lints {
+        println!("\n- {lint}\n  help: {}", lint.help());
+    }
+}
@@ -0,0 +1,225 @@
+//! Implementation for the `db` subcommands.
+
+use std::path::Path;
+
+use bytesize::ByteSize;
+use error_stack::{report, Report, ResultExt};
+use itertools::Itertools;
+use tracing::info;
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    api::remote::{Integrations, Reference, Remote},
+    config::Config,
+    db::{self, Database},
+    doc::crate_version,
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper},
+        result::WrapErr,
+    },
+};
+
+/// Errors encountered managing the database.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The requested integration isn't in the config file.
+    #[error("integration '{0}' is not configured")]
+    IntegrationNotFound(String),
+
+    /// Interacting with the database failed.
+    #[error("interact with the database")]
+    Interact,
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::IntegrationNotFound(..) => ErrorCode::new(3701),
+            Self::Interact => ErrorCode::new(3702),
+        }
+    }
+}
+
+/// Move state stored under the remote of each integration as it was written in the config file,
+/// and under each of the integration's aliases, to the integration's canonical repository name.
+///
+/// Older versions of Broker stored state for git integrations under the remote exactly as written,
+/// so the same repository configured with different remotes (for example, via ssh and via https)
+/// was scanned and imported once per remote.
+/// Moving is a no-op once done, so this is safe to run on every start.
+#[tracing::instrument(skip_all)]
+pub async fn canonicalize_repositories<D: Database>(
+    db: &D,
+    integrations: &Integrations,
+) -> Result<(), Report<Error>> {
+    for integration in integrations.iter() {
+        let repository = integration.repository();
+        let previous = std::iter::once(integration.remote())
+            .chain(integration.aliases())
+            .map(Remote::for_coordinate)
+            .chain(integration.alias_repositories())
+            .unique()
+            .filter(|previous| previous != &repository);
+
+        for previous in previous {
+            let moved =
+                move_repository(db, &integration.namespace(), &previous, &repository).await?;
+            if moved > 0 {
+                info!(%moved, "Moved stored state for '{previous}' to '{repository}'");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Move the stored state for a remote to the integration now configured with a new remote,
+/// for example after the repository was renamed, so that its references aren't scanned again.
+///
+/// To have this happen automatically, add the old remote to the integration's `aliases` instead.
+#[tracing::instrument(skip(config, db))]
+pub async fn migrate_remote<D: Database>(
+    config: &Config,
+    db: &D,
+    old: &str,
+    new: &str,
+) -> Result<(), Report<Error>> {
+    let Some(found) = config
+        .integrations()
+        .iter()
+        .find(|candidate| candidate.remote().to_string() == new)
+    else {
+        return report!(Error::IntegrationNotFound(new.to_string()))
+            .wrap_err()
+            .help("update the integration in the config file to the new remote first, then provide the new remote exactly as it is written there")
+            .describe_lazy(|| format!("configured integrations: {}", configured(config)));
+    };
+
+    canonicalize_repositories(db, config.integrations()).await?;
+    let old = Remote::new(old.to_string());
+    let repository = found.repository();
+    let previous = [old.for_coordinate(), found.protocol().repository_of(&old)]
+        .into_iter()
+        .unique()
+        .filter(|previous| previous != &repository);
+
+    let mut moved = 0;
+    for previous in previous {
+        moved += move_repository(db, &found.namespace(), &previous, &repository).await?;
+    }
+
+    info!(%moved, "Moved stored state for '{old}' to '{found}'");
+    if moved > 0 {
+        println!("Moved {moved} stored state(s) from '{old}' to '{new}'; its references won't be scanned again unless they change.");
+    } else {
+        println!("No stored state was found for '{old}', so nothing was moved.");
+    }
+    Ok(())
+}
+
+/// Move the stored state for one repository to another, returning how many states were moved.
+async fn move_repository<D: Database>(
+    db: &D,
+    namespace: &db::Namespace,
+    from: &str,
+    to: &str,
+) -> Result<u64, Report<Error>> {
+    db.rename_repository(namespace, from, to)
+        .await
+        .change_context(Error::Interact)
+        .describe_lazy(|| format!("move state for '{from}' to '{to}'"))
+}
+
+/// The remotes of the configured integrations, for listing in errors.
+fn configured(config: &Config) -> String {
+    config
+        .integrations()
+        .iter()
+        .map(|integration| format!("'{}'", integration.remote()))
+        .join(", ")
+}
+
+/// Clear the stored state for an integration (or a single reference of it),
+/// so that it is scanned again on the next poll.
+#[tracing::instrument(skip(config, db))]
+pub async fn reset<D: Database>(
+    config: &Config,
+    db: &D,
+    integration: &str,
+    reference: Option<&str>,
+) -> Result<(), Report<Error>> {
+    let Some(found) = config
+        .integrations()
+        .iter()
+        .find(|candidate| candidate.remote().to_string() == integration)
+    else {
+        return report!(Error::IntegrationNotFound(integration.to_string()))
+            .wrap_err()
+            .help(
+                "provide the remote of the integration exactly as it is written in the config file",
+            )
+            .describe_lazy(|| format!("configured integrations: {}", configured(config)));
+    };
+
+    canonicalize_repositories(db, config.integrations()).await?;
+    let repository = found.repository();
+    let deleted = match reference {
+        Some(name) => {
+            let mut deleted = 0;
+            for prefix in Reference::coordinate_prefixes(name) {
+                deleted += db
+                    .reset_states(&found.namespace(), &repository, Some(&prefix))
+                    .await
+                    .change_context(Error::Interact)?;
+            }
+            deleted
+        }
+        None => db
+            .reset_states(&found.namespace(), &repository, None)
+            .await
+            .change_context(Error::Interact)?,
+    };
+
+    // Otherwise the next poll may see the same references as last time and skip checking them.
+    db.delete_references_hash(&found.namespace(), &repository)
+        .await
+        .change_context(Error::Interact)?;
+
+    info!(%deleted, "Reset state for '{found}'");
+    match reference {
+        Some(name) => println!("Reset {deleted} stored state(s) for '{name}' in '{integration}'; it will be scanned on the next poll."),
+        None => println!("Reset {deleted} stored state(s) for '{integration}'; its references will be scanned on the next poll."),
+    }
+    Ok(())
+}
+
+/// Show the schema version, claimed Broker version, and size of the database.
+///
+/// The database is inspected without being migrated or claimed,
+/// so this works even if the database was last used by a newer version of Broker.
+#[tracing::instrument]
+pub async fn info(location: &Path) -> Result<(), Report<Error>> {
+    let info = db::inspect_sqlite(location)
+        .await
+        .change_context(Error::Interact)?;
+
+    let current = crate_version();
+    let schema_version = info
+        .schema_version()
+        .map(|version| version.to_string())
+        .unwrap_or_else(|| String::from("none"));
+    let (broker_version, compatibility) = match info.broker_version() {
+        Some(claimed) if claimed > current => (
+            claimed.to_string(),
+            format!("no: upgrade to Broker {claimed} or later to use this database"),
+        ),
+        Some(claimed) => (claimed.to_string(), String::from("yes")),
+        None => (String::from("none"), String::from("yes")),
+    };
+
+    println!("Database:       {}", location.display());
+    println!("Schema version: {schema_version}");
+    println!("Claimed by:     Broker {broker_version}");
+    println!("Size:           {}", ByteSize(info.size()));
+    println!("Compatible:     {compatibility} (this is Broker {current})");
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//! Implementation for the `debug` subcommands, which help diagnose problems with integrations.
+
+use std::path::{Path, PathBuf};
+
+use error_stack::{report, Report, ResultExt};
+use itertools::Itertools;
+use tracing::info;
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    api::remote::{
+        git::{self, Backend},
+        Integration, Reference, RemoteProvider,
+    },
+    config::Config,
+    ext::{
+        command,
+        error_stack::{DescribeContext, ErrorHelper},
+        io,
+        result::WrapErr,
+    },
+};
+
+/// Errors encountered debugging integrations.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The requested integration isn't in the config file.
+    #[error("integration '{0}' is not configured")]
+    IntegrationNotFound(String),
+
+    /// Listing the references of the integration failed.
+    #[error("list references")]
+    ListReferences,
+
+    /// The integration has no reference with the requested name.
+    #[error("reference '{0}' not found")]
+    ReferenceNotFound(String),
+
+    /// No reference was requested, and the integration doesn't scan any references.
+    #[error("no references to clone")]
+    NoReferences,
+
+    /// Cloning the reference failed.
+    #[error("clone reference")]
+    Clone,
+
+    /// The clone couldn't be kept at the requested destination.
+    #[error("keep clone at '{}'", .0.display())]
+    KeepClone(PathBuf),
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::IntegrationNotFound(..) => ErrorCode::new(4701),
+            Self::ListReferences => ErrorCode::new(4702),
+            Self::ReferenceNotFound(..) => ErrorCode::new(4703),
+            Self::NoReferences => ErrorCode::new(4704),
+            Self::Clone => ErrorCode::new(4705),
+            Self::KeepClone(..) => ErrorCode::new(4706),
+        }
+    }
+}
+
+/// Clone a reference of the integration the same way `broker run` does, printing each git command as it runs,
+/// and keep the clone on disk so that it can be inspected.
+///
+/// If `reference` is `None`, the first reference the integration scans is cloned.
+/// If `dest` is `None`, the clone is kept in the temporary directory it was cloned into.
+#[tracing::instrument(skip(config))]
+pub async fn clone(
+    config: &Config,
+    integration: &str,
+    reference: Option<&str>,
+    dest: Option<&Path>,
+) -> Result<(), Report<Error>> {
+    let integration = select(config, integration).await?;
+    if let Some(dest) = dest {
+        if dest.exists() {
+            return report!(Error::KeepClone(dest.to_path_buf()))
+                .wrap_err()
+                .help("provide a destination that doesn't exist yet");
+        }
+    }
+
+    command::echo_commands();
+    if git::backend() == Backend::Native {
+        println!(
+            "Broker is configured to use its built in git client, which doesn't run commands;"
+        );
+        println!("only commands run when it falls back to the git executable are printed.");
+    }
+
+    let reference = select_reference(&integration, reference).await?;
+    println!("Cloning '{reference}' of '{integration}':");
+    let cloned = integration
+        .clone_reference(&reference)
+        .await
+        .change_context(Error::Clone)
+        .describe_lazy(|| format!("clone '{reference}' of '{integration}'"))?;
+
+    let path = cloned.into_path();
+    let path = match dest {
+        Some(dest) => {
+            io::rename(&path, dest)
+                .await
+                .change_context(Error::KeepClone(dest.to_path_buf()))
+                .help("provide a destination on the same file system as the temporary directory, or omit '--dest'")
+                .describe_lazy(|| format!("the clone is still at '{}'", path.display()))?;
+            dest.to_path_buf()
+        }
+        None => path,
+    };
+
+    info!("Kept clone of '{reference}' at '{}'", path.display());
+    println!("Cloned '{reference}' to '{}'.", path.display());
+    println!("Broker doesn't remove it; delete it once you're done inspecting it.");
+    Ok(())
+}
+
+/// Find the integration with the provided remote, including those listed in `remotes_from` files.
+async fn select(config: &Config, integration: &str) -> Result<Integration, Report<Error>> {
+    let integrations = crate::cmd::run::expanded_integrations(config).await;
+    match integrations
+        .iter()
+        .find(|candidate| candidate.remote().to_string() == integration)
+    {
+        Some(selected) => Ok(selected.clone()),
+        None => {
+            let configured = integrations
+                .iter()
+                .map(|integration| format!("'{}'", integration.remote()))
+                .join(", ");
+            report!(Error::IntegrationNotFound(integration.to_string()))
+                .wrap_err()
+                .help("provide the remote of the integration exactly as it is written in the config file")
+                .describe_lazy(|| format!("configured integrations: {configured}"))
+        }
+    }
+}
+
+/// Find the reference of the integration with the provided name,
+/// or the first reference the integration scans if no name is provided.
+async fn select_reference(
+    integration: &Integration,
+    name: Option<&str>,
+) -> Result<Reference, Report<Error>> {
+    let Some(name) = name else {
+        let references = integration
+            .scanned_references()
+            .await
+            .change_context(Error::ListReferences)
+            .describe_lazy(|| format!("list references for '{integration}'"))?;
+        return references
+            .into_iter()
+            .next()
+            .ok_or_else(|| report!(Error::NoReferences))
+            .help("provide the name of a branch or tag to clone with '--ref'")
+            .describe_lazy(|| format!("'{integration}' doesn't scan any of its references"));
+    };
+
+    let references = integration
+        .references()
+        .await
+        .change_context(Error::ListReferences)
+        .describe_lazy(|| format!("list references for '{integration}'"))?;
+    references
+        .into_iter()
+        .find(|reference| reference.name() == name)
+        .ok_or_else(|| report!(Error::ReferenceNotFound(name.to_string())))
+        .help("provide the name of a branch or tag of the repository, like 'main' or 'v1.0.0'")
+}
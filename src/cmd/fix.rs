@@ -1,26 +1,37 @@
 //! Implementation for the fix command
 
 use crate::{
+    api::fossa,
     api::remote::{
         git::{MAIN_BRANCH, MASTER_BRANCH},
         Reference, RemoteProvider, RemoteProviderError,
     },
-    debug::{self, bundler, Bundle, BundleExport},
+    db,
+    debug::{self, bundler, snapshot::Snapshot, Bundle, BundleExport, BundleUpload},
     ext::secrecy::REDACTION_LITERAL,
     fossa_cli::{self, DesiredVersion},
-    AppContext,
+    locale::Message,
+    msg, AppContext,
 };
 use colored::Colorize;
 use core::result::Result;
 use error_stack::{Report, ResultExt};
-use indoc::formatdoc;
-use std::time::Duration;
+use futures::{stream, StreamExt};
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tap::TapFallible;
 use tracing::warn;
 use uuid::Uuid;
 
+use crate::doc::code::{ErrorCode, HasErrorCode};
 use crate::{
     api::{
-        http,
+        http::{
+            self,
+            client::{Client, Purpose},
+        },
         remote::{
             git::{
                 repository,
@@ -31,7 +42,7 @@ use crate::{
         ssh,
     },
     config::Config,
-    ext::result::WrapErr,
+    ext::result::{DiscardResult, WrapErr},
 };
 
 /// Errors encountered when running the fix command.
@@ -83,6 +94,10 @@ pub enum Error {
     #[error("generate debug bundle")]
     GenerateDebugBundle,
 
+    /// Uploading the debug bundle.
+    #[error("upload debug bundle")]
+    UploadDebugBundle,
+
     /// Downloading cli
     #[error("download fossa cli")]
     DownloadFossaCli {
@@ -104,6 +119,22 @@ pub enum Error {
     },
 }
 
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::CheckIntegrationConnection { .. } => ErrorCode::new(3001),
+            Self::CheckIntegrationScan { .. } => ErrorCode::new(3002),
+            Self::CheckFossaGet { .. } => ErrorCode::new(3003),
+            Self::CreateFullFossaUrl { .. } => ErrorCode::new(3004),
+            Self::GenerateExampleCommand => ErrorCode::new(3005),
+            Self::GenerateDebugBundle => ErrorCode::new(3006),
+            Self::UploadDebugBundle => ErrorCode::new(3007),
+            Self::DownloadFossaCli { .. } => ErrorCode::new(3008),
+            Self::CloneReference { .. } => ErrorCode::new(3009),
+        }
+    }
+}
+
 #[cfg(target_family = "windows")]
 fn fossa_path_command() -> &'static str {
     "where.exe fossa"
@@ -132,28 +163,30 @@ impl Error {
                 format!("❌ {remote}\n\n{msg}")
             }
             Error::CheckFossaGet { msg } => {
-                let err = "Error checking connection to FOSSA:".red();
+                let err = msg!(Message::FixErrorFossaConnection).red();
                 format!("❌ {err} {msg}")
             }
             Error::CreateFullFossaUrl { remote, path } => {
-                format!("❌ Creating a full URL from your remote of '{remote}' and path = '{path}'")
+                let err = msg!(Message::FixErrorFossaUrl, remote = remote, path = path);
+                format!("❌ {err}")
             }
             Error::GenerateExampleCommand => {
-                "❌ Generating an example command for a remote".to_string()
+                format!("❌ {}", msg!(Message::FixErrorExampleCommand))
             }
             Error::CheckIntegrationScan { remote, msg, .. } => {
                 let remote = remote.to_string().red();
                 format!("❌ {remote}\n\n{msg}")
             }
             Error::CloneReference { msg, .. } => {
-                let err = "❌ Cloning Reference".to_string();
+                let err = format!("❌ {}", msg!(Message::FixErrorCloneReference));
                 format!("❌ {err}\n\n{msg}")
             }
             Error::DownloadFossaCli { msg, .. } => {
-                let err = "Error downloading FOSSA CLI".red();
+                let err = msg!(Message::FixErrorDownloadCli).red();
                 format!("❌ {err}\n\n{msg}")
             }
-            Error::GenerateDebugBundle => "❌ Generating the debug bundle".to_string(),
+            Error::GenerateDebugBundle => format!("❌ {}", msg!(Message::FixErrorGenerateBundle)),
+            Error::UploadDebugBundle => format!("❌ {}", msg!(Message::FixErrorUploadBundle)),
         }
     }
 
@@ -167,15 +200,11 @@ impl Error {
             Ok(exp) => exp,
         };
 
-        let msg = formatdoc!(
-            "
-            Broker encountered an error while trying to connect to your git remote at '{remote}'.
-
-            {explanation}
-
-            Full error message from git:
-
-            {err}"
+        let msg = msg!(
+            Message::FixConnectionError,
+            remote = remote,
+            explanation = explanation,
+            error = err,
         );
         Error::CheckIntegrationConnection {
             remote: remote.clone(),
@@ -187,7 +216,7 @@ impl Error {
     fn integration_connection_explanation(
         transport: &transport::Transport,
     ) -> Result<String, Error> {
-        let shared_instructions = "Broker was unable to connect to this repository. Ensure that the authentication info and the remote are set correctly in your config.yml file.";
+        let shared_instructions = msg!(Message::FixConnectionShared);
         let base64_command = r#"echo -n "<username>:<password>" | base64"#.green();
 
         // Generate an example command. The basic command is `git ls-remote`, but there are other arguments and env variables added
@@ -202,73 +231,88 @@ impl Error {
                 auth: ssh::Auth::KeyFile(_),
                 ..
             } => {
-                formatdoc!(
-                    "You are using SSH keyfile authentication for this remote. This connects to your repository by setting the `GIT_SSH_COMMAND` environment variable with the path to the ssh key that you provided in your config file. Ensure you can run the following command to verify the connection:
-
-                    {command}"
-                )
+                msg!(Message::FixConnectionSshKeyFile, command = command)
             }
             transport::Transport::Ssh {
                 auth: ssh::Auth::KeyValue(_),
                 ..
             } => {
-                formatdoc!(
-                    "You are using SSH key authentication for this remote. This method of authentication writes the SSH key that you provided in your config file to a temporary file, and then connects to your repository by setting the 'GIT_SSH_COMMAND' environment variable with the path to the temporary file. To debug this, write the ssh key to a file and ensure you can run the following command to verify the connection.
-
-                    Note that the path to the SSH key in this example command is a path to a temporary file that will no longer exist. You will need to edit this command to change the path to point at the file you just created.
-
-                    The path with the ssh key in it must have permissions of 0x660 on Linux and MacOS.
-
-                    {command}"
-                )
+                msg!(Message::FixConnectionSshKeyValue, command = command)
             }
             transport::Transport::Http {
                 auth: Some(http::Auth::Basic { .. }),
                 ..
             } => {
-                formatdoc!(
-                    r#"You are using HTTP basic authentication for this remote. This method of authentication encodes the username and password as a base64 string and then passes that to git using the "http.extraHeader" parameter. To debug this, ensure that the following commands work.
-
-                    You generate the base64 encoded username and password by joining them with a ":" and then base64 encoding them. If your username was "pat" and your password was "password123", then you would base64 encode "pat:password123". For example, you can use a command like this:
-
-                    {base64_command}
-
-                    Once you have the base64 encoded username and password, use them in a command like this, replacing {REDACTION_LITERAL} with your base64 encoded string:
-
-                    {command}"#
+                msg!(
+                    Message::FixConnectionHttpBasic,
+                    base64_command = base64_command,
+                    redacted = REDACTION_LITERAL,
+                    command = command,
                 )
             }
             transport::Transport::Http {
                 auth: Some(http::Auth::Header { .. }),
                 ..
             } => {
-                formatdoc!(
-                    r#"You are using HTTP header authentication for this remote. This method of authentication passes the header that you have provided in your config file to git using the "http.extraHeader" parameter. To debug this, ensure the following command works, after replacing {REDACTION_LITERAL} with the header from your config file:
-
-                    {command}
-
-                    You generate the header by making a string that looks like this:
-
-                    Authorization: Basic <base64 encoded username:password>
-
-                    If your username was "pat" and your password was "password123", then you would base64 encode "pat:password123". For example, you can use a command like this:
-
-                    {base64_command}
-
-                    The username you use depends on the git hosting platform you are authenticating to. For details on this, see the 'config.example.yml' file in your broker config directory. You can re-generate this file at any time by running 'broker init'.
-                    "#
+                msg!(
+                    Message::FixConnectionHttpHeader,
+                    redacted = REDACTION_LITERAL,
+                    command = command,
+                    base64_command = base64_command,
                 )
             }
-            transport::Transport::Http { auth: None, .. } => {
-                formatdoc!(
-                    r#"You are using http transport with no authentication for this integration. To debug this, ensure that the following command works:
-
-                    {command}"#
+            transport::Transport::Http {
+                auth: Some(http::Auth::Command(credential)),
+                ..
+            } => {
+                let credential_command =
+                    format!("{} {}", credential.command(), credential.args().join(" "));
+                let credential_command = credential_command.trim().green();
+                msg!(
+                    Message::FixConnectionHttpCommand,
+                    credential_command = credential_command,
+                    command = command,
+                )
+            }
+            transport::Transport::Http {
+                auth: Some(http::Auth::AwsCodeCommit(repository)),
+                ..
+            } => {
+                let profile = match repository.profile() {
+                    Some(profile) => {
+                        msg!(Message::FixConnectionCodeCommitProfile, profile = profile)
+                    }
+                    None => msg!(Message::FixConnectionCodeCommitDefaultCredentials),
+                };
+                let profile_arg = repository
+                    .profile()
+                    .as_ref()
+                    .map(|profile| format!(" --profile {profile}"))
+                    .unwrap_or_default();
+                let helper_command = format!(
+                    "git -c credential.helper='!aws{profile_arg} codecommit credential-helper $@' -c credential.UseHttpPath=true ls-remote {}",
+                    transport.endpoint()
                 )
+                .green();
+                msg!(
+                    Message::FixConnectionCodeCommit,
+                    profile = profile,
+                    helper_command = helper_command,
+                )
+            }
+            transport::Transport::Http { auth: None, .. } => {
+                msg!(Message::FixConnectionHttpNoAuth, command = command)
             }
         };
 
-        Ok(format!("{shared_instructions}\n\n{specific_instructions}"))
+        let passphrase_instructions = match transport.ssh_passphrase() {
+            Some(_) => format!("\n\n{}", msg!(Message::FixConnectionSshPassphrase)),
+            None => String::new(),
+        };
+
+        Ok(format!(
+            "{shared_instructions}\n\n{specific_instructions}{passphrase_instructions}"
+        ))
     }
 
     fn integration_scan_error(remote: &Remote, branch: &String) -> Self {
@@ -276,20 +320,13 @@ impl Error {
         let fossa_path_command = fossa_path_command().green();
         let git_command = format!("git clone -b {} {}", branch, remote).green();
 
-        let msg = formatdoc!(
-            "Broker encountered an error while scanning your git remote at '{remote}' on branch '{branch}'.
-
-            To view the error, you must first download the failing integration. You can download the integration by using the following command:
-
-            {git_command} 
-            
-            Next, use the following command to locate the path of the fossa-cli:
-
-            {fossa_path_command}
-
-            Once the download is complete, you can debug the issue by running the following command in the directory of your downloaded integration:
-             
-            {cli_command}"
+        let msg = msg!(
+            Message::FixScanError,
+            remote = remote,
+            branch = branch,
+            git_command = git_command,
+            fossa_path_command = fossa_path_command,
+            cli_command = cli_command,
         );
         Error::CheckIntegrationScan {
             remote: remote.clone(),
@@ -301,18 +338,11 @@ impl Error {
     fn download_cli_error(remote: &Remote, err: Report<fossa_cli::Error>) -> Self {
         let url_reference = "https://github.com/fossas/fossa-cli/#installation".green();
 
-        let msg = formatdoc!(
-            "Broker encountered an error while trying to download the Fossa CLI in order to initiate a scan on '{remote}'.
-
-            Follow the installation instructions provided in the following link:
-
-            {url_reference}
-
-            This will ensure that you have Fossa CLI correctly configured on your machine.
-            
-            Full error message from Fossa CLI:
-            
-            {err}"
+        let msg = msg!(
+            Message::FixDownloadCliError,
+            remote = remote,
+            url = url_reference,
+            error = err,
         );
         Error::DownloadFossaCli {
             msg,
@@ -321,12 +351,10 @@ impl Error {
     }
 
     fn clone_reference_error(reference: &Reference, err: Report<RemoteProviderError>) -> Self {
-        let msg = formatdoc!(
-            "Broker encountered an error while trying to clone reference: {reference}
-            
-            Full error message:
-
-            {err}"
+        let msg = msg!(
+            Message::FixCloneReferenceError,
+            reference = reference,
+            error = err,
         );
 
         Error::CloneReference {
@@ -347,7 +375,7 @@ impl Error {
             Some(reqwest::StatusCode::UNAUTHORIZED) => Error::CheckFossaGet {
                 msg: Self::fossa_get_explanation(
                     description,
-                    r#"Broker received an "Unauthorized" status response from FOSSA. This can mean that the fossa_integration_key configured in your config.yml file is not correct. You can obtain a FOSSA API key by going to Settings => Integrations => API in the FOSSA application."#,
+                    &msg!(Message::FixFossaUnauthorized),
                     url,
                     example_command,
                     err,
@@ -356,7 +384,7 @@ impl Error {
             Some(status) => Error::CheckFossaGet {
                 msg: Self::fossa_get_explanation(
                     description,
-                    &format!("Broker received a {status} status response from FOSSA."),
+                    &msg!(Message::FixFossaStatus, status = status),
                     url,
                     example_command,
                     err,
@@ -367,7 +395,7 @@ impl Error {
                     Error::CheckFossaGet {
                         msg: Self::fossa_get_explanation(
                             description,
-                            "Broker received a timeout error while attempting to connect to FOSSA. This can happen if Broker is unable to connect to FOSSA due to various reasons.",
+                            &msg!(Message::FixFossaTimeout),
                             url,
                             example_command,
                             err,
@@ -377,7 +405,7 @@ impl Error {
                     Error::CheckFossaGet {
                         msg: Self::fossa_get_explanation(
                             description,
-                            "An error occurred while attempting to connect to FOSSA.",
+                            &msg!(Message::FixFossaOther),
                             url,
                             example_command,
                             err,
@@ -397,20 +425,30 @@ impl Error {
     ) -> String {
         let description = description.red();
         let example_command = example_command.green();
-        formatdoc!(
-            "{description}
-
-            {specific_error_message}
-
-            The URL Broker attempted to connect to was '{url}'. Make sure you can make a request to that URL. For example, try this curl command:
-
-            {example_command}
-
-            Full error message: {err}"
+        msg!(
+            Message::FixFossaExplanation,
+            description = description,
+            reason = specific_error_message,
+            url = url,
+            command = example_command,
+            error = err,
         )
     }
 }
 
+/// Whether integrations are cloned and analyzed after their connections are checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanCheck {
+    /// Only connections to integrations are checked.
+    Disable,
+
+    /// One integration, the first whose connection check succeeds, is cloned and analyzed.
+    ///
+    /// Analysis problems are usually caused by the host rather than the repository,
+    /// so this catches most of them without cloning and analyzing every integration.
+    Representative,
+}
+
 /// A logger. This is used to print the output to stdout
 pub trait Logger {
     /// Log things
@@ -437,49 +475,117 @@ macro_rules! log {
 pub async fn main<L: Logger>(
     ctx: &AppContext,
     config: &Config,
+    database: &Path,
     logger: &L,
     export: debug::BundleExport,
+    upload: debug::BundleUpload,
+    scan_check: ScanCheck,
 ) -> Result<(), Report<Error>> {
-    let fossa_connection_errors = check_fossa_connection(logger, config).await;
-    let integration_errors = check_integrations(ctx, logger, config).await;
+    let fossa_connection_errors = check_fossa_connection(ctx, logger, config).await;
+    let integration_errors = check_integrations(ctx, logger, config, scan_check).await;
     let had_errors = !integration_errors.is_empty() || !fossa_connection_errors.is_empty();
 
     print_errors(
         logger,
-        "\nErrors found while checking integrations",
+        &format!("\n{}", msg!(Message::FixIntegrationErrors)),
         integration_errors,
     );
     print_errors(
         logger,
-        "\nErrors found while checking connection to FOSSA",
+        &format!("\n{}", msg!(Message::FixFossaErrors)),
         fossa_connection_errors,
     );
 
-    log!(logger, "\n{}\n", "Collecting debug bundle".bold().blue());
-    match export {
-        BundleExport::Disable if had_errors => log!(logger, "❌ Debug bundle collection disabled."),
-        BundleExport::Auto if had_errors => collect_bundle(config, logger)?,
-        BundleExport::Disable | BundleExport::Auto => log!(logger, "✅ Debug bundle not needed."),
-        BundleExport::Always => collect_bundle(config, logger)?,
-    }
+    log!(
+        logger,
+        "\n{}\n",
+        msg!(Message::FixCollectingBundle).bold().blue()
+    );
+    let bundle = match export {
+        BundleExport::Disable if had_errors => {
+            log!(logger, "❌ {}", msg!(Message::FixBundleDisabled));
+            None
+        }
+        BundleExport::Auto if had_errors => {
+            Some(collect_bundle(ctx, config, database, logger).await?)
+        }
+        BundleExport::Disable | BundleExport::Auto => {
+            log!(logger, "✅ {}", msg!(Message::FixBundleNotNeeded));
+            None
+        }
+        BundleExport::Always => Some(collect_bundle(ctx, config, database, logger).await?),
+    };
 
-    Ok(())
+    match (upload, bundle) {
+        (BundleUpload::Enable, Some(bundle)) => upload_bundle(ctx, config, logger, &bundle).await,
+        (BundleUpload::Enable, None) | (BundleUpload::Disable, _) => Ok(()),
+    }
 }
 
-fn collect_bundle<L: Logger>(config: &Config, logger: &L) -> Result<(), Report<Error>> {
+async fn collect_bundle<L: Logger>(
+    ctx: &AppContext,
+    config: &Config,
+    database: &Path,
+    logger: &L,
+) -> Result<Bundle, Report<Error>> {
+    // The database is opened read only, so that the snapshot can be collected while `broker run` is using it.
+    let db = db::open_sqlite_read_only(database)
+        .await
+        .tap_err(|err| warn!("Unable to open the database for the debug bundle: {err:#?}"))
+        .ok();
+    let snapshot = Snapshot::collect(
+        config.debug(),
+        config.integrations(),
+        db.as_ref(),
+        ctx.data_root(),
+    )
+    .await;
+
     let bundler = bundler::TarGz::new().change_context(Error::GenerateDebugBundle)?;
-    let bundle = Bundle::collect(config.debug(), bundler, "fossa.broker.debug.tar.gz")
-        .change_context(Error::GenerateDebugBundle)?;
+    let bundle = Bundle::collect(
+        config.debug(),
+        config.encryption().as_ref(),
+        bundler,
+        "fossa.broker.debug.tar.gz",
+        &snapshot,
+    )
+    .change_context(Error::GenerateDebugBundle)?;
 
     log!(
         logger,
-        "✅ Collected debug bundle at '{}'",
-        bundle.location().display()
+        "✅ {}",
+        msg!(
+            Message::FixBundleCollected,
+            location = bundle.location().display()
+        )
     );
+    log!(logger, "{}", msg!(Message::FixBundleInclude));
+
+    Ok(bundle)
+}
+
+async fn upload_bundle<L: Logger>(
+    ctx: &AppContext,
+    config: &Config,
+    logger: &L,
+    bundle: &Bundle,
+) -> Result<(), Report<Error>> {
     log!(
         logger,
-        "Please include this debug bundle in any request to FOSSA Support."
+        "\n{}\n",
+        msg!(Message::FixUploadingBundle).bold().blue()
     );
+    let client = ctx.http().get(Purpose::Fossa);
+    let reference = fossa::upload_debug_bundle(client, config.fossa_api(), bundle.location())
+        .await
+        .change_context(Error::UploadDebugBundle)?;
+
+    log!(
+        logger,
+        "✅ {}",
+        msg!(Message::FixBundleUploaded, reference = reference.green())
+    );
+    log!(logger, "{}", msg!(Message::FixBundleReferenceInclude));
 
     Ok(())
 }
@@ -496,48 +602,97 @@ fn print_errors<L: Logger>(logger: &L, msg: &str, errors: Vec<Error>) {
     }
 }
 
+/// How many integrations are checked at once.
+///
+/// Checking an integration clones and analyzes it, so this is kept small to avoid overwhelming the host.
+const INTEGRATION_CHECK_CONCURRENCY: usize = 4;
+
 /// Check that Broker can connect to the integrations
 /// This is currently done by running `git ls-remote <remote>` using the authentication
 /// info from the transport.
+///
+/// Integrations are checked concurrently, and each is shown as soon as its check completes;
+/// the errors are returned in the order the integrations are configured.
+///
+/// At most one integration is cloned and analyzed, so only one check finds or downloads FOSSA CLI.
 #[tracing::instrument(skip(config, logger))]
 async fn check_integrations<L: Logger>(
     ctx: &AppContext,
     logger: &L,
     config: &Config,
+    scan_check: ScanCheck,
 ) -> Vec<Error> {
-    let title = "\nDiagnosing connections to configured repositories\n"
-        .bold()
-        .blue()
-        .to_string();
+    let title = format!(
+        "\n{}\n",
+        msg!(Message::FixDiagnosingIntegrations).bold().blue()
+    );
     logger.log(title);
-    let integrations = config.integrations();
-    let mut errors = Vec::new();
-    for integration in integrations.iter() {
-        let remote = integration.remote();
-        if let Err(err) = check_integration_connection(integration).await {
-            log!(logger, "❌ {remote}");
-            errors.push(err);
-            continue;
-        }
 
-        match check_integration_scan(ctx, config, integration).await {
+    // Set once an integration has claimed the scan check.
+    let scan_claimed = AtomicBool::new(scan_check == ScanCheck::Disable);
+    let scan_claimed = &scan_claimed;
+    let checks = config
+        .integrations()
+        .iter()
+        .enumerate()
+        .map(|(index, integration)| async move {
+            let checked = check_integration(ctx, config, integration, scan_claimed).await;
+            (index, integration.remote(), checked)
+        });
+    let mut checks = stream::iter(checks).buffer_unordered(INTEGRATION_CHECK_CONCURRENCY);
+
+    let mut errors = Vec::new();
+    while let Some((index, remote, checked)) = checks.next().await {
+        match checked {
             Ok(_) => log!(logger, "✅ {remote}"),
             Err(err) => {
                 log!(logger, "❌ {remote}");
-                errors.push(err);
+                errors.push((index, err));
             }
         }
     }
-    errors
+    errors.sort_by_key(|(index, _)| *index);
+    errors.into_iter().map(|(_, err)| err).collect()
+}
+
+/// Check that Broker can connect to the integration, then that it can clone and analyze it
+/// if no other integration has claimed the scan check yet.
+async fn check_integration(
+    ctx: &AppContext,
+    config: &Config,
+    integration: &Integration,
+    scan_claimed: &AtomicBool,
+) -> Result<(), Error> {
+    check_integration_connection(integration).await?;
+    if scan_claimed.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    check_integration_scan(ctx, config, integration).await
 }
 
 #[tracing::instrument]
 async fn check_integration_connection(integration: &Integration) -> Result<(), Error> {
-    let Protocol::Git(transport) = integration.protocol();
-    repository::ls_remote(transport).await.or_else(|err| {
-        Error::integration_connection_error(integration.remote(), transport, err).wrap_err()
-    })?;
-    Ok(())
+    match integration.protocol() {
+        // Git connections fail in many ways, so they get detailed explanations.
+        Protocol::Git(transport) => repository::ls_remote(transport)
+            .await
+            .or_else(|err| {
+                Error::integration_connection_error(integration.remote(), transport, err).wrap_err()
+            })
+            .discard_ok(),
+        protocol => protocol.check_connection().await.or_else(|err| {
+            Error::CheckIntegrationConnection {
+                remote: integration.remote().clone(),
+                error: format!("{err:#}"),
+                msg: msg!(
+                    Message::FixConnectionOther,
+                    protocol = protocol,
+                    error = format!("{err:#}"),
+                ),
+            }
+            .wrap_err()
+        }),
+    }
 }
 
 #[tracing::instrument]
@@ -547,9 +702,15 @@ async fn check_integration_scan(
     integration: &Integration,
 ) -> Result<(), Error> {
     let remote = integration.remote();
-    let cli = fossa_cli::find_or_download(ctx, config.debug().location(), DesiredVersion::Latest)
-        .await
-        .or_else(|err| Error::download_cli_error(remote, err).wrap_err())?;
+    let cli = fossa_cli::find_or_download(
+        ctx,
+        config.fossa_cli(),
+        config.debug().location(),
+        DesiredVersion::Latest,
+    )
+    .await
+    .or_else(|err| Error::download_cli_error(remote, err).wrap_err())?
+    .with_encryption(config.encryption().clone());
 
     let references = integration.references().await.unwrap_or_default();
 
@@ -573,41 +734,50 @@ async fn check_integration_scan(
 
     // The error from analyze is overloaded with debug details
     // Discarding the error here and pointing users to the broker fix explanation for concise error message
-    cli.analyze(&scan_id, cloned_location.path())
-        .await
-        .or_else(|_err| {
-            Error::integration_scan_error(remote, &reference.name().to_string()).wrap_err()
-        })?;
+    cli.analyze(
+        &scan_id,
+        cloned_location.path(),
+        integration.cli_env(),
+        integration.cli_options(),
+    )
+    .await
+    .or_else(|_err| {
+        Error::integration_scan_error(remote, &reference.name().to_string()).wrap_err()
+    })?;
 
     Ok(())
 }
 
-#[tracing::instrument(skip(config, logger))]
-async fn check_fossa_connection<L: Logger>(logger: &L, config: &Config) -> Vec<Error> {
-    let title = "\nDiagnosing connection to FOSSA\n"
-        .bold()
-        .blue()
-        .to_string();
+#[tracing::instrument(skip(ctx, config, logger))]
+async fn check_fossa_connection<L: Logger>(
+    ctx: &AppContext,
+    logger: &L,
+    config: &Config,
+) -> Vec<Error> {
+    let title = format!("\n{}\n", msg!(Message::FixDiagnosingFossa).bold().blue());
     logger.log(title);
     let mut errors = Vec::new();
 
-    let get_with_no_auth = check_fossa_get_with_no_auth(config).await;
+    let client = ctx.http().get(Purpose::Diagnostics);
+    let get_with_no_auth = check_fossa_get_with_no_auth(client, config).await;
+    let check = msg!(Message::FixCheckFossaNoAuth);
     match get_with_no_auth {
         Ok(_) => {
-            logger.log("✅ check fossa API connection with no auth required");
+            log!(logger, "✅ {check}");
         }
         Err(err) => {
-            logger.log("❌ check fossa API connection with no auth required");
+            log!(logger, "❌ {check}");
             errors.push(err);
         }
     }
-    let get_with_auth = check_fossa_get_with_auth(config).await;
+    let get_with_auth = check_fossa_get_with_auth(client, config).await;
+    let check = msg!(Message::FixCheckFossaAuth);
     match get_with_auth {
         Ok(_) => {
-            logger.log("✅ check fossa API connection with auth required");
+            log!(logger, "✅ {check}");
         }
         Err(err) => {
-            logger.log("❌ check fossa API connection with auth required");
+            log!(logger, "❌ {check}");
             errors.push(err);
         }
     }
@@ -615,20 +785,10 @@ async fn check_fossa_connection<L: Logger>(logger: &L, config: &Config) -> Vec<E
     errors
 }
 
-const FOSSA_CONNECT_TIMEOUT_IN_SECONDS: u64 = 30;
-
-#[tracing::instrument(skip(config))]
-async fn check_fossa_get_with_no_auth(config: &Config) -> Result<(), Error> {
+#[tracing::instrument(skip(client, config))]
+async fn check_fossa_get_with_no_auth(client: &Client, config: &Config) -> Result<(), Error> {
     let endpoint = config.fossa_api().endpoint().as_ref();
     let path = "/api/cli/organization";
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .connect_timeout(Duration::from_secs(FOSSA_CONNECT_TIMEOUT_IN_SECONDS))
-        .build()
-        .map_err(|_| Error::CreateFullFossaUrl {
-            remote: endpoint.clone(),
-            path: path.to_string(),
-        })?;
     let url = endpoint
         .join("/health")
         .map_err(|_| Error::CreateFullFossaUrl {
@@ -636,44 +796,40 @@ async fn check_fossa_get_with_no_auth(config: &Config) -> Result<(), Error> {
             path: path.to_string(),
         })?;
     let health_check_response = client
-        .get(url.as_str())
-        .header(reqwest::header::ACCEPT, "application/json")
-        .send()
+        .send(
+            client
+                .get(url.as_str())
+                .header(reqwest::header::ACCEPT, "application/json"),
+        )
         .await;
 
     describe_fossa_request(
         health_check_response,
-        &format!("GET to fossa endpoint '{url}' with no authentication required"),
+        &msg!(Message::FixFossaGetNoAuth, url = url),
         url.as_ref(),
         &format!("curl {url}"),
     )
 }
 
-#[tracing::instrument(skip(config))]
-async fn check_fossa_get_with_auth(config: &Config) -> Result<(), Error> {
+#[tracing::instrument(skip(client, config))]
+async fn check_fossa_get_with_auth(client: &Client, config: &Config) -> Result<(), Error> {
     let endpoint = config.fossa_api().endpoint().as_ref();
     let path = "/api/cli/organization";
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .connect_timeout(Duration::from_secs(FOSSA_CONNECT_TIMEOUT_IN_SECONDS))
-        .build()
-        .map_err(|_| Error::CreateFullFossaUrl {
-            remote: endpoint.clone(),
-            path: path.to_string(),
-        })?;
     let url = endpoint.join(path).map_err(|_| Error::CreateFullFossaUrl {
         remote: endpoint.clone(),
         path: path.to_string(),
     })?;
     let org_endpoint_response = client
-        .get(url.as_str())
-        .header(reqwest::header::ACCEPT, "application/json")
-        .bearer_auth(config.fossa_api().key().expose_secret())
-        .send()
+        .send(
+            client
+                .get(url.as_str())
+                .header(reqwest::header::ACCEPT, "application/json")
+                .bearer_auth(config.fossa_api().key().expose_secret()),
+        )
         .await;
     describe_fossa_request(
         org_endpoint_response,
-        &format!("GET to fossa endpoint '{url}' with authentication required"),
+        &msg!(Message::FixFossaGetAuth, url = url),
         url.as_ref(),
         &format!(r#"curl -H "Authorization: Bearer <your fossa api key>" {url}"#),
     )
@@ -4,10 +4,11 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use crate::doc::code::{ErrorCode, HasErrorCode};
 use crate::ext::error_stack::{DescribeContext, ErrorHelper, IntoContext};
+use crate::locale::Message;
+use crate::msg;
 use error_stack::Result;
-use indoc::formatdoc;
-use indoc::indoc;
 
 /// Errors encountered during init.
 #[derive(Debug, thiserror::Error)]
@@ -28,48 +29,118 @@ pub enum Error {
         /// The data_root directory
         data_root: PathBuf,
     },
+
+    /// Writing an example service definition or CI workflow did not work
+    #[error("write example at '{}'", .0.display())]
+    WriteExample(PathBuf),
 }
 
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::ConfigFileExists => ErrorCode::new(3201),
+            Self::CreateDataRoot(..) => ErrorCode::new(3202),
+            Self::WriteConfigFile { .. } => ErrorCode::new(3203),
+            Self::WriteExample(..) => ErrorCode::new(3204),
+        }
+    }
+}
+
+/// The name of the directory in the data root to which examples are written.
+const EXAMPLES_DIR: &str = "examples";
+
+/// The example service definitions and CI workflows, by file name.
+///
+/// Each is a template with `{broker}`, `{config}`, and `{data_root}` placeholders.
+const EXAMPLES: &[(&str, &str)] = &[
+    ("broker.service", include_str!("init/broker.service")),
+    (
+        "broker-service.xml",
+        include_str!("init/broker-service.xml"),
+    ),
+    (
+        "docker-compose.yml",
+        include_str!("init/docker-compose.yml"),
+    ),
+    (
+        "github-actions.yml",
+        include_str!("init/github-actions.yml"),
+    ),
+];
+
 /// generate the config and db files in the default location
 #[tracing::instrument]
 pub fn main(data_root: &Path) -> Result<(), Error> {
     let default_already_exists = write_config(data_root, "config.yml", false)?;
     write_config(data_root, "config.example.yml", true)?;
+    let config = data_root.join("config.yml");
+    let example_config = data_root.join("config.example.yml");
     if default_already_exists {
-        let output = formatdoc! {r#"
-
-        `broker init` detected a previously existing config file at {config} and left it as is.
-
-        `broker init` did, however, create a new example config file for you at {example_config}.
-
-        This example config file contains a detailed explanation of everything you need to do to get broker up and running.
-
-        You can safely re-run `broker init` at any time to re-generate the "config.example.yml" file.
-        "#,
-            config = data_root.join("config.yml").display(),
-            example_config = data_root.join("config.example.yml").display(),
-        };
-        println!("{output}");
+        let output = msg!(
+            Message::InitConfigExists,
+            config = config.display(),
+            example_config = example_config.display(),
+        );
+        println!("\n{output}\n");
     } else {
-        let output = formatdoc! {r#"
-
-        `broker init` created an example config in {config}.
-
-        The config file contains a detailed explanation of everything you need to do to get broker up and running.
-
-        The next step is to open {config} and follow the instructions to configure broker.
+        let output = msg!(
+            Message::InitConfigCreated,
+            config = config.display(),
+            example_config = example_config.display(),
+        );
+        println!("\n{output}\n\n");
+    }
+    Ok(())
+}
 
-        We also wrote the same example file to {example_config} to serve as a reference for you in the future.
+/// write example service definitions and CI workflows to the examples directory in the data root,
+/// parameterized with the data root and its config file.
+///
+/// Like `config.example.yml`, the examples are regenerated every time this runs.
+#[tracing::instrument]
+pub fn examples(data_root: &Path) -> Result<PathBuf, Error> {
+    // The examples should run the executable the user ran, if it can be found.
+    let broker = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("broker"));
+    let examples_dir = data_root.join(EXAMPLES_DIR);
+    std::fs::create_dir_all(&examples_dir)
+        .context_lazy(|| Error::WriteExample(examples_dir.clone()))
+        .help_lazy(|| msg!(Message::InitWriteConfigHelp))?;
+
+    for (name, template) in EXAMPLES {
+        let path = examples_dir.join(name);
+        let example = render_example(name, template, &broker, data_root);
+        fs::write(&path, example)
+            .context_lazy(|| Error::WriteExample(path.clone()))
+            .help_lazy(|| msg!(Message::InitWriteConfigHelp))?;
+    }
 
-        You can safely re-run `broker init` at any time to re-generate the "config.example.yml" file.
+    let output = msg!(
+        Message::InitExamplesCreated,
+        examples = examples_dir.display()
+    );
+    println!("{output}\n");
+    Ok(examples_dir)
+}
 
-        "#,
-            config = data_root.join("config.yml").display(),
-            example_config = data_root.join("config.example.yml").display(),
-        };
-        println!("{output}");
-    }
-    Ok(())
+/// Fill in the placeholders of an example.
+///
+/// Paths are escaped in XML examples, since they may contain characters with meaning in XML.
+fn render_example(name: &str, template: &str, broker: &Path, data_root: &Path) -> String {
+    let escape = |path: &Path| {
+        let path = path.display().to_string();
+        if name.ends_with(".xml") {
+            path.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+        } else {
+            path
+        }
+    };
+    template
+        .replace("{broker}", &escape(broker))
+        .replace("{config}", &escape(&data_root.join("config.yml")))
+        .replace("{data_root}", &escape(data_root))
 }
 
 fn write_config(data_root: &Path, filename: &str, force_write: bool) -> Result<bool, Error> {
@@ -80,27 +151,15 @@ fn write_config(data_root: &Path, filename: &str, force_write: bool) -> Result<b
 
     std::fs::create_dir_all(data_root)
         .context_lazy(|| Error::CreateDataRoot(data_root.to_owned()))
-        .describe_lazy(|| indoc! {"
-            Broker requires that the data root exists and is a directory in order to create config files.
-            If the directory does not exist, Broker attempts to create it at a default location for your user.
-        "})
-        .help_lazy(|| indoc! {"
-            This can happen if Broker did not have permission to create the directory.
-            Try creating the directory yourself then running Broker again.
-            Alternately, you may specify a different data root: run Broker with the `-h` argument to see how.
-        "})?;
+        .describe_lazy(|| msg!(Message::InitCreateDataRootDescription))
+        .help_lazy(|| msg!(Message::InitCreateDataRootHelp))?;
 
     fs::write(&config_file_path, default_config_file(data_root))
         .context_lazy(|| Error::WriteConfigFile {
             path: config_file_path.to_path_buf(),
             data_root: data_root.to_path_buf(),
         })
-        .help_lazy(|| indoc! {"
-            This can happen if Broker did not have permission to create files in the data root directory.
-            Ensure that your current user in the operating system is allowed to create files in the data root;
-            deleting it and re-creating it may resolve this issue.
-            Alternately, you may specify a different data root: run Broker with the `-h` argument to see how.
-        "})?;
+        .help_lazy(|| msg!(Message::InitWriteConfigHelp))?;
 
     Ok(false)
 }
@@ -0,0 +1,499 @@
+//! Implementation for the `monitor` subcommand.
+//!
+//! The monitor is a terminal UI which periodically refreshes the state shown by `broker status` and `broker queue ls`,
+//! read from the database opened read only, alongside the most recent errors in the trace files.
+
+use std::{
+    io::{self, Stdout},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use atty::Stream;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use error_stack::{report, Report, ResultExt};
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Cell, List, ListItem, Paragraph, Row, Table},
+    Frame, Terminal,
+};
+use tokio::sync::mpsc;
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    config::Config,
+    db::{self, Backlog, Database, JobStage, QueuedJob},
+    debug::snapshot::{self, RecordedError},
+    ext::error_stack::ErrorHelper,
+};
+
+/// How often the monitor refreshes its state.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often the thread reading key presses checks whether the monitor has exited.
+const KEY_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Column widths of the integrations table.
+const INTEGRATION_WIDTHS: [Constraint; 4] = [
+    Constraint::Percentage(40),
+    Constraint::Percentage(15),
+    Constraint::Percentage(35),
+    Constraint::Percentage(10),
+];
+
+/// Column widths of the jobs table.
+const JOB_WIDTHS: [Constraint; 4] = [
+    Constraint::Percentage(10),
+    Constraint::Percentage(40),
+    Constraint::Percentage(35),
+    Constraint::Percentage(15),
+];
+
+/// Errors encountered running the monitor.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The monitor needs an interactive terminal to draw on.
+    #[error("standard output is not a terminal")]
+    NotATerminal,
+
+    /// Interacting with the database failed.
+    #[error("interact with the database")]
+    Interact,
+
+    /// Drawing on the terminal failed.
+    #[error("draw on the terminal")]
+    Terminal,
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::NotATerminal => ErrorCode::new(4201),
+            Self::Interact => ErrorCode::new(4202),
+            Self::Terminal => ErrorCode::new(4203),
+        }
+    }
+}
+
+/// The state shown by the monitor.
+#[derive(Debug, Clone)]
+struct State {
+    /// When the state was collected.
+    collected_at: SystemTime,
+
+    /// The state of each configured integration.
+    integrations: Vec<IntegrationState>,
+
+    /// The jobs enqueued by `broker run`, oldest first.
+    jobs: Vec<QueuedJob>,
+
+    /// The most recent warnings and errors from the trace files, newest first.
+    errors: Vec<RecordedError>,
+
+    /// Parts of the state which couldn't be collected, and why.
+    problems: Vec<String>,
+}
+
+/// The state of an integration shown by the monitor.
+#[derive(Debug, Clone)]
+struct IntegrationState {
+    /// The integration, as it's named in logs.
+    name: String,
+
+    /// When the integration was last polled, if it has been.
+    last_poll: Option<SystemTime>,
+
+    /// Progress through the references enqueued for scanning, if any have been.
+    backlog: Option<Backlog>,
+
+    /// How many scans of the integration are waiting to retry their upload.
+    pending_uploads: usize,
+}
+
+impl State {
+    /// Collect the state from the database and trace files.
+    ///
+    /// Nothing that fails to be collected stops the monitor; failures are shown instead.
+    async fn collect<D: Database>(config: &Config, db: &D, trace_root: &Path) -> Self {
+        let mut state = Self {
+            collected_at: SystemTime::now(),
+            integrations: Vec::new(),
+            jobs: Vec::new(),
+            errors: snapshot::recent_errors(trace_root),
+            problems: Vec::new(),
+        };
+
+        match db.queued_jobs().await {
+            Ok(mut jobs) => {
+                jobs.sort_by_key(|job| job.enqueued_at());
+                state.jobs = jobs;
+            }
+            Err(err) => state.problems.push(format!("read queued jobs: {err}")),
+        }
+
+        for integration in config.integrations().iter() {
+            let namespace = integration.namespace();
+            let repository = integration.repository();
+            let mut integration_state = IntegrationState {
+                name: integration.to_string(),
+                last_poll: None,
+                backlog: None,
+                pending_uploads: 0,
+            };
+
+            match db.last_poll(&namespace, &repository).await {
+                Ok(polled) => integration_state.last_poll = polled,
+                Err(err) => state
+                    .problems
+                    .push(format!("read last poll for '{integration}': {err}")),
+            }
+            match db.backlog(&namespace, &repository).await {
+                Ok(backlog) => integration_state.backlog = backlog,
+                Err(err) => state
+                    .problems
+                    .push(format!("read backlog for '{integration}': {err}")),
+            }
+            match db.pending_uploads(&namespace, &repository).await {
+                Ok(uploads) => integration_state.pending_uploads = uploads.len(),
+                Err(err) => state
+                    .problems
+                    .push(format!("read pending uploads for '{integration}': {err}")),
+            }
+
+            state.integrations.push(integration_state);
+        }
+
+        state
+    }
+
+    /// How many jobs are in the stage.
+    fn queued(&self, stage: JobStage) -> usize {
+        self.jobs.iter().filter(|job| job.stage() == stage).count()
+    }
+}
+
+/// Show the live state of Broker in a terminal UI until the user quits.
+///
+/// The database is opened read only, so this can be run while `broker run` is using it.
+#[tracing::instrument(skip(config))]
+pub async fn main(config: &Config, location: &Path) -> Result<(), Report<Error>> {
+    if !atty::is(Stream::Stdout) {
+        return Err(report!(Error::NotATerminal))
+            .help("run 'broker status' or 'broker queue ls' to print the state instead");
+    }
+
+    let db = db::open_sqlite_read_only(location)
+        .await
+        .change_context(Error::Interact)
+        .help(
+            "run 'broker run' with this version of Broker at least once to prepare the database",
+        )?;
+    let trace_root = config.debug().location().as_path().join("trace");
+
+    let mut screen = Screen::enter().change_context(Error::Terminal)?;
+    let (sender, mut keys) = mpsc::unbounded_channel();
+    std::thread::spawn(move || read_keys(sender));
+
+    loop {
+        let state = State::collect(config, &db, &trace_root).await;
+        screen
+            .0
+            .draw(|frame| render(frame, &state))
+            .change_context(Error::Terminal)?;
+
+        tokio::select! {
+            _ = tokio::time::sleep(REFRESH_INTERVAL) => {}
+            key = keys.recv() => match key {
+                Some(key) if is_quit(&key) => return Ok(()),
+                Some(_) => {}
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+/// The terminal, switched to raw mode and the alternate screen for as long as this is alive.
+struct Screen(Terminal<CrosstermBackend<Stdout>>);
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        let terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        terminal::enable_raw_mode()?;
+        let mut screen = Self(terminal);
+        execute!(screen.0.backend_mut(), EnterAlternateScreen)?;
+        screen.0.hide_cursor()?;
+        Ok(screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(self.0.backend_mut(), LeaveAlternateScreen);
+        let _ = self.0.show_cursor();
+    }
+}
+
+/// Forward key presses to the monitor until it exits.
+///
+/// Reading terminal events blocks, so this runs on its own thread.
+fn read_keys(sender: mpsc::UnboundedSender<KeyEvent>) {
+    loop {
+        match event::poll(KEY_POLL_INTERVAL) {
+            Ok(true) => {
+                if let Ok(Event::Key(key)) = event::read() {
+                    if key.kind != KeyEventKind::Release && sender.send(key).is_err() {
+                        return;
+                    }
+                }
+            }
+            Ok(false) if sender.is_closed() => return,
+            Ok(false) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+/// Whether the key quits the monitor.
+///
+/// The terminal is in raw mode, so ctrl+c is read as a key press rather than interrupting Broker.
+fn is_quit(key: &KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => true,
+        KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
+
+/// Draw the state.
+fn render<B: Backend>(frame: &mut Frame<'_, B>, state: &State) {
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(4),
+            Constraint::Percentage(30),
+            Constraint::Percentage(35),
+            Constraint::Min(5),
+        ])
+        .split(frame.size());
+
+    frame.render_widget(render_header(state), areas[0]);
+    frame.render_widget(render_integrations(state), areas[1]);
+    frame.render_widget(render_jobs(state), areas[2]);
+    frame.render_widget(render_errors(state), areas[3]);
+}
+
+fn render_header(state: &State) -> Paragraph<'static> {
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    let pending_uploads = state
+        .integrations
+        .iter()
+        .map(|integration| integration.pending_uploads)
+        .sum::<usize>();
+    let mut lines = vec![Line::from(vec![
+        Span::styled("Broker monitor", bold.fg(Color::Cyan)),
+        Span::raw(format!(
+            "  refreshed at {}  ",
+            humantime::format_rfc3339_seconds(state.collected_at)
+        )),
+        Span::styled("scanning: ", bold),
+        Span::raw(format!("{}  ", state.queued(JobStage::Scan))),
+        Span::styled("uploading: ", bold),
+        Span::raw(format!("{}  ", state.queued(JobStage::Upload))),
+        Span::styled("following up: ", bold),
+        Span::raw(format!("{}  ", state.queued(JobStage::FollowUp))),
+        Span::styled("retrying: ", bold),
+        Span::styled(pending_uploads.to_string(), count_style(pending_uploads)),
+        Span::styled("  (q to quit, any other key to refresh)", dim()),
+    ])];
+    if !state.problems.is_empty() {
+        lines.push(Line::from(Span::styled(
+            state.problems.join("; "),
+            Style::default().fg(Color::Red),
+        )));
+    }
+    Paragraph::new(lines).block(Block::default().borders(Borders::ALL))
+}
+
+fn render_integrations(state: &State) -> Table<'static> {
+    let rows = state.integrations.iter().map(|integration| {
+        let last_poll = match integration.last_poll {
+            Some(polled) => format!("{} ago", since(state.collected_at, polled)),
+            None => String::from("never"),
+        };
+        let backlog = match &integration.backlog {
+            None => Cell::from("nothing enqueued yet").style(dim()),
+            Some(backlog) if backlog.is_complete() => {
+                Cell::from(format!("idle; scanned {} references", backlog.total()))
+                    .style(Style::default().fg(Color::Green))
+            }
+            Some(backlog) => Cell::from(format!(
+                "scanned {} of {} ({:.0}%)",
+                backlog.completed(),
+                backlog.total(),
+                backlog.percent_complete(),
+            ))
+            .style(Style::default().fg(Color::Yellow)),
+        };
+        Row::new(vec![
+            Cell::from(integration.name.clone()),
+            Cell::from(last_poll),
+            backlog,
+            Cell::from(integration.pending_uploads.to_string())
+                .style(count_style(integration.pending_uploads)),
+        ])
+    });
+
+    Table::new(rows.collect::<Vec<_>>())
+        .header(header_row([
+            "INTEGRATION",
+            "LAST POLL",
+            "BACKLOG",
+            "RETRIES",
+        ]))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" Integrations ({}) ", state.integrations.len())),
+        )
+        .widths(&INTEGRATION_WIDTHS)
+}
+
+fn render_jobs(state: &State) -> Table<'static> {
+    let rows = state.jobs.iter().map(|job| {
+        let stage = match job.stage() {
+            JobStage::Scan => Style::default().fg(Color::Yellow),
+            JobStage::Upload => Style::default().fg(Color::Blue),
+            JobStage::FollowUp => Style::default().fg(Color::Magenta),
+        };
+        Row::new(vec![
+            Cell::from(job.stage().to_string()).style(stage),
+            Cell::from(job.coordinate().remote().clone()),
+            Cell::from(job.reference().clone()),
+            Cell::from(since(state.collected_at, job.enqueued_at())),
+        ])
+    });
+
+    Table::new(rows.collect::<Vec<_>>())
+        .header(header_row(["STAGE", "INTEGRATION", "REFERENCE", "ELAPSED"]))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" Jobs ({}) ", state.jobs.len())),
+        )
+        .widths(&JOB_WIDTHS)
+}
+
+fn render_errors(state: &State) -> List<'static> {
+    let items = state
+        .errors
+        .iter()
+        .map(|error| {
+            let level = match error.level().as_str() {
+                "ERROR" => Style::default().fg(Color::Red),
+                _ => Style::default().fg(Color::Yellow),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{} ", error.timestamp().as_deref().unwrap_or("-")),
+                    dim(),
+                ),
+                Span::styled(format!("{:<5} ", error.level()), level),
+                Span::raw(error.message().clone()),
+            ]))
+        })
+        .collect::<Vec<_>>();
+
+    List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Recent errors "),
+    )
+}
+
+fn header_row<const N: usize>(titles: [&'static str; N]) -> Row<'static> {
+    Row::new(titles).style(Style::default().add_modifier(Modifier::BOLD))
+}
+
+fn count_style(count: usize) -> Style {
+    if count > 0 {
+        Style::default().fg(Color::Red)
+    } else {
+        Style::default()
+    }
+}
+
+fn dim() -> Style {
+    Style::default().fg(Color::DarkGray)
+}
+
+/// Describe how long ago the time was, rounded to the second.
+fn since(now: SystemTime, then: SystemTime) -> String {
+    let elapsed = now.duration_since(then).unwrap_or_default();
+    let elapsed = Duration::from_secs(elapsed.as_secs());
+    humantime::format_duration(elapsed).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+
+    use crate::db::{Coordinate, Namespace};
+
+    use super::*;
+
+    #[test]
+    fn renders_state() {
+        let now = SystemTime::now();
+        let minutes_ago = |minutes| now - Duration::from_secs(minutes * 60);
+        let state = State {
+            collected_at: now,
+            integrations: vec![IntegrationState {
+                name: String::from("github.com/fossas/broker"),
+                last_poll: Some(minutes_ago(2)),
+                backlog: Some(Backlog::new(10, 4, minutes_ago(30), minutes_ago(1))),
+                pending_uploads: 1,
+            }],
+            jobs: vec![QueuedJob::new(
+                String::from("scan-1"),
+                JobStage::Scan,
+                Coordinate::new(
+                    Namespace::Git,
+                    String::from("github.com/fossas/broker"),
+                    String::from("main"),
+                ),
+                String::from("main"),
+                Vec::new(),
+                true,
+                minutes_ago(5),
+                None,
+            )],
+            errors: Vec::new(),
+            problems: Vec::new(),
+        };
+
+        let mut terminal = Terminal::new(TestBackend::new(160, 40)).expect("must create terminal");
+        terminal
+            .draw(|frame| render(frame, &state))
+            .expect("must draw");
+        let screen = terminal
+            .backend()
+            .buffer()
+            .content
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect::<String>();
+
+        assert!(screen.contains("github.com/fossas/broker"), "{screen}");
+        assert!(screen.contains("scanned 4 of 10 (40%)"), "{screen}");
+        assert!(screen.contains("2m ago"), "{screen}");
+        assert!(screen.contains("5m"), "{screen}");
+    }
+}
@@ -0,0 +1,128 @@
+//! Implementation for the `pause` and `resume` subcommands.
+//!
+//! Pauses are stored in the database, which `broker run` checks before each poll and each scan,
+//! so integrations can be taken out of rotation while Broker is running without editing the config file.
+
+use std::time::SystemTime;
+
+use error_stack::{report, Report, ResultExt};
+use itertools::Itertools;
+use tracing::info;
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    config::Config,
+    db::{Database, PauseScope},
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper},
+        result::WrapErr,
+    },
+};
+
+/// Errors encountered pausing or resuming integrations.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The requested integration isn't in the config file.
+    #[error("integration '{0}' is not configured")]
+    IntegrationNotFound(String),
+
+    /// Interacting with the database failed.
+    #[error("interact with the database")]
+    Interact,
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::IntegrationNotFound(..) => ErrorCode::new(4301),
+            Self::Interact => ErrorCode::new(4302),
+        }
+    }
+}
+
+/// Pause an integration, or every integration if `integration` is `None`,
+/// so that `broker run` doesn't poll or scan it until it's resumed.
+#[tracing::instrument(skip(config, db))]
+pub async fn pause<D: Database>(
+    config: &Config,
+    db: &D,
+    integration: Option<&str>,
+) -> Result<(), Report<Error>> {
+    let scope = scope(config, integration)?;
+    db.pause(&scope, SystemTime::now())
+        .await
+        .change_context(Error::Interact)?;
+
+    info!(?scope, "Paused integrations");
+    match integration {
+        Some(integration) => println!("Paused '{integration}'; Broker won't poll or scan it until it's resumed with 'broker resume {integration}'."),
+        None => println!("Paused every integration; Broker won't poll or scan any of them until they're resumed with 'broker resume --all'."),
+    }
+    Ok(())
+}
+
+/// Resume an integration, or every integration if `integration` is `None`.
+///
+/// Resuming every integration also resumes those which were paused individually.
+#[tracing::instrument(skip(config, db))]
+pub async fn resume<D: Database>(
+    config: &Config,
+    db: &D,
+    integration: Option<&str>,
+) -> Result<(), Report<Error>> {
+    let scope = scope(config, integration)?;
+    let resumed = db.resume(&scope).await.change_context(Error::Interact)?;
+    info!(?scope, %resumed, "Resumed integrations");
+
+    let (Some(integration), PauseScope::Repository(namespace, repository)) = (integration, &scope)
+    else {
+        println!("Resumed every integration; Broker polls and scans them again as usual.");
+        return Ok(());
+    };
+    if resumed == 0 {
+        println!("'{integration}' wasn't paused on its own, so nothing was resumed.");
+    }
+
+    // Pausing every integration also applies to this one, and isn't undone by resuming it alone.
+    let paused = db
+        .is_paused(namespace, repository)
+        .await
+        .change_context(Error::Interact)?;
+    if paused {
+        println!("Every integration is still paused, including '{integration}'; resume them with 'broker resume --all'.");
+    } else if resumed > 0 {
+        println!("Resumed '{integration}'; Broker polls and scans it again as usual.");
+    }
+    Ok(())
+}
+
+/// The scope of the pause for the integration with the provided remote, or every integration if it's `None`.
+pub(crate) fn scope(
+    config: &Config,
+    integration: Option<&str>,
+) -> Result<PauseScope, Report<Error>> {
+    let Some(integration) = integration else {
+        return Ok(PauseScope::All);
+    };
+    let Some(found) = config
+        .integrations()
+        .iter()
+        .find(|candidate| candidate.remote().to_string() == integration)
+    else {
+        let configured = config
+            .integrations()
+            .iter()
+            .map(|integration| format!("'{}'", integration.remote()))
+            .join(", ");
+        return report!(Error::IntegrationNotFound(integration.to_string()))
+            .wrap_err()
+            .help(
+                "provide the remote of the integration exactly as it is written in the config file",
+            )
+            .describe_lazy(|| format!("configured integrations: {configured}"));
+    };
+    Ok(PauseScope::Repository(
+        found.namespace(),
+        found.repository(),
+    ))
+}
@@ -0,0 +1,191 @@
+//! Implementation for the `queue` subcommands.
+//!
+//! `broker run` records each job it enqueues in the database until the job is done,
+//! so that jobs can be inspected from outside the running process.
+//! Scans which failed to upload and are waiting to be retried are listed alongside them.
+
+use std::{path::Path, time::SystemTime};
+
+use error_stack::{report, Report, ResultExt};
+use tracing::info;
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    config::Config,
+    db::{self, Database},
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper},
+        result::WrapErr,
+    },
+    AppContext,
+};
+
+use super::run::{lock::InstanceLock, pending, uploads_dir};
+
+/// Errors encountered inspecting or modifying the queue.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// No job has the provided ID.
+    #[error("job '{0}' is not enqueued")]
+    JobNotFound(String),
+
+    /// Jobs can only be dropped while `broker run` is stopped.
+    #[error("lock data root")]
+    Lock,
+
+    /// Interacting with the database failed.
+    #[error("interact with the database")]
+    Interact,
+
+    /// Removing a saved scan failed.
+    #[error("remove saved scan")]
+    RemoveSavedScan,
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::JobNotFound(..) => ErrorCode::new(3801),
+            Self::Lock => ErrorCode::new(3802),
+            Self::Interact => ErrorCode::new(3803),
+            Self::RemoveSavedScan => ErrorCode::new(3804),
+        }
+    }
+}
+
+/// A job shown by `broker queue ls` and listed by the admin API.
+pub(crate) struct Listed {
+    pub(crate) id: String,
+    pub(crate) stage: String,
+    pub(crate) integration: String,
+    pub(crate) reference: String,
+    pub(crate) since: SystemTime,
+}
+
+/// List the jobs enqueued by `broker run`, and the scans waiting to be uploaded again.
+///
+/// The database is opened read only, so this can be run while `broker run` is using it.
+#[tracing::instrument(skip(config))]
+pub async fn list(config: &Config, location: &Path) -> Result<(), Report<Error>> {
+    let db = db::open_sqlite_read_only(location)
+        .await
+        .change_context(Error::Interact)?;
+
+    let listed = jobs(config, &db).await.help(
+        "run 'broker run' with this version of Broker at least once to prepare the database",
+    )?;
+    if listed.is_empty() {
+        println!("No jobs are enqueued.");
+        return Ok(());
+    }
+
+    let now = SystemTime::now();
+    println!("ID\tSTAGE\tINTEGRATION\tREFERENCE\tAGE");
+    for job in listed {
+        let age = now.duration_since(job.since).unwrap_or_default();
+        let age = std::time::Duration::from_secs(age.as_secs());
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            job.id,
+            job.stage,
+            job.integration,
+            job.reference,
+            humantime::format_duration(age)
+        );
+    }
+    Ok(())
+}
+
+/// The jobs enqueued by `broker run`, followed by the scans waiting to be uploaded again.
+pub(crate) async fn jobs<D: Database>(
+    config: &Config,
+    db: &D,
+) -> Result<Vec<Listed>, Report<Error>> {
+    let mut listed = db
+        .queued_jobs()
+        .await
+        .change_context(Error::Interact)?
+        .into_iter()
+        .map(|job| Listed {
+            id: job.scan_id().clone(),
+            stage: job.stage().to_string(),
+            integration: job.coordinate().remote().clone(),
+            reference: job.reference().clone(),
+            since: job.enqueued_at(),
+        })
+        .collect::<Vec<_>>();
+    for integration in config.integrations().iter() {
+        let pending = db
+            .pending_uploads(&integration.namespace(), &integration.repository())
+            .await
+            .change_context(Error::Interact)
+            .describe_lazy(|| format!("read pending uploads for '{integration}'"))?;
+        listed.extend(pending.into_iter().map(|upload| Listed {
+            id: upload.scan_id().clone(),
+            stage: format!("retry ({} failed)", upload.attempts()),
+            integration: upload.coordinate().remote().clone(),
+            reference: upload.coordinate().reference().clone(),
+            since: upload.first_failed_at(),
+        }));
+    }
+    Ok(listed)
+}
+
+/// Drop a job so that `broker run` doesn't work on it again,
+/// for example a reference whose scan crashes Broker each time it's attempted.
+///
+/// A reference that was waiting to be scanned or uploaded is recorded as scanned,
+/// so it isn't enqueued again until it changes; a scan waiting to be uploaded again is removed.
+/// Since `broker run` holds its jobs in memory, this refuses to run while `broker run` is using the data root.
+#[tracing::instrument(skip(ctx, config, db))]
+pub async fn drop_job<D: Database>(
+    ctx: &AppContext,
+    config: &Config,
+    db: &D,
+    id: &str,
+) -> Result<(), Report<Error>> {
+    let _lock = InstanceLock::acquire(ctx.data_root())
+        .change_context(Error::Lock)
+        .help("stop 'broker run' before dropping jobs, then start it again afterwards")?;
+
+    let jobs = db.queued_jobs().await.change_context(Error::Interact)?;
+    if let Some(job) = jobs.into_iter().find(|job| job.scan_id() == id) {
+        db.set_state(job.coordinate(), job.state(), &job.is_branch())
+            .await
+            .change_context(Error::Interact)?;
+        db.delete_queued_job(id)
+            .await
+            .change_context(Error::Interact)?;
+
+        info!("Dropped {} job '{id}'", job.stage());
+        println!(
+            "Dropped job '{id}'; '{}' at '{}' won't be scanned again until it changes.",
+            job.coordinate().remote(),
+            job.reference()
+        );
+        return Ok(());
+    }
+
+    for integration in config.integrations().iter() {
+        let pending = db
+            .pending_uploads(&integration.namespace(), &integration.repository())
+            .await
+            .change_context(Error::Interact)?;
+        if pending.iter().any(|upload| upload.scan_id() == id) {
+            db.delete_pending_upload(id)
+                .await
+                .change_context(Error::Interact)?;
+            pending::remove(&uploads_dir(ctx), id)
+                .await
+                .change_context(Error::RemoveSavedScan)?;
+
+            info!("Dropped pending upload '{id}'");
+            println!("Dropped job '{id}'; its scan won't be uploaded again.");
+            return Ok(());
+        }
+    }
+
+    report!(Error::JobNotFound(id.to_string()))
+        .wrap_err()
+        .help("run 'broker queue ls' to see the IDs of enqueued jobs")
+}
@@ -0,0 +1,427 @@
+//! Implementation for the `report` subcommands.
+//!
+//! Reports combine the scan history Broker records with what FOSSA found in the uploaded scans,
+//! for example to produce a weekly compliance roll-up.
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use error_stack::{Report, ResultExt};
+use itertools::Itertools;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    api::{fossa, http::client::Purpose},
+    config::Config,
+    db::{self, Database, HistoricScan, PolicyStatus},
+    ext::error_stack::{DescribeContext, ErrorHelper, IntoContext},
+    AppContext,
+};
+
+/// Errors encountered producing reports.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Interacting with the database failed.
+    #[error("interact with the database")]
+    Interact,
+
+    /// The report couldn't be rendered.
+    #[error("render report as {0}")]
+    Render(Format),
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Interact => ErrorCode::new(4401),
+            Self::Render(..) => ErrorCode::new(4402),
+        }
+    }
+}
+
+/// The format in which a report is printed.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, clap::ValueEnum, strum::Display,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum Format {
+    /// Rendered as text for reading in the terminal.
+    #[default]
+    Text,
+
+    /// Rendered as JSON.
+    Json,
+
+    /// Rendered as CSV, with a row for each repository followed by a row of totals.
+    Csv,
+}
+
+/// Severities in the order they're reported, most severe first.
+/// Any other severities FOSSA reports follow these in alphabetical order.
+const SEVERITIES: [&str; 5] = ["critical", "high", "medium", "low", "unknown"];
+
+/// Print a roll-up of the scans recorded in the last `period`, and the issues FOSSA found in them.
+///
+/// Issues are those FOSSA found in the most recently uploaded scan of each repository in the period,
+/// so that revisions scanned more than once aren't counted more than once.
+/// The database is opened read only, so this can be run while `broker run` is using it.
+#[tracing::instrument(skip(ctx, config))]
+pub async fn summary(
+    ctx: &AppContext,
+    config: &Config,
+    location: &Path,
+    period: Duration,
+    format: Format,
+) -> Result<(), Report<Error>> {
+    let db = db::open_sqlite_read_only(location)
+        .await
+        .change_context(Error::Interact)?;
+
+    let until = SystemTime::now();
+    let since = until.checked_sub(period).unwrap_or(UNIX_EPOCH);
+    let client = ctx.http().get(Purpose::Fossa);
+
+    let mut repositories = Vec::new();
+    for integration in config.integrations().iter() {
+        let namespace = integration.namespace();
+        let repository = integration.repository();
+        let scans = db
+            .scans_since(&namespace, &repository, since)
+            .await
+            .change_context(Error::Interact)
+            .describe_lazy(|| format!("read scan history for '{integration}'"))
+            .help("run 'broker run' with this version of Broker at least once to prepare the database")?;
+
+        // Repositories scanned before the period were still imported, even if nothing changed since.
+        let imported = !scans.is_empty()
+            || !db
+                .recent_scans(&namespace, &repository, 1)
+                .await
+                .change_context(Error::Interact)
+                .describe_lazy(|| format!("read scan history for '{integration}'"))?
+                .is_empty();
+
+        let locator = scans.iter().find_map(|scan| scan.locator().clone());
+        let issues = match &locator {
+            Some(locator) => match fossa::find_issues(client, config.fossa_api(), locator).await {
+                Ok(issues) => issues.map(|issues| issues.severities().clone()),
+                Err(err) => {
+                    warn!("Unable to look up issues for '{locator}': {err:#?}");
+                    None
+                }
+            },
+            None => None,
+        };
+
+        repositories.push(RepositorySummary::new(
+            integration.remote().to_string(),
+            imported,
+            &scans,
+            locator,
+            issues,
+        ));
+    }
+
+    let summary = Summary::new(since, until, repositories);
+    let rendered = match format {
+        Format::Text => summary.render_text(),
+        Format::Json => serde_json::to_string_pretty(&summary)
+            .map(|rendered| format!("{rendered}\n"))
+            .context(Error::Render(format))?,
+        Format::Csv => summary.render_csv(),
+    };
+    print!("{rendered}");
+    Ok(())
+}
+
+/// A roll-up of the scans recorded over a period, and the issues FOSSA found in them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Summary {
+    /// The start of the period.
+    since: String,
+
+    /// The end of the period.
+    until: String,
+
+    /// The number of configured repositories.
+    repositories_configured: usize,
+
+    /// The number of repositories Broker has scanned at least once.
+    repositories_imported: usize,
+
+    /// The number of repositories scanned in the period.
+    repositories_scanned: usize,
+
+    /// The number of distinct revisions scanned in the period.
+    revisions_scanned: usize,
+
+    /// The number of scans in the period, including scans of the same revision.
+    scans: usize,
+
+    /// The number of scans in the period which passed their check for issues.
+    policy_passed: usize,
+
+    /// The number of scans in the period which failed their check for issues.
+    policy_failed: usize,
+
+    /// The number of issues of each severity, summed over repositories.
+    issues: BTreeMap<String, usize>,
+
+    /// The number of repositories scanned in the period whose issues couldn't be looked up.
+    issues_unavailable: usize,
+
+    /// The summary of each repository.
+    repositories: Vec<RepositorySummary>,
+}
+
+impl Summary {
+    fn new(since: SystemTime, until: SystemTime, repositories: Vec<RepositorySummary>) -> Self {
+        let sum = |count: fn(&RepositorySummary) -> usize| -> usize {
+            repositories.iter().map(count).sum()
+        };
+        let issues = repositories
+            .iter()
+            .filter_map(|repository| repository.issues.as_ref())
+            .flatten()
+            .fold(BTreeMap::new(), |mut issues, (severity, count)| {
+                *issues.entry(severity.clone()).or_insert(0) += count;
+                issues
+            });
+        Self {
+            since: timestamp(since),
+            until: timestamp(until),
+            repositories_configured: repositories.len(),
+            repositories_imported: sum(|repository| usize::from(repository.imported)),
+            repositories_scanned: sum(|repository| usize::from(repository.scans > 0)),
+            revisions_scanned: sum(|repository| repository.revisions_scanned),
+            scans: sum(|repository| repository.scans),
+            policy_passed: sum(|repository| repository.policy_passed),
+            policy_failed: sum(|repository| repository.policy_failed),
+            issues_unavailable: sum(|repository| {
+                usize::from(repository.scans > 0 && repository.issues.is_none())
+            }),
+            issues,
+            repositories,
+        }
+    }
+
+    fn render_text(&self) -> String {
+        let mut lines = vec![
+            format!("Summary from {} to {}", self.since, self.until),
+            format!(
+                "Repositories imported: {} of {} configured",
+                self.repositories_imported, self.repositories_configured
+            ),
+            format!("Repositories scanned: {}", self.repositories_scanned),
+            format!(
+                "Revisions scanned: {} ({} scans)",
+                self.revisions_scanned, self.scans
+            ),
+            format!(
+                "Policy checks: {} passed, {} failed",
+                self.policy_passed, self.policy_failed
+            ),
+            format!("Issues by severity: {}", describe_issues(&self.issues)),
+        ];
+        if self.issues_unavailable > 0 {
+            lines.push(format!(
+                "Issues unavailable for {} repositories; FOSSA may still be checking them, or they weren't uploaded to the primary FOSSA endpoint",
+                self.issues_unavailable
+            ));
+        }
+
+        if !self.repositories.is_empty() {
+            lines.push(String::new());
+        }
+        for repository in &self.repositories {
+            let activity = match &repository.last_scanned {
+                Some(last) => format!(
+                    "{} revisions in {} scans, last at {last}",
+                    repository.revisions_scanned, repository.scans
+                ),
+                None => String::from("not scanned in this period"),
+            };
+            let issues = match &repository.issues {
+                Some(issues) => describe_issues(issues),
+                None => String::from("unavailable"),
+            };
+            lines.push(format!(
+                "{}: {activity}; issues: {issues}",
+                repository.remote
+            ));
+        }
+
+        lines.into_iter().map(|line| format!("{line}\n")).collect()
+    }
+
+    fn render_csv(&self) -> String {
+        let severities = ordered_severities(self.issues.keys());
+        let header = [
+            "remote",
+            "revisions_scanned",
+            "scans",
+            "last_scanned",
+            "locator",
+        ]
+        .into_iter()
+        .map(String::from)
+        .chain(severities.iter().cloned());
+
+        let rows = self.repositories.iter().map(|repository| {
+            let counts = severities.iter().map(|severity| {
+                repository
+                    .issues
+                    .as_ref()
+                    .map(|issues| {
+                        issues
+                            .get(severity)
+                            .copied()
+                            .unwrap_or_default()
+                            .to_string()
+                    })
+                    .unwrap_or_default()
+            });
+            [
+                repository.remote.clone(),
+                repository.revisions_scanned.to_string(),
+                repository.scans.to_string(),
+                repository.last_scanned.clone().unwrap_or_default(),
+                repository.locator.clone().unwrap_or_default(),
+            ]
+            .into_iter()
+            .chain(counts)
+            .collect_vec()
+        });
+
+        let totals = [
+            String::from("total"),
+            self.revisions_scanned.to_string(),
+            self.scans.to_string(),
+            String::new(),
+            String::new(),
+        ]
+        .into_iter()
+        .chain(severities.iter().map(|severity| {
+            self.issues
+                .get(severity)
+                .copied()
+                .unwrap_or_default()
+                .to_string()
+        }));
+
+        std::iter::once(header.collect_vec())
+            .chain(rows)
+            .chain(std::iter::once(totals.collect_vec()))
+            .map(|row| format!("{}\n", row.iter().map(|field| csv_field(field)).join(",")))
+            .collect()
+    }
+}
+
+/// A roll-up of the scans of a repository recorded over a period.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct RepositorySummary {
+    /// The remote of the repository's integration.
+    remote: String,
+
+    /// Whether Broker has scanned the repository at least once, in this period or before it.
+    imported: bool,
+
+    /// The number of distinct revisions scanned in the period.
+    revisions_scanned: usize,
+
+    /// The number of scans in the period, including scans of the same revision.
+    scans: usize,
+
+    /// The number of scans in the period which passed their check for issues.
+    policy_passed: usize,
+
+    /// The number of scans in the period which failed their check for issues.
+    policy_failed: usize,
+
+    /// When the repository was last scanned in the period, if it was.
+    last_scanned: Option<String>,
+
+    /// The locator of the most recent scan in the period which was uploaded to the primary FOSSA endpoint.
+    locator: Option<String>,
+
+    /// The number of issues of each severity FOSSA found in the scan at `locator`,
+    /// or `None` if they couldn't be looked up.
+    issues: Option<BTreeMap<String, usize>>,
+}
+
+impl RepositorySummary {
+    /// Summarize the scans of a repository, which are ordered newest first.
+    fn new(
+        remote: String,
+        imported: bool,
+        scans: &[HistoricScan],
+        locator: Option<String>,
+        issues: Option<BTreeMap<String, usize>>,
+    ) -> Self {
+        let policy = |status: PolicyStatus| {
+            scans
+                .iter()
+                .filter(|scan| scan.record().policy() == Some(status))
+                .count()
+        };
+        Self {
+            remote,
+            imported,
+            revisions_scanned: scans.iter().map(HistoricScan::revision).unique().count(),
+            scans: scans.len(),
+            policy_passed: policy(PolicyStatus::Passed),
+            policy_failed: policy(PolicyStatus::Failed),
+            last_scanned: scans.first().map(|scan| timestamp(scan.recorded_at())),
+            locator,
+            issues,
+        }
+    }
+}
+
+/// Describe the number of issues of each severity, most severe first.
+fn describe_issues(issues: &BTreeMap<String, usize>) -> String {
+    let described = ordered_severities(issues.keys())
+        .into_iter()
+        .filter_map(|severity| {
+            let count = issues.get(&severity).copied().unwrap_or_default();
+            (count > 0).then(|| format!("{count} {severity}"))
+        })
+        .join(", ");
+    if described.is_empty() {
+        String::from("none")
+    } else {
+        described
+    }
+}
+
+/// The well known severities, followed by any others that were found.
+fn ordered_severities<'a>(found: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let others = found
+        .filter(|severity| !SEVERITIES.contains(&severity.as_str()))
+        .cloned()
+        .sorted();
+    SEVERITIES
+        .into_iter()
+        .map(String::from)
+        .chain(others)
+        .collect()
+}
+
+/// Render the time for the report, rounded to the second.
+fn timestamp(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+/// Quote the field for CSV if it contains characters which would otherwise change its meaning.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -1,41 +1,80 @@
 //! Implementation for the `run` subcommand.
 
-use std::time::Duration;
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    future::Future,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
 
-use error_stack::{report, Result, ResultExt};
-use futures::TryStreamExt;
-use futures::{future::try_join_all, try_join, StreamExt};
+use bytesize::ByteSize;
+use error_stack::{report, Report, Result, ResultExt};
+use futures::{
+    future::{join_all, try_join_all},
+    stream::FuturesUnordered,
+    try_join, StreamExt,
+};
 use governor::{Quota, RateLimiter};
 use indoc::indoc;
+use itertools::Itertools;
 use nonzero_ext::nonzero;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use srclib::Locator;
 use tap::TapFallible;
+use tokio::sync::broadcast;
 use tokio_retry::strategy::jitter;
 use tokio_retry::strategy::ExponentialBackoff;
-use tokio_retry::Retry;
 use tracing::warn;
 use tracing::{debug, info};
 use uuid::Uuid;
 
 use crate::api::fossa::{self, CliMetadata, ProjectMetadata};
-use crate::api::remote::git::repository;
+use crate::api::http;
 use crate::api::remote::{
-    git, BranchImportStrategy, Integrations, Protocol, Reference, TagImportStrategy,
+    git, BranchImportStrategy, Contributors, Integrations, Protocol, Reference, Remote,
+    ScanOnStartup, TagImportStrategy,
 };
+use crate::clock::Clock;
+use crate::crypto;
+use crate::doc::code::{ErrorCode, HasErrorCode};
 use crate::ext::result::WrapErr;
 use crate::ext::tracing::span_record;
 use crate::fossa_cli::{self, DesiredVersion, Location, SourceUnits};
-use crate::queue::Queue;
-use crate::AppContext;
+use crate::queue::{self, Queue};
 use crate::{
     api::remote::{Integration, RemoteProvider},
-    config::Config,
-    db::Database,
+    config::{self, Config},
+    db::{self, Database, JobStage},
     ext::{
-        error_stack::{DescribeContext, ErrorHelper},
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        io,
         result::DiscardResult,
+        tempfile,
+        tokio::CancellationToken,
     },
 };
+use crate::{
+    audit::{self, Action, Event},
+    debug, disk, hooks,
+    notify::{self, Notifier},
+    secrets, AppContext,
+};
+
+use self::lock::InstanceLock;
+use self::marker::{ImportMarker, MARKER_FILE};
+use self::schedule::{Scheduler, Sender};
+use self::targets::{Target, Targets};
+
+mod admin;
+mod cache;
+pub(crate) mod history;
+pub(crate) mod lock;
+mod marker;
+pub(crate) mod pending;
+mod schedule;
+mod targets;
 
 /// Errors encountered during runtime.
 #[derive(Debug, thiserror::Error)]
@@ -57,6 +96,14 @@ pub enum Error {
     #[error("clone reference: {0:?}")]
     CloneReference(Reference),
 
+    /// If cloning a reference takes longer than the integration's clone timeout, this error is returned.
+    #[error("clone timed out after {}", humantime::format_duration(*.0))]
+    CloneTimeout(Duration),
+
+    /// If analyzing a reference takes longer than the integration's scan timeout, this error is returned.
+    #[error("scan timed out after {}", humantime::format_duration(*.0))]
+    ScanTimeout(Duration),
+
     /// If we fail to send tasks to the async task queue, this error is raised.
     #[error("enqueue task for processing")]
     TaskEnqueue,
@@ -85,21 +132,105 @@ pub enum Error {
     #[error("run FOSSA CLI")]
     RunFossaCli,
 
+    /// If a required hook fails, this error is raised.
+    #[error("run required hook")]
+    Hook,
+
+    /// If exporting or saving an SBOM fails, this error is raised.
+    #[error("export SBOM")]
+    ExportSbom,
+
     /// If we fail to delete tasks' state in the sqlite DB, this error is raised
     #[error("delete tasks' state")]
     TaskDeleteState,
 
+    /// Saving, reading, or removing a scan whose upload failed.
+    #[error("save scan for upload retry")]
+    PendingUpload,
+
+    /// Saving or removing cached results of analysis.
+    #[error("cache analysis results")]
+    AnalysisCache,
+
     /// Preflight checks failed
     #[error("preflight checks")]
     PreflightChecks,
 
-    /// Failed to connect to at least one integration
-    #[error("integration connections")]
-    IntegrationConnection,
-
     /// Failed to connect to FOSSA  
     #[error("FOSSA connection")]
     FossaConnection,
+
+    /// No git executable recent enough for Broker is available.
+    #[error("prepare git")]
+    Git,
+
+    /// Another instance of Broker holds the lock on the data root.
+    #[error("data root is in use by another instance: {}", .0.display())]
+    InstanceLocked(PathBuf),
+
+    /// Taking the lock on the data root failed.
+    #[error("lock data root: {}", .0.display())]
+    InstanceLock(PathBuf),
+
+    /// When scanning once, some integrations or references failed to be polled, scanned, or uploaded.
+    #[error("{0} poll(s) or scan(s) failed")]
+    ScanFailed(usize),
+
+    /// The secrets guard found secrets in a scan, and its policy blocks uploading it.
+    #[error("scan contained {0} secret(s), upload blocked")]
+    UploadBlocked(usize),
+
+    /// The clone of a reference is larger than the configured limit, so it isn't scanned.
+    #[error("clone is {0}, larger than the limit of {1}")]
+    CloneTooLarge(ByteSize, ByteSize),
+
+    /// The results of a scan are larger than the configured limit, so they aren't uploaded.
+    #[error("scan results are {0}, larger than the limit of {1}")]
+    UploadTooLarge(ByteSize, ByteSize),
+
+    /// Reading the file listing an integration's remotes failed.
+    #[error("read remotes from: {}", .0.display())]
+    RemotesFrom(PathBuf),
+
+    /// Serving the admin API, or handling a request to it, failed.
+    #[error("serve admin API")]
+    AdminApi,
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Healthcheck => ErrorCode::new(3101),
+            Self::SetupPipeline => ErrorCode::new(3102),
+            Self::PollIntegration => ErrorCode::new(3103),
+            Self::CloneReference(..) => ErrorCode::new(3104),
+            Self::CloneTimeout(..) => ErrorCode::new(3105),
+            Self::ScanTimeout(..) => ErrorCode::new(3106),
+            Self::TaskEnqueue => ErrorCode::new(3107),
+            Self::TaskReceive => ErrorCode::new(3108),
+            Self::TaskHandle => ErrorCode::new(3109),
+            Self::TaskSetState => ErrorCode::new(3110),
+            Self::TaskComplete => ErrorCode::new(3111),
+            Self::DownloadFossaCli => ErrorCode::new(3112),
+            Self::RunFossaCli => ErrorCode::new(3113),
+            Self::Hook => ErrorCode::new(3114),
+            Self::ExportSbom => ErrorCode::new(3115),
+            Self::TaskDeleteState => ErrorCode::new(3116),
+            Self::PendingUpload => ErrorCode::new(3117),
+            Self::AnalysisCache => ErrorCode::new(3118),
+            Self::PreflightChecks => ErrorCode::new(3119),
+            Self::FossaConnection => ErrorCode::new(3121),
+            Self::Git => ErrorCode::new(3122),
+            Self::InstanceLocked(..) => ErrorCode::new(3123),
+            Self::InstanceLock(..) => ErrorCode::new(3124),
+            Self::ScanFailed(..) => ErrorCode::new(3125),
+            Self::UploadBlocked(..) => ErrorCode::new(3126),
+            Self::CloneTooLarge(..) => ErrorCode::new(3127),
+            Self::UploadTooLarge(..) => ErrorCode::new(3128),
+            Self::RemotesFrom(..) => ErrorCode::new(3129),
+            Self::AdminApi => ErrorCode::new(3130),
+        }
+    }
 }
 
 /// Similar to [`AppContext`], but scoped for this subcommand.
@@ -113,16 +244,187 @@ struct CmdContext<D> {
 
     /// The database connection.
     db: D,
+
+    /// The audit log, recording all externally visible actions.
+    audit: audit::Log,
+
+    /// Sends notifications about failures to the configured sinks.
+    notifier: Notifier,
+
+    /// Tracks whether the locations Broker writes to have enough free space to start new scans.
+    disk: disk::Monitor,
+
+    /// The directory in which persistent mirrors are stored,
+    /// for integrations configured to use them.
+    mirrors: PathBuf,
+
+    /// The directory in which scans that failed to upload are saved until they're retried.
+    uploads: PathBuf,
+
+    /// The directory in which the results of analysis are cached by the hash of the analyzed tree.
+    analysis_cache: PathBuf,
+
+    /// The FOSSA endpoints to which scans are uploaded.
+    targets: Targets,
+
+    /// The directory to which SBOMs of uploaded scans are exported.
+    sboms: PathBuf,
+
+    /// The source of time for the workers.
+    clock: Clock,
+
+    /// Identifies this run of Broker as the holder of the scan leases it takes.
+    instance: String,
+
+    /// Cancelled to stop the workers.
+    cancel: CancellationToken,
+
+    /// The remotes of integrations for which a poll was requested through the admin API.
+    poll_requests: broadcast::Sender<String>,
+}
+
+impl<D> CmdContext<D> {
+    fn new(ctx: &AppContext, config: Config, db: D, cancel: CancellationToken) -> Self {
+        let audit = audit::Log::new(config.debug().location());
+        let notifier = Notifier::new(
+            config.notifications().clone(),
+            ctx.http().get(http::client::Purpose::Notifications).clone(),
+        );
+        let mirrors = crate::data_dir!(ctx).join("mirrors");
+        let uploads = uploads_dir(ctx);
+        let analysis_cache = crate::data_dir!(ctx).join("analysis-cache");
+        let targets = Targets::new(config.fossa_api());
+        let sboms = ctx.data_root().join("sboms");
+
+        // Clones are written to the system temp location; everything else is written to the data root or debug location.
+        let disk = disk::Monitor::new(
+            *config.disk_space(),
+            vec![
+                ctx.data_root().clone(),
+                config.debug().location().as_path().to_path_buf(),
+                std::env::temp_dir(),
+            ],
+        );
+        Self {
+            app: ctx.clone(),
+            config,
+            db,
+            audit,
+            notifier,
+            disk,
+            mirrors,
+            uploads,
+            analysis_cache,
+            targets,
+            sboms,
+            clock: ctx.clock().clone(),
+            instance: Uuid::new_v4().to_string(),
+            cancel,
+            poll_requests: broadcast::channel(POLL_REQUEST_CAPACITY).0,
+        }
+    }
+
+    /// The shared client for requests to the FOSSA API.
+    fn fossa_client(&self) -> &http::client::Client {
+        self.app.http().get(http::client::Purpose::Fossa)
+    }
+
+    /// Sleep for the duration on the clock, unless cancelled first.
+    /// Returns `false` if cancelled.
+    async fn sleep(&self, duration: Duration) -> bool {
+        self.cancel
+            .run_until_cancelled(self.clock.sleep(duration))
+            .await
+            .is_some()
+    }
+
+    /// Wait to poll the integration: until the duration elapses on the clock,
+    /// or until a poll of the integration is requested through the admin API.
+    /// Returns `false` if cancelled.
+    async fn wait_for_poll(
+        &self,
+        integration: &Integration,
+        requests: &mut broadcast::Receiver<String>,
+        duration: Duration,
+    ) -> bool {
+        let remote = integration.remote().to_string();
+        let requested = async {
+            loop {
+                match requests.recv().await {
+                    Ok(requested) if requested == remote => return,
+                    Ok(_) => continue,
+                    // The missed requests may have included this integration, so poll it to be safe.
+                    Err(broadcast::error::RecvError::Lagged(_)) => return,
+                    Err(broadcast::error::RecvError::Closed) => std::future::pending::<()>().await,
+                }
+            }
+        };
+        let wait = async {
+            tokio::select! {
+                _ = self.clock.sleep(duration) => {}
+                _ = requested => info!("Polling '{integration}' now, as requested through the admin API"),
+            }
+        };
+        self.cancel.run_until_cancelled(wait).await.is_some()
+    }
+
+    /// Wait on the clock until the poll window of the integration is open,
+    /// if it has one and it's closed.
+    /// Returns `false` if cancelled.
+    async fn wait_for_poll_window(&self, integration: &Integration) -> bool {
+        let Some(window) = integration.poll_window() else {
+            return true;
+        };
+        let wait = window.wait_from(self.clock.now());
+        if wait.is_zero() {
+            return true;
+        }
+
+        // Many integrations may share the same window, so spread them out when it opens.
+        let wait = wait + poll_jitter(window.duration());
+        info!("Waiting {wait:?} for poll window {window} for '{integration}'");
+        self.sleep(wait).await
+    }
+}
+
+/// How many poll requests from the admin API are buffered for each integration before older ones are dropped.
+const POLL_REQUEST_CAPACITY: usize = 64;
+
+/// The directory in which scans that failed to upload are saved until they're retried.
+pub(crate) fn uploads_dir(ctx: &AppContext) -> PathBuf {
+    crate::data_dir!(ctx).join("uploads")
 }
 
 /// The primary entrypoint.
+///
+/// Runs until a worker fails or `cancel` is cancelled.
+/// Workers stop at the next point at which they'd otherwise wait once cancelled,
+/// dropping any work in progress; jobs that weren't finished are redelivered when Broker next starts.
 #[tracing::instrument(skip_all, fields(subcommand = "run"))]
-pub async fn main<D: Database>(ctx: &AppContext, config: Config, db: D) -> Result<(), Error> {
-    let ctx = CmdContext {
-        app: ctx.clone(),
-        config,
-        db,
-    };
+pub async fn main<D: Database>(
+    ctx: &AppContext,
+    config: Config,
+    db: D,
+    cancel: CancellationToken,
+) -> Result<(), Error> {
+    // Held until this function returns, so that no other instance uses the data root while this one runs.
+    let _lock = InstanceLock::acquire(ctx.data_root())?;
+    let ctx = CmdContext::new(ctx, config, db, cancel);
+    canonicalize_repositories(&ctx).await;
+    for lint in config::lint(&ctx.config, Some(&ctx.db)).await {
+        warn!("Config: {lint}; {}", lint.help());
+    }
+    prepare_git(&ctx).await?;
+
+    // References which a previous run enqueued but didn't scan are enqueued again by the first poll.
+    match ctx.db.abandon_backlogs().await {
+        Ok(abandoned) if abandoned > 0 => {
+            info!("Restarting {abandoned} backlog(s) left in progress by a previous run")
+        }
+        Ok(_) => {}
+        Err(err) => warn!("Unable to reset backlogs: {err:#?}"),
+    }
+    forget_unconfigured_jobs(&ctx).await;
 
     for integration in ctx.config.integrations().iter() {
         if let Err(err) = remove_repository_scan_targets(&ctx.db, integration).await {
@@ -131,44 +433,437 @@ pub async fn main<D: Database>(ctx: &AppContext, config: Config, db: D) -> Resul
     }
 
     let preflight_checks = preflight_checks(&ctx);
-    let healthcheck_worker = healthcheck(&ctx.db);
+    let connectivity_worker = monitor_connectivity(&ctx);
+    let healthcheck_worker = healthcheck(&ctx);
+    let checkpoint_worker = checkpoint_database(&ctx);
+    let retention_worker = debug_retention(&ctx);
+    let temp_worker = prune_temporary_items(&ctx);
+    let cache_worker = prune_analysis_cache(&ctx);
+    let digest_worker = notification_digests(&ctx.notifier, &ctx.cancel);
+    let disk_worker = monitor_disk_space(&ctx);
+    let backlog_worker = report_backlogs(&ctx);
+    let http_worker = report_http_metrics(&ctx);
     let integration_worker = integrations(&ctx);
-    try_join!(preflight_checks, healthcheck_worker, integration_worker).discard_ok()
+    let admin_worker = admin::serve(&ctx);
+    try_join!(
+        preflight_checks,
+        connectivity_worker,
+        healthcheck_worker,
+        checkpoint_worker,
+        retention_worker,
+        temp_worker,
+        cache_worker,
+        digest_worker,
+        disk_worker,
+        backlog_worker,
+        http_worker,
+        integration_worker,
+        admin_worker
+    )
+    .discard_ok()
+}
+
+/// Poll each of the provided integrations once, then scan and upload each reference that needs it before returning.
+///
+/// Unlike [`main`], a failure doesn't stop other references from being scanned;
+/// failures are logged as they happen, and reported together at the end.
+#[tracing::instrument(skip_all, fields(subcommand = "scan"))]
+pub async fn scan_once<D: Database>(
+    ctx: &AppContext,
+    config: Config,
+    db: D,
+    integrations: &[Integration],
+    scan: ScanOnStartup,
+) -> Result<(), Error> {
+    // Like `broker run`, this uses the database and data root as if it owns them.
+    let _lock = InstanceLock::acquire(ctx.data_root())?;
+    let ctx = CmdContext::new(ctx, config, db, CancellationToken::new());
+    canonicalize_repositories(&ctx).await;
+    prepare_git(&ctx).await?;
+    let cli = fossa_cli::find_or_download(
+        &ctx.app,
+        ctx.config.fossa_cli(),
+        ctx.config.debug().location(),
+        DesiredVersion::Latest,
+    )
+    .await
+    .change_context(Error::DownloadFossaCli)
+    .describe("Broker relies on fossa-cli to perform analysis of your projects")?
+    .with_encryption(ctx.config.encryption().clone());
+
+    let mut scanned = 0;
+    let mut failed = 0;
+    for integration in integrations {
+        let started = Instant::now();
+        let references =
+            poll_references(&ctx.db, &ctx.clock, &ctx.mirrors, integration, scan).await;
+        let event = Event::new(Action::Poll, integration.remote());
+        ctx.audit.record(event, started, &references).await;
+        let references = match references {
+            Ok(references) => references,
+            Err(err) => {
+                warn!("Unable to poll '{integration}': {err:#?}");
+                failed += 1;
+                continue;
+            }
+        };
+
+        let (succeeded, errored) = scan_references(&ctx, &cli, integration, references).await;
+        scanned += succeeded;
+        failed += errored;
+    }
+
+    println!(
+        "Scanned {scanned} reference(s) across {} integration(s).",
+        integrations.len()
+    );
+    if failed > 0 {
+        return report!(Error::ScanFailed(failed))
+            .wrap_err()
+            .help("review the warnings logged above for details on each failure");
+    }
+    Ok(())
+}
+
+/// Scan and upload the provided references of an integration before returning,
+/// regardless of whether they changed since they were last scanned.
+///
+/// Like [`scan_once`], a failure doesn't stop other references from being scanned.
+#[tracing::instrument(skip_all, fields(subcommand = "backfill"))]
+pub async fn scan_references_once<D: Database>(
+    ctx: &AppContext,
+    config: Config,
+    db: D,
+    integration: &Integration,
+    references: Vec<Reference>,
+) -> Result<(), Error> {
+    // Like `broker run`, this uses the database and data root as if it owns them.
+    let _lock = InstanceLock::acquire(ctx.data_root())?;
+    let ctx = CmdContext::new(ctx, config, db, CancellationToken::new());
+    canonicalize_repositories(&ctx).await;
+    prepare_git(&ctx).await?;
+    let cli = fossa_cli::find_or_download(
+        &ctx.app,
+        ctx.config.fossa_cli(),
+        ctx.config.debug().location(),
+        DesiredVersion::Latest,
+    )
+    .await
+    .change_context(Error::DownloadFossaCli)
+    .describe("Broker relies on fossa-cli to perform analysis of your projects")?
+    .with_encryption(ctx.config.encryption().clone());
+
+    let (scanned, failed) = scan_references(&ctx, &cli, integration, references).await;
+    println!("Scanned {scanned} reference(s) of '{integration}'.");
+    if failed > 0 {
+        return report!(Error::ScanFailed(failed))
+            .wrap_err()
+            .help("review the warnings logged above for details on each failure");
+    }
+    Ok(())
+}
+
+/// The FOSSA CLI version reported for uploads of simulated scans, which don't run FOSSA CLI.
+const SIMULATED_CLI_VERSION: semver::Version = semver::Version::new(0, 0, 0);
+
+/// Replay recorded polls of the provided integrations through the pipeline, then return.
+///
+/// Instead of polling each integration, the references listed by each of its recorded polls are replayed in order;
+/// instead of cloning and analyzing each reference, the recorded source units for its revision are uploaded.
+/// Everything in between, like filtering to the references which changed and uploading to each FOSSA target,
+/// is the same as [`main`]. Like [`scan_once`], a failure doesn't stop other references from being scanned.
+#[tracing::instrument(skip_all, fields(subcommand = "simulate"))]
+pub async fn simulate<D: Database>(
+    ctx: &AppContext,
+    config: Config,
+    db: D,
+    polls: &[(Integration, Vec<Vec<Reference>>)],
+    source_units: &BTreeMap<String, SourceUnits>,
+) -> Result<(), Error> {
+    let ctx = CmdContext::new(ctx, config, db, CancellationToken::new());
+    let cli = CliMetadata::new(SIMULATED_CLI_VERSION.into());
+
+    let mut scanned = 0;
+    let mut failed = 0;
+    for (integration, recorded) in polls {
+        // The first poll after startup may be configured to scan differently than subsequent polls.
+        let mut scan = integration.scan_on_startup();
+        for (poll, references) in recorded.iter().enumerate() {
+            info!(
+                "Replaying poll {} of {} for '{integration}'",
+                poll + 1,
+                recorded.len()
+            );
+            let references = references.clone();
+            let references = match filter_references(&ctx.db, integration, references, scan).await {
+                Ok(references) => references,
+                Err(err) => {
+                    warn!("Unable to poll '{integration}': {err:#?}");
+                    failed += 1;
+                    continue;
+                }
+            };
+            scan = ScanOnStartup::Changed;
+
+            for reference in references {
+                let job = ScanGitVCSReference::new(integration, &reference);
+                match simulate_scan(&ctx, &cli, job, source_units).await {
+                    Ok(_) => scanned += 1,
+                    Err(err) => {
+                        warn!("Unable to scan '{integration}' at '{reference}': {err:#?}");
+                        failed += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    println!(
+        "Simulated {scanned} scan(s) across {} integration(s).",
+        polls.len()
+    );
+    if failed > 0 {
+        return report!(Error::ScanFailed(failed))
+            .wrap_err()
+            .help("review the warnings logged above for details on each failure");
+    }
+    Ok(())
+}
+
+/// Upload the recorded source units for the reference as though it had been scanned, then follow up on the upload.
+async fn simulate_scan<D: Database>(
+    ctx: &CmdContext<D>,
+    cli: &CliMetadata,
+    job: ScanGitVCSReference,
+    source_units: &BTreeMap<String, SourceUnits>,
+) -> Result<(), Error> {
+    let revision = job.reference.revision();
+    let Some(source_units) = source_units.get(revision).cloned() else {
+        return report!(Error::RunFossaCli)
+            .wrap_err()
+            .describe_lazy(|| format!("no recorded source units for revision '{revision}'"))
+            .help("add the output of 'fossa analyze --output' for the revision to the fixtures");
+    };
+
+    info!(
+        "Simulated scan of '{}' at '{}', uploading",
+        job.integration, job.reference
+    );
+    let upload = UploadSourceUnits {
+        scan_id: job.scan_id,
+        integration: job.integration,
+        reference: job.reference,
+        cli: cli.clone(),
+        source_units,
+        contributors: None,
+        rewritten_from: None,
+    };
+    let meta = ProjectMetadata::new(&upload.integration, &upload.reference);
+    let uploaded = execute_upload_scans(ctx, &meta, &upload).await?;
+    if follows_up(ctx) {
+        execute_follow_up(ctx, &FollowUp::new(upload, uploaded)).await;
+    }
+    Ok(())
+}
+
+/// Scan and upload each of the references in turn, returning how many succeeded and how many failed.
+async fn scan_references<D: Database>(
+    ctx: &CmdContext<D>,
+    cli: &Location,
+    integration: &Integration,
+    references: Vec<Reference>,
+) -> (usize, usize) {
+    let mut scanned = 0;
+    let mut failed = 0;
+
+    // Uploads are rate limited per integration, the same as when running.
+    let limiter = RateLimiter::direct(Quota::per_minute(nonzero!(1u32)));
+    for reference in references {
+        let job = ScanGitVCSReference::new(integration, &reference);
+        let result = match scan_git_reference(ctx, &job, cli).await {
+            Ok(Some(upload)) => {
+                let meta = ProjectMetadata::new(&upload.integration, &upload.reference);
+                if limiter.check().is_err() {
+                    info!("Integration '{meta}': waiting for rate limit");
+                    limiter.until_ready().await;
+                }
+                match execute_upload_scans(ctx, &meta, &upload).await {
+                    Ok(uploaded) if follows_up(ctx) => {
+                        execute_follow_up(ctx, &FollowUp::new(upload, uploaded)).await;
+                        Ok(())
+                    }
+                    uploaded => uploaded.discard_ok(),
+                }
+            }
+            Ok(None) => mark_scanned(ctx, integration, &reference).await,
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(_) => scanned += 1,
+            Err(err) => {
+                warn!("Unable to scan '{integration}' at '{reference}': {err:#?}");
+                failed += 1;
+            }
+        }
+    }
+    (scanned, failed)
+}
+
+/// Ensure that git is available and recent enough for the configured git integrations,
+/// downloading portable git if it's configured and the system git is missing or too old.
+///
+/// With the native backend, git is only run for the operations the built in client can't perform,
+/// so a missing or outdated git is logged instead of stopping Broker.
+async fn prepare_git<D>(ctx: &CmdContext<D>) -> Result<(), Error> {
+    let uses_git = ctx
+        .config
+        .integrations()
+        .iter()
+        .any(|integration| matches!(integration.protocol(), Protocol::Git(_)));
+    if !uses_git {
+        return Ok(());
+    }
+
+    let prepared = git::executable::prepare(&ctx.app, ctx.config.portable_git().as_ref()).await;
+    match prepared {
+        Ok(version) => {
+            info!(%version, program = %git::executable::program().display(), "Using git");
+            Ok(())
+        }
+        Err(err)
+            if crypto::git_backend(crypto::mode(), *ctx.config.git_backend())
+                == git::Backend::Native =>
+        {
+            warn!("No suitable git executable; operations the built in git client can't perform will fail: {err:#?}");
+            Ok(())
+        }
+        Err(err) => err.change_context(Error::Git).wrap_err().describe_lazy(|| {
+            format!(
+                "Broker requires git {} or later",
+                git::executable::MINIMUM_VERSION
+            )
+        }),
+    }
 }
 
 /// Checks and catches network misconfigurations before Broker attempts its operations
+///
+/// Connections to integrations aren't checked here, so that a slow code host doesn't hold up the others;
+/// see [`monitor_connectivity`].
 async fn preflight_checks<D: Database>(ctx: &CmdContext<D>) -> Result<(), Error> {
-    let check_integration_connections = check_integration_connections(ctx.config.integrations());
-    let check_fossa_connection = check_fossa_connection(&ctx.config);
-    try_join!(check_integration_connections, check_fossa_connection)
-        .discard_ok()
+    check_fossa_connection(ctx.fossa_client(), &ctx.config)
+        .await
         .change_context(Error::PreflightChecks)
 }
 
+/// How often Broker checks whether it can connect to the code host of each integration.
+const CONNECTIVITY_CHECK_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+/// Continuously check whether Broker can connect to the code host of each integration,
+/// recording the results so that they're shown by `broker status` and the admin API.
+///
+/// Each integration is checked on its own, and checks never hold up polling or scanning:
+/// integrations that can't be reached are logged and checked again later, and don't stop Broker.
 #[tracing::instrument(skip_all)]
-/// Check that Broker can connect to at least one integration
-async fn check_integration_connections(integrations: &Integrations) -> Result<(), Error> {
-    if integrations.as_ref().is_empty() {
-        return Ok(());
+async fn monitor_connectivity<D: Database>(ctx: &CmdContext<D>) -> Result<(), Error> {
+    let integrations = expanded_integrations(&ctx.config).await;
+    let monitors = integrations
+        .iter()
+        .map(|integration| monitor_integration_connectivity(ctx, integration));
+    join_all(monitors).await;
+    Ok(())
+}
+
+/// Check whether Broker can connect to the code host of the integration every [`CONNECTIVITY_CHECK_PERIOD`],
+/// until cancelled.
+///
+/// Integrations reached more recently than that, for example by a poll, aren't checked again until it elapses,
+/// and paused integrations aren't checked at all.
+async fn monitor_integration_connectivity<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+) {
+    let mut reachable = None;
+    loop {
+        if !is_paused(ctx, integration).await && !recently_reached(ctx, integration).await {
+            let checked = integration.protocol().check_connection();
+            let Some(checked) = ctx.cancel.run_until_cancelled(checked).await else {
+                return;
+            };
+            let reached = checked.is_ok();
+            record_connectivity(ctx, integration, reached).await;
+            match (reachable, checked) {
+                (Some(false), Ok(())) => info!("Connected to '{integration}' again"),
+                (_, Ok(())) => debug!("Connected to '{integration}'"),
+                (Some(false), Err(_)) => {
+                    debug!("Still unable to connect to '{integration}'")
+                }
+                (_, Err(err)) => warn!(
+                    "Unable to connect to '{integration}'; it's checked again in {CONNECTIVITY_CHECK_PERIOD:?}, and 'broker fix' explains failing integration connections in detail: {err:#?}"
+                ),
+            }
+            reachable = Some(reached);
+        }
+
+        if !ctx.sleep(CONNECTIVITY_CHECK_PERIOD).await {
+            return;
+        }
     }
+}
 
-    for integration in integrations.iter() {
-        let Protocol::Git(transport) = integration.protocol();
-        if repository::ls_remote(transport).await.is_ok() {
-            return Ok(());
+/// Whether Broker connected to the code host of the integration within the last [`CONNECTIVITY_CHECK_PERIOD`].
+///
+/// Failing to check isn't fatal: the integration is treated as not recently reached, so it's checked again.
+async fn recently_reached<D: Database>(ctx: &CmdContext<D>, integration: &Integration) -> bool {
+    let checked = ctx
+        .db
+        .connectivity(&integration.namespace(), &integration.repository())
+        .await;
+    match checked {
+        Ok(Some(checked)) if checked.reachable() => ctx
+            .clock
+            .now()
+            .duration_since(checked.checked_at())
+            .map(|age| age < CONNECTIVITY_CHECK_PERIOD)
+            .unwrap_or(true),
+        Ok(_) => false,
+        Err(err) => {
+            warn!("Unable to read connectivity of '{integration}': {err:#?}");
+            false
         }
     }
+}
 
-    report!(Error::IntegrationConnection)
-        .wrap_err()
-        .help("run broker fix for detailed explanation on failing integration connections")
-        .describe("integration connections")
+/// Record whether Broker could connect to the code host of the integration, so that it's visible from outside the running process.
+///
+/// Failing to record connectivity doesn't affect scanning, so it's only logged.
+async fn record_connectivity<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+    reachable: bool,
+) {
+    let recorded = ctx
+        .db
+        .record_connectivity(
+            &integration.namespace(),
+            &integration.repository(),
+            reachable,
+            ctx.clock.now(),
+        )
+        .await;
+    if let Err(err) = recorded {
+        warn!("Unable to record connectivity of '{integration}': {err:#?}");
+    }
 }
 
 #[tracing::instrument(skip_all)]
 /// Check that Broker can connect to FOSSA
-async fn check_fossa_connection(config: &Config) -> Result<(), Error> {
-    match fossa::OrgConfig::lookup(config.fossa_api()).await {
+async fn check_fossa_connection(
+    client: &http::client::Client,
+    config: &Config,
+) -> Result<(), Error> {
+    match fossa::OrgConfig::lookup(client, config.fossa_api()).await {
         Ok(_) => Ok(()),
         Err(err) => err
             .change_context(Error::FossaConnection)
@@ -178,12 +873,22 @@ async fn check_fossa_connection(config: &Config) -> Result<(), Error> {
     }
 }
 
+/// Move state stored by older versions of Broker to the canonical repository name of each integration.
+///
+/// If this fails, affected references are scanned again; that's not worth stopping Broker over.
+async fn canonicalize_repositories<D: Database>(ctx: &CmdContext<D>) {
+    let integrations = Integrations::new(expanded_integrations(&ctx.config).await);
+    if let Err(err) = crate::cmd::db::canonicalize_repositories(&ctx.db, &integrations).await {
+        warn!("Unable to move stored state to canonical repository names: {err:#?}");
+    }
+}
+
 #[tracing::instrument(skip_all)]
 async fn remove_repository_scan_targets<D: Database>(
     db: &D,
     integration: &Integration,
 ) -> Result<(), Error> {
-    let repository = integration.remote().for_coordinate();
+    let repository = integration.repository();
     let import_branches = integration.import_branches();
     let import_tags = integration.import_tags();
 
@@ -203,367 +908,3056 @@ async fn remove_repository_scan_targets<D: Database>(
 
 /// Conduct internal diagnostics to ensure Broker is still in a good state.
 #[tracing::instrument(skip_all)]
-async fn healthcheck<D: Database>(db: &D) -> Result<(), Error> {
+async fn healthcheck<D: Database>(ctx: &CmdContext<D>) -> Result<(), Error> {
     let period = Duration::from_secs(60);
-    for _ in 0.. {
-        db.healthcheck()
+    loop {
+        ctx.db
+            .healthcheck()
             .await
             .tap_ok(|_| debug!("db healtheck ok"))
             .change_context(Error::Healthcheck)
             .describe("Broker periodically runs internal healthchecks to validate that it is still in a good state")
             .help("this health check failing may have been related to a temporary condition, restarting Broker may resolve the issue")?;
 
-        tokio::time::sleep(period).await;
+        if !ctx.sleep(period).await {
+            return Ok(());
+        }
     }
-
-    Ok(())
-}
-
-/// Job for scanning git vcs
-#[derive(Debug, Deserialize, Serialize)]
-struct ScanGitVCSReference {
-    scan_id: String,
-    integration: Integration,
-    reference: Reference,
 }
 
-impl ScanGitVCSReference {
-    fn new(integration: &Integration, reference: &Reference) -> Self {
-        Self {
-            scan_id: Uuid::new_v4().to_string(),
-            integration: integration.to_owned(),
-            reference: reference.to_owned(),
+/// Periodically checkpoint the database's write-ahead log, so that it doesn't grow without bound
+/// while scans keep the database busy.
+///
+/// Failing to checkpoint isn't fatal: it's logged and attempted again next period.
+#[tracing::instrument(skip_all)]
+async fn checkpoint_database<D: Database>(ctx: &CmdContext<D>) -> Result<(), Error> {
+    let period = ctx.config.database().checkpoint_interval();
+    if period.is_zero() {
+        return Ok(());
+    }
+    loop {
+        if !ctx.sleep(period).await {
+            return Ok(());
+        }
+        match ctx.db.checkpoint().await {
+            Ok(()) => debug!("db checkpoint ok"),
+            Err(err) => warn!("Unable to checkpoint the database: {err:#?}"),
         }
     }
 }
 
-/// Job for uploading a scan
-#[derive(Debug, Deserialize, Serialize)]
-struct UploadSourceUnits {
-    scan_id: String,
-    integration: Integration,
-    reference: Reference,
-    cli: CliMetadata,
-    source_units: SourceUnits,
+/// Periodically clean up debug artifacts which are not rotated as they are written.
+///
+/// Failing to clean up debug artifacts isn't fatal: it's logged and attempted again next period.
+#[tracing::instrument(skip_all)]
+async fn debug_retention<D>(ctx: &CmdContext<D>) -> Result<(), Error> {
+    let period = Duration::from_secs(60 * 60);
+    let retention = ctx.config.debug().retention().cli_bundles();
+    loop {
+        // Walking and removing bundles blocks, so it runs in the background to not stall other workers.
+        let now = ctx.clock.now();
+        let root = ctx.config.debug().location().clone();
+        let enforced = io::spawn_blocking(move || {
+            debug::retention::enforce_cli_bundles(&root, retention, now)
+        })
+        .await;
+        match enforced {
+            Ok(summary) if summary.removed() > 0 => info!(
+                "Removed {} FOSSA CLI debug bundles, reclaiming {}",
+                summary.removed(),
+                summary.reclaimed()
+            ),
+            Ok(_) => debug!("No FOSSA CLI debug bundles needed to be removed"),
+            Err(err) => warn!("Unable to enforce retention on FOSSA CLI debug bundles: {err:#?}"),
+        }
+
+        if !ctx.sleep(period).await {
+            return Ok(());
+        }
+    }
 }
 
-/// Manage the lifecycle of all integrations.
-async fn integrations<D: Database>(ctx: &CmdContext<D>) -> Result<(), Error> {
-    // Each integration is configured with a poll interval.
-    // Rather than have one big poll loop that has to track polling times for each integration,
-    // just create a task per integration; they're cheap.
-    let integration_workers = ctx
-        .config
-        .integrations()
-        .iter()
-        .map(|conf| async { integration(ctx, conf).await });
+/// Remove temporary items (such as clones) left behind by instances of Broker that are no longer running,
+/// for example if Broker was killed in the middle of a scan.
+///
+/// This runs at startup, and then periodically afterwards.
+/// Failing to clean up temporary items isn't fatal: it's logged and attempted again next period.
+#[tracing::instrument(skip_all)]
+async fn prune_temporary_items<D>(ctx: &CmdContext<D>) -> Result<(), Error> {
+    let period = Duration::from_secs(60 * 60);
+    loop {
+        let now = ctx.clock.now();
+        let pruned = io::spawn_blocking_wrap(move || tempfile::prune_orphaned(now)).await;
+        match pruned {
+            Ok(summary) if summary.removed() > 0 => info!(
+                "Removed {} orphaned temporary items, reclaiming {}",
+                summary.removed(),
+                summary.reclaimed()
+            ),
+            Ok(_) => debug!("No orphaned temporary items needed to be removed"),
+            Err(err) => warn!("Unable to remove orphaned temporary items: {err:#?}"),
+        }
 
-    // Run all the workers in parallel. If one errors, return that error and drop the rest.
-    try_join_all(integration_workers).await.discard_ok()
+        if !ctx.sleep(period).await {
+            return Ok(());
+        }
+    }
 }
 
-/// Manage the lifecycle of an integration.
-async fn integration<D: Database>(
-    ctx: &CmdContext<D>,
-    integration: &Integration,
+/// Periodically remove cached results of analysis once they've expired.
+///
+/// This runs at startup, and then periodically afterwards.
+/// Failing to remove expired entries isn't fatal: it's logged and attempted again next period.
+#[tracing::instrument(skip_all)]
+async fn prune_analysis_cache<D>(ctx: &CmdContext<D>) -> Result<(), Error> {
+    let period = Duration::from_secs(60 * 60);
+    loop {
+        let now = ctx.clock.now();
+        let root = ctx.analysis_cache.clone();
+        let pruned = io::spawn_blocking(move || cache::prune(&root, now)).await;
+        match pruned {
+            Ok(removed) if removed > 0 => info!("Removed {removed} expired cached analyses"),
+            Ok(_) => debug!("No cached analyses needed to be removed"),
+            Err(err) => warn!("Unable to remove expired cached analyses: {err:#?}"),
+        }
+
+        if !ctx.sleep(period).await {
+            return Ok(());
+        }
+    }
+}
+
+/// Periodically send digests of failures to the notification sinks which collect them.
+#[tracing::instrument(skip_all)]
+async fn notification_digests(
+    notifier: &Notifier,
+    cancel: &CancellationToken,
 ) -> Result<(), Error> {
-    // Queues are per-integration.
-    //
-    // The reason for having the queues in the first place is basically to allow for polls, scans, and uploads
-    // to operate concurrently with some buffer space.
+    cancel.run_until_cancelled(notifier.run_digests()).await;
+    Ok(())
+}
+
+/// How often free disk space is checked.
+const DISK_SPACE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Periodically check free disk space, notifying when it drops below the configured minimum.
+///
+/// This checks at startup, before any scans begin, and then periodically afterwards.
+/// While space is low, scan workers wait instead of starting new scans.
+/// Low disk space isn't fatal, since Broker continues once space is freed.
+#[tracing::instrument(skip_all)]
+async fn monitor_disk_space<D>(ctx: &CmdContext<D>) -> Result<(), Error> {
+    let min_free = ctx.disk.min_free();
+    loop {
+        let was_low = ctx.disk.is_low();
+        let shortages = ctx.disk.check();
+        if shortages.is_empty() && was_low {
+            info!("Free disk space is above {min_free} again; resuming scans");
+        }
+
+        // Only notify when space becomes low, not every period that it stays low.
+        if !was_low {
+            for shortage in &shortages {
+                let path = shortage.path().display();
+                let message = format!(
+                    "only {} free, below the minimum of {min_free}; new scans are paused until space is freed",
+                    shortage.available()
+                );
+                warn!("Low disk space at '{path}': {message}");
+                let event = notify::Event::new(notify::Kind::LowDiskSpace, path, message);
+                ctx.notifier.notify(event).await;
+            }
+        }
+
+        if !ctx.sleep(DISK_SPACE_PERIOD).await {
+            return Ok(());
+        }
+    }
+}
+
+/// How often progress through backlogs is logged.
+const BACKLOG_REPORT_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+/// Periodically log progress through the backlog of each integration with references waiting to be scanned.
+///
+/// This is most useful when Broker first imports many repositories,
+/// which can take a long time; `broker status` shows the same progress on demand.
+/// Failing to read progress isn't fatal: it's logged and attempted again next period.
+#[tracing::instrument(skip_all)]
+async fn report_backlogs<D: Database>(ctx: &CmdContext<D>) -> Result<(), Error> {
+    loop {
+        if !ctx.sleep(BACKLOG_REPORT_PERIOD).await {
+            return Ok(());
+        }
+
+        for integration in expanded_integrations(&ctx.config).await.iter() {
+            let backlog = ctx
+                .db
+                .backlog(&integration.namespace(), &integration.repository())
+                .await;
+            match backlog {
+                Ok(Some(backlog)) if !backlog.is_complete() => info!(
+                    "Backlog for '{integration}': scanned {} of {} references ({:.0}%)",
+                    backlog.completed(),
+                    backlog.total(),
+                    backlog.percent_complete(),
+                ),
+                Ok(_) => {}
+                Err(err) => warn!("Unable to read backlog for '{integration}': {err:#?}"),
+            }
+        }
+    }
+}
+
+/// How often outbound request metrics are logged.
+const HTTP_METRICS_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Periodically log the number of outbound requests, failures, and their latency for each purpose and host.
+///
+/// Metrics are cumulative since Broker started, and are logged at debug level
+/// so that they're included in debug bundles without cluttering the console.
+#[tracing::instrument(skip_all)]
+async fn report_http_metrics<D>(ctx: &CmdContext<D>) -> Result<(), Error> {
+    loop {
+        if !ctx.sleep(HTTP_METRICS_PERIOD).await {
+            return Ok(());
+        }
+
+        for ((purpose, host), stats) in ctx.app.http().metrics().snapshot() {
+            debug!(
+                %purpose,
+                %host,
+                requests = stats.requests(),
+                failures = stats.failures(),
+                mean_latency_ms = stats.mean_latency().unwrap_or_default().as_millis(),
+                max_latency_ms = stats.max_latency().as_millis(),
+                "Outbound HTTP requests"
+            );
+        }
+    }
+}
+
+/// Wait until there's enough free disk space to start a scan.
+///
+/// Returns `false` if cancelled while waiting.
+async fn wait_for_disk_space<D>(ctx: &CmdContext<D>) -> bool {
+    while ctx.disk.is_low() {
+        debug!("Waiting for free disk space before starting a scan");
+        if !ctx.sleep(DISK_SPACE_PERIOD).await {
+            return false;
+        }
+    }
+    true
+}
+
+/// Job for scanning git vcs
+#[derive(Debug, Deserialize, Serialize)]
+struct ScanGitVCSReference {
+    scan_id: String,
+    integration: Integration,
+    reference: Reference,
+
+    /// Other references at the same commit as `reference`, which aren't scanned themselves;
+    /// instead the results of this scan are uploaded for each of them.
+    /// Jobs enqueued before references were batched don't have this field.
+    #[serde(default)]
+    batched: Vec<BatchedReference>,
+}
+
+/// A reference uploaded with the results of scanning another reference at the same commit.
+#[derive(Debug, Deserialize, Serialize)]
+struct BatchedReference {
+    scan_id: String,
+    reference: Reference,
+}
+
+impl BatchedReference {
+    fn new(reference: Reference) -> Self {
+        Self {
+            scan_id: Uuid::new_v4().to_string(),
+            reference,
+        }
+    }
+}
+
+impl ScanGitVCSReference {
+    fn new(integration: &Integration, reference: &Reference) -> Self {
+        Self {
+            scan_id: Uuid::new_v4().to_string(),
+            integration: integration.to_owned(),
+            reference: reference.to_owned(),
+            batched: Vec::new(),
+        }
+    }
+
+    /// Scan the first reference in the batch, uploading the results for every reference in the batch.
+    ///
+    /// Batches are never empty, as constructed by [`batch_by_commit`].
+    fn for_batch(integration: &Integration, batch: Vec<Reference>) -> Option<Self> {
+        let mut batch = batch.into_iter();
+        let reference = batch.next()?;
+        Some(Self {
+            scan_id: Uuid::new_v4().to_string(),
+            integration: integration.to_owned(),
+            reference,
+            batched: batch.map(BatchedReference::new).collect(),
+        })
+    }
+
+    /// The scan ID and reference of every reference whose results come from this scan,
+    /// starting with the reference that is scanned.
+    fn references(&self) -> impl Iterator<Item = (&str, &Reference)> {
+        std::iter::once((self.scan_id.as_str(), &self.reference)).chain(
+            self.batched
+                .iter()
+                .map(|batched| (batched.scan_id.as_str(), &batched.reference)),
+        )
+    }
+}
+
+/// Group references which point at the same commit, so that each commit is scanned only once per poll;
+/// release and hotfix tags, for example, often point at the same commit as a branch or each other.
+///
+/// Batches are in the order in which their first reference was listed, and branches come before tags
+/// within a batch so that the scanned reference is a branch if possible.
+/// References which don't point at a commit are each in a batch of their own.
+fn batch_by_commit(references: Vec<Reference>) -> Vec<Vec<Reference>> {
+    let mut batches: Vec<Vec<Reference>> = Vec::new();
+    let mut by_commit = HashMap::new();
+    for reference in references {
+        let Some(commit) = reference.commit().map(str::to_string) else {
+            batches.push(vec![reference]);
+            continue;
+        };
+        match by_commit.entry(commit) {
+            Entry::Occupied(entry) => batches[*entry.get()].push(reference),
+            Entry::Vacant(entry) => {
+                entry.insert(batches.len());
+                batches.push(vec![reference]);
+            }
+        }
+    }
+
+    for batch in batches.iter_mut() {
+        batch.sort_by_key(|reference| !reference.is_branch());
+    }
+    batches
+}
+
+/// Job for uploading a scan
+#[derive(Debug, Deserialize, Serialize)]
+struct UploadSourceUnits {
+    scan_id: String,
+    integration: Integration,
+    reference: Reference,
+    cli: CliMetadata,
+    source_units: SourceUnits,
+
+    /// Uploads saved before contributors were collected don't have this field.
+    #[serde(default)]
+    contributors: Option<Contributors>,
+
+    /// The commit the branch pointed at before it was rewritten, if it was rewritten since it was last scanned.
+    /// Uploads saved before rewrites were detected don't have this field.
+    #[serde(default)]
+    rewritten_from: Option<String>,
+}
+
+impl UploadSourceUnits {
+    /// The same results, uploaded for another reference at the same commit.
+    ///
+    /// Rewrites are only detected for the reference that was scanned, so the other reference isn't annotated.
+    fn for_reference(&self, scan_id: &str, reference: &Reference) -> Self {
+        Self {
+            scan_id: scan_id.to_string(),
+            integration: self.integration.clone(),
+            reference: reference.clone(),
+            cli: self.cli.clone(),
+            source_units: self.source_units.clone(),
+            contributors: self.contributors.clone(),
+            rewritten_from: None,
+        }
+    }
+}
+
+/// Job for following up on an uploaded scan once FOSSA processes it:
+/// checking it for issues and exporting its SBOMs.
+#[derive(Debug, Deserialize, Serialize)]
+struct FollowUp {
+    scan_id: String,
+    integration: Integration,
+    reference: Reference,
+    locator: String,
+
+    /// The additional FOSSA endpoint which accepted the upload, or `None` for the primary endpoint.
+    /// Jobs enqueued before uploads could fan out don't have this field.
+    #[serde(default)]
+    target: Option<String>,
+}
+
+impl FollowUp {
+    fn new(upload: UploadSourceUnits, uploaded: Uploaded) -> Self {
+        Self {
+            scan_id: upload.scan_id,
+            integration: upload.integration,
+            reference: upload.reference,
+            locator: uploaded.locator,
+            target: uploaded.target,
+        }
+    }
+
+    /// Redeliver the follow up recorded for the integration.
+    fn redelivered(scan_id: String, integration: Integration, record: FollowUpRecord) -> Self {
+        Self {
+            scan_id,
+            integration,
+            reference: record.reference,
+            locator: record.locator,
+            target: record.target,
+        }
+    }
+}
+
+/// The part of a [`FollowUp`] recorded in the database so that it can be redelivered.
+///
+/// The integration is found in the config when the follow up is redelivered,
+/// so that its credentials aren't written to the database.
+#[derive(Debug, Deserialize, Serialize)]
+struct FollowUpRecord {
+    reference: Reference,
+    locator: String,
+    target: Option<String>,
+}
+
+impl From<&FollowUp> for FollowUpRecord {
+    fn from(follow_up: &FollowUp) -> Self {
+        Self {
+            reference: follow_up.reference.clone(),
+            locator: follow_up.locator.clone(),
+            target: follow_up.target.clone(),
+        }
+    }
+}
+
+/// A scan which was uploaded to FOSSA.
+#[derive(Debug)]
+struct Uploaded {
+    /// The locator of the uploaded project revision.
+    locator: String,
+
+    /// The additional FOSSA endpoint which accepted the upload, or `None` for the primary endpoint.
+    target: Option<String>,
+}
+
+/// Manage the lifecycle of all integrations.
+async fn integrations<D: Database>(ctx: &CmdContext<D>) -> Result<(), Error> {
+    let integrations = ctx.config.integrations().iter().collect_vec();
+
+    // Scan jobs are sent into a lane per integration, and scan workers are shared by all integrations.
+    // The scheduler interleaves jobs from each lane according to the integration's weight,
+    // so an integration with many changes can't starve the others.
     //
+    // Lanes are backpressured, so if an integration's lane fills up then its poll will wait.
+    let scheduler = Scheduler::new(
+        integrations
+            .iter()
+            .map(|integration| integration.scan_weight().as_nonzero()),
+    );
+
     // The upload queue is small since this is per-integration
     // and contains (potentially) lots of data sitting around in memory.
     //
     // Queues are backpressured, so if the upload queue fills up then additional scans will wait.
-    let scan = Queue::default();
-    let upload = Queue::new(5);
+    let uploads = integrations.iter().map(|_| Queue::new(5)).collect_vec();
+
+    // Follow up jobs are small, and each may wait a long time for FOSSA,
+    // so they get their own queue rather than holding up uploads.
+    let follow_ups = integrations.iter().map(|_| Queue::default()).collect_vec();
+
+    // Each integration is configured with a poll interval.
+    // Rather than have one big poll loop that has to track polling times for each integration,
+    // just create a task per integration; they're cheap.
+    let integration_workers = integrations
+        .iter()
+        .zip(uploads.iter())
+        .zip(follow_ups.iter())
+        .enumerate()
+        .map(|(lane, ((conf, upload), follow_up))| {
+            integration(ctx, conf, scheduler.sender(lane), upload, follow_up)
+        });
+
+    // There are as many scan workers as integrations, so overall scan concurrency is unchanged
+    // from when each integration had its own scan worker.
+    let scan_workers = integrations
+        .iter()
+        .map(|_| scan_git_references(ctx, &scheduler, &uploads));
+
+    // Run all the workers in parallel. If one errors, return that error and drop the rest.
+    try_join!(
+        try_join_all(integration_workers),
+        try_join_all(scan_workers)
+    )
+    .discard_ok()
+}
+
+/// Manage the lifecycle of an integration.
+async fn integration<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+    scan: Sender<'_, ScanGitVCSReference>,
+    upload: &Queue<UploadSourceUnits>,
+    follow_up: &Queue<FollowUp>,
+) -> Result<(), Error> {
+    let upload_worker = upload_scans(ctx, upload, follow_up);
+    let follow_up_worker = follow_up_uploads(ctx, follow_up);
+
+    // Integrations which read their remotes from a file share their lane and queues
+    // with the integrations expanded from it, which are polled instead.
+    if let Some(path) = integration.remotes_from() {
+        let remotes_worker = expand_remotes(ctx, integration, path, &scan, upload, follow_up);
+        return try_join!(remotes_worker, upload_worker, follow_up_worker).discard_ok();
+    }
 
-    let poll_worker = poll_integration(&ctx.db, integration, &scan);
-    let scan_worker = scan_git_references(ctx, &scan, &upload);
-    let upload_worker = upload_scans(ctx, &upload);
+    let poll_worker = poll_integration(ctx, integration, &scan, follow_up);
+    let retry_worker = retry_uploads(ctx, integration, upload);
 
     // `try_join!` keeps all of the workers running until one of them fails,
     // at which point the failure is returned and remaining tasks are dropped.
     // It also returns all of their results as a tuple, which we don't care about,
     // so we discard that value.
-    try_join!(poll_worker, scan_worker, upload_worker).discard_ok()
+    try_join!(poll_worker, upload_worker, retry_worker, follow_up_worker).discard_ok()
+}
+
+/// How often the files listing the remotes of integrations are read again.
+const REMOTES_FROM_REFRESH_PERIOD: Duration = Duration::from_secs(60);
+
+/// Run an integration for each remote listed in the integration's `remotes_from` file.
+///
+/// The file is read again every [`REMOTES_FROM_REFRESH_PERIOD`]: integrations are started for remotes added to it
+/// and stopped for remotes removed from it. If the file can't be read, the current integrations keep running.
+#[tracing::instrument(skip(ctx, template, scan, upload, follow_up))]
+async fn expand_remotes<D: Database>(
+    ctx: &CmdContext<D>,
+    template: &Integration,
+    path: &Path,
+    scan: &Sender<'_, ScanGitVCSReference>,
+    upload: &Queue<UploadSourceUnits>,
+    follow_up: &Queue<FollowUp>,
+) -> Result<(), Error> {
+    let mut running = BTreeMap::<String, CancellationToken>::new();
+    let mut workers = FuturesUnordered::new();
+    loop {
+        match read_remotes(template, path).await {
+            Ok(integrations) => {
+                running.retain(|remote, stop| {
+                    let listed = integrations
+                        .iter()
+                        .any(|integration| integration.remote().to_string() == *remote);
+                    if !listed {
+                        info!(
+                            "Stopping '{remote}', which was removed from '{}'",
+                            path.display()
+                        );
+                        stop.cancel();
+                    }
+                    listed
+                });
+
+                for integration in integrations {
+                    let remote = integration.remote().to_string();
+                    if running.contains_key(&remote) {
+                        continue;
+                    }
+
+                    info!(
+                        "Starting '{remote}', which was added to '{}'",
+                        path.display()
+                    );
+                    let stop = CancellationToken::new();
+                    workers.push(expanded_integration(
+                        ctx,
+                        integration,
+                        scan,
+                        upload,
+                        follow_up,
+                        stop.clone(),
+                    ));
+                    running.insert(remote, stop);
+                }
+            }
+            Err(err) => warn!(
+                "Unable to read remotes for '{template}', keeping the current remotes: {err:#?}"
+            ),
+        }
+
+        // Run the expanded integrations until it's time to read the file again.
+        let refresh = ctx.sleep(REMOTES_FROM_REFRESH_PERIOD);
+        tokio::pin!(refresh);
+        loop {
+            tokio::select! {
+                refreshed = &mut refresh => {
+                    if !refreshed {
+                        return Ok(());
+                    }
+                    break;
+                }
+                Some(finished) = workers.next() => finished?,
+            }
+        }
+    }
+}
+
+/// Read the integrations for the remotes listed in the integration's `remotes_from` file.
+async fn read_remotes(template: &Integration, path: &Path) -> Result<Vec<Integration>, Error> {
+    tokio::fs::read_to_string(path)
+        .await
+        .context_lazy(|| Error::RemotesFrom(path.to_path_buf()))
+        .help("ensure the file exists and Broker can read it")
+        .map(|content| template.expand_remotes(&content))
+}
+
+/// Run an integration expanded from a `remotes_from` file until it's stopped.
+///
+/// Its scans are scheduled, uploaded, and followed up on by the workers of the integration it was expanded from,
+/// so only polls and upload retries are run here.
+async fn expanded_integration<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: Integration,
+    scan: &Sender<'_, ScanGitVCSReference>,
+    upload: &Queue<UploadSourceUnits>,
+    follow_up: &Queue<FollowUp>,
+    stop: CancellationToken,
+) -> Result<(), Error> {
+    let poll_worker = poll_integration(ctx, &integration, scan, follow_up);
+    let retry_worker = retry_uploads(ctx, &integration, upload);
+    let workers = async { try_join!(poll_worker, retry_worker).discard_ok() };
+    stop.run_until_cancelled(workers).await.unwrap_or(Ok(()))
+}
+
+/// The configured integrations, with those reading their remotes from a file
+/// replaced by the integrations for the remotes currently listed in it.
+///
+/// Files which can't be read are logged and contribute no integrations.
+pub(crate) async fn expanded_integrations(config: &Config) -> Vec<Integration> {
+    let mut expanded = Vec::new();
+    for integration in config.integrations().iter() {
+        let Some(path) = integration.remotes_from() else {
+            expanded.push(integration.clone());
+            continue;
+        };
+        match read_remotes(integration, path).await {
+            Ok(integrations) => expanded.extend(integrations),
+            Err(err) => warn!("Unable to read remotes for '{integration}': {err:#?}"),
+        }
+    }
+    expanded
+}
+
+#[tracing::instrument(skip(ctx, sender, follow_ups))]
+async fn poll_integration<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+    sender: &Sender<'_, ScanGitVCSReference>,
+    follow_ups: &Queue<FollowUp>,
+) -> Result<(), Error> {
+    let redelivered = redeliver_queued(ctx, integration, sender, follow_ups);
+    match ctx.cancel.run_until_cancelled(redelivered).await {
+        None => return Ok(()),
+        Some(Err(err)) => {
+            warn!("Unable to redeliver unfinished jobs for '{integration}': {err:#?}")
+        }
+        Some(Ok(_)) => {}
+    }
+
+    let poll_interval = integration.poll_interval().as_duration();
+    let mut requests = ctx.poll_requests.subscribe();
+
+    // Integrations are often configured with the same poll interval,
+    // so without jitter they'd all poll at the same moment every interval after startup.
+    let delay = poll_jitter(poll_interval);
+    info!("First poll for '{integration}' in {delay:?}");
+    if !ctx.wait_for_poll(integration, &mut requests, delay).await {
+        return Ok(());
+    }
+
+    // The first poll after startup may be configured to scan differently than subsequent polls.
+    let mut scan = integration.scan_on_startup();
+    loop {
+        if !ctx.wait_for_poll_window(integration).await {
+            return Ok(());
+        }
+
+        if is_paused(ctx, integration).await {
+            info!("Skipping poll of '{integration}', which is paused; checking again in {poll_interval:?}");
+            if !ctx
+                .wait_for_poll(integration, &mut requests, poll_interval)
+                .await
+            {
+                return Ok(());
+            }
+            continue;
+        }
+
+        // This poll satisfies any requests made while waiting for it.
+        requests = requests.resubscribe();
+
+        let started = Instant::now();
+        let polled = execute_poll_integration(ctx, integration, sender, scan);
+        let Some(polled) = ctx.cancel.run_until_cancelled(polled).await else {
+            return Ok(());
+        };
+        let event = Event::new(Action::Poll, integration.remote());
+        ctx.audit.record(event, started, &polled).await;
+        match polled {
+            // Once the first poll has succeeded, only changes need to be scanned.
+            Ok(_) => scan = ScanOnStartup::Changed,
+            Err(err) => {
+                warn!("Unable to poll '{integration}': {err:#?}");
+                let event = notify::Event::new(
+                    notify::Kind::PollFailure,
+                    integration.remote(),
+                    format!("{err:#}"),
+                );
+                ctx.notifier.notify(event).await;
+            }
+        }
+
+        // Now wait for the next poll time.
+        // The fact that we poll, _then_ wait for the poll time, means that the actual
+        // time at which the poll occurs will slowly creep forward by whatever time
+        // it takes to perform the poll.
+        //
+        // This is considered okay, because we're interpreting "poll interval" as
+        // "poll at most this often"; i.e. it's used primarily as a rate limiting feature
+        // than as a "track changes this often" feature.
+        //
+        // The alternative would be starting the clock at the top of the loop,
+        // which while doable could (in the worst case) allow for endlessly
+        // polling if the poll takes at least as long as the interval.
+        //
+        // If we decide to make polling more consistent, [`tokio::time::interval`]
+        // is most likely the correct way to implement it.
+        info!("Next poll interval for '{integration}' in {poll_interval:?}");
+        if !ctx
+            .wait_for_poll(integration, &mut requests, poll_interval)
+            .await
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// Whether the integration was paused with `broker pause`.
+///
+/// Failing to check isn't fatal: the integration is treated as not paused, as it would be without pauses.
+async fn is_paused<D: Database>(ctx: &CmdContext<D>, integration: &Integration) -> bool {
+    let paused = ctx
+        .db
+        .is_paused(&integration.namespace(), &integration.repository())
+        .await;
+    match paused {
+        Ok(paused) => paused,
+        Err(err) => {
+            warn!("Unable to check whether '{integration}' is paused: {err:#?}");
+            false
+        }
+    }
+}
+
+/// The maximum delay added to spread out polls of integrations.
+const MAX_POLL_JITTER: Duration = Duration::from_secs(10 * 60);
+
+/// Choose a random delay, up to the smaller of `bound` and [`MAX_POLL_JITTER`],
+/// used to spread out polls of integrations that would otherwise happen at the same moment.
+fn poll_jitter(bound: Duration) -> Duration {
+    let bound = bound.min(MAX_POLL_JITTER);
+    if bound.is_zero() {
+        return Duration::ZERO;
+    }
+    rand::thread_rng().gen_range(Duration::ZERO..bound)
+}
+
+#[tracing::instrument(skip_all)]
+async fn execute_poll_integration<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+    sender: &Sender<'_, ScanGitVCSReference>,
+    scan: ScanOnStartup,
+) -> Result<(), Error> {
+    // We sink the references only after they have all been filtered so that
+    // if an error is encountered reading state, we don't send partial lists.
+    let references = poll_references(&ctx.db, &ctx.clock, &ctx.mirrors, integration, scan).await?;
+    record_poll(ctx, integration).await;
+
+    // Listing the references proves that the code host can be reached, so it needn't be checked separately.
+    record_connectivity(ctx, integration, true).await;
+    let references = skip_queued(&ctx.db, integration, references).await;
+    let jobs = batch_by_commit(references)
+        .into_iter()
+        .filter_map(|batch| ScanGitVCSReference::for_batch(integration, batch))
+        .collect_vec();
+    if !jobs.is_empty() {
+        let count = u64::try_from(jobs.len()).unwrap_or(u64::MAX);
+        let enqueued = ctx
+            .db
+            .enqueue_backlog(
+                &integration.namespace(),
+                &integration.repository(),
+                count,
+                ctx.clock.now(),
+            )
+            .await;
+        if let Err(err) = enqueued {
+            warn!("Unable to record backlog for '{integration}': {err:#?}");
+        }
+    }
+    for job in jobs {
+        // Batched references are recorded individually, so that each is redelivered on its own
+        // if Broker stops before the scan is done.
+        for (scan_id, reference) in job.references() {
+            record_queued(
+                ctx,
+                scan_id,
+                integration,
+                reference,
+                JobStage::Scan,
+                reference,
+            )
+            .await;
+        }
+        sender.send(&job).await.change_context(Error::TaskEnqueue)?;
+
+        if job.batched.is_empty() {
+            info!(
+                "Enqueued task to scan '{integration}' at '{}'",
+                job.reference
+            );
+        } else {
+            info!(
+                "Enqueued task to scan '{integration}' at '{}', uploading the results for {} other reference(s) at the same commit",
+                job.reference,
+                job.batched.len()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Record when the integration was polled, so that it's visible from outside the running process.
+///
+/// Failing to record the poll doesn't affect scanning, so it's only logged.
+async fn record_poll<D: Database>(ctx: &CmdContext<D>, integration: &Integration) {
+    let recorded = ctx
+        .db
+        .record_poll(
+            &integration.namespace(),
+            &integration.repository(),
+            ctx.clock.now(),
+        )
+        .await;
+    if let Err(err) = recorded {
+        warn!("Unable to record poll of '{integration}': {err:#?}");
+    }
+}
+
+/// Filter out references which are already enqueued at their current state,
+/// for example because a job left unfinished by a previous run was redelivered.
+async fn skip_queued<D: Database>(
+    db: &D,
+    integration: &Integration,
+    references: Vec<Reference>,
+) -> Vec<Reference> {
+    let queued = match db.queued_jobs().await {
+        Ok(queued) => queued,
+        Err(err) => {
+            warn!("Unable to read enqueued jobs for '{integration}': {err:#?}");
+            return references;
+        }
+    };
+
+    references
+        .into_iter()
+        .filter(|reference| {
+            let coordinate = reference.as_coordinate(integration.remote());
+            let enqueued = queued.iter().any(|job| {
+                job.coordinate() == &coordinate && job.state().as_slice() == reference.as_state()
+            });
+            if enqueued {
+                debug!("Skipping '{integration}' at '{reference}', which is already enqueued");
+            }
+            !enqueued
+        })
+        .collect()
+}
+
+/// Enqueue the jobs for the integration which a previous run of Broker didn't finish,
+/// so that they're scanned or followed up on without waiting for the integration to be polled.
+///
+/// Scan results aren't kept across restarts, so jobs which were waiting to be uploaded are scanned again.
+#[tracing::instrument(skip_all)]
+async fn redeliver_queued<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+    sender: &Sender<'_, ScanGitVCSReference>,
+    follow_ups: &Queue<FollowUp>,
+) -> Result<(), Error> {
+    let (follow_up_jobs, jobs): (Vec<_>, Vec<_>) = ctx
+        .db
+        .queued_jobs()
+        .await
+        .change_context(Error::TaskEnqueue)
+        .describe_lazy(|| format!("read unfinished jobs for '{integration}'"))?
+        .into_iter()
+        .filter(|job| is_job_for(integration, job))
+        .partition(|job| job.stage() == JobStage::FollowUp);
+
+    for queued in follow_up_jobs {
+        let record = queued
+            .payload()
+            .as_deref()
+            .map(serde_json::from_slice::<FollowUpRecord>);
+        let Some(Ok(record)) = record else {
+            // The scan was already uploaded, so there's nothing left to redeliver it from.
+            warn!(
+                "Unable to redeliver follow up '{}' for '{integration}' at '{}', it won't be followed up on",
+                queued.scan_id(),
+                queued.reference()
+            );
+            forget_queued(&ctx.db, queued.scan_id()).await;
+            continue;
+        };
+
+        let follow_up =
+            FollowUp::redelivered(queued.scan_id().clone(), integration.to_owned(), record);
+        enqueue_follow_up(ctx, follow_ups, &follow_up).await?;
+        info!(
+            "Redelivered unfinished task to follow up on '{integration}' at '{}'",
+            follow_up.reference
+        );
+    }
+
+    if jobs.is_empty() {
+        return Ok(());
+    }
+
+    let count = u64::try_from(jobs.len()).unwrap_or(u64::MAX);
+    let enqueued = ctx
+        .db
+        .enqueue_backlog(
+            &integration.namespace(),
+            &integration.repository(),
+            count,
+            ctx.clock.now(),
+        )
+        .await;
+    if let Err(err) = enqueued {
+        warn!("Unable to record backlog for '{integration}': {err:#?}");
+    }
+
+    for queued in jobs {
+        let reference = queued
+            .payload()
+            .as_deref()
+            .map(serde_json::from_slice::<Reference>);
+        let Some(Ok(reference)) = reference else {
+            // Jobs which can't be redelivered are found again when the integration is polled.
+            warn!(
+                "Unable to redeliver job '{}' for '{integration}' at '{}', it will be enqueued again when polled",
+                queued.scan_id(),
+                queued.reference()
+            );
+            forget_queued(&ctx.db, queued.scan_id()).await;
+            continue;
+        };
+
+        // The previous run may have stopped after the job was done but before it was forgotten.
+        if is_scanned(ctx, integration, &reference).await {
+            debug!("Skipping unfinished task to scan '{integration}' at '{reference}', which is already scanned");
+            progress_backlog(ctx, integration).await;
+            forget_queued(&ctx.db, queued.scan_id()).await;
+            continue;
+        }
+
+        let job = ScanGitVCSReference {
+            scan_id: queued.scan_id().clone(),
+            integration: integration.to_owned(),
+            reference,
+            batched: Vec::new(),
+        };
+        record_queued(
+            ctx,
+            &job.scan_id,
+            integration,
+            &job.reference,
+            JobStage::Scan,
+            &job.reference,
+        )
+        .await;
+        sender.send(&job).await.change_context(Error::TaskEnqueue)?;
+
+        info!(
+            "Redelivered unfinished task to scan '{integration}' at '{}'",
+            job.reference
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether the recorded job belongs to the integration.
+fn is_job_for(integration: &Integration, job: &db::QueuedJob) -> bool {
+    let coordinate = job.coordinate();
+    coordinate.namespace() == &integration.namespace()
+        && coordinate.remote() == &integration.repository()
+}
+
+/// Forget the jobs a previous run didn't finish for integrations which are no longer configured,
+/// since they can't be redelivered.
+async fn forget_unconfigured_jobs<D: Database>(ctx: &CmdContext<D>) {
+    let jobs = match ctx.db.queued_jobs().await {
+        Ok(jobs) => jobs,
+        Err(err) => {
+            warn!("Unable to read jobs left unfinished by a previous run: {err:#?}");
+            return;
+        }
+    };
+
+    let integrations = expanded_integrations(&ctx.config).await;
+    for job in jobs {
+        let configured = integrations
+            .iter()
+            .any(|integration| is_job_for(integration, &job));
+        if !configured {
+            info!(
+                "Forgetting job '{}' for '{}', which is no longer configured",
+                job.scan_id(),
+                job.coordinate().remote()
+            );
+            forget_queued(&ctx.db, job.scan_id()).await;
+        }
+    }
+}
+
+/// Poll the integration for the references which need to be scanned.
+#[tracing::instrument(skip_all)]
+async fn poll_references<D: Database>(
+    db: &D,
+    clock: &Clock,
+    mirrors: &Path,
+    integration: &Integration,
+    scan: ScanOnStartup,
+) -> Result<Vec<Reference>, Error> {
+    // We use this in a few places and may send it across threads, so just clone it locally.
+    let remote = integration.remote().to_owned();
+
+    // [`Clock::retry`] needs a function that runs without any arguments to perform the retry, so turn the method into a closure.
+    let get_references = || async {
+        match integration.scanned_references().await {
+            Ok(success) => Ok(success),
+            Err(err) => {
+                warn!("Unable to poll integration at {remote}: {err:#}");
+                Err(err)
+            }
+        }
+    };
+
+    info!("Polling '{integration}'");
+
+    // Given that this operation is not latency sensitive, and temporary network issues can interfere,
+    // retry several times before permanently failing since a permanent failure means Broker shuts down
+    // entirely.
+    let strategy = ExponentialBackoff::from_millis(1000).map(jitter).take(10);
+    let references = clock
+            .retry(strategy, get_references)
+            .await
+            .change_context(Error::PollIntegration)
+            .describe_lazy(|| format!("poll for changes at {remote} in integration: {integration}"))
+            .help(indoc! {"
+            Issues with this process are usually related to network errors, but may be due to misconfiguration.
+            Each time this polling operation was attempted, it logged a warning; please review those
+            warnings in the logs for more details.
+            "})?;
+
+    let references = filter_references(db, integration, references, scan).await?;
+    if !references.is_empty() {
+        if let Err(err) = integration.update_mirror(mirrors).await {
+            // Not fatal: checking out a reference updates the mirror if it's missing the commit.
+            warn!("Unable to update mirror for '{integration}': {err:#?}");
+        }
+    }
+    Ok(references)
+}
+
+/// Filter the references listed by a poll of the integration to the ones which need to be scanned,
+/// recording what was seen so that the next poll only finds changes.
+async fn filter_references<D: Database>(
+    db: &D,
+    integration: &Integration,
+    references: Vec<Reference>,
+    scan: ScanOnStartup,
+) -> Result<Vec<Reference>, Error> {
+    let remote = integration.remote().to_owned();
+
+    // Filter to the list of references the integration is configured to scan.
+    // Polled references are filtered as they're listed, but recorded polls replayed by simulations aren't.
+    let references = references
+        .into_iter()
+        .filter(|reference| integration.scans_reference(reference.branch()))
+        .collect::<Vec<_>>();
+
+    // If the references are exactly the same as the last time every reference was already scanned,
+    // there's nothing new; skip checking each reference individually.
+    let hash = references_hash(&references);
+    let repository = integration.repository();
+    let last_hash = db
+        .references_hash(&integration.namespace(), &repository)
+        .await
+        .change_context(Error::PollIntegration)
+        .describe_lazy(|| {
+            format!("read last seen references for {remote} in integration: {integration}")
+        })?;
+    if scan == ScanOnStartup::Changed && last_hash.as_deref() == Some(hash.as_slice()) {
+        info!("No changes to '{integration}'");
+        return Ok(Vec::new());
+    }
+
+    // When configured not to scan at startup, record the current state of every reference as scanned
+    // so that only references which change from here on are scanned.
+    if scan == ScanOnStartup::None {
+        for reference in &references {
+            let coordinate = reference.as_coordinate(&remote);
+            db.set_state(&coordinate, reference.as_state(), &reference.is_branch())
+                .await
+                .change_context(Error::PollIntegration)
+                .describe_lazy(|| {
+                    format!("record current state of {reference} at {remote} in integration: {integration}")
+                })?;
+        }
+        db.set_references_hash(&integration.namespace(), &repository, &hash)
+            .await
+            .change_context(Error::PollIntegration)
+            .describe_lazy(|| {
+                format!("record last seen references for {remote} in integration: {integration}")
+            })?;
+
+        info!(
+            "Recorded {} references for '{integration}' without scanning them",
+            references.len()
+        );
+        return Ok(Vec::new());
+    }
+
+    // Filter to the list of references that are new since we last saw them.
+    // States are looked up in a single batch, since remotes may have tens of thousands of references.
+    let coordinates = references
+        .iter()
+        .map(|reference| reference.as_coordinate(&remote))
+        .collect_vec();
+    let states = db
+        .states_for(&coordinates)
+        .await
+        .change_context(Error::PollIntegration)
+        .describe_lazy(|| {
+            format!("filter to only changes at {remote} in integration: {integration}")
+        })
+        .help(indoc! {"
+        Problems at this stage are most likely caused by a database error.
+        Broker manages a local sqlite database; deleting it so it can be re-generated from scratch may resolve the issue.
+        "})?;
+    let states = adopt_legacy_states(db, &remote, &references, states)
+        .await
+        .change_context(Error::PollIntegration)
+        .describe_lazy(|| {
+            format!("carry over states recorded by earlier versions at {remote} in integration: {integration}")
+        })?;
+
+    let references = references
+        .into_iter()
+        .zip(states)
+        .filter(|(reference, db_state)| match db_state {
+            // Configured to scan everything, regardless of state.
+            _ if scan == ScanOnStartup::All => true,
+            // No previous state; this must be a new reference.
+            None => true,
+            // There was previous state, it's only new if the state is different.
+            // We're assuming "different state" always means "newer state".
+            // This is because state is currently expressed as a git commit string,
+            // which on its own doesn't have any form of ordering.
+            Some(db_state) => db_state != reference.as_state(),
+        })
+        .map(|(reference, _)| reference)
+        .collect_vec();
+
+    // Only once every reference has been scanned is it safe to skip checking them individually next time.
+    // Until then, references which haven't yet been scanned need to be found again on the next poll.
+    if references.is_empty() {
+        db.set_references_hash(&integration.namespace(), &repository, &hash)
+            .await
+            .change_context(Error::PollIntegration)
+            .describe_lazy(|| {
+                format!("record last seen references for {remote} in integration: {integration}")
+            })?;
+    }
+
+    if references.is_empty() {
+        info!("No changes to '{integration}'");
+    }
+    Ok(references)
+}
+
+/// Carry over the states of references which earlier versions of Broker recorded at a different coordinate,
+/// like annotated tags recorded at the ID of their tag object instead of the commit they point at,
+/// so that they aren't scanned again just because the way they're recorded changed.
+///
+/// Each carried over state is moved to the reference's current coordinate, and returned in place of its missing state.
+async fn adopt_legacy_states<D: Database>(
+    db: &D,
+    remote: &Remote,
+    references: &[Reference],
+    mut states: Vec<Option<Vec<u8>>>,
+) -> Result<Vec<Option<Vec<u8>>>, db::Error> {
+    let legacy = references
+        .iter()
+        .zip(&states)
+        .enumerate()
+        .filter(|(_, (_, state))| state.is_none())
+        .filter_map(|(index, (reference, _))| {
+            let (coordinate, state) = reference.legacy_coordinate(remote)?;
+            Some((index, coordinate, state))
+        })
+        .collect_vec();
+    if legacy.is_empty() {
+        return Ok(states);
+    }
+
+    let coordinates = legacy
+        .iter()
+        .map(|(_, coordinate, _)| coordinate.clone())
+        .collect_vec();
+    let recorded = db.states_for(&coordinates).await?;
+    for ((index, coordinate, state), recorded) in legacy.into_iter().zip(recorded) {
+        if recorded.as_deref() != Some(state) {
+            continue;
+        }
+
+        let reference = &references[index];
+        let current = reference.as_coordinate(remote);
+        db.set_state(&current, reference.as_state(), &reference.is_branch())
+            .await?;
+        db.delete_state(&coordinate).await?;
+        states[index] = Some(reference.as_state().to_vec());
+    }
+    Ok(states)
+}
+
+/// Hash the references an integration is configured to scan along with their current state,
+/// in a form that doesn't depend on the order in which the remote listed them.
+fn references_hash(references: &[Reference]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for reference in references.iter().map(Reference::to_string).sorted() {
+        hasher.update(reference.as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher.finalize().to_vec()
+}
+
+#[tracing::instrument(skip_all)]
+async fn scan_git_references<D: Database>(
+    ctx: &CmdContext<D>,
+    receiver: &Scheduler<ScanGitVCSReference>,
+    uploaders: &[Queue<UploadSourceUnits>],
+) -> Result<(), Error> {
+    let cli = fossa_cli::find_or_download(
+        &ctx.app,
+        ctx.config.fossa_cli(),
+        ctx.config.debug().location(),
+        DesiredVersion::Latest,
+    )
+    .await
+    .change_context(Error::DownloadFossaCli)
+    .describe("Broker relies on fossa-cli to perform analysis of your projects")?
+    .with_encryption(ctx.config.encryption().clone());
+
+    loop {
+        if !wait_for_disk_space(ctx).await {
+            return Ok(());
+        }
+
+        let scanned = execute_scan_git_references(ctx, receiver, uploaders, &cli);
+        match ctx.cancel.run_until_cancelled(scanned).await {
+            None => return Ok(()),
+            Some(Err(err)) => warn!("Unable to scan git reference: {err:#?}"),
+            Some(Ok(_)) => {}
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn execute_scan_git_references<D: Database>(
+    ctx: &CmdContext<D>,
+    receiver: &Scheduler<ScanGitVCSReference>,
+    uploaders: &[Queue<UploadSourceUnits>],
+    cli: &Location,
+) -> Result<(), Error> {
+    // Lanes and upload queues are both per-integration, in the same order.
+    let (lane, job) = receiver.recv().await.change_context(Error::TaskReceive)?;
+
+    // Every reference batched into the scan is leased, since its results come from this scan.
+    let leased = job
+        .references()
+        .map(|(_, reference)| reference.clone())
+        .collect_vec();
+
+    // The job is done if its integration was paused after it was enqueued, or another Broker is scanning the reference;
+    // either way, the reference is found again when the integration is next polled if it still needs to be scanned.
+    let skipped = if is_paused(ctx, &job.integration).await {
+        Some("its integration is paused")
+    } else if !acquire_leases(ctx, &job.integration, &leased).await {
+        Some("another Broker is scanning it")
+    } else {
+        None
+    };
+    if let Some(reason) = skipped {
+        info!(
+            "Skipping '{}' at '{}': {reason}",
+            job.integration, job.reference
+        );
+        progress_backlog(ctx, &job.integration).await;
+        for (scan_id, _) in job.references() {
+            forget_queued(&ctx.db, scan_id).await;
+        }
+        job.commit();
+        return Ok(());
+    }
+
+    let scanned = with_leases(
+        ctx,
+        &job.integration,
+        &leased,
+        scan_git_reference(ctx, &job, cli),
+    )
+    .await;
+    let upload = match scanned {
+        Ok(upload) => upload,
+        // Dropping the job without committing it redelivers it, so the scan is attempted again.
+        Err(err) if !job.is_last_delivery() => {
+            return Err(err)
+                .change_context(Error::TaskHandle)
+                .describe_lazy(|| {
+                    format!(
+                        "attempt {} of {}, the scan will be retried",
+                        job.deliveries(),
+                        queue::MAX_DELIVERIES
+                    )
+                });
+        }
+        Err(err) => {
+            progress_backlog(ctx, &job.integration).await;
+            for (scan_id, _) in job.references() {
+                forget_queued(&ctx.db, scan_id).await;
+            }
+            release_leases(ctx, &job.integration, &leased).await;
+            let job = job.commit();
+            let event = notify::Event::new(
+                notify::Kind::ScanFailure,
+                job.integration.remote(),
+                format!("{err:#}"),
+            )
+            .reference(&job.reference)
+            .scan_id(&job.scan_id);
+            ctx.notifier.notify(event).await;
+            return Err(err).change_context(Error::TaskHandle);
+        }
+    };
+    progress_backlog(ctx, &job.integration).await;
+    match upload {
+        // Once the scan is enqueued for upload, the upload job for each reference is responsible for it, and its lease.
+        Some(upload) => {
+            for batched in job.batched.iter() {
+                let upload = upload.for_reference(&batched.scan_id, &batched.reference);
+                enqueue_upload(ctx, &uploaders[lane], &upload).await?;
+            }
+            enqueue_upload(ctx, &uploaders[lane], &upload).await?;
+            job.commit();
+            Ok(())
+        }
+        None => {
+            for (scan_id, reference) in job.references() {
+                mark_scanned(ctx, &job.integration, reference).await?;
+                forget_queued(&ctx.db, scan_id).await;
+            }
+            release_leases(ctx, &job.integration, &leased).await;
+            job.commit();
+            Ok(())
+        }
+    }
+}
+
+/// Enqueue the results of a scan for upload.
+async fn enqueue_upload<D: Database>(
+    ctx: &CmdContext<D>,
+    uploader: &Queue<UploadSourceUnits>,
+    upload: &UploadSourceUnits,
+) -> Result<(), Error> {
+    let (integration, reference) = (&upload.integration, &upload.reference);
+    record_queued(
+        ctx,
+        &upload.scan_id,
+        integration,
+        reference,
+        JobStage::Upload,
+        reference,
+    )
+    .await;
+    uploader
+        .send(upload)
+        .await
+        .change_context(Error::TaskEnqueue)
+}
+
+/// Enqueue an uploaded scan to be followed up on.
+///
+/// Unlike scans, follow ups are recorded with what's needed to redeliver them without scanning again,
+/// since the scan they follow up on was already uploaded.
+async fn enqueue_follow_up<D: Database>(
+    ctx: &CmdContext<D>,
+    follow_ups: &Queue<FollowUp>,
+    follow_up: &FollowUp,
+) -> Result<(), Error> {
+    record_queued(
+        ctx,
+        &follow_up.scan_id,
+        &follow_up.integration,
+        &follow_up.reference,
+        JobStage::FollowUp,
+        &FollowUpRecord::from(follow_up),
+    )
+    .await;
+    follow_ups
+        .send(follow_up)
+        .await
+        .change_context(Error::TaskEnqueue)
+}
+
+/// Record that a job was enqueued at the stage, so that it can be inspected with `broker queue ls`
+/// and redelivered from the payload if Broker stops before it's done.
+///
+/// Failing to record the job isn't fatal: if Broker stops before it's done, the reference is found again when polled.
+async fn record_queued<D: Database>(
+    ctx: &CmdContext<D>,
+    scan_id: &str,
+    integration: &Integration,
+    reference: &Reference,
+    stage: JobStage,
+    payload: &impl Serialize,
+) {
+    let job = db::QueuedJob::new(
+        scan_id.to_string(),
+        stage,
+        reference.as_coordinate(integration.remote()),
+        reference.name().to_string(),
+        reference.as_state().to_vec(),
+        reference.is_branch(),
+        ctx.clock.now(),
+        serde_json::to_vec(payload).ok(),
+    );
+    if let Err(err) = ctx.db.set_queued_job(&job).await {
+        warn!("Unable to record queued job for scan '{scan_id}': {err:#?}");
+    }
+}
+
+/// Forget the record of an enqueued job once it's done.
+///
+/// Failing to forget the job isn't fatal: at worst, it's redelivered when Broker next starts.
+async fn forget_queued<D: Database>(db: &D, scan_id: &str) {
+    if let Err(err) = db.delete_queued_job(scan_id).await {
+        warn!("Unable to forget queued job for scan '{scan_id}': {err:#?}");
+    }
+}
+
+/// How long a scan lease lasts unless it's renewed.
+///
+/// Leases outlive the Broker that took them, so this is also how long a reference left in progress
+/// by a Broker that stopped waits before it can be scanned again.
+const SCAN_LEASE_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// How often a scan lease is renewed while its reference is scanned or uploaded.
+const SCAN_LEASE_RENEWAL_PERIOD: Duration = Duration::from_secs(60);
+
+/// Take or renew the lease on scanning a reference, so that no other Broker scans it at the same time.
+/// Returns `false` if another Broker holds the lease.
+///
+/// Failing to take the lease isn't fatal: the reference is scanned anyway, as it would be without leases.
+async fn acquire_lease<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+    reference: &Reference,
+) -> bool {
+    let coordinate = reference.as_coordinate(integration.remote());
+    let now = ctx.clock.now();
+    let acquired = ctx
+        .db
+        .acquire_scan_lease(&coordinate, &ctx.instance, now, now + SCAN_LEASE_DURATION)
+        .await;
+    match acquired {
+        Ok(acquired) => acquired,
+        Err(err) => {
+            warn!("Unable to take lease on scanning '{integration}' at '{reference}': {err:#?}");
+            true
+        }
+    }
+}
+
+/// Take the leases on scanning each of the references, which are scanned together.
+/// Returns `false` if another Broker holds the lease on any of them, releasing the leases already taken.
+async fn acquire_leases<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+    references: &[Reference],
+) -> bool {
+    for (taken, reference) in references.iter().enumerate() {
+        if !acquire_lease(ctx, integration, reference).await {
+            release_leases(ctx, integration, &references[..taken]).await;
+            return false;
+        }
+    }
+    true
+}
+
+/// Run `work` while renewing the leases on scanning the references, so that the leases don't expire while it runs.
+async fn with_leases<D: Database, T>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+    references: &[Reference],
+    work: impl Future<Output = T>,
+) -> T {
+    let renew = async {
+        while ctx.sleep(SCAN_LEASE_RENEWAL_PERIOD).await {
+            for reference in references {
+                if !acquire_lease(ctx, integration, reference).await {
+                    warn!("Lease on scanning '{integration}' at '{reference}' was taken by another Broker");
+                }
+            }
+        }
+    };
+
+    tokio::pin!(work);
+    tokio::select! {
+        output = &mut work => return output,
+        _ = renew => {}
+    }
+    // Once cancelled, the work stops at its own next opportunity.
+    work.await
+}
+
+/// Release the leases on scanning the references once their job is done.
+///
+/// Failing to release a lease isn't fatal, since it expires on its own.
+async fn release_leases<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+    references: &[Reference],
+) {
+    for reference in references {
+        let coordinate = reference.as_coordinate(integration.remote());
+        if let Err(err) = ctx.db.release_scan_lease(&coordinate, &ctx.instance).await {
+            warn!("Unable to release lease on scanning '{integration}' at '{reference}': {err:#?}");
+        }
+    }
+}
+
+/// Whether the reference was already scanned at its current state.
+async fn is_scanned<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+    reference: &Reference,
+) -> bool {
+    let coordinate = reference.as_coordinate(integration.remote());
+    match ctx.db.state(&coordinate).await {
+        Ok(state) => state.as_deref() == Some(reference.as_state()),
+        Err(err) => {
+            warn!("Unable to read state of '{integration}' at '{reference}': {err:#?}");
+            false
+        }
+    }
+}
+
+/// Record that a reference enqueued for the integration was scanned, successfully or not.
+///
+/// Failing to record progress isn't fatal, since it's only used to report progress.
+async fn progress_backlog<D: Database>(ctx: &CmdContext<D>, integration: &Integration) {
+    let progressed = ctx
+        .db
+        .progress_backlog(
+            &integration.namespace(),
+            &integration.repository(),
+            ctx.clock.now(),
+        )
+        .await;
+    if let Err(err) = progressed {
+        warn!("Unable to record backlog progress for '{integration}': {err:#?}");
+    }
+}
+
+#[tracing::instrument(skip(ctx, cli), fields(scan_id, cli_version))]
+async fn scan_git_reference<D: Database>(
+    ctx: &CmdContext<D>,
+    job: &ScanGitVCSReference,
+    cli: &fossa_cli::Location,
+) -> Result<Option<UploadSourceUnits>, Error> {
+    info!("Scanning '{}' at '{}'", job.integration, job.reference);
+    span_record!(scan_id, &job.scan_id);
+
+    let event = |action| {
+        Event::new(action, job.integration.remote())
+            .reference(&job.reference)
+            .scan_id(&job.scan_id)
+    };
+
+    // Clone the reference into a temporary directory.
+    //
+    // If the clone or the scan runs past its timeout, the job fails like any other failure:
+    // since the reference isn't marked as scanned, it's retried on the next poll.
+    let started = Instant::now();
+    let clone_timeout = job.integration.clone_timeout().as_duration();
+    let cloned_location = job
+        .integration
+        .checkout_reference(&ctx.mirrors, &job.reference);
+    let cloned_location = match tokio::time::timeout(clone_timeout, cloned_location).await {
+        Ok(cloned) => cloned.change_context_lazy(|| Error::CloneReference(job.reference.clone())),
+        Err(_) => report!(Error::CloneTimeout(clone_timeout))
+            .wrap_err()
+            .help("if this repository is expected to take longer to clone, increase 'clone_timeout' for the integration"),
+    };
+    ctx.audit
+        .record(event(Action::Clone), started, &cloned_location)
+        .await;
+    let cloned_location = cloned_location?;
+    let clone_duration = started.elapsed();
+
+    // Repository owners may opt references out of scanning with a marker file.
+    let marker = ImportMarker::read(cloned_location.path()).await;
+    if !marker.scan() {
+        info!(
+            "Skipping '{}' at '{}': disabled by '{MARKER_FILE}'",
+            job.integration, job.reference
+        );
+        return Ok(None);
+    }
+
+    // The clone is removed as soon as this returns, rather than being analyzed.
+    if let Err(err) = check_clone_size(ctx, cloned_location.path()).await {
+        skip_oversized(ctx, job, err).await;
+        return Ok(None);
+    }
+
+    let rewritten_from = detect_rewrite(ctx, job, cloned_location.path()).await;
+
+    let hook_context = hooks::Context::new(&job.scan_id, job.integration.remote(), &job.reference)
+        .path(cloned_location.path());
+    hooks::run(ctx.config.hooks(), hooks::Point::PostClone, &hook_context)
+        .await
+        .change_context(Error::Hook)?;
+
+    // Record the CLI version for debugging purposes.
+    let cli_version = cli.version().await.change_context(Error::RunFossaCli)?;
+    span_record!(cli_version, display cli_version);
+
+    // Run the scan, unless an identical tree was already analyzed.
+    let started = Instant::now();
+    let cache_key = analysis_cache_key(ctx, job, cloned_location.path(), &cli_version).await;
+    let cached = match &cache_key {
+        Some(key) => cache::load(&ctx.analysis_cache, key, ctx.config.encryption().as_ref()).await,
+        None => None,
+    };
+    let reused = cached.is_some();
+    let source_units = match cached {
+        Some(source_units) => {
+            info!(
+                "Reusing cached analysis of an identical tree for '{}' at '{}'",
+                job.integration, job.reference
+            );
+            source_units
+        }
+        None => {
+            let scan_timeout = job.integration.scan_timeout().as_duration();
+            let source_units = cli.analyze(
+                &job.scan_id,
+                cloned_location.path(),
+                job.integration.cli_env(),
+                job.integration.cli_options(),
+            );
+            let source_units = match tokio::time::timeout(scan_timeout, source_units).await {
+                Ok(source_units) => source_units.change_context(Error::RunFossaCli),
+                Err(_) => report!(Error::ScanTimeout(scan_timeout))
+                    .wrap_err()
+                    .help("if this repository is expected to take longer to analyze, increase 'scan_timeout' for the integration"),
+            };
+            ctx.audit
+                .record(event(Action::Analyze), started, &source_units)
+                .await;
+            let source_units = source_units?;
+            if let Some(key) = &cache_key {
+                let encryption = ctx.config.encryption().as_ref();
+                if let Err(err) =
+                    cache::save(&ctx.analysis_cache, key, &source_units, encryption).await
+                {
+                    warn!(
+                        "Unable to cache analysis of '{}' at '{}': {err:#?}",
+                        job.integration, job.reference
+                    );
+                }
+            }
+            source_units
+        }
+    };
+    let analyze_duration = started.elapsed();
+
+    if let Err(err) = check_upload_size(ctx, &source_units) {
+        skip_oversized(ctx, job, err).await;
+        return Ok(None);
+    }
+
+    if !ctx.config.hooks().post_scan().is_empty() {
+        run_post_scan_hooks(ctx, &hook_context, &source_units).await?;
+    }
+
+    let contributors = if ctx.config.fossa_api().upload_contributors() {
+        collect_contributors(job, cloned_location.path()).await
+    } else {
+        None
+    };
+
+    info!(
+        "Scanned '{}' at '{}' (clone: {}, analysis: {}), enqueueing for upload",
+        job.integration,
+        job.reference,
+        humantime::format_duration(Duration::from_secs(clone_duration.as_secs())),
+        humantime::format_duration(Duration::from_secs(analyze_duration.as_secs())),
+    );
+    // Reusing cached results takes no time, so it would skew the history used to detect slow scans.
+    if !reused {
+        let record = db::ScanRecord::new(job.scan_id.clone(), clone_duration, analyze_duration);
+        record_scan_history(ctx, job, &record).await;
+    }
+
+    Ok(Some(UploadSourceUnits {
+        cli: CliMetadata::new(cli_version),
+        integration: job.integration.clone(),
+        reference: job.reference.clone(),
+        scan_id: job.scan_id.clone(),
+        source_units,
+        contributors,
+        rewritten_from,
+    }))
+}
+
+/// Check that the clone is no larger than `size_limits.max_clone_size`, if it's set.
+async fn check_clone_size<D: Database>(ctx: &CmdContext<D>, checkout: &Path) -> Result<(), Error> {
+    let Some(limit) = ctx.config.size_limits().max_clone_size() else {
+        return Ok(());
+    };
+    let checkout = checkout.to_path_buf();
+    let size = tokio::task::spawn_blocking(move || disk::usage(&checkout))
+        .await
+        .unwrap_or(ByteSize::b(0));
+    if size <= limit {
+        return Ok(());
+    }
+    report!(Error::CloneTooLarge(size, limit))
+        .wrap_err()
+        .help("remove the large files from the repository, stop scanning the reference with a marker file, or increase 'size_limits.max_clone_size'")
+}
+
+/// Check that the results of a scan are no larger than `size_limits.max_upload_size`, if it's set.
+fn check_upload_size<D: Database>(
+    ctx: &CmdContext<D>,
+    source_units: &SourceUnits,
+) -> Result<(), Error> {
+    let Some(limit) = ctx.config.size_limits().max_upload_size() else {
+        return Ok(());
+    };
+    let size = ByteSize::b(source_units.to_string().len() as u64);
+    if size <= limit {
+        return Ok(());
+    }
+    report!(Error::UploadTooLarge(size, limit))
+        .wrap_err()
+        .help("exclude the paths producing the large results with 'cli_options.exclude_paths' for the integration, or increase 'size_limits.max_upload_size'")
+}
+
+/// Report a scan which is skipped because it exceeded a size limit.
+///
+/// It would exceed the limit again, so the reference is marked as scanned and isn't retried until it changes.
+async fn skip_oversized<D: Database>(
+    ctx: &CmdContext<D>,
+    job: &ScanGitVCSReference,
+    err: Report<Error>,
+) {
+    warn!(
+        "Skipping '{}' at '{}': {err:#?}",
+        job.integration, job.reference
+    );
+    let event = notify::Event::new(
+        notify::Kind::ScanTooLarge,
+        job.integration.remote(),
+        format!("{err:#}"),
+    )
+    .reference(&job.reference)
+    .scan_id(&job.scan_id);
+    ctx.notifier.notify(event).await;
+}
+
+/// If the job's reference is a branch which was rewritten since it was last scanned, for example by a force push,
+/// report it and return the commit the branch previously pointed at.
+///
+/// The branch is scanned as usual either way; this only annotates the scan.
+/// Failing to check isn't fatal: it's logged, and the branch is treated as not rewritten.
+async fn detect_rewrite<D: Database>(
+    ctx: &CmdContext<D>,
+    job: &ScanGitVCSReference,
+    checkout: &Path,
+) -> Option<String> {
+    let branch = job.reference.branch()?;
+    let integration = &job.integration;
+    let previous = ctx
+        .db
+        .last_branch_state(&integration.namespace(), &integration.repository(), branch)
+        .await
+        .tap_err(|err| warn!("Unable to read history of '{integration}' at '{branch}': {err:#?}"))
+        .ok()
+        .flatten()
+        .filter(|previous| previous.as_slice() != job.reference.as_state())?;
+
+    match integration
+        .protocol()
+        .descends_from(checkout, &previous)
+        .await
+    {
+        Ok(Some(false)) => {}
+        Ok(_) => return None,
+        Err(err) => {
+            warn!(
+                "Unable to check whether '{integration}' at '{}' was rewritten: {err:#?}",
+                job.reference
+            );
+            return None;
+        }
+    }
+
+    let previous = String::from_utf8_lossy(&previous).to_string();
+    warn!(
+        "'{integration}' at '{}' was rewritten: it no longer descends from '{previous}', where it was last scanned",
+        job.reference
+    );
+    let event = notify::Event::new(
+        notify::Kind::ReferenceRewritten,
+        integration.remote(),
+        format!(
+            "branch '{branch}' no longer descends from '{previous}', where it was last scanned"
+        ),
+    )
+    .reference(&job.reference)
+    .scan_id(&job.scan_id);
+    ctx.notifier.notify(event).await;
+    Some(previous)
+}
+
+/// The key under which the results of analyzing the checkout are cached, or `None` if they can't be cached.
+///
+/// Post-clone hooks may change what's checked out, so results aren't cached when any are configured.
+/// Failing to hash the tree isn't fatal: it's logged, and the checkout is analyzed without the cache.
+async fn analysis_cache_key<D>(
+    ctx: &CmdContext<D>,
+    job: &ScanGitVCSReference,
+    checkout: &Path,
+    cli_version: &fossa_cli::Version,
+) -> Option<String> {
+    if !ctx.config.hooks().post_clone().is_empty() {
+        return None;
+    }
+
+    match job.integration.protocol().tree_hash(checkout).await {
+        Ok(tree) => tree.map(|tree| cache::key(&tree, cli_version, &job.integration)),
+        Err(err) => {
+            warn!(
+                "Unable to hash the tree of '{}' at '{}', analyzing it without the cache: {err:#}",
+                job.integration, job.reference
+            );
+            None
+        }
+    }
+}
+
+/// Run the hooks configured after each scan, providing them the scan results in a file.
+///
+/// Scan results are often too large for an environment variable, so they're written to a temporary file
+/// which is removed once the hooks finish.
+async fn run_post_scan_hooks<D: Database>(
+    ctx: &CmdContext<D>,
+    context: &hooks::Context,
+    source_units: &SourceUnits,
+) -> Result<(), Error> {
+    let results = tempfile::named_tempfile_with_suffix(".json")
+        .context(Error::Hook)
+        .describe("create temporary file for scan results")?;
+    tokio::fs::write(results.path(), source_units.to_string())
+        .await
+        .context(Error::Hook)
+        .describe_lazy(|| format!("write scan results to '{}'", results.path().display()))?;
+
+    let context = context.clone().scan_results(results.path());
+    hooks::run(ctx.config.hooks(), hooks::Point::PostScan, &context)
+        .await
+        .change_context(Error::Hook)
+}
+
+/// Collect the people who recently committed to the reference checked out at the provided location.
+///
+/// Contributors are informational, so failing to collect them is logged and the scan continues without them.
+async fn collect_contributors(job: &ScanGitVCSReference, checkout: &Path) -> Option<Contributors> {
+    match job.integration.protocol().contributors(checkout).await {
+        Ok(contributors) => contributors,
+        Err(err) => {
+            warn!(
+                "Unable to collect contributors for '{}' at '{}': {err:#}",
+                job.integration, job.reference
+            );
+            None
+        }
+    }
+}
+
+/// Record the scan in the scan history, reporting it if it took much longer than recent scans of the integration.
+///
+/// Failing to read or write the scan history isn't fatal: it's logged, and the scan continues.
+async fn record_scan_history<D: Database>(
+    ctx: &CmdContext<D>,
+    job: &ScanGitVCSReference,
+    record: &db::ScanRecord,
+) {
+    let remote = job.integration.remote();
+    let repository = job.integration.repository();
+    match ctx
+        .db
+        .recent_scans(&job.integration.namespace(), &repository, history::WINDOW)
+        .await
+    {
+        Ok(recent) => {
+            let multiple = job.integration.slow_scan_multiple();
+            for slow in history::detect(record, &recent, multiple) {
+                warn!(
+                    "Slow scan of '{}' at '{}': {slow}",
+                    job.integration, job.reference
+                );
+                let event = notify::Event::new(notify::Kind::SlowScan, remote, slow)
+                    .reference(&job.reference)
+                    .scan_id(&job.scan_id);
+                ctx.notifier.notify(event).await;
+            }
+        }
+        Err(err) => warn!(
+            "Unable to read scan history for '{}': {err:#?}",
+            job.integration
+        ),
+    }
+
+    let coordinate = job.reference.as_coordinate(remote);
+    if let Err(err) = ctx.db.record_scan(&coordinate, record).await {
+        warn!(
+            "Unable to record scan history for '{}' at '{}': {err:#?}",
+            job.integration, job.reference
+        );
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn upload_scans<D: Database>(
+    ctx: &CmdContext<D>,
+    receiver: &Queue<UploadSourceUnits>,
+    follow_ups: &Queue<FollowUp>,
+) -> Result<(), Error> {
+    // This worker is per integration, so the rate limiter should be constructed here instead of globally.
+    let quota = Quota::per_minute(nonzero!(1u32));
+    let limiter = RateLimiter::direct(quota);
+
+    loop {
+        let Some(job) = ctx.cancel.run_until_cancelled(receiver.recv()).await else {
+            return Ok(());
+        };
+        let job = match job.change_context(Error::TaskReceive) {
+            Ok(job) => job,
+            Err(err) => {
+                warn!("Unable to read enqueued upload job: {err:#?}");
+                continue;
+            }
+        };
+
+        let meta = ProjectMetadata::new(&job.integration, &job.reference);
+        if limiter.check().is_err() {
+            info!("Integration '{meta}': waiting for rate limit");
+            if ctx
+                .cancel
+                .run_until_cancelled(limiter.until_ready())
+                .await
+                .is_none()
+            {
+                return Ok(());
+            }
+        }
+
+        // The lease taken when the reference was scanned is held until the scan is uploaded.
+        let leased = std::slice::from_ref(&job.reference);
+        let uploaded = with_leases(
+            ctx,
+            &job.integration,
+            leased,
+            execute_upload_scans(ctx, &meta, &job),
+        );
+        let Some(uploaded) = ctx.cancel.run_until_cancelled(uploaded).await else {
+            return Ok(());
+        };
+        release_leases(ctx, &job.integration, leased).await;
+        let job = job.commit();
+        match uploaded {
+            // Scans which fail to upload are saved to be retried, or found again when polled,
+            // so the job is done either way.
+            Err(err) => {
+                forget_queued(&ctx.db, &job.scan_id).await;
+                warn!("Unable to upload scan for '{meta}': {err:#?}")
+            }
+            // The record of the job moves on to the follow up stage, so that it's redelivered
+            // if Broker stops before following up.
+            Ok(uploaded) if follows_up(ctx) => {
+                let follow_up = FollowUp::new(job, uploaded);
+                match ctx
+                    .cancel
+                    .run_until_cancelled(enqueue_follow_up(ctx, follow_ups, &follow_up))
+                    .await
+                {
+                    None => return Ok(()),
+                    Some(Err(err)) => {
+                        warn!("Unable to enqueue follow up for '{meta}': {err:#?}")
+                    }
+                    Some(Ok(_)) => {}
+                }
+            }
+            Ok(_) => forget_queued(&ctx.db, &job.scan_id).await,
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn execute_upload_scans<D: Database>(
+    ctx: &CmdContext<D>,
+    meta: &ProjectMetadata,
+    job: &UploadSourceUnits,
+) -> Result<Uploaded, Error> {
+    let redacted;
+    let job = match guard_secrets(ctx, job).await? {
+        Some(job) => {
+            redacted = job;
+            &redacted
+        }
+        None => job,
+    };
+
+    let meta = &match existing_project(ctx, &job.integration).await {
+        Some(mapping) => meta
+            .clone()
+            .with_project(mapping.project().clone(), mapping.title().clone()),
+        None => meta.clone(),
+    };
+    // Branch mappings are configured for specific branches, so they take precedence over the project found above.
+    let meta = &match job.integration.branch_mapping(&job.reference) {
+        Some(mapping) => meta.clone().with_mapping(mapping),
+        None => meta.clone(),
+    };
+
+    // CI may deposit metadata about the build of the revision at any point, so it's read at upload time.
+    let build = ctx
+        .config
+        .ci_metadata()
+        .read(job.reference.revision())
+        .await;
+    let meta = &match build.as_ref().and_then(|build| build.link().clone()) {
+        Some(link) => meta.clone().with_link(link),
+        None => meta.clone(),
+    };
+    let meta = &match &job.rewritten_from {
+        Some(previous) => meta.clone().with_rewritten_from(previous.clone()),
+        None => meta.clone(),
+    };
+
+    info!("Uploading scan for project: '{meta}'");
+    let primary = ctx.targets.primary();
+    let mut uploaded = upload_to_target(ctx, primary, meta, job, build.as_ref())
+        .await
+        .map(|locator| (primary, locator));
+    match ctx.targets.fan_out() {
+        // Additional targets only receive the scan once the primary target accepted it,
+        // since failed uploads are retried until the primary target accepts them.
+        fossa::FanOut::All if uploaded.is_ok() => {
+            for target in ctx.targets.additional() {
+                if let Err(err) = upload_to_target(ctx, target, meta, job, build.as_ref()).await {
+                    warn!(
+                        "Unable to upload scan for '{meta}' to FOSSA target '{target}': {err:#?}"
+                    );
+                    let event = notify::Event::new(
+                        notify::Kind::UploadFailure,
+                        job.integration.remote(),
+                        format!("FOSSA target '{target}': {err:#}"),
+                    )
+                    .reference(&job.reference)
+                    .scan_id(&job.scan_id);
+                    ctx.notifier.notify(event).await;
+                }
+            }
+        }
+        fossa::FanOut::PrimaryWithFallback if uploaded.is_err() => {
+            for target in ctx.targets.additional() {
+                info!("Falling back to FOSSA target '{target}' to upload scan for '{meta}'");
+                match upload_to_target(ctx, target, meta, job, build.as_ref()).await {
+                    Ok(locator) => {
+                        uploaded = Ok((target, locator));
+                        break;
+                    }
+                    Err(err) => warn!(
+                        "Unable to upload scan for '{meta}' to FOSSA target '{target}': {err:#?}"
+                    ),
+                }
+            }
+        }
+        fossa::FanOut::All | fossa::FanOut::PrimaryWithFallback => {}
+    }
+
+    // If every target failed, the failure uploading to the primary target is the one reported.
+    if let Err(err) = &uploaded {
+        // Only the first and last failures are notified, so that retries during an outage don't flood the sinks.
+        let deferral = match defer_upload(ctx, job).await {
+            Ok(deferral) => deferral,
+            Err(defer_err) => {
+                warn!("Unable to save scan for '{meta}' to retry its upload: {defer_err:#?}");
+                Deferral::Disabled
+            }
+        };
+        let message = match deferral {
+            Deferral::Disabled => Some(format!("{err:#}")),
+            Deferral::Scheduled {
+                attempts: 1,
+                retry_in,
+            } => Some(format!(
+                "{err:#}; retrying in {}",
+                humantime::format_duration(retry_in)
+            )),
+            Deferral::Scheduled { .. } => None,
+            Deferral::Expired { attempts } => Some(format!(
+                "{err:#}; gave up after {attempts} attempts, the reference will be scanned again"
+            )),
+        };
+        if let Some(message) = message {
+            let event = notify::Event::new(
+                notify::Kind::UploadFailure,
+                job.integration.remote(),
+                message,
+            )
+            .reference(&job.reference)
+            .scan_id(&job.scan_id);
+            ctx.notifier.notify(event).await;
+        }
+    }
+    let (target, locator) = uploaded.change_context(Error::TaskHandle)?;
+
+    debug!(scan_id = %job.scan_id, locator = %locator, target = %target, "Uploaded scan");
+    info!("Uploaded scan for project '{meta}' to FOSSA target '{target}' as locator: '{locator}'");
+
+    if ctx.config.upload_retry().enabled() {
+        forget_pending_upload(ctx, &job.scan_id).await;
+    }
+    mark_scanned(ctx, &job.integration, &job.reference).await?;
+
+    let locator = locator.to_string();
+    // Summary reports look up issues with the primary FOSSA endpoint, so only uploads to it are recorded.
+    if target.name().is_none() {
+        if let Err(err) = ctx.db.set_scan_locator(&job.scan_id, &locator).await {
+            warn!("Unable to record locator for '{meta}': {err:#?}");
+        }
+    }
+    if let Some(contributors) = &job.contributors {
+        upload_contributors(ctx.fossa_client(), target, &locator, contributors).await;
+    }
+
+    // The scan is already uploaded, so even required hooks can't fail it at this point.
+    let hook_context = hooks::Context::new(&job.scan_id, job.integration.remote(), &job.reference)
+        .locator(&locator);
+    if let Err(err) = hooks::run(ctx.config.hooks(), hooks::Point::PostUpload, &hook_context).await
+    {
+        warn!("Unable to run hooks after uploading '{meta}': {err:#?}");
+    }
+    Ok(Uploaded {
+        locator,
+        target: target.name().map(ToString::to_string),
+    })
+}
+
+/// Check the scan for secrets before it's uploaded, recording the check in the audit log.
+///
+/// Returns a redacted copy of the job if the policy redacts the secrets that were found,
+/// or an error if the policy blocks uploading them.
+async fn guard_secrets<D: Database>(
+    ctx: &CmdContext<D>,
+    job: &UploadSourceUnits,
+) -> Result<Option<UploadSourceUnits>, Error> {
+    let guard = ctx.config.secrets_guard();
+    if !guard.enabled() {
+        return Ok(None);
+    }
+
+    let started = Instant::now();
+    let findings = secrets::scan(&job.source_units);
+    let blocked = !findings.is_empty() && guard.policy() == secrets::Policy::Block;
+    let result: Result<(), Error> = if blocked {
+        report!(Error::UploadBlocked(findings.len())).wrap_err()
+    } else {
+        Ok(())
+    };
+    let event = Event::new(Action::SecretScan, job.integration.remote())
+        .reference(&job.reference)
+        .scan_id(&job.scan_id)
+        .findings(&findings);
+    ctx.audit.record(event, started, &result).await;
+
+    if findings.is_empty() {
+        return Ok(None);
+    }
+    let listed = findings.iter().join(", ");
+    warn!(
+        "Found {} secret(s) in scan of '{}' at '{}' ({}): {listed}",
+        findings.len(),
+        job.integration,
+        job.reference,
+        guard.policy(),
+    );
+    let event = notify::Event::new(
+        notify::Kind::SecretsFound,
+        job.integration.remote(),
+        format!("{listed} (policy: {})", guard.policy()),
+    )
+    .reference(&job.reference)
+    .scan_id(&job.scan_id);
+    ctx.notifier.notify(event).await;
+
+    match guard.policy() {
+        secrets::Policy::Disabled | secrets::Policy::Flag => Ok(None),
+        secrets::Policy::Redact => Ok(Some(UploadSourceUnits {
+            source_units: secrets::redact(&job.source_units),
+            ..job.for_reference(&job.scan_id, &job.reference)
+        })),
+        secrets::Policy::Block => {
+            // The reference isn't scanned again until it changes, since the result wouldn't either.
+            mark_scanned(ctx, &job.integration, &job.reference).await?;
+            result.map(|_| None).help(
+                "remove the secrets from the repository, or change the 'secrets_guard' policy",
+            )
+        }
+    }
 }
 
-#[tracing::instrument(skip(db, sender))]
-async fn poll_integration<D: Database>(
-    db: &D,
-    integration: &Integration,
-    sender: &Queue<ScanGitVCSReference>,
-) -> Result<(), Error> {
-    let poll_interval = integration.poll_interval().as_duration();
-    loop {
-        if let Err(err) = execute_poll_integration(db, integration, sender).await {
-            warn!("Unable to poll '{integration}': {err:#?}");
+/// Upload the scan to a single FOSSA target, recording the upload in the audit log.
+async fn upload_to_target<D: Database>(
+    ctx: &CmdContext<D>,
+    target: &Target,
+    meta: &ProjectMetadata,
+    job: &UploadSourceUnits,
+    build: Option<&fossa::BuildInfo>,
+) -> Result<Locator, fossa::Error> {
+    target.ready().await;
+    let started = Instant::now();
+    let locator = fossa::upload_scan(
+        ctx.fossa_client(),
+        target.config(),
+        meta,
+        &job.cli,
+        &job.source_units,
+    )
+    .await;
+
+    let event = Event::new(Action::Upload, job.integration.remote())
+        .reference(&job.reference)
+        .scan_id(&job.scan_id);
+    let event = match build {
+        Some(build) => {
+            let event = event.artifacts(build.artifacts());
+            match build.build() {
+                Some(id) => event.build(id),
+                None => event,
+            }
         }
+        None => event,
+    };
+    let event = match target.name() {
+        Some(name) => event.target(name),
+        None => event,
+    };
+    let event = match &locator {
+        Ok(locator) => event.locator(locator),
+        Err(_) => event,
+    };
+    ctx.audit.record(event, started, &locator).await;
+    locator
+}
 
-        // Now wait for the next poll time.
-        // The fact that we poll, _then_ wait for the poll time, means that the actual
-        // time at which the poll occurs will slowly creep forward by whatever time
-        // it takes to perform the poll.
-        //
-        // This is considered okay, because we're interpreting "poll interval" as
-        // "poll at most this often"; i.e. it's used primarily as a rate limiting feature
-        // than as a "track changes this often" feature.
-        //
-        // The alternative would be starting the clock at the top of the loop,
-        // which while doable could (in the worst case) allow for endlessly
-        // polling if the poll takes at least as long as the interval.
-        //
-        // If we decide to make polling more consistent, [`tokio::time::interval`]
-        // is most likely the correct way to implement it.
-        info!("Next poll interval for '{integration}' in {poll_interval:?}");
-        tokio::time::sleep(poll_interval).await;
+/// Upload the contributors for an uploaded scan.
+///
+/// The scan is already uploaded, so failing to upload its contributors is logged rather than retried.
+async fn upload_contributors(
+    client: &http::client::Client,
+    target: &Target,
+    locator: &str,
+    contributors: &Contributors,
+) {
+    if contributors.is_empty() {
+        return;
+    }
+    match fossa::upload_contributors(client, target.config(), locator, contributors).await {
+        Ok(()) => debug!(
+            "Uploaded {} contributors for '{locator}'",
+            contributors.len()
+        ),
+        Err(err) => warn!("Unable to upload contributors for '{locator}': {err:#}"),
     }
 }
 
 #[tracing::instrument(skip_all)]
-async fn execute_poll_integration<D: Database>(
-    db: &D,
-    integration: &Integration,
-    sender: &Queue<ScanGitVCSReference>,
+async fn follow_up_uploads<D: Database>(
+    ctx: &CmdContext<D>,
+    receiver: &Queue<FollowUp>,
 ) -> Result<(), Error> {
-    // We use this in a few places and may send it across threads, so just clone it locally.
-    let remote = integration.remote().to_owned();
-
-    // [`Retry`] needs a function that runs without any arguments to perform the retry, so turn the method into a closure.
-    let get_references = || async {
-        match integration.references().await {
-            Ok(success) => Ok(success),
+    loop {
+        let Some(job) = ctx.cancel.run_until_cancelled(receiver.recv()).await else {
+            return Ok(());
+        };
+        let job = match job.change_context(Error::TaskReceive) {
+            Ok(job) => job,
             Err(err) => {
-                warn!("Unable to poll integration at {remote}: {err:#}");
-                Err(err)
+                warn!("Unable to read enqueued follow up job: {err:#?}");
+                continue;
             }
+        };
+
+        let followed_up = execute_follow_up(ctx, &job);
+        if ctx.cancel.run_until_cancelled(followed_up).await.is_none() {
+            return Ok(());
         }
+        forget_queued(&ctx.db, &job.scan_id).await;
+        job.commit();
+    }
+}
+
+/// Whether uploaded scans are followed up on once FOSSA processes them.
+fn follows_up<D>(ctx: &CmdContext<D>) -> bool {
+    ctx.config.policy_check().enabled() || ctx.config.sbom_export().enabled()
+}
+
+/// Follow up on an uploaded scan with the FOSSA target which accepted it.
+#[tracing::instrument(skip_all, fields(scan_id = %job.scan_id))]
+async fn execute_follow_up<D: Database>(ctx: &CmdContext<D>, job: &FollowUp) {
+    let Some(target) = ctx.targets.find(job.target.as_deref()) else {
+        warn!(
+            "Unable to follow up on '{}': FOSSA target '{}' is no longer configured",
+            job.locator,
+            job.target.as_deref().unwrap_or_default()
+        );
+        return;
     };
 
-    info!("Polling '{integration}'");
+    if ctx.config.policy_check().enabled() {
+        execute_check_policy(ctx, target, job).await;
+    }
+    if ctx.config.sbom_export().enabled() {
+        export_sboms(ctx, target, job).await;
+    }
+}
 
-    // Given that this operation is not latency sensitive, and temporary network issues can interfere,
-    // retry several times before permanently failing since a permanent failure means Broker shuts down
-    // entirely.
-    let strategy = ExponentialBackoff::from_millis(1000).map(jitter).take(10);
-    let references = Retry::spawn(strategy, get_references)
-            .await
-            .change_context(Error::PollIntegration)
-            .describe_lazy(|| format!("poll for changes at {remote} in integration: {integration}"))
-            .help(indoc! {"
-            Issues with this process are usually related to network errors, but may be due to misconfiguration.
-            Each time this polling operation was attempted, it logged a warning; please review those
-            warnings in the logs for more details.
-            "})?;
+/// Export SBOMs of an uploaded scan in each configured format, writing them to the SBOM directory.
+///
+/// SBOMs are written to a temporary name and then renamed, so that tooling watching the directory never reads a partial SBOM.
+/// Failing to export an SBOM is logged; the reference is already marked as scanned, so it isn't scanned again.
+async fn export_sboms<D: Database>(ctx: &CmdContext<D>, target: &Target, job: &FollowUp) {
+    let config = ctx.config.sbom_export();
+    for &format in config.formats() {
+        let started = Instant::now();
+        let exported = fossa::export_sbom(
+            ctx.fossa_client(),
+            target.config(),
+            &job.locator,
+            format,
+            config.timeout(),
+        )
+        .await;
+        let path = ctx
+            .sboms
+            .join(format!("{}.{}", job.scan_id, format.extension()));
+        let written = match exported {
+            Ok(sbom) => write_sbom(&path, &sbom).await,
+            Err(err) => Err(err.change_context(Error::ExportSbom)),
+        };
 
-    // Filter to the list of references that are new since we last saw them.
-    let references = futures::stream::iter(references.into_iter())
-            // Using `filter_map` instead of `filter` so that this closure gets ownership of `reference`,
-            // which makes binding it across an await point easier (no lifetimes to mess with).
-            .filter_map(|reference| async {
-                match &reference {
-                    Reference::Git(git_reference) => match git_reference {
-                        git::Reference::Branch {..} => {
-                            // Skipping because integration is not configured to scan branches or branch was not in the integration's watched branches
-                            if integration.import_branches().should_skip_branches() || !integration.should_scan_reference(reference.name()){
-                                return None
-                            }
-                        },
-                        git::Reference::Tag{..}  => {
-                            // Skipping because integration was not configured to scan tags
-                            if integration.import_tags().should_skip_tags() {
-                                return None
-                            }
-                        },
-                    }
-                }
+        let event = Event::new(Action::Export, job.integration.remote())
+            .reference(&job.reference)
+            .scan_id(&job.scan_id)
+            .locator(&job.locator)
+            .format(format);
+        let event = match target.name() {
+            Some(name) => event.target(name),
+            None => event,
+        };
+        ctx.audit.record(event, started, &written).await;
+        match written {
+            Ok(()) => info!(
+                "Exported {format} SBOM of '{}' to '{}'",
+                job.locator,
+                path.display()
+            ),
+            Err(err) => warn!(
+                "Unable to export {format} SBOM of '{}': {err:#?}",
+                job.locator
+            ),
+        }
+    }
+}
 
-                let coordinate = reference.as_coordinate(&remote);
-                match db.state(&coordinate).await {
-                    // No previous state; this must be a new reference.
-                    Ok(None) => Some(Ok(reference)),
-                    // There was previous state, it's only new if the state is different.
-                    // We're assuming "different state" always means "newer state".
-                    // This is because state is currently expressed as a git commit string,
-                    // which on its own doesn't have any form of ordering.
-                    Ok(Some(db_state)) => {
-                        if db_state == reference.as_state() {
-                            None
-                        } else {
-                            Some(Ok(reference))
-                        }
-                    }
-                    // Pass through errors.
-                    Err(err) => Some(Err(err)),
-                }
-            })
-            // Fold ourselves because `collect` expects its destination to implement `Default`.
-            .try_fold(Vec::new(), |mut references, reference| async {
-                references.push(reference);
-                Ok(references)
-            })
+async fn write_sbom(path: &Path, sbom: &[u8]) -> Result<(), Error> {
+    let partial = path.with_extension("partial");
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
             .await
-            .change_context(Error::PollIntegration)
-            .describe_lazy(|| {
-                format!("filter to only changes at {remote} in integration: {integration}")
-            })
-            .help(indoc! {"
-            Problems at this stage are most likely caused by a database error.
-            Broker manages a local sqlite database; deleting it so it can be re-generated from scratch may resolve the issue.
-            "})?;
-
-    // We sink the references here instead of during the stream so that
-    // if an error is encountered reading the stream, we don't send partial lists.
-    if references.is_empty() {
-        info!("No changes to '{integration}'");
+            .context(Error::ExportSbom)
+            .describe_lazy(|| format!("create directory '{}'", parent.display()))?;
     }
-    for reference in references {
-        let job = ScanGitVCSReference::new(integration, &reference);
-        sender.send(&job).await.change_context(Error::TaskEnqueue)?;
+    tokio::fs::write(&partial, sbom)
+        .await
+        .context(Error::ExportSbom)
+        .describe_lazy(|| format!("write SBOM to '{}'", partial.display()))?;
+    tokio::fs::rename(&partial, path)
+        .await
+        .context(Error::ExportSbom)
+        .describe_lazy(|| format!("move SBOM to '{}'", path.display()))
+}
+
+/// Wait for FOSSA to check an uploaded scan for issues, then record the result in the scan history
+/// and notify if issues were found or the check failed.
+///
+/// The reference is already marked as scanned, so failing the check doesn't cause it to be scanned again.
+async fn execute_check_policy<D: Database>(ctx: &CmdContext<D>, target: &Target, job: &FollowUp) {
+    let meta = ProjectMetadata::new(&job.integration, &job.reference);
+    info!("Checking '{meta}' for issues");
+
+    // The scan is checked by the target which accepted it.
+    let timeout = ctx.config.policy_check().timeout();
+    let checked =
+        fossa::check_issues(ctx.fossa_client(), target.config(), &job.locator, timeout).await;
+    let message = match &checked {
+        Ok(issues) if issues.passed() => {
+            info!("'{meta}' passed its policy check");
+            None
+        }
+        Ok(issues) => {
+            warn!("FOSSA found {issues} in '{meta}'");
+            Some(format!("FOSSA found {issues} in '{}'", job.locator))
+        }
+        Err(err) => {
+            warn!("Unable to check '{meta}' for issues: {err:#?}");
+            Some(format!("{err:#}"))
+        }
+    };
 
-        info!("Enqueued task to scan '{integration}' at '{reference}'");
+    if let Ok(issues) = &checked {
+        let status = if issues.passed() {
+            db::PolicyStatus::Passed
+        } else {
+            db::PolicyStatus::Failed
+        };
+        if let Err(err) = ctx.db.set_scan_policy(&job.scan_id, status).await {
+            warn!("Unable to record policy check for '{meta}': {err:#?}");
+        }
     }
 
-    Ok(())
+    if let Some(message) = message {
+        let event = notify::Event::new(
+            notify::Kind::PolicyFailure,
+            job.integration.remote(),
+            message,
+        )
+        .reference(&job.reference)
+        .scan_id(&job.scan_id);
+        ctx.notifier.notify(event).await;
+    }
 }
 
-#[tracing::instrument(skip_all)]
-async fn scan_git_references<D: Database>(
+/// The project in FOSSA to which scans of the integration should be uploaded instead of the one it names,
+/// or `None` to upload to the named project.
+///
+/// Repositories previously imported by other means, like FOSSA CLI, may already have a project under a different name.
+/// Before the first upload for an integration, Broker looks for such a project and reuses it instead of creating a duplicate.
+/// The project that's chosen is stored so that later uploads go to the same project without looking it up again.
+#[tracing::instrument(skip_all, fields(integration = %integration))]
+async fn existing_project<D: Database>(
     ctx: &CmdContext<D>,
-    receiver: &Queue<ScanGitVCSReference>,
-    uploader: &Queue<UploadSourceUnits>,
-) -> Result<(), Error> {
-    let cli = fossa_cli::find_or_download(
-        &ctx.app,
-        ctx.config.debug().location(),
-        DesiredVersion::Latest,
-    )
-    .await
-    .change_context(Error::DownloadFossaCli)
-    .describe("Broker relies on fossa-cli to perform analysis of your projects")?;
+    integration: &Integration,
+) -> Option<db::ProjectMapping> {
+    if !ctx.config.fossa_api().match_existing_projects() {
+        return None;
+    }
 
-    loop {
-        if let Err(err) = execute_scan_git_references(ctx, receiver, uploader, &cli).await {
-            warn!("Unable to scan git reference: {err:#?}");
+    // The stored project is only used while it's still one of the integration's candidates,
+    // so that changing the integration's config changes its project.
+    let candidates = integration.protocol().existing_projects();
+    let namespace = integration.namespace();
+    let repository = integration.repository();
+    match ctx.db.project_mapping(&namespace, &repository).await {
+        Ok(Some(mapping)) if candidates.contains(mapping.project()) => return Some(mapping),
+        Ok(_) => {}
+        Err(err) => {
+            warn!("Unable to read the FOSSA project for '{integration}': {err:#?}");
+            return None;
         }
     }
-}
 
-#[tracing::instrument(skip_all)]
-async fn execute_scan_git_references<D: Database>(
-    ctx: &CmdContext<D>,
-    receiver: &Queue<ScanGitVCSReference>,
-    uploader: &Queue<UploadSourceUnits>,
-    cli: &Location,
-) -> Result<(), Error> {
-    let job = receiver.recv().await.change_context(Error::TaskReceive)?;
-    let upload = scan_git_reference(ctx, &job, cli)
-        .await
-        .change_context(Error::TaskHandle)?;
-    uploader
-        .send(&upload)
+    let org = match fossa::OrgConfig::lookup(ctx.fossa_client(), ctx.config.fossa_api()).await {
+        Ok(org) => org,
+        Err(err) => {
+            warn!("Unable to look up existing FOSSA projects for '{integration}': {err:#}");
+            return None;
+        }
+    };
+
+    // If a lookup fails, nothing is stored so that the next upload looks again.
+    let mut found = None;
+    for name in &candidates {
+        match org.find_project(ctx.fossa_client(), name).await {
+            Ok(Some(project)) => {
+                found = Some(project);
+                break;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                warn!("Unable to look up existing FOSSA project '{name}' for '{integration}': {err:#}");
+                return None;
+            }
+        }
+    }
+
+    let mapping = match found {
+        Some(project) => {
+            info!(
+                "Uploading scans of '{integration}' to existing FOSSA project '{}'",
+                project.name()
+            );
+            db::ProjectMapping::new(project.name().clone(), project.title().clone())
+        }
+        None => db::ProjectMapping::new(integration.protocol().project(), None),
+    };
+    if let Err(err) = ctx
+        .db
+        .set_project_mapping(&namespace, &repository, &mapping)
         .await
-        .change_context(Error::TaskEnqueue)
+    {
+        warn!("Unable to store the FOSSA project for '{integration}': {err:#?}");
+    }
+    Some(mapping)
 }
 
-#[tracing::instrument(skip(_ctx, cli), fields(scan_id, cli_version))]
-async fn scan_git_reference<D: Database>(
-    _ctx: &CmdContext<D>,
-    job: &ScanGitVCSReference,
-    cli: &fossa_cli::Location,
-) -> Result<UploadSourceUnits, Error> {
-    info!("Scanning '{}' at '{}'", job.integration, job.reference);
-    span_record!(scan_id, &job.scan_id);
+/// What happened to a scan which failed to upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Deferral {
+    /// The scan wasn't saved, so the reference is scanned again on the next poll.
+    Disabled,
 
-    // Clone the reference into a temporary directory.
-    let cloned_location = job
-        .integration
-        .clone_reference(&job.reference)
-        .await
-        .change_context_lazy(|| Error::CloneReference(job.reference.clone()))?;
+    /// The upload is retried later.
+    Scheduled { attempts: u32, retry_in: Duration },
 
-    // Record the CLI version for debugging purposes.
-    let cli_version = cli.version().await.change_context(Error::RunFossaCli)?;
-    span_record!(cli_version, display cli_version);
+    /// The upload first failed longer ago than the maximum age, so it's no longer retried
+    /// and the reference is scanned again on the next poll.
+    Expired { attempts: u32 },
+}
 
-    // Run the scan.
-    let source_units = cli
-        .analyze(&job.scan_id, cloned_location.path())
+/// Save a scan which failed to upload so that the upload is retried later,
+/// or give up on it if it first failed longer ago than the configured maximum age.
+#[tracing::instrument(skip_all, fields(scan_id = %job.scan_id))]
+async fn defer_upload<D: Database>(
+    ctx: &CmdContext<D>,
+    job: &UploadSourceUnits,
+) -> Result<Deferral, Error> {
+    let retry = ctx.config.upload_retry();
+    if !retry.enabled() {
+        return Ok(Deferral::Disabled);
+    }
+
+    let now = ctx.clock.now();
+    let coordinate = job.reference.as_coordinate(job.integration.remote());
+    let existing = ctx
+        .db
+        .pending_upload(&coordinate, &job.scan_id)
+        .await
+        .change_context(Error::PendingUpload)?;
+
+    let pending = match existing {
+        Some(pending) => {
+            let age = now
+                .duration_since(pending.first_failed_at())
+                .unwrap_or_default();
+            if age >= retry.max_age() {
+                expire_upload(ctx, &pending).await?;
+                return Ok(Deferral::Expired {
+                    attempts: pending.attempts(),
+                });
+            }
+
+            let attempts = pending.attempts().saturating_add(1);
+            db::PendingUpload::new(
+                job.scan_id.clone(),
+                coordinate,
+                attempts,
+                pending.first_failed_at(),
+                now + pending::retry_delay(attempts),
+            )
+        }
+        None => {
+            pending::save(
+                &ctx.uploads,
+                &job.scan_id,
+                job,
+                ctx.config.encryption().as_ref(),
+            )
+            .await?;
+            db::PendingUpload::new(
+                job.scan_id.clone(),
+                coordinate,
+                1,
+                now,
+                now + pending::retry_delay(1),
+            )
+        }
+    };
+    ctx.db
+        .set_pending_upload(&pending)
         .await
-        .change_context(Error::RunFossaCli)?;
+        .change_context(Error::PendingUpload)?;
+
+    // The scan is saved, so there's no need to scan the reference again unless the upload expires.
+    if pending.attempts() == 1 {
+        mark_scanned(ctx, &job.integration, &job.reference).await?;
+    }
 
+    let retry_in = pending::retry_delay(pending.attempts());
     info!(
-        "Scanned '{}' at '{}', enqueueing for upload",
-        job.integration, job.reference
+        "Retrying upload of scan '{}' for '{}' at '{}' in {}",
+        job.scan_id,
+        job.integration,
+        job.reference,
+        humantime::format_duration(retry_in)
     );
-    Ok(UploadSourceUnits {
-        cli: CliMetadata::new(cli_version),
-        integration: job.integration.clone(),
-        reference: job.reference.clone(),
-        scan_id: job.scan_id.clone(),
-        source_units,
+    Ok(Deferral::Scheduled {
+        attempts: pending.attempts(),
+        retry_in,
     })
 }
 
-#[tracing::instrument(skip_all)]
-async fn upload_scans<D: Database>(
+/// Stop retrying an upload, and forget that the reference was scanned so that it's scanned again on the next poll.
+async fn expire_upload<D: Database>(
     ctx: &CmdContext<D>,
-    receiver: &Queue<UploadSourceUnits>,
+    pending: &db::PendingUpload,
 ) -> Result<(), Error> {
-    // This worker is per integration, so the rate limiter should be constructed here instead of globally.
-    let quota = Quota::per_minute(nonzero!(1u32));
-    let limiter = RateLimiter::direct(quota);
+    warn!(
+        "Giving up on uploading scan '{}' after {} attempts",
+        pending.scan_id(),
+        pending.attempts()
+    );
+    forget_pending_upload(ctx, pending.scan_id()).await;
+    ctx.db
+        .delete_state(pending.coordinate())
+        .await
+        .change_context(Error::TaskDeleteState)
+}
+
+/// Remove the saved scan and its retry schedule, if the upload was ever deferred.
+///
+/// Failing to remove them isn't fatal: a saved scan which can't be uploaded again is eventually expired.
+async fn forget_pending_upload<D: Database>(ctx: &CmdContext<D>, scan_id: &str) {
+    if let Err(err) = ctx.db.delete_pending_upload(scan_id).await {
+        warn!("Unable to remove retry schedule for scan '{scan_id}': {err:#?}");
+    }
+    if let Err(err) = pending::remove(&ctx.uploads, scan_id).await {
+        warn!("Unable to remove saved scan '{scan_id}': {err:#?}");
+    }
+}
 
+/// How often pending uploads are checked to see whether they're due to be retried.
+const UPLOAD_RETRY_PERIOD: Duration = Duration::from_secs(60);
+
+/// Periodically send scans which previously failed to upload back to the upload queue once they're due to be retried.
+///
+/// Since the schedule is stored in the database, uploads which were pending when Broker stopped are retried after it restarts.
+#[tracing::instrument(skip(ctx, upload))]
+async fn retry_uploads<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+    upload: &Queue<UploadSourceUnits>,
+) -> Result<(), Error> {
+    if !ctx.config.upload_retry().enabled() {
+        return Ok(());
+    }
+
+    let namespace = integration.namespace();
+    let repository = integration.repository();
     loop {
-        let job = match receiver.recv().await.change_context(Error::TaskReceive) {
-            Ok(job) => job,
+        let now = ctx.clock.now();
+        let due = match ctx
+            .db
+            .due_pending_uploads(&namespace, &repository, now)
+            .await
+        {
+            Ok(due) => due,
             Err(err) => {
-                warn!("Unable to read enqueued upload job: {err:#?}");
-                continue;
+                warn!("Unable to read pending uploads for '{integration}': {err:#?}");
+                Vec::new()
             }
         };
 
-        let meta = ProjectMetadata::new(&job.integration, &job.reference);
-        if limiter.check().is_err() {
-            info!("Integration '{meta}': waiting for rate limit");
-            limiter.until_ready().await;
+        for pending in due {
+            if let Err(err) = enqueue_retry(ctx, upload, pending, now).await {
+                warn!("Unable to retry upload for '{integration}': {err:#?}");
+            }
         }
 
-        if let Err(err) = execute_upload_scans(ctx, &meta, job).await {
-            warn!("Unable to upload scan for '{meta}': {err:#?}");
+        if !ctx.sleep(UPLOAD_RETRY_PERIOD).await {
+            return Ok(());
         }
     }
 }
 
-#[tracing::instrument(skip_all)]
-async fn execute_upload_scans<D: Database>(
+/// Send a saved scan back to the upload queue.
+/// If the saved scan can't be read, the upload is expired so that the reference is scanned again.
+#[tracing::instrument(skip(ctx, upload, pending), fields(scan_id = %pending.scan_id()))]
+async fn enqueue_retry<D: Database>(
     ctx: &CmdContext<D>,
-    meta: &ProjectMetadata,
-    job: UploadSourceUnits,
+    upload: &Queue<UploadSourceUnits>,
+    pending: db::PendingUpload,
+    now: SystemTime,
 ) -> Result<(), Error> {
-    info!("Uploading scan for project: '{meta}'");
-    let locator = fossa::upload_scan(ctx.config.fossa_api(), meta, &job.cli, job.source_units)
+    // Push back the next attempt before enqueueing the upload, so that it isn't enqueued again while it waits.
+    // If the upload fails again, its schedule is updated with the new attempt.
+    let delay = pending::retry_delay(pending.attempts());
+    let leased = pending.clone().with_next_attempt_at(now + delay);
+    ctx.db
+        .set_pending_upload(&leased)
         .await
-        .change_context(Error::TaskHandle)?;
-
-    debug!(scan_id = %job.scan_id, locator = %locator, "Uploaded scan");
-    info!("Uploaded scan for project '{meta}' as locator: '{locator}'");
-
-    let remote = job.integration.remote().to_owned();
-    let coordinate = job.reference.as_coordinate(&remote);
-    let state = job.reference.as_state();
-    let is_branch = match &job.reference {
-        Reference::Git(git_reference) => match git_reference {
-            git::Reference::Branch { .. } => true,
-            git::Reference::Tag { .. } => false,
-        },
-    };
+        .change_context(Error::PendingUpload)?;
+
+    let encryption = ctx.config.encryption().as_ref();
+    match pending::load::<UploadSourceUnits>(&ctx.uploads, pending.scan_id(), encryption).await {
+        Ok(job) => {
+            info!(
+                "Retrying upload of scan '{}' for '{}' at '{}'",
+                job.scan_id, job.integration, job.reference
+            );
+            upload.send(&job).await.change_context(Error::TaskEnqueue)
+        }
+        Err(err) => {
+            warn!("Unable to read saved scan, so it will be scanned again: {err:#?}");
+            expire_upload(ctx, &pending).await
+        }
+    }
+}
 
-    // Mark this reference as scanned in the local DB.
+/// Mark the reference as scanned in the local DB, so that it isn't scanned again until it changes.
+async fn mark_scanned<D: Database>(
+    ctx: &CmdContext<D>,
+    integration: &Integration,
+    reference: &Reference,
+) -> Result<(), Error> {
+    let coordinate = reference.as_coordinate(integration.remote());
     ctx.db
-        .set_state(&coordinate, state, &is_branch)
+        .set_state(&coordinate, reference.as_state(), &reference.is_branch())
+        .await
+        .change_context(Error::TaskSetState)?;
+
+    // Branch history is only used to detect rewrites, so failing to record it isn't fatal.
+    if let Some(branch) = reference.branch() {
+        let recorded = ctx
+            .db
+            .record_branch_state(
+                &integration.namespace(),
+                &integration.repository(),
+                branch,
+                reference.as_state(),
+            )
+            .await;
+        if let Err(err) = recorded {
+            warn!("Unable to record history of '{integration}' at '{branch}': {err:#?}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str, commit: &str) -> Reference {
+        Reference::Git(git::Reference::new_tag(
+            name.to_string(),
+            commit.to_string(),
+        ))
+    }
+
+    fn branch(name: &str, head: &str) -> Reference {
+        Reference::Git(git::Reference::new_branch(
+            name.to_string(),
+            head.to_string(),
+        ))
+    }
+
+    #[test]
+    fn batches_references_at_the_same_commit() {
+        let batches = batch_by_commit(vec![
+            tag("v1.0.0", "abcd"),
+            branch("main", "ef01"),
+            tag("v1.0.0-hotfix", "abcd"),
+            branch("release", "abcd"),
+        ]);
+        assert_eq!(
+            batches,
+            vec![
+                vec![
+                    branch("release", "abcd"),
+                    tag("v1.0.0", "abcd"),
+                    tag("v1.0.0-hotfix", "abcd"),
+                ],
+                vec![branch("main", "ef01")],
+            ]
+        );
+    }
+
+    /// Load a config with an integration which polls between 22:00 and 04:00 UTC,
+    /// and which writes debug artifacts inside `root`.
+    async fn poll_window_config(root: &Path) -> Config {
+        let debug = root.join("debug");
+        let content = std::fs::read_to_string("testdata/config/basic-poll-window.yml")
+            .expect("must read config")
+            .replace(
+                "/home/me/.config/fossa/broker/debugging/",
+                &debug.display().to_string(),
+            );
+        let path = root.join("config.yml");
+        std::fs::write(&path, content).expect("must write config");
+        Config::load(&path).await.expect("must load config")
+    }
+
+    #[tokio::test]
+    async fn waits_on_the_clock_for_the_poll_window() {
+        let root = tempfile::tempdir().expect("must create temp dir");
+        // Noon UTC, ten hours before the poll window opens.
+        let clock = Clock::manual(SystemTime::UNIX_EPOCH + Duration::from_secs(12 * 60 * 60));
+        let app = AppContext::new(root.path().to_path_buf())
+            .expect("must create context")
+            .with_clock(clock.clone());
+        let config = poll_window_config(root.path()).await;
+        let integration = config
+            .integrations()
+            .iter()
+            .next()
+            .expect("must configure integration")
+            .clone();
+        let ctx = CmdContext::new(
+            &app,
+            config,
+            db::memory::Database::new(),
+            CancellationToken::new(),
+        );
+
+        let waiting = tokio::spawn(async move { ctx.wait_for_poll_window(&integration).await });
+        tokio::task::yield_now().await;
+
+        clock.advance(Duration::from_secs(10 * 60 * 60 - 1));
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished(), "must wait for the window to open");
+
+        // Once the window opens, polls are spread out by up to the maximum jitter.
+        clock.advance(MAX_POLL_JITTER);
+        let waited = waiting.await.expect("must finish waiting");
+        assert!(waited, "must not be cancelled");
+    }
+
+    #[tokio::test]
+    async fn removes_debug_bundles_once_retention_elapses_on_the_clock() {
+        let root = tempfile::tempdir().expect("must create temp dir");
+        let clock = Clock::manual(SystemTime::now());
+        let app = AppContext::new(root.path().to_path_buf())
+            .expect("must create context")
+            .with_clock(clock.clone());
+        let config = poll_window_config(root.path()).await;
+        let location = config.debug().location();
+        std::fs::create_dir_all(location.as_path()).expect("must create debug location");
+        let bundle = location.debug_bundle("some scan");
+        std::fs::write(&bundle, "bundle").expect("must write bundle");
+
+        let cancel = CancellationToken::new();
+        let ctx = CmdContext::new(&app, config, db::memory::Database::new(), cancel.clone());
+        let worker = tokio::spawn(async move { debug_retention(&ctx).await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(bundle.exists(), "must retain recent bundles");
+
+        // FOSSA CLI debug bundles are retained for seven days by default.
+        clock.advance(Duration::from_secs(8 * 24 * 60 * 60));
+        let removed = async {
+            while bundle.exists() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(10), removed)
+            .await
+            .expect("must remove expired bundles");
+
+        cancel.cancel();
+        worker
+            .await
+            .expect("worker must not panic")
+            .expect("worker must stop");
+    }
+
+    #[tokio::test]
+    async fn redelivers_follow_ups_after_restart() {
+        let root = tempfile::tempdir().expect("must create temp dir");
+        let app = AppContext::new(root.path().to_path_buf()).expect("must create context");
+        let config = Config::load(Path::new(
+            "testdata/config/basic-http-no-auth-empty-repo.yml",
+        ))
         .await
-        .change_context(Error::TaskSetState)
+        .expect("must load config");
+        let integration = config
+            .integrations()
+            .iter()
+            .next()
+            .expect("must configure integration")
+            .clone();
+        let db = db::memory::Database::new();
+        let follow_up = FollowUp {
+            scan_id: String::from("some scan"),
+            integration: integration.clone(),
+            reference: branch("main", "abcd"),
+            locator: String::from("custom+1/github.com/fossas/empty$abcd"),
+            target: None,
+        };
+
+        // The first run enqueues the follow up, then stops before following up.
+        let first = CmdContext::new(&app, config.clone(), db.clone(), CancellationToken::new());
+        let follow_ups = Queue::default();
+        enqueue_follow_up(&first, &follow_ups, &follow_up)
+            .await
+            .expect("must enqueue follow up");
+        drop(follow_ups);
+
+        // The next run redelivers it to be followed up on, rather than scanning it again.
+        let next = CmdContext::new(&app, config, db.clone(), CancellationToken::new());
+        let scheduler = Scheduler::new([nonzero!(1u32)]);
+        let follow_ups = Queue::default();
+        redeliver_queued(&next, &integration, &scheduler.sender(0), &follow_ups)
+            .await
+            .expect("must redeliver jobs");
+
+        let redelivered = follow_ups
+            .try_recv()
+            .expect("must redeliver follow up")
+            .expect("must read follow up")
+            .commit();
+        assert_eq!(redelivered.scan_id, follow_up.scan_id);
+        assert_eq!(redelivered.reference, follow_up.reference);
+        assert_eq!(redelivered.locator, follow_up.locator);
+
+        let jobs = db.queued_jobs().await.expect("must read jobs");
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].stage(), JobStage::FollowUp);
+        let backlog = db
+            .backlog(&integration.namespace(), &integration.repository())
+            .await
+            .expect("must read backlog");
+        assert!(backlog.is_none(), "follow ups must not be scanned again");
+    }
+
+    #[tokio::test]
+    async fn leases_every_reference_in_a_batch_or_none() {
+        let root = tempfile::tempdir().expect("must create temp dir");
+        let app = AppContext::new(root.path().to_path_buf()).expect("must create context");
+        let config = poll_window_config(root.path()).await;
+        let integration = config
+            .integrations()
+            .iter()
+            .next()
+            .expect("must configure integration")
+            .clone();
+        let db = db::memory::Database::new();
+        let ctx = CmdContext::new(&app, config, db.clone(), CancellationToken::new());
+        let batch = vec![branch("main", "abcd"), tag("v1.0.0", "abcd")];
+
+        // Another Broker is scanning the tag, so none of the batch is leased.
+        let now = ctx.clock.now();
+        let tagged = batch[1].as_coordinate(integration.remote());
+        let taken = db
+            .acquire_scan_lease(&tagged, "another", now, now + SCAN_LEASE_DURATION)
+            .await
+            .expect("must take lease");
+        assert!(taken);
+        assert!(!acquire_leases(&ctx, &integration, &batch).await);
+        let main = batch[0].as_coordinate(integration.remote());
+        let taken = db
+            .acquire_scan_lease(&main, "another", now, now + SCAN_LEASE_DURATION)
+            .await
+            .expect("must take lease");
+        assert!(taken, "must release the leases already taken");
+
+        db.release_scan_lease(&tagged, "another")
+            .await
+            .expect("must release lease");
+        db.release_scan_lease(&main, "another")
+            .await
+            .expect("must release lease");
+        assert!(acquire_leases(&ctx, &integration, &batch).await);
+        let taken = db
+            .acquire_scan_lease(&tagged, "another", now, now + SCAN_LEASE_DURATION)
+            .await
+            .expect("must take lease");
+        assert!(!taken, "must lease every reference in the batch");
+    }
+
+    #[tokio::test]
+    async fn carries_over_annotated_tags_recorded_at_their_tag_object() {
+        let db = db::memory::Database::new();
+        let remote = Remote::new(String::from("https://github.com/fossas/broker.git"));
+        let annotated = Reference::Git(git::Reference::Tag {
+            name: String::from("v1"),
+            commit: String::from("abcd"),
+            object: Some(String::from("ef01")),
+        });
+        let (legacy, state) = annotated
+            .legacy_coordinate(&remote)
+            .expect("must have legacy coordinate");
+        db.set_state(&legacy, state, &false)
+            .await
+            .expect("must set state");
+
+        let references = vec![annotated.clone(), tag("v2", "2345")];
+        let states = adopt_legacy_states(&db, &remote, &references, vec![None, None])
+            .await
+            .expect("must carry over states");
+        assert_eq!(states, vec![Some(b"abcd".to_vec()), None]);
+
+        let current = annotated.as_coordinate(&remote);
+        let moved = db.state(&current).await.expect("must read state");
+        assert_eq!(moved.as_deref(), Some(b"abcd".as_slice()));
+        let legacy = db.state(&legacy).await.expect("must read state");
+        assert!(legacy.is_none(), "must move the legacy state");
+    }
 }
@@ -0,0 +1,230 @@
+//! The routes of the admin API, served while Broker runs if it's configured; see [`crate::admin`].
+//!
+//! The routes perform the same operations as the `status`, `pause`, `resume`, and `queue ls` subcommands,
+//! and can also request that an integration is polled immediately instead of at its next poll interval.
+
+use std::time::SystemTime;
+
+use error_stack::{Report, ResultExt};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::{
+    admin::{self, Request, Response, Status},
+    cmd::{pause, queue},
+    db::{Database, PauseScope},
+};
+
+use super::{expanded_integrations, CmdContext, Error};
+
+/// Serve the admin API until Broker stops, if it's configured.
+pub(super) async fn serve<D: Database>(ctx: &CmdContext<D>) -> Result<(), Report<Error>> {
+    let Some(config) = ctx.config.admin_api() else {
+        return Ok(());
+    };
+
+    let server = admin::Server::bind(config)
+        .await
+        .change_context(Error::AdminApi)?;
+    server
+        .serve(&ctx.cancel, |request| route(ctx, request))
+        .await;
+    Ok(())
+}
+
+/// Handle the request with the route for its method and path.
+async fn route<D: Database>(ctx: &CmdContext<D>, request: Request) -> Response {
+    let handled = match (request.method().as_str(), request.path().as_str()) {
+        ("GET", "/v1/status") => status(ctx).await,
+        ("GET", "/v1/queue") => list_queue(ctx).await,
+        ("POST", "/v1/pause") => pause(ctx, &request).await,
+        ("POST", "/v1/resume") => resume(ctx, &request).await,
+        ("POST", "/v1/scan") => scan(ctx, &request).await,
+        (_, "/v1/status" | "/v1/queue" | "/v1/pause" | "/v1/resume" | "/v1/scan") => {
+            return Response::error(
+                Status::MethodNotAllowed,
+                format!(
+                    "'{}' doesn't support '{}'",
+                    request.path(),
+                    request.method()
+                ),
+            )
+        }
+        (_, path) => return Response::error(Status::NotFound, format!("no route for '{path}'")),
+    };
+
+    handled.unwrap_or_else(|err| {
+        warn!(
+            "Admin API: unable to handle {} {}: {err:#?}",
+            request.method(),
+            request.path()
+        );
+        Response::error(Status::InternalServerError, format!("{err:#}"))
+    })
+}
+
+/// Progress through the backlog of each integration, whether it's paused, and whether its code host could be reached.
+async fn status<D: Database>(ctx: &CmdContext<D>) -> Result<Response, Report<Error>> {
+    let mut integrations = Vec::new();
+    for integration in expanded_integrations(&ctx.config).await {
+        let (namespace, repository) = (integration.namespace(), integration.repository());
+        let backlog = ctx
+            .db
+            .backlog(&namespace, &repository)
+            .await
+            .change_context(Error::AdminApi)?;
+        let paused = ctx
+            .db
+            .is_paused(&namespace, &repository)
+            .await
+            .change_context(Error::AdminApi)?;
+        let connectivity = ctx
+            .db
+            .connectivity(&namespace, &repository)
+            .await
+            .change_context(Error::AdminApi)?;
+        integrations.push(json!({
+            "remote": integration.remote().to_string(),
+            "paused": paused,
+            "connectivity": connectivity.map(|connectivity| json!({
+                "reachable": connectivity.reachable(),
+                "checked_at": timestamp(connectivity.checked_at()),
+            })),
+            "backlog": backlog.map(|backlog| json!({
+                "total": backlog.total(),
+                "completed": backlog.completed(),
+                "started_at": timestamp(backlog.started_at()),
+                "updated_at": timestamp(backlog.updated_at()),
+            })),
+        }));
+    }
+    Ok(Response::ok(json!({ "integrations": integrations })))
+}
+
+/// The jobs enqueued by Broker, and the scans waiting to be uploaded again.
+async fn list_queue<D: Database>(ctx: &CmdContext<D>) -> Result<Response, Report<Error>> {
+    let jobs = queue::jobs(&ctx.config, &ctx.db)
+        .await
+        .change_context(Error::AdminApi)?
+        .into_iter()
+        .map(|job| {
+            json!({
+                "id": job.id,
+                "stage": job.stage,
+                "integration": job.integration,
+                "reference": job.reference,
+                "since": timestamp(job.since),
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(Response::ok(json!({ "jobs": jobs })))
+}
+
+/// Pause the integration named by the request, or every integration.
+async fn pause<D: Database>(
+    ctx: &CmdContext<D>,
+    request: &Request,
+) -> Result<Response, Report<Error>> {
+    let scope = match scope(ctx, request) {
+        Ok(scope) => scope,
+        Err(response) => return Ok(response),
+    };
+    ctx.db
+        .pause(&scope, SystemTime::now())
+        .await
+        .change_context(Error::AdminApi)?;
+    Ok(Response::ok(json!({ "paused": true })))
+}
+
+/// Resume the integration named by the request, or every integration.
+///
+/// An integration which is still paused along with every integration is reported as paused.
+async fn resume<D: Database>(
+    ctx: &CmdContext<D>,
+    request: &Request,
+) -> Result<Response, Report<Error>> {
+    let scope = match scope(ctx, request) {
+        Ok(scope) => scope,
+        Err(response) => return Ok(response),
+    };
+    let resumed = ctx
+        .db
+        .resume(&scope)
+        .await
+        .change_context(Error::AdminApi)?;
+    let paused = match &scope {
+        PauseScope::All => false,
+        PauseScope::Repository(namespace, repository) => ctx
+            .db
+            .is_paused(namespace, repository)
+            .await
+            .change_context(Error::AdminApi)?,
+    };
+    Ok(Response::ok(
+        json!({ "resumed": resumed, "paused": paused }),
+    ))
+}
+
+/// Request that the integration named by the request is polled now, instead of at its next poll interval.
+///
+/// Paused integrations are still skipped when they'd be polled.
+async fn scan<D: Database>(
+    ctx: &CmdContext<D>,
+    request: &Request,
+) -> Result<Response, Report<Error>> {
+    let Some(remote) = request.param("integration") else {
+        return Ok(Response::error(
+            Status::BadRequest,
+            "provide the remote of the integration to scan with the 'integration' parameter",
+        ));
+    };
+    let integrations = expanded_integrations(&ctx.config).await;
+    let Some(integration) = integrations
+        .iter()
+        .find(|integration| integration.remote().to_string() == remote)
+    else {
+        return Ok(Response::error(
+            Status::NotFound,
+            format!("integration '{remote}' is not configured"),
+        ));
+    };
+
+    // Sending only fails if no integration is waiting to poll, in which case one is about to poll anyway.
+    let _ = ctx.poll_requests.send(remote.to_string());
+    let paused = ctx
+        .db
+        .is_paused(&integration.namespace(), &integration.repository())
+        .await
+        .change_context(Error::AdminApi)?;
+    Ok(Response::new(
+        Status::Accepted,
+        json!({ "requested": remote, "paused": paused }),
+    ))
+}
+
+/// The scope of the pause named by the request: the integration in its `integration` parameter,
+/// or every integration if its `all` parameter is `true`.
+///
+/// Requests naming neither, both, or an integration that isn't configured are answered with the returned response.
+fn scope<D>(ctx: &CmdContext<D>, request: &Request) -> Result<PauseScope, Response> {
+    let integration = request.param("integration");
+    let all = request.param("all") == Some("true");
+    if integration.is_some() == all {
+        return Err(Response::error(
+            Status::BadRequest,
+            "provide either the remote of an integration with the 'integration' parameter, or 'all=true'",
+        ));
+    }
+
+    pause::scope(&ctx.config, integration).map_err(|err| match err.current_context() {
+        pause::Error::IntegrationNotFound(..) => {
+            Response::error(Status::NotFound, err.current_context())
+        }
+        _ => Response::error(Status::InternalServerError, format!("{err:#}")),
+    })
+}
+
+/// Format the time for responses.
+fn timestamp(time: SystemTime) -> Value {
+    Value::from(humantime::format_rfc3339_seconds(time).to_string())
+}
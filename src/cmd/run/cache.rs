@@ -0,0 +1,193 @@
+//! The source units produced by analyzing a tree are cached by the hash of the tree,
+//! so that re-tagged or re-pushed identical trees skip analysis and upload the cached results instead.
+//!
+//! Entries are content addressed: each is a file named after a hash of everything that affects the results of analysis,
+//! which is the tree along with the FOSSA CLI version and the integration's analysis settings.
+//! Entries are removed once they're older than [`MAX_AGE`]; a tree analyzed after that is analyzed again.
+//! Entries are encrypted if an encryption key is configured (see [`crate::encryption`]).
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use error_stack::{Result, ResultExt};
+use tracing::warn;
+
+use crate::{
+    api::remote::Integration,
+    encryption::{self, Key},
+    ext::{
+        error_stack::{DescribeContext, IntoContext},
+        sha2,
+    },
+    fossa_cli::{SourceUnits, Version},
+};
+
+use super::Error;
+
+/// How long entries are kept after they're written.
+pub const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// The key under which the results of analyzing the tree are cached.
+///
+/// Environment variables are part of the key since they may change analysis, like `GOFLAGS`;
+/// their values are hashed into the key, so secrets aren't written in the clear.
+pub fn key(tree: &str, cli_version: &Version, integration: &Integration) -> String {
+    let options = serde_json::to_string(integration.cli_options()).unwrap_or_default();
+    let env = serde_json::to_string(integration.cli_env()).unwrap_or_default();
+    let components = [
+        tree.to_string(),
+        cli_version.to_string(),
+        integration.scan_mode().to_string(),
+        options,
+        env,
+    ];
+    sha2::sha256(components.join("\n").as_bytes())
+}
+
+/// The location of the entry for the key.
+fn location(root: &Path, key: &str) -> PathBuf {
+    root.join(format!("{key}.json"))
+}
+
+/// Load the cached results for the key, if there are any.
+///
+/// Entries which can't be read are treated as missing, since the tree can be analyzed again; this is logged.
+pub async fn load(root: &Path, key: &str, encryption: Option<&Key>) -> Option<SourceUnits> {
+    let path = location(root, key);
+    let content = match tokio::fs::read(&path).await {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(err) => {
+            warn!(
+                "Unable to read cached analysis at '{}': {err:#}",
+                path.display()
+            );
+            return None;
+        }
+    };
+
+    let content = match encryption::open(encryption, content) {
+        Ok(content) => content,
+        Err(err) => {
+            warn!(
+                "Unable to decrypt cached analysis at '{}': {err:#}",
+                path.display()
+            );
+            return None;
+        }
+    };
+
+    match serde_json::from_slice(&content) {
+        Ok(source_units) => Some(source_units),
+        Err(err) => {
+            warn!(
+                "Unable to parse cached analysis at '{}': {err:#}",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+/// Cache the results of analysis under the key.
+///
+/// The entry is written next to its final location and then moved into place,
+/// so that an interrupted write isn't mistaken for a complete entry.
+pub async fn save(
+    root: &Path,
+    key: &str,
+    source_units: &SourceUnits,
+    encryption: Option<&Key>,
+) -> Result<(), Error> {
+    let path = location(root, key);
+    let staged = path.with_extension("new");
+    let content = serde_json::to_vec(source_units)
+        .context(Error::AnalysisCache)
+        .describe("serialize scan results")?;
+    let content = encryption::seal(encryption, content)
+        .change_context(Error::AnalysisCache)
+        .describe("encrypt scan results")?;
+
+    tokio::fs::create_dir_all(root)
+        .await
+        .context(Error::AnalysisCache)
+        .describe_lazy(|| format!("create directory '{}'", root.display()))?;
+    tokio::fs::write(&staged, content)
+        .await
+        .context(Error::AnalysisCache)
+        .describe_lazy(|| format!("write scan results to '{}'", staged.display()))?;
+    tokio::fs::rename(&staged, &path)
+        .await
+        .context(Error::AnalysisCache)
+        .describe_lazy(|| format!("move scan results to '{}'", path.display()))
+}
+
+/// Remove entries written longer than [`MAX_AGE`] before `now`, returning how many were removed.
+pub fn prune(root: &Path, now: SystemTime) -> Result<usize, Error> {
+    let entries = match std::fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => {
+            return Err(err)
+                .context(Error::AnalysisCache)
+                .describe_lazy(|| format!("list directory '{}'", root.display()))
+        }
+    };
+
+    let mut removed = 0;
+    for entry in entries {
+        let path = entry
+            .context(Error::AnalysisCache)
+            .describe_lazy(|| format!("list directory '{}'", root.display()))?
+            .path();
+        let modified = std::fs::metadata(&path).and_then(|meta| meta.modified());
+        let expired = match modified {
+            Ok(modified) => now.duration_since(modified).unwrap_or_default() > MAX_AGE,
+            Err(err) => {
+                warn!(
+                    "Unable to read age of cached analysis at '{}': {err:#}",
+                    path.display()
+                );
+                continue;
+            }
+        };
+        if expired {
+            std::fs::remove_file(&path)
+                .context(Error::AnalysisCache)
+                .describe_lazy(|| format!("remove cached analysis at '{}'", path.display()))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn round_trips_and_prunes() {
+        let root = tempfile::tempdir().expect("must create tempdir");
+        let source_units = serde_json::from_str::<SourceUnits>(r#"[{"Name": "broker"}]"#)
+            .expect("must parse source units");
+
+        assert!(load(root.path(), "abcd", None).await.is_none());
+        save(root.path(), "abcd", &source_units, None)
+            .await
+            .expect("must save source units");
+        let loaded = load(root.path(), "abcd", None)
+            .await
+            .expect("must load source units");
+        assert_eq!(loaded.to_string(), source_units.to_string());
+
+        assert_eq!(
+            prune(root.path(), SystemTime::now()).expect("must prune"),
+            0
+        );
+        let later = SystemTime::now() + MAX_AGE + Duration::from_secs(60);
+        assert_eq!(prune(root.path(), later).expect("must prune"), 1);
+        assert!(load(root.path(), "abcd", None).await.is_none());
+    }
+}
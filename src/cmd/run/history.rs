@@ -0,0 +1,139 @@
+//! Slow scan detection, based on the scan history.
+//!
+//! Each completed scan records how long its reference took to clone and to analyze.
+//! When either takes much longer than the recent average for the integration it's reported,
+//! since this usually means that something about the repository changed
+//! in a way that makes it pathological to scan.
+
+use std::{fmt::Display, time::Duration};
+
+use crate::{api::remote::SlowScanMultiple, db::ScanRecord};
+
+/// The number of recent scans of an integration that are averaged to detect slow scans.
+pub const WINDOW: u32 = 20;
+
+/// Slow scans are only detected once an integration has at least this many scans in its history,
+/// so that a handful of unusually fast scans don't make the next normal scan look slow.
+pub const MIN_SAMPLES: usize = 5;
+
+/// The stages of a scan for which durations are tracked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+pub enum Stage {
+    /// Cloning the reference.
+    #[strum(serialize = "clone")]
+    Clone,
+
+    /// Analyzing the reference.
+    #[strum(serialize = "analysis")]
+    Analyze,
+}
+
+/// A stage of a scan which took much longer than the recent average.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowScan {
+    stage: Stage,
+    duration: Duration,
+    average: Duration,
+}
+
+impl Display for SlowScan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ratio = self.duration.as_secs_f64() / self.average.as_secs_f64();
+        write!(
+            f,
+            "{} took {}, {ratio:.1}x the recent average of {}",
+            self.stage,
+            humantime::format_duration(whole_seconds(self.duration)),
+            humantime::format_duration(whole_seconds(self.average)),
+        )
+    }
+}
+
+/// Report the stages of `scan` which took longer than `multiple` times their average across `history`.
+pub fn detect(
+    scan: &ScanRecord,
+    history: &[ScanRecord],
+    multiple: SlowScanMultiple,
+) -> Vec<SlowScan> {
+    if history.len() < MIN_SAMPLES {
+        return Vec::new();
+    }
+
+    [
+        (
+            Stage::Clone,
+            ScanRecord::clone_duration as fn(&ScanRecord) -> Duration,
+        ),
+        (Stage::Analyze, ScanRecord::analyze_duration),
+    ]
+    .into_iter()
+    .filter_map(|(stage, duration_of)| {
+        let total = history.iter().map(duration_of).sum::<Duration>();
+        let average = total.div_f64(history.len() as f64);
+        let duration = duration_of(scan);
+
+        // Very short stages vary wildly relative to their average and aren't worth reporting.
+        let is_slow = !average.is_zero()
+            && duration >= MIN_REPORTED
+            && duration.as_secs_f64() > average.as_secs_f64() * multiple.as_f64();
+        is_slow.then_some(SlowScan {
+            stage,
+            duration,
+            average,
+        })
+    })
+    .collect()
+}
+
+/// Stages which take less than this are never reported as slow.
+const MIN_REPORTED: Duration = Duration::from_secs(60);
+
+fn whole_seconds(duration: Duration) -> Duration {
+    Duration::from_secs(duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(clone_secs: u64, analyze_secs: u64) -> ScanRecord {
+        ScanRecord::new(
+            String::from("scan"),
+            Duration::from_secs(clone_secs),
+            Duration::from_secs(analyze_secs),
+        )
+    }
+
+    #[test]
+    fn detects_slow_analysis() {
+        let history = vec![scan(60, 120); MIN_SAMPLES];
+        let slow = detect(&scan(60, 600), &history, SlowScanMultiple::DEFAULT);
+        assert_eq!(
+            slow,
+            vec![SlowScan {
+                stage: Stage::Analyze,
+                duration: Duration::from_secs(600),
+                average: Duration::from_secs(120),
+            }]
+        );
+        assert_eq!(
+            slow[0].to_string(),
+            "analysis took 10m, 5.0x the recent average of 2m"
+        );
+    }
+
+    #[test]
+    fn ignores_normal_and_short_scans() {
+        let history = vec![scan(60, 120); MIN_SAMPLES];
+        assert!(detect(&scan(90, 300), &history, SlowScanMultiple::DEFAULT).is_empty());
+
+        let history = vec![scan(1, 2); MIN_SAMPLES];
+        assert!(detect(&scan(10, 20), &history, SlowScanMultiple::DEFAULT).is_empty());
+    }
+
+    #[test]
+    fn requires_minimum_history() {
+        let history = vec![scan(60, 120); MIN_SAMPLES - 1];
+        assert!(detect(&scan(600, 1200), &history, SlowScanMultiple::DEFAULT).is_empty());
+    }
+}
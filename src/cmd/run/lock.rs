@@ -0,0 +1,129 @@
+//! Ensures only one instance of `broker run` uses a data root at a time.
+//!
+//! Broker's database and scan queue assume they're owned by a single process;
+//! two instances running against the same data root race each other and corrupt that state.
+//! To prevent this, `broker run` holds an advisory lock on a file in the data root for as long as it runs.
+//!
+//! The lock is released by the operating system when the process exits, even if it crashes,
+//! so a stale lock file left on disk never blocks a later instance.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use error_stack::{report, Result};
+use fs2::FileExt;
+use tracing::{debug, warn};
+
+use crate::ext::{
+    error_stack::{DescribeContext, ErrorHelper, IntoContext},
+    result::WrapErr,
+};
+
+use super::Error;
+
+/// The name of the lock file, relative to the data root.
+pub const LOCK_FILE: &str = "broker.lock";
+
+/// When set to `true` or `1`, `broker run` does not take the instance lock.
+pub const DISABLE_INSTANCE_LOCK_VAR: &str = "DISABLE_INSTANCE_LOCK";
+
+/// The instance lock on a data root, released when dropped.
+#[derive(Debug)]
+pub struct InstanceLock {
+    /// The locked file; `None` if locking is disabled.
+    file: Option<File>,
+}
+
+impl InstanceLock {
+    /// Lock the data root for this instance.
+    ///
+    /// Fails if another instance already holds the lock.
+    pub fn acquire(data_root: &Path) -> Result<Self, Error> {
+        if !lock_enabled() {
+            warn!("Instance lock disabled via '{DISABLE_INSTANCE_LOCK_VAR}' env var; make sure no other instance uses '{}'", data_root.display());
+            return Ok(Self { file: None });
+        }
+
+        let path = data_root.join(LOCK_FILE);
+        std::fs::create_dir_all(data_root)
+            .context_lazy(|| Error::InstanceLock(path.clone()))
+            .describe_lazy(|| format!("create data root '{}'", data_root.display()))?;
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .context_lazy(|| Error::InstanceLock(path.clone()))
+            .describe_lazy(|| format!("open lock file '{}'", path.display()))?;
+
+        if file.try_lock_exclusive().is_err() {
+            let help = locked_help(&mut file, &path);
+            return report!(Error::InstanceLocked(path)).wrap_err().help(help);
+        }
+
+        // The PID is only informational: it's shown to the user if another instance fails to take the lock.
+        let pid = std::process::id();
+        file.set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| write!(file, "{pid}"))
+            .and_then(|_| file.flush())
+            .context_lazy(|| Error::InstanceLock(path.clone()))
+            .describe_lazy(|| format!("write pid to lock file '{}'", path.display()))?;
+
+        debug!("Acquired instance lock at '{}'", path.display());
+        Ok(Self { file: Some(file) })
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        if let Some(file) = &self.file {
+            if let Err(err) = file.unlock() {
+                warn!("Unable to release instance lock: {err:#}");
+            }
+        }
+    }
+}
+
+/// Explain to the user how to resolve the lock being held by another instance.
+fn locked_help(file: &mut File, path: &Path) -> String {
+    let mut holder = String::new();
+    let holder = match file.read_to_string(&mut holder) {
+        Ok(_) if !holder.trim().is_empty() => format!(" (pid {})", holder.trim()),
+        _ => String::new(),
+    };
+
+    format!(
+        "another instance of 'broker run'{holder} is using this data root. Stop that instance, or use a different data root with '--data-root'. If you're sure no other instance is running, set '{DISABLE_INSTANCE_LOCK_VAR}=true' to skip this check (lock file: '{}')",
+        path.display()
+    )
+}
+
+fn lock_enabled() -> bool {
+    std::env::var(DISABLE_INSTANCE_LOCK_VAR)
+        .map(|value| ["true", "1"].contains(&value.as_str()))
+        .map(|value| !value)
+        .unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_instance_is_rejected() {
+        let root = tempfile::tempdir().expect("must create temp dir");
+
+        let first = InstanceLock::acquire(root.path()).expect("must acquire lock");
+        let err = InstanceLock::acquire(root.path()).expect_err("must not acquire lock twice");
+        assert!(matches!(err.current_context(), Error::InstanceLocked(_)));
+
+        drop(first);
+        InstanceLock::acquire(root.path()).expect("must acquire lock after release");
+    }
+}
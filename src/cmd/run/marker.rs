@@ -0,0 +1,102 @@
+//! Repository owners can control how Broker imports a reference without changing Broker's config,
+//! by committing a marker file named `.broker-import` to the root of the repository.
+//!
+//! The marker is a YAML file; since it's read from the checked out reference,
+//! it applies only to the branches and tags in which it's committed.
+//!
+//! ```yaml
+//! # Whether Broker scans this reference. Defaults to `true`.
+//! scan: false
+//! ```
+//!
+//! A marker which can't be read or parsed is ignored (with a warning) so that a mistake in the marker
+//! doesn't silently stop the reference from being imported.
+
+use std::path::Path;
+
+use serde::Deserialize;
+use tracing::warn;
+
+/// The name of the marker file, relative to the root of the repository.
+pub const MARKER_FILE: &str = ".broker-import";
+
+/// Import settings read from the marker file in a checked out reference.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImportMarker {
+    /// Whether Broker scans the reference.
+    #[serde(default = "default_scan")]
+    scan: bool,
+}
+
+fn default_scan() -> bool {
+    true
+}
+
+impl Default for ImportMarker {
+    fn default() -> Self {
+        Self {
+            scan: default_scan(),
+        }
+    }
+}
+
+impl ImportMarker {
+    /// Read the marker from the root of the checked out reference at `root`.
+    /// If there is no marker, or it is invalid, the default settings are used.
+    pub async fn read(root: &Path) -> Self {
+        let path = root.join(MARKER_FILE);
+        let content = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(err) => {
+                warn!(path = %path.display(), %err, "Unable to read import marker, ignoring it");
+                return Self::default();
+            }
+        };
+
+        Self::parse(&content).unwrap_or_else(|err| {
+            warn!(path = %path.display(), %err, "Unable to parse import marker, ignoring it");
+            Self::default()
+        })
+    }
+
+    fn parse(content: &str) -> Result<Self, serde_yaml::Error> {
+        // An empty file parses as null, which is treated the same as an empty marker.
+        let marker: Option<Self> = serde_yaml::from_str(content)?;
+        Ok(marker.unwrap_or_default())
+    }
+
+    /// Whether Broker scans the reference.
+    pub fn scan(&self) -> bool {
+        self.scan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_marker() {
+        let marker = ImportMarker::parse("scan: false").expect("must parse");
+        assert!(!marker.scan());
+
+        let marker = ImportMarker::parse("").expect("must parse");
+        assert!(marker.scan());
+
+        ImportMarker::parse("unknown: true").expect_err("must reject unknown fields");
+    }
+
+    #[tokio::test]
+    async fn reads_marker() {
+        let tmp = tempfile::tempdir().expect("must create tempdir");
+        assert!(ImportMarker::read(tmp.path()).await.scan());
+
+        std::fs::write(tmp.path().join(MARKER_FILE), "scan: false").expect("must write marker");
+        assert!(!ImportMarker::read(tmp.path()).await.scan());
+
+        std::fs::write(tmp.path().join(MARKER_FILE), "scan: [").expect("must write marker");
+        assert!(ImportMarker::read(tmp.path()).await.scan());
+    }
+}
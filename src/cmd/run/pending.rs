@@ -0,0 +1,109 @@
+//! Scans which fail to upload are saved so that the upload can be retried later, even if Broker restarts.
+//!
+//! The results of the scan are written to a file named after the scan ID,
+//! while the schedule on which the upload is retried is stored in the database.
+//! The file is encrypted if an encryption key is configured (see [`crate::encryption`]).
+//! Retries back off exponentially from minutes to hours, so that a prolonged FOSSA outage
+//! doesn't cause a flood of uploads, but uploads resume promptly after a short one.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use error_stack::{Result, ResultExt};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    encryption::{self, Key},
+    ext::error_stack::{DescribeContext, IntoContext},
+};
+
+use super::Error;
+
+/// The delay before the first retry; each later retry waits twice as long as the one before it.
+const BASE_DELAY: Duration = Duration::from_secs(60);
+
+/// The longest delay between retries.
+const MAX_DELAY: Duration = Duration::from_secs(4 * 60 * 60);
+
+/// How long to wait before the next attempt, given how many attempts have failed.
+pub fn retry_delay(attempts: u32) -> Duration {
+    let exponent = attempts.saturating_sub(1).min(16);
+    BASE_DELAY.saturating_mul(2u32.pow(exponent)).min(MAX_DELAY)
+}
+
+/// The location of the saved scan results for the scan ID.
+fn location(root: &Path, scan_id: &str) -> PathBuf {
+    root.join(format!("{scan_id}.json"))
+}
+
+/// Save the scan results for the scan ID.
+pub async fn save<T: Serialize>(
+    root: &Path,
+    scan_id: &str,
+    job: &T,
+    encryption: Option<&Key>,
+) -> Result<(), Error> {
+    let path = location(root, scan_id);
+    let content = serde_json::to_vec(job)
+        .context(Error::PendingUpload)
+        .describe("serialize scan results")?;
+    let content = encryption::seal(encryption, content)
+        .change_context(Error::PendingUpload)
+        .describe("encrypt scan results")?;
+
+    tokio::fs::create_dir_all(root)
+        .await
+        .context(Error::PendingUpload)
+        .describe_lazy(|| format!("create directory '{}'", root.display()))?;
+    tokio::fs::write(&path, content)
+        .await
+        .context(Error::PendingUpload)
+        .describe_lazy(|| format!("write scan results to '{}'", path.display()))
+}
+
+/// Load the saved scan results for the scan ID.
+pub async fn load<T: DeserializeOwned>(
+    root: &Path,
+    scan_id: &str,
+    encryption: Option<&Key>,
+) -> Result<T, Error> {
+    let path = location(root, scan_id);
+    let content = tokio::fs::read(&path)
+        .await
+        .context(Error::PendingUpload)
+        .describe_lazy(|| format!("read scan results from '{}'", path.display()))?;
+    let content = encryption::open(encryption, content)
+        .change_context(Error::PendingUpload)
+        .describe_lazy(|| format!("decrypt scan results in '{}'", path.display()))?;
+
+    serde_json::from_slice(&content)
+        .context(Error::PendingUpload)
+        .describe_lazy(|| format!("parse scan results in '{}'", path.display()))
+}
+
+/// Remove the saved scan results for the scan ID, if they exist.
+pub async fn remove(root: &Path, scan_id: &str) -> Result<(), Error> {
+    let path = location(root, scan_id);
+    match tokio::fs::remove_file(&path).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err)
+            .context(Error::PendingUpload)
+            .describe_lazy(|| format!("remove scan results at '{}'", path.display())),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_from_minutes_to_hours() {
+        assert_eq!(retry_delay(1), Duration::from_secs(60));
+        assert_eq!(retry_delay(2), Duration::from_secs(2 * 60));
+        assert_eq!(retry_delay(5), Duration::from_secs(16 * 60));
+        assert_eq!(retry_delay(9), MAX_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_DELAY);
+    }
+}
@@ -0,0 +1,217 @@
+//! Fair scheduling of scan jobs across integrations.
+//!
+//! Each integration sends scan jobs into its own lane, which is a bounded queue;
+//! this keeps the back pressure for each integration independent.
+//! Scan workers are shared by all integrations, and receive jobs from the lanes
+//! in weighted round-robin order: each lane in turn may provide up to its weight in jobs
+//! before the scheduler moves on to the next lane.
+//!
+//! This means that an integration with many changed references can't starve
+//! other integrations of scan workers; at worst they wait for one round.
+//!
+//! Like the queues backing each lane, jobs are delivered at least once:
+//! a job that isn't committed is redelivered to the back of its lane.
+
+use std::{
+    fmt::Debug,
+    num::NonZeroU32,
+    sync::{Mutex, PoisonError},
+};
+
+use error_stack::Report;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Notify;
+
+use crate::queue::{self, Guard, Queue};
+
+/// Schedules jobs across a set of lanes in weighted round-robin order.
+pub struct Scheduler<T> {
+    lanes: Vec<Lane<T>>,
+    cursor: Mutex<Cursor>,
+    notify: Notify,
+}
+
+struct Lane<T> {
+    queue: Queue<T>,
+    weight: u32,
+}
+
+/// The lane currently being drawn from, and how many more jobs it may provide this round.
+struct Cursor {
+    lane: usize,
+    remaining: u32,
+}
+
+impl<T> Scheduler<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Create a scheduler with one lane per provided weight.
+    /// Lanes are identified by their index in this list.
+    pub fn new(weights: impl IntoIterator<Item = NonZeroU32>) -> Self {
+        let lanes = weights
+            .into_iter()
+            .map(|weight| Lane {
+                queue: Queue::default(),
+                weight: weight.get(),
+            })
+            .collect::<Vec<_>>();
+        let remaining = lanes.first().map(|lane| lane.weight).unwrap_or_default();
+        Self {
+            lanes,
+            cursor: Mutex::new(Cursor { lane: 0, remaining }),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Get a handle for sending jobs into the provided lane.
+    pub fn sender(&self, lane: usize) -> Sender<'_, T> {
+        Sender {
+            scheduler: self,
+            lane,
+        }
+    }
+
+    /// Receive the next job, along with the lane from which it was received.
+    /// If no lane has a job available, waits until one does.
+    ///
+    /// The job is redelivered unless the returned guard is committed.
+    pub async fn recv(&self) -> Result<(usize, Guard<'_, T>), Report<queue::Error>> {
+        loop {
+            // Register interest before checking the lanes,
+            // so that a job sent in between isn't missed.
+            let notified = self.notify.notified();
+            if let Some(job) = self.try_recv() {
+                return job;
+            }
+            notified.await;
+        }
+    }
+
+    fn try_recv(&self) -> Option<Result<(usize, Guard<'_, T>), Report<queue::Error>>> {
+        if self.lanes.is_empty() {
+            return None;
+        }
+
+        let mut cursor = self.cursor.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Check the current lane, then each lane (including the current one) for a full round.
+        for _ in 0..=self.lanes.len() {
+            if cursor.remaining > 0 {
+                if let Some(job) = self.lanes[cursor.lane].queue.try_recv() {
+                    cursor.remaining -= 1;
+                    let lane = cursor.lane;
+                    return Some(job.map(|job| (lane, job.notifying(&self.notify))));
+                }
+            }
+
+            cursor.lane = (cursor.lane + 1) % self.lanes.len();
+            cursor.remaining = self.lanes[cursor.lane].weight;
+        }
+
+        None
+    }
+}
+
+impl<T> Debug for Scheduler<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Scheduler<{}>({} lanes)",
+            std::any::type_name::<T>(),
+            self.lanes.len()
+        )
+    }
+}
+
+/// Sends jobs into a specific lane of a [`Scheduler`].
+pub struct Sender<'a, T> {
+    scheduler: &'a Scheduler<T>,
+    lane: usize,
+}
+
+impl<T> Sender<'_, T>
+where
+    T: Serialize,
+{
+    /// Sends a job into the lane.
+    ///
+    /// If the lane is full, waits until it has space before sending.
+    pub async fn send(&self, job: &T) -> Result<(), Report<queue::Error>> {
+        self.scheduler.lanes[self.lane].queue.send(job).await?;
+        self.scheduler.notify.notify_waiters();
+        Ok(())
+    }
+}
+
+impl<T> Debug for Sender<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sender({})", self.lane)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nonzero_ext::nonzero;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn interleaves_lanes_by_weight() {
+        let scheduler = Scheduler::<String>::new([nonzero!(2u32), nonzero!(1u32)]);
+        for job in ["a1", "a2", "a3", "a4"] {
+            scheduler
+                .sender(0)
+                .send(&job.to_string())
+                .await
+                .expect("must send");
+        }
+        for job in ["b1", "b2"] {
+            scheduler
+                .sender(1)
+                .send(&job.to_string())
+                .await
+                .expect("must send");
+        }
+
+        let mut received = Vec::new();
+        for _ in 0..6 {
+            let (_, job) = scheduler.recv().await.expect("must receive");
+            received.push(job.commit());
+        }
+        assert_eq!(received, vec!["a1", "a2", "b1", "a3", "a4", "b2"]);
+    }
+
+    #[tokio::test]
+    async fn skips_empty_lanes() {
+        let scheduler = Scheduler::<String>::new([nonzero!(1u32), nonzero!(1u32), nonzero!(1u32)]);
+        scheduler
+            .sender(2)
+            .send(&"c1".to_string())
+            .await
+            .expect("must send");
+
+        let (lane, job) = scheduler.recv().await.expect("must receive");
+        assert_eq!(lane, 2);
+        assert_eq!(job.commit(), "c1");
+    }
+
+    #[tokio::test]
+    async fn redelivers_uncommitted_jobs() {
+        let scheduler = Scheduler::<String>::new([nonzero!(1u32), nonzero!(1u32)]);
+        scheduler
+            .sender(1)
+            .send(&"b1".to_string())
+            .await
+            .expect("must send");
+
+        let (lane, job) = scheduler.recv().await.expect("must receive");
+        assert_eq!(job.deliveries(), 1);
+        drop(job);
+
+        let (redelivered, job) = scheduler.recv().await.expect("must receive");
+        assert_eq!(lane, redelivered);
+        assert_eq!(job.deliveries(), 2);
+        assert_eq!(job.commit(), "b1");
+    }
+}
@@ -0,0 +1,178 @@
+//! Scans can be uploaded to more than one FOSSA endpoint, like a staging and a production instance.
+//!
+//! The endpoint set in `fossa_endpoint` is the primary target; additional targets are listed in `fossa_api.targets`.
+//! Each target may be rate limited; the limit is shared by all integrations,
+//! unlike the limit per integration which applies to every upload regardless of target.
+
+use std::fmt::Display;
+
+use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
+use tracing::info;
+
+use crate::api::fossa::{self, FanOut};
+
+/// A FOSSA endpoint to which scans are uploaded.
+#[derive(Debug)]
+pub struct Target {
+    /// The name of the target; `None` for the primary target.
+    name: Option<String>,
+
+    /// The config used to upload to the target.
+    config: fossa::Config,
+
+    /// Limits how often scans are uploaded to the target, if configured.
+    limiter: Option<DefaultDirectRateLimiter>,
+}
+
+impl Target {
+    fn new(name: Option<String>, config: fossa::Config) -> Self {
+        let limiter = config
+            .max_uploads_per_minute()
+            .map(|max| RateLimiter::direct(Quota::per_minute(max)));
+        Self {
+            name,
+            config,
+            limiter,
+        }
+    }
+
+    /// The name of the target; `None` for the primary target.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The config used to upload to the target.
+    pub fn config(&self) -> &fossa::Config {
+        &self.config
+    }
+
+    /// Wait until the target's rate limit allows another upload.
+    pub async fn ready(&self) {
+        if let Some(limiter) = &self.limiter {
+            if limiter.check().is_err() {
+                info!("FOSSA target '{self}': waiting for rate limit");
+                limiter.until_ready().await;
+            }
+        }
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{name}"),
+            None => write!(f, "{}", fossa::Target::PRIMARY),
+        }
+    }
+}
+
+/// The FOSSA endpoints to which scans are uploaded, and how uploads fan out to them.
+#[derive(Debug)]
+pub struct Targets {
+    fan_out: FanOut,
+    primary: Target,
+    additional: Vec<Target>,
+}
+
+impl Targets {
+    /// The targets configured for the FOSSA API.
+    pub fn new(config: &fossa::Config) -> Self {
+        let additional = config
+            .targets()
+            .iter()
+            .map(|target| Target::new(Some(target.name().clone()), config.for_target(target)))
+            .collect();
+        Self {
+            fan_out: config.fan_out(),
+            primary: Target::new(None, config.clone()),
+            additional,
+        }
+    }
+
+    /// How scans are uploaded to the additional targets.
+    pub fn fan_out(&self) -> FanOut {
+        self.fan_out
+    }
+
+    /// The target set in `fossa_endpoint`.
+    pub fn primary(&self) -> &Target {
+        &self.primary
+    }
+
+    /// The additional targets, in the order they're tried.
+    pub fn additional(&self) -> &[Target] {
+        &self.additional
+    }
+
+    /// The additional target with the name, or the primary target if `None`.
+    ///
+    /// Returns `None` if no additional target has the name, like when it was removed from the config.
+    pub fn find(&self, name: Option<&str>) -> Option<&Target> {
+        match name {
+            None => Some(&self.primary),
+            Some(name) => self
+                .additional
+                .iter()
+                .find(|target| target.name() == Some(name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use nonzero_ext::nonzero;
+
+    use super::*;
+
+    fn endpoint(url: &str) -> fossa::Endpoint {
+        fossa::Endpoint::try_from(url.to_string()).expect("must parse endpoint")
+    }
+
+    fn key(key: &str) -> fossa::Key {
+        fossa::Key::try_from(key.to_string()).expect("must parse key")
+    }
+
+    #[test]
+    fn additional_targets_use_their_own_endpoint() {
+        let staging = fossa::Target::new(
+            String::from("staging"),
+            endpoint("https://staging.fossa.example"),
+            key("staging-key"),
+            Some(nonzero!(10u32)),
+        );
+        let config = fossa::Config::new(
+            endpoint("https://app.fossa.com"),
+            key("primary-key"),
+            fossa::Upload::validate(None, BTreeMap::new()).expect("must validate upload"),
+            true,
+            false,
+            None,
+            vec![staging],
+            FanOut::PrimaryWithFallback,
+            fossa::Retries::default(),
+        );
+
+        let targets = Targets::new(&config);
+        assert_eq!(targets.fan_out(), FanOut::PrimaryWithFallback);
+        assert_eq!(targets.primary().to_string(), "primary");
+        assert_eq!(targets.primary().config(), &config);
+
+        let staging = targets.find(Some("staging")).expect("must find target");
+        assert_eq!(staging.name(), Some("staging"));
+        assert_eq!(
+            staging.config().endpoint(),
+            &endpoint("https://staging.fossa.example")
+        );
+        assert_eq!(staging.config().key(), &key("staging-key"));
+        assert_eq!(staging.config().upload(), config.upload());
+        assert_eq!(
+            staging.config().max_uploads_per_minute(),
+            Some(nonzero!(10u32))
+        );
+
+        assert!(targets.find(None).is_some());
+        assert!(targets.find(Some("removed")).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+//! Implementation for the `scan` subcommand.
+
+use error_stack::{report, Report, ResultExt};
+use itertools::Itertools;
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    api::remote::{Integration, ScanOnStartup},
+    config::Config,
+    db::Database,
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper},
+        result::WrapErr,
+    },
+    AppContext,
+};
+
+/// Errors encountered scanning integrations.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// No integration in the config file belongs to the requested group.
+    #[error("no integrations are in group '{0}'")]
+    GroupNotFound(String),
+
+    /// The requested integration isn't in the config file.
+    #[error("integration '{0}' is not configured")]
+    IntegrationNotFound(String),
+
+    /// Scanning the selected integrations failed.
+    #[error("scan integrations")]
+    Scan,
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::GroupNotFound(..) => ErrorCode::new(3401),
+            Self::IntegrationNotFound(..) => ErrorCode::new(3402),
+            Self::Scan => ErrorCode::new(3403),
+        }
+    }
+}
+
+/// Poll the selected integrations once, scanning and uploading each reference that needs it.
+///
+/// Integrations are selected by `group` and by `integration` (the remote as written in the config file);
+/// if neither is provided, every integration is scanned.
+/// If `all` is set, every reference is scanned even if it hasn't changed since it was last scanned.
+#[tracing::instrument(skip(ctx, config, db))]
+pub async fn main<D: Database>(
+    ctx: &AppContext,
+    config: Config,
+    db: D,
+    group: Option<&str>,
+    integration: Option<&str>,
+    all: bool,
+) -> Result<(), Report<Error>> {
+    let selected = select(&config, group, integration)?;
+    let scan = if all {
+        ScanOnStartup::All
+    } else {
+        ScanOnStartup::Changed
+    };
+
+    crate::cmd::run::scan_once(ctx, config, db, &selected, scan)
+        .await
+        .change_context(Error::Scan)
+}
+
+/// Select the integrations matching the provided group and remote.
+fn select(
+    config: &Config,
+    group: Option<&str>,
+    integration: Option<&str>,
+) -> Result<Vec<Integration>, Report<Error>> {
+    let integrations = config.integrations();
+
+    if let Some(group) = group {
+        if !integrations
+            .iter()
+            .any(|candidate| candidate.in_group(group))
+        {
+            let groups = integrations
+                .iter()
+                .filter_map(|integration| integration.group().as_deref())
+                .unique()
+                .map(|group| format!("'{group}'"))
+                .join(", ");
+            return report!(Error::GroupNotFound(group.to_string()))
+                .wrap_err()
+                .help("provide the name of a group that at least one integration declares with 'group'")
+                .describe_lazy(|| format!("groups with integrations: {groups}"));
+        }
+    }
+
+    if let Some(integration) = integration {
+        if !integrations
+            .iter()
+            .any(|candidate| candidate.remote().to_string() == integration)
+        {
+            let configured = integrations
+                .iter()
+                .map(|integration| format!("'{}'", integration.remote()))
+                .join(", ");
+            return report!(Error::IntegrationNotFound(integration.to_string()))
+                .wrap_err()
+                .help(
+                    "provide the remote of the integration exactly as it is written in the config file",
+                )
+                .describe_lazy(|| format!("configured integrations: {configured}"));
+        }
+    }
+
+    let selected = integrations
+        .iter()
+        .filter(|candidate| group.map_or(true, |group| candidate.in_group(group)))
+        .filter(|candidate| {
+            integration.map_or(true, |remote| candidate.remote().to_string() == remote)
+        })
+        .cloned()
+        .collect_vec();
+
+    // Each filter matches something on its own, so this only happens when the integration isn't in the group.
+    if selected.is_empty() {
+        let integration = integration.unwrap_or_default();
+        let group = group.unwrap_or_default();
+        return report!(Error::IntegrationNotFound(integration.to_string()))
+            .wrap_err()
+            .help("provide an integration which is in the group, or don't provide the group")
+            .describe_lazy(|| format!("integration is not in group '{group}'"));
+    }
+
+    Ok(selected)
+}
@@ -0,0 +1,319 @@
+//! Implementation for the `simulate` subcommand.
+//!
+//! Simulations replay recorded `git ls-remote` output and FOSSA CLI source units through the same pipeline
+//! as `broker run`, uploading to a mock FOSSA endpoint instead of FOSSA.
+//! This reproduces how Broker behaves for an installation, for example from the config in its debug bundle,
+//! without access to its repositories or FOSSA organization.
+//!
+//! Fixtures are laid out like this:
+//! ```not_rust
+//! <fixtures>/
+//!   ls-remote/
+//!     <integration>/     # The repository of the integration, with characters other than letters, digits, '.', '-', and '_' replaced by '_'.
+//!       001.txt          # The output of `git ls-remote` for each poll, replayed in order of file name.
+//!       002.txt
+//!   source-units/
+//!     <revision>.json    # The output of `fossa analyze --output` for the revision.
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use error_stack::{Report, ResultExt};
+use itertools::Itertools;
+use tracing::{info, warn};
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    api::{
+        fossa,
+        remote::{git::repository::references_from_ls_remote, Integration, Protocol, Reference},
+    },
+    config::Config,
+    db,
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        tempfile::tempdir,
+    },
+    fossa_cli::SourceUnits,
+    hooks, notify, AppContext,
+};
+
+pub(crate) mod mock;
+
+/// Errors encountered running a simulation.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The fixtures couldn't be read.
+    #[error("read fixtures")]
+    ReadFixtures,
+
+    /// The mock FOSSA endpoint couldn't be started.
+    #[error("start mock FOSSA endpoint")]
+    MockEndpoint,
+
+    /// The temporary data root or database for the simulation couldn't be created.
+    #[error("set up simulation")]
+    Setup,
+
+    /// Some recorded polls or scans failed to replay.
+    #[error("replay fixtures")]
+    Replay,
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::ReadFixtures => ErrorCode::new(3501),
+            Self::MockEndpoint => ErrorCode::new(3502),
+            Self::Setup => ErrorCode::new(3503),
+            Self::Replay => ErrorCode::new(3504),
+        }
+    }
+}
+
+/// Replay the fixtures for each configured integration through the pipeline, then report what was uploaded.
+///
+/// The simulation uses its own temporary data root and database, so it doesn't affect `broker run`.
+/// Notifications aren't sent and hooks aren't run, since they'd act on the real world;
+/// uploads aren't rate limited, since the mock endpoint doesn't need protecting.
+#[tracing::instrument(skip(ctx, config))]
+pub async fn main(ctx: &AppContext, config: Config, fixtures: &Path) -> Result<(), Report<Error>> {
+    let mut polls = Vec::new();
+    for integration in config.integrations().iter() {
+        if let Some(recorded) = recorded_polls(fixtures, integration).await? {
+            polls.push((integration.clone(), recorded));
+        }
+    }
+    let source_units = recorded_source_units(fixtures).await?;
+    info!(
+        "Loaded fixtures for {} integration(s) and {} revision(s)",
+        polls.len(),
+        source_units.len()
+    );
+
+    let endpoint = mock::Endpoint::start().await.context(Error::MockEndpoint)?;
+    let config = with_mock_endpoint(config, endpoint.url())?;
+
+    let root = tempdir()
+        .context(Error::Setup)
+        .describe("create temporary data root")?;
+    let sim_ctx = AppContext::new(root.path().to_path_buf())
+        .change_context(Error::Setup)?
+        .with_clock(ctx.clock().clone())
+        .with_http(ctx.http().clone());
+    let db = db::connect_sqlite(&root.path().join("db.sqlite"), *config.database())
+        .await
+        .change_context(Error::Setup)?
+        .with_clock(sim_ctx.clock().clone());
+
+    let simulated = crate::cmd::run::simulate(&sim_ctx, config, db, &polls, &source_units).await;
+
+    let requests = endpoint.requests();
+    println!(
+        "The mock FOSSA endpoint received {} request(s):",
+        requests.len()
+    );
+    for request in requests {
+        match request.uploaded_locator() {
+            Some(locator) => println!(
+                "- {} {} (uploaded '{locator}', {} bytes)",
+                request.method(),
+                request.path(),
+                request.size()
+            ),
+            None => println!("- {} {}", request.method(), request.path()),
+        }
+    }
+    simulated.change_context(Error::Replay)
+}
+
+/// The directory containing the recorded polls of the integration.
+fn ls_remote_dir(fixtures: &Path, integration: &Integration) -> PathBuf {
+    let name = integration
+        .repository()
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    fixtures.join("ls-remote").join(name)
+}
+
+/// The references listed by each recorded poll of the integration, in order.
+///
+/// Returns `None` if the integration can't be simulated or has no recorded polls, which is logged.
+async fn recorded_polls(
+    fixtures: &Path,
+    integration: &Integration,
+) -> Result<Option<Vec<Vec<Reference>>>, Report<Error>> {
+    if !matches!(integration.protocol(), Protocol::Git(_)) {
+        warn!("Skipping '{integration}': only git integrations can be simulated");
+        return Ok(None);
+    }
+
+    let dir = ls_remote_dir(fixtures, integration);
+    if !dir.is_dir() {
+        warn!(
+            "Skipping '{integration}': no recorded polls in '{}'",
+            dir.display()
+        );
+        return Ok(None);
+    }
+
+    let mut polls = Vec::new();
+    for path in files_in(&dir).await? {
+        let output = tokio::fs::read_to_string(&path)
+            .await
+            .context(Error::ReadFixtures)
+            .describe_lazy(|| format!("read recorded poll '{}'", path.display()))?;
+        let references = references_from_ls_remote(output)
+            .into_iter()
+            .map(Reference::Git)
+            .collect();
+        polls.push(references);
+    }
+    Ok(Some(polls))
+}
+
+/// The recorded source units for each revision.
+async fn recorded_source_units(
+    fixtures: &Path,
+) -> Result<BTreeMap<String, SourceUnits>, Report<Error>> {
+    let dir = fixtures.join("source-units");
+    if !dir.is_dir() {
+        warn!("No recorded source units in '{}'", dir.display());
+        return Ok(BTreeMap::new());
+    }
+
+    let mut source_units = BTreeMap::new();
+    for path in files_in(&dir).await? {
+        let Some(revision) = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+        else {
+            continue;
+        };
+        let content = tokio::fs::read(&path)
+            .await
+            .context(Error::ReadFixtures)
+            .describe_lazy(|| format!("read recorded source units '{}'", path.display()))?;
+        let units = serde_json::from_slice::<SourceUnits>(&content)
+            .context(Error::ReadFixtures)
+            .describe_lazy(|| format!("parse recorded source units '{}'", path.display()))
+            .help("record source units with 'fossa analyze --output'")?;
+        source_units.insert(revision, units);
+    }
+    Ok(source_units)
+}
+
+/// The files in the directory, in order of name.
+async fn files_in(dir: &Path) -> Result<Vec<PathBuf>, Report<Error>> {
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .context(Error::ReadFixtures)
+        .describe_lazy(|| format!("list directory '{}'", dir.display()))?;
+
+    let mut files = Vec::new();
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .context(Error::ReadFixtures)
+        .describe_lazy(|| format!("list directory '{}'", dir.display()))?
+    {
+        if entry.path().is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(files.into_iter().sorted().collect())
+}
+
+/// The config, with every FOSSA target uploading to the mock endpoint without a rate limit,
+/// and without notifications, hooks, or the admin API.
+fn with_mock_endpoint(config: Config, url: &str) -> Result<Config, Report<Error>> {
+    let endpoint =
+        fossa::Endpoint::try_from(url.to_string()).change_context(Error::MockEndpoint)?;
+    let api = config.fossa_api();
+    let targets = api
+        .targets()
+        .iter()
+        .map(|target| {
+            fossa::Target::new(
+                target.name().clone(),
+                endpoint.clone(),
+                target.key().clone(),
+                None,
+            )
+        })
+        .collect();
+    let api = fossa::Config::new(
+        endpoint,
+        api.key().clone(),
+        api.upload().clone(),
+        api.match_existing_projects(),
+        api.upload_contributors(),
+        None,
+        targets,
+        api.fan_out(),
+        api.retries(),
+    );
+
+    Ok(Config::new(
+        api,
+        config.debug().clone(),
+        config.integrations().clone(),
+        notify::Config::default(),
+        config.fossa_cli().clone(),
+        *config.disk_space(),
+        *config.upload_retry(),
+        *config.policy_check(),
+        config.ci_metadata().clone(),
+        hooks::Config::default(),
+        config.sbom_export().clone(),
+        *config.http(),
+        *config.git_backend(),
+        config.portable_git().clone(),
+        *config.crypto_mode(),
+        config.encryption().clone(),
+        *config.locale(),
+        *config.secrets_guard(),
+        *config.bandwidth(),
+        *config.size_limits(),
+        *config.database(),
+        None,
+        config.lints().clone(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_source_units_by_revision() {
+        let fixtures = tempfile::tempdir().expect("must create tempdir");
+        let dir = fixtures.path().join("source-units");
+        std::fs::create_dir_all(&dir).expect("must create directory");
+        std::fs::write(dir.join("abcd1234.json"), r#"[{"Name": "broker"}]"#)
+            .expect("must write source units");
+        std::fs::write(dir.join("ef567890.json"), "[]").expect("must write source units");
+
+        let source_units = recorded_source_units(fixtures.path())
+            .await
+            .expect("must read source units");
+        assert_eq!(
+            source_units.keys().collect_vec(),
+            vec!["abcd1234", "ef567890"]
+        );
+        assert!(!source_units["abcd1234"].is_empty());
+        assert!(source_units["ef567890"].is_empty());
+
+        std::fs::write(dir.join("invalid.json"), "{").expect("must write source units");
+        recorded_source_units(fixtures.path())
+            .await
+            .expect_err("must fail to parse invalid source units");
+    }
+}
@@ -0,0 +1,248 @@
+//! A mock FOSSA endpoint, which accepts every upload and records each request it receives.
+//!
+//! It only implements as much of HTTP and the FOSSA API as Broker uses:
+//! each connection carries a single request, and responses are the minimum Broker parses,
+//! describing a FOSSA organization with no existing projects in which every build succeeds without issues.
+
+use std::{
+    io,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use getset::Getters;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+use tracing::{debug, warn};
+
+/// A request received by the mock endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+pub struct Request {
+    /// The HTTP method of the request.
+    #[getset(get = "pub")]
+    method: String,
+
+    /// The path of the request, without its query.
+    #[getset(get = "pub")]
+    path: String,
+
+    /// The query parameters of the request, in the order they were provided.
+    #[getset(get = "pub")]
+    query: Vec<(String, String)>,
+
+    /// The headers of the request, with lowercase names, in the order they were provided.
+    #[getset(get = "pub")]
+    headers: Vec<(String, String)>,
+
+    /// The body of the request.
+    #[getset(get = "pub")]
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// The size of the body of the request, in bytes.
+    pub fn size(&self) -> usize {
+        self.body.len()
+    }
+
+    /// The locator of the scan, if this request uploaded one.
+    pub fn uploaded_locator(&self) -> Option<&str> {
+        if self.method != "POST" {
+            return None;
+        }
+        self.query
+            .iter()
+            .find(|(name, _)| name == "locator")
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A mock FOSSA endpoint listening on the loopback interface, which stops when dropped.
+#[derive(Debug)]
+pub struct Endpoint {
+    url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+    server: JoinHandle<()>,
+}
+
+impl Endpoint {
+    /// Start the endpoint on an unused port.
+    pub async fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let url = format!("http://{}/", listener.local_addr()?);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let server = tokio::spawn(serve(listener, requests.clone()));
+        Ok(Self {
+            url,
+            requests,
+            server,
+        })
+    }
+
+    /// The URL of the endpoint, for use as the FOSSA endpoint in the config.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The requests the endpoint received, in the order they were received.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn serve(listener: TcpListener, requests: Arc<Mutex<Vec<Request>>>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                warn!("Mock FOSSA endpoint: unable to accept connection: {err}");
+                continue;
+            }
+        };
+        let requests = requests.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle(stream, &requests).await {
+                warn!("Mock FOSSA endpoint: unable to handle request: {err}");
+            }
+        });
+    }
+}
+
+async fn handle(stream: TcpStream, requests: &Mutex<Vec<Request>>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(());
+    };
+    let (method, target) = (method.to_string(), target.to_string());
+
+    let mut headers = Vec::new();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            break;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or_default();
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let request = Request {
+        method,
+        path: path.to_string(),
+        query: url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect(),
+        headers,
+        body,
+    };
+    let (status, response) = respond(&request);
+    debug!(
+        "Mock FOSSA endpoint: {} {} -> {status}",
+        request.method, request.path
+    );
+    requests
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(request);
+
+    let mut stream = reader.into_inner();
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// The status and body with which the endpoint responds to the request.
+fn respond(request: &Request) -> (&'static str, String) {
+    const OK: &str = "200 OK";
+    const NOT_FOUND: &str = "404 Not Found";
+
+    let path = request.path.as_str();
+    if let Some(locator) = request.uploaded_locator() {
+        let response = serde_json::json!({ "locator": locator, "error": null });
+        return (OK, response.to_string());
+    }
+
+    let response = match path {
+        "/api/cli/organization" => r#"{"organizationId":1}"#,
+        "/api/contributors" => "{}",
+        "/api/support/broker/debug-bundles" => r#"{"referenceId":"mock-debug-bundle"}"#,
+        _ if path.ends_with("/latest_build") => r#"{"error":null,"task":{"status":"SUCCEEDED"}}"#,
+        _ if path.ends_with("/issues") => r#"{"issues":[],"status":"SCANNED"}"#,
+        _ if path.contains("/attribution/") => "{}",
+        _ => return (NOT_FOUND, String::from("{}")),
+    };
+    (OK, response.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn accepts_uploads() {
+        let endpoint = Endpoint::start().await.expect("must start endpoint");
+        let client = reqwest::Client::new();
+
+        let response = client
+            .post(format!("{}api/builds/custom", endpoint.url()))
+            .query(&[("locator", "custom+broker$abcd1234"), ("title", "broker")])
+            .body("[]")
+            .send()
+            .await
+            .expect("must upload");
+        assert!(response.status().is_success());
+        let body = response.text().await.expect("must read response");
+        let body = serde_json::from_str::<serde_json::Value>(&body).expect("must parse response");
+        assert_eq!(body["locator"], "custom+broker$abcd1234");
+
+        let response = client
+            .get(format!(
+                "{}api/cli/custom%2B1%2Fbroker/project",
+                endpoint.url()
+            ))
+            .send()
+            .await
+            .expect("must look up project");
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let requests = endpoint.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].path(), "/api/builds/custom");
+        assert_eq!(
+            requests[0].uploaded_locator(),
+            Some("custom+broker$abcd1234")
+        );
+        assert_eq!(requests[0].size(), 2);
+        assert_eq!(requests[1].uploaded_locator(), None);
+    }
+}
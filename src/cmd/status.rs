@@ -0,0 +1,96 @@
+//! Implementation for the `status` subcommand.
+
+use std::{path::Path, time::SystemTime};
+
+use error_stack::{Report, ResultExt};
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    config::Config,
+    db::{self, Database},
+    ext::error_stack::{DescribeContext, ErrorHelper},
+};
+
+/// Errors encountered showing status.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Interacting with the database failed.
+    #[error("interact with the database")]
+    Interact,
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Interact => ErrorCode::new(3901),
+        }
+    }
+}
+
+/// Show progress through the backlog of references enqueued for scanning for each integration.
+///
+/// The database is opened read only, so this can be run while `broker run` is using it.
+#[tracing::instrument(skip(config))]
+pub async fn main(config: &Config, location: &Path) -> Result<(), Report<Error>> {
+    let db = db::open_sqlite_read_only(location)
+        .await
+        .change_context(Error::Interact)?;
+
+    let now = SystemTime::now();
+    for integration in config.integrations().iter() {
+        let backlog = db
+            .backlog(&integration.namespace(), &integration.repository())
+            .await
+            .change_context(Error::Interact)
+            .describe_lazy(|| format!("read backlog for '{integration}'"))
+            .help("run 'broker run' with this version of Broker at least once to prepare the database")?;
+
+        let status = match backlog {
+            None => String::from("nothing enqueued yet"),
+            Some(backlog) if backlog.is_complete() => format!(
+                "idle; scanned {} references in the last backlog, finished {} ago",
+                backlog.total(),
+                since(now, backlog.updated_at()),
+            ),
+            Some(backlog) => format!(
+                "scanned {} of {} references ({:.0}%), started {} ago",
+                backlog.completed(),
+                backlog.total(),
+                backlog.percent_complete(),
+                since(now, backlog.started_at()),
+            ),
+        };
+        let paused = db
+            .is_paused(&integration.namespace(), &integration.repository())
+            .await
+            .change_context(Error::Interact)
+            .describe_lazy(|| format!("check whether '{integration}' is paused"))
+            .help("run 'broker run' with this version of Broker at least once to prepare the database")?;
+        let connectivity = db
+            .connectivity(&integration.namespace(), &integration.repository())
+            .await
+            .change_context(Error::Interact)
+            .describe_lazy(|| format!("read connectivity for '{integration}'"))
+            .help("run 'broker run' with this version of Broker at least once to prepare the database")?;
+        let status = match connectivity {
+            Some(checked) if !checked.reachable() => format!(
+                "{status}; unable to connect as of {} ago",
+                since(now, checked.checked_at())
+            ),
+            _ => status,
+        };
+        if paused {
+            println!("{integration}: paused; {status}");
+        } else {
+            println!("{integration}: {status}");
+        }
+    }
+    Ok(())
+}
+
+/// Describe how long ago the time was, rounded to the second.
+fn since(now: SystemTime, then: SystemTime) -> String {
+    let elapsed = now.duration_since(then).unwrap_or_default();
+    let elapsed = std::time::Duration::from_secs(elapsed.as_secs());
+    humantime::format_duration(elapsed).to_string()
+}
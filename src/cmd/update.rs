@@ -0,0 +1,356 @@
+//! Implementation for the `update` subcommand.
+//!
+//! Broker releases are published on GitHub, with one executable per platform
+//! (for example `broker-0.3.2-x86_64-linux`) alongside a `.sha256` file containing its checksum
+//! and a `.sig` file containing its Ed25519 signature.
+//! Like FOSSA CLI, the latest release is found using GitHub's `latest` pseudo-tag.
+//!
+//! The checksum only protects against corrupted downloads, since anyone able to replace the executable
+//! can replace its checksum too; the signature is verified against a key pinned in Broker
+//! so that only executables signed by the release workflow are installed.
+
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use error_stack::{report, Report, ResultExt};
+use indoc::formatdoc;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use semver::Version;
+use tracing::{debug, info};
+
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::{
+    api::http::client::{Client, Purpose},
+    cmd::run::lock::InstanceLock,
+    doc::crate_version,
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        io::spawn_blocking_wrap,
+        result::WrapErr,
+        sha2,
+    },
+    AppContext,
+};
+
+/// The location of Broker releases on GitHub.
+const RELEASES: &str = "https://github.com/fossas/broker/releases";
+
+/// The Ed25519 public key with which the release workflow signs Broker executables.
+///
+/// The same key is published in PEM form in `docs/subcommands/update.md`, for verifying releases manually.
+const RELEASE_SIGNING_KEY: [u8; 32] = [
+    0x9a, 0x11, 0x01, 0xd5, 0xbc, 0x80, 0x6c, 0xda, 0x0f, 0xdf, 0xb5, 0xf7, 0xba, 0x72, 0xf2, 0x05,
+    0xf4, 0xa1, 0x83, 0xab, 0xe7, 0x02, 0x4b, 0x6e, 0x52, 0xdd, 0xf0, 0x25, 0x62, 0x63, 0xe6, 0x84,
+];
+
+/// Errors encountered updating Broker.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Broker checks the latest release on GitHub to determine whether it is out of date.
+    #[error("find latest Broker version")]
+    FindVersion,
+
+    /// The tag of the latest release couldn't be parsed as a version.
+    #[error("parse release tag '{0}' as a version")]
+    ParseVersion(String),
+
+    /// Releases aren't published for every platform on which Broker can be built.
+    #[error("releases are not published for this platform")]
+    UnsupportedPlatform,
+
+    /// Downloading part of the release failed.
+    #[error("download '{0}'")]
+    Download(String),
+
+    /// The downloaded executable couldn't be verified against the checksum published with the release.
+    #[error("verify '{0}' against its published checksum")]
+    Verify(String),
+
+    /// The downloaded executable wasn't signed with the key pinned in Broker.
+    #[error("verify '{0}' against its signature")]
+    VerifySignature(String),
+
+    /// Broker isn't replaced while `broker run` is using the data root.
+    #[error("replace Broker while 'broker run' is using data root '{}'", .0.display())]
+    Running(PathBuf),
+
+    /// The path to the currently running executable couldn't be determined.
+    #[error("locate the running Broker executable")]
+    LocateExecutable,
+
+    /// Replacing the currently running executable failed.
+    #[error("replace Broker executable at '{}'", .0.display())]
+    Replace(PathBuf),
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::FindVersion => ErrorCode::new(4001),
+            Self::ParseVersion(..) => ErrorCode::new(4002),
+            Self::UnsupportedPlatform => ErrorCode::new(4003),
+            Self::Download(..) => ErrorCode::new(4004),
+            Self::Verify(..) => ErrorCode::new(4005),
+            Self::LocateExecutable => ErrorCode::new(4006),
+            Self::Replace(..) => ErrorCode::new(4007),
+            Self::VerifySignature(..) => ErrorCode::new(4008),
+            Self::Running(..) => ErrorCode::new(4009),
+        }
+    }
+}
+
+/// Check for a newer release of Broker, and unless `check` is set, replace the running executable with it.
+#[tracing::instrument(skip(ctx))]
+pub async fn main(ctx: &AppContext, check: bool) -> Result<(), Report<Error>> {
+    let client = ctx.http().get(Purpose::Updates);
+    let current = crate_version();
+    let latest = latest_release_version(client).await?;
+    if &latest <= current {
+        println!("Broker is up to date (version {current}).");
+        return Ok(());
+    }
+
+    if check {
+        println!("Broker {latest} is available (this is version {current}); run 'broker update' to install it.");
+        return Ok(());
+    }
+
+    let asset = asset_name(&latest)
+        .ok_or_else(|| report!(Error::UnsupportedPlatform))
+        .help_lazy(|| format!("download a release for this platform manually from {RELEASES}, or build Broker from source"))?;
+
+    // Held until the executable is replaced, so that 'broker run' can't start with the old executable in the meantime.
+    let _locks = lock_data_roots(ctx.data_root())?;
+
+    let executable = download(client, &format!("{RELEASES}/download/v{latest}/{asset}")).await?;
+    let checksum = download(
+        client,
+        &format!("{RELEASES}/download/v{latest}/{asset}.sha256"),
+    )
+    .await?;
+    sha2::parse_sha256(&checksum)
+        .and_then(|checksum| sha2::verify_sha256(&executable, &checksum))
+        .change_context_lazy(|| Error::Verify(asset.clone()))?;
+    let signature = download(
+        client,
+        &format!("{RELEASES}/download/v{latest}/{asset}.sig"),
+    )
+    .await?;
+    verify_signature(&RELEASE_SIGNING_KEY, &asset, &executable, &signature)?;
+
+    let current_exe = std::env::current_exe()
+        .context(Error::LocateExecutable)
+        .help("run 'broker update' using the full path to the Broker executable")?;
+    let target = current_exe.clone();
+    spawn_blocking_wrap(move || replace(&target, &executable))
+        .await
+        .change_context_lazy(|| Error::Replace(current_exe.clone()))
+        .help(
+            "ensure you have permission to write to the directory containing the Broker executable",
+        )?;
+
+    info!(%current, %latest, path = %current_exe.display(), "Updated Broker");
+    println!("Updated Broker from version {current} to {latest}.");
+    Ok(())
+}
+
+/// Get the version of the latest release on GitHub.
+#[tracing::instrument(skip(client))]
+async fn latest_release_version(client: &Client) -> Result<Version, Report<Error>> {
+    // This follows the redirect, so the final path is something like "/fossas/broker/releases/tag/v0.3.2".
+    let response = client
+        .send(
+            client
+                .get(format!("{RELEASES}/latest"))
+                .header(reqwest::header::ACCEPT, "application/json"),
+        )
+        .await
+        .context(Error::FindVersion)
+        .describe("uses GitHub's 'latest' pseudo-tag to determine the latest release")?;
+
+    let path = response.url().path();
+    let tag = path.rsplit('/').next().unwrap_or_default();
+    debug!(%path, %tag, "Resolved latest release");
+    parse_tag(tag)
+}
+
+/// Parse a release tag, like `v0.3.2`, into a version.
+fn parse_tag(tag: &str) -> Result<Version, Report<Error>> {
+    tag.strip_prefix('v')
+        .and_then(|version| Version::parse(version).ok())
+        .ok_or_else(|| report!(Error::ParseVersion(tag.to_string())))
+        .describe("release tags are expected to be a 'v' followed by the version, like 'v0.3.2'")
+}
+
+/// The name of the release asset for the platform on which Broker is running, if releases are published for it.
+fn asset_name(version: &Version) -> Option<String> {
+    let platform = if cfg!(all(target_os = "linux", target_arch = "x86_64")) {
+        "x86_64-linux"
+    } else if cfg!(all(target_os = "macos", target_arch = "x86_64")) {
+        "x86_64-macos"
+    } else if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+        "aarch64-macos"
+    } else if cfg!(all(target_os = "windows", target_arch = "x86_64")) {
+        "x86_64-windows.exe"
+    } else {
+        return None;
+    };
+    Some(format!("broker-{version}-{platform}"))
+}
+
+#[tracing::instrument(skip(client))]
+async fn download(client: &Client, url: &str) -> Result<Bytes, Report<Error>> {
+    let help = || {
+        formatdoc! {"
+        Try downloading '{url}' to determine if this is an issue with the local network.
+        You may also download a release manually from {RELEASES}.
+        "}
+    };
+
+    client
+        .send(client.get(url))
+        .await
+        .and_then(|response| response.error_for_status())
+        .context_lazy(|| Error::Download(url.to_string()))
+        .help_lazy(help)?
+        .bytes()
+        .await
+        .context_lazy(|| Error::Download(url.to_string()))
+        .help_lazy(help)
+}
+
+/// Lock the data root and the data root of each profile inside it,
+/// failing if `broker run` is using any of them.
+fn lock_data_roots(data_root: &Path) -> Result<Vec<InstanceLock>, Report<Error>> {
+    let profiles = std::fs::read_dir(data_root.join("profiles"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir());
+    std::iter::once(data_root.to_path_buf())
+        .chain(profiles)
+        .map(|root| {
+            InstanceLock::acquire(&root)
+                .change_context_lazy(|| Error::Running(root.clone()))
+                .help("stop 'broker run' before updating Broker, then start it again afterwards")
+        })
+        .collect()
+}
+
+/// Verify that the executable downloaded from the asset was signed with the Ed25519 key.
+fn verify_signature(
+    key: &[u8],
+    asset: &str,
+    executable: &[u8],
+    signature: &[u8],
+) -> Result<(), Report<Error>> {
+    // ring intentionally doesn't explain why verification failed, so there's no underlying error to report.
+    if UnparsedPublicKey::new(&ED25519, key)
+        .verify(executable, signature)
+        .is_ok()
+    {
+        return Ok(());
+    }
+    report!(Error::VerifySignature(asset.to_string()))
+        .wrap_err()
+        .help(formatdoc! {"
+        The executable may have been modified after it was published, or it wasn't published by FOSSA.
+        Do not run it; download a release manually from {RELEASES} and verify it as described in the documentation for 'broker update'.
+        "})
+}
+
+/// Replace the executable at `current` with `content`.
+///
+/// The new executable is written next to the current one and then renamed over it,
+/// so that the current executable is never left partially written.
+fn replace(current: &Path, content: &[u8]) -> std::io::Result<()> {
+    let staged = with_suffix(current, "new");
+    std::fs::write(&staged, content)?;
+    std::fs::set_permissions(&staged, std::fs::metadata(current)?.permissions())?;
+    swap(current, &staged)
+}
+
+/// A running executable can be replaced by renaming over it on unix.
+#[cfg(target_family = "unix")]
+fn swap(current: &Path, staged: &Path) -> std::io::Result<()> {
+    std::fs::rename(staged, current)
+}
+
+/// A running executable can't be overwritten on Windows, but it can be renamed out of the way.
+/// The old executable is removed the next time Broker is updated.
+#[cfg(target_family = "windows")]
+fn swap(current: &Path, staged: &Path) -> std::io::Result<()> {
+    let old = with_suffix(current, "old");
+    if old.exists() {
+        std::fs::remove_file(&old)?;
+    }
+    std::fs::rename(current, &old)?;
+    std::fs::rename(staged, current)
+}
+
+/// The path with a suffix appended to its file name, like `broker.new`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tag() {
+        let version = parse_tag("v0.3.2").expect("must parse");
+        assert_eq!(version, Version::new(0, 3, 2));
+        assert!(parse_tag("0.3.2").is_err());
+        assert!(parse_tag("vnext").is_err());
+    }
+
+    #[test]
+    fn verifies_signature() {
+        // Test 2 from RFC 8032, section 7.1.
+        let key = hex("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c");
+        let signature = hex("92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00");
+
+        verify_signature(&key, "broker", &[0x72], &signature).expect("must verify signature");
+        verify_signature(&key, "broker", &[0x73], &signature)
+            .expect_err("must reject modified executable");
+        verify_signature(&RELEASE_SIGNING_KEY, "broker", &[0x72], &signature)
+            .expect_err("must reject executable signed with another key");
+    }
+
+    fn hex(value: &str) -> Vec<u8> {
+        (0..value.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&value[i..i + 2], 16).expect("must parse hex"))
+            .collect()
+    }
+
+    #[test]
+    fn refuses_while_running() {
+        let root = tempfile::tempdir().expect("must create temp dir");
+        let profile = root.path().join("profiles").join("staging");
+
+        let running = InstanceLock::acquire(&profile).expect("must acquire lock");
+        let err = lock_data_roots(root.path()).expect_err("must not lock data root in use");
+        assert!(matches!(err.current_context(), Error::Running(path) if path == &profile));
+
+        drop(running);
+        let locks = lock_data_roots(root.path()).expect("must lock data roots");
+        assert_eq!(locks.len(), 2);
+    }
+
+    #[test]
+    fn replaces_executable() {
+        let tmp = tempfile::TempDir::new().expect("must create tempdir");
+        let current = tmp.path().join("broker");
+        std::fs::write(&current, b"old").expect("must write current");
+
+        replace(&current, b"new").expect("must replace");
+        assert_eq!(std::fs::read(&current).expect("must read"), b"new");
+        assert!(!with_suffix(&current, "new").exists());
+    }
+}
@@ -2,13 +2,28 @@
 
 use error_stack::{Result, ResultExt};
 
+use crate::{
+    api::{http::client::Clients, remote::git},
+    bandwidth, crypto,
+    ext::tempfile,
+    AppContext,
+};
+
 // Keep `config` opaque externally, only export what is required for callers.
 // To re-export a symbol, just `pub use`.
 mod args;
 mod file;
+mod lint;
 
-pub use args::{RawFixArgs, RawInitArgs, RawRunArgs, RunArgs, DISABLE_FILE_DISCOVERY_VAR};
-pub use file::Config;
+pub use args::{
+    BackfillArgs, ConfigShowArgs, DbMigrateRemoteArgs, DbResetArgs, DebugCloneArgs, PauseArgs,
+    QueueDropArgs, RawBackfillArgs, RawConfigShowArgs, RawDbMigrateRemoteArgs, RawDbResetArgs,
+    RawDebugCloneArgs, RawFixArgs, RawInitArgs, RawPauseArgs, RawQueueDropArgs,
+    RawReportSummaryArgs, RawRunArgs, RawScanArgs, RawSimulateArgs, RawUpdateArgs,
+    ReportSummaryArgs, RunArgs, ScanArgs, SimulateArgs, UpdateArgs, DISABLE_FILE_DISCOVERY_VAR,
+};
+pub use file::{Config, Effective};
+pub use lint::{lint, Lint};
 
 /// Errors that are possibly surfaced during validation of config values.
 #[derive(Debug, thiserror::Error)]
@@ -28,6 +43,10 @@ pub enum Error {
     /// and bubbles up the context from [`file`] to the user.
     #[error("load config file")]
     LoadConfigFile,
+
+    /// The settings in the config file couldn't be applied once it was loaded.
+    #[error("apply config file")]
+    Apply,
 }
 
 /// Load the config for the application.
@@ -36,3 +55,19 @@ pub async fn load(args: &RunArgs) -> Result<file::Config, Error> {
         .await
         .change_context(Error::LoadConfigFile)
 }
+
+/// The application context, using the HTTP clients shared by the process, with the connection settings in the config.
+///
+/// This also installs the configured crypto mode, git backend, and bandwidth limit, which are process wide,
+/// along with the directory under the data root holding temporary files with secrets.
+/// Both the `broker` binary and [`crate::facade`] apply the config this way once it's loaded;
+/// applying a config whose process wide settings differ from those already installed is an error.
+pub fn apply(ctx: &AppContext, conf: &Config) -> Result<AppContext, Error> {
+    tempfile::install_secrets_root(ctx.data_root()).change_context(Error::Apply)?;
+    crypto::install(*conf.crypto_mode()).change_context(Error::Apply)?;
+    git::install_backend(crypto::git_backend(crypto::mode(), *conf.git_backend()))
+        .change_context(Error::Apply)?;
+    bandwidth::install(*conf.bandwidth()).change_context(Error::Apply)?;
+    let http = Clients::shared(*conf.http()).change_context(Error::Apply)?;
+    Ok(ctx.clone().with_http(http))
+}
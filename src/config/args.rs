@@ -21,7 +21,7 @@
 //! This odd dichotomy is why we have to leak the `Raw*` implementations to the package consumer,
 //! because the consumer (`main`) needs to be able to give this type to `clap` for it to be parsed.
 
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use clap::Parser;
 use derive_new::new;
@@ -31,9 +31,12 @@ use indoc::indoc;
 use serde::Serialize;
 
 use crate::{
-    debug::BundleExport,
+    cmd::config::Format as ConfigFormat,
+    cmd::fix::ScanCheck,
+    cmd::report::Format as ReportFormat,
+    debug::{BundleExport, BundleUpload},
     ext::{
-        error_stack::{merge_error_stacks, DescribeContext, ErrorHelper},
+        error_stack::{merge_error_stacks, DescribeContext, ErrorHelper, IntoContext},
         io,
         result::{WrapErr, WrapOk},
     },
@@ -57,6 +60,26 @@ pub enum Error {
     /// The data root was not able to be determined.
     #[error("determine data root")]
     DataRoot,
+
+    /// The pattern used to select tags is not a valid glob.
+    #[error("parse tag pattern")]
+    TagPattern,
+
+    /// The fixtures directory for a simulation doesn't exist.
+    #[error("locate fixtures directory")]
+    Fixtures,
+
+    /// The period to report on is not a valid duration.
+    #[error("parse report period")]
+    ReportPeriod,
+
+    /// The profile name can't be used as a directory name.
+    #[error("validate profile name")]
+    Profile,
+
+    /// The HTTP clients for the context couldn't be constructed.
+    #[error("construct HTTP clients")]
+    HttpClients,
 }
 
 /// Arguments used by the "fix" command.
@@ -76,6 +99,19 @@ pub struct RawFixArgs {
     /// to resolve the issue, but this option causes the debug bundle to always be saved.
     #[arg(long)]
     export_bundle: bool,
+
+    /// Upload the debug bundle to FOSSA Support.
+    ///
+    /// This implies `--export-bundle`. Once uploaded, Broker prints a reference ID
+    /// which can be provided to FOSSA Support instead of attaching the debug bundle.
+    #[arg(long)]
+    upload_bundle: bool,
+
+    /// Only check connections to integrations, without cloning and analyzing one of them.
+    ///
+    /// By default, the first integration that Broker connects to is also cloned and analyzed with FOSSA CLI.
+    #[arg(long)]
+    no_scan_check: bool,
 }
 
 impl RawFixArgs {
@@ -91,19 +127,101 @@ impl RawFixArgs {
     #[tracing::instrument]
     pub async fn validate(self) -> Result<FixArgs, Report<Error>> {
         let runtime = self.runtime.validate().await?;
-        let export_bundle = if self.export_bundle {
+        let export_bundle = if self.export_bundle || self.upload_bundle {
             BundleExport::Always
         } else {
             BundleExport::Auto
         };
+        let upload_bundle = if self.upload_bundle {
+            BundleUpload::Enable
+        } else {
+            BundleUpload::Disable
+        };
+        let scan_check = if self.no_scan_check {
+            ScanCheck::Disable
+        } else {
+            ScanCheck::Representative
+        };
 
         Ok(FixArgs {
             runtime,
             export_bundle,
+            upload_bundle,
+            scan_check,
+        })
+    }
+}
+
+/// Arguments used by the "backfill" command.
+#[derive(Debug, Clone, Parser, Serialize, new)]
+#[command(version, about)]
+pub struct RawBackfillArgs {
+    /// Include all the same args as used with `run`.
+    ///
+    /// These are flattened into the args, so they appear to the user
+    /// as though they were in this struct directly.
+    #[clap(flatten)]
+    runtime: RawRunArgs,
+
+    /// The remote of the integration to backfill, as written in the config file.
+    integration: String,
+
+    /// Only scan tags whose names match this glob, like 'v*'.
+    ///
+    /// If unset, every tag is considered.
+    #[arg(long)]
+    tags_matching: Option<String>,
+
+    /// The maximum number of tags to scan, starting from the most recent.
+    #[arg(long, default_value_t = 50)]
+    max: usize,
+}
+
+impl RawBackfillArgs {
+    /// Validate the raw args provided.
+    ///
+    /// The runtime args are validated the same way as for `run`.
+    #[tracing::instrument]
+    pub async fn validate(self) -> Result<BackfillArgs, Report<Error>> {
+        let runtime = self.runtime.validate().await?;
+        let tags_matching = self
+            .tags_matching
+            .map(|pattern| {
+                glob::Pattern::new(&pattern)
+                    .context(Error::TagPattern)
+                    .help("provide a glob like 'v*' or 'release-1.*'")
+                    .describe_lazy(|| format!("provided pattern: '{pattern}'"))
+            })
+            .transpose()?;
+        Ok(BackfillArgs {
+            runtime,
+            integration: self.integration,
+            tags_matching,
+            max: self.max,
         })
     }
 }
 
+/// Arguments used by the "backfill" command.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct BackfillArgs {
+    /// Runtime config options, like those used in `run`.
+    #[getset(get = "pub")]
+    runtime: RunArgs,
+
+    /// The remote of the integration to backfill.
+    #[getset(get = "pub")]
+    integration: String,
+
+    /// The pattern tags must match to be scanned, if restricted.
+    #[getset(get = "pub")]
+    tags_matching: Option<glob::Pattern>,
+
+    /// The maximum number of tags to scan.
+    #[getset(get_copy = "pub")]
+    max: usize,
+}
+
 /// Arguments used by the "run" command.
 #[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
 pub struct FixArgs {
@@ -114,6 +232,473 @@ pub struct FixArgs {
     /// How to export the debug bundle.
     #[getset(get_copy = "pub")]
     export_bundle: BundleExport,
+
+    /// Whether to upload the debug bundle.
+    #[getset(get_copy = "pub")]
+    upload_bundle: BundleUpload,
+
+    /// Whether to clone and analyze an integration after checking connections.
+    #[getset(get_copy = "pub")]
+    scan_check: ScanCheck,
+}
+
+/// Arguments used by the "db reset" command.
+#[derive(Debug, Clone, Parser, Serialize, new)]
+#[command(version, about)]
+pub struct RawDbResetArgs {
+    /// Include all the same args as used with `run`.
+    ///
+    /// These are flattened into the args, so they appear to the user
+    /// as though they were in this struct directly.
+    #[clap(flatten)]
+    runtime: RawRunArgs,
+
+    /// The remote of the integration to reset, as written in the config file.
+    #[arg(long)]
+    integration: String,
+
+    /// The name of a branch or tag to reset.
+    ///
+    /// If unset, every reference for the integration is reset.
+    #[arg(long)]
+    reference: Option<String>,
+}
+
+impl RawDbResetArgs {
+    /// Validate the raw args provided.
+    ///
+    /// The runtime args are validated the same way as for `run`.
+    #[tracing::instrument]
+    pub async fn validate(self) -> Result<DbResetArgs, Report<Error>> {
+        let runtime = self.runtime.validate().await?;
+        Ok(DbResetArgs {
+            runtime,
+            integration: self.integration,
+            reference: self.reference,
+        })
+    }
+}
+
+/// Arguments used by the "db reset" command.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct DbResetArgs {
+    /// Runtime config options, like those used in `run`.
+    runtime: RunArgs,
+
+    /// The remote of the integration to reset.
+    integration: String,
+
+    /// The name of the branch or tag to reset, if only one reference should be reset.
+    reference: Option<String>,
+}
+
+/// Arguments used by the "db migrate-remote" command.
+#[derive(Debug, Clone, Parser, Serialize, new)]
+#[command(version, about)]
+pub struct RawDbMigrateRemoteArgs {
+    /// Include all the same args as used with `run`.
+    ///
+    /// These are flattened into the args, so they appear to the user
+    /// as though they were in this struct directly.
+    #[clap(flatten)]
+    runtime: RawRunArgs,
+
+    /// The remote the integration was previously configured with.
+    old: String,
+
+    /// The remote of the integration, as now written in the config file.
+    new: String,
+}
+
+impl RawDbMigrateRemoteArgs {
+    /// Validate the raw args provided.
+    ///
+    /// The runtime args are validated the same way as for `run`.
+    #[tracing::instrument]
+    pub async fn validate(self) -> Result<DbMigrateRemoteArgs, Report<Error>> {
+        let runtime = self.runtime.validate().await?;
+        Ok(DbMigrateRemoteArgs {
+            runtime,
+            old: self.old,
+            new: self.new,
+        })
+    }
+}
+
+/// Arguments used by the "db migrate-remote" command.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct DbMigrateRemoteArgs {
+    /// Runtime config options, like those used in `run`.
+    runtime: RunArgs,
+
+    /// The remote the integration was previously configured with.
+    old: String,
+
+    /// The remote of the integration, as now written in the config file.
+    new: String,
+}
+
+/// Arguments used by the "queue drop" command.
+#[derive(Debug, Clone, Parser, Serialize, new)]
+#[command(version, about)]
+pub struct RawQueueDropArgs {
+    /// Include all the same args as used with `run`.
+    ///
+    /// These are flattened into the args, so they appear to the user
+    /// as though they were in this struct directly.
+    #[clap(flatten)]
+    runtime: RawRunArgs,
+
+    /// The ID of the job to drop, as shown by `broker queue ls`.
+    id: String,
+}
+
+impl RawQueueDropArgs {
+    /// Validate the raw args provided.
+    ///
+    /// The runtime args are validated the same way as for `run`.
+    #[tracing::instrument]
+    pub async fn validate(self) -> Result<QueueDropArgs, Report<Error>> {
+        let runtime = self.runtime.validate().await?;
+        Ok(QueueDropArgs {
+            runtime,
+            id: self.id,
+        })
+    }
+}
+
+/// Arguments used by the "queue drop" command.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct QueueDropArgs {
+    /// Runtime config options, like those used in `run`.
+    runtime: RunArgs,
+
+    /// The ID of the job to drop.
+    id: String,
+}
+
+/// Arguments used by the "pause" and "resume" commands.
+#[derive(Debug, Clone, Parser, Serialize, new)]
+#[command(version, about)]
+pub struct RawPauseArgs {
+    /// Include all the same args as used with `run`.
+    ///
+    /// These are flattened into the args, so they appear to the user
+    /// as though they were in this struct directly.
+    #[clap(flatten)]
+    runtime: RawRunArgs,
+
+    /// The remote of the integration, as written in the config file.
+    #[arg(required_unless_present = "all")]
+    integration: Option<String>,
+
+    /// Apply to every integration instead of a single one.
+    #[arg(long, conflicts_with = "integration")]
+    all: bool,
+}
+
+impl RawPauseArgs {
+    /// Validate the raw args provided.
+    ///
+    /// The runtime args are validated the same way as for `run`.
+    #[tracing::instrument]
+    pub async fn validate(self) -> Result<PauseArgs, Report<Error>> {
+        let runtime = self.runtime.validate().await?;
+        // Clap requires exactly one of the two, so the integration is only unset when applying to all of them.
+        let integration = if self.all { None } else { self.integration };
+        Ok(PauseArgs {
+            runtime,
+            integration,
+        })
+    }
+}
+
+/// Arguments used by the "pause" and "resume" commands.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct PauseArgs {
+    /// Runtime config options, like those used in `run`.
+    runtime: RunArgs,
+
+    /// The remote of the integration, or `None` to apply to every integration.
+    integration: Option<String>,
+}
+
+/// Arguments used by the "debug clone" command.
+#[derive(Debug, Clone, Parser, Serialize, new)]
+#[command(version, about)]
+pub struct RawDebugCloneArgs {
+    /// Include all the same args as used with `run`.
+    ///
+    /// These are flattened into the args, so they appear to the user
+    /// as though they were in this struct directly.
+    #[clap(flatten)]
+    runtime: RawRunArgs,
+
+    /// The remote of the integration to clone, as written in the config file.
+    integration: String,
+
+    /// The name of the branch or tag to clone.
+    ///
+    /// If unset, the first reference the integration scans is cloned.
+    #[arg(long = "ref")]
+    reference: Option<String>,
+
+    /// The directory to which the clone is moved once it's cloned; it must not exist yet.
+    ///
+    /// If unset, the clone is kept in the temporary directory it was cloned into.
+    #[arg(long)]
+    dest: Option<PathBuf>,
+}
+
+impl RawDebugCloneArgs {
+    /// Validate the raw args provided.
+    ///
+    /// The runtime args are validated the same way as for `run`.
+    #[tracing::instrument]
+    pub async fn validate(self) -> Result<DebugCloneArgs, Report<Error>> {
+        let runtime = self.runtime.validate().await?;
+        Ok(DebugCloneArgs {
+            runtime,
+            integration: self.integration,
+            reference: self.reference,
+            dest: self.dest,
+        })
+    }
+}
+
+/// Arguments used by the "debug clone" command.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct DebugCloneArgs {
+    /// Runtime config options, like those used in `run`.
+    runtime: RunArgs,
+
+    /// The remote of the integration to clone.
+    integration: String,
+
+    /// The name of the branch or tag to clone, if one was requested.
+    reference: Option<String>,
+
+    /// The directory to which the clone is moved, if one was requested.
+    dest: Option<PathBuf>,
+}
+
+/// Arguments used by the "report summary" command.
+#[derive(Debug, Clone, Parser, Serialize, new)]
+#[command(version, about)]
+pub struct RawReportSummaryArgs {
+    /// Include all the same args as used with `run`.
+    ///
+    /// These are flattened into the args, so they appear to the user
+    /// as though they were in this struct directly.
+    #[clap(flatten)]
+    runtime: RawRunArgs,
+
+    /// Report on scans recorded within this long before now, like '7d' or '24h'.
+    #[arg(long, default_value = "7d")]
+    since: String,
+
+    /// The format in which to show the report.
+    #[arg(long, value_enum, default_value_t)]
+    format: ReportFormat,
+}
+
+impl RawReportSummaryArgs {
+    /// Validate the raw args provided.
+    ///
+    /// The runtime args are validated the same way as for `run`.
+    #[tracing::instrument]
+    pub async fn validate(self) -> Result<ReportSummaryArgs, Report<Error>> {
+        let runtime = self.runtime.validate().await?;
+        let since = humantime::parse_duration(&self.since)
+            .context(Error::ReportPeriod)
+            .help("provide a duration like '7d' or '24h'")
+            .describe_lazy(|| format!("provided period: '{}'", self.since))?;
+        Ok(ReportSummaryArgs {
+            runtime,
+            since,
+            format: self.format,
+        })
+    }
+}
+
+/// Arguments used by the "report summary" command.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct ReportSummaryArgs {
+    /// Runtime config options, like those used in `run`.
+    #[getset(get = "pub")]
+    runtime: RunArgs,
+
+    /// How long before now the reported period starts.
+    #[getset(get_copy = "pub")]
+    since: Duration,
+
+    /// The format in which to show the report.
+    #[getset(get_copy = "pub")]
+    format: ReportFormat,
+}
+
+/// Arguments used by the "simulate" command.
+#[derive(Debug, Clone, Parser, Serialize, new)]
+#[command(version, about)]
+pub struct RawSimulateArgs {
+    /// Include all the same args as used with `run`.
+    ///
+    /// These are flattened into the args, so they appear to the user
+    /// as though they were in this struct directly.
+    #[clap(flatten)]
+    runtime: RawRunArgs,
+
+    /// The directory containing the recorded `git ls-remote` output and FOSSA CLI source units to replay.
+    #[arg(long)]
+    fixtures: PathBuf,
+}
+
+impl RawSimulateArgs {
+    /// Validate the raw args provided.
+    ///
+    /// The runtime args are validated the same way as for `run`.
+    #[tracing::instrument]
+    pub async fn validate(self) -> Result<SimulateArgs, Report<Error>> {
+        let runtime = self.runtime.validate().await?;
+        if !self.fixtures.is_dir() {
+            return report!(Error::Fixtures)
+                .wrap_err()
+                .describe_lazy(|| format!("provided path: '{}'", self.fixtures.display()))
+                .help("provide the directory containing the 'ls-remote' and 'source-units' directories");
+        }
+        Ok(SimulateArgs {
+            runtime,
+            fixtures: self.fixtures,
+        })
+    }
+}
+
+/// Arguments used by the "simulate" command.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct SimulateArgs {
+    /// Runtime config options, like those used in `run`.
+    runtime: RunArgs,
+
+    /// The directory containing the fixtures to replay.
+    fixtures: PathBuf,
+}
+
+/// Arguments used by the "config show" command.
+#[derive(Debug, Clone, Parser, Serialize, new)]
+#[command(version, about)]
+pub struct RawConfigShowArgs {
+    /// Include all the same args as used with `run`.
+    ///
+    /// These are flattened into the args, so they appear to the user
+    /// as though they were in this struct directly.
+    #[clap(flatten)]
+    runtime: RawRunArgs,
+
+    /// Show the configuration as Broker resolved it, with defaults applied and secrets redacted,
+    /// instead of the config file as written.
+    #[arg(long)]
+    effective: bool,
+
+    /// The format in which to show the effective configuration.
+    #[arg(long, value_enum, default_value_t, requires = "effective")]
+    format: ConfigFormat,
+}
+
+impl RawConfigShowArgs {
+    /// Validate the raw args provided.
+    ///
+    /// The runtime args are validated the same way as for `run`.
+    #[tracing::instrument]
+    pub async fn validate(self) -> Result<ConfigShowArgs, Report<Error>> {
+        let runtime = self.runtime.validate().await?;
+        Ok(ConfigShowArgs {
+            runtime,
+            effective: self.effective,
+            format: self.format,
+        })
+    }
+}
+
+/// Arguments used by the "config show" command.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct ConfigShowArgs {
+    /// Runtime config options, like those used in `run`.
+    #[getset(get = "pub")]
+    runtime: RunArgs,
+
+    /// Whether to show the effective configuration.
+    #[getset(get_copy = "pub")]
+    effective: bool,
+
+    /// The format in which to show the effective configuration.
+    #[getset(get_copy = "pub")]
+    format: ConfigFormat,
+}
+
+/// Arguments used by the "scan" command.
+#[derive(Debug, Clone, Parser, Serialize, new)]
+#[command(version, about)]
+pub struct RawScanArgs {
+    /// Include all the same args as used with `run`.
+    ///
+    /// These are flattened into the args, so they appear to the user
+    /// as though they were in this struct directly.
+    #[clap(flatten)]
+    runtime: RawRunArgs,
+
+    /// Only scan integrations in this group.
+    #[arg(long)]
+    group: Option<String>,
+
+    /// Only scan the integration with this remote, as written in the config file.
+    #[arg(long)]
+    integration: Option<String>,
+
+    /// Scan every reference, even those which haven't changed since they were last scanned.
+    #[arg(long)]
+    all: bool,
+}
+
+impl RawScanArgs {
+    /// Validate the raw args provided.
+    ///
+    /// The runtime args are validated the same way as for `run`.
+    #[tracing::instrument]
+    pub async fn validate(self) -> Result<ScanArgs, Report<Error>> {
+        let runtime = self.runtime.validate().await?;
+        Ok(ScanArgs {
+            runtime,
+            group: self.group,
+            integration: self.integration,
+            all: self.all,
+        })
+    }
+}
+
+/// Arguments used by the "scan" command.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct ScanArgs {
+    /// Runtime config options, like those used in `run`.
+    #[getset(get = "pub")]
+    runtime: RunArgs,
+
+    /// The group of integrations to scan, if restricted to a group.
+    #[getset(get = "pub")]
+    group: Option<String>,
+
+    /// The remote of the integration to scan, if restricted to one integration.
+    #[getset(get = "pub")]
+    integration: Option<String>,
+
+    /// Whether to scan every reference, even those which haven't changed.
+    #[getset(get_copy = "pub")]
+    all: bool,
 }
 
 /// Arguments used by the "run" command.
@@ -143,6 +728,13 @@ pub struct RawRunArgs {
     /// - On Windows: `%USERPROFILE%\.config\fossa\broker`
     #[arg(short = 'r', long)]
     data_root: Option<PathBuf>,
+
+    /// The profile to use, for example one per FOSSA organization or environment.
+    ///
+    /// Each profile has its own data root, at `profiles/<name>` inside the data root,
+    /// from which its config file and database are read unless they're provided explicitly.
+    #[arg(long, env = "BROKER_PROFILE")]
+    profile: Option<String>,
 }
 
 impl RawRunArgs {
@@ -157,11 +749,7 @@ impl RawRunArgs {
     /// Database implementations then create it if it does not exist.
     #[tracing::instrument]
     pub async fn validate(self) -> Result<RunArgs, Report<Error>> {
-        let data_root = match self.data_root {
-            Some(data_root) => data_root,
-            None => default_data_root().await?,
-        };
-        let ctx = AppContext::new(data_root);
+        let ctx = app_context(self.data_root, self.profile).await?;
 
         let config_path = if let Some(provided_path) = self.config_file_path {
             ConfigFilePath::from(provided_path).wrap_ok()
@@ -265,9 +853,50 @@ pub struct RunArgs {
     context: AppContext,
 }
 
-/// Arguments used by the "init" command.
+/// Arguments used by the "update" command.
 #[derive(Debug, Clone, Parser, Serialize, new)]
 #[command(version, about)]
+pub struct RawUpdateArgs {
+    /// Only check whether a newer version of Broker is available, without installing it.
+    #[arg(long)]
+    check: bool,
+
+    /// The root data directory for Broker.
+    /// Broker uses this directory to store working state and to read configuration information.
+    ///
+    /// - On Linux and macOS: `~/.config/fossa/broker/`
+    /// - On Windows: `%USERPROFILE%\.config\fossa\broker`
+    #[arg(short = 'r', long)]
+    data_root: Option<PathBuf>,
+}
+
+impl RawUpdateArgs {
+    /// Validate the raw args provided.
+    #[tracing::instrument]
+    pub async fn validate(self) -> Result<UpdateArgs, Report<Error>> {
+        let context = app_context(self.data_root, None).await?;
+        Ok(UpdateArgs {
+            check: self.check,
+            context,
+        })
+    }
+}
+
+/// Arguments used by the "update" command.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct UpdateArgs {
+    /// Whether to only check for a newer version.
+    #[getset(get_copy = "pub")]
+    check: bool,
+
+    /// The context for the data root.
+    #[getset(get = "pub")]
+    context: AppContext,
+}
+
+/// Arguments used by the "init" command.
+#[derive(Debug, Clone, Parser, Serialize, new, CopyGetters)]
+#[command(version, about)]
 pub struct RawInitArgs {
     /// The root data directory for Broker.
     /// Broker uses this directory to store working state and to read configuration information.
@@ -276,18 +905,23 @@ pub struct RawInitArgs {
     /// - On Windows: `%USERPROFILE%\.config\fossa\broker`
     #[arg(short = 'r', long)]
     data_root: Option<PathBuf>,
+
+    /// The profile to initialize, whose config file is written to `profiles/<name>` inside the data root.
+    #[arg(long, env = "BROKER_PROFILE")]
+    profile: Option<String>,
+
+    /// Also write examples for running Broker to `examples` in the data root:
+    /// a systemd unit, a Windows service definition, a Docker Compose file, and a GitHub Actions workflow.
+    #[arg(long)]
+    #[getset(get_copy = "pub")]
+    examples: bool,
 }
 
 impl RawInitArgs {
     /// validate the args for the init subcommand
     #[tracing::instrument]
     pub async fn validate(self) -> Result<AppContext, Report<Error>> {
-        let data_root = match self.data_root {
-            Some(data_root) => data_root,
-            None => default_data_root().await?,
-        };
-
-        AppContext::new(data_root).wrap_ok()
+        app_context(self.data_root, self.profile).await
     }
 }
 
@@ -372,6 +1006,36 @@ fn discovery_enabled() -> bool {
         .unwrap_or(true)
 }
 
+/// The context for the data root, or the default data root if none was provided,
+/// with the profile selected if one was provided.
+async fn app_context(
+    data_root: Option<PathBuf>,
+    profile: Option<String>,
+) -> Result<AppContext, Report<Error>> {
+    let data_root = match data_root {
+        Some(data_root) => data_root,
+        None => default_data_root().await?,
+    };
+    let ctx = AppContext::new(data_root).change_context(Error::HttpClients)?;
+    match profile {
+        Some(profile) => validate_profile(&profile).map(|_| ctx.with_profile(profile)),
+        None => ctx.wrap_ok(),
+    }
+}
+
+/// Profiles are directories inside the data root, so their names are restricted to characters
+/// which are valid in directory names on every platform and can't refer to other directories.
+fn validate_profile(profile: &str) -> Result<(), Report<Error>> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if !profile.is_empty() && profile.chars().all(valid) {
+        return Ok(());
+    }
+    report!(Error::Profile)
+        .wrap_err()
+        .help("use only letters, numbers, '-', and '_' in profile names")
+        .describe_lazy(|| format!("provided profile: '{profile}'"))
+}
+
 async fn default_data_root() -> Result<PathBuf, Report<Error>> {
     io::home_dir()
         .await
@@ -41,18 +41,26 @@ use getset::Getters;
 use serde::Deserialize;
 
 use crate::{
+    admin,
     api::{self},
-    debug,
+    bandwidth,
+    config::Lint,
+    crypto, db, debug, disk, encryption,
     ext::{
         error_stack::{DescribeContext, ErrorHelper, IntoContext},
         result::WrapErr,
+        secrecy::ComparableSecretString,
     },
+    fossa_cli, hooks, locale, notify, secrets, size_limits,
 };
 
 use crate::ext::io;
 
+mod effective;
 mod v1;
 
+pub use effective::Effective;
+
 /// Errors that are possibly surfaced during validation of config values.
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -84,6 +92,67 @@ pub struct Config {
 
     /// Configured integration points.
     integrations: api::remote::Integrations,
+
+    /// Configured notification sinks.
+    notifications: notify::Config,
+
+    /// Configuration for downloading FOSSA CLI.
+    fossa_cli: fossa_cli::Config,
+
+    /// Configuration for disk space monitoring.
+    disk_space: disk::Config,
+
+    /// Configuration for retrying uploads which failed.
+    upload_retry: api::fossa::UploadRetry,
+
+    /// Configuration for checking uploaded scans for issues.
+    policy_check: api::fossa::PolicyCheck,
+
+    /// Configuration for reading metadata about CI builds.
+    ci_metadata: api::fossa::CiMetadata,
+
+    /// Commands run at points in the lifecycle of each scan.
+    hooks: hooks::Config,
+
+    /// Configuration for exporting SBOMs of uploaded scans.
+    sbom_export: api::fossa::SbomExport,
+
+    /// Connection pool settings for outbound HTTP requests.
+    http: api::http::client::Config,
+
+    /// The git client used to list references and clone repositories.
+    git_backend: api::remote::git::Backend,
+
+    /// The portable git to download if the system git is missing or too old.
+    portable_git: Option<api::remote::git::executable::Portable>,
+
+    /// Whether the cryptography used for outbound connections is constrained.
+    crypto_mode: crypto::Mode,
+
+    /// The key used to encrypt sensitive artifacts written to disk, if encryption is enabled.
+    encryption: Option<encryption::Key>,
+
+    /// The language of messages shown by `broker fix`, if configured; otherwise it's detected from the environment.
+    locale: Option<locale::Locale>,
+
+    /// Configuration for checking scans for secrets before they're uploaded.
+    secrets_guard: secrets::Config,
+
+    /// Limits on the bandwidth used to clone code and download files.
+    bandwidth: bandwidth::Config,
+
+    /// Limits on the size of clones and of the results uploaded to FOSSA.
+    size_limits: size_limits::Config,
+
+    /// Configuration for the database Broker uses to store its state.
+    database: db::Config,
+
+    /// Configuration for the admin API, if it's enabled.
+    admin_api: Option<admin::Config>,
+
+    /// Settings found to be unused or ineffective while validating the config file,
+    /// which can't be found from the validated config; see [`lint`](crate::config::lint).
+    lints: Vec<Lint>,
 }
 
 impl Config {
@@ -107,6 +176,17 @@ impl Config {
             v => fail(Error::Unsupported, v).help("ensure that Broker is at the latest version"),
         }
     }
+
+    /// The effective configuration, with defaults applied and secrets redacted.
+    pub fn effective(&self) -> Effective {
+        Effective::from(self)
+    }
+
+    /// Every secret in the configuration, such as API keys, tokens, and passwords,
+    /// so that they can be redacted from output Broker writes.
+    pub fn secrets(&self) -> Vec<ComparableSecretString> {
+        effective::secrets(self)
+    }
 }
 
 /// Fail the config load process with the provided file.
@@ -0,0 +1,847 @@
+//! The effective configuration: the config as Broker resolved it after validation.
+//!
+//! This is laid out like the v1 config file, but with every default filled in,
+//! groups resolved into the integrations that use them, and watched branches
+//! that Broker inferred included alongside those that were configured.
+//! Secrets are always replaced with [`REDACTION_LITERAL`], so the output is safe to share.
+//! The secrets themselves are listed by [`secrets`], so that they can be redacted from other output.
+
+use std::{
+    collections::BTreeMap,
+    num::{NonZeroU32, NonZeroUsize},
+    path::PathBuf,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    api::{
+        fossa::{FanOut, Retries, SbomFormat},
+        http,
+        remote::{
+            self,
+            bucket::{self, Bucket},
+            gerrit,
+            git::{transport::Transport, Backend},
+            local::RevisionScheme,
+            BranchImportStrategy, CliEnvValue, CloneStrategy, Protocol, ScanMode, ScanOnStartup,
+            TagImportStrategy,
+        },
+        ssh,
+    },
+    crypto,
+    ext::secrecy::{ComparableSecretString, REDACTION_LITERAL},
+    hooks,
+    locale::Locale,
+    notify::{self, smtp, webhook},
+    secrets,
+};
+
+use super::Config;
+
+/// The effective configuration, ready to be serialized.
+#[derive(Debug, Clone, Serialize)]
+pub struct Effective {
+    version: usize,
+    fossa_endpoint: String,
+    fossa_integration_key: &'static str,
+    fossa_api: FossaApi,
+    debugging: Debugging,
+    fossa_cli: FossaCli,
+    disk_space: DiskSpace,
+    upload_retry: UploadRetry,
+    policy_check: PolicyCheck,
+    ci_metadata: CiMetadata,
+    hooks: Hooks,
+    sbom_export: SbomExport,
+    http: Http,
+    git_backend: Backend,
+    portable_git: Option<PortableGit>,
+    crypto_mode: crypto::Mode,
+    encryption: Option<Encryption>,
+    locale: Option<Locale>,
+    secrets_guard: secrets::Config,
+    bandwidth: Bandwidth,
+    size_limits: SizeLimits,
+    database: Database,
+    admin_api: Option<AdminApi>,
+    notifications: Vec<Notification>,
+    integrations: Vec<Integration>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct FossaApi {
+    upload_path: String,
+    upload_query: BTreeMap<String, String>,
+    match_existing_projects: bool,
+    upload_contributors: bool,
+    max_uploads_per_minute: Option<u32>,
+    targets: Vec<FossaTarget>,
+    fan_out: FanOut,
+    retries: Retries,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct FossaTarget {
+    name: String,
+    fossa_endpoint: String,
+    fossa_integration_key: &'static str,
+    max_uploads_per_minute: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct FossaCli {
+    download_base_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct UploadRetry {
+    max_age: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PolicyCheck {
+    enabled: bool,
+    timeout: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CiMetadata {
+    location: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SbomExport {
+    formats: Vec<SbomFormat>,
+    timeout: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Http {
+    max_idle_connections_per_host: usize,
+    idle_timeout: String,
+    tcp_keepalive: String,
+    max_connections_per_host: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Encryption {
+    key: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PortableGit {
+    url: String,
+    sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Hooks {
+    post_clone: Vec<Hook>,
+    post_scan: Vec<Hook>,
+    post_upload: Vec<Hook>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Hook {
+    command: String,
+    args: Vec<String>,
+    timeout: String,
+    required: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DiskSpace {
+    min_free: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Bandwidth {
+    limit: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SizeLimits {
+    max_clone_size: Option<String>,
+    max_upload_size: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Database {
+    busy_timeout: String,
+    checkpoint_interval: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct AdminApi {
+    address: String,
+    token: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Debugging {
+    location: PathBuf,
+    retention: Retention,
+    redact_patterns: Vec<String>,
+    capture_api_calls: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Retention {
+    days: usize,
+    cli_bundles: CliBundleRetention,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CliBundleRetention {
+    days: usize,
+    max_size: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Notification {
+    Webhook {
+        url: &'static str,
+        events: Vec<notify::Kind>,
+        template: Option<String>,
+    },
+    Slack {
+        url: &'static str,
+        events: Vec<notify::Kind>,
+        template: Option<String>,
+    },
+    Smtp {
+        host: String,
+        port: u16,
+        tls: smtp::Tls,
+        username: Option<String>,
+        password: Option<&'static str>,
+        from: String,
+        to: Vec<String>,
+        interval: String,
+        events: Vec<notify::Kind>,
+        template: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Integration {
+    Git {
+        // Only one of these is configured, so the other is left out rather than rendered as null.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        remote: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        remotes_from: Option<PathBuf>,
+        auth: Auth,
+        import_branches: bool,
+        import_tags: bool,
+        watched_branches: Vec<String>,
+        excluded_branches: Vec<String>,
+        branch_mappings: Vec<remote::BranchMapping>,
+        mirror_cache: bool,
+        scan_mode: ScanMode,
+        #[serde(flatten)]
+        settings: Settings,
+    },
+    Local {
+        path: String,
+        project: Option<String>,
+        revision: RevisionScheme,
+        #[serde(flatten)]
+        settings: Settings,
+    },
+    Archive {
+        location: String,
+        project: Option<String>,
+        #[serde(flatten)]
+        settings: Settings,
+    },
+    Bucket {
+        location: String,
+        region: String,
+        service_url: Option<String>,
+        project: Option<String>,
+        auth: BucketAuth,
+        #[serde(flatten)]
+        settings: Settings,
+    },
+    Perforce {
+        port: String,
+        depot: String,
+        user: String,
+        auth: PerforceAuth,
+        charset: Option<String>,
+        project: Option<String>,
+        import_streams: bool,
+        import_labels: bool,
+        watched_streams: Vec<String>,
+        #[serde(flatten)]
+        settings: Settings,
+    },
+    Gerrit {
+        url: String,
+        repository: String,
+        auth: GerritAuth,
+        project: Option<String>,
+        import_branches: bool,
+        import_changes: bool,
+        watched_branches: Vec<String>,
+        #[serde(flatten)]
+        settings: Settings,
+    },
+}
+
+/// Settings shared by every type of integration.
+#[derive(Debug, Clone, Serialize)]
+struct Settings {
+    group: Option<String>,
+    poll_interval: String,
+    team: Option<String>,
+    title: Option<String>,
+    clone_timeout: String,
+    scan_timeout: String,
+    scan_weight: u32,
+    scan_on_startup: ScanOnStartup,
+    poll_window: Option<String>,
+    slow_scan_multiple: f64,
+    bandwidth_limit: Option<String>,
+    aliases: Vec<String>,
+    env: BTreeMap<String, String>,
+    cli_options: remote::CliOptions,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Auth {
+    SshKeyFile {
+        path: PathBuf,
+        passphrase: Option<&'static str>,
+    },
+    SshKey {
+        key: &'static str,
+        passphrase: Option<&'static str>,
+    },
+    HttpHeader {
+        header: &'static str,
+    },
+    HttpBasic {
+        username: String,
+        password: &'static str,
+    },
+    HttpCommand {
+        command: String,
+        args: Vec<String>,
+        username: Option<String>,
+    },
+    #[serde(rename = "aws_codecommit")]
+    AwsCodeCommit {
+        profile: Option<String>,
+    },
+    None {
+        transport: &'static str,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BucketAuth {
+    AwsAccessKey {
+        access_key_id: String,
+        secret_access_key: &'static str,
+        session_token: Option<&'static str>,
+    },
+    GcpHmacKey {
+        access_id: String,
+        secret: &'static str,
+    },
+    GcpTokenCommand {
+        command: String,
+        args: Vec<String>,
+    },
+    None,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PerforceAuth {
+    Ticket { ticket: &'static str },
+    None,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum GerritAuth {
+    HttpBasic {
+        username: String,
+        password: &'static str,
+    },
+    HttpDigest {
+        username: String,
+        password: &'static str,
+    },
+    None,
+}
+
+impl From<&Config> for Effective {
+    fn from(config: &Config) -> Self {
+        let retention = config.debug().retention();
+        let cli_bundles = retention.cli_bundles();
+        Self {
+            version: 1,
+            fossa_endpoint: config.fossa_api().endpoint().to_string(),
+            fossa_integration_key: REDACTION_LITERAL,
+            fossa_api: FossaApi {
+                upload_path: config.fossa_api().upload().path().clone(),
+                upload_query: config.fossa_api().upload().query().clone(),
+                match_existing_projects: config.fossa_api().match_existing_projects(),
+                upload_contributors: config.fossa_api().upload_contributors(),
+                max_uploads_per_minute: config
+                    .fossa_api()
+                    .max_uploads_per_minute()
+                    .map(NonZeroU32::get),
+                targets: config
+                    .fossa_api()
+                    .targets()
+                    .iter()
+                    .map(|target| FossaTarget {
+                        name: target.name().clone(),
+                        fossa_endpoint: target.endpoint().to_string(),
+                        fossa_integration_key: REDACTION_LITERAL,
+                        max_uploads_per_minute: target
+                            .max_uploads_per_minute()
+                            .map(NonZeroU32::get),
+                    })
+                    .collect(),
+                fan_out: config.fossa_api().fan_out(),
+                retries: config.fossa_api().retries(),
+            },
+            debugging: Debugging {
+                location: config.debug().location().as_path().to_path_buf(),
+                retention: Retention {
+                    days: retention.days().into(),
+                    cli_bundles: CliBundleRetention {
+                        days: cli_bundles.days().into(),
+                        max_size: cli_bundles.max_size().map(|size| size.to_string()),
+                    },
+                },
+                redact_patterns: config.debug().redact_patterns().clone(),
+                capture_api_calls: *config.debug().capture_api_calls(),
+            },
+            fossa_cli: FossaCli {
+                download_base_url: config
+                    .fossa_cli()
+                    .download_base_url()
+                    .map(ToString::to_string),
+            },
+            disk_space: DiskSpace {
+                min_free: config.disk_space().min_free().to_string(),
+            },
+            upload_retry: UploadRetry {
+                max_age: duration(config.upload_retry().max_age()),
+            },
+            policy_check: PolicyCheck {
+                enabled: config.policy_check().enabled(),
+                timeout: duration(config.policy_check().timeout()),
+            },
+            ci_metadata: CiMetadata {
+                location: config.ci_metadata().location().clone(),
+            },
+            hooks: Hooks::from(config.hooks()),
+            sbom_export: SbomExport {
+                formats: config.sbom_export().formats().clone(),
+                timeout: duration(config.sbom_export().timeout()),
+            },
+            http: Http {
+                max_idle_connections_per_host: config.http().max_idle_per_host(),
+                idle_timeout: duration(config.http().idle_timeout()),
+                tcp_keepalive: duration(config.http().tcp_keepalive()),
+                max_connections_per_host: config
+                    .http()
+                    .max_connections_per_host()
+                    .map(NonZeroUsize::get),
+            },
+            git_backend: *config.git_backend(),
+            portable_git: config.portable_git().as_ref().map(|portable| PortableGit {
+                url: portable.url().to_string(),
+                sha256: portable.sha256().to_string(),
+            }),
+            crypto_mode: *config.crypto_mode(),
+            encryption: config.encryption().as_ref().map(|_| Encryption {
+                key: REDACTION_LITERAL,
+            }),
+            locale: *config.locale(),
+            secrets_guard: *config.secrets_guard(),
+            bandwidth: Bandwidth {
+                limit: config.bandwidth().limit().map(|limit| limit.to_string()),
+            },
+            size_limits: SizeLimits {
+                max_clone_size: config
+                    .size_limits()
+                    .max_clone_size()
+                    .map(|limit| limit.to_string()),
+                max_upload_size: config
+                    .size_limits()
+                    .max_upload_size()
+                    .map(|limit| limit.to_string()),
+            },
+            database: Database {
+                busy_timeout: duration(config.database().busy_timeout()),
+                checkpoint_interval: duration(config.database().checkpoint_interval()),
+            },
+            admin_api: config.admin_api().as_ref().map(|admin_api| AdminApi {
+                address: admin_api.address().to_string(),
+                token: REDACTION_LITERAL,
+            }),
+            notifications: config
+                .notifications()
+                .sinks()
+                .iter()
+                .map(Notification::from)
+                .collect(),
+            integrations: config
+                .integrations()
+                .iter()
+                .map(Integration::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<&hooks::Config> for Hooks {
+    fn from(config: &hooks::Config) -> Self {
+        let list = |point| {
+            config
+                .hooks(point)
+                .iter()
+                .map(|hook| Hook {
+                    command: hook.command().clone(),
+                    args: hook.args().clone(),
+                    timeout: duration(hook.timeout()),
+                    required: hook.required(),
+                })
+                .collect()
+        };
+        Self {
+            post_clone: list(hooks::Point::PostClone),
+            post_scan: list(hooks::Point::PostScan),
+            post_upload: list(hooks::Point::PostUpload),
+        }
+    }
+}
+
+impl From<&notify::Sink> for Notification {
+    fn from(sink: &notify::Sink) -> Self {
+        match sink {
+            notify::Sink::Webhook(hook) => {
+                let events = hook.events().kinds();
+                let template = hook.template().as_ref().map(|t| t.as_str().to_string());
+                match hook.format() {
+                    webhook::Format::Json => Notification::Webhook {
+                        url: REDACTION_LITERAL,
+                        events,
+                        template,
+                    },
+                    webhook::Format::Slack => Notification::Slack {
+                        url: REDACTION_LITERAL,
+                        events,
+                        template,
+                    },
+                }
+            }
+            notify::Sink::Smtp(smtp) => Notification::Smtp {
+                host: smtp.host().clone(),
+                port: smtp.port(),
+                tls: smtp.tls(),
+                username: smtp.auth().as_ref().map(|auth| auth.username().clone()),
+                password: smtp.auth().as_ref().map(|_| REDACTION_LITERAL),
+                from: smtp.from().to_string(),
+                to: smtp.to().iter().map(ToString::to_string).collect(),
+                interval: duration(smtp.interval().as_duration()),
+                events: smtp.events().kinds(),
+                template: smtp.template().as_ref().map(|t| t.as_str().to_string()),
+            },
+        }
+    }
+}
+
+impl From<&remote::Integration> for Integration {
+    fn from(integration: &remote::Integration) -> Self {
+        let settings = Settings {
+            group: integration.group().clone(),
+            poll_interval: duration(integration.poll_interval().as_duration()),
+            team: integration.team().clone(),
+            title: integration.title().clone(),
+            clone_timeout: duration(integration.clone_timeout().as_duration()),
+            scan_timeout: duration(integration.scan_timeout().as_duration()),
+            scan_weight: integration.scan_weight().as_nonzero().get(),
+            scan_on_startup: integration.scan_on_startup(),
+            poll_window: integration.poll_window().map(|window| window.to_string()),
+            slow_scan_multiple: integration.slow_scan_multiple().as_f64(),
+            bandwidth_limit: integration.bandwidth_limit().map(|limit| limit.to_string()),
+            aliases: integration
+                .aliases()
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            env: integration
+                .cli_env()
+                .iter()
+                .map(|var| {
+                    let value = match var.value() {
+                        CliEnvValue::Plain(value) => value.clone(),
+                        CliEnvValue::Secret(_) => REDACTION_LITERAL.to_string(),
+                    };
+                    (var.name().clone(), value)
+                })
+                .collect(),
+            cli_options: integration.cli_options().clone(),
+        };
+        match integration.protocol() {
+            Protocol::Git(transport) => Integration::Git {
+                remote: match integration.remotes_from() {
+                    Some(_) => None,
+                    None => Some(integration.remote().to_string()),
+                },
+                remotes_from: integration.remotes_from().clone(),
+                auth: Auth::from(transport),
+                import_branches: matches!(
+                    integration.import_branches(),
+                    BranchImportStrategy::Enabled
+                ),
+                import_tags: matches!(integration.import_tags(), TagImportStrategy::Enabled),
+                watched_branches: integration
+                    .watched_branches()
+                    .iter()
+                    .map(|branch| branch.name().to_string())
+                    .collect(),
+                excluded_branches: integration
+                    .excluded_branches()
+                    .iter()
+                    .map(|branch| branch.name().to_string())
+                    .collect(),
+                branch_mappings: integration.branch_mappings().clone(),
+                mirror_cache: integration.clone_strategy() == CloneStrategy::Mirror,
+                scan_mode: integration.scan_mode(),
+                settings,
+            },
+            Protocol::Local(directory) => Integration::Local {
+                path: directory.endpoint().to_string(),
+                project: directory.project().clone(),
+                revision: directory.revision(),
+                settings,
+            },
+            Protocol::Archive(archives) => Integration::Archive {
+                location: archives.endpoint().to_string(),
+                project: archives.project().clone(),
+                settings,
+            },
+            Protocol::Bucket(bucket) => Integration::Bucket {
+                location: bucket.endpoint().to_string(),
+                region: bucket.region().clone(),
+                service_url: bucket.service_url().clone(),
+                project: bucket.project().clone(),
+                auth: BucketAuth::from(bucket),
+                settings,
+            },
+            Protocol::Perforce(depot) => Integration::Perforce {
+                port: depot.port().clone(),
+                depot: depot.depot().clone(),
+                user: depot.user().clone(),
+                auth: match depot.ticket() {
+                    Some(_) => PerforceAuth::Ticket {
+                        ticket: REDACTION_LITERAL,
+                    },
+                    None => PerforceAuth::None,
+                },
+                charset: depot.charset().clone(),
+                project: depot.project().clone(),
+                import_streams: matches!(
+                    integration.import_branches(),
+                    BranchImportStrategy::Enabled
+                ),
+                import_labels: matches!(integration.import_tags(), TagImportStrategy::Enabled),
+                watched_streams: integration
+                    .watched_branches()
+                    .iter()
+                    .map(|branch| branch.name().to_string())
+                    .collect(),
+                settings,
+            },
+            Protocol::Gerrit(project) => Integration::Gerrit {
+                url: project.url().clone(),
+                repository: project.repository().clone(),
+                auth: match project.auth() {
+                    gerrit::Auth::Basic { username, .. } => GerritAuth::HttpBasic {
+                        username: username.clone(),
+                        password: REDACTION_LITERAL,
+                    },
+                    gerrit::Auth::Digest { username, .. } => GerritAuth::HttpDigest {
+                        username: username.clone(),
+                        password: REDACTION_LITERAL,
+                    },
+                    gerrit::Auth::None => GerritAuth::None,
+                },
+                project: project.project().clone(),
+                import_branches: matches!(
+                    integration.import_branches(),
+                    BranchImportStrategy::Enabled
+                ),
+                import_changes: matches!(integration.import_tags(), TagImportStrategy::Enabled),
+                watched_branches: project.branches().clone(),
+                settings,
+            },
+        }
+    }
+}
+
+impl From<&Transport> for Auth {
+    fn from(transport: &Transport) -> Self {
+        let redact = |secret: &Option<_>| secret.as_ref().map(|_| REDACTION_LITERAL);
+        match transport {
+            Transport::Ssh {
+                auth, passphrase, ..
+            } => match auth {
+                ssh::Auth::KeyFile(path) => Auth::SshKeyFile {
+                    path: path.clone(),
+                    passphrase: redact(passphrase),
+                },
+                ssh::Auth::KeyValue(_) => Auth::SshKey {
+                    key: REDACTION_LITERAL,
+                    passphrase: redact(passphrase),
+                },
+            },
+            Transport::Http { auth, .. } => match auth {
+                None => Auth::None { transport: "http" },
+                Some(http::Auth::Header(_)) => Auth::HttpHeader {
+                    header: REDACTION_LITERAL,
+                },
+                Some(http::Auth::Basic { username, .. }) => Auth::HttpBasic {
+                    username: username.to_string(),
+                    password: REDACTION_LITERAL,
+                },
+                Some(http::Auth::Command(command)) => Auth::HttpCommand {
+                    command: command.command().clone(),
+                    args: command.args().clone(),
+                    username: command.username().clone(),
+                },
+                Some(http::Auth::AwsCodeCommit(repository)) => Auth::AwsCodeCommit {
+                    profile: repository.profile().clone(),
+                },
+            },
+        }
+    }
+}
+
+impl From<&Bucket> for BucketAuth {
+    fn from(bucket: &Bucket) -> Self {
+        match bucket.auth() {
+            bucket::Auth::AccessKey {
+                access_key_id,
+                session_token,
+                ..
+            } => match bucket.service() {
+                bucket::Service::S3 => BucketAuth::AwsAccessKey {
+                    access_key_id: access_key_id.clone(),
+                    secret_access_key: REDACTION_LITERAL,
+                    session_token: session_token.as_ref().map(|_| REDACTION_LITERAL),
+                },
+                bucket::Service::Gcs => BucketAuth::GcpHmacKey {
+                    access_id: access_key_id.clone(),
+                    secret: REDACTION_LITERAL,
+                },
+            },
+            bucket::Auth::Command(command) => BucketAuth::GcpTokenCommand {
+                command: command.command().clone(),
+                args: command.args().clone(),
+            },
+            bucket::Auth::None => BucketAuth::None,
+        }
+    }
+}
+
+/// Every secret in the config, which the effective config replaces with [`REDACTION_LITERAL`].
+pub(super) fn secrets(config: &Config) -> Vec<ComparableSecretString> {
+    let key = |key: &crate::api::fossa::Key| ComparableSecretString::from(key.expose_secret());
+    let mut secrets = vec![key(config.fossa_api().key())];
+    secrets.extend(
+        config
+            .fossa_api()
+            .targets()
+            .iter()
+            .map(|target| key(target.key())),
+    );
+    secrets.extend(
+        config
+            .admin_api()
+            .iter()
+            .map(|admin_api| admin_api.token().clone()),
+    );
+    secrets.extend(config.encryption().iter().map(|key| key.encoded().clone()));
+    for sink in config.notifications().sinks() {
+        match sink {
+            notify::Sink::Webhook(hook) => secrets.push(hook.url().clone()),
+            notify::Sink::Smtp(smtp) => {
+                secrets.extend(smtp.auth().iter().map(|auth| auth.password().clone()))
+            }
+        }
+    }
+    for integration in config.integrations().iter() {
+        secrets.extend(integration_secrets(integration));
+    }
+    secrets
+}
+
+/// The secrets used by the integration.
+fn integration_secrets(integration: &remote::Integration) -> Vec<ComparableSecretString> {
+    let mut secrets = integration
+        .cli_env()
+        .iter()
+        .filter_map(|var| match var.value() {
+            CliEnvValue::Secret(value) => Some(value.clone()),
+            CliEnvValue::Plain(_) => None,
+        })
+        .collect::<Vec<_>>();
+    match integration.protocol() {
+        Protocol::Git(Transport::Ssh {
+            auth, passphrase, ..
+        }) => {
+            if let ssh::Auth::KeyValue(key) = auth {
+                secrets.push(key.clone());
+            }
+            secrets.extend(passphrase.clone());
+        }
+        Protocol::Git(Transport::Http { auth, .. }) => match auth {
+            Some(http::Auth::Header(header)) => secrets.push(header.clone()),
+            Some(http::Auth::Basic { password, .. }) => secrets.push(password.clone()),
+            Some(http::Auth::Command(_) | http::Auth::AwsCodeCommit(_)) | None => {}
+        },
+        Protocol::Bucket(bucket) => {
+            if let bucket::Auth::AccessKey {
+                secret_access_key,
+                session_token,
+                ..
+            } = bucket.auth()
+            {
+                secrets.push(secret_access_key.clone());
+                secrets.extend(session_token.clone());
+            }
+        }
+        Protocol::Perforce(depot) => secrets.extend(depot.ticket().clone()),
+        Protocol::Gerrit(project) => match project.auth() {
+            gerrit::Auth::Basic { password, .. } | gerrit::Auth::Digest { password, .. } => {
+                secrets.push(password.clone())
+            }
+            gerrit::Auth::None => {}
+        },
+        Protocol::Local(_) | Protocol::Archive(_) => {}
+    }
+    secrets
+}
+
+/// Render a duration the same way durations are written in the config file.
+fn duration(duration: Duration) -> String {
+    humantime::format_duration(duration).to_string()
+}
@@ -2,27 +2,38 @@
 
 use error_stack::{report, Report, ResultExt};
 use futures::future::join_all;
+use itertools::Itertools;
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    num::{NonZeroU32, NonZeroUsize},
+    path::{Path, PathBuf},
+};
 use tap::Pipe;
 use tracing::warn;
 
 use crate::{
+    admin,
     api::{
         fossa, http,
         remote::{
-            self,
+            self, archive, gerrit,
             git::{self, MAIN_BRANCH, MASTER_BRANCH},
-            RemoteProvider,
+            local, perforce, RemoteProvider,
         },
         ssh,
     },
-    debug, doc,
+    bandwidth,
+    config::Lint,
+    crypto, db, debug, disk, doc, encryption,
     ext::{
         error_stack::{DescribeContext, ErrorDocReference, ErrorHelper, IntoContext},
         result::{WrapErr, WrapOk},
         secrecy::ComparableSecretString,
     },
+    fossa_cli, hooks, keyring,
+    locale::Locale,
+    notify, secrets, size_limits,
 };
 
 /// Errors surfaced parsing v1 config values.
@@ -53,13 +64,79 @@ struct RawConfigV1 {
     endpoint: String,
 
     #[serde(rename = "fossa_integration_key")]
-    integration_key: String,
+    integration_key: Secret,
+
+    #[serde(default)]
+    fossa_api: FossaApi,
 
     #[serde(default)]
     integrations: Vec<Integration>,
 
+    #[serde(default)]
+    groups: BTreeMap<String, Group>,
+
     debugging: Debugging,
 
+    #[serde(default)]
+    scan_on_startup: remote::ScanOnStartup,
+
+    #[serde(default)]
+    notifications: Vec<Notification>,
+
+    #[serde(default)]
+    fossa_cli: FossaCli,
+
+    #[serde(default)]
+    disk_space: DiskSpace,
+
+    #[serde(default)]
+    upload_retry: UploadRetry,
+
+    #[serde(default)]
+    policy_check: PolicyCheck,
+
+    #[serde(default)]
+    ci_metadata: CiMetadata,
+
+    #[serde(default)]
+    hooks: Hooks,
+
+    #[serde(default)]
+    sbom_export: SbomExport,
+
+    #[serde(default)]
+    http: Http,
+
+    #[serde(default)]
+    git_backend: remote::git::Backend,
+
+    #[serde(default)]
+    portable_git: Option<PortableGit>,
+
+    #[serde(default)]
+    crypto_mode: crypto::Mode,
+
+    #[serde(default)]
+    encryption: Option<Encryption>,
+
+    #[serde(default)]
+    locale: Option<Locale>,
+
+    #[serde(default)]
+    secrets_guard: secrets::Config,
+
+    #[serde(default)]
+    bandwidth: Bandwidth,
+
+    #[serde(default)]
+    size_limits: SizeLimits,
+
+    #[serde(default)]
+    database: Database,
+
+    #[serde(default)]
+    admin_api: Option<AdminApi>,
+
     #[serde(rename(deserialize = "version"))]
     _version: usize,
 }
@@ -73,21 +150,395 @@ impl RawConfigV1 {
 
 async fn validate(config: RawConfigV1) -> Result<super::Config, Report<Error>> {
     let endpoint = fossa::Endpoint::try_from(config.endpoint).change_context(Error::Validate)?;
-    let key = fossa::Key::try_from(config.integration_key).change_context(Error::Validate)?;
-    let api = fossa::Config::new(endpoint, key);
+    let key = config
+        .integration_key
+        .resolve()
+        .await
+        .change_context(Error::Validate)?;
+    let key = fossa::Key::try_from(key).change_context(Error::Validate)?;
+    let upload =
+        fossa::Upload::validate(config.fossa_api.upload_path, config.fossa_api.upload_query)
+            .change_context(Error::Validate)?;
+    let match_existing_projects = config.fossa_api.match_existing_projects.unwrap_or(true);
+    let upload_contributors = config.fossa_api.upload_contributors.unwrap_or(false);
+    let mut targets = Vec::new();
+    for target in config.fossa_api.targets {
+        targets.push(target.validate().await?);
+    }
+    fossa::Target::validate_names(&targets).change_context(Error::Validate)?;
+    let api = fossa::Config::new(
+        endpoint,
+        key,
+        upload,
+        match_existing_projects,
+        upload_contributors,
+        config.fossa_api.max_uploads_per_minute,
+        targets,
+        config.fossa_api.fan_out,
+        config.fossa_api.retries,
+    );
     let debugging = debug::Config::try_from(config.debugging).change_context(Error::Validate)?;
+    let scan_on_startup = config.scan_on_startup;
+    let lints = config
+        .integrations
+        .iter()
+        .filter_map(|integration| integration.ignored_group_branches(&config.groups))
+        .collect::<Vec<_>>();
+    let groups = &config.groups;
     let integrations = config
         .integrations
         .into_iter()
-        .map(|integration| async { remote::Integration::validate(integration).await })
+        .map(|integration| async move {
+            let integration = integration.with_group_defaults(groups)?;
+            remote::Integration::validate(integration, scan_on_startup).await
+        })
         .pipe(join_all)
         .await
         .into_iter()
         .collect::<Result<Vec<_>, Report<remote::ValidationError>>>()
         .change_context(Error::Validate)
         .map(remote::Integrations::new)?;
+    let notifications = config
+        .notifications
+        .into_iter()
+        .map(notify::Sink::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .change_context(Error::Validate)
+        .map(notify::Config::new)?;
+
+    let fossa_cli = fossa_cli::Config::new(config.fossa_cli.download_base_url)
+        .change_context(Error::Validate)?;
+
+    let disk_space = disk::Config::from(config.disk_space);
+    let upload_retry = match config.upload_retry.max_age {
+        Some(max_age) => fossa::UploadRetry::try_from(max_age).change_context(Error::Validate)?,
+        None => fossa::UploadRetry::default(),
+    };
+    let policy_check =
+        fossa::PolicyCheck::validate(config.policy_check.enabled, config.policy_check.timeout)
+            .change_context(Error::Validate)?;
+    let ci_metadata =
+        fossa::CiMetadata::validate(config.ci_metadata.location).change_context(Error::Validate)?;
+    let hooks = hooks::Config::try_from(config.hooks).change_context(Error::Validate)?;
+    let sbom_export =
+        fossa::SbomExport::validate(config.sbom_export.formats, config.sbom_export.timeout)
+            .change_context(Error::Validate)?;
+    let http = http::client::Config::validate(
+        config.http.max_idle_connections_per_host,
+        config.http.idle_timeout,
+        config.http.tcp_keepalive,
+        config.http.max_connections_per_host,
+    )
+    .change_context(Error::Validate)?;
+
+    let database = db::Config::validate(
+        config.database.busy_timeout,
+        config.database.checkpoint_interval,
+    )
+    .change_context(Error::Validate)?;
+
+    let admin_api = match config.admin_api {
+        Some(admin_api) => {
+            let token = admin_api
+                .token
+                .resolve()
+                .await
+                .change_context(Error::Validate)?;
+            admin::Config::validate(admin_api.address, token)
+                .map(Some)
+                .change_context(Error::Validate)?
+        }
+        None => None,
+    };
+
+    let encryption = match config.encryption.map(|encryption| encryption.key) {
+        Some(EncryptionKey::Env(EnvSecret { env })) => {
+            Some(encryption::Key::from_env(&env).change_context(Error::Validate)?)
+        }
+        Some(EncryptionKey::Keyring(KeyringSecret { keyring })) => {
+            let key = keyring::read(&keyring)
+                .await
+                .change_context(Error::Validate)?;
+            Some(encryption::Key::decode(key.expose_secret()).change_context(Error::Validate)?)
+        }
+        None => None,
+    };
+
+    let portable_git = config
+        .portable_git
+        .map(|portable| remote::git::executable::Portable::validate(portable.url, portable.sha256))
+        .transpose()
+        .change_context(Error::Validate)?;
+
+    super::Config::new(
+        api,
+        debugging,
+        integrations,
+        notifications,
+        fossa_cli,
+        disk_space,
+        upload_retry,
+        policy_check,
+        ci_metadata,
+        hooks,
+        sbom_export,
+        http,
+        config.git_backend,
+        portable_git,
+        config.crypto_mode,
+        encryption,
+        config.locale,
+        config.secrets_guard,
+        bandwidth::Config::from(config.bandwidth),
+        size_limits::Config::from(config.size_limits),
+        database,
+        admin_api,
+        lints,
+    )
+    .wrap_ok()
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct FossaApi {
+    upload_path: Option<String>,
+    upload_query: BTreeMap<String, String>,
+    match_existing_projects: Option<bool>,
+    upload_contributors: Option<bool>,
+    max_uploads_per_minute: Option<NonZeroU32>,
+    targets: Vec<FossaTarget>,
+    fan_out: fossa::FanOut,
+    retries: fossa::Retries,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct FossaTarget {
+    name: String,
+
+    fossa_endpoint: String,
+
+    fossa_integration_key: Secret,
+
+    #[serde(default)]
+    max_uploads_per_minute: Option<NonZeroU32>,
+}
+
+impl FossaTarget {
+    async fn validate(self) -> Result<fossa::Target, Report<Error>> {
+        let endpoint =
+            fossa::Endpoint::try_from(self.fossa_endpoint).change_context(Error::Validate)?;
+        let key = self
+            .fossa_integration_key
+            .resolve()
+            .await
+            .change_context(Error::Validate)?;
+        let key = fossa::Key::try_from(key).change_context(Error::Validate)?;
+        fossa::Target::new(self.name, endpoint, key, self.max_uploads_per_minute).wrap_ok()
+    }
+}
+
+/// A secret, either written in the config file or stored in the keyring of the operating system
+/// with `{ keyring: <service> }`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(super) enum Secret {
+    Literal(String),
+    Keyring(KeyringSecret),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct KeyringSecret {
+    keyring: String,
+}
+
+impl Secret {
+    /// The value of the secret, read from the keyring if it's stored there.
+    async fn resolve(self) -> Result<String, Report<keyring::Error>> {
+        match self {
+            Secret::Literal(value) => Ok(value),
+            Secret::Keyring(KeyringSecret { keyring }) => keyring::read(&keyring)
+                .await
+                .map(|secret| secret.expose_secret().to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Encryption {
+    key: EncryptionKey,
+}
+
+/// The key used to encrypt artifacts at rest, either read from an environment variable with `{ env: <name> }`
+/// or stored in the keyring of the operating system with `{ keyring: <service> }`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(super) enum EncryptionKey {
+    Env(EnvSecret),
+    Keyring(KeyringSecret),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct EnvSecret {
+    env: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct FossaCli {
+    download_base_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct Http {
+    max_idle_connections_per_host: Option<usize>,
+    idle_timeout: Option<String>,
+    tcp_keepalive: Option<String>,
+    max_connections_per_host: Option<NonZeroUsize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct PortableGit {
+    url: String,
+    sha256: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct UploadRetry {
+    max_age: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct PolicyCheck {
+    enabled: bool,
+    timeout: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct CiMetadata {
+    location: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct SbomExport {
+    formats: Vec<fossa::SbomFormat>,
+    timeout: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct Hooks {
+    post_clone: Vec<Hook>,
+    post_scan: Vec<Hook>,
+    post_upload: Vec<Hook>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Hook {
+    command: String,
 
-    super::Config::new(api, debugging, integrations).wrap_ok()
+    #[serde(default)]
+    args: Vec<String>,
+
+    timeout: Option<String>,
+
+    #[serde(default)]
+    required: bool,
+}
+
+impl TryFrom<Hooks> for hooks::Config {
+    type Error = Report<hooks::ValidationError>;
+
+    fn try_from(value: Hooks) -> Result<Self, Self::Error> {
+        let validate = |hooks: Vec<Hook>| {
+            hooks
+                .into_iter()
+                .map(|hook| {
+                    hooks::Hook::validate(hook.command, hook.args, hook.timeout, hook.required)
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Self::new(
+            validate(value.post_clone)?,
+            validate(value.post_scan)?,
+            validate(value.post_upload)?,
+        )
+        .wrap_ok()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct DiskSpace {
+    min_free: bytesize::ByteSize,
+}
+
+impl Default for DiskSpace {
+    fn default() -> Self {
+        Self {
+            min_free: disk::Config::default().min_free(),
+        }
+    }
+}
+
+impl From<DiskSpace> for disk::Config {
+    fn from(value: DiskSpace) -> Self {
+        Self::new(value.min_free)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct Bandwidth {
+    limit: Option<bytesize::ByteSize>,
+}
+
+impl From<Bandwidth> for bandwidth::Config {
+    fn from(value: Bandwidth) -> Self {
+        Self::new(value.limit)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct SizeLimits {
+    max_clone_size: Option<bytesize::ByteSize>,
+    max_upload_size: Option<bytesize::ByteSize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct Database {
+    busy_timeout: Option<String>,
+    checkpoint_interval: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct AdminApi {
+    address: String,
+    token: Secret,
+}
+
+impl From<SizeLimits> for size_limits::Config {
+    fn from(value: SizeLimits) -> Self {
+        // A limit of zero disables it, like the minimum free disk space.
+        let enabled = |limit: Option<bytesize::ByteSize>| limit.filter(|limit| limit.as_u64() > 0);
+        Self::new(
+            enabled(value.max_clone_size),
+            enabled(value.max_upload_size),
+        )
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -97,6 +548,12 @@ pub(super) struct Debugging {
 
     #[serde(default)]
     retention: DebuggingRetention,
+
+    #[serde(default)]
+    redact_patterns: Vec<String>,
+
+    #[serde(default)]
+    capture_api_calls: bool,
 }
 
 impl TryFrom<Debugging> for debug::Config {
@@ -105,7 +562,19 @@ impl TryFrom<Debugging> for debug::Config {
     fn try_from(value: Debugging) -> Result<Self, Self::Error> {
         let root = debug::Root::from(value.location);
         let retention = debug::Retention::try_from(value.retention)?;
-        Self::new(root, retention).wrap_ok()
+        for pattern in &value.redact_patterns {
+            regex::Regex::new(pattern)
+                .context(debug::ValidationError::RedactPattern)
+                .help("patterns are regular expressions; escape characters like '.' and '+' to match them literally")
+                .describe_lazy(|| format!("provided pattern: '{pattern}'"))?;
+        }
+        Self::new(
+            root,
+            retention,
+            value.redact_patterns,
+            value.capture_api_calls,
+        )
+        .wrap_ok()
     }
 }
 
@@ -113,12 +582,16 @@ impl TryFrom<Debugging> for debug::Config {
 #[serde(deny_unknown_fields)]
 pub(super) struct DebuggingRetention {
     days: usize,
+
+    #[serde(default)]
+    cli_bundles: DebuggingCliBundleRetention,
 }
 
 impl Default for DebuggingRetention {
     fn default() -> Self {
         Self {
             days: debug::ArtifactRetentionCount::default().into(),
+            cli_bundles: DebuggingCliBundleRetention::default(),
         }
     }
 }
@@ -127,23 +600,66 @@ impl TryFrom<DebuggingRetention> for debug::Retention {
     type Error = Report<debug::ValidationError>;
 
     fn try_from(value: DebuggingRetention) -> Result<Self, Self::Error> {
+        let days: debug::ArtifactRetentionCount = value
+            .days
+            .try_into()
+            .describe("validate 'retention.days'")?;
+        let cli_bundles = debug::retention::CliBundleRetention::try_from(value.cli_bundles)?;
+        Self::new(days, cli_bundles).wrap_ok()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct DebuggingCliBundleRetention {
+    days: usize,
+    max_size: Option<bytesize::ByteSize>,
+}
+
+impl Default for DebuggingCliBundleRetention {
+    fn default() -> Self {
+        let default = debug::retention::CliBundleRetention::default();
+        Self {
+            days: default.days().into(),
+            max_size: default.max_size(),
+        }
+    }
+}
+
+impl TryFrom<DebuggingCliBundleRetention> for debug::retention::CliBundleRetention {
+    type Error = Report<debug::ValidationError>;
+
+    fn try_from(value: DebuggingCliBundleRetention) -> Result<Self, Self::Error> {
         value
             .days
             .try_into()
-            .describe("validate 'retention.days'")
-            .map(Self::new)
+            .describe("validate 'retention.cli_bundles.days'")
+            .map(|days| Self::new(days, value.max_size))
     }
 }
 
+/// Settings shared by every integration in a group.
+/// Integrations may override these by setting the same value themselves.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Group {
+    poll_interval: Option<String>,
+    team: Option<String>,
+    watched_branches: Option<Vec<String>>,
+    excluded_branches: Option<Vec<String>>,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", deny_unknown_fields)]
 pub(super) enum Integration {
     #[serde(rename = "git")]
     Git {
-        poll_interval: String,
+        group: Option<String>,
+        poll_interval: Option<String>,
         team: Option<String>,
         title: Option<String>,
-        remote: String,
+        remote: Option<String>,
+        remotes_from: Option<PathBuf>,
         auth: Auth,
         import_branches: Option<bool>,
         import_tags: Option<bool>,
@@ -151,32 +667,559 @@ pub(super) enum Integration {
         // However, this needs to be an option due to serde deny_unknown_fields.
         // An empty vector will throw errors, which is not the intended action for users on these new changes
         watched_branches: Option<Vec<String>>,
+        excluded_branches: Option<Vec<String>>,
+        #[serde(default)]
+        branch_mappings: Vec<BranchMapping>,
+        mirror_cache: Option<bool>,
+        scan_mode: Option<remote::ScanMode>,
+        scan_weight: Option<NonZeroU32>,
+        clone_timeout: Option<String>,
+        scan_timeout: Option<String>,
+        poll_window: Option<String>,
+        scan_on_startup: Option<remote::ScanOnStartup>,
+        slow_scan_multiple: Option<f64>,
+        bandwidth_limit: Option<bytesize::ByteSize>,
+        #[serde(default)]
+        aliases: Vec<String>,
+        #[serde(default)]
+        env: BTreeMap<String, CliEnvValue>,
+        #[serde(default)]
+        cli_options: CliOptions,
+    },
+
+    #[serde(rename = "local")]
+    Local {
+        group: Option<String>,
+        poll_interval: Option<String>,
+        team: Option<String>,
+        title: Option<String>,
+        path: PathBuf,
+        project: Option<String>,
+        #[serde(default)]
+        revision: local::RevisionScheme,
+        scan_weight: Option<NonZeroU32>,
+        clone_timeout: Option<String>,
+        scan_timeout: Option<String>,
+        poll_window: Option<String>,
+        scan_on_startup: Option<remote::ScanOnStartup>,
+        slow_scan_multiple: Option<f64>,
+        #[serde(default)]
+        aliases: Vec<String>,
+        #[serde(default)]
+        env: BTreeMap<String, CliEnvValue>,
+        #[serde(default)]
+        cli_options: CliOptions,
+    },
+
+    #[serde(rename = "archive")]
+    Archive {
+        group: Option<String>,
+        poll_interval: Option<String>,
+        team: Option<String>,
+        title: Option<String>,
+        location: String,
+        project: Option<String>,
+        scan_weight: Option<NonZeroU32>,
+        clone_timeout: Option<String>,
+        scan_timeout: Option<String>,
+        poll_window: Option<String>,
+        scan_on_startup: Option<remote::ScanOnStartup>,
+        slow_scan_multiple: Option<f64>,
+        bandwidth_limit: Option<bytesize::ByteSize>,
+        #[serde(default)]
+        aliases: Vec<String>,
+        #[serde(default)]
+        env: BTreeMap<String, CliEnvValue>,
+        #[serde(default)]
+        cli_options: CliOptions,
+    },
+
+    #[serde(rename = "bucket")]
+    Bucket {
+        group: Option<String>,
+        poll_interval: Option<String>,
+        team: Option<String>,
+        title: Option<String>,
+        location: String,
+        region: Option<String>,
+        service_url: Option<String>,
+        project: Option<String>,
+        auth: BucketAuth,
+        scan_weight: Option<NonZeroU32>,
+        clone_timeout: Option<String>,
+        scan_timeout: Option<String>,
+        poll_window: Option<String>,
+        scan_on_startup: Option<remote::ScanOnStartup>,
+        slow_scan_multiple: Option<f64>,
+        bandwidth_limit: Option<bytesize::ByteSize>,
+        #[serde(default)]
+        aliases: Vec<String>,
+        #[serde(default)]
+        env: BTreeMap<String, CliEnvValue>,
+        #[serde(default)]
+        cli_options: CliOptions,
+    },
+
+    #[serde(rename = "perforce")]
+    Perforce {
+        group: Option<String>,
+        poll_interval: Option<String>,
+        team: Option<String>,
+        title: Option<String>,
+        port: String,
+        depot: String,
+        user: String,
+        auth: PerforceAuth,
+        charset: Option<String>,
+        project: Option<String>,
+        import_streams: Option<bool>,
+        import_labels: Option<bool>,
+        watched_streams: Option<Vec<String>>,
+        scan_weight: Option<NonZeroU32>,
+        clone_timeout: Option<String>,
+        scan_timeout: Option<String>,
+        poll_window: Option<String>,
+        scan_on_startup: Option<remote::ScanOnStartup>,
+        slow_scan_multiple: Option<f64>,
+        bandwidth_limit: Option<bytesize::ByteSize>,
+        #[serde(default)]
+        aliases: Vec<String>,
+        #[serde(default)]
+        env: BTreeMap<String, CliEnvValue>,
+        #[serde(default)]
+        cli_options: CliOptions,
+    },
+
+    #[serde(rename = "gerrit")]
+    Gerrit {
+        group: Option<String>,
+        poll_interval: Option<String>,
+        team: Option<String>,
+        title: Option<String>,
+        url: String,
+        repository: String,
+        auth: GerritAuth,
+        project: Option<String>,
+        import_branches: Option<bool>,
+        import_changes: Option<bool>,
+        watched_branches: Option<Vec<String>>,
+        scan_weight: Option<NonZeroU32>,
+        clone_timeout: Option<String>,
+        scan_timeout: Option<String>,
+        poll_window: Option<String>,
+        scan_on_startup: Option<remote::ScanOnStartup>,
+        slow_scan_multiple: Option<f64>,
+        bandwidth_limit: Option<bytesize::ByteSize>,
+        #[serde(default)]
+        aliases: Vec<String>,
+        #[serde(default)]
+        env: BTreeMap<String, CliEnvValue>,
+        #[serde(default)]
+        cli_options: CliOptions,
     },
 }
 
+impl Integration {
+    /// Fill in any settings the integration doesn't set itself from its group, if it has one.
+    fn with_group_defaults(
+        self,
+        groups: &BTreeMap<String, Group>,
+    ) -> Result<Self, Report<remote::ValidationError>> {
+        let Some(name) = self.group() else {
+            return self.wrap_ok();
+        };
+        let Some(group) = groups.get(name) else {
+            let defined = groups.keys().map(|name| format!("'{name}'")).join(", ");
+            return report!(remote::ValidationError::UnknownGroup(name.to_string()))
+                .wrap_err()
+                .help("define the group in the top level 'groups' section of the config file")
+                .describe_lazy(|| format!("defined groups: {defined}"));
+        };
+
+        match self {
+            Integration::Git {
+                group,
+                poll_interval,
+                team,
+                watched_branches,
+                title,
+                remote,
+                remotes_from,
+                auth,
+                import_branches,
+                import_tags,
+                excluded_branches,
+                branch_mappings,
+                mirror_cache,
+                scan_mode,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            } => Integration::Git {
+                poll_interval: poll_interval.or_else(|| group.poll_interval.clone()),
+                team: team.or_else(|| group.team.clone()),
+                // Branches aren't scanned if branch imports are disabled, so the group's branches don't apply;
+                // this is reported by `ignored_group_branches`.
+                watched_branches: match import_branches {
+                    Some(false) => watched_branches,
+                    _ => watched_branches.or_else(|| group.watched_branches.clone()),
+                },
+                excluded_branches: excluded_branches.or_else(|| group.excluded_branches.clone()),
+                group,
+                title,
+                remote,
+                remotes_from,
+                auth,
+                import_branches,
+                import_tags,
+                branch_mappings,
+                mirror_cache,
+                scan_mode,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            }
+            .wrap_ok(),
+            // Watched branches only apply to git integrations.
+            Integration::Local {
+                group,
+                poll_interval,
+                team,
+                title,
+                path,
+                project,
+                revision,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup,
+                slow_scan_multiple,
+                aliases,
+                env,
+                cli_options,
+            } => Integration::Local {
+                poll_interval: poll_interval.or_else(|| group.poll_interval.clone()),
+                team: team.or_else(|| group.team.clone()),
+                group,
+                title,
+                path,
+                project,
+                revision,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup,
+                slow_scan_multiple,
+                aliases,
+                env,
+                cli_options,
+            }
+            .wrap_ok(),
+            Integration::Archive {
+                group,
+                poll_interval,
+                team,
+                title,
+                location,
+                project,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            } => Integration::Archive {
+                poll_interval: poll_interval.or_else(|| group.poll_interval.clone()),
+                team: team.or_else(|| group.team.clone()),
+                group,
+                title,
+                location,
+                project,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            }
+            .wrap_ok(),
+            Integration::Bucket {
+                group,
+                poll_interval,
+                team,
+                title,
+                location,
+                region,
+                service_url,
+                project,
+                auth,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            } => Integration::Bucket {
+                poll_interval: poll_interval.or_else(|| group.poll_interval.clone()),
+                team: team.or_else(|| group.team.clone()),
+                group,
+                title,
+                location,
+                region,
+                service_url,
+                project,
+                auth,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            }
+            .wrap_ok(),
+            Integration::Perforce {
+                group,
+                poll_interval,
+                team,
+                title,
+                port,
+                depot,
+                user,
+                auth,
+                charset,
+                project,
+                import_streams,
+                import_labels,
+                watched_streams,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            } => Integration::Perforce {
+                poll_interval: poll_interval.or_else(|| group.poll_interval.clone()),
+                team: team.or_else(|| group.team.clone()),
+                group,
+                title,
+                port,
+                depot,
+                user,
+                auth,
+                charset,
+                project,
+                import_streams,
+                import_labels,
+                watched_streams,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            }
+            .wrap_ok(),
+            Integration::Gerrit {
+                group,
+                poll_interval,
+                team,
+                title,
+                url,
+                repository,
+                auth,
+                project,
+                import_branches,
+                import_changes,
+                watched_branches,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            } => Integration::Gerrit {
+                poll_interval: poll_interval.or_else(|| group.poll_interval.clone()),
+                team: team.or_else(|| group.team.clone()),
+                watched_branches: watched_branches.or_else(|| group.watched_branches.clone()),
+                group,
+                title,
+                url,
+                repository,
+                auth,
+                project,
+                import_branches,
+                import_changes,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            }
+            .wrap_ok(),
+        }
+    }
+
+    /// Report the watched branches of the integration's group
+    /// if the integration disables branch imports, since they're ignored.
+    fn ignored_group_branches(&self, groups: &BTreeMap<String, Group>) -> Option<Lint> {
+        let Integration::Git {
+            group: Some(name),
+            remote,
+            import_branches: Some(false),
+            watched_branches: None,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        let group = groups.get(name)?;
+        group.watched_branches.as_ref().map(|_| {
+            Lint::new(
+                Some(remote.clone()),
+                format!("the watched branches of group '{name}' are ignored because 'import_branches' is false"),
+                String::from("set 'import_branches' to true to scan the group's watched branches, or move the integration out of the group"),
+            )
+        })
+    }
+
+    /// The group to which the integration belongs, if any.
+    fn group(&self) -> Option<&str> {
+        match self {
+            Integration::Git { group, .. }
+            | Integration::Local { group, .. }
+            | Integration::Archive { group, .. }
+            | Integration::Bucket { group, .. }
+            | Integration::Perforce { group, .. }
+            | Integration::Gerrit { group, .. } => group.as_deref(),
+        }
+    }
+}
+
 impl remote::Integration {
-    async fn validate(value: Integration) -> Result<Self, Report<remote::ValidationError>> {
+    /// Validate the integration. `scan_on_startup` is the global default,
+    /// used unless the integration overrides it.
+    async fn validate(
+        value: Integration,
+        scan_on_startup: remote::ScanOnStartup,
+    ) -> Result<Self, Report<remote::ValidationError>> {
         let mut integration = match value {
             Integration::Git {
+                group,
                 poll_interval,
                 remote,
+                remotes_from,
                 team,
                 title,
                 auth,
                 import_branches,
                 import_tags,
                 watched_branches,
+                excluded_branches,
+                branch_mappings,
+                mirror_cache,
+                scan_mode,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup: integration_scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
             } => {
-                let poll_interval = remote::PollInterval::try_from(poll_interval)?;
-                let endpoint = remote::Remote::try_from(remote)?;
+                let poll_interval = validate_poll_interval(poll_interval)?;
+                let endpoint = validate_git_remote(remote, remotes_from.as_deref())?;
+                if remotes_from.is_some() {
+                    validate_remotes_from(&auth, &aliases)?;
+                }
                 let import_branches = remote::BranchImportStrategy::from(import_branches);
                 let import_tags = remote::TagImportStrategy::from(import_tags);
+                let clone_strategy = remote::CloneStrategy::from(mirror_cache);
+                let scan_mode = scan_mode.unwrap_or_default();
+                if clone_strategy == remote::CloneStrategy::Mirror
+                    && scan_mode == remote::ScanMode::ManifestsOnly
+                {
+                    return report!(remote::ValidationError::ManifestsOnlyMirror)
+                        .wrap_err()
+                        .help("remove 'mirror_cache' or 'scan_mode' from the integration");
+                }
+                let scan_weight = scan_weight.map(remote::ScanWeight::new).unwrap_or_default();
+                let clone_timeout = validate_timeout(
+                    clone_timeout,
+                    "clone_timeout",
+                    remote::JobTimeout::DEFAULT_CLONE,
+                )?;
+                let scan_timeout = validate_timeout(
+                    scan_timeout,
+                    "scan_timeout",
+                    remote::JobTimeout::DEFAULT_SCAN,
+                )?;
+                let poll_window = validate_poll_window(poll_window)?;
+                let scan_on_startup = integration_scan_on_startup.unwrap_or(scan_on_startup);
+                let slow_scan_multiple = validate_slow_scan_multiple(slow_scan_multiple)?;
                 let watched_branches = watched_branches
                     .unwrap_or_default()
                     .into_iter()
                     .map(remote::WatchedBranch::new)
                     .collect::<Vec<_>>();
 
+                let excluded_branches = excluded_branches
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(remote::ExcludedBranch::try_from)
+                    .collect::<Result<Vec<_>, _>>()?;
+
                 if !import_branches.is_valid(&watched_branches) {
                     return report!(remote::ValidationError::ImportBranches)
                         .wrap_err()
@@ -185,14 +1228,16 @@ impl remote::Integration {
                 }
 
                 let protocol = match auth {
-                    Auth::SshKeyFile { path } => {
+                    Auth::SshKeyFile { path, passphrase } => {
                         let auth = ssh::Auth::KeyFile(path);
-                        git::transport::Transport::new_ssh(endpoint, auth)
+                        let passphrase = passphrase.map(ComparableSecretString::from);
+                        git::transport::Transport::new_ssh(endpoint, auth, passphrase)
                     }
-                    Auth::SshKey { key } => {
+                    Auth::SshKey { key, passphrase } => {
                         let secret = ComparableSecretString::from(key);
                         let auth = ssh::Auth::KeyValue(secret);
-                        git::transport::Transport::new_ssh(endpoint, auth)
+                        let passphrase = passphrase.map(ComparableSecretString::from);
+                        git::transport::Transport::new_ssh(endpoint, auth, passphrase)
                     }
                     Auth::HttpHeader { header } => {
                         let secret = ComparableSecretString::from(header);
@@ -201,7 +1246,21 @@ impl remote::Integration {
                     }
                     Auth::HttpBasic { username, password } => {
                         let password = ComparableSecretString::from(password);
-                        let auth = http::Auth::new_basic(username, password);
+                        let auth = http::Auth::new_basic(username.into(), password);
+                        git::transport::Transport::new_http(endpoint, Some(auth))
+                    }
+                    Auth::HttpCommand {
+                        command,
+                        args,
+                        username,
+                    } => {
+                        let command = http::CredentialCommand::new(command, args, username);
+                        let auth = http::Auth::new_command(command);
+                        git::transport::Transport::new_http(endpoint, Some(auth))
+                    }
+                    Auth::AwsCodeCommit { profile } => {
+                        let repository = git::codecommit::CodeCommit::validate(&endpoint, profile)?;
+                        let auth = http::Auth::new_aws_code_commit(repository);
                         git::transport::Transport::new_http(endpoint, Some(auth))
                     }
                     Auth::None { transport } => match transport.as_str() {
@@ -225,6 +1284,407 @@ impl remote::Integration {
                     .import_branches(import_branches)
                     .import_tags(import_tags)
                     .watched_branches(watched_branches)
+                    .excluded_branches(excluded_branches)
+                    .branch_mappings(validate_branch_mappings(branch_mappings)?)
+                    .clone_strategy(clone_strategy)
+                    .scan_mode(scan_mode)
+                    .scan_weight(scan_weight)
+                    .clone_timeout(clone_timeout)
+                    .scan_timeout(scan_timeout)
+                    .poll_window(poll_window)
+                    .scan_on_startup(scan_on_startup)
+                    .slow_scan_multiple(slow_scan_multiple)
+                    .bandwidth_limit(bandwidth_limit)
+                    .aliases(validate_aliases(aliases)?)
+                    .cli_env(validate_cli_env(env)?)
+                    .cli_options(remote::CliOptions::try_from(cli_options)?)
+                    .group(group)
+                    .remotes_from(remotes_from)
+                    .build()
+            }
+            Integration::Local {
+                group,
+                poll_interval,
+                team,
+                title,
+                path,
+                project,
+                revision,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup: integration_scan_on_startup,
+                slow_scan_multiple,
+                aliases,
+                env,
+                cli_options,
+            } => {
+                if !path.is_absolute() {
+                    return report!(remote::ValidationError::LocalPath)
+                        .wrap_err()
+                        .help("provide the absolute path to the directory")
+                        .describe_lazy(|| format!("provided path: '{}'", path.display()));
+                }
+
+                let endpoint = remote::Remote::try_from(path.display().to_string())?;
+                let directory = local::Directory::new(endpoint, project, revision);
+
+                // Directories have a single reference, their current snapshot, which is imported like a tag.
+                remote::Integration::builder()
+                    .poll_interval(validate_poll_interval(poll_interval)?)
+                    .team(team)
+                    .title(title)
+                    .protocol(directory)
+                    .import_branches(remote::BranchImportStrategy::Disabled)
+                    .import_tags(remote::TagImportStrategy::Enabled)
+                    .watched_branches(Vec::new())
+                    .scan_weight(scan_weight.map(remote::ScanWeight::new).unwrap_or_default())
+                    .clone_timeout(validate_timeout(
+                        clone_timeout,
+                        "clone_timeout",
+                        remote::JobTimeout::DEFAULT_CLONE,
+                    )?)
+                    .scan_timeout(validate_timeout(
+                        scan_timeout,
+                        "scan_timeout",
+                        remote::JobTimeout::DEFAULT_SCAN,
+                    )?)
+                    .poll_window(validate_poll_window(poll_window)?)
+                    .scan_on_startup(integration_scan_on_startup.unwrap_or(scan_on_startup))
+                    .slow_scan_multiple(validate_slow_scan_multiple(slow_scan_multiple)?)
+                    .aliases(validate_aliases(aliases)?)
+                    .cli_env(validate_cli_env(env)?)
+                    .cli_options(remote::CliOptions::try_from(cli_options)?)
+                    .group(group)
+                    .build()
+            }
+            Integration::Archive {
+                group,
+                poll_interval,
+                team,
+                title,
+                location,
+                project,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup: integration_scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            } => {
+                let endpoint = remote::Remote::try_from(location)?;
+                let archives = archive::Archives::new(endpoint, project);
+                if let archive::Location::Directory(path) = archives.location() {
+                    if !path.is_absolute() {
+                        return report!(remote::ValidationError::ArchiveLocation)
+                            .wrap_err()
+                            .help("provide the absolute path to the directory, or the URL of the index page")
+                            .describe_lazy(|| format!("provided location: '{}'", path.display()));
+                    }
+                }
+
+                // Each archive is a release, which is imported like a tag.
+                remote::Integration::builder()
+                    .poll_interval(validate_poll_interval(poll_interval)?)
+                    .team(team)
+                    .title(title)
+                    .protocol(archives)
+                    .import_branches(remote::BranchImportStrategy::Disabled)
+                    .import_tags(remote::TagImportStrategy::Enabled)
+                    .watched_branches(Vec::new())
+                    .scan_weight(scan_weight.map(remote::ScanWeight::new).unwrap_or_default())
+                    .clone_timeout(validate_timeout(
+                        clone_timeout,
+                        "clone_timeout",
+                        remote::JobTimeout::DEFAULT_CLONE,
+                    )?)
+                    .scan_timeout(validate_timeout(
+                        scan_timeout,
+                        "scan_timeout",
+                        remote::JobTimeout::DEFAULT_SCAN,
+                    )?)
+                    .poll_window(validate_poll_window(poll_window)?)
+                    .scan_on_startup(integration_scan_on_startup.unwrap_or(scan_on_startup))
+                    .slow_scan_multiple(validate_slow_scan_multiple(slow_scan_multiple)?)
+                    .bandwidth_limit(bandwidth_limit)
+                    .aliases(validate_aliases(aliases)?)
+                    .cli_env(validate_cli_env(env)?)
+                    .cli_options(remote::CliOptions::try_from(cli_options)?)
+                    .group(group)
+                    .build()
+            }
+            Integration::Bucket {
+                group,
+                poll_interval,
+                team,
+                title,
+                location,
+                region,
+                service_url,
+                project,
+                auth,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup: integration_scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            } => {
+                let (service, name, prefix) = validate_bucket_location(&location)?;
+                let endpoint = remote::Remote::try_from(location)?;
+                let region = region.unwrap_or_else(|| match service {
+                    bucket::Service::S3 => bucket::DEFAULT_S3_REGION.to_string(),
+                    // Google Cloud Storage accepts any region when signing with HMAC keys.
+                    bucket::Service::Gcs => String::from("auto"),
+                });
+                if let Some(service_url) = &service_url {
+                    if !(service_url.starts_with("http://") || service_url.starts_with("https://"))
+                    {
+                        return report!(remote::ValidationError::BucketLocation)
+                            .wrap_err()
+                            .help("'service_url' must be an http or https URL")
+                            .describe_lazy(|| format!("provided service URL: '{service_url}'"));
+                    }
+                }
+                let auth = match auth {
+                    BucketAuth::AwsAccessKey {
+                        access_key_id,
+                        secret_access_key,
+                        session_token,
+                    } => bucket::Auth::new_access_key(
+                        access_key_id,
+                        ComparableSecretString::from(secret_access_key),
+                        session_token.map(ComparableSecretString::from),
+                    ),
+                    BucketAuth::GcpHmacKey { access_id, secret } => bucket::Auth::new_access_key(
+                        access_id,
+                        ComparableSecretString::from(secret),
+                        None,
+                    ),
+                    BucketAuth::GcpTokenCommand { command, args } => {
+                        bucket::Auth::new_command(http::CredentialCommand::new(command, args, None))
+                    }
+                    BucketAuth::None => bucket::Auth::None,
+                };
+                let bucket = bucket::Bucket::new(
+                    endpoint,
+                    service,
+                    name,
+                    prefix,
+                    region,
+                    service_url,
+                    project,
+                    auth,
+                );
+
+                // Each object is imported like a tag.
+                remote::Integration::builder()
+                    .poll_interval(validate_poll_interval(poll_interval)?)
+                    .team(team)
+                    .title(title)
+                    .protocol(bucket)
+                    .import_branches(remote::BranchImportStrategy::Disabled)
+                    .import_tags(remote::TagImportStrategy::Enabled)
+                    .watched_branches(Vec::new())
+                    .scan_weight(scan_weight.map(remote::ScanWeight::new).unwrap_or_default())
+                    .clone_timeout(validate_timeout(
+                        clone_timeout,
+                        "clone_timeout",
+                        remote::JobTimeout::DEFAULT_CLONE,
+                    )?)
+                    .scan_timeout(validate_timeout(
+                        scan_timeout,
+                        "scan_timeout",
+                        remote::JobTimeout::DEFAULT_SCAN,
+                    )?)
+                    .poll_window(validate_poll_window(poll_window)?)
+                    .scan_on_startup(integration_scan_on_startup.unwrap_or(scan_on_startup))
+                    .slow_scan_multiple(validate_slow_scan_multiple(slow_scan_multiple)?)
+                    .bandwidth_limit(bandwidth_limit)
+                    .aliases(validate_aliases(aliases)?)
+                    .cli_env(validate_cli_env(env)?)
+                    .cli_options(remote::CliOptions::try_from(cli_options)?)
+                    .group(group)
+                    .build()
+            }
+            Integration::Perforce {
+                group,
+                poll_interval,
+                team,
+                title,
+                port,
+                depot,
+                user,
+                auth,
+                charset,
+                project,
+                import_streams,
+                import_labels,
+                watched_streams,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup: integration_scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            } => {
+                let depot = validate_perforce_depot(depot)?;
+                let endpoint = remote::Remote::try_from(format!("{port}{depot}"))?;
+
+                // Streams are imported like branches and labels like tags.
+                let import_branches = remote::BranchImportStrategy::from(import_streams);
+                let import_tags = remote::TagImportStrategy::from(import_labels);
+
+                // Stream names are full depot paths, so the primary branch can't be inferred
+                // the way it is for git; default to the conventional mainline stream instead.
+                let watched_branches = match watched_streams {
+                    Some(streams) => streams,
+                    None if import_branches == remote::BranchImportStrategy::Enabled => {
+                        vec![format!("{depot}/main")]
+                    }
+                    None => Vec::new(),
+                }
+                .into_iter()
+                .map(remote::WatchedBranch::new)
+                .collect::<Vec<_>>();
+                if !import_branches.is_valid(&watched_branches) {
+                    return report!(remote::ValidationError::ImportBranches)
+                        .wrap_err()
+                        .help("import streams must be 'true' if watched streams are provided")
+                        .describe_lazy(|| "import streams: 'false'".to_string());
+                }
+
+                let ticket = match auth {
+                    PerforceAuth::Ticket { ticket } => Some(ComparableSecretString::from(ticket)),
+                    PerforceAuth::None => None,
+                };
+                let depot =
+                    perforce::Depot::new(endpoint, port, depot, user, ticket, charset, project);
+
+                remote::Integration::builder()
+                    .poll_interval(validate_poll_interval(poll_interval)?)
+                    .team(team)
+                    .title(title)
+                    .protocol(depot)
+                    .import_branches(import_branches)
+                    .import_tags(import_tags)
+                    .watched_branches(watched_branches)
+                    .scan_weight(scan_weight.map(remote::ScanWeight::new).unwrap_or_default())
+                    .clone_timeout(validate_timeout(
+                        clone_timeout,
+                        "clone_timeout",
+                        remote::JobTimeout::DEFAULT_CLONE,
+                    )?)
+                    .scan_timeout(validate_timeout(
+                        scan_timeout,
+                        "scan_timeout",
+                        remote::JobTimeout::DEFAULT_SCAN,
+                    )?)
+                    .poll_window(validate_poll_window(poll_window)?)
+                    .scan_on_startup(integration_scan_on_startup.unwrap_or(scan_on_startup))
+                    .slow_scan_multiple(validate_slow_scan_multiple(slow_scan_multiple)?)
+                    .bandwidth_limit(bandwidth_limit)
+                    .aliases(validate_aliases(aliases)?)
+                    .cli_env(validate_cli_env(env)?)
+                    .cli_options(remote::CliOptions::try_from(cli_options)?)
+                    .group(group)
+                    .build()
+            }
+            Integration::Gerrit {
+                group,
+                poll_interval,
+                team,
+                title,
+                url,
+                repository,
+                auth,
+                project,
+                import_branches,
+                import_changes,
+                watched_branches,
+                scan_weight,
+                clone_timeout,
+                scan_timeout,
+                poll_window,
+                scan_on_startup: integration_scan_on_startup,
+                slow_scan_multiple,
+                bandwidth_limit,
+                aliases,
+                env,
+                cli_options,
+            } => {
+                let url = validate_gerrit_url(url)?;
+                let repository = repository.trim_matches('/').to_string();
+                let endpoint = remote::Remote::try_from(format!("{url}/{repository}"))?;
+
+                // Merged changes are imported like branches and open changes like tags.
+                let import_branches = remote::BranchImportStrategy::from(import_branches);
+                let import_tags = remote::TagImportStrategy::from(import_changes);
+
+                // Changes are queried by the exact name of the branch they target, so branches can't be patterns;
+                // default to the branch Gerrit creates for new projects.
+                let branches = watched_branches.unwrap_or_else(|| vec![MASTER_BRANCH.to_string()]);
+                let watched_branches = match import_branches {
+                    remote::BranchImportStrategy::Enabled => branches
+                        .iter()
+                        .cloned()
+                        .map(remote::WatchedBranch::new)
+                        .collect(),
+                    remote::BranchImportStrategy::Disabled => Vec::new(),
+                };
+
+                let auth = match auth {
+                    GerritAuth::HttpBasic { username, password } => {
+                        gerrit::Auth::new_basic(username, ComparableSecretString::from(password))
+                    }
+                    GerritAuth::HttpDigest { username, password } => {
+                        gerrit::Auth::new_digest(username, ComparableSecretString::from(password))
+                    }
+                    GerritAuth::None => gerrit::Auth::None,
+                };
+                let project =
+                    gerrit::Project::new(endpoint, url, repository, branches, project, auth);
+
+                remote::Integration::builder()
+                    .poll_interval(validate_poll_interval(poll_interval)?)
+                    .team(team)
+                    .title(title)
+                    .protocol(project)
+                    .import_branches(import_branches)
+                    .import_tags(import_tags)
+                    .watched_branches(watched_branches)
+                    .scan_weight(scan_weight.map(remote::ScanWeight::new).unwrap_or_default())
+                    .clone_timeout(validate_timeout(
+                        clone_timeout,
+                        "clone_timeout",
+                        remote::JobTimeout::DEFAULT_CLONE,
+                    )?)
+                    .scan_timeout(validate_timeout(
+                        scan_timeout,
+                        "scan_timeout",
+                        remote::JobTimeout::DEFAULT_SCAN,
+                    )?)
+                    .poll_window(validate_poll_window(poll_window)?)
+                    .scan_on_startup(integration_scan_on_startup.unwrap_or(scan_on_startup))
+                    .slow_scan_multiple(validate_slow_scan_multiple(slow_scan_multiple)?)
+                    .bandwidth_limit(bandwidth_limit)
+                    .aliases(validate_aliases(aliases)?)
+                    .cli_env(validate_cli_env(env)?)
+                    .cli_options(remote::CliOptions::try_from(cli_options)?)
+                    .group(group)
                     .build()
             }
         };
@@ -260,14 +1720,215 @@ impl remote::Integration {
     }
 }
 
+/// Split a bucket location like `s3://bucket/prefix/` into its service, bucket name, and prefix.
+fn validate_bucket_location(
+    location: &str,
+) -> Result<(bucket::Service, String, String), Report<remote::ValidationError>> {
+    let parsed = url::Url::parse(location).ok().and_then(|url| {
+        let service = match url.scheme() {
+            "s3" => bucket::Service::S3,
+            "gs" => bucket::Service::Gcs,
+            _ => return None,
+        };
+        let name = url.host_str().filter(|name| !name.is_empty())?.to_string();
+        let prefix = url.path().trim_start_matches('/').to_string();
+        Some((service, name, prefix))
+    });
+
+    match parsed {
+        Some(parsed) => parsed.wrap_ok(),
+        None => report!(remote::ValidationError::BucketLocation)
+            .wrap_err()
+            .help("write the location like 's3://bucket/prefix/' or 'gs://bucket/prefix/'")
+            .describe_lazy(|| format!("provided location: '{location}'")),
+    }
+}
+
+/// Normalize a depot like `//game/` to `//game`, rejecting anything that isn't a depot path.
+/// Validate the remote of a git integration, which is either its single `remote`
+/// or a placeholder named after its `remotes_from` file until the remotes are read from the file.
+fn validate_git_remote(
+    remote: Option<String>,
+    remotes_from: Option<&Path>,
+) -> Result<remote::Remote, Report<remote::ValidationError>> {
+    match (remote, remotes_from) {
+        (Some(remote), None) => remote::Remote::try_from(remote),
+        (None, Some(path)) if path.is_absolute() => {
+            remote::Remote::try_from(path.display().to_string())
+        }
+        (None, Some(path)) => report!(remote::ValidationError::RemotesFrom)
+            .wrap_err()
+            .help("provide the absolute path to the file listing the remotes")
+            .describe_lazy(|| format!("provided path: '{}'", path.display())),
+        (Some(_), Some(_)) => report!(remote::ValidationError::RemotesFrom)
+            .wrap_err()
+            .help("provide either 'remote' or 'remotes_from', but not both"),
+        (None, None) => report!(remote::ValidationError::Remote).wrap_err().help(
+            "provide the integration's 'remote', or the file listing its remotes in 'remotes_from'",
+        ),
+    }
+}
+
+/// Validate the settings of a git integration which reads its remotes from a file.
+///
+/// Settings tied to a single remote don't apply to remotes read from a file.
+fn validate_remotes_from(
+    auth: &Auth,
+    aliases: &[String],
+) -> Result<(), Report<remote::ValidationError>> {
+    if matches!(auth, Auth::AwsCodeCommit { .. }) {
+        return report!(remote::ValidationError::RemotesFrom)
+            .wrap_err()
+            .help("'aws_code_commit' authentication requires a single 'remote'");
+    }
+    if !aliases.is_empty() {
+        return report!(remote::ValidationError::RemotesFrom)
+            .wrap_err()
+            .help("'aliases' require a single 'remote'");
+    }
+    Ok(())
+}
+
+fn validate_perforce_depot(depot: String) -> Result<String, Report<remote::ValidationError>> {
+    let normalized = depot.trim_end_matches('/');
+    if normalized.len() > 2 && normalized.starts_with("//") {
+        normalized.to_string().wrap_ok()
+    } else {
+        report!(remote::ValidationError::PerforceDepot)
+            .wrap_err()
+            .help("write the depot as a depot path, like '//game'")
+            .describe_lazy(|| format!("provided depot: '{depot}'"))
+    }
+}
+
+/// Normalize the URL of a Gerrit server like `https://gerrit.example.com/`
+/// to `https://gerrit.example.com`, rejecting anything that isn't an HTTP URL.
+fn validate_gerrit_url(url: String) -> Result<String, Report<remote::ValidationError>> {
+    let normalized = url.trim_end_matches('/');
+    match url::Url::parse(normalized) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {
+            normalized.to_string().wrap_ok()
+        }
+        _ => report!(remote::ValidationError::GerritUrl)
+            .wrap_err()
+            .help("write the url of the server like 'https://gerrit.example.com'")
+            .describe_lazy(|| format!("provided url: '{url}'")),
+    }
+}
+
+/// Validate the previous remotes of an integration, whose stored state moves to its current remote.
+fn validate_aliases(
+    aliases: Vec<String>,
+) -> Result<Vec<remote::Remote>, Report<remote::ValidationError>> {
+    aliases
+        .into_iter()
+        .map(remote::Remote::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .describe("validate 'aliases'")
+}
+
+/// Validate the branch mappings of an integration, rejecting mappings whose patterns overlap.
+fn validate_branch_mappings(
+    mappings: Vec<BranchMapping>,
+) -> Result<Vec<remote::BranchMapping>, Report<remote::ValidationError>> {
+    let mappings = mappings
+        .into_iter()
+        .map(|mapping| {
+            remote::BranchMapping::validate(mapping.branches, mapping.project, mapping.branch)
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .describe("validate 'branch_mappings'")?;
+
+    for (first, second) in mappings.iter().tuple_combinations() {
+        if first.overlaps(second) {
+            return report!(remote::ValidationError::BranchMappingOverlap(
+                first.branches().clone(),
+                second.branches().clone(),
+            ))
+            .wrap_err()
+            .help("make the patterns of branch mappings specific enough that no branch matches more than one");
+        }
+    }
+    mappings.wrap_ok()
+}
+
+/// Validate the environment variables set for FOSSA CLI, preserving whether each value is secret.
+fn validate_cli_env(
+    env: BTreeMap<String, CliEnvValue>,
+) -> Result<Vec<remote::CliEnv>, Report<remote::ValidationError>> {
+    env.into_iter()
+        .map(|(name, value)| {
+            if name.is_empty() || name.contains(['=', '\0']) {
+                return report!(remote::ValidationError::CliEnvName)
+                    .wrap_err()
+                    .help("environment variable names must be nonempty and may not contain '='")
+                    .describe_lazy(|| format!("provided name: '{name}'"));
+            }
+            let value = match value {
+                CliEnvValue::Plain(value) => remote::CliEnvValue::Plain(value),
+                CliEnvValue::Secret { secret } => {
+                    remote::CliEnvValue::Secret(ComparableSecretString::from(secret))
+                }
+            };
+            remote::CliEnv::new(name, value).wrap_ok()
+        })
+        .collect()
+}
+
+fn validate_poll_interval(
+    poll_interval: Option<String>,
+) -> Result<remote::PollInterval, Report<remote::ValidationError>> {
+    poll_interval
+        .ok_or_else(|| report!(remote::ValidationError::PollIntervalMissing))
+        .help("set 'poll_interval' on the integration or on its group")?
+        .pipe(remote::PollInterval::try_from)
+}
+
+fn validate_timeout(
+    timeout: Option<String>,
+    field: &str,
+    default: remote::JobTimeout,
+) -> Result<remote::JobTimeout, Report<remote::ValidationError>> {
+    timeout
+        .map(remote::JobTimeout::try_from)
+        .transpose()
+        .describe_lazy(|| format!("validate '{field}'"))
+        .map(|timeout| timeout.unwrap_or(default))
+}
+
+fn validate_poll_window(
+    poll_window: Option<String>,
+) -> Result<Option<remote::PollWindow>, Report<remote::ValidationError>> {
+    poll_window
+        .map(remote::PollWindow::try_from)
+        .transpose()
+        .describe("validate 'poll_window'")
+}
+
+fn validate_slow_scan_multiple(
+    slow_scan_multiple: Option<f64>,
+) -> Result<remote::SlowScanMultiple, Report<remote::ValidationError>> {
+    slow_scan_multiple
+        .map(remote::SlowScanMultiple::try_from)
+        .transpose()
+        .describe("validate 'slow_scan_multiple'")
+        .map(Option::unwrap_or_default)
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", deny_unknown_fields)]
 pub(super) enum Auth {
     #[serde(rename = "ssh_key_file")]
-    SshKeyFile { path: PathBuf },
+    SshKeyFile {
+        path: PathBuf,
+        passphrase: Option<String>,
+    },
 
     #[serde(rename = "ssh_key")]
-    SshKey { key: String },
+    SshKey {
+        key: String,
+        passphrase: Option<String>,
+    },
 
     #[serde(rename = "http_header")]
     HttpHeader { header: String },
@@ -275,6 +1936,225 @@ pub(super) enum Auth {
     #[serde(rename = "http_basic")]
     HttpBasic { username: String, password: String },
 
+    #[serde(rename = "http_command")]
+    HttpCommand {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        username: Option<String>,
+    },
+
+    #[serde(rename = "aws_codecommit")]
+    AwsCodeCommit { profile: Option<String> },
+
     #[serde(rename = "none")]
     None { transport: String },
 }
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub(super) enum BucketAuth {
+    #[serde(rename = "aws_access_key")]
+    AwsAccessKey {
+        access_key_id: String,
+        secret_access_key: String,
+        session_token: Option<String>,
+    },
+
+    #[serde(rename = "gcp_hmac_key")]
+    GcpHmacKey { access_id: String, secret: String },
+
+    #[serde(rename = "gcp_token_command")]
+    GcpTokenCommand {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+
+    #[serde(rename = "none")]
+    None,
+}
+
+/// Uploads scans of the branches matching `branches` to a different FOSSA project or branch.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct BranchMapping {
+    branches: String,
+    project: Option<String>,
+    branch: Option<String>,
+}
+
+/// Options for FOSSA CLI, translated into arguments for `fossa analyze`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct CliOptions {
+    unpack_archives: bool,
+    without_default_filters: bool,
+    only_paths: Vec<String>,
+    exclude_paths: Vec<String>,
+    detect_vendored: bool,
+    vendored_scan_method: Option<remote::VendoredScanMethod>,
+    force_vendored_rescans: bool,
+    first_party_scans: Option<remote::FirstPartyScans>,
+}
+
+impl TryFrom<CliOptions> for remote::CliOptions {
+    type Error = Report<remote::ValidationError>;
+
+    fn try_from(value: CliOptions) -> Result<Self, Self::Error> {
+        let only_paths = value
+            .only_paths
+            .into_iter()
+            .map(remote::validate_cli_option_path)
+            .collect::<Result<Vec<_>, _>>()?;
+        let exclude_paths = value
+            .exclude_paths
+            .into_iter()
+            .map(remote::validate_cli_option_path)
+            .collect::<Result<Vec<_>, _>>()?;
+        remote::CliOptions::builder()
+            .unpack_archives(value.unpack_archives)
+            .without_default_filters(value.without_default_filters)
+            .only_paths(only_paths)
+            .exclude_paths(exclude_paths)
+            .detect_vendored(value.detect_vendored)
+            .vendored_scan_method(value.vendored_scan_method)
+            .force_vendored_rescans(value.force_vendored_rescans)
+            .first_party_scans(value.first_party_scans)
+            .build()
+            .wrap_ok()
+    }
+}
+
+/// The value of an environment variable for FOSSA CLI: either a plain string,
+/// or a map with a `secret` key for values which must be redacted.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(super) enum CliEnvValue {
+    Plain(String),
+    Secret { secret: String },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub(super) enum PerforceAuth {
+    #[serde(rename = "ticket")]
+    Ticket { ticket: String },
+
+    #[serde(rename = "none")]
+    None,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub(super) enum GerritAuth {
+    #[serde(rename = "http_basic")]
+    HttpBasic { username: String, password: String },
+
+    #[serde(rename = "http_digest")]
+    HttpDigest { username: String, password: String },
+
+    #[serde(rename = "none")]
+    None,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub(super) enum Notification {
+    #[serde(rename = "webhook")]
+    Webhook {
+        url: String,
+        #[serde(default)]
+        events: Vec<notify::Kind>,
+        template: Option<String>,
+    },
+
+    #[serde(rename = "slack")]
+    Slack {
+        url: String,
+        #[serde(default)]
+        events: Vec<notify::Kind>,
+        template: Option<String>,
+    },
+
+    #[serde(rename = "smtp")]
+    Smtp {
+        host: String,
+        port: Option<u16>,
+        #[serde(default)]
+        tls: notify::smtp::Tls,
+        username: Option<String>,
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+        interval: Option<String>,
+        #[serde(default)]
+        events: Vec<notify::Kind>,
+        template: Option<String>,
+    },
+}
+
+impl TryFrom<Notification> for notify::Sink {
+    type Error = Report<notify::ValidationError>;
+
+    fn try_from(value: Notification) -> Result<Self, Self::Error> {
+        match value {
+            Notification::Webhook {
+                url,
+                events,
+                template,
+            } => validate_webhook(notify::webhook::Format::Json, url, events, template),
+            Notification::Slack {
+                url,
+                events,
+                template,
+            } => validate_webhook(notify::webhook::Format::Slack, url, events, template),
+            Notification::Smtp {
+                host,
+                port,
+                tls,
+                username,
+                password,
+                from,
+                to,
+                interval,
+                events,
+                template,
+            } => {
+                let interval = interval
+                    .map(notify::smtp::DigestInterval::try_from)
+                    .transpose()
+                    .describe("validate notification 'interval'")?
+                    .unwrap_or_default();
+                let template = validate_notification_template(template)?;
+                let events = notify::Subscription::from(events);
+                notify::smtp::Smtp::new(
+                    host, port, tls, username, password, from, to, interval, events, template,
+                )
+                .map(Self::Smtp)
+            }
+        }
+    }
+}
+
+fn validate_webhook(
+    format: notify::webhook::Format,
+    url: String,
+    events: Vec<notify::Kind>,
+    template: Option<String>,
+) -> Result<notify::Sink, Report<notify::ValidationError>> {
+    let template = validate_notification_template(template)?;
+    let events = notify::Subscription::from(events);
+    notify::webhook::Webhook::new(url, format, events, template)
+        .describe("validate notification 'url'")
+        .map(notify::Sink::Webhook)
+}
+
+fn validate_notification_template(
+    template: Option<String>,
+) -> Result<Option<notify::Template>, Report<notify::ValidationError>> {
+    template
+        .map(notify::Template::try_from)
+        .transpose()
+        .describe("validate notification 'template'")
+}
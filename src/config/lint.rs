@@ -0,0 +1,178 @@
+//! Semantic checks of the config, beyond what's needed to parse it.
+//!
+//! Lints report settings which are valid but unused or ineffective, so they likely aren't what the user intended.
+//! Unlike validation errors they don't prevent Broker from running:
+//! they're shown by `broker config validate` and logged as warnings when `broker run` starts.
+
+use std::{collections::BTreeMap, fmt::Display, time::Duration};
+
+use derive_new::new;
+use getset::Getters;
+use itertools::Itertools;
+use tracing::debug;
+
+use crate::{
+    api::remote::git::Backend,
+    cmd::run::history,
+    crypto,
+    db::{Database, ScanRecord},
+};
+
+use super::Config;
+
+/// A setting in the config which is valid, but likely not what the user intended.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, new)]
+#[getset(get = "pub")]
+pub struct Lint {
+    /// The remote of the integration the lint is about, if it's about one.
+    integration: Option<String>,
+
+    /// What about the setting is likely unintended.
+    message: String,
+
+    /// How to resolve the lint.
+    help: String,
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.integration {
+            Some(integration) => write!(f, "{integration}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Check the config for settings which are likely unintended.
+///
+/// Lints which depend on the scans Broker has observed are only checked if `db` is provided.
+pub async fn lint<D: Database>(config: &Config, db: Option<&D>) -> Vec<Lint> {
+    let mut lints = config.lints().clone();
+    lints.extend(duplicate_remotes(config));
+    lints.extend(ignored_git_backend(config));
+    if let Some(db) = db {
+        lints.extend(short_poll_intervals(config, db).await);
+    }
+    lints
+}
+
+/// Integrations for the same repository scan it twice, and race to update its state.
+///
+/// Remotes which are written differently but refer to the same repository are duplicates too.
+fn duplicate_remotes(config: &Config) -> Vec<Lint> {
+    let mut by_repository = BTreeMap::<_, Vec<_>>::new();
+    for integration in config.integrations().iter() {
+        by_repository
+            .entry(integration.repository())
+            .or_default()
+            .push(integration.remote().to_string());
+    }
+
+    by_repository
+        .into_values()
+        .filter(|remotes| remotes.len() > 1)
+        .map(|remotes| {
+            let listed = remotes
+                .iter()
+                .map(|remote| format!("'{remote}'"))
+                .join(", ");
+            Lint::new(
+                remotes.first().cloned(),
+                format!(
+                    "{} integrations have the same remote: {listed}",
+                    remotes.len()
+                ),
+                String::from("combine them into a single integration, or remove all but one"),
+            )
+        })
+        .collect()
+}
+
+/// The built in git client is never used in strict crypto mode, since it connects with Broker's built in TLS implementation.
+fn ignored_git_backend(config: &Config) -> Option<Lint> {
+    let mode = crypto::effective(*config.crypto_mode());
+    if *config.git_backend() != Backend::Native || mode != crypto::Mode::Strict {
+        return None;
+    }
+
+    Some(Lint::new(
+        None,
+        String::from("'git_backend: native' is ignored in strict crypto mode, which always runs the git executable"),
+        String::from("remove 'git_backend', or set 'crypto_mode: standard' if this build allows it"),
+    ))
+}
+
+/// Integrations polled more often than they take to scan find new changes before the previous ones are scanned,
+/// so the queue of scans grows without bound.
+async fn short_poll_intervals<D: Database>(config: &Config, db: &D) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    for integration in config.integrations().iter() {
+        let repository = integration.repository();
+        let recent = match db
+            .recent_scans(&integration.namespace(), &repository, history::WINDOW)
+            .await
+        {
+            Ok(recent) => recent,
+            Err(err) => {
+                debug!("Unable to read scan history for '{integration}': {err:#}");
+                continue;
+            }
+        };
+        let Some(typical) = typical_duration(&recent) else {
+            continue;
+        };
+
+        let poll_interval = integration.poll_interval().as_duration();
+        if poll_interval < typical {
+            lints.push(Lint::new(
+                Some(integration.remote().to_string()),
+                format!(
+                    "poll interval of {} is shorter than its typical scan, which takes {}",
+                    humantime::format_duration(poll_interval),
+                    humantime::format_duration(Duration::from_secs(typical.as_secs())),
+                ),
+                String::from(
+                    "increase 'poll_interval' so that each scan finishes before the next poll",
+                ),
+            ));
+        }
+    }
+    lints
+}
+
+/// The average time scans in the history took to clone and analyze,
+/// if there are enough scans for the average to be meaningful.
+fn typical_duration(history: &[ScanRecord]) -> Option<Duration> {
+    if history.len() < history::MIN_SAMPLES {
+        return None;
+    }
+    let total = history
+        .iter()
+        .map(|scan| scan.clone_duration() + scan.analyze_duration())
+        .sum::<Duration>();
+    Some(total.div_f64(history.len() as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(clone_secs: u64, analyze_secs: u64) -> ScanRecord {
+        ScanRecord::new(
+            String::from("scan"),
+            Duration::from_secs(clone_secs),
+            Duration::from_secs(analyze_secs),
+        )
+    }
+
+    #[test]
+    fn typical_duration_requires_samples() {
+        let history = vec![scan(60, 120); history::MIN_SAMPLES - 1];
+        assert_eq!(typical_duration(&history), None);
+
+        let mut history = vec![scan(60, 120); history::MIN_SAMPLES - 1];
+        history.push(scan(60, 420));
+        let expected = Duration::from_secs(180 + 300 / history::MIN_SAMPLES as u64);
+        assert_eq!(typical_duration(&history), Some(expected));
+    }
+}
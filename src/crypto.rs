@@ -0,0 +1,131 @@
+//! The cryptography used for outbound connections.
+//!
+//! Broker connects to FOSSA, code hosts, and notification sinks with the TLS implementation built into it
+//! ([rustls](https://docs.rs/rustls), using [ring](https://docs.rs/ring) for cryptography),
+//! while the `git` executable connects with the TLS library of the system, typically OpenSSL.
+//!
+//! In [`Mode::Strict`], git operations always run the `git` executable, so that they use the system's TLS library,
+//! which follows the FIPS mode of the host.
+//! The built in TLS implementation isn't FIPS 140 validated, so strict mode alone doesn't change how Broker connects itself.
+//!
+//! Builds with the `fips` feature connect with the system's TLS library instead
+//! (through [native-tls](https://docs.rs/native-tls): OpenSSL on Linux),
+//! which uses FIPS-validated cryptography when the host enforces FIPS mode, and require TLS 1.2 or later.
+//! Every HTTP client Broker constructs starts from [`http_client`] so that it connects with [`TLS_PROVIDER`];
+//! SMTP connections select it separately.
+//! These builds always use strict mode, regardless of the config file, so the built in git client is never used either.
+//!
+//! Like the git backend, the mode is process wide. `broker doctor` reports the mode and the provider.
+
+use error_stack::Report;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::remote::git::Backend,
+    ext::once_cell::{Conflict, InstallOnce},
+};
+
+/// The TLS implementation used for every connection Broker makes itself.
+#[cfg(not(feature = "fips"))]
+pub const TLS_PROVIDER: &str = "rustls (ring)";
+
+/// The TLS implementation used for every connection Broker makes itself.
+#[cfg(feature = "fips")]
+pub const TLS_PROVIDER: &str = "native-tls (the system's TLS library)";
+
+/// Whether the cryptography used for outbound connections is constrained.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Mode {
+    /// Connections use the defaults of their TLS implementation.
+    #[default]
+    Standard,
+
+    /// Git operations always use the system's TLS library.
+    Strict,
+}
+
+/// The mode installed with [`install`].
+static MODE: OnceCell<Mode> = OnceCell::new();
+
+/// Use the provided mode for every connection made after this is called.
+///
+/// Connections made since it was installed rely on the mode, so installing a different one is an error.
+pub fn install(mode: Mode) -> Result<(), Report<Conflict>> {
+    MODE.install("crypto mode", mode)
+}
+
+/// The mode used for connections: the one installed with [`install`], or the standard mode,
+/// unless the build requires strict mode.
+pub fn mode() -> Mode {
+    effective(MODE.get().copied().unwrap_or_default())
+}
+
+/// The mode used when the provided mode is configured: strict if required by the build, otherwise the configured mode.
+pub fn effective(configured: Mode) -> Mode {
+    if required_by_build() {
+        Mode::Strict
+    } else {
+        configured
+    }
+}
+
+/// Whether this build of Broker requires strict mode, because it was built with the `fips` feature.
+///
+/// These builds also connect with the system's TLS library, rather than the built in one.
+pub fn required_by_build() -> bool {
+    cfg!(feature = "fips")
+}
+
+/// A builder for HTTP clients which connect with [`TLS_PROVIDER`].
+#[cfg(not(feature = "fips"))]
+pub fn http_client() -> reqwest::ClientBuilder {
+    reqwest::Client::builder().use_rustls_tls()
+}
+
+/// A builder for HTTP clients which connect with [`TLS_PROVIDER`].
+#[cfg(feature = "fips")]
+pub fn http_client() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .use_native_tls()
+        .min_tls_version(reqwest::tls::Version::TLS_1_2)
+}
+
+/// The git backend to use in the mode: the configured one, except in strict mode,
+/// where the built in client is never used since it connects with the built in TLS implementation.
+pub fn git_backend(mode: Mode, configured: Backend) -> Backend {
+    match mode {
+        Mode::Standard => configured,
+        Mode::Strict => Backend::System,
+    }
+}
+
+/// Whether the host enforces FIPS mode for the system's cryptography, if it can be determined on this platform.
+#[cfg(target_os = "linux")]
+pub fn host_fips_enabled() -> Option<bool> {
+    let enabled = std::fs::read_to_string("/proc/sys/crypto/fips_enabled").ok()?;
+    Some(enabled.trim() == "1")
+}
+
+/// Whether the host enforces FIPS mode for the system's cryptography, if it can be determined on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn host_fips_enabled() -> Option<bool> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_mode_uses_system_git() {
+        assert_eq!(
+            git_backend(Mode::Standard, Backend::Native),
+            Backend::Native
+        );
+        assert_eq!(git_backend(Mode::Strict, Backend::Native), Backend::System);
+        assert_eq!(git_backend(Mode::Strict, Backend::System), Backend::System);
+    }
+}
@@ -1,14 +1,27 @@
 //! Interface for interacting with the database, abstracted over database implementation.
 
-use std::{fmt::Debug, path::Path};
+use std::{
+    fmt::Debug,
+    path::Path,
+    time::{Duration, SystemTime},
+};
 
 use async_trait::async_trait;
 use derive_new::new;
-use error_stack::{Result, ResultExt};
+use error_stack::{report, Result, ResultExt};
+use getset::{CopyGetters, Getters};
+use indoc::indoc;
 use semver::Version;
-use strum::Display;
+use strum::{Display, EnumString};
 use thiserror::Error;
 
+use crate::ext::{
+    error_stack::{DescribeContext, ErrorHelper},
+    result::WrapErr,
+};
+
+#[cfg(any(test, feature = "test-fixtures"))]
+pub mod memory;
 mod sqlite;
 
 /// Errors interacting with the database.
@@ -31,6 +44,66 @@ pub enum Error {
     /// Applications should refuse to run when this error is encountered.
     #[error("newer version of Broker has used this database")]
     BrokerOutdated,
+
+    /// Encountered when inspecting a database which does not exist.
+    #[error("database does not exist")]
+    NotFound,
+}
+
+/// Errors that are possibly surfaced during validation of config values.
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    /// A duration for the database is not valid.
+    #[error("validate database {0}")]
+    Duration(&'static str),
+}
+
+/// Validated config values for the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters, new)]
+#[getset(get_copy = "pub")]
+pub struct Config {
+    /// How long a statement waits for other connections to release their locks
+    /// before the database reports that it's busy.
+    busy_timeout: Duration,
+
+    /// How often the write-ahead log is checkpointed into the database.
+    /// If zero, it's only checkpointed when sqlite does so automatically.
+    checkpoint_interval: Duration,
+}
+
+impl Config {
+    /// Validate the database settings, using the defaults for any which aren't provided.
+    pub fn validate(
+        busy_timeout: Option<String>,
+        checkpoint_interval: Option<String>,
+    ) -> Result<Self, ValidationError> {
+        let default = Self::default();
+        let busy_timeout = match busy_timeout {
+            Some(value) => parse_duration("busy_timeout", &value)?,
+            None => default.busy_timeout,
+        };
+        let checkpoint_interval = match checkpoint_interval {
+            Some(value) => parse_duration("checkpoint_interval", &value)?,
+            None => default.checkpoint_interval,
+        };
+        Ok(Self::new(busy_timeout, checkpoint_interval))
+    }
+}
+
+fn parse_duration(name: &'static str, value: &str) -> Result<Duration, ValidationError> {
+    humantime::parse_duration(value)
+        .context(ValidationError::Duration(name))
+        .describe_lazy(|| format!("provided value: '{value}'"))
+        .help("provide a duration like '30s' or '5m'")
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            busy_timeout: Duration::from_secs(30),
+            checkpoint_interval: Duration::from_secs(5 * 60),
+        }
+    }
 }
 
 /// Each integration gets its own coordinate namespace.
@@ -41,10 +114,25 @@ pub enum Error {
 ///
 /// When a new integration is written, a representative entry should
 /// be added to this namespace.
-#[derive(Debug, Clone, PartialEq, Eq, Display, new)]
+#[derive(Debug, Clone, PartialEq, Eq, Display, EnumString, new)]
 pub enum Namespace {
     /// The namespace for `git` integrations.
     Git,
+
+    /// The namespace for `local` integrations.
+    Local,
+
+    /// The namespace for `archive` integrations.
+    Archive,
+
+    /// The namespace for `bucket` integrations.
+    Bucket,
+
+    /// The namespace for `perforce` integrations.
+    Perforce,
+
+    /// The namespace for `gerrit` integrations.
+    Gerrit,
 }
 
 /// A coordinate is a remote and a reference on that remote.
@@ -56,13 +144,243 @@ pub enum Namespace {
 /// This is also why it requires a namespace for the integration:
 /// since remotes are arbitrarily encoded, it'd be otherwise possible
 /// for them to accidentally collide.
-#[derive(Debug, Clone, PartialEq, Eq, new)]
+#[derive(Debug, Clone, PartialEq, Eq, Getters, new)]
+#[getset(get = "pub")]
 pub struct Coordinate {
     namespace: Namespace,
     remote: String,
     reference: String,
 }
 
+/// The record of a completed scan, as stored in the scan history.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, new)]
+pub struct ScanRecord {
+    /// The ID of the scan.
+    #[getset(get = "pub")]
+    scan_id: String,
+
+    /// How long the reference took to clone.
+    #[getset(get_copy = "pub")]
+    clone_duration: Duration,
+
+    /// How long the reference took to analyze.
+    #[getset(get_copy = "pub")]
+    analyze_duration: Duration,
+
+    /// Whether the uploaded scan passed its check for issues in FOSSA, if it was checked.
+    #[getset(get_copy = "pub")]
+    #[new(default)]
+    policy: Option<PolicyStatus>,
+}
+
+impl ScanRecord {
+    /// Set the result of checking the uploaded scan for issues.
+    pub fn with_policy(self, policy: Option<PolicyStatus>) -> Self {
+        Self { policy, ..self }
+    }
+}
+
+/// A scan in the scan history, along with what it scanned and when.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, new)]
+pub struct HistoricScan {
+    /// The reference which was scanned, as written in its coordinate.
+    #[getset(get = "pub")]
+    revision: String,
+
+    /// When the scan was recorded.
+    #[getset(get_copy = "pub")]
+    recorded_at: SystemTime,
+
+    /// The locator of the scan in FOSSA, if it was uploaded to the primary FOSSA endpoint.
+    #[getset(get = "pub")]
+    locator: Option<String>,
+
+    /// The record of the scan.
+    #[getset(get = "pub")]
+    record: ScanRecord,
+}
+
+/// The result of checking an uploaded scan for issues in FOSSA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum PolicyStatus {
+    /// FOSSA found no issues in the scan.
+    Passed,
+
+    /// FOSSA found issues in the scan.
+    Failed,
+}
+
+/// The FOSSA project to which scans of a repository are uploaded.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, new)]
+#[getset(get = "pub")]
+pub struct ProjectMapping {
+    /// The name of the project. Used to build the locator.
+    project: String,
+
+    /// The title of the project, if it already existed in FOSSA.
+    title: Option<String>,
+}
+
+/// A scan which failed to upload, saved so that the upload can be retried later.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, new)]
+pub struct PendingUpload {
+    /// The ID of the scan.
+    #[getset(get = "pub")]
+    scan_id: String,
+
+    /// The coordinate of the scanned reference.
+    #[getset(get = "pub")]
+    coordinate: Coordinate,
+
+    /// How many times the upload has failed.
+    #[getset(get_copy = "pub")]
+    attempts: u32,
+
+    /// When the upload first failed.
+    #[getset(get_copy = "pub")]
+    first_failed_at: SystemTime,
+
+    /// When the upload should next be attempted.
+    #[getset(get_copy = "pub")]
+    next_attempt_at: SystemTime,
+}
+
+impl PendingUpload {
+    /// Set when the upload should next be attempted.
+    pub fn with_next_attempt_at(mut self, next_attempt_at: SystemTime) -> Self {
+        self.next_attempt_at = next_attempt_at;
+        self
+    }
+}
+
+/// The stage of `broker run` at which a job is waiting or being worked on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum JobStage {
+    /// The reference is waiting to be scanned, or is being scanned.
+    Scan,
+
+    /// The scan is waiting to be uploaded, or is being uploaded.
+    Upload,
+
+    /// The uploaded scan is waiting to be followed up on, or is being followed up on.
+    FollowUp,
+}
+
+/// A job enqueued by `broker run`, recorded until it's done so that it can be inspected while it waits
+/// and redelivered if Broker stops before finishing it.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, new)]
+pub struct QueuedJob {
+    /// The ID of the scan performed by the job.
+    #[getset(get = "pub")]
+    scan_id: String,
+
+    /// The stage at which the job is waiting.
+    #[getset(get_copy = "pub")]
+    stage: JobStage,
+
+    /// The coordinate of the reference being scanned.
+    #[getset(get = "pub")]
+    coordinate: Coordinate,
+
+    /// The name of the reference being scanned.
+    #[getset(get = "pub")]
+    reference: String,
+
+    /// The state of the reference being scanned, recorded once it's scanned.
+    #[getset(get = "pub")]
+    state: Vec<u8>,
+
+    /// Whether the reference being scanned is a branch.
+    #[getset(get_copy = "pub")]
+    is_branch: bool,
+
+    /// When the job entered its current stage.
+    #[getset(get_copy = "pub")]
+    enqueued_at: SystemTime,
+
+    /// What the job needs to be redelivered if Broker stops before it's done, serialized:
+    /// the reference being scanned, or once the scan is uploaded, the follow up on the upload.
+    /// Jobs recorded before jobs were redelivered don't have this.
+    #[getset(get = "pub")]
+    payload: Option<Vec<u8>>,
+}
+
+/// Progress through the references enqueued for scanning for a repository.
+///
+/// A backlog starts when references are enqueued while no backlog is in progress,
+/// and grows if more are enqueued before it completes.
+#[derive(Debug, Clone, PartialEq, Eq, CopyGetters, new)]
+#[getset(get_copy = "pub")]
+pub struct Backlog {
+    /// How many references have been enqueued in this backlog.
+    total: u64,
+
+    /// How many of the enqueued references have been scanned, successfully or not.
+    completed: u64,
+
+    /// When the first references in this backlog were enqueued.
+    started_at: SystemTime,
+
+    /// When references were last enqueued or scanned.
+    updated_at: SystemTime,
+}
+
+impl Backlog {
+    /// Whether every enqueued reference has been scanned.
+    pub fn is_complete(&self) -> bool {
+        self.completed >= self.total
+    }
+
+    /// The percentage of enqueued references which have been scanned.
+    pub fn percent_complete(&self) -> f64 {
+        if self.total == 0 {
+            return 100.0;
+        }
+        (self.completed as f64 / self.total as f64) * 100.0
+    }
+}
+
+/// The result of the most recent check of whether Broker can connect to the code host of a repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters, new)]
+#[getset(get_copy = "pub")]
+pub struct Connectivity {
+    /// Whether Broker could connect to the code host.
+    reachable: bool,
+
+    /// When the code host was checked.
+    checked_at: SystemTime,
+}
+
+/// The integrations to which a pause applies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PauseScope {
+    /// Every integration, including those added to the config after the pause.
+    All,
+
+    /// The integration for a single repository.
+    Repository(Namespace, String),
+}
+
+/// Information about a database, as shown by `broker db info`.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, new)]
+pub struct DatabaseInfo {
+    /// The version of the most recent schema migration applied to the database,
+    /// or `None` if no migrations have been applied.
+    #[getset(get_copy = "pub")]
+    schema_version: Option<i64>,
+
+    /// The last version of Broker to claim the database,
+    /// or `None` if no version of Broker has claimed it.
+    #[getset(get = "pub")]
+    broker_version: Option<Version>,
+
+    /// The size of the database in bytes.
+    #[getset(get_copy = "pub")]
+    size: u64,
+}
+
 /// All databases implement this type.
 ///
 /// Database is `Send`, `Sync` and `Clone`.
@@ -73,6 +391,16 @@ pub trait Database: Debug + Clone + Send + Sync {
     /// Check that we can contact the DB.
     async fn healthcheck(&self) -> Result<(), Error>;
 
+    /// Move the changes recorded in the DB's write-ahead log into the DB itself,
+    /// so that the log doesn't grow without bound while the DB is busy.
+    ///
+    /// Implementations without a write-ahead log do nothing.
+    async fn checkpoint(&self) -> Result<(), Error>;
+
+    /// Check the integrity of the DB, returning the problems found,
+    /// or a single `ok` if there are none.
+    async fn integrity_check(&self) -> Result<Vec<String>, Error>;
+
     /// The last version of Broker used to access the database.
     /// If the DB has never been accessed before, returns `None`.
     ///
@@ -83,11 +411,23 @@ pub trait Database: Debug + Clone + Send + Sync {
 
     /// Set the current Broker version as the last used version to access the database.
     /// This checks whether the last used version is newer first and returns an error if so.
+    ///
+    /// Broker's compatibility policy is that a database may be used by the version of Broker
+    /// that last claimed it or any newer version, but never by an older version:
+    /// newer versions may add invariants that older versions would not uphold.
     async fn claim_broker_version(&self) -> Result<(), Error>;
 
+    /// Report the schema version, claimed Broker version, and size of the database.
+    async fn info(&self) -> Result<DatabaseInfo, Error>;
+
     /// Get the last scanned state of a given [`Coordinate`].
     async fn state(&self, coordinate: &Coordinate) -> Result<Option<Vec<u8>>, Error>;
 
+    /// Get the last scanned state of each provided [`Coordinate`] in a single round trip.
+    ///
+    /// States are returned in the same order as the provided coordinates.
+    async fn states_for(&self, coordinates: &[Coordinate]) -> Result<Vec<Option<Vec<u8>>>, Error>;
+
     /// Set the state of a given [`Coordinate`].
     async fn set_state(
         &self,
@@ -96,8 +436,270 @@ pub trait Database: Debug + Clone + Send + Sync {
         is_branch: &bool,
     ) -> Result<(), Error>;
 
+    /// Deletes the state of a given [`Coordinate`], so that it is scanned again.
+    async fn delete_state(&self, coordinate: &Coordinate) -> Result<(), Error>;
+
     /// Deletes all states with the given repository and is_branch values
     async fn delete_states(&self, repository: &str, is_branch: bool) -> Result<(), Error>;
+
+    /// Get the hash of the references last seen for a repository,
+    /// as stored by [`Database::set_references_hash`].
+    async fn references_hash(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Set the hash of the references seen for a repository.
+    ///
+    /// Callers should only set this hash once every reference it covers has been scanned,
+    /// since an unchanged hash is used to skip checking references individually.
+    async fn set_references_hash(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        hash: &[u8],
+    ) -> Result<(), Error>;
+
+    /// Deletes the stored states for a repository, so that its references are scanned again.
+    /// If `revision_prefix` is provided, only states whose revision begins with it are deleted.
+    ///
+    /// Returns the number of states deleted.
+    async fn reset_states(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        revision_prefix: Option<&str>,
+    ) -> Result<u64, Error>;
+
+    /// Deletes the hash of the references last seen for a repository,
+    /// so that the next poll checks each reference individually.
+    async fn delete_references_hash(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<(), Error>;
+
+    /// Moves everything stored for a repository to a new repository name, such as its states, scan history,
+    /// pending uploads, queued jobs, and project mapping, so that coordinates created before the repository's
+    /// representation changed keep working.
+    ///
+    /// States, and other values stored once per repository, already stored under the new name
+    /// take precedence over those being moved.
+    /// The hash of the references last seen for the repository is deleted rather than moved,
+    /// so that the next poll checks each reference individually.
+    ///
+    /// Returns the number of states moved.
+    async fn rename_repository(
+        &self,
+        namespace: &Namespace,
+        from: &str,
+        to: &str,
+    ) -> Result<u64, Error>;
+
+    /// Record a completed scan of the given [`Coordinate`] in the scan history.
+    async fn record_scan(&self, coordinate: &Coordinate, scan: &ScanRecord) -> Result<(), Error>;
+
+    /// Record that a branch was scanned at the given state in its history.
+    ///
+    /// Coordinates of branches include their state, so this is how Broker knows where a branch was before it moved.
+    async fn record_branch_state(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        branch: &str,
+        state: &[u8],
+    ) -> Result<(), Error>;
+
+    /// Get the state at which a branch was most recently scanned from its history, if it has been scanned.
+    async fn last_branch_state(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        branch: &str,
+    ) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Record that a repository was polled at the provided time.
+    async fn record_poll(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        now: SystemTime,
+    ) -> Result<(), Error>;
+
+    /// Get when a repository was last polled, if it has been polled.
+    async fn last_poll(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<SystemTime>, Error>;
+
+    /// Record whether Broker could connect to the code host of a repository at the provided time.
+    async fn record_connectivity(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        reachable: bool,
+        now: SystemTime,
+    ) -> Result<(), Error>;
+
+    /// Get the result of the most recent check of whether Broker can connect to the code host of a repository,
+    /// if it has been checked.
+    async fn connectivity(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<Connectivity>, Error>;
+
+    /// Record the result of checking an uploaded scan for issues in the scan history.
+    async fn set_scan_policy(&self, scan_id: &str, policy: PolicyStatus) -> Result<(), Error>;
+
+    /// Record the locator under which a scan was uploaded to the primary FOSSA endpoint in the scan history.
+    async fn set_scan_locator(&self, scan_id: &str, locator: &str) -> Result<(), Error>;
+
+    /// Get the scans of a repository recorded at or after `since` from the scan history, newest first.
+    async fn scans_since(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        since: SystemTime,
+    ) -> Result<Vec<HistoricScan>, Error>;
+
+    /// Get up to `limit` of the most recent scans of a repository from the scan history, newest first.
+    async fn recent_scans(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        limit: u32,
+    ) -> Result<Vec<ScanRecord>, Error>;
+
+    /// Get the FOSSA project to which scans of a repository are uploaded, if it has been determined.
+    async fn project_mapping(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<ProjectMapping>, Error>;
+
+    /// Set the FOSSA project to which scans of a repository are uploaded.
+    async fn set_project_mapping(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        mapping: &ProjectMapping,
+    ) -> Result<(), Error>;
+
+    /// Get the pending upload for a scan of the given [`Coordinate`], if its upload previously failed.
+    async fn pending_upload(
+        &self,
+        coordinate: &Coordinate,
+        scan_id: &str,
+    ) -> Result<Option<PendingUpload>, Error>;
+
+    /// Get the pending uploads for a repository which are due to be attempted at `now`, oldest first.
+    async fn due_pending_uploads(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        now: SystemTime,
+    ) -> Result<Vec<PendingUpload>, Error>;
+
+    /// Create or update a pending upload.
+    async fn set_pending_upload(&self, upload: &PendingUpload) -> Result<(), Error>;
+
+    /// Delete the pending upload for a scan, if there is one.
+    async fn delete_pending_upload(&self, scan_id: &str) -> Result<(), Error>;
+
+    /// Get every pending upload for a repository, regardless of when it's due, oldest first.
+    async fn pending_uploads(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Vec<PendingUpload>, Error>;
+
+    /// Record a job enqueued by `broker run`, replacing any job recorded with the same scan ID.
+    async fn set_queued_job(&self, job: &QueuedJob) -> Result<(), Error>;
+
+    /// Forget a job enqueued by `broker run`, once it's done.
+    async fn delete_queued_job(&self, scan_id: &str) -> Result<(), Error>;
+
+    /// Get the jobs enqueued by `broker run` which aren't done, oldest first.
+    async fn queued_jobs(&self) -> Result<Vec<QueuedJob>, Error>;
+
+    /// Take or renew the lease on scanning the given [`Coordinate`] for `holder` until `expires_at`.
+    ///
+    /// The lease is granted if nobody holds it, `holder` already holds it, or the previous lease expired at `now`.
+    /// Returns whether `holder` holds the lease.
+    async fn acquire_scan_lease(
+        &self,
+        coordinate: &Coordinate,
+        holder: &str,
+        now: SystemTime,
+        expires_at: SystemTime,
+    ) -> Result<bool, Error>;
+
+    /// Release the lease on scanning the given [`Coordinate`], if `holder` holds it.
+    async fn release_scan_lease(&self, coordinate: &Coordinate, holder: &str) -> Result<(), Error>;
+
+    /// Get the backlog of references enqueued for scanning for a repository, if any were ever enqueued.
+    async fn backlog(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<Backlog>, Error>;
+
+    /// Record that `count` references of a repository were enqueued for scanning.
+    ///
+    /// If the repository's previous backlog is complete, this starts a new backlog;
+    /// otherwise the references are added to the one in progress.
+    async fn enqueue_backlog(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        count: u64,
+        now: SystemTime,
+    ) -> Result<(), Error>;
+
+    /// Mark every backlog in progress as complete, returning how many were in progress.
+    ///
+    /// References enqueued by a previous run which weren't scanned are found again when their repository is next polled,
+    /// so this is used at startup to start fresh backlogs instead of counting those references twice.
+    async fn abandon_backlogs(&self) -> Result<u64, Error>;
+
+    /// Record that one of the references enqueued for a repository was scanned, successfully or not.
+    async fn progress_backlog(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        now: SystemTime,
+    ) -> Result<(), Error>;
+
+    /// Pause the integrations in the scope as of `now`, so that `broker run` skips them until they're resumed.
+    async fn pause(&self, scope: &PauseScope, now: SystemTime) -> Result<(), Error>;
+
+    /// Resume the integrations in the scope, returning how many pauses were removed.
+    ///
+    /// Resuming [`PauseScope::All`] removes every pause, including those of individual integrations.
+    async fn resume(&self, scope: &PauseScope) -> Result<u64, Error>;
+
+    /// Whether the integration for a repository is paused, either on its own or along with every integration.
+    async fn is_paused(&self, namespace: &Namespace, repository: &str) -> Result<bool, Error>;
+}
+
+/// The error returned when the database was last claimed by a newer version of Broker.
+fn outdated(current_version: &Version, db_version: &Version) -> Result<(), Error> {
+    report!(Error::BrokerOutdated)
+        .wrap_err()
+        .describe(indoc! {"
+            Broker stores the last used version in the DB to ensure
+            that older versions of Broker cannot break invariants added in newer
+            versions of Broker.
+            "})
+        .describe_lazy(|| {
+            format!("this is Broker {current_version}, but Broker {db_version} has used this database")
+        })
+        .help_lazy(|| {
+            format!("upgrade to Broker {db_version} or later, or use a different database with '--database-file-path' (Broker then scans all references again)")
+        })
 }
 
 /// Connect to the sqlite database implementation.
@@ -107,8 +709,32 @@ pub trait Database: Debug + Clone + Send + Sync {
 /// but not _accepting_ the type.
 ///
 /// Instead, functions should accept [`Database`].
-pub async fn connect_sqlite(location: &Path) -> Result<sqlite::Database, Error> {
-    sqlite::Database::connect(location)
+pub async fn connect_sqlite(location: &Path, config: Config) -> Result<sqlite::Database, Error> {
+    sqlite::Database::connect(location, config)
+        .await
+        .change_context(Error::Initialize)
+}
+
+/// Open an existing sqlite database to inspect it.
+///
+/// Unlike [`connect_sqlite`], this neither migrates the database nor claims it for the current version of Broker,
+/// so that databases last used by newer versions of Broker can still be inspected.
+pub async fn inspect_sqlite(location: &Path) -> Result<DatabaseInfo, Error> {
+    open_sqlite_read_only(location).await?.info().await
+}
+
+/// Open an existing sqlite database for reading, for example while `broker run` is using it.
+///
+/// Like [`inspect_sqlite`], this neither migrates the database nor claims it for the current version of Broker.
+pub async fn open_sqlite_read_only(location: &Path) -> Result<sqlite::Database, Error> {
+    if !location.exists() {
+        return report!(Error::NotFound)
+            .wrap_err()
+            .describe_lazy(|| format!("no database exists at '{}'", location.display()))
+            .help("provide the location of the database with '--database-file-path'");
+    }
+
+    sqlite::Database::open_read_only(location)
         .await
         .change_context(Error::Initialize)
 }
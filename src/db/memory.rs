@@ -0,0 +1,758 @@
+//! Database implementation which keeps everything in memory.
+//!
+//! This is a deterministic stand-in for the sqlite implementation, for tests which exercise code
+//! that reads or writes the database without creating a database file.
+//! It implements the same semantics as the sqlite implementation; the sqlite implementation's tests
+//! are the reference for how each operation behaves.
+//!
+//! Nothing is persisted: clones of a [`Database`] share the same storage,
+//! which is dropped along with the last clone.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use error_stack::Result;
+use semver::Version;
+
+use crate::{doc::crate_version, ext::result::WrapOk};
+
+use super::{
+    Backlog, Connectivity, Coordinate, DatabaseInfo, HistoricScan, Namespace, PauseScope,
+    PendingUpload, PolicyStatus, ProjectMapping, QueuedJob, ScanRecord,
+};
+
+/// Identifies a repository: the namespace and the repository name.
+type RepositoryKey = (String, String);
+
+/// Identifies a reference: the namespace, repository name, and revision.
+type CoordinateKey = (String, String, String);
+
+/// An in-memory database.
+#[derive(Debug, Clone, Default)]
+pub struct Database {
+    storage: Arc<Mutex<Storage>>,
+}
+
+#[derive(Debug, Default)]
+struct Storage {
+    broker_version: Option<Version>,
+    states: BTreeMap<CoordinateKey, State>,
+    references_hashes: BTreeMap<RepositoryKey, Vec<u8>>,
+    scans: Vec<RecordedScan>,
+    branch_history: Vec<RecordedBranchState>,
+    project_mappings: BTreeMap<RepositoryKey, ProjectMapping>,
+    pending_uploads: BTreeMap<String, PendingUpload>,
+    queued_jobs: BTreeMap<String, QueuedJob>,
+    backlogs: BTreeMap<RepositoryKey, Backlog>,
+    polls: BTreeMap<RepositoryKey, SystemTime>,
+    connectivity: BTreeMap<RepositoryKey, Connectivity>,
+    scan_leases: BTreeMap<CoordinateKey, ScanLease>,
+    /// Pauses by the repository they apply to, or `None` for the pause of every integration.
+    pauses: BTreeMap<Option<RepositoryKey>, SystemTime>,
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    state: Vec<u8>,
+    is_branch: bool,
+}
+
+#[derive(Debug, Clone)]
+struct ScanLease {
+    holder: String,
+    expires_at: SystemTime,
+}
+
+#[derive(Debug, Clone)]
+struct RecordedScan {
+    repository: RepositoryKey,
+    revision: String,
+    recorded_at: SystemTime,
+    locator: Option<String>,
+    record: ScanRecord,
+}
+
+#[derive(Debug, Clone)]
+struct RecordedBranchState {
+    repository: RepositoryKey,
+    branch: String,
+    state: Vec<u8>,
+}
+
+impl Database {
+    /// Create an empty database.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A database which claims to have last been used by the provided version of Broker.
+    pub fn with_broker_version(version: Version) -> Self {
+        let db = Self::default();
+        db.storage().broker_version = Some(version);
+        db
+    }
+
+    /// Lock the storage for the duration of an operation.
+    ///
+    /// Operations never panic while holding the lock, but if a test does,
+    /// the storage is still consistent, so a poisoned lock is recovered.
+    fn storage(&self) -> MutexGuard<'_, Storage> {
+        self.storage.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn repository_key(namespace: &Namespace, repository: &str) -> RepositoryKey {
+    (namespace.to_string(), repository.to_string())
+}
+
+fn pause_key(scope: &PauseScope) -> Option<RepositoryKey> {
+    match scope {
+        PauseScope::All => None,
+        PauseScope::Repository(namespace, repository) => {
+            Some(repository_key(namespace, repository))
+        }
+    }
+}
+
+fn coordinate_key(coordinate: &Coordinate) -> CoordinateKey {
+    (
+        coordinate.namespace.to_string(),
+        coordinate.remote.clone(),
+        coordinate.reference.clone(),
+    )
+}
+
+fn coordinate_repository_key(coordinate: &Coordinate) -> RepositoryKey {
+    (coordinate.namespace.to_string(), coordinate.remote.clone())
+}
+
+/// Move the value stored for a repository to its new key, unless a value is already stored under the new key.
+fn rename_key<V>(map: &mut BTreeMap<RepositoryKey, V>, from: &RepositoryKey, to: &RepositoryKey) {
+    if let Some(value) = map.remove(from) {
+        map.entry(to.clone()).or_insert(value);
+    }
+}
+
+#[async_trait]
+impl super::Database for Database {
+    async fn healthcheck(&self) -> Result<(), super::Error> {
+        Ok(())
+    }
+
+    async fn checkpoint(&self) -> Result<(), super::Error> {
+        Ok(())
+    }
+
+    async fn integrity_check(&self) -> Result<Vec<String>, super::Error> {
+        Ok(vec![String::from("ok")])
+    }
+
+    async fn broker_version(&self) -> Result<Option<Version>, super::Error> {
+        self.storage().broker_version.clone().wrap_ok()
+    }
+
+    async fn claim_broker_version(&self) -> Result<(), super::Error> {
+        let current_version = crate_version();
+        let mut storage = self.storage();
+        match &storage.broker_version {
+            Some(db_version) if current_version < db_version => {
+                super::outdated(current_version, db_version)
+            }
+            _ => {
+                storage.broker_version = Some(current_version.clone());
+                Ok(())
+            }
+        }
+    }
+
+    async fn info(&self) -> Result<DatabaseInfo, super::Error> {
+        let broker_version = self.storage().broker_version.clone();
+        DatabaseInfo::new(None, broker_version, 0).wrap_ok()
+    }
+
+    async fn state(&self, coordinate: &Coordinate) -> Result<Option<Vec<u8>>, super::Error> {
+        self.storage()
+            .states
+            .get(&coordinate_key(coordinate))
+            .map(|state| state.state.clone())
+            .wrap_ok()
+    }
+
+    async fn states_for(
+        &self,
+        coordinates: &[Coordinate],
+    ) -> Result<Vec<Option<Vec<u8>>>, super::Error> {
+        let storage = self.storage();
+        coordinates
+            .iter()
+            .map(|coordinate| {
+                storage
+                    .states
+                    .get(&coordinate_key(coordinate))
+                    .map(|state| state.state.clone())
+            })
+            .collect::<Vec<_>>()
+            .wrap_ok()
+    }
+
+    async fn set_state(
+        &self,
+        coordinate: &Coordinate,
+        state: &[u8],
+        is_branch: &bool,
+    ) -> Result<(), super::Error> {
+        // Like the sqlite implementation, updating a state doesn't change whether it's a branch.
+        self.storage()
+            .states
+            .entry(coordinate_key(coordinate))
+            .and_modify(|existing| existing.state = state.to_vec())
+            .or_insert_with(|| State {
+                state: state.to_vec(),
+                is_branch: *is_branch,
+            });
+        Ok(())
+    }
+
+    async fn delete_state(&self, coordinate: &Coordinate) -> Result<(), super::Error> {
+        self.storage().states.remove(&coordinate_key(coordinate));
+        Ok(())
+    }
+
+    async fn delete_states(&self, repository: &str, is_branch: bool) -> Result<(), super::Error> {
+        self.storage()
+            .states
+            .retain(|(_, remote, _), state| remote != repository || state.is_branch != is_branch);
+        Ok(())
+    }
+
+    async fn references_hash(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<Vec<u8>>, super::Error> {
+        self.storage()
+            .references_hashes
+            .get(&repository_key(namespace, repository))
+            .cloned()
+            .wrap_ok()
+    }
+
+    async fn set_references_hash(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        hash: &[u8],
+    ) -> Result<(), super::Error> {
+        self.storage()
+            .references_hashes
+            .insert(repository_key(namespace, repository), hash.to_vec());
+        Ok(())
+    }
+
+    async fn reset_states(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        revision_prefix: Option<&str>,
+    ) -> Result<u64, super::Error> {
+        let (namespace, repository) = repository_key(namespace, repository);
+        let prefix = revision_prefix.unwrap_or_default();
+
+        let mut storage = self.storage();
+        let before = storage.states.len();
+        storage.states.retain(|(ns, remote, revision), _| {
+            !(ns == &namespace && remote == &repository && revision.starts_with(prefix))
+        });
+        u64::try_from(before - storage.states.len())
+            .unwrap_or(u64::MAX)
+            .wrap_ok()
+    }
+
+    async fn delete_references_hash(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<(), super::Error> {
+        self.storage()
+            .references_hashes
+            .remove(&repository_key(namespace, repository));
+        Ok(())
+    }
+
+    async fn rename_repository(
+        &self,
+        namespace: &Namespace,
+        from: &str,
+        to: &str,
+    ) -> Result<u64, super::Error> {
+        let from_key = repository_key(namespace, from);
+        let to_key = repository_key(namespace, to);
+        let mut storage = self.storage();
+
+        // States which would conflict with a state already stored under the new name are dropped.
+        let (renamed, kept): (BTreeMap<_, _>, BTreeMap<_, _>) = std::mem::take(&mut storage.states)
+            .into_iter()
+            .partition(|((ns, remote, _), _)| ns == &from_key.0 && remote == &from_key.1);
+        storage.states = kept;
+        let mut moved = 0u64;
+        for ((ns, _, revision), state) in renamed {
+            let key = (ns, to.to_string(), revision);
+            if !storage.states.contains_key(&key) {
+                storage.states.insert(key, state);
+                moved += 1;
+            }
+        }
+
+        storage.references_hashes.remove(&from_key);
+        for scan in storage.scans.iter_mut() {
+            if scan.repository == from_key {
+                scan.repository = to_key.clone();
+            }
+        }
+        for recorded in storage.branch_history.iter_mut() {
+            if recorded.repository == from_key {
+                recorded.repository = to_key.clone();
+            }
+        }
+        for upload in storage.pending_uploads.values_mut() {
+            if coordinate_repository_key(upload.coordinate()) == from_key {
+                upload.coordinate.remote = to.to_string();
+            }
+        }
+        for job in storage.queued_jobs.values_mut() {
+            if coordinate_repository_key(job.coordinate()) == from_key {
+                job.coordinate.remote = to.to_string();
+            }
+        }
+        rename_key(&mut storage.project_mappings, &from_key, &to_key);
+        rename_key(&mut storage.backlogs, &from_key, &to_key);
+        rename_key(&mut storage.polls, &from_key, &to_key);
+        rename_key(&mut storage.connectivity, &from_key, &to_key);
+        if let Some(paused_at) = storage.pauses.remove(&Some(from_key.clone())) {
+            storage
+                .pauses
+                .entry(Some(to_key.clone()))
+                .or_insert(paused_at);
+        }
+
+        // A lease that's still live keeps protecting the reference under its new name until it expires.
+        let (renamed, kept): (BTreeMap<_, _>, BTreeMap<_, _>) =
+            std::mem::take(&mut storage.scan_leases)
+                .into_iter()
+                .partition(|((ns, remote, _), _)| ns == &from_key.0 && remote == &from_key.1);
+        storage.scan_leases = kept;
+        for ((ns, _, revision), lease) in renamed {
+            storage
+                .scan_leases
+                .entry((ns, to.to_string(), revision))
+                .or_insert(lease);
+        }
+
+        Ok(moved)
+    }
+
+    async fn record_scan(
+        &self,
+        coordinate: &Coordinate,
+        scan: &ScanRecord,
+    ) -> Result<(), super::Error> {
+        self.storage().scans.push(RecordedScan {
+            repository: coordinate_repository_key(coordinate),
+            revision: coordinate.reference.clone(),
+            recorded_at: SystemTime::now(),
+            locator: None,
+            record: scan.clone(),
+        });
+        Ok(())
+    }
+
+    async fn record_branch_state(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        branch: &str,
+        state: &[u8],
+    ) -> Result<(), super::Error> {
+        self.storage().branch_history.push(RecordedBranchState {
+            repository: repository_key(namespace, repository),
+            branch: branch.to_string(),
+            state: state.to_vec(),
+        });
+        Ok(())
+    }
+
+    async fn record_poll(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        now: SystemTime,
+    ) -> Result<(), super::Error> {
+        self.storage()
+            .polls
+            .insert(repository_key(namespace, repository), now);
+        Ok(())
+    }
+
+    async fn last_poll(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<SystemTime>, super::Error> {
+        self.storage()
+            .polls
+            .get(&repository_key(namespace, repository))
+            .copied()
+            .wrap_ok()
+    }
+
+    async fn record_connectivity(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        reachable: bool,
+        now: SystemTime,
+    ) -> Result<(), super::Error> {
+        self.storage().connectivity.insert(
+            repository_key(namespace, repository),
+            Connectivity::new(reachable, now),
+        );
+        Ok(())
+    }
+
+    async fn connectivity(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<Connectivity>, super::Error> {
+        self.storage()
+            .connectivity
+            .get(&repository_key(namespace, repository))
+            .copied()
+            .wrap_ok()
+    }
+
+    async fn last_branch_state(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        branch: &str,
+    ) -> Result<Option<Vec<u8>>, super::Error> {
+        let key = repository_key(namespace, repository);
+        self.storage()
+            .branch_history
+            .iter()
+            .rev()
+            .find(|recorded| recorded.repository == key && recorded.branch == branch)
+            .map(|recorded| recorded.state.clone())
+            .wrap_ok()
+    }
+
+    async fn set_scan_policy(
+        &self,
+        scan_id: &str,
+        policy: PolicyStatus,
+    ) -> Result<(), super::Error> {
+        for scan in self.storage().scans.iter_mut() {
+            if scan.record.scan_id() == scan_id {
+                scan.record = scan.record.clone().with_policy(Some(policy));
+            }
+        }
+        Ok(())
+    }
+
+    async fn set_scan_locator(&self, scan_id: &str, locator: &str) -> Result<(), super::Error> {
+        for scan in self.storage().scans.iter_mut() {
+            if scan.record.scan_id() == scan_id {
+                scan.locator = Some(locator.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    async fn scans_since(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        since: SystemTime,
+    ) -> Result<Vec<HistoricScan>, super::Error> {
+        let key = repository_key(namespace, repository);
+        self.storage()
+            .scans
+            .iter()
+            .rev()
+            .filter(|scan| scan.repository == key && scan.recorded_at >= since)
+            .map(|scan| {
+                HistoricScan::new(
+                    scan.revision.clone(),
+                    scan.recorded_at,
+                    scan.locator.clone(),
+                    scan.record.clone(),
+                )
+            })
+            .collect::<Vec<_>>()
+            .wrap_ok()
+    }
+
+    async fn recent_scans(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        limit: u32,
+    ) -> Result<Vec<ScanRecord>, super::Error> {
+        let key = repository_key(namespace, repository);
+        let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+        self.storage()
+            .scans
+            .iter()
+            .rev()
+            .filter(|scan| scan.repository == key)
+            .take(limit)
+            .map(|scan| scan.record.clone())
+            .collect::<Vec<_>>()
+            .wrap_ok()
+    }
+
+    async fn project_mapping(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<ProjectMapping>, super::Error> {
+        self.storage()
+            .project_mappings
+            .get(&repository_key(namespace, repository))
+            .cloned()
+            .wrap_ok()
+    }
+
+    async fn set_project_mapping(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        mapping: &ProjectMapping,
+    ) -> Result<(), super::Error> {
+        self.storage()
+            .project_mappings
+            .insert(repository_key(namespace, repository), mapping.clone());
+        Ok(())
+    }
+
+    async fn pending_upload(
+        &self,
+        coordinate: &Coordinate,
+        scan_id: &str,
+    ) -> Result<Option<PendingUpload>, super::Error> {
+        let key = coordinate_repository_key(coordinate);
+        self.storage()
+            .pending_uploads
+            .get(scan_id)
+            .filter(|upload| coordinate_repository_key(upload.coordinate()) == key)
+            .cloned()
+            .wrap_ok()
+    }
+
+    async fn due_pending_uploads(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        now: SystemTime,
+    ) -> Result<Vec<PendingUpload>, super::Error> {
+        let mut due = self.pending_uploads(namespace, repository).await?;
+        due.retain(|upload| upload.next_attempt_at() <= now);
+        Ok(due)
+    }
+
+    async fn set_pending_upload(&self, upload: &PendingUpload) -> Result<(), super::Error> {
+        // Like the sqlite implementation, updating a pending upload only changes its schedule.
+        self.storage()
+            .pending_uploads
+            .entry(upload.scan_id().clone())
+            .and_modify(|existing| {
+                existing.attempts = upload.attempts();
+                existing.next_attempt_at = upload.next_attempt_at();
+            })
+            .or_insert_with(|| upload.clone());
+        Ok(())
+    }
+
+    async fn delete_pending_upload(&self, scan_id: &str) -> Result<(), super::Error> {
+        self.storage().pending_uploads.remove(scan_id);
+        Ok(())
+    }
+
+    async fn pending_uploads(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Vec<PendingUpload>, super::Error> {
+        let key = repository_key(namespace, repository);
+        let mut uploads = self
+            .storage()
+            .pending_uploads
+            .values()
+            .filter(|upload| coordinate_repository_key(upload.coordinate()) == key)
+            .cloned()
+            .collect::<Vec<_>>();
+        uploads.sort_by_key(|upload| upload.first_failed_at());
+        Ok(uploads)
+    }
+
+    async fn set_queued_job(&self, job: &QueuedJob) -> Result<(), super::Error> {
+        // Like the sqlite implementation, updating a job only changes its stage and payload.
+        self.storage()
+            .queued_jobs
+            .entry(job.scan_id().clone())
+            .and_modify(|existing| {
+                existing.stage = job.stage();
+                existing.enqueued_at = job.enqueued_at();
+                existing.payload = job.payload().clone();
+            })
+            .or_insert_with(|| job.clone());
+        Ok(())
+    }
+
+    async fn delete_queued_job(&self, scan_id: &str) -> Result<(), super::Error> {
+        self.storage().queued_jobs.remove(scan_id);
+        Ok(())
+    }
+
+    async fn queued_jobs(&self) -> Result<Vec<QueuedJob>, super::Error> {
+        let mut jobs = self
+            .storage()
+            .queued_jobs
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        jobs.sort_by_key(|job| job.enqueued_at());
+        Ok(jobs)
+    }
+
+    async fn acquire_scan_lease(
+        &self,
+        coordinate: &Coordinate,
+        holder: &str,
+        now: SystemTime,
+        expires_at: SystemTime,
+    ) -> Result<bool, super::Error> {
+        let mut storage = self.storage();
+        let key = coordinate_key(coordinate);
+        if let Some(lease) = storage.scan_leases.get(&key) {
+            if lease.holder != holder && lease.expires_at > now {
+                return Ok(false);
+            }
+        }
+        let lease = ScanLease {
+            holder: holder.to_string(),
+            expires_at,
+        };
+        storage.scan_leases.insert(key, lease);
+        Ok(true)
+    }
+
+    async fn release_scan_lease(
+        &self,
+        coordinate: &Coordinate,
+        holder: &str,
+    ) -> Result<(), super::Error> {
+        let mut storage = self.storage();
+        let key = coordinate_key(coordinate);
+        if matches!(storage.scan_leases.get(&key), Some(lease) if lease.holder == holder) {
+            storage.scan_leases.remove(&key);
+        }
+        Ok(())
+    }
+
+    async fn backlog(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<Backlog>, super::Error> {
+        self.storage()
+            .backlogs
+            .get(&repository_key(namespace, repository))
+            .cloned()
+            .wrap_ok()
+    }
+
+    async fn enqueue_backlog(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        count: u64,
+        now: SystemTime,
+    ) -> Result<(), super::Error> {
+        self.storage()
+            .backlogs
+            .entry(repository_key(namespace, repository))
+            .and_modify(|backlog| {
+                if backlog.is_complete() {
+                    *backlog = Backlog::new(count, 0, now, now);
+                } else {
+                    backlog.total = backlog.total.saturating_add(count);
+                    backlog.updated_at = now;
+                }
+            })
+            .or_insert_with(|| Backlog::new(count, 0, now, now));
+        Ok(())
+    }
+
+    async fn abandon_backlogs(&self) -> Result<u64, super::Error> {
+        let mut abandoned = 0;
+        for backlog in self.storage().backlogs.values_mut() {
+            if !backlog.is_complete() {
+                backlog.completed = backlog.total;
+                abandoned += 1;
+            }
+        }
+        Ok(abandoned)
+    }
+
+    async fn progress_backlog(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        now: SystemTime,
+    ) -> Result<(), super::Error> {
+        if let Some(backlog) = self
+            .storage()
+            .backlogs
+            .get_mut(&repository_key(namespace, repository))
+        {
+            backlog.completed = backlog.completed.saturating_add(1).min(backlog.total);
+            backlog.updated_at = now;
+        }
+        Ok(())
+    }
+
+    async fn pause(&self, scope: &PauseScope, now: SystemTime) -> Result<(), super::Error> {
+        self.storage().pauses.entry(pause_key(scope)).or_insert(now);
+        Ok(())
+    }
+
+    async fn resume(&self, scope: &PauseScope) -> Result<u64, super::Error> {
+        let mut storage = self.storage();
+        let resumed = match scope {
+            PauseScope::All => std::mem::take(&mut storage.pauses).len(),
+            PauseScope::Repository(..) => {
+                usize::from(storage.pauses.remove(&pause_key(scope)).is_some())
+            }
+        };
+        Ok(u64::try_from(resumed).unwrap_or(u64::MAX))
+    }
+
+    async fn is_paused(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<bool, super::Error> {
+        let storage = self.storage();
+        let paused = storage.pauses.contains_key(&None)
+            || storage
+                .pauses
+                .contains_key(&Some(repository_key(namespace, repository)));
+        Ok(paused)
+    }
+}
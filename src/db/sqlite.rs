@@ -22,13 +22,17 @@
 //! - 0.7x tracking issue: https://github.com/launchbadge/sqlx/issues/1163
 
 use std::{
+    collections::HashMap,
     fmt::Debug,
+    future::Future,
     path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use async_trait::async_trait;
 use derive_new::new;
-use error_stack::{report, Result, ResultExt};
+use error_stack::{report, Report, Result, ResultExt};
 use indoc::indoc;
 use semver::Version;
 use sqlx::{
@@ -38,17 +42,22 @@ use sqlx::{
 };
 use tap::TapFallible;
 use thiserror::Error;
+use tracing::debug;
 
 use crate::{
+    clock::Clock,
     doc::{crate_name, crate_version},
     ext::{
         error_stack::{DescribeContext, ErrorHelper, IntoContext},
-        result::{DiscardResult, WrapErr},
+        result::{DiscardResult, WrapErr, WrapOk},
         tracing::{span_record, span_records},
     },
 };
 
-use super::Coordinate;
+use super::{
+    Backlog, Connectivity, Coordinate, DatabaseInfo, HistoricScan, JobStage, Namespace, PauseScope,
+    PendingUpload, PolicyStatus, ProjectMapping, QueuedJob, ScanRecord,
+};
 
 /// Errors interacting with sqlite.
 #[derive(Debug, Error)]
@@ -79,6 +88,10 @@ pub enum Error {
 pub struct Database {
     location: PathBuf,
     internal: SqlitePool,
+
+    /// Waited on between attempts of statements while the database is busy.
+    #[new(default)]
+    clock: Clock,
 }
 
 impl Debug for Database {
@@ -93,11 +106,12 @@ impl Debug for Database {
 impl Database {
     /// Connect to the database.
     #[tracing::instrument(fields(options))]
-    pub async fn connect(location: &Path) -> Result<Self, Error> {
+    pub async fn connect(location: &Path, config: super::Config) -> Result<Self, Error> {
         let options = SqliteConnectOptions::new()
             .filename(location)
             .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
             .synchronous(sqlx::sqlite::SqliteSynchronous::Normal)
+            .busy_timeout(config.busy_timeout())
             .create_if_missing(true);
 
         span_record!(options, debug options);
@@ -109,8 +123,15 @@ impl Database {
             .context(Error::Connect)
             .describe_lazy(|| format!("attempted to open sqlite db at '{}'", location.display()))?;
 
-        let db = Self::new(location.to_path_buf(), db).migrate().await?;
+        // Check the version before migrating: if a newer version of Broker applied migrations this version doesn't know about,
+        // migrating fails with an error that doesn't explain the actual problem.
+        let db = Self::new(location.to_path_buf(), db);
+        db.ensure_not_outdated()
+            .await
+            .change_context(Error::Connect)
+            .describe("during initial connection, Broker validates that it's the latest version connecting to the DB")?;
 
+        let db = db.migrate().await?;
         super::Database::claim_broker_version(&db)
             .await
             .change_context(Error::Connect)
@@ -119,6 +140,69 @@ impl Database {
         Ok(db)
     }
 
+    /// Wait on the provided clock between attempts of statements while the database is busy,
+    /// instead of the system clock.
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Open an existing database without migrating it or claiming it for the current version of Broker.
+    #[tracing::instrument]
+    pub async fn open_read_only(location: &Path) -> Result<Self, Error> {
+        let options = SqliteConnectOptions::new()
+            .filename(location)
+            .read_only(true);
+
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .context(Error::Connect)
+            .describe_lazy(|| format!("attempted to open sqlite db at '{}'", location.display()))
+            .map(|db| Self::new(location.to_path_buf(), db))
+    }
+
+    /// Refuse to use a database last claimed by a newer version of Broker.
+    ///
+    /// Databases which were never claimed, including new databases, are accepted.
+    #[tracing::instrument]
+    async fn ensure_not_outdated(&self) -> Result<(), super::Error> {
+        if !self
+            .has_table("broker_version")
+            .await
+            .change_context(super::Error::Interact)?
+        {
+            return Ok(());
+        }
+
+        let current_version = crate_version();
+        match super::Database::broker_version(self).await? {
+            Some(db_version) if current_version < &db_version => {
+                super::outdated(current_version, &db_version)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether a table with the provided name exists.
+    ///
+    /// This can't use the `query!` macros: the table being checked may not exist in the canonical database.
+    #[tracing::instrument(fields(exists))]
+    async fn has_table(&self, name: &str) -> Result<bool, Error> {
+        self.retry_busy(|| {
+            sqlx::query_scalar::<_, i64>(
+                "select count(*) from sqlite_master where type = 'table' and name = ?",
+            )
+            .bind(name)
+            .fetch_one(&self.internal)
+        })
+        .await
+        .map(|count| count > 0)
+        .tap_ok(|exists| span_record!(exists, exists))
+        .context(Error::Communication)
+    }
+
     /// Migrate the database.
     #[tracing::instrument]
     async fn migrate(self) -> Result<Self, Error> {
@@ -139,19 +223,197 @@ impl Database {
     async fn update_db_version(&self, version: &Version) -> Result<(), Error> {
         let name = crate_name();
         let version = version.to_string();
-        query!(
-            r#"
+        self.retry_busy(|| {
+            query!(
+                r#"
             insert into broker_version values (?, ?)
             on conflict do update set version = excluded.version
             "#,
-            name,
-            version
-        )
-        .execute(&self.internal)
+                name,
+                version
+            )
+            .execute(&self.internal)
+        })
         .await
         .map(|result| span_record!(result, debug result))
         .context(Error::Communication)
     }
+
+    /// Move everything stored for the repository to its new name in a single transaction,
+    /// returning the number of states moved.
+    ///
+    /// Rows keyed by the repository alone, which conflict with a row already stored under the new name,
+    /// are dropped in favor of the existing row.
+    async fn rename_repository_tx(
+        &self,
+        integration: &str,
+        from: &str,
+        to: &str,
+    ) -> std::result::Result<u64, sqlx::Error> {
+        let mut tx = self.internal.begin().await?;
+
+        let moved = query!(
+            "update or ignore repo_state set repository = ? where integration = ? and repository = ?",
+            to,
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        query!(
+            "delete from repo_state where integration = ? and repository = ?",
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            "delete from references_hash where integration = ? and repository = ?",
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            "update scan_history set repository = ? where integration = ? and repository = ?",
+            to,
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            "update branch_history set repository = ? where integration = ? and repository = ?",
+            to,
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            "update pending_upload set repository = ? where integration = ? and repository = ?",
+            to,
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            "update queued_job set repository = ? where integration = ? and repository = ?",
+            to,
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            "update or ignore project_mapping set repository = ? where integration = ? and repository = ?",
+            to,
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+        query!(
+            "delete from project_mapping where integration = ? and repository = ?",
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            "update or ignore backlog set repository = ? where integration = ? and repository = ?",
+            to,
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+        query!(
+            "delete from backlog where integration = ? and repository = ?",
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            "update or ignore integration_poll set repository = ? where integration = ? and repository = ?",
+            to,
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+        query!(
+            "delete from integration_poll where integration = ? and repository = ?",
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            "update or ignore integration_pause set repository = ? where integration = ? and repository = ?",
+            to,
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+        query!(
+            "delete from integration_pause where integration = ? and repository = ?",
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            "update or ignore integration_connectivity set repository = ? where integration = ? and repository = ?",
+            to,
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+        query!(
+            "delete from integration_connectivity where integration = ? and repository = ?",
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        // A lease that's still live keeps protecting the reference under its new name until it expires,
+        // so that it isn't scanned a second time while the scan under the old name finishes.
+        query!(
+            "update or ignore scan_lease set repository = ? where integration = ? and repository = ?",
+            to,
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+        query!(
+            "delete from scan_lease where integration = ? and repository = ?",
+            integration,
+            from,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(moved)
+    }
 }
 
 #[derive(Debug)]
@@ -164,6 +426,142 @@ struct RepoStateRow {
     repo_state: Vec<u8>,
 }
 
+#[derive(Debug)]
+struct CoordinateStateRow {
+    integration: String,
+    repository: String,
+    revision: String,
+    repo_state: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct ReferencesHashRow {
+    hash: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct ScanHistoryRow {
+    scan_id: String,
+    clone_ms: i64,
+    analyze_ms: i64,
+    policy_status: Option<String>,
+}
+
+#[derive(Debug)]
+struct HistoricScanRow {
+    revision: String,
+    recorded_at: i64,
+    locator: Option<String>,
+    scan_id: String,
+    clone_ms: i64,
+    analyze_ms: i64,
+    policy_status: Option<String>,
+}
+
+#[derive(Debug)]
+struct PendingUploadRow {
+    scan_id: String,
+    revision: String,
+    attempts: i64,
+    first_failed_at: i64,
+    next_attempt_at: i64,
+}
+
+impl PendingUploadRow {
+    fn into_pending(self, namespace: Namespace, repository: &str) -> PendingUpload {
+        PendingUpload::new(
+            self.scan_id,
+            Coordinate::new(namespace, repository.to_string(), self.revision),
+            u32::try_from(self.attempts).unwrap_or_default(),
+            from_unix_seconds(self.first_failed_at),
+            from_unix_seconds(self.next_attempt_at),
+        )
+    }
+}
+
+#[derive(Debug)]
+struct QueuedJobRow {
+    scan_id: String,
+    stage: String,
+    integration: String,
+    repository: String,
+    revision: String,
+    reference: String,
+    repo_state: Vec<u8>,
+    is_branch: i64,
+    enqueued_at: i64,
+    payload: Option<Vec<u8>>,
+}
+
+impl TryFrom<QueuedJobRow> for QueuedJob {
+    type Error = Report<Error>;
+
+    fn try_from(row: QueuedJobRow) -> std::result::Result<Self, Self::Error> {
+        let stage = JobStage::from_str(&row.stage)
+            .context(Error::Parse)
+            .describe_lazy(|| format!("parse job stage: '{}'", row.stage))?;
+        let namespace = Namespace::from_str(&row.integration)
+            .context(Error::Parse)
+            .describe_lazy(|| format!("parse namespace: '{}'", row.integration))?;
+        Ok(Self::new(
+            row.scan_id,
+            stage,
+            Coordinate::new(namespace, row.repository, row.revision),
+            row.reference,
+            row.repo_state,
+            row.is_branch != 0,
+            from_unix_seconds(row.enqueued_at),
+            row.payload,
+        ))
+    }
+}
+
+#[derive(Debug)]
+struct BacklogRow {
+    total: i64,
+    completed: i64,
+    started_at: i64,
+    updated_at: i64,
+}
+
+impl From<BacklogRow> for Backlog {
+    fn from(row: BacklogRow) -> Self {
+        Self::new(
+            u64::try_from(row.total).unwrap_or_default(),
+            u64::try_from(row.completed).unwrap_or_default(),
+            from_unix_seconds(row.started_at),
+            from_unix_seconds(row.updated_at),
+        )
+    }
+}
+
+impl From<ScanHistoryRow> for ScanRecord {
+    fn from(row: ScanHistoryRow) -> Self {
+        let millis = |ms: i64| Duration::from_millis(u64::try_from(ms).unwrap_or_default());
+        let policy = row
+            .policy_status
+            .and_then(|status| PolicyStatus::from_str(&status).ok());
+        Self::new(row.scan_id, millis(row.clone_ms), millis(row.analyze_ms)).with_policy(policy)
+    }
+}
+
+impl From<HistoricScanRow> for HistoricScan {
+    fn from(row: HistoricScanRow) -> Self {
+        let record = ScanRecord::from(ScanHistoryRow {
+            scan_id: row.scan_id,
+            clone_ms: row.clone_ms,
+            analyze_ms: row.analyze_ms,
+            policy_status: row.policy_status,
+        });
+        Self::new(
+            row.revision,
+            from_unix_seconds(row.recorded_at),
+            row.locator,
+            record,
+        )
+    }
+}
+
 #[async_trait]
 impl super::Database for Database {
     #[tracing::instrument]
@@ -171,15 +569,53 @@ impl super::Database for Database {
         self.broker_version().await.discard_ok()
     }
 
+    #[tracing::instrument(fields(busy, log_frames, checkpointed_frames))]
+    async fn checkpoint(&self) -> Result<(), super::Error> {
+        // Pragmas aren't tables in the canonical database, so this can't use the `query!` macros.
+        // Truncating the log once it's checkpointed keeps it from staying at its largest size.
+        let (busy, log_frames, checkpointed_frames) = self
+            .retry_busy(|| {
+                sqlx::query_as::<_, (i64, i64, i64)>("pragma wal_checkpoint(truncate)")
+                    .fetch_one(&self.internal)
+            })
+            .await
+            .context(Error::Communication)
+            .change_context(super::Error::Interact)?;
+
+        span_records! {
+            busy => busy;
+            log_frames => log_frames;
+            checkpointed_frames => checkpointed_frames;
+        };
+        if busy != 0 {
+            debug!("Checkpointed {checkpointed_frames} of {log_frames} frames; the rest are in use by other connections");
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(fields(problems))]
+    async fn integrity_check(&self) -> Result<Vec<String>, super::Error> {
+        // Pragmas aren't tables in the canonical database, so this can't use the `query!` macros.
+        self.retry_busy(|| {
+            sqlx::query_scalar::<_, String>("pragma integrity_check").fetch_all(&self.internal)
+        })
+        .await
+        .tap_ok(|rows| span_record!(problems, debug rows))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
     #[tracing::instrument(fields(read, parsed))]
     async fn broker_version(&self) -> Result<Option<Version>, super::Error> {
         let name = crate_name();
-        query_as!(
-            BrokerVersionRow,
-            "select version from broker_version where name = ? limit 1",
-            name
-        )
-        .fetch_optional(&self.internal)
+        self.retry_busy(|| {
+            query_as!(
+                BrokerVersionRow,
+                "select version from broker_version where name = ? limit 1",
+                name
+            )
+            .fetch_optional(&self.internal)
+        })
         .await
         .tap_ok(|raw| span_record!(read, debug raw))
         .context(Error::Communication)
@@ -208,14 +644,7 @@ impl super::Database for Database {
                 .await
                 .change_context(super::Error::Interact),
             Some(db_version) if current_version < db_version => {
-                report!(super::Error::BrokerOutdated)
-                    .wrap_err()
-                    .describe(indoc! {"
-                        Broker stores the last used version in the DB to ensure
-                        that older versions of Broker cannot break invariants added in newer
-                        versions of Broker.
-                        "})
-                    .help("try again with the latest version of Broker")
+                super::outdated(&current_version, &db_version)
             }
             Some(db_version) if current_version > db_version => self
                 .update_db_version(&current_version)
@@ -225,17 +654,69 @@ impl super::Database for Database {
         }
     }
 
+    #[tracing::instrument(fields(schema_version, size))]
+    async fn info(&self) -> Result<DatabaseInfo, super::Error> {
+        // Migrations are tracked by sqlx, so these tables aren't in the canonical database used by the `query!` macros.
+        let schema_version = if self
+            .has_table("_sqlx_migrations")
+            .await
+            .change_context(super::Error::Interact)?
+        {
+            self.retry_busy(|| {
+                sqlx::query_scalar::<_, Option<i64>>(
+                    "select max(version) from _sqlx_migrations where success = 1",
+                )
+                .fetch_one(&self.internal)
+            })
+            .await
+            .context(Error::Communication)
+            .change_context(super::Error::Interact)?
+        } else {
+            None
+        };
+
+        let broker_version = if self
+            .has_table("broker_version")
+            .await
+            .change_context(super::Error::Interact)?
+        {
+            self.broker_version().await?
+        } else {
+            None
+        };
+
+        let size = self
+            .retry_busy(|| {
+                sqlx::query_scalar::<_, i64>(
+                    "select page_count * page_size from pragma_page_count(), pragma_page_size()",
+                )
+                .fetch_one(&self.internal)
+            })
+            .await
+            .context(Error::Communication)
+            .change_context(super::Error::Interact)?;
+        let size = u64::try_from(size).unwrap_or_default();
+
+        span_records! {
+            schema_version => debug schema_version;
+            size => size;
+        };
+        Ok(DatabaseInfo::new(schema_version, broker_version, size))
+    }
+
     #[tracing::instrument(fields(repo_state))]
     async fn state(&self, coordinate: &Coordinate) -> Result<Option<Vec<u8>>, super::Error> {
         let integration = coordinate.namespace.to_string();
-        query_as!(
-            RepoStateRow,
-            "select repo_state from repo_state where integration = ? and repository = ? and revision = ?",
-            integration,
-            coordinate.remote,
-            coordinate.reference
-        )
-        .fetch_optional(&self.internal)
+        self.retry_busy(|| {
+            query_as!(
+                RepoStateRow,
+                "select repo_state from repo_state where integration = ? and repository = ? and revision = ?",
+                integration,
+                coordinate.remote,
+                coordinate.reference
+            )
+            .fetch_optional(&self.internal)
+        })
         .await
         .tap_ok(|raw| span_record!(repo_state, debug raw))
         .context(Error::Communication)
@@ -243,6 +724,69 @@ impl super::Database for Database {
         .map(|result| result.map(|row| row.repo_state))
     }
 
+    #[tracing::instrument(skip(coordinates), fields(count = coordinates.len(), found))]
+    async fn states_for(
+        &self,
+        coordinates: &[Coordinate],
+    ) -> Result<Vec<Option<Vec<u8>>>, super::Error> {
+        // sqlx can't bind a list of values, so instead the coordinates are sent as a JSON array
+        // and expanded into rows by sqlite.
+        let keys = coordinates
+            .iter()
+            .map(|c| (c.namespace.to_string(), &c.remote, &c.reference))
+            .collect::<Vec<_>>();
+        let keys = serde_json::to_string(&keys)
+            .context(Error::Serialize)
+            .change_context(super::Error::Interact)?;
+
+        let rows = self
+            .retry_busy(|| {
+                query_as!(
+                    CoordinateStateRow,
+                    r#"
+            select
+              r.integration as "integration!",
+              r.repository as "repository!",
+              r.revision as "revision!",
+              r.repo_state as "repo_state!"
+            from repo_state r
+            join json_each(?) k
+              on r.integration = json_extract(k.value, '$[0]')
+              and r.repository = json_extract(k.value, '$[1]')
+              and r.revision = json_extract(k.value, '$[2]')
+            "#,
+                    keys
+                )
+                .fetch_all(&self.internal)
+            })
+            .await
+            .tap_ok(|rows| span_record!(found, rows.len()))
+            .context(Error::Communication)
+            .change_context(super::Error::Interact)?;
+
+        let states = rows
+            .into_iter()
+            .map(|row| {
+                (
+                    (row.integration, row.repository, row.revision),
+                    row.repo_state,
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        coordinates
+            .iter()
+            .map(|c| {
+                let key = (
+                    c.namespace.to_string(),
+                    c.remote.clone(),
+                    c.reference.clone(),
+                );
+                states.get(&key).cloned()
+            })
+            .collect::<Vec<_>>()
+            .wrap_ok()
+    }
+
     #[tracing::instrument(fields(result))]
     async fn set_state(
         &self,
@@ -251,18 +795,38 @@ impl super::Database for Database {
         is_branch: &bool,
     ) -> Result<(), super::Error> {
         let integration = coordinate.namespace.to_string();
-        query!(
-            r#"
+        self.retry_busy(|| {
+            query!(
+                r#"
             insert into repo_state values (?, ?, ?, ?, ?)
             on conflict do update set repo_state = excluded.repo_state
             "#,
-            integration,
-            coordinate.remote,
-            coordinate.reference,
-            state,
-            is_branch,
-        )
-        .execute(&self.internal)
+                integration,
+                coordinate.remote,
+                coordinate.reference,
+                state,
+                is_branch,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn delete_state(&self, coordinate: &Coordinate) -> Result<(), super::Error> {
+        let integration = coordinate.namespace.to_string();
+        self.retry_busy(|| {
+            query!(
+                "delete from repo_state where integration = ? and repository = ? and revision = ?",
+                integration,
+                coordinate.remote,
+                coordinate.reference,
+            )
+            .execute(&self.internal)
+        })
         .await
         .map(|result| span_record!(result, debug result))
         .context(Error::Communication)
@@ -271,57 +835,1068 @@ impl super::Database for Database {
 
     #[tracing::instrument(fields(result))]
     async fn delete_states(&self, repository: &str, is_branch: bool) -> Result<(), super::Error> {
-        query!(
-            "delete from repo_state where repository = ? and is_branch = ? ",
-            repository,
-            is_branch,
-        )
-        .execute(&self.internal)
+        self.retry_busy(|| {
+            query!(
+                "delete from repo_state where repository = ? and is_branch = ? ",
+                repository,
+                is_branch,
+            )
+            .execute(&self.internal)
+        })
         .await
         .map(|result| span_record!(result, debug result))
         .context(Error::Communication)
         .change_context(super::Error::Interact)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use tempfile::tempdir;
+    #[tracing::instrument(fields(hash))]
+    async fn references_hash(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<Vec<u8>>, super::Error> {
+        let integration = namespace.to_string();
+        self.retry_busy(|| {
+            query_as!(
+                ReferencesHashRow,
+                "select hash from references_hash where integration = ? and repository = ?",
+                integration,
+                repository
+            )
+            .fetch_optional(&self.internal)
+        })
+        .await
+        .tap_ok(|raw| span_record!(hash, debug raw))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+        .map(|result| result.map(|row| row.hash))
+    }
 
-    use super::*;
+    #[tracing::instrument(fields(result))]
+    async fn set_references_hash(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        hash: &[u8],
+    ) -> Result<(), super::Error> {
+        let integration = namespace.to_string();
+        self.retry_busy(|| {
+            query!(
+                r#"
+            insert into references_hash values (?, ?, ?)
+            on conflict do update set hash = excluded.hash
+            "#,
+                integration,
+                repository,
+                hash,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
 
-    macro_rules! temp_db {
-        () => {{
-            let tmp = tempdir().expect("must create temporary directory");
-            let db = super::Database::connect(&tmp.path().join("test.db"))
+    #[tracing::instrument(fields(result))]
+    async fn reset_states(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        revision_prefix: Option<&str>,
+    ) -> Result<u64, super::Error> {
+        let integration = namespace.to_string();
+        let result = match revision_prefix {
+            Some(prefix) => {
+                self.retry_busy(|| {
+                    query!(
+                        r#"
+                    delete from repo_state
+                    where integration = ? and repository = ? and substr(revision, 1, length(?)) = ?
+                    "#,
+                        integration,
+                        repository,
+                        prefix,
+                        prefix,
+                    )
+                    .execute(&self.internal)
+                })
                 .await
-                .expect("must create db");
-            (tmp, db)
-        }};
-    }
+            }
+            None => {
+                self.retry_busy(|| {
+                    query!(
+                        "delete from repo_state where integration = ? and repository = ?",
+                        integration,
+                        repository,
+                    )
+                    .execute(&self.internal)
+                })
+                .await
+            }
+        };
 
-    #[tokio::test]
-    async fn inserts_version() {
-        let (_tmp, db) = temp_db!();
+        result
+            .tap_ok(|result| span_record!(result, debug result))
+            .context(Error::Communication)
+            .change_context(super::Error::Interact)
+            .map(|result| result.rows_affected())
+    }
 
-        let version = crate_version();
-        let row = query_as!(
-            BrokerVersionRow,
-            "select version from broker_version limit 1"
-        )
-        .fetch_one(&db.internal)
-        .await
-        .expect("must fetch version");
+    #[tracing::instrument(fields(result))]
+    async fn rename_repository(
+        &self,
+        namespace: &Namespace,
+        from: &str,
+        to: &str,
+    ) -> Result<u64, super::Error> {
+        let integration = namespace.to_string();
+        let moved = self
+            .retry_busy(|| self.rename_repository_tx(&integration, from, to))
+            .await
+            .context(Error::Communication)
+            .change_context(super::Error::Interact)?;
 
-        assert_eq!(row.version, version.to_string());
+        span_record!(result, moved);
+        Ok(moved)
     }
 
-    #[tokio::test]
-    async fn updates_version() {
-        let (_tmp, db) = temp_db!();
+    #[tracing::instrument(fields(result))]
+    async fn delete_references_hash(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<(), super::Error> {
+        let integration = namespace.to_string();
+        self.retry_busy(|| {
+            query!(
+                "delete from references_hash where integration = ? and repository = ?",
+                integration,
+                repository,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
 
-        let version = crate_version();
-        let version = Version::new(version.major + 1, 0, 0);
+    #[tracing::instrument(fields(result))]
+    async fn record_scan(
+        &self,
+        coordinate: &Coordinate,
+        scan: &ScanRecord,
+    ) -> Result<(), super::Error> {
+        let integration = coordinate.namespace.to_string();
+        let millis = |duration: Duration| i64::try_from(duration.as_millis()).unwrap_or(i64::MAX);
+        let clone_ms = millis(scan.clone_duration());
+        let analyze_ms = millis(scan.analyze_duration());
+        self.retry_busy(|| {
+            query!(
+                r#"
+            insert into scan_history (integration, repository, revision, scan_id, clone_ms, analyze_ms, recorded_at)
+            values (?, ?, ?, ?, ?, ?, cast(strftime('%s', 'now') as integer))
+            "#,
+                integration,
+                coordinate.remote,
+                coordinate.reference,
+                scan.scan_id(),
+                clone_ms,
+                analyze_ms,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(skip(state), fields(result))]
+    async fn record_branch_state(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        branch: &str,
+        state: &[u8],
+    ) -> Result<(), super::Error> {
+        let integration = namespace.to_string();
+        self.retry_busy(|| {
+            query!(
+                r#"
+            insert into branch_history (integration, repository, branch, repo_state, recorded_at)
+            values (?, ?, ?, ?, cast(strftime('%s', 'now') as integer))
+            "#,
+                integration,
+                repository,
+                branch,
+                state,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn record_poll(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        now: SystemTime,
+    ) -> Result<(), super::Error> {
+        let integration = namespace.to_string();
+        let now = unix_seconds(now);
+        self.retry_busy(|| {
+            query!(
+                r#"
+            insert into integration_poll (integration, repository, polled_at)
+            values (?, ?, ?)
+            on conflict do update set polled_at = excluded.polled_at
+            "#,
+                integration,
+                repository,
+                now,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(found))]
+    async fn last_poll(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<SystemTime>, super::Error> {
+        let integration = namespace.to_string();
+        self.retry_busy(|| {
+            query!(
+                r#"
+            select polled_at from integration_poll
+            where integration = ? and repository = ?
+            "#,
+                integration,
+                repository,
+            )
+            .fetch_optional(&self.internal)
+        })
+        .await
+        .tap_ok(|row| span_record!(found, row.is_some()))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+        .map(|row| row.map(|row| from_unix_seconds(row.polled_at)))
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn record_connectivity(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        reachable: bool,
+        now: SystemTime,
+    ) -> Result<(), super::Error> {
+        let integration = namespace.to_string();
+        let now = unix_seconds(now);
+        self.retry_busy(|| {
+            query!(
+                r#"
+            insert into integration_connectivity (integration, repository, reachable, checked_at)
+            values (?, ?, ?, ?)
+            on conflict do update set reachable = excluded.reachable, checked_at = excluded.checked_at
+            "#,
+                integration,
+                repository,
+                reachable,
+                now,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(found))]
+    async fn connectivity(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<Connectivity>, super::Error> {
+        let integration = namespace.to_string();
+        self.retry_busy(|| {
+            query!(
+                r#"
+            select reachable, checked_at from integration_connectivity
+            where integration = ? and repository = ?
+            "#,
+                integration,
+                repository,
+            )
+            .fetch_optional(&self.internal)
+        })
+        .await
+        .tap_ok(|row| span_record!(found, row.is_some()))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+        .map(|row| {
+            row.map(|row| Connectivity::new(row.reachable != 0, from_unix_seconds(row.checked_at)))
+        })
+    }
+
+    #[tracing::instrument(fields(found))]
+    async fn last_branch_state(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        branch: &str,
+    ) -> Result<Option<Vec<u8>>, super::Error> {
+        let integration = namespace.to_string();
+        self.retry_busy(|| {
+            query!(
+                r#"
+            select repo_state from branch_history
+            where integration = ? and repository = ? and branch = ?
+            order by id desc
+            limit 1
+            "#,
+                integration,
+                repository,
+                branch,
+            )
+            .fetch_optional(&self.internal)
+        })
+        .await
+        .tap_ok(|row| span_record!(found, row.is_some()))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+        .map(|row| row.map(|row| row.repo_state))
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn set_scan_policy(
+        &self,
+        scan_id: &str,
+        policy: PolicyStatus,
+    ) -> Result<(), super::Error> {
+        let policy = policy.to_string();
+        self.retry_busy(|| {
+            query!(
+                "update scan_history set policy_status = ? where scan_id = ?",
+                policy,
+                scan_id,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn set_scan_locator(&self, scan_id: &str, locator: &str) -> Result<(), super::Error> {
+        self.retry_busy(|| {
+            query!(
+                "update scan_history set locator = ? where scan_id = ?",
+                locator,
+                scan_id,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(found))]
+    async fn scans_since(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        since: SystemTime,
+    ) -> Result<Vec<HistoricScan>, super::Error> {
+        let integration = namespace.to_string();
+        let since = unix_seconds(since);
+        self.retry_busy(|| {
+            query_as!(
+                HistoricScanRow,
+                r#"
+            select revision, recorded_at, locator, scan_id, clone_ms, analyze_ms, policy_status from scan_history
+            where integration = ? and repository = ? and recorded_at >= ?
+            order by id desc
+            "#,
+                integration,
+                repository,
+                since,
+            )
+            .fetch_all(&self.internal)
+        })
+        .await
+        .tap_ok(|rows| span_record!(found, rows.len()))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+        .map(|rows| rows.into_iter().map(HistoricScan::from).collect())
+    }
+
+    #[tracing::instrument(fields(found))]
+    async fn recent_scans(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        limit: u32,
+    ) -> Result<Vec<ScanRecord>, super::Error> {
+        let integration = namespace.to_string();
+        self.retry_busy(|| {
+            query_as!(
+                ScanHistoryRow,
+                r#"
+            select scan_id, clone_ms, analyze_ms, policy_status from scan_history
+            where integration = ? and repository = ?
+            order by id desc
+            limit ?
+            "#,
+                integration,
+                repository,
+                limit,
+            )
+            .fetch_all(&self.internal)
+        })
+        .await
+        .tap_ok(|rows| span_record!(found, rows.len()))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+        .map(|rows| rows.into_iter().map(ScanRecord::from).collect())
+    }
+
+    #[tracing::instrument(fields(found))]
+    async fn project_mapping(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<ProjectMapping>, super::Error> {
+        let integration = namespace.to_string();
+        self.retry_busy(|| {
+            query!(
+            "select project, title from project_mapping where integration = ? and repository = ?",
+            integration,
+            repository,
+        )
+            .fetch_optional(&self.internal)
+        })
+        .await
+        .tap_ok(|row| span_record!(found, row.is_some()))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+        .map(|row| row.map(|row| ProjectMapping::new(row.project, row.title)))
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn set_project_mapping(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        mapping: &ProjectMapping,
+    ) -> Result<(), super::Error> {
+        let integration = namespace.to_string();
+        let title = mapping.title().as_deref();
+        self.retry_busy(|| {
+            query!(
+                r#"
+            insert into project_mapping (integration, repository, project, title)
+            values (?, ?, ?, ?)
+            on conflict do update set project = excluded.project, title = excluded.title
+            "#,
+                integration,
+                repository,
+                mapping.project(),
+                title,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(found))]
+    async fn pending_upload(
+        &self,
+        coordinate: &Coordinate,
+        scan_id: &str,
+    ) -> Result<Option<PendingUpload>, super::Error> {
+        let integration = coordinate.namespace.to_string();
+        self.retry_busy(|| {
+            query_as!(
+                PendingUploadRow,
+                r#"
+            select scan_id, revision, attempts, first_failed_at, next_attempt_at from pending_upload
+            where scan_id = ? and integration = ? and repository = ?
+            "#,
+                scan_id,
+                integration,
+                coordinate.remote,
+            )
+            .fetch_optional(&self.internal)
+        })
+        .await
+        .tap_ok(|row| span_record!(found, row.is_some()))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+        .map(|row| {
+            row.map(|row| row.into_pending(coordinate.namespace.clone(), &coordinate.remote))
+        })
+    }
+
+    #[tracing::instrument(fields(found))]
+    async fn due_pending_uploads(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        now: SystemTime,
+    ) -> Result<Vec<PendingUpload>, super::Error> {
+        let integration = namespace.to_string();
+        let now = unix_seconds(now);
+        self.retry_busy(|| {
+            query_as!(
+                PendingUploadRow,
+                r#"
+            select scan_id, revision, attempts, first_failed_at, next_attempt_at from pending_upload
+            where integration = ? and repository = ? and next_attempt_at <= ?
+            order by first_failed_at
+            "#,
+                integration,
+                repository,
+                now,
+            )
+            .fetch_all(&self.internal)
+        })
+        .await
+        .tap_ok(|rows| span_record!(found, rows.len()))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+        .map(|rows| {
+            rows.into_iter()
+                .map(|row| row.into_pending(namespace.clone(), repository))
+                .collect()
+        })
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn set_pending_upload(&self, upload: &PendingUpload) -> Result<(), super::Error> {
+        let coordinate = upload.coordinate();
+        let integration = coordinate.namespace.to_string();
+        let attempts = i64::from(upload.attempts());
+        let first_failed_at = unix_seconds(upload.first_failed_at());
+        let next_attempt_at = unix_seconds(upload.next_attempt_at());
+        self.retry_busy(|| {
+            query!(
+                r#"
+            insert into pending_upload (scan_id, integration, repository, revision, attempts, first_failed_at, next_attempt_at)
+            values (?, ?, ?, ?, ?, ?, ?)
+            on conflict do update set
+              attempts = excluded.attempts,
+              next_attempt_at = excluded.next_attempt_at
+            "#,
+                upload.scan_id(),
+                integration,
+                coordinate.remote,
+                coordinate.reference,
+                attempts,
+                first_failed_at,
+                next_attempt_at,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn delete_pending_upload(&self, scan_id: &str) -> Result<(), super::Error> {
+        self.retry_busy(|| {
+            query!("delete from pending_upload where scan_id = ?", scan_id).execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(found))]
+    async fn pending_uploads(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Vec<PendingUpload>, super::Error> {
+        let integration = namespace.to_string();
+        self.retry_busy(|| {
+            query_as!(
+                PendingUploadRow,
+                r#"
+            select scan_id, revision, attempts, first_failed_at, next_attempt_at from pending_upload
+            where integration = ? and repository = ?
+            order by first_failed_at
+            "#,
+                integration,
+                repository,
+            )
+            .fetch_all(&self.internal)
+        })
+        .await
+        .tap_ok(|rows| span_record!(found, rows.len()))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+        .map(|rows| {
+            rows.into_iter()
+                .map(|row| row.into_pending(namespace.clone(), repository))
+                .collect()
+        })
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn set_queued_job(&self, job: &QueuedJob) -> Result<(), super::Error> {
+        let coordinate = job.coordinate();
+        let stage = job.stage().to_string();
+        let integration = coordinate.namespace.to_string();
+        let state = job.state().as_slice();
+        let enqueued_at = unix_seconds(job.enqueued_at());
+        let payload = job.payload().as_deref();
+        self.retry_busy(|| {
+            query!(
+                r#"
+            insert into queued_job (scan_id, stage, integration, repository, revision, reference, repo_state, is_branch, enqueued_at, payload)
+            values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            on conflict do update set stage = excluded.stage, enqueued_at = excluded.enqueued_at, payload = excluded.payload
+            "#,
+                job.scan_id(),
+                stage,
+                integration,
+                coordinate.remote,
+                coordinate.reference,
+                job.reference(),
+                state,
+                job.is_branch(),
+                enqueued_at,
+                payload,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn delete_queued_job(&self, scan_id: &str) -> Result<(), super::Error> {
+        self.retry_busy(|| {
+            query!("delete from queued_job where scan_id = ?", scan_id).execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(found))]
+    async fn queued_jobs(&self) -> Result<Vec<QueuedJob>, super::Error> {
+        let rows = self.retry_busy(|| {
+            query_as!(
+                QueuedJobRow,
+                r#"
+            select scan_id, stage, integration, repository, revision, reference, repo_state, is_branch, enqueued_at, payload
+            from queued_job
+            order by enqueued_at
+            "#,
+            )
+            .fetch_all(&self.internal)
+        })
+        .await
+        .tap_ok(|rows| span_record!(found, rows.len()))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)?;
+
+        rows.into_iter()
+            .map(QueuedJob::try_from)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(acquired))]
+    async fn acquire_scan_lease(
+        &self,
+        coordinate: &Coordinate,
+        holder: &str,
+        now: SystemTime,
+        expires_at: SystemTime,
+    ) -> Result<bool, super::Error> {
+        let integration = coordinate.namespace.to_string();
+        let now = unix_seconds(now);
+        let expires_at = unix_seconds(expires_at);
+        // The conditional update leaves the row untouched if someone else holds an unexpired lease,
+        // in which case no rows are affected.
+        self.retry_busy(|| {
+            query!(
+                r#"
+            insert into scan_lease (integration, repository, revision, holder, expires_at)
+            values (?, ?, ?, ?, ?)
+            on conflict do update set holder = excluded.holder, expires_at = excluded.expires_at
+            where scan_lease.holder = excluded.holder or scan_lease.expires_at <= ?
+            "#,
+                integration,
+                coordinate.remote,
+                coordinate.reference,
+                holder,
+                expires_at,
+                now,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .tap_ok(|acquired| span_record!(acquired, acquired))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn release_scan_lease(
+        &self,
+        coordinate: &Coordinate,
+        holder: &str,
+    ) -> Result<(), super::Error> {
+        let integration = coordinate.namespace.to_string();
+        self.retry_busy(|| {
+            query!(
+                r#"
+            delete from scan_lease
+            where integration = ? and repository = ? and revision = ? and holder = ?
+            "#,
+                integration,
+                coordinate.remote,
+                coordinate.reference,
+                holder,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(found))]
+    async fn backlog(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<Option<Backlog>, super::Error> {
+        let integration = namespace.to_string();
+        self.retry_busy(|| {
+            query_as!(
+                BacklogRow,
+                r#"
+            select total, completed, started_at, updated_at from backlog
+            where integration = ? and repository = ?
+            "#,
+                integration,
+                repository,
+            )
+            .fetch_optional(&self.internal)
+        })
+        .await
+        .tap_ok(|row| span_record!(found, row.is_some()))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+        .map(|row| row.map(Backlog::from))
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn enqueue_backlog(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        count: u64,
+        now: SystemTime,
+    ) -> Result<(), super::Error> {
+        let integration = namespace.to_string();
+        let count = i64::try_from(count).unwrap_or(i64::MAX);
+        let now = unix_seconds(now);
+
+        // Expressions in the update refer to the row as it was before the update,
+        // so each column sees whether the previous backlog was complete.
+        self.retry_busy(|| {
+            query!(
+                r#"
+            insert into backlog (integration, repository, total, completed, started_at, updated_at)
+            values (?, ?, ?, 0, ?, ?)
+            on conflict do update set
+              total = case when completed >= total then excluded.total else total + excluded.total end,
+              completed = case when completed >= total then 0 else completed end,
+              started_at = case when completed >= total then excluded.started_at else started_at end,
+              updated_at = excluded.updated_at
+            "#,
+                integration,
+                repository,
+                count,
+                now,
+                now,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(abandoned))]
+    async fn abandon_backlogs(&self) -> Result<u64, super::Error> {
+        self.retry_busy(|| {
+            query!("update backlog set completed = total where completed < total")
+                .execute(&self.internal)
+        })
+        .await
+        .map(|result| result.rows_affected())
+        .tap_ok(|abandoned| span_record!(abandoned, *abandoned))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn progress_backlog(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+        now: SystemTime,
+    ) -> Result<(), super::Error> {
+        let integration = namespace.to_string();
+        let now = unix_seconds(now);
+        self.retry_busy(|| {
+            query!(
+                r#"
+            update backlog set completed = min(completed + 1, total), updated_at = ?
+            where integration = ? and repository = ?
+            "#,
+                now,
+                integration,
+                repository,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(result))]
+    async fn pause(&self, scope: &PauseScope, now: SystemTime) -> Result<(), super::Error> {
+        let (integration, repository) = pause_key(scope);
+        let now = unix_seconds(now);
+        self.retry_busy(|| {
+            query!(
+                r#"
+            insert into integration_pause (integration, repository, paused_at)
+            values (?, ?, ?)
+            on conflict do nothing
+            "#,
+                integration,
+                repository,
+                now,
+            )
+            .execute(&self.internal)
+        })
+        .await
+        .map(|result| span_record!(result, debug result))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(resumed))]
+    async fn resume(&self, scope: &PauseScope) -> Result<u64, super::Error> {
+        let result = match scope {
+            PauseScope::All => {
+                self.retry_busy(|| query!("delete from integration_pause").execute(&self.internal))
+                    .await
+            }
+            PauseScope::Repository(..) => {
+                let (integration, repository) = pause_key(scope);
+                self.retry_busy(|| {
+                    query!(
+                        r#"
+                    delete from integration_pause
+                    where integration = ? and repository = ?
+                    "#,
+                        integration,
+                        repository,
+                    )
+                    .execute(&self.internal)
+                })
+                .await
+            }
+        };
+        result
+            .map(|result| result.rows_affected())
+            .tap_ok(|resumed| span_record!(resumed, resumed))
+            .context(Error::Communication)
+            .change_context(super::Error::Interact)
+    }
+
+    #[tracing::instrument(fields(paused))]
+    async fn is_paused(
+        &self,
+        namespace: &Namespace,
+        repository: &str,
+    ) -> Result<bool, super::Error> {
+        let (all_integration, all_repository) = pause_key(&PauseScope::All);
+        let integration = namespace.to_string();
+        self.retry_busy(|| {
+            query!(
+                r#"
+            select count(*) as "count!: i64" from integration_pause
+            where (integration = ? and repository = ?) or (integration = ? and repository = ?)
+            "#,
+                integration,
+                repository,
+                all_integration,
+                all_repository,
+            )
+            .fetch_one(&self.internal)
+        })
+        .await
+        .map(|row| row.count > 0)
+        .tap_ok(|paused| span_record!(paused, paused))
+        .context(Error::Communication)
+        .change_context(super::Error::Interact)
+    }
+}
+
+/// The integration and repository under which a pause is stored.
+///
+/// Pausing every integration is stored with an empty integration and repository,
+/// which no configured integration has.
+fn pause_key(scope: &PauseScope) -> (String, String) {
+    match scope {
+        PauseScope::All => (String::new(), String::new()),
+        PauseScope::Repository(namespace, repository) => {
+            (namespace.to_string(), repository.clone())
+        }
+    }
+}
+
+/// How many times a statement is attempted while the database is busy, including the first attempt.
+const BUSY_ATTEMPTS: u32 = 5;
+
+/// How long to wait before attempting a statement again after the database was busy.
+/// This doubles after each attempt.
+const BUSY_BACKOFF: Duration = Duration::from_millis(100);
+
+impl Database {
+    /// Run a statement, attempting it again with backoff while the database is busy.
+    ///
+    /// sqlite already waits up to the busy timeout for other connections to release their locks,
+    /// but under heavy contention it can still report that it's busy; this is temporary, so it's retried
+    /// rather than failing whatever Broker was doing.
+    async fn retry_busy<T, F, Fut>(&self, run: F) -> std::result::Result<T, sqlx::Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = std::result::Result<T, sqlx::Error>>,
+    {
+        let mut backoff = BUSY_BACKOFF;
+        for attempt in 1..BUSY_ATTEMPTS {
+            match run().await {
+                Err(err) if is_busy(&err) => {
+                    debug!("Database busy on attempt {attempt} of {BUSY_ATTEMPTS}, retrying in {backoff:?}: {err}");
+                    self.clock.sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+        run().await
+    }
+}
+
+/// Whether the error is sqlite reporting that the database is busy or locked.
+fn is_busy(err: &sqlx::Error) -> bool {
+    // The code is the extended result code; its low byte is the primary result code.
+    const SQLITE_BUSY: i32 = 5;
+    const SQLITE_LOCKED: i32 = 6;
+
+    let sqlx::Error::Database(err) = err else {
+        return false;
+    };
+    let code = err.code().and_then(|code| code.parse::<i32>().ok());
+    matches!(
+        code.map(|code| code & 0xff),
+        Some(SQLITE_BUSY | SQLITE_LOCKED)
+    )
+}
+
+/// Convert the time to seconds since the unix epoch, as stored in the DB.
+fn unix_seconds(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since| i64::try_from(since.as_secs()).unwrap_or(i64::MAX))
+        .unwrap_or_default()
+}
+
+/// Convert seconds since the unix epoch, as stored in the DB, to a time.
+fn from_unix_seconds(seconds: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(u64::try_from(seconds).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::db::Database as _;
+
+    macro_rules! temp_db {
+        () => {{
+            let tmp = tempdir().expect("must create temporary directory");
+            let db = super::Database::connect(&tmp.path().join("test.db"), Default::default())
+                .await
+                .expect("must create db");
+            (tmp, db)
+        }};
+    }
+
+    #[tokio::test]
+    async fn inserts_version() {
+        let (_tmp, db) = temp_db!();
+
+        let version = crate_version();
+        let row = query_as!(
+            BrokerVersionRow,
+            "select version from broker_version limit 1"
+        )
+        .fetch_one(&db.internal)
+        .await
+        .expect("must fetch version");
+
+        assert_eq!(row.version, version.to_string());
+    }
+
+    #[tokio::test]
+    async fn updates_version() {
+        let (_tmp, db) = temp_db!();
+
+        let version = crate_version();
+        let version = Version::new(version.major + 1, 0, 0);
         db.update_db_version(&version)
             .await
             .expect("must update version");
@@ -336,4 +1911,371 @@ mod tests {
 
         assert_eq!(row.version, version.to_string());
     }
+
+    #[tokio::test]
+    async fn sets_references_hash() {
+        let (_tmp, db) = temp_db!();
+
+        let repository = "https://github.com/fossas/broker.git";
+        let hash = db
+            .references_hash(&Namespace::Git, repository)
+            .await
+            .expect("must read hash");
+        assert_eq!(hash, None);
+
+        for expected in [b"first".to_vec(), b"second".to_vec()] {
+            db.set_references_hash(&Namespace::Git, repository, &expected)
+                .await
+                .expect("must set hash");
+            let hash = db
+                .references_hash(&Namespace::Git, repository)
+                .await
+                .expect("must read hash");
+            assert_eq!(hash, Some(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_states_in_batch() {
+        let (_tmp, db) = temp_db!();
+
+        let remote = "https://github.com/fossas/broker.git";
+        let coordinate = |reference: &str| {
+            Coordinate::new(Namespace::Git, remote.to_string(), reference.to_string())
+        };
+        let scanned = coordinate("git:branch:main");
+        let other = coordinate("git:tag:v1.0.0");
+        let unscanned = coordinate("git:branch:feature");
+
+        db.set_state(&scanned, b"abcd", &true)
+            .await
+            .expect("must set state");
+        db.set_state(&other, b"efgh", &false)
+            .await
+            .expect("must set state");
+
+        let states = db
+            .states_for(&[unscanned, scanned.clone(), other])
+            .await
+            .expect("must read states");
+        assert_eq!(
+            states,
+            vec![None, Some(b"abcd".to_vec()), Some(b"efgh".to_vec())]
+        );
+
+        let states = db.states_for(&[]).await.expect("must read states");
+        assert!(states.is_empty());
+    }
+
+    #[tokio::test]
+    async fn resets_states() {
+        let (_tmp, db) = temp_db!();
+
+        let remote = "https://github.com/fossas/broker.git";
+        let other_remote = "https://github.com/fossas/other.git";
+        let coordinate = |remote: &str, reference: &str| {
+            Coordinate::new(Namespace::Git, remote.to_string(), reference.to_string())
+        };
+        let main = coordinate(remote, "git:branch:main@abcd");
+        let main_next = coordinate(remote, "git:branch:main-next@efgh");
+        let tag = coordinate(remote, "git:tag:v1.0.0@ijkl");
+        let other = coordinate(other_remote, "git:branch:main@mnop");
+        for (coordinate, is_branch) in [
+            (&main, true),
+            (&main_next, true),
+            (&tag, false),
+            (&other, true),
+        ] {
+            db.set_state(coordinate, b"state", &is_branch)
+                .await
+                .expect("must set state");
+        }
+
+        let deleted = db
+            .reset_states(&Namespace::Git, remote, Some("git:branch:main@"))
+            .await
+            .expect("must reset states");
+        assert_eq!(deleted, 1);
+
+        let states = db
+            .states_for(&[main.clone(), main_next.clone(), tag.clone()])
+            .await
+            .expect("must read states");
+        assert_eq!(
+            states,
+            vec![None, Some(b"state".to_vec()), Some(b"state".to_vec())]
+        );
+
+        let deleted = db
+            .reset_states(&Namespace::Git, remote, None)
+            .await
+            .expect("must reset states");
+        assert_eq!(deleted, 2);
+
+        let state = db.state(&other).await.expect("must read state");
+        assert_eq!(
+            state,
+            Some(b"state".to_vec()),
+            "other repositories are unaffected"
+        );
+    }
+
+    #[tokio::test]
+    async fn deletes_references_hash() {
+        let (_tmp, db) = temp_db!();
+
+        let repository = "https://github.com/fossas/broker.git";
+        db.set_references_hash(&Namespace::Git, repository, b"hash")
+            .await
+            .expect("must set hash");
+        db.delete_references_hash(&Namespace::Git, repository)
+            .await
+            .expect("must delete hash");
+
+        let hash = db
+            .references_hash(&Namespace::Git, repository)
+            .await
+            .expect("must read hash");
+        assert_eq!(hash, None);
+    }
+
+    #[tokio::test]
+    async fn records_scan_history() {
+        let (_tmp, db) = temp_db!();
+
+        let repository = "https://github.com/fossas/broker.git";
+        let coordinate = |remote: &str| {
+            Coordinate::new(
+                Namespace::Git,
+                remote.to_string(),
+                "git:branch:main@abcd".to_string(),
+            )
+        };
+        let scan = |id: &str, secs: u64| {
+            ScanRecord::new(
+                id.to_string(),
+                Duration::from_secs(secs),
+                Duration::from_secs(secs * 10),
+            )
+        };
+
+        for (id, secs) in [("first", 1), ("second", 2), ("third", 3)] {
+            db.record_scan(&coordinate(repository), &scan(id, secs))
+                .await
+                .expect("must record scan");
+        }
+        db.record_scan(
+            &coordinate("https://github.com/fossas/other.git"),
+            &scan("other", 4),
+        )
+        .await
+        .expect("must record scan");
+
+        let scans = db
+            .recent_scans(&Namespace::Git, repository, 2)
+            .await
+            .expect("must read scans");
+        assert_eq!(scans, vec![scan("third", 3), scan("second", 2)]);
+
+        db.set_scan_policy("third", PolicyStatus::Failed)
+            .await
+            .expect("must set scan policy");
+        let scans = db
+            .recent_scans(&Namespace::Git, repository, 2)
+            .await
+            .expect("must read scans");
+        assert_eq!(
+            scans,
+            vec![
+                scan("third", 3).with_policy(Some(PolicyStatus::Failed)),
+                scan("second", 2)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn records_polls() {
+        let (_tmp, db) = temp_db!();
+
+        let repository = "https://github.com/fossas/broker.git";
+        let last = db
+            .last_poll(&Namespace::Git, repository)
+            .await
+            .expect("must read last poll");
+        assert_eq!(last, None);
+
+        for seconds in [100, 200] {
+            let now = UNIX_EPOCH + Duration::from_secs(seconds);
+            db.record_poll(&Namespace::Git, repository, now)
+                .await
+                .expect("must record poll");
+        }
+        let last = db
+            .last_poll(&Namespace::Git, repository)
+            .await
+            .expect("must read last poll");
+        assert_eq!(last, Some(UNIX_EPOCH + Duration::from_secs(200)));
+
+        let integrity = db.integrity_check().await.expect("must check integrity");
+        assert_eq!(integrity, vec![String::from("ok")]);
+    }
+
+    #[tokio::test]
+    async fn records_connectivity() {
+        let (_tmp, db) = temp_db!();
+
+        let repository = "https://github.com/fossas/broker.git";
+        let checked = db
+            .connectivity(&Namespace::Git, repository)
+            .await
+            .expect("must read connectivity");
+        assert_eq!(checked, None);
+
+        for (seconds, reachable) in [(100, true), (200, false)] {
+            let now = UNIX_EPOCH + Duration::from_secs(seconds);
+            db.record_connectivity(&Namespace::Git, repository, reachable, now)
+                .await
+                .expect("must record connectivity");
+        }
+        let checked = db
+            .connectivity(&Namespace::Git, repository)
+            .await
+            .expect("must read connectivity");
+        assert_eq!(
+            checked,
+            Some(Connectivity::new(
+                false,
+                UNIX_EPOCH + Duration::from_secs(200)
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn records_branch_history() {
+        let (_tmp, db) = temp_db!();
+
+        let repository = "https://github.com/fossas/broker.git";
+        let last = db
+            .last_branch_state(&Namespace::Git, repository, "main")
+            .await
+            .expect("must read history");
+        assert_eq!(last, None);
+
+        for state in [b"abcd", b"ef01"] {
+            db.record_branch_state(&Namespace::Git, repository, "main", state)
+                .await
+                .expect("must record state");
+        }
+        db.record_branch_state(&Namespace::Git, repository, "release", b"2345")
+            .await
+            .expect("must record state");
+
+        for (branch, expected) in [("main", b"ef01"), ("release", b"2345")] {
+            let last = db
+                .last_branch_state(&Namespace::Git, repository, branch)
+                .await
+                .expect("must read history");
+            assert_eq!(last, Some(expected.to_vec()), "{branch}");
+        }
+    }
+
+    #[tokio::test]
+    async fn tracks_backlog_progress() {
+        let (_tmp, db) = temp_db!();
+
+        let repository = "github.com/fossas/broker";
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let backlog = db
+            .backlog(&Namespace::Git, repository)
+            .await
+            .expect("must read backlog");
+        assert_eq!(backlog, None);
+
+        db.enqueue_backlog(&Namespace::Git, repository, 3, at(10))
+            .await
+            .expect("must enqueue");
+        db.progress_backlog(&Namespace::Git, repository, at(20))
+            .await
+            .expect("must progress");
+
+        // References enqueued before the backlog completes are added to it.
+        db.enqueue_backlog(&Namespace::Git, repository, 2, at(30))
+            .await
+            .expect("must enqueue");
+        let backlog = db
+            .backlog(&Namespace::Git, repository)
+            .await
+            .expect("must read backlog");
+        assert_eq!(backlog, Some(Backlog::new(5, 1, at(10), at(30))));
+
+        for _ in 0..6 {
+            db.progress_backlog(&Namespace::Git, repository, at(40))
+                .await
+                .expect("must progress");
+        }
+        let backlog = db
+            .backlog(&Namespace::Git, repository)
+            .await
+            .expect("must read backlog")
+            .expect("must have backlog");
+        assert_eq!(backlog, Backlog::new(5, 5, at(10), at(40)));
+        assert!(backlog.is_complete());
+
+        // References enqueued after the backlog completes start a new one.
+        db.enqueue_backlog(&Namespace::Git, repository, 4, at(50))
+            .await
+            .expect("must enqueue");
+        let backlog = db
+            .backlog(&Namespace::Git, repository)
+            .await
+            .expect("must read backlog");
+        assert_eq!(backlog, Some(Backlog::new(4, 0, at(50), at(50))));
+    }
+
+    #[tokio::test]
+    async fn records_queued_jobs() {
+        let (_tmp, db) = temp_db!();
+
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let job = |scan_id: &str, stage, secs| {
+            QueuedJob::new(
+                scan_id.to_string(),
+                stage,
+                Coordinate::new(
+                    Namespace::Git,
+                    String::from("github.com/fossas/broker"),
+                    format!("git:branch:{scan_id}"),
+                ),
+                scan_id.to_string(),
+                b"abcd1234".to_vec(),
+                true,
+                at(secs),
+                Some(scan_id.as_bytes().to_vec()),
+            )
+        };
+
+        db.set_queued_job(&job("second", JobStage::Scan, 20))
+            .await
+            .expect("must record job");
+        db.set_queued_job(&job("first", JobStage::Scan, 10))
+            .await
+            .expect("must record job");
+        db.set_queued_job(&job("second", JobStage::Upload, 30))
+            .await
+            .expect("must update job");
+        let jobs = db.queued_jobs().await.expect("must read jobs");
+        assert_eq!(
+            jobs,
+            vec![
+                job("first", JobStage::Scan, 10),
+                job("second", JobStage::Upload, 30)
+            ]
+        );
+
+        db.delete_queued_job("first")
+            .await
+            .expect("must delete job");
+        let jobs = db.queued_jobs().await.expect("must read jobs");
+        assert_eq!(jobs, vec![job("second", JobStage::Upload, 30)]);
+    }
 }
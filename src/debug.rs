@@ -6,7 +6,10 @@
 //! - They can contain more than just logs, for example traces.
 //! - They can comprise other types of data, for example time series metrics snapshots.
 
-use std::path::{Path, PathBuf};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 use derive_more::{AsRef, From, Into};
 use derive_new::new;
@@ -16,18 +19,31 @@ use rolling_file::{BasicRollingFileAppender, RollingConditionBasic};
 use tracing::{info, Metadata};
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{
-    filter, fmt::format::FmtSpan, layer::Context, prelude::*, Layer, Registry,
+    filter,
+    fmt::{format::FmtSpan, MakeWriter},
+    layer::Context,
+    prelude::*,
+    Layer, Registry,
 };
 
+use crate::encryption::Key;
 use crate::ext::{
+    command::{self, Redacter, Value},
     error_stack::{DescribeContext, ErrorHelper, IntoContext},
     result::WrapErr,
+    secrecy::ComparableSecretString,
 };
 
-use self::bundler::Bundler;
+use self::{bundler::Bundler, snapshot::Snapshot};
 
+pub mod api_calls;
 mod bundle;
 pub mod bundler;
+pub mod retention;
+pub mod snapshot;
+
+/// The file name suffix of debug bundles written by FOSSA CLI.
+const CLI_DEBUG_BUNDLE_SUFFIX: &str = ".fossa.debug.json.gz";
 
 /// Errors that are possibly surfaced when running debugging operations.
 #[derive(Debug, thiserror::Error)]
@@ -50,6 +66,14 @@ pub enum Error {
     /// It's unfortunately possible for collecting a debug bundle to fail.
     #[error("collecting debug bundle")]
     CollectDebugBundle,
+
+    /// The configured redaction patterns couldn't be installed.
+    #[error("install redaction patterns")]
+    RedactPatterns,
+
+    /// The recorder for FOSSA API calls couldn't be installed.
+    #[error("install FOSSA API call recorder")]
+    CaptureApiCalls,
 }
 
 /// Errors that are possibly surfaced during validation of config values.
@@ -58,6 +82,10 @@ pub enum ValidationError {
     /// Retentions must be above a minimum value.
     #[error("retention value is too small")]
     RetentionBelowMinimum,
+
+    /// Redaction patterns must be valid regular expressions.
+    #[error("redaction pattern is not a valid regular expression")]
+    RedactPattern,
 }
 
 /// Export mode for the debug bundle.
@@ -73,6 +101,16 @@ pub enum BundleExport {
     Always,
 }
 
+/// Upload mode for the debug bundle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleUpload {
+    /// The debug bundle is only written to disk.
+    Disable,
+
+    /// After the debug bundle is written to disk, it is also uploaded to FOSSA Support.
+    Enable,
+}
+
 /// Validated config values for observability.
 #[derive(Debug, Clone, PartialEq, Eq, Getters, new)]
 #[getset(get = "pub")]
@@ -82,17 +120,55 @@ pub struct Config {
 
     /// The configured retention settings.
     retention: Retention,
+
+    /// Regular expressions redacted from the output of every command Broker runs,
+    /// in addition to the secrets in the config file.
+    redact_patterns: Vec<String>,
+
+    /// Whether requests to the FOSSA API and their responses are recorded into the debug artifacts.
+    capture_api_calls: bool,
 }
 
 impl Config {
     /// Initialize debugging singletons.
     ///
-    /// Until this method is run, traces are not output anywhere and are lost forever;
+    /// Until this method is run, traces are not output anywhere and are lost forever,
+    /// and command output is only redacted of the secrets provided to each command;
     /// run it as soon as possible.
+    ///
+    /// The provided secrets, along with the configured redaction patterns,
+    /// are redacted from every trace before it's written.
     #[must_use = "This guard must be stored in a variable that is retained; if it is dropped the tracing sink will stop running"]
-    pub fn run_tracing_sink(&self) -> Result<WorkerGuard, Report<Error>> {
+    pub fn run_tracing_sink(
+        &self,
+        secrets: Vec<ComparableSecretString>,
+    ) -> Result<WorkerGuard, Report<Error>> {
         self.ensure_tracing_root_exists()?;
-        self.initialize_tracing_sink()
+        self.install_redact_patterns()?;
+        self.install_api_call_recorder()?;
+        self.initialize_tracing_sink(trace_redacter(secrets))
+    }
+
+    /// Install the recorder for FOSSA API calls, if enabled.
+    fn install_api_call_recorder(&self) -> Result<(), Report<Error>> {
+        if !self.capture_api_calls {
+            return Ok(());
+        }
+
+        let root = self.location().as_ref().join("api");
+        std::fs::create_dir_all(&root)
+            .context(Error::CaptureApiCalls)
+            .help("this location is set in the config file")
+            .describe_lazy(|| format!("create directory '{}'", root.display()))?;
+        let file = self.retention().sink(&root.join("fossa-api.jsonl"))?;
+        api_calls::install(file).change_context(Error::CaptureApiCalls)
+    }
+
+    /// Install the configured redaction patterns for the output of every command.
+    fn install_redact_patterns(&self) -> Result<(), Report<Error>> {
+        command::install_redaction_patterns(&self.redact_patterns)
+            .context(Error::RedactPatterns)
+            .help("patterns are validated when the config file is loaded, so this is likely a program bug")
     }
 
     /// The path to the directory containing trace files.
@@ -114,13 +190,15 @@ impl Config {
             })
     }
 
-    /// Initialize tracing sinks:
+    /// Initialize tracing sinks, both of which are redacted by the provided redacter:
     /// - Hourly rotating sink of all raw traces in JSON format to disk.
     /// - Pretty sink of INFO-level traces to stdout.
-    fn initialize_tracing_sink(&self) -> Result<WorkerGuard, Report<Error>> {
+    fn initialize_tracing_sink(&self, redacter: Redacter) -> Result<WorkerGuard, Report<Error>> {
         let target = self.tracing_root().join("broker.trace");
         let file = self.retention().sink(&target)?;
         let (sink, guard) = tracing_appender::non_blocking(file);
+        let sink = Redacting::new(sink, redacter.clone());
+        let stderr = Redacting::new(std::io::stderr, redacter);
 
         let subscriber = Registry::default()
             // log pretty info traces to terminal
@@ -131,7 +209,7 @@ impl Config {
                     .with_level(false)
                     .with_line_number(false)
                     .with_target(false)
-                    .with_writer(std::io::stderr)
+                    .with_writer(stderr)
                     .with_ansi(atty::is(atty::Stream::Stderr))
                     .with_filter(filter::dynamic_filter_fn(filter_to_events))
                     .with_filter(filter::LevelFilter::INFO),
@@ -158,6 +236,65 @@ impl Config {
     }
 }
 
+/// Build the redacter for traces.
+///
+/// Traces written to disk are JSON, which escapes characters like quotes and backslashes,
+/// so secrets are redacted both as they are and as they appear once escaped.
+fn trace_redacter(secrets: Vec<ComparableSecretString>) -> Redacter {
+    let escaped = secrets
+        .iter()
+        .filter_map(|secret| {
+            let quoted = serde_json::to_string(secret.expose_secret()).ok()?;
+            let escaped = quoted.strip_prefix('"')?.strip_suffix('"')?;
+            (escaped != secret.expose_secret()).then(|| ComparableSecretString::from(escaped))
+        })
+        .collect::<Vec<_>>();
+    let values = secrets
+        .into_iter()
+        .chain(escaped)
+        .filter(|secret| !secret.expose_secret().is_empty())
+        .map(Value::new_secret);
+    Redacter::for_secrets(values)
+}
+
+/// Wraps a [`MakeWriter`] so that everything written through it is redacted first.
+///
+/// Tracing formats each event in full before writing it, so each write contains whole events;
+/// secrets are never split across writes.
+#[derive(Debug, new)]
+struct Redacting<M> {
+    inner: M,
+    redacter: Redacter,
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<'a, M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            redacter: &self.redacter,
+        }
+    }
+}
+
+/// A writer created by [`Redacting`].
+struct RedactingWriter<'a, W> {
+    inner: W,
+    redacter: &'a Redacter,
+}
+
+impl<W: Write> Write for RedactingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write_all(&self.redacter.redact_bytes(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 /// When logging trace output, we're talking to FOSSA users, not developers.
 /// Users don't care about the vast majority of what traces contain, things like:
 /// - Line numbers
@@ -190,7 +327,7 @@ impl Root {
 
     /// The location for the debug bundle for a given scan ID.
     pub fn debug_bundle(&self, scan_id: &str) -> PathBuf {
-        let file_name = format!("{}.fossa.debug.json.gz", scan_id);
+        let file_name = format!("{scan_id}{CLI_DEBUG_BUNDLE_SUFFIX}");
         self.as_ref().join(file_name)
     }
 }
@@ -202,6 +339,9 @@ impl Root {
 pub struct Retention {
     /// The number of days to retain.
     days: ArtifactRetentionCount,
+
+    /// Retention for FOSSA CLI debug bundles.
+    cli_bundles: retention::CliBundleRetention,
 }
 
 impl Retention {
@@ -256,14 +396,24 @@ pub struct Bundle {
 }
 
 impl Bundle {
-    /// Collect a debug bundle from the working environment.
-    pub fn collect<B, P>(conf: &Config, bundler: B, path: P) -> Result<Self, Report<Error>>
+    /// Collect a debug bundle from the working environment,
+    /// including the snapshot of runtime state.
+    ///
+    /// FOSSA CLI debug bundles encrypted with the provided key are decrypted as they're collected.
+    pub fn collect<B, P>(
+        conf: &Config,
+        encryption: Option<&Key>,
+        bundler: B,
+        path: P,
+        snapshot: &Snapshot,
+    ) -> Result<Self, Report<Error>>
     where
         P: AsRef<Path>,
         B: Bundler,
         B::Error: error_stack::Context,
     {
-        bundle::generate(conf, bundler, path).change_context(Error::CollectDebugBundle)
+        bundle::generate(conf, encryption, bundler, path, snapshot)
+            .change_context(Error::CollectDebugBundle)
     }
 
     /// Whether the debug bundle is is empty.
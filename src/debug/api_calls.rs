@@ -0,0 +1,188 @@
+//! Records calls to the FOSSA API into the debug artifacts, so that Support can see exactly which call failed and why.
+//!
+//! Recording is opt-in through the `capture_api_calls` debugging option.
+//! Each call is written as a line of JSON with its method, URL, status, and timing,
+//! along with the start of the request and response bodies.
+//! Secrets are redacted from everything that is recorded:
+//! the API key used for the call, along with any match of the installed redaction patterns.
+
+use std::{
+    io::Write,
+    sync::{Mutex, PoisonError},
+    time::{Instant, SystemTime},
+};
+
+use error_stack::{report, Report};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::ext::{
+    command::{Redacter, Value},
+    error_stack::{DescribeContext, ErrorHelper},
+    result::WrapErr,
+};
+
+use super::Error;
+
+/// Bodies longer than this are truncated when recorded.
+const MAX_BODY_BYTES: usize = 4096;
+
+/// Where calls are recorded, if recording is enabled.
+static RECORDER: OnceCell<Mutex<Box<dyn Write + Send>>> = OnceCell::new();
+
+/// Record every subsequent call to the FOSSA API into the provided sink.
+pub fn install<W: Write + Send + 'static>(sink: W) -> Result<(), Report<Error>> {
+    RECORDER
+        .set(Mutex::new(Box::new(sink)))
+        .or_else(|_| {
+            report!(Error::CaptureApiCalls)
+                .wrap_err()
+                .help("if you're a user and you're seeing this, please report this as a defect to FOSSA support")
+                .describe("the recorder was installed more than once; this is a program bug")
+        })
+}
+
+/// Begin recording a call to the FOSSA API, if recording is enabled.
+///
+/// `key` is the API key used for the call, which is redacted from everything that is recorded.
+pub fn start(method: &str, url: &str, key: Option<&str>, body: Option<&[u8]>) -> Option<Call> {
+    RECORDER.get()?;
+    Some(Call::new(method, url, key, body))
+}
+
+/// A call to the FOSSA API which is in progress.
+#[derive(Debug)]
+pub struct Call {
+    timestamp: SystemTime,
+    started: Instant,
+    redacter: Redacter,
+    method: String,
+    url: String,
+    request_body: Option<String>,
+}
+
+impl Call {
+    fn new(method: &str, url: &str, key: Option<&str>, body: Option<&[u8]>) -> Self {
+        let secrets = key.map(|key| Value::new_secret(key.to_string()));
+        let redacter = Redacter::for_secrets(secrets);
+        Self {
+            timestamp: SystemTime::now(),
+            started: Instant::now(),
+            method: method.to_string(),
+            url: redacter.redact_str(url),
+            request_body: body.map(|body| excerpt(&redacter, body)),
+            redacter,
+        }
+    }
+
+    /// Record that the FOSSA API responded to the call.
+    pub fn responded(self, status: u16, body: &[u8]) {
+        self.record(Some(status), Some(body), None);
+    }
+
+    /// Record that the call failed without a response from the FOSSA API, for example due to a network error.
+    pub fn failed(self, error: impl std::fmt::Display) {
+        self.record(None, None, Some(error.to_string()));
+    }
+
+    fn record(self, status: Option<u16>, body: Option<&[u8]>, error: Option<String>) {
+        let Some(recorder) = RECORDER.get() else {
+            return;
+        };
+
+        let record = self.into_record(status, body, error);
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(err) => {
+                warn!("Unable to serialize FOSSA API call: {err}");
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let mut sink = recorder.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(err) = sink.write_all(&line).and_then(|_| sink.flush()) {
+            warn!("Unable to record FOSSA API call: {err}");
+        }
+    }
+
+    fn into_record(
+        self,
+        status: Option<u16>,
+        body: Option<&[u8]>,
+        error: Option<String>,
+    ) -> Record {
+        Record {
+            timestamp: humantime::format_rfc3339_millis(self.timestamp).to_string(),
+            duration_ms: self.started.elapsed().as_millis(),
+            response_body: body.map(|body| excerpt(&self.redacter, body)),
+            error: error.map(|error| self.redacter.redact_str(&error)),
+            method: self.method,
+            url: self.url,
+            status,
+            request_body: self.request_body,
+        }
+    }
+}
+
+/// A recorded call, as written to the debug artifacts.
+#[derive(Debug, Serialize)]
+struct Record {
+    timestamp: String,
+    method: String,
+    url: String,
+    status: Option<u16>,
+    duration_ms: u128,
+    request_body: Option<String>,
+    response_body: Option<String>,
+    error: Option<String>,
+}
+
+/// The body, with secrets redacted, truncated to [`MAX_BODY_BYTES`].
+///
+/// Secrets are redacted before truncating so that a secret crossing the cutoff isn't partially recorded.
+fn excerpt(redacter: &Redacter, body: &[u8]) -> String {
+    let redacted = redacter.redact_bytes(body);
+    if redacted.len() <= MAX_BODY_BYTES {
+        return String::from_utf8_lossy(&redacted).to_string();
+    }
+
+    let excerpt = String::from_utf8_lossy(&redacted[..MAX_BODY_BYTES]);
+    format!("{excerpt}... (truncated from {} bytes)", redacted.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_and_truncates() {
+        let key = "abcd1234";
+        let body = format!(
+            "{{\"key\":\"{key}\",\"units\":\"{}\"}}",
+            "a".repeat(MAX_BODY_BYTES)
+        );
+        let call = Call::new(
+            "POST",
+            &format!("https://app.fossa.com/api/builds/custom?key={key}"),
+            Some(key),
+            Some(body.as_bytes()),
+        );
+        let record = call.into_record(
+            Some(401),
+            Some(b"{\"message\":\"abcd1234 is invalid\"}"),
+            None,
+        );
+
+        assert_eq!(record.method, "POST");
+        assert_eq!(record.status, Some(401));
+        assert!(!record.url.contains(key));
+        let request_body = record.request_body.expect("must record request body");
+        assert!(!request_body.contains(key));
+        assert!(request_body.contains("... (truncated from "));
+        let response_body = record.response_body.expect("must record response body");
+        assert!(!response_body.contains(key));
+        assert!(response_body.contains("is invalid"));
+    }
+}
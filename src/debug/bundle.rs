@@ -10,13 +10,21 @@
 use std::path::{Path, PathBuf};
 
 use error_stack::{Context, Result, ResultExt};
+use tempfile::NamedTempFile;
 use thiserror::Error;
 use tracing::{debug, error};
 use walkdir::WalkDir;
 
-use crate::ext::{error_stack::IntoContext, io::sync::copy_debug_bundle, tracing::span_records};
+use crate::{
+    encryption::{self, Key},
+    ext::{error_stack::IntoContext, io::sync::copy_debug_bundle, tracing::span_records},
+};
 
-use super::{bundler::Bundler, Bundle, Config};
+use super::{
+    bundler::Bundler,
+    snapshot::{self, Snapshot},
+    Bundle, Config,
+};
 
 /// Errors encountered generating a debug bundle.
 #[derive(Debug, Error)]
@@ -41,6 +49,10 @@ pub enum Error {
         file: PathBuf,
     },
 
+    /// Broker wasn't able to write the snapshot of runtime state into the bundle.
+    #[error("bundle runtime snapshot")]
+    BundleSnapshot,
+
     /// When finished bundling everything, it's finalized into a [`Bundle`].
     /// If this fails, this error is reported.
     #[error("finalize debug bundle")]
@@ -63,9 +75,16 @@ impl Error {
 /// Generate a debug bundle for the application at the specified location.
 /// Ultimately this means "write a copy of every file inside the debug root to the bundler".
 ///
-/// FOSSA CLI debug bundles are decompressed and prettified before including in the overall bundle.
-#[tracing::instrument(skip(bundler, path), fields(debug_root, path))]
-pub fn generate<B, P>(conf: &Config, mut bundler: B, path: P) -> Result<Bundle, Error>
+/// FOSSA CLI debug bundles are decompressed and prettified before including in the overall bundle,
+/// and the snapshot of runtime state is written alongside them.
+#[tracing::instrument(skip(encryption, bundler, path, snapshot), fields(debug_root, path))]
+pub fn generate<B, P>(
+    conf: &Config,
+    encryption: Option<&Key>,
+    mut bundler: B,
+    path: P,
+    snapshot: &Snapshot,
+) -> Result<Bundle, Error>
 where
     B: Bundler,
     B::Error: Context,
@@ -99,8 +118,21 @@ where
         }
 
         // Copy the file to temp first, so that it's not changed while the tar is being built.
-        // This also decompresses and formats debug bundles if the copied file is in fact a debug bundle.
-        let (copy, rel) = copy_debug_bundle(path, rel).change_context(Error::CreateTempFile)?;
+        // This also decrypts, decompresses, and formats debug bundles if the copied file is in fact a debug bundle.
+        // Debug bundles encrypted with a key other than the configured one can't be read, so they're left out.
+        let (copy, rel) = match copy_debug_bundle(path, rel, encryption) {
+            Ok(copied) => copied,
+            Err(err) if err.contains::<encryption::Error>() => {
+                error!(
+                    path = %path.display(),
+                    err = %format!("{err:#}"),
+                    "Skipping '{}': could not decrypt, see 'err' for details",
+                    rel.display(),
+                );
+                continue;
+            }
+            Err(err) => return Err(err.change_context(Error::CreateTempFile)),
+        };
 
         // Add the file to the bundle.
         bundler
@@ -108,5 +140,11 @@ where
             .context_lazy(|| Error::bundle_contents(debug_root, &rel))?;
     }
 
+    let mut copy = NamedTempFile::new().context(Error::CreateTempFile)?;
+    serde_json::to_writer_pretty(&mut copy, snapshot).context(Error::BundleSnapshot)?;
+    bundler
+        .add_file(copy.path(), snapshot::FILE_NAME)
+        .context(Error::BundleSnapshot)?;
+
     bundler.finalize(path).context(Error::Finalize)
 }
@@ -0,0 +1,169 @@
+//! Retention for debug artifacts.
+//!
+//! Traces are rotated by the tracing sink as they are written,
+//! so the retention for them is handled when the sink is configured.
+//!
+//! FOSSA CLI debug bundles are written once per scan and are never touched again,
+//! so they are instead cleaned up periodically by the functions in this module.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use bytesize::ByteSize;
+use derive_new::new;
+use error_stack::Result;
+use getset::CopyGetters;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::ext::error_stack::IntoContext;
+
+use super::{ArtifactRetentionCount, Root, CLI_DEBUG_BUNDLE_SUFFIX};
+
+/// Errors encountered enforcing retention.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Broker wasn't able to list the contents of the debug root.
+    #[error("list contents of directory: '{}'", .0.display())]
+    ListContents(PathBuf),
+}
+
+/// Retention settings for FOSSA CLI debug bundles.
+///
+/// These are configured separately from the retention for traces,
+/// since FOSSA CLI debug bundles are generally much larger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters, new)]
+#[getset(get_copy = "pub")]
+pub struct CliBundleRetention {
+    /// The number of days for which bundles are retained.
+    days: ArtifactRetentionCount,
+
+    /// The maximum total size of retained bundles.
+    /// When exceeded, the oldest bundles are removed first.
+    max_size: Option<ByteSize>,
+}
+
+impl CliBundleRetention {
+    /// The default maximum total size of retained bundles.
+    pub const DEFAULT_MAX_SIZE: ByteSize = ByteSize::gb(5);
+
+    fn max_age(&self) -> Duration {
+        let days: usize = self.days.into();
+        Duration::from_secs(days as u64 * 24 * 60 * 60)
+    }
+}
+
+impl Default for CliBundleRetention {
+    fn default() -> Self {
+        Self {
+            days: ArtifactRetentionCount::default(),
+            max_size: Some(Self::DEFAULT_MAX_SIZE),
+        }
+    }
+}
+
+/// The result of enforcing retention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct Summary {
+    /// The number of artifacts removed.
+    removed: usize,
+
+    /// The amount of space reclaimed by removing artifacts.
+    reclaimed: ByteSize,
+}
+
+/// A FOSSA CLI debug bundle on disk.
+struct CliBundle {
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+}
+
+/// Enforce retention on the FOSSA CLI debug bundles stored in the debug root.
+///
+/// Bundles older than the configured number of days are removed.
+/// Afterwards, if the remaining bundles are larger than the configured maximum size,
+/// the oldest bundles are removed until they fit.
+///
+/// Failing to remove an individual bundle is not an error; it's logged and retried next time.
+#[tracing::instrument(skip(root), fields(root = %root.as_path().display()))]
+pub fn enforce_cli_bundles(
+    root: &Root,
+    retention: CliBundleRetention,
+    now: SystemTime,
+) -> Result<Summary, Error> {
+    let mut bundles = list_cli_bundles(root.as_path())?;
+
+    // Newest first, so that the running total of retained bundles
+    // is spent on the most recent bundles.
+    bundles.sort_by(|a, b| b.modified.cmp(&a.modified));
+
+    let max_age = retention.max_age();
+    let max_size = retention.max_size().map(|size| size.as_u64());
+
+    let mut summary = Summary::default();
+    let mut retained = 0u64;
+    for bundle in bundles {
+        let age = now.duration_since(bundle.modified).unwrap_or_default();
+        let too_old = age > max_age;
+        let too_big = max_size
+            .map(|max| retained + bundle.size > max)
+            .unwrap_or(false);
+
+        if !too_old && !too_big {
+            retained += bundle.size;
+            continue;
+        }
+
+        match std::fs::remove_file(&bundle.path) {
+            Ok(_) => {
+                debug!(path = %bundle.path.display(), ?age, too_old, too_big, "Removed FOSSA CLI debug bundle");
+                summary.removed += 1;
+                summary.reclaimed = ByteSize::b(summary.reclaimed.as_u64() + bundle.size);
+            }
+            Err(err) => {
+                warn!(path = %bundle.path.display(), %err, "Unable to remove FOSSA CLI debug bundle");
+                retained += bundle.size;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+fn list_cli_bundles(root: &Path) -> Result<Vec<CliBundle>, Error> {
+    // If nothing has been written yet, there's nothing to clean up.
+    if !root.exists() {
+        return Ok(Vec::new());
+    }
+
+    let entries = std::fs::read_dir(root).context_lazy(|| Error::ListContents(root.to_owned()))?;
+    let mut bundles = Vec::new();
+    for entry in entries {
+        let entry = entry.context_lazy(|| Error::ListContents(root.to_owned()))?;
+        let is_bundle = entry
+            .file_name()
+            .to_string_lossy()
+            .ends_with(CLI_DEBUG_BUNDLE_SUFFIX);
+        if !is_bundle {
+            continue;
+        }
+
+        // Bundles may be removed while we're looking at them; these are simply skipped.
+        let Ok(meta) = entry.metadata() else { continue };
+        if !meta.is_file() {
+            continue;
+        }
+
+        bundles.push(CliBundle {
+            path: entry.path(),
+            modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            size: meta.len(),
+        });
+    }
+
+    Ok(bundles)
+}
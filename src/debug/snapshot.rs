@@ -0,0 +1,345 @@
+//! Snapshots of Broker's runtime state, included in debug bundles so that they carry enough state
+//! to diagnose a stuck pipeline: how much work is waiting, when each integration was last polled,
+//! the most recent errors, how much disk the data root uses, and whether the database is intact.
+//!
+//! Snapshots are collected by `broker fix`, outside the running Broker process,
+//! so everything is read from the database, the data root, and the trace files instead of from memory.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use getset::{CopyGetters, Getters};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use walkdir::WalkDir;
+
+use crate::{
+    api::remote::Integrations,
+    db::{Database, JobStage},
+};
+
+use super::Config;
+
+/// The most errors included in a snapshot.
+pub const MAX_RECENT_ERRORS: usize = 50;
+
+/// The name of the snapshot in debug bundles.
+pub const FILE_NAME: &str = "snapshot.json";
+
+/// Broker's runtime state at the time the snapshot was collected.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct Snapshot {
+    /// When the snapshot was collected.
+    collected_at: Option<String>,
+
+    /// How many jobs are waiting or being worked on.
+    queues: Queues,
+
+    /// The state of each configured integration.
+    integrations: Vec<IntegrationState>,
+
+    /// The most recent warnings and errors from the trace files, newest first.
+    recent_errors: Vec<RecordedError>,
+
+    /// How much disk the data root uses.
+    data_root: DiskUsage,
+
+    /// The output of checking the integrity of the database: `ok`, or the problems found.
+    integrity_check: Vec<String>,
+
+    /// Parts of the snapshot which couldn't be collected, and why.
+    collection_errors: Vec<String>,
+}
+
+/// How many jobs are waiting or being worked on, by stage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct Queues {
+    /// References waiting to be scanned, or being scanned.
+    scan: usize,
+
+    /// Scans waiting to be uploaded, or being uploaded.
+    upload: usize,
+
+    /// Uploaded scans waiting to be followed up on, or being followed up on.
+    follow_up: usize,
+
+    /// Scans which failed to upload and are waiting to be retried.
+    pending_uploads: usize,
+}
+
+/// The state of an integration.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct IntegrationState {
+    /// The integration, as it's named in logs.
+    integration: String,
+
+    /// When the integration was last polled, if it has been.
+    last_poll: Option<String>,
+
+    /// Progress through the references enqueued for scanning, if any have been.
+    backlog: Option<BacklogProgress>,
+
+    /// How many scans of the integration are waiting to retry their upload.
+    pending_uploads: usize,
+}
+
+/// Progress through the references enqueued for scanning for an integration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct BacklogProgress {
+    /// How many references have been enqueued in the backlog.
+    total: u64,
+
+    /// How many of the enqueued references have been scanned.
+    completed: u64,
+}
+
+/// A warning or error recorded in the trace files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct RecordedError {
+    /// When the event was recorded.
+    timestamp: Option<String>,
+
+    /// The level of the event: `WARN` or `ERROR`.
+    level: String,
+
+    /// The message of the event.
+    message: String,
+}
+
+/// The disk used by a directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Getters, CopyGetters)]
+pub struct DiskUsage {
+    /// The directory.
+    #[getset(get = "pub")]
+    location: PathBuf,
+
+    /// The total size of the files in the directory, in bytes.
+    #[getset(get_copy = "pub")]
+    bytes: u64,
+
+    /// The number of files in the directory.
+    #[getset(get_copy = "pub")]
+    files: u64,
+}
+
+impl Snapshot {
+    /// Collect a snapshot of the runtime state.
+    ///
+    /// If the database couldn't be opened, `db` is `None` and the parts of the snapshot read from it are empty.
+    /// Nothing that fails to be collected prevents the rest of the snapshot from being collected;
+    /// failures are recorded in the snapshot instead.
+    #[tracing::instrument(skip_all)]
+    pub async fn collect<D: Database>(
+        conf: &Config,
+        integrations: &Integrations,
+        db: Option<&D>,
+        data_root: &Path,
+    ) -> Self {
+        let mut snapshot = Self {
+            collected_at: Some(format_time(SystemTime::now())),
+            recent_errors: recent_errors(&conf.location().as_path().join("trace")),
+            data_root: disk_usage(data_root),
+            ..Default::default()
+        };
+
+        let Some(db) = db else {
+            snapshot
+                .collection_errors
+                .push(String::from("open database: unavailable"));
+            return snapshot;
+        };
+
+        match db.integrity_check().await {
+            Ok(output) => snapshot.integrity_check = output,
+            Err(err) => snapshot
+                .collection_errors
+                .push(format!("check database integrity: {err:#}")),
+        }
+
+        match db.queued_jobs().await {
+            Ok(jobs) => {
+                let count = |stage| jobs.iter().filter(|job| job.stage() == stage).count();
+                snapshot.queues.scan = count(JobStage::Scan);
+                snapshot.queues.upload = count(JobStage::Upload);
+                snapshot.queues.follow_up = count(JobStage::FollowUp);
+            }
+            Err(err) => snapshot
+                .collection_errors
+                .push(format!("read queued jobs: {err:#}")),
+        }
+
+        for integration in integrations.iter() {
+            let namespace = integration.namespace();
+            let repository = integration.repository();
+            let mut state = IntegrationState {
+                integration: integration.to_string(),
+                ..Default::default()
+            };
+
+            match db.last_poll(&namespace, &repository).await {
+                Ok(polled) => state.last_poll = polled.map(format_time),
+                Err(err) => snapshot
+                    .collection_errors
+                    .push(format!("read last poll for '{integration}': {err:#}")),
+            }
+            match db.backlog(&namespace, &repository).await {
+                Ok(backlog) => {
+                    state.backlog = backlog.map(|backlog| BacklogProgress {
+                        total: backlog.total(),
+                        completed: backlog.completed(),
+                    })
+                }
+                Err(err) => snapshot
+                    .collection_errors
+                    .push(format!("read backlog for '{integration}': {err:#}")),
+            }
+            match db.pending_uploads(&namespace, &repository).await {
+                Ok(uploads) => state.pending_uploads = uploads.len(),
+                Err(err) => snapshot
+                    .collection_errors
+                    .push(format!("read pending uploads for '{integration}': {err:#}")),
+            }
+
+            snapshot.queues.pending_uploads += state.pending_uploads;
+            snapshot.integrations.push(state);
+        }
+
+        snapshot
+    }
+}
+
+/// Render the time for the snapshot.
+fn format_time(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+/// The most recent warnings and errors in the trace files in the directory, newest first.
+///
+/// The current trace file is named `broker.trace`, and rotated files are suffixed with increasing numbers as they age;
+/// files are read from newest to oldest until [`MAX_RECENT_ERRORS`] are found.
+pub fn recent_errors(trace_root: &Path) -> Vec<RecordedError> {
+    let mut files = match fs::read_dir(trace_root) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter_map(|path| {
+                let name = path.file_name()?.to_str()?;
+                let age = match name.strip_prefix("broker.trace") {
+                    Some("") => 0,
+                    Some(suffix) => suffix.strip_prefix('.')?.parse::<u32>().ok()?,
+                    None => return None,
+                };
+                Some((age, path))
+            })
+            .collect::<Vec<_>>(),
+        Err(err) => {
+            debug!(
+                "Unable to list trace files in '{}': {err:#}",
+                trace_root.display()
+            );
+            return Vec::new();
+        }
+    };
+    files.sort();
+
+    let mut errors = Vec::new();
+    for (_, path) in files {
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) => {
+                debug!("Unable to read trace file '{}': {err:#}", path.display());
+                continue;
+            }
+        };
+        for line in content.lines().rev() {
+            if let Some(error) = parse_error(line) {
+                errors.push(error);
+                if errors.len() >= MAX_RECENT_ERRORS {
+                    return errors;
+                }
+            }
+        }
+    }
+    errors
+}
+
+/// Parse a line of a trace file, if it records a warning or error.
+fn parse_error(line: &str) -> Option<RecordedError> {
+    let event = serde_json::from_str::<serde_json::Value>(line).ok()?;
+    let level = event.get("level")?.as_str()?;
+    if !matches!(level, "WARN" | "ERROR") {
+        return None;
+    }
+    Some(RecordedError {
+        timestamp: event
+            .get("timestamp")
+            .and_then(|timestamp| timestamp.as_str())
+            .map(String::from),
+        level: level.to_string(),
+        message: event.get("message")?.as_str()?.to_string(),
+    })
+}
+
+/// Sum the size of the files in the directory.
+///
+/// Files which can't be read, for example because they were removed while walking, are skipped.
+fn disk_usage(root: &Path) -> DiskUsage {
+    let mut usage = DiskUsage {
+        location: root.to_path_buf(),
+        ..Default::default()
+    };
+    let files = WalkDir::new(root)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file());
+    for file in files {
+        if let Ok(meta) = file.metadata() {
+            usage.bytes += meta.len();
+            usage.files += 1;
+        }
+    }
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_recent_errors_newest_first() {
+        let root = tempfile::tempdir().expect("must create tempdir");
+        let event = |level: &str, message: &str| {
+            format!(
+                r#"{{"timestamp":"2023-12-18T00:00:00Z","level":"{level}","message":"{message}","target":"broker"}}"#
+            )
+        };
+        let rotated = [event("WARN", "oldest"), event("INFO", "ignored")].join("\n");
+        let current = [
+            event("ERROR", "older"),
+            String::from("not json"),
+            event("WARN", "newest"),
+        ]
+        .join("\n");
+        fs::write(root.path().join("broker.trace.1"), rotated).expect("must write trace");
+        fs::write(root.path().join("broker.trace"), current).expect("must write trace");
+
+        let messages = recent_errors(root.path())
+            .into_iter()
+            .map(|error| error.message)
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec!["newest", "older", "oldest"]);
+
+        let usage = disk_usage(root.path());
+        assert_eq!(usage.files(), 2);
+        assert!(usage.bytes() > 0);
+    }
+}
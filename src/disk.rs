@@ -0,0 +1,164 @@
+//! Monitoring for free disk space.
+//!
+//! Broker writes traces, clones, and FOSSA CLI debug bundles to disk.
+//! When the disk fills up, these fail in ways that don't make the cause obvious,
+//! so instead Broker watches free space on the locations it writes to
+//! and stops starting new scans while any of them is below the configured minimum.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use bytesize::ByteSize;
+use derive_new::new;
+use getset::{CopyGetters, Getters};
+use tracing::warn;
+use walkdir::WalkDir;
+
+/// Validated config values for disk space monitoring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters, new)]
+#[getset(get_copy = "pub")]
+pub struct Config {
+    /// Scans aren't started while any monitored location has less than this much free space.
+    /// A minimum of zero disables the check.
+    min_free: ByteSize,
+}
+
+impl Config {
+    /// The default minimum free space.
+    pub const DEFAULT_MIN_FREE: ByteSize = ByteSize::gb(1);
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            min_free: Self::DEFAULT_MIN_FREE,
+        }
+    }
+}
+
+/// A monitored location without enough free space.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, new)]
+pub struct Shortage {
+    /// The location being monitored.
+    #[getset(get = "pub")]
+    path: PathBuf,
+
+    /// The free space available to Broker at the location.
+    #[getset(get_copy = "pub")]
+    available: ByteSize,
+}
+
+/// Tracks whether the monitored locations have enough free space.
+#[derive(Debug)]
+pub struct Monitor {
+    config: Config,
+    paths: Vec<PathBuf>,
+    low: AtomicBool,
+}
+
+impl Monitor {
+    /// Monitor the provided locations.
+    pub fn new(config: Config, paths: Vec<PathBuf>) -> Self {
+        Self {
+            config,
+            paths,
+            low: AtomicBool::new(false),
+        }
+    }
+
+    /// The configured minimum free space.
+    pub fn min_free(&self) -> ByteSize {
+        self.config.min_free()
+    }
+
+    /// Whether any monitored location was below the minimum free space when last checked.
+    pub fn is_low(&self) -> bool {
+        self.low.load(Ordering::SeqCst)
+    }
+
+    /// Check the free space at each monitored location, returning the locations below the minimum.
+    ///
+    /// Locations for which free space can't be determined are logged and otherwise ignored,
+    /// since failing to check shouldn't stop Broker from scanning.
+    pub fn check(&self) -> Vec<Shortage> {
+        let shortages = if self.config.min_free.as_u64() == 0 {
+            Vec::new()
+        } else {
+            self.paths
+                .iter()
+                .filter_map(|path| match available_space(path) {
+                    Ok(available) if available < self.config.min_free => {
+                        Some(Shortage::new(path.clone(), available))
+                    }
+                    Ok(_) => None,
+                    Err(err) => {
+                        warn!("Unable to check free space at '{}': {err}", path.display());
+                        None
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        self.low.store(!shortages.is_empty(), Ordering::SeqCst);
+        shortages
+    }
+}
+
+/// The free space available to unprivileged users at the location.
+pub fn available_space(path: &Path) -> std::io::Result<ByteSize> {
+    fs2::available_space(path).map(ByteSize::b)
+}
+
+/// The total size of the files inside the location, without following links.
+///
+/// Files which can't be read are skipped, so this may be an underestimate.
+pub fn usage(path: &Path) -> ByteSize {
+    let bytes = WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|meta| meta.len())
+        .sum();
+    ByteSize::b(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_shortage_below_minimum() {
+        let root = tempfile::tempdir().expect("must create temp dir");
+        let paths = vec![root.path().to_path_buf()];
+
+        let monitor = Monitor::new(Config::new(ByteSize::b(1)), paths.clone());
+        assert!(monitor.check().is_empty(), "temp dir must have a byte free");
+        assert!(!monitor.is_low());
+
+        let monitor = Monitor::new(Config::new(ByteSize::b(u64::MAX)), paths.clone());
+        let shortages = monitor.check();
+        assert_eq!(shortages.len(), 1);
+        assert_eq!(shortages[0].path(), root.path());
+        assert!(monitor.is_low());
+
+        let monitor = Monitor::new(Config::new(ByteSize::b(0)), paths);
+        assert!(
+            monitor.check().is_empty(),
+            "a minimum of zero disables the check"
+        );
+    }
+
+    #[test]
+    fn measures_usage() {
+        let root = tempfile::tempdir().expect("must create temp dir");
+        std::fs::write(root.path().join("a"), [0; 10]).expect("must write file");
+        std::fs::create_dir(root.path().join("nested")).expect("must create dir");
+        std::fs::write(root.path().join("nested").join("b"), [0; 5]).expect("must write file");
+
+        assert_eq!(usage(root.path()), ByteSize::b(15));
+    }
+}
@@ -3,6 +3,7 @@
 use once_cell::sync::OnceCell;
 use semver::Version;
 
+pub mod code;
 pub mod link;
 
 /// The git SHA for the current build.
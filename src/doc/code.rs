@@ -0,0 +1,160 @@
+//! Stable, machine-readable codes for the errors Broker reports.
+//!
+//! Each error variant is assigned a code when it is added, and codes are never renumbered or reused:
+//! support and automation key off the code, since the text of the error message may change between releases.
+//! Every code is documented in `docs/reference/error-codes.md`; new variants must be added there as well.
+
+use std::fmt;
+
+use error_stack::{Frame, Report};
+
+use crate::{admin, api, cmd, encryption, fossa_cli, keyring};
+
+/// The prefix of rendered error codes.
+const PREFIX: &str = "BRKR";
+
+/// A stable code identifying an error variant, rendered like `BRKR-1203`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ErrorCode(u16);
+
+impl ErrorCode {
+    /// Create a code from its number.
+    pub const fn new(code: u16) -> Self {
+        Self(code)
+    }
+
+    /// The number of the code.
+    pub fn number(self) -> u16 {
+        self.0
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{PREFIX}-{:04}", self.0)
+    }
+}
+
+/// Errors which have a stable code.
+pub trait HasErrorCode {
+    /// The code of the error.
+    fn code(&self) -> ErrorCode;
+}
+
+/// The codes of the errors in the report, outermost first, without duplicates.
+pub fn codes<C>(report: &Report<C>) -> Vec<ErrorCode> {
+    let mut codes = Vec::new();
+    for code in report.frames().filter_map(code_of) {
+        if !codes.contains(&code) {
+            codes.push(code);
+        }
+    }
+    codes
+}
+
+/// The code of the error in the frame, if the frame is an error with a code.
+fn code_of(frame: &Frame) -> Option<ErrorCode> {
+    macro_rules! downcast_code {
+        ($($error:ty),* $(,)?) => {
+            $(
+                if let Some(err) = frame.downcast_ref::<$error>() {
+                    return Some(err.code());
+                }
+            )*
+        };
+    }
+
+    downcast_code!(
+        admin::Error,
+        api::fossa::Error,
+        api::fossa::ValidationError,
+        api::http::Error,
+        api::http::client::Error,
+        api::http::client::ValidationError,
+        api::remote::ValidationError,
+        api::remote::RemoteProviderError,
+        api::remote::git::repository::Error,
+        api::remote::git::executable::Error,
+        api::remote::git::executable::ValidationError,
+        fossa_cli::Error,
+        fossa_cli::ValidationError,
+        keyring::Error,
+        encryption::Error,
+        cmd::fix::Error,
+        cmd::run::Error,
+        cmd::init::Error,
+        cmd::backfill::Error,
+        cmd::scan::Error,
+        cmd::simulate::Error,
+        cmd::config::Error,
+        cmd::db::Error,
+        cmd::queue::Error,
+        cmd::status::Error,
+        cmd::update::Error,
+        cmd::doctor::Error,
+        cmd::monitor::Error,
+        cmd::pause::Error,
+        cmd::report::Error,
+        cmd::debug::Error,
+    );
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use error_stack::report;
+
+    use super::*;
+
+    /// The codes documented in the error code reference.
+    fn documented() -> Vec<String> {
+        include_str!("../../docs/reference/error-codes.md")
+            .lines()
+            .filter_map(|line| line.strip_prefix("### "))
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn renders_code() {
+        assert_eq!(ErrorCode::new(1203).to_string(), "BRKR-1203");
+        assert_eq!(ErrorCode::new(51).to_string(), "BRKR-0051");
+    }
+
+    #[test]
+    fn documented_codes_are_unique() {
+        let documented = documented();
+        let unique = documented.iter().collect::<HashSet<_>>();
+        assert_eq!(documented.len(), unique.len(), "codes must not be reused");
+        for code in &documented {
+            let number = code
+                .strip_prefix("BRKR-")
+                .unwrap_or_else(|| panic!("code '{code}' must have the prefix"));
+            assert_eq!(number.len(), 4, "code '{code}' must have four digits");
+            assert!(
+                number.parse::<u16>().is_ok(),
+                "code '{code}' must be numeric"
+            );
+        }
+    }
+
+    #[test]
+    fn collects_codes_from_report() {
+        let report = report!(cmd::doctor::Error::ChecksFailed(2))
+            .change_context(cmd::monitor::Error::NotATerminal)
+            .change_context(cmd::doctor::Error::ChecksFailed(1));
+
+        let codes = codes(&report);
+        assert_eq!(codes, vec![ErrorCode::new(4101), ErrorCode::new(4201)]);
+
+        let documented = documented();
+        for code in codes {
+            assert!(
+                documented.contains(&code.to_string()),
+                "'{code}' must be documented"
+            );
+        }
+    }
+}
@@ -2,6 +2,8 @@
 
 use once_cell::sync::OnceCell;
 
+use super::code::ErrorCode;
+
 /// The link to the support site.
 pub fn fossa_support() -> &'static str {
     "https://support.fossa.com"
@@ -18,4 +20,17 @@ pub fn config_file_reference() -> &'static str {
     })
 }
 
+/// The reference documentation for an error code.
+pub fn error_code_reference(code: ErrorCode) -> String {
+    // This value is set by Cargo and evaluated at compile time.
+    static LAZY: OnceCell<String> = OnceCell::new();
+    let reference = LAZY.get_or_init(|| {
+        let sha = super::build_sha();
+        let home = super::repo_home();
+        format!("{home}/blob/{sha}/docs/reference/error-codes.md")
+    });
+    let anchor = code.to_string().to_lowercase();
+    format!("{reference}#{anchor}")
+}
+
 // TODO: add tests that hit the URLs and validate they exist.
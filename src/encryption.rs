@@ -0,0 +1,242 @@
+//! Encryption at rest for the sensitive artifacts Broker writes to disk.
+//!
+//! When a key is configured, the scan results saved for upload retries, the analysis cache,
+//! and the FOSSA CLI debug bundles kept in the debug root are encrypted with AES-256-GCM before they're written,
+//! and decrypted transparently when they're read back to be uploaded or bundled.
+//!
+//! Encrypted content starts with [`MAGIC`], so content written before a key was configured is still read as-is.
+//!
+//! SSH keys aren't encrypted, since `ssh` reads them from disk itself;
+//! they're written to private temporary files instead (see [`crate::ext::tempfile::secret_tempfile`]).
+//! The key is passed from the config to everything that writes or reads these artifacts,
+//! rather than installed for the whole process, so it can't be left out by a caller that skips setup.
+
+use aes_gcm::{
+    aead::{generic_array::GenericArray, Aead, KeyInit},
+    Aes256Gcm,
+};
+use base64::{engine::general_purpose, Engine};
+use error_stack::{bail, report, Report};
+
+use crate::{
+    doc::code::{ErrorCode, HasErrorCode},
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        result::WrapErr,
+        secrecy::{ComparableSecretString, REDACTION_LITERAL},
+    },
+};
+
+/// Encrypted content starts with these bytes, followed by the nonce and the ciphertext.
+pub const MAGIC: &[u8] = b"BRKRENC\x01";
+
+/// The length of keys, in bytes.
+const KEY_LEN: usize = 32;
+
+/// The length of the nonce written after [`MAGIC`], in bytes.
+const NONCE_LEN: usize = 12;
+
+/// Errors encountered encrypting or decrypting artifacts.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The key couldn't be read from the environment variable.
+    #[error("read encryption key from environment variable '{0}'")]
+    ReadKey(String),
+
+    /// The key isn't 32 bytes encoded in base64.
+    #[error("encryption key must be 32 bytes encoded in base64")]
+    InvalidKey,
+
+    /// The content couldn't be encrypted.
+    #[error("encrypt content")]
+    Encrypt,
+
+    /// The content is encrypted, but no key is configured.
+    #[error("content is encrypted, but no encryption key is configured")]
+    KeyRequired,
+
+    /// The content couldn't be decrypted, because it was encrypted with a different key or it's corrupted.
+    #[error("decrypt content")]
+    Decrypt,
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::ReadKey(..) => ErrorCode::new(4801),
+            Self::InvalidKey => ErrorCode::new(4802),
+            Self::Encrypt => ErrorCode::new(4803),
+            Self::KeyRequired => ErrorCode::new(4804),
+            Self::Decrypt => ErrorCode::new(4805),
+        }
+    }
+}
+
+/// A key used to encrypt artifacts.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key {
+    encoded: ComparableSecretString,
+    bytes: [u8; KEY_LEN],
+}
+
+impl Key {
+    /// Decode a key from 32 bytes encoded in base64, such as the output of `openssl rand -base64 32`.
+    pub fn decode(encoded: &str) -> Result<Self, Report<Error>> {
+        let encoded = encoded.trim();
+        let decoded = general_purpose::STANDARD
+            .decode(encoded)
+            .context(Error::InvalidKey)
+            .help("generate a key with 'openssl rand -base64 32'")?;
+        let Ok(bytes) = <[u8; KEY_LEN]>::try_from(decoded.as_slice()) else {
+            return report!(Error::InvalidKey)
+                .wrap_err()
+                .help("generate a key with 'openssl rand -base64 32'")
+                .describe_lazy(|| format!("the key decodes to {} bytes", decoded.len()));
+        };
+        Ok(Self {
+            encoded: ComparableSecretString::from(encoded),
+            bytes,
+        })
+    }
+
+    /// Read a key from the environment variable, decoding it with [`Key::decode`].
+    pub fn from_env(var: &str) -> Result<Self, Report<Error>> {
+        let value = std::env::var(var)
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .ok_or_else(|| report!(Error::ReadKey(var.to_string())))
+            .help_lazy(|| format!("set '{var}' in the environment Broker runs in"))?;
+        Self::decode(&value)
+    }
+
+    /// The key as it was encoded, so that it can be redacted.
+    pub fn encoded(&self) -> &ComparableSecretString {
+        &self.encoded
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(GenericArray::from_slice(&self.bytes))
+    }
+}
+
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Key").field(&REDACTION_LITERAL).finish()
+    }
+}
+
+/// Encrypt the content with the key, or return it unchanged if there's no key.
+pub fn seal(key: Option<&Key>, content: Vec<u8>) -> Result<Vec<u8>, Report<Error>> {
+    match key {
+        Some(key) => seal_with(key, &content),
+        None => Ok(content),
+    }
+}
+
+/// Decrypt the content with the key, or return it unchanged if it isn't encrypted.
+pub fn open(key: Option<&Key>, content: Vec<u8>) -> Result<Vec<u8>, Report<Error>> {
+    if !is_sealed(&content) {
+        return Ok(content);
+    }
+    match key {
+        Some(key) => open_with(key, &content),
+        None => report!(Error::KeyRequired)
+            .wrap_err()
+            .help("configure the key the content was encrypted with in 'encryption.key'"),
+    }
+}
+
+/// Whether the content was encrypted by [`seal`].
+pub fn is_sealed(content: &[u8]) -> bool {
+    content.starts_with(MAGIC)
+}
+
+/// Encrypt the content with the key.
+pub fn seal_with(key: &Key, content: &[u8]) -> Result<Vec<u8>, Report<Error>> {
+    let nonce = rand::random::<[u8; NONCE_LEN]>();
+    let ciphertext = key
+        .cipher()
+        .encrypt(GenericArray::from_slice(&nonce), content)
+        .map_err(|_| report!(Error::Encrypt))?;
+
+    let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(MAGIC);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt content encrypted by [`seal_with`] with the key.
+pub fn open_with(key: &Key, content: &[u8]) -> Result<Vec<u8>, Report<Error>> {
+    let Some(sealed) = content.strip_prefix(MAGIC) else {
+        bail!(Error::Decrypt);
+    };
+    if sealed.len() < NONCE_LEN {
+        return report!(Error::Decrypt)
+            .wrap_err()
+            .describe("the content is truncated");
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    key.cipher()
+        .decrypt(GenericArray::from_slice(nonce), ciphertext)
+        .map_err(|_| report!(Error::Decrypt))
+        .help("ensure 'encryption.key' is the key the content was encrypted with")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> Key {
+        Key::decode(&general_purpose::STANDARD.encode([byte; KEY_LEN])).expect("must decode key")
+    }
+
+    #[test]
+    fn round_trips() {
+        let key = key(1);
+        let sealed = seal_with(&key, b"source units").expect("must encrypt");
+        assert!(is_sealed(&sealed));
+        assert!(!sealed
+            .windows(b"source units".len())
+            .any(|window| window == b"source units"));
+        assert_eq!(
+            open_with(&key, &sealed).expect("must decrypt"),
+            b"source units"
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_key_and_corruption() {
+        let sealed = seal_with(&key(1), b"source units").expect("must encrypt");
+        let err = open_with(&key(2), &sealed).expect_err("must reject wrong key");
+        assert!(matches!(err.current_context(), Error::Decrypt));
+
+        let truncated = &sealed[..sealed.len() - 1];
+        let err = open_with(&key(1), truncated).expect_err("must reject corrupted content");
+        assert!(matches!(err.current_context(), Error::Decrypt));
+    }
+
+    #[test]
+    fn seals_only_with_key() {
+        let plain = seal(None, b"source units".to_vec()).expect("must pass through");
+        assert_eq!(plain, b"source units");
+
+        let sealed = seal(Some(&key(1)), plain).expect("must encrypt");
+        assert!(is_sealed(&sealed));
+        let err = open(None, sealed).expect_err("must require key");
+        assert!(matches!(err.current_context(), Error::KeyRequired));
+    }
+
+    #[test]
+    fn validates_keys() {
+        let err = Key::decode("not base64!").expect_err("must reject invalid base64");
+        assert!(matches!(err.current_context(), Error::InvalidKey));
+
+        let short = general_purpose::STANDARD.encode([0; 16]);
+        let err = Key::decode(&short).expect_err("must reject short keys");
+        assert!(matches!(err.current_context(), Error::InvalidKey));
+
+        assert!(!format!("{:?}", key(1)).contains(&general_purpose::STANDARD.encode([1; KEY_LEN])));
+    }
+}
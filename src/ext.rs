@@ -11,6 +11,10 @@ pub mod error_stack;
 pub mod generic;
 pub mod io;
 pub mod iter;
+pub mod once_cell;
 pub mod result;
 pub mod secrecy;
+pub mod sha2;
+pub mod tempfile;
+pub mod tokio;
 pub mod tracing;
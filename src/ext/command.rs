@@ -3,27 +3,87 @@
 use std::{
     ffi::{OsStr, OsString},
     fmt::Display,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::{ExitStatus, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
 };
 
 use aho_corasick::AhoCorasick;
 use getset::Getters;
 use itertools::Itertools;
+use once_cell::sync::OnceCell;
 use thiserror::Error;
-use tokio::process::{Child, ChildStderr, ChildStdout};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    process::{Child, ChildStderr, ChildStdout},
+    time::Instant,
+};
+use tracing::{debug, trace};
 
 use crate::ext::secrecy::REDACTION_LITERAL;
 
 use super::{result::WrapOk, secrecy::ComparableSecretString};
 
 const REMOVED_LITERAL: &str = "<REMOVED>";
+
+/// Patterns redacted from the output of every command, in addition to the secrets provided to the command.
+static EXTRA_REDACTIONS: OnceCell<Vec<RedactionPattern>> = OnceCell::new();
+
+/// Whether every command is printed before it runs; see [`echo_commands`].
+static ECHO_COMMANDS: AtomicBool = AtomicBool::new(false);
+
+/// Print every command run after this is called to stderr before it runs,
+/// in a form that can be pasted into a terminal, with its secrets redacted.
+///
+/// This is process wide, and is meant for debugging subcommands which only run a few commands.
+pub fn echo_commands() {
+    ECHO_COMMANDS.store(true, Ordering::Relaxed);
+}
+
+/// A regular expression redacted from command output, compiled for both strings and bytes.
+#[derive(Debug)]
+struct RedactionPattern {
+    text: regex::Regex,
+    bytes: regex::bytes::Regex,
+}
+
+/// Redact matches of the provided regular expressions from the output of every command run after this is called,
+/// in addition to the secrets provided to each command.
+///
+/// These are process wide and can only be installed once; later calls are ignored.
+/// Returns an error if any pattern is not a valid regular expression, in which case nothing is installed.
+pub fn install_redaction_patterns<S: AsRef<str>>(patterns: &[S]) -> Result<(), regex::Error> {
+    let compiled = patterns
+        .iter()
+        .map(|pattern| {
+            let pattern = pattern.as_ref();
+            Ok(RedactionPattern {
+                text: regex::Regex::new(pattern)?,
+                bytes: regex::bytes::Regex::new(pattern)?,
+            })
+        })
+        .collect::<Result<Vec<_>, regex::Error>>()?;
+    let _ = EXTRA_REDACTIONS.set(compiled);
+    Ok(())
+}
 /// Any error encountered running the program.
 #[derive(Debug, Error)]
 pub enum Error {
     /// An underlying IO error occurred.
     #[error("underlying IO error: {}", .0.trim())]
     IO(String),
+
+    /// The command ran longer than its timeout, and was killed.
+    /// Includes the description of the command along with any output it produced before it was killed.
+    #[error("timed out after {}: {}", humantime::format_duration(*.timeout), .description.trim())]
+    TimedOut {
+        /// The timeout which the command exceeded.
+        timeout: Duration,
+
+        /// The redacted description of the command.
+        description: String,
+    },
 }
 
 impl Error {
@@ -63,6 +123,14 @@ pub struct Command {
     /// Commands really reference paths on the local file system,
     /// which may or may not be UTF8.
     name: OsString,
+
+    /// The maximum amount of time the command may run before it is killed.
+    /// If not specified, the command may run indefinitely.
+    timeout: Option<Duration>,
+
+    /// Content written to the standard input of the command.
+    /// If not specified, the command's standard input is empty.
+    stdin: Option<Value>,
 }
 
 impl Command {
@@ -73,6 +141,8 @@ impl Command {
             envs: Vec::new(),
             name: command.as_ref().to_owned(),
             working_dir: None,
+            timeout: None,
+            stdin: None,
         }
     }
 
@@ -95,6 +165,14 @@ impl Command {
         self
     }
 
+    /// Adds a path to pass to the program as an argument.
+    ///
+    /// Unlike [`Command::arg_plain`], the path is passed as it is, even if it isn't valid UTF-8.
+    pub fn arg_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.args.push(Value::new_path(path));
+        self
+    }
+
     /// Adds multiple arguments to pass to the program.
     pub fn args<V, I>(mut self, values: I) -> Self
     where
@@ -157,9 +235,128 @@ impl Command {
         self
     }
 
+    /// Limits the amount of wall-clock time the command may run.
+    ///
+    /// If the command runs longer than this, it is killed (along with any processes it started,
+    /// on Unix systems) and [`Error::TimedOut`] is returned.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Writes the provided content to the standard input of the command.
+    ///
+    /// Like arguments, secret content is redacted from anything the command outputs.
+    pub fn stdin<V: Into<Value>>(mut self, value: V) -> Self {
+        self.stdin = Some(value.into());
+        self
+    }
+
     /// Executes the command as a child process,
     /// waiting for it to finish and collecting all of its output.
     pub async fn output(&self) -> Result<Output, Error> {
+        if self.timeout.is_some() || self.stdin.is_some() {
+            self.output_piped(false).await
+        } else {
+            self.output_unbounded().await
+        }
+    }
+
+    /// Executes the command as a child process like [`Command::output`],
+    /// additionally emitting each line the child writes to traces as it is written.
+    ///
+    /// This is intended for long running commands, so that their progress is visible in the logs.
+    /// Lines are redacted before being traced, and lines longer than [`MAX_TRACED_LINE`] are truncated.
+    /// Progress updates (lines ending in a carriage return) are traced at most once per [`PROGRESS_INTERVAL`].
+    ///
+    /// Since progress updates can make stderr very large, only the last [`MAX_RETAINED_STDERR`]
+    /// of stderr is retained in the output.
+    pub async fn output_traced(&self) -> Result<Output, Error> {
+        self.output_piped(true).await
+    }
+
+    async fn output_piped(&self, traced: bool) -> Result<Output, Error> {
+        let mut cmd = self.as_cmd();
+        let redact = self.redaction_engine();
+        let name = self.name.to_string_lossy();
+
+        let stdin = match self.stdin {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        };
+        let mut child = cmd
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| Error::io(err, &redact))?;
+
+        // Read the output incrementally so that whatever was written before the timeout
+        // is available to describe the command if it times out.
+        let stdout_pipe = child.stdout.take();
+        let stderr_pipe = child.stderr.take();
+        let mut stdout = Capture::new(&name, "stdout", &redact, traced, None);
+        let mut stderr = Capture::new(
+            &name,
+            "stderr",
+            &redact,
+            traced,
+            traced.then_some(MAX_RETAINED_STDERR),
+        );
+        // Input is written concurrently with reading output, so that a child which writes
+        // before it has read all of its input can't deadlock.
+        let stdin_pipe = child.stdin.take();
+        let input = self
+            .stdin
+            .as_ref()
+            .map(|value| value.expose_secret().as_bytes());
+        let write = async {
+            if let (Some(mut pipe), Some(input)) = (stdin_pipe, input) {
+                pipe.write_all(input).await?;
+            }
+            Ok::<_, std::io::Error>(())
+        };
+        let run = async {
+            let (out, err, wrote) = tokio::join!(
+                stdout.read_from(stdout_pipe),
+                stderr.read_from(stderr_pipe),
+                write
+            );
+            out.and(err).and(wrote)?;
+            child.wait().await
+        };
+
+        let status = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, run).await,
+            None => Ok(run.await),
+        };
+
+        match status {
+            Ok(Ok(status)) => {
+                let output = std::process::Output {
+                    status,
+                    stdout: stdout.into_inner(),
+                    stderr: stderr.into_inner(),
+                };
+                Output::new(output, redact, self.describe()).wrap_ok()
+            }
+            Ok(Err(err)) => Err(Error::io(err, &redact)),
+            Err(_) => {
+                kill(&mut child).await;
+                let description = self
+                    .describe()
+                    .with_stdout(redact_str(&stdout.to_string_lossy(), &redact))
+                    .with_stderr(redact_str(&stderr.to_string_lossy(), &redact));
+                Err(Error::TimedOut {
+                    timeout: self.timeout.unwrap_or_default(),
+                    description: description.to_string(),
+                })
+            }
+        }
+    }
+
+    async fn output_unbounded(&self) -> Result<Output, Error> {
         let mut cmd = self.as_cmd();
         let redact = self.redaction_engine();
 
@@ -174,6 +371,8 @@ impl Command {
 
     /// Spawns the command as a child process, returning a handle to it
     /// that can be used to read the output in a streaming fashion.
+    ///
+    /// If a timeout is set, it is enforced by [`OutputStream::wait`].
     pub fn stream(&self) -> Result<OutputStream, Error> {
         let mut cmd = self.as_cmd();
         let engine = self.redaction_engine();
@@ -189,6 +388,9 @@ impl Command {
             child,
             engine,
             description: self.describe(),
+            deadline: self
+                .timeout
+                .map(|timeout| (timeout, Instant::now() + timeout)),
         })
     }
 
@@ -196,7 +398,18 @@ impl Command {
     ///
     /// Note that secrets are exposed as part of this; it's important to not
     /// log any of its output directly.
+    ///
+    /// The command is printed here if commands are echoed (see [`echo_commands`]), since it's about to run.
     fn as_cmd(&self) -> tokio::process::Command {
+        if ECHO_COMMANDS.load(Ordering::Relaxed) {
+            let pastable = self.describe().pastable();
+            let pastable = pastable.trim();
+            match &self.working_dir {
+                Some(dir) => eprintln!("$ (cd '{}' && {pastable})", dir.display()),
+                None => eprintln!("$ {pastable}"),
+            }
+        }
+
         let mut cmd = tokio::process::Command::new(&self.name);
 
         if let Some(working_dir) = &self.working_dir {
@@ -204,13 +417,13 @@ impl Command {
         }
 
         for arg in &self.args {
-            cmd.arg(arg.expose_secret());
+            cmd.arg(arg.as_os_str());
         }
 
         for (key, value) in &self.envs {
             match value {
                 Some(value) => {
-                    cmd.env(key, value.expose_secret());
+                    cmd.env(key, value.as_os_str());
                 }
                 None => {
                     cmd.env_remove(key);
@@ -218,6 +431,13 @@ impl Command {
             }
         }
 
+        // Commands with a timeout are run in their own process group,
+        // so that any processes they start can be killed along with them.
+        #[cfg(target_family = "unix")]
+        if self.timeout.is_some() {
+            cmd.process_group(0);
+        }
+
         cmd
     }
 
@@ -228,6 +448,7 @@ impl Command {
             .iter()
             .filter_map(|(_, v)| v.as_ref())
             .chain(self.args.iter())
+            .chain(self.stdin.iter())
             .cloned();
         redaction_engine(values)
     }
@@ -306,6 +527,9 @@ pub struct OutputStream {
     child: Child,
     engine: AhoCorasick,
     description: Description,
+
+    /// The timeout for the command, and the instant at which it expires.
+    deadline: Option<(Duration, Instant)>,
 }
 
 impl OutputStream {
@@ -340,11 +564,29 @@ impl OutputStream {
     }
 
     /// Wait for the child process to exit.
+    ///
+    /// If the command was configured with a timeout and the child process is still running
+    /// once it expires, the child is killed and [`Error::TimedOut`] is returned.
+    /// Since the caller owns the output streams, the error doesn't include the output.
     pub async fn wait(&mut self) -> Result<ExitStatus, Error> {
-        self.child
-            .wait()
-            .await
-            .map_err(|err| Error::io(err, &self.engine))
+        let Some((timeout, deadline)) = self.deadline else {
+            return self
+                .child
+                .wait()
+                .await
+                .map_err(|err| Error::io(err, &self.engine));
+        };
+
+        match tokio::time::timeout_at(deadline, self.child.wait()).await {
+            Ok(status) => status.map_err(|err| Error::io(err, &self.engine)),
+            Err(_) => {
+                kill(&mut self.child).await;
+                Err(Error::TimedOut {
+                    timeout,
+                    description: self.description.to_string(),
+                })
+            }
+        }
     }
 
     /// Create a redactor capable of redacting outputs for this command.
@@ -359,6 +601,148 @@ impl CommandDescriber for OutputStream {
     }
 }
 
+/// The maximum length of a single line emitted to traces by [`Command::output_traced`].
+/// Longer lines are truncated.
+pub const MAX_TRACED_LINE: usize = 4 * 1024;
+
+/// The maximum amount of stderr retained in the output of [`Command::output_traced`].
+/// Once exceeded, the oldest output is discarded.
+pub const MAX_RETAINED_STDERR: usize = 64 * 1024;
+
+/// The minimum interval between progress updates emitted to traces by [`Command::output_traced`].
+pub const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Captures an output stream of a child process, optionally emitting it to traces line by line.
+struct Capture<'a> {
+    command: &'a str,
+    stream: &'static str,
+    redact: &'a AhoCorasick,
+    traced: bool,
+    retain: Option<usize>,
+    buf: Vec<u8>,
+    line: Vec<u8>,
+    last_progress: Option<Instant>,
+}
+
+impl<'a> Capture<'a> {
+    fn new(
+        command: &'a str,
+        stream: &'static str,
+        redact: &'a AhoCorasick,
+        traced: bool,
+        retain: Option<usize>,
+    ) -> Self {
+        Self {
+            command,
+            stream,
+            redact,
+            traced,
+            retain,
+            buf: Vec::new(),
+            line: Vec::new(),
+            last_progress: None,
+        }
+    }
+
+    /// Read everything from the pipe.
+    ///
+    /// Unlike `read_to_end`, everything read is kept even if this future is cancelled.
+    async fn read_from<R: AsyncRead + Unpin>(&mut self, pipe: Option<R>) -> std::io::Result<()> {
+        let Some(mut pipe) = pipe else {
+            return Ok(());
+        };
+
+        let mut chunk = [0u8; 8 * 1024];
+        loop {
+            let read = pipe.read(&mut chunk).await?;
+            if read == 0 {
+                self.trace_line(false);
+                return Ok(());
+            }
+
+            let chunk = &chunk[..read];
+            self.buf.extend_from_slice(chunk);
+            if let Some(max) = self.retain {
+                let excess = self.buf.len().saturating_sub(max);
+                self.buf.drain(..excess);
+            }
+
+            if self.traced {
+                for &byte in chunk {
+                    match byte {
+                        b'\n' => self.trace_line(false),
+                        b'\r' => self.trace_line(true),
+                        _ if self.line.len() < MAX_TRACED_LINE => self.line.push(byte),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Emit the current line to traces, then start a new line.
+    fn trace_line(&mut self, progress: bool) {
+        if self.line.is_empty() {
+            return;
+        }
+
+        let throttled = progress
+            && self
+                .last_progress
+                .map(|last| last.elapsed() < PROGRESS_INTERVAL)
+                .unwrap_or(false);
+        if !throttled {
+            // Stdout is often machine readable and very long (e.g. `git ls-remote`),
+            // while stderr is where commands generally report their progress.
+            let line = redact_str(&String::from_utf8_lossy(&self.line), self.redact);
+            let line = line.trim_end();
+            if self.stream == "stderr" {
+                debug!(command = %self.command, stream = %self.stream, "{line}");
+            } else {
+                trace!(command = %self.command, stream = %self.stream, "{line}");
+            }
+            if progress {
+                self.last_progress = Some(Instant::now());
+            }
+        }
+
+        self.line.clear();
+    }
+
+    fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.buf).to_string()
+    }
+
+    fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Kill the child along with the rest of its process group.
+///
+/// Errors are ignored: the most likely cause is that the processes already exited.
+#[cfg(target_family = "unix")]
+async fn kill(child: &mut Child) {
+    use nix::{
+        sys::signal::{killpg, Signal},
+        unistd::Pid,
+    };
+
+    // The child is the leader of its own process group, so its process group ID is its process ID.
+    if let Some(id) = child.id().and_then(|id| i32::try_from(id).ok()) {
+        let _ = killpg(Pid::from_raw(id), Signal::SIGKILL);
+    }
+    let _ = child.kill().await;
+}
+
+/// Kill the child.
+///
+/// Errors are ignored: the most likely cause is that the process already exited.
+#[cfg(target_family = "windows")]
+async fn kill(child: &mut Child) {
+    let _ = child.kill().await;
+}
+
 /// Handles the possibility of being either a secret or a string.
 ///
 /// It's used for any value provided to a command:
@@ -368,6 +752,7 @@ impl CommandDescriber for OutputStream {
 /// When creating a new `CommandValue`, use the appropriate `new` function:
 /// - `new_secret`: This value is used as a secret and will be redacted from any debugging output.
 /// - `new_plain`: This value is used as plain text, and is not redacted.
+/// - `new_path`: This value is a path on the local file system, and is not redacted.
 ///
 /// `CommandValue` doesn't implement `From` for its input types, because it's possible
 /// that the user wants to create a `Secret` variant from a `String`,
@@ -393,6 +778,11 @@ pub enum Value {
 
     /// Plain text, not redacted in debugging output.
     Plain(String),
+
+    /// A path on the local file system, along with its lossy conversion to a string used for debugging output.
+    ///
+    /// The path is provided to the command as it is, so that paths which aren't valid UTF-8 can still be used.
+    Path(String, PathBuf),
 }
 
 impl Value {
@@ -505,12 +895,31 @@ impl Value {
         Self::Plain(value.into())
     }
 
+    /// Create a new instance as a `Path` variant.
+    ///
+    /// `Path` variants are not redacted in debugging output.
+    pub fn new_path<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        Self::Path(path.to_string_lossy().to_string(), path.to_path_buf())
+    }
+
     /// Used to view the inner value, potentially exposing the secret (if this is a secret value).
+    ///
+    /// Paths which aren't valid UTF-8 are converted lossily; use [`Value::as_os_str`] to provide them to commands.
     fn expose_secret(&self) -> &str {
         match self {
             Value::Secret(secret) => secret.expose_secret(),
             Value::SecretDisplay(_, secret) => secret.expose_secret(),
             Value::Plain(value) => value,
+            Value::Path(display, _) => display,
+        }
+    }
+
+    /// The value as it's provided to commands, potentially exposing the secret (if this is a secret value).
+    fn as_os_str(&self) -> &OsStr {
+        match self {
+            Value::Path(_, path) => path.as_os_str(),
+            value => OsStr::new(value.expose_secret()),
         }
     }
 }
@@ -521,6 +930,7 @@ impl Display for Value {
             Value::Secret(_) => write!(f, "{REDACTION_LITERAL}"),
             Value::SecretDisplay(plain, _) => write!(f, "{plain}"),
             Value::Plain(value) => write!(f, "{value}"),
+            Value::Path(display, _) => write!(f, "{display}"),
         }
     }
 }
@@ -839,6 +1249,11 @@ impl Redacter {
         Self { engine }
     }
 
+    /// Create a redaction engine for the provided secrets, which also redacts the installed redaction patterns.
+    pub fn for_secrets<I: IntoIterator<Item = Value>>(values: I) -> Self {
+        Self::new(redaction_engine(values))
+    }
+
     /// Redacts secrets provided to the original command from the provided string.
     pub fn redact_str(&self, input: &str) -> String {
         redact_str(input, &self.engine)
@@ -850,23 +1265,37 @@ impl Redacter {
     }
 }
 
-/// Generically redacts the provided bytes with any match found by the provided engine.
+/// Generically redacts the provided string with any match found by the provided engine,
+/// along with any match of the installed redaction patterns.
 fn redact_str(provided: &str, engine: &AhoCorasick) -> String {
     let mut redacted = String::new();
     engine.replace_all_with(provided, &mut redacted, |_, _, dst| {
         dst.push_str(REDACTION_LITERAL);
         true
     });
+    for pattern in EXTRA_REDACTIONS.get().into_iter().flatten() {
+        redacted = pattern
+            .text
+            .replace_all(&redacted, REDACTION_LITERAL)
+            .into_owned();
+    }
     redacted
 }
 
-/// Generically redacts the provided bytes with any match found by the provided engine.
+/// Generically redacts the provided bytes with any match found by the provided engine,
+/// along with any match of the installed redaction patterns.
 fn redact_bytes(provided: &[u8], engine: &AhoCorasick) -> Vec<u8> {
     let mut redacted = Vec::new();
     engine.replace_all_with_bytes(provided, &mut redacted, |_, _, dst| {
         dst.extend_from_slice(REDACTION_LITERAL.as_bytes());
         true
     });
+    for pattern in EXTRA_REDACTIONS.get().into_iter().flatten() {
+        redacted = pattern
+            .bytes
+            .replace_all(&redacted, REDACTION_LITERAL.as_bytes())
+            .into_owned();
+    }
     redacted
 }
 
@@ -914,4 +1343,113 @@ mod tests {
         let redacted = redact_bytes(provided, &engine);
         assert_eq!(redacted, expected);
     }
+
+    #[test]
+    fn redacts_installed_patterns() {
+        install_redaction_patterns(&["ghp_[A-Za-z0-9]{8,}"]).expect("must compile pattern");
+        let engine = redaction_engine([Value::new_secret("hunter2")]);
+
+        let provided = "cloning with hunter2, then echoed ghp_abcd1234efgh";
+        let expected = "cloning with <REDACTED>, then echoed <REDACTED>";
+        assert_eq!(redact_str(provided, &engine), expected);
+        assert_eq!(
+            redact_bytes(provided.as_bytes(), &engine),
+            expected.as_bytes()
+        );
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn times_out_with_partial_output() {
+        let secret = "hunter2";
+        let err = Command::new("sh")
+            .arg_plain("-c")
+            .arg_plain("echo started $SECRET; sleep 10")
+            .env_secret("SECRET", secret)
+            .timeout(Duration::from_millis(500))
+            .output()
+            .await
+            .expect_err("must time out");
+
+        let Error::TimedOut {
+            timeout,
+            description,
+        } = err
+        else {
+            panic!("must have timed out, got: {err:?}");
+        };
+        assert_eq!(timeout, Duration::from_millis(500));
+        assert!(
+            description.contains("started"),
+            "description: {description}"
+        );
+        assert!(!description.contains(secret), "description: {description}");
+    }
+
+    // macOS file systems reject names which aren't valid UTF-8.
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn passes_paths_which_are_not_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let tmp = tempfile::tempdir().expect("must create temp dir");
+        let dir = tmp.path().join(OsStr::from_bytes(b"clone-\xff"));
+        std::fs::create_dir(&dir).expect("must create dir");
+
+        let cmd = Command::new("sh")
+            .arg_plain("-c")
+            .arg_plain(r#"test -d "$1""#)
+            .arg_plain("sh")
+            .arg_path(&dir);
+        let output = cmd.output().await.expect("must run command");
+        assert!(output.status().success(), "must find dir");
+        assert!(cmd
+            .describe()
+            .args()
+            .iter()
+            .any(|arg| arg.contains("clone-\u{FFFD}")));
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn completes_within_timeout() {
+        let output = Command::new("sh")
+            .arg_plain("-c")
+            .arg_plain("echo done")
+            .timeout(Duration::from_secs(10))
+            .output()
+            .await
+            .expect("must run command");
+
+        assert!(output.status().success());
+        assert_eq!(output.stdout_string_lossy().trim(), "done");
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn traced_output_is_collected() {
+        let output = Command::new("sh")
+            .arg_plain("-c")
+            .arg_plain("printf 'one\\ntwo'; printf 'progress\\rdone\\n' >&2")
+            .output_traced()
+            .await
+            .expect("must run command");
+
+        assert!(output.status().success());
+        assert_eq!(output.stdout_string_lossy(), "one\ntwo");
+        assert_eq!(output.stderr_string_lossy(), "progress\rdone\n");
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn writes_stdin() {
+        let output = Command::new("cat")
+            .stdin(Value::new_secret("hunter2"))
+            .output()
+            .await
+            .expect("must run command");
+
+        assert!(output.status().success());
+        assert_eq!(output.stdout_string_lossy(), REDACTION_LITERAL);
+    }
 }
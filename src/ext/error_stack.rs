@@ -172,6 +172,32 @@ fn support_literal() -> String {
     "support:".bold().red().to_string()
 }
 
+/// Used to show the stable codes of the errors in a report.
+///
+/// Codes are meant to be quoted in support requests and matched by automation,
+/// so they're shown alongside a link to their documentation.
+pub trait ErrorCodeReference {
+    /// Show the code of each error in the report, with a link to its documentation.
+    fn error_codes(self) -> Self;
+}
+
+impl<T, C> ErrorCodeReference for error_stack::Result<T, C> {
+    fn error_codes(self) -> Self {
+        self.map_err(|report| {
+            let codes = doc::code::codes(&report);
+            codes.into_iter().fold(report, |report, code| {
+                let literal = code_literal();
+                let url = doc::link::error_code_reference(code);
+                report.attach_printable(format!("{literal} {code} ({url})"))
+            })
+        })
+    }
+}
+
+fn code_literal() -> String {
+    "code:".bold().cyan().to_string()
+}
+
 /// Extends [`Result`] to convert the [`Err`] variant to a [`Report`]
 /// and immediately change the context.
 pub trait IntoContext<C> {
@@ -21,8 +21,9 @@
 //! but still any call to `spawn_blocking` _may_ result in a spawned thread.
 
 use std::{
+    ffi::OsString,
     fmt,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf, Prefix},
 };
 
 use error_stack::{report, IntoReport, Report, ResultExt};
@@ -167,6 +168,74 @@ pub async fn home_dir() -> Result<&'static PathBuf, Report<Error>> {
     run_background(sync::home_dir).await
 }
 
+/// The length at which Windows stops accepting paths, unless they're in the extended-length form.
+const MAX_PATH: usize = 260;
+
+/// Convert the path to a form that isn't subject to the `MAX_PATH` limit on Windows,
+/// for providing to other programs such as FOSSA CLI.
+///
+/// On Windows, absolute paths of at least `MAX_PATH` characters are converted to the extended-length form
+/// (`\\?\C:\...`, or `\\?\UNC\server\share\...` for network shares), which Windows accepts regardless of length.
+/// Windows doesn't normalize paths in this form, so separators are normalized and `.` and `..` are resolved first.
+/// Other paths, and every path on other platforms, are returned unchanged.
+///
+/// Broker's own file system operations don't need this, since the standard library converts long paths itself.
+pub fn long_path(path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        extended_length(path).unwrap_or_else(|| path.to_path_buf())
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// The extended-length form of the path, if it's a long absolute path that isn't already in that form.
+fn extended_length(path: &Path) -> Option<PathBuf> {
+    if path.as_os_str().len() < MAX_PATH {
+        return None;
+    }
+
+    let mut components = path.components();
+    let mut extended = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(drive) => OsString::from(format!(r"\\?\{}:", char::from(drive))),
+            Prefix::UNC(server, share) => {
+                let mut extended = OsString::from(r"\\?\UNC\");
+                extended.push(server);
+                extended.push(r"\");
+                extended.push(share);
+                extended
+            }
+            // Verbatim paths are already in the extended-length form, and device paths can't be converted.
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    // Paths relative to the current directory of a drive, like `C:project`, can't be converted.
+    if components.next() != Some(Component::RootDir) {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    for component in components {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::ParentDir => {
+                parts.pop();
+            }
+            _ => {}
+        }
+    }
+    if parts.is_empty() {
+        extended.push(r"\");
+    }
+    for part in parts {
+        extended.push(r"\");
+        extended.push(part);
+    }
+    Some(PathBuf::from(extended))
+}
+
 /// Run the provided blocking closure in the background.
 #[tracing::instrument(skip_all)]
 async fn run_background<T, E, F>(work: F) -> Result<T, Report<Error>>
@@ -208,3 +277,34 @@ where
 {
     run_background(work).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(windows))]
+    #[test]
+    fn long_paths_are_unchanged() {
+        let path = PathBuf::from("/tmp").join("nested".repeat(MAX_PATH));
+        assert_eq!(long_path(&path), path);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn long_paths_are_extended() {
+        let nested = "nested\\".repeat(40);
+        let path = PathBuf::from(format!(r"C:\clones\{nested}..\repo"));
+        let expected = format!(r"\\?\C:\clones\{}repo", "nested\\".repeat(39));
+        assert_eq!(long_path(&path), PathBuf::from(expected));
+
+        let path = PathBuf::from(format!(r"\\server\share\{nested}"));
+        let expected = format!(r"\\?\UNC\server\share\{}", nested.trim_end_matches('\\'));
+        assert_eq!(long_path(&path), PathBuf::from(expected));
+
+        let short = PathBuf::from(r"C:\clones\repo");
+        assert_eq!(long_path(&short), short);
+
+        let verbatim = PathBuf::from(format!(r"\\?\C:\{nested}"));
+        assert_eq!(long_path(&verbatim), verbatim);
+    }
+}
@@ -36,6 +36,7 @@ use tempfile::NamedTempFile;
 use tracing::debug;
 
 use crate::{
+    encryption::{self, Key},
     ext::{
         error_stack::{DescribeContext, ErrorHelper, IntoContext},
         iter::{AlternativeIter, ChainOnceWithIter},
@@ -141,9 +142,14 @@ where
 /// The contents of the source file will have been written to the temp file and a best effort
 /// is made to sync the contents to disk before this function returns.
 ///
-/// The debug bundle is decompressed and prettified during this operation.
-#[tracing::instrument]
-pub fn copy_debug_bundle<P, Q>(file: P, rel: Q) -> Result<(NamedTempFile, PathBuf), Report<Error>>
+/// The debug bundle is decrypted with the key (if it was encrypted, see [`crate::encryption`]),
+/// decompressed, and prettified during this operation.
+#[tracing::instrument(skip(key))]
+pub fn copy_debug_bundle<P, Q>(
+    file: P,
+    rel: Q,
+    key: Option<&Key>,
+) -> Result<(NamedTempFile, PathBuf), Report<Error>>
 where
     P: AsRef<Path> + std::fmt::Debug,
     Q: AsRef<Path> + std::fmt::Debug,
@@ -154,8 +160,9 @@ where
         return (copy, rel.as_ref().to_path_buf()).wrap_ok();
     }
 
-    let source = File::open(file).context(Error::IO)?;
-    let mut reader = gzip::Decoder::new(source).context(Error::IO)?;
+    let source = fs::read(file).context(Error::IO)?;
+    let source = encryption::open(key, source).change_context(Error::IO)?;
+    let mut reader = gzip::Decoder::new(source.as_slice()).context(Error::IO)?;
     let data: Value = serde_json::from_reader(&mut reader).context(Error::IO)?;
 
     let mut copy = NamedTempFile::new().context(Error::IO)?;
@@ -171,8 +178,16 @@ where
 /// Locations searched:
 /// - The [`data_root`] location.
 /// - The [`working_dir`] location.
+///
+/// If a profile is selected, only its data root is searched,
+/// so that files in the working directory aren't shared between profiles.
 #[tracing::instrument]
 pub fn find(ctx: &AppContext, name: &str) -> Result<PathBuf, Report<Error>> {
+    if let Some(profile) = ctx.profile() {
+        return validate_file(ctx.data_root().join(name))
+            .describe_lazy(|| format!("searches the data root of profile '{profile}'"));
+    }
+
     iter::once_with(|| working_dir().map(|d| d.join(name)).and_then(validate_file))
         .chain_once_with(|| validate_file(ctx.data_root().join(name)))
         .alternative_fold()
@@ -0,0 +1,57 @@
+//! Extensions to `once_cell`.
+
+use std::fmt::Debug;
+
+use error_stack::{report, Report};
+use once_cell::sync::OnceCell;
+
+use super::{
+    error_stack::{DescribeContext, ErrorHelper},
+    result::WrapErr,
+};
+
+/// A process wide setting was installed with a value other than the one already installed.
+#[derive(Debug, thiserror::Error)]
+#[error("install {0}, which is already installed with a different value")]
+pub struct Conflict(&'static str);
+
+/// Installs process wide settings held in a [`OnceCell`].
+pub trait InstallOnce<T> {
+    /// Install the value of the setting named `name`.
+    ///
+    /// Installing the value that's already installed does nothing, so that instances of Broker
+    /// with the same settings can share a process. Installing a different value is an error rather than being ignored,
+    /// since everything in the process already relies on the installed one.
+    fn install(&self, name: &'static str, value: T) -> Result<(), Report<Conflict>>;
+}
+
+impl<T: PartialEq + Debug> InstallOnce<T> for OnceCell<T> {
+    fn install(&self, name: &'static str, value: T) -> Result<(), Report<Conflict>> {
+        let Err(value) = self.set(value) else {
+            return Ok(());
+        };
+        match self.get() {
+            Some(installed) if *installed == value => Ok(()),
+            installed => report!(Conflict(name))
+                .wrap_err()
+                .help("every instance of Broker in a process must use the same value for this setting")
+                .describe_lazy(|| format!("installed: {installed:?}, provided: {value:?}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn installs_the_same_value_again_but_not_another() {
+        let cell = OnceCell::new();
+        cell.install("setting", 1).expect("must install");
+        cell.install("setting", 1)
+            .expect("must install the same value again");
+        cell.install("setting", 2)
+            .expect_err("must not install another value");
+        assert_eq!(cell.get(), Some(&1));
+    }
+}
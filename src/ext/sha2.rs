@@ -0,0 +1,78 @@
+//! Extensions to the `sha2` crate, for verifying downloads against published checksums.
+
+use error_stack::{report, Report};
+use sha2::{Digest, Sha256};
+
+use crate::ext::{
+    error_stack::{DescribeContext, ErrorHelper},
+    result::{WrapErr, WrapOk},
+};
+
+/// Errors encountered verifying a checksum.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The published checksum wasn't in the expected format.
+    #[error("parse SHA-256 checksum")]
+    Parse,
+
+    /// The content doesn't match the published checksum.
+    #[error("checksum mismatch: expected '{expected}', but content has '{actual}'")]
+    Mismatch {
+        /// The published checksum.
+        expected: String,
+        /// The checksum of the content.
+        actual: String,
+    },
+}
+
+/// The hex encoded SHA-256 checksum of the content.
+pub fn sha256(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Parse a SHA-256 checksum file in the format written by `sha256sum`,
+/// which is the hex encoded checksum optionally followed by the name of the file.
+pub fn parse_sha256(content: &[u8]) -> Result<String, Report<Error>> {
+    let content = String::from_utf8_lossy(content);
+    let checksum = content.split_whitespace().next().unwrap_or_default();
+    if checksum.len() == 64 && checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+        checksum.to_lowercase().wrap_ok()
+    } else {
+        report!(Error::Parse)
+            .wrap_err()
+            .describe("checksum files are expected to contain a hex encoded SHA-256 checksum")
+    }
+}
+
+/// Verify that the content has the expected SHA-256 checksum.
+pub fn verify_sha256(content: &[u8], expected: &str) -> Result<(), Report<Error>> {
+    let actual = sha256(content);
+    if actual == expected {
+        Ok(())
+    } else {
+        report!(Error::Mismatch {
+            expected: expected.to_string(),
+            actual,
+        })
+        .wrap_err()
+        .help("the download may have been corrupted or tampered with; try again, or download it manually")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_checksum() {
+        let content = b"broker";
+        let checksum = sha256(content);
+        let file = format!("{}  broker-0.3.2-x86_64-linux\n", checksum.to_uppercase());
+
+        let parsed = parse_sha256(file.as_bytes()).expect("must parse");
+        assert_eq!(parsed, checksum);
+        verify_sha256(content, &parsed).expect("must verify");
+        assert!(verify_sha256(b"something else", &parsed).is_err());
+        assert!(parse_sha256(b"not a checksum").is_err());
+    }
+}
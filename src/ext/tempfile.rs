@@ -0,0 +1,392 @@
+//! Extensions to the `tempfile` crate.
+//!
+//! Temporary files and directories are normally removed when they are dropped,
+//! but if Broker is killed (for example, in the middle of a scan) this doesn't happen
+//! and the clones and other temporary content leak into the system temp location.
+//!
+//! To make it possible to clean these up later, Broker names every temporary item it creates
+//! with a common prefix followed by the process ID and a per-process instance ID.
+//! On startup (and periodically) Broker looks for items following this convention
+//! that are owned by some other instance which is no longer running, and removes them.
+//!
+//! Whether an instance is still running can't be told from its process ID: process IDs are reused,
+//! and in containers every instance of Broker is usually process 1. Instead, each instance holds a lock
+//! on a marker file in the system temp location, named the same way as its temporary items, for as long as it runs.
+//! The operating system releases the lock when the process exits, even if it's killed,
+//! so an instance whose marker isn't locked is no longer running.
+//!
+//! Temporary files holding secrets, like SSH keys, are created in a private directory under the data root instead;
+//! see [`secret_tempfile`].
+
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use bytesize::ByteSize;
+use error_stack::Report;
+use fs2::FileExt;
+use getset::CopyGetters;
+use once_cell::sync::{Lazy, OnceCell};
+use tempfile::{Builder, NamedTempFile, TempDir};
+use tracing::{debug, warn};
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+use super::once_cell::{Conflict, InstallOnce};
+
+/// The prefix of every temporary item created by Broker.
+const PREFIX: &str = "fossa-broker-";
+
+/// Identifies this instance of Broker.
+///
+/// The process ID alone isn't enough to identify ownership, since process IDs are reused.
+static INSTANCE: Lazy<String> = Lazy::new(|| {
+    let id = Uuid::new_v4().simple().to_string();
+    id[..8].to_string()
+});
+
+/// The marker of this instance, locked for as long as the process runs; `None` if it couldn't be created.
+///
+/// It's created before the first temporary item, so that other instances never see items without it.
+static MARKER: Lazy<Option<File>> = Lazy::new(|| {
+    let name = format!(
+        "{PREFIX}{}-{}-{MARKER_SUFFIX}",
+        std::process::id(),
+        *INSTANCE
+    );
+    let path = env::temp_dir().join(name);
+    let marker = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .and_then(|file| file.try_lock_exclusive().map(|_| file));
+    match marker {
+        Ok(file) => Some(file),
+        Err(err) => {
+            warn!(path = %path.display(), %err, "Unable to lock temporary item marker; temporary items left behind by this instance are removed once they're old");
+            None
+        }
+    }
+});
+
+/// Appended to the prefix of an instance to name its marker.
+const MARKER_SUFFIX: &str = "alive";
+
+/// The directory in which temporary files holding secrets are created, installed with [`install_secrets_root`].
+static SECRETS_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// If it can't be determined whether the owning instance is still running, for example because it was created by
+/// a version of Broker which didn't lock a marker, temporary items are considered orphaned once they are at least this old.
+pub const ORPHAN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Create a new temporary directory in the system temp location, owned by this instance of Broker.
+pub fn tempdir() -> io::Result<TempDir> {
+    Builder::new().prefix(&prefix()).tempdir()
+}
+
+/// Create a new named temporary file in the system temp location, owned by this instance of Broker.
+pub fn named_tempfile() -> io::Result<NamedTempFile> {
+    Builder::new().prefix(&prefix()).tempfile()
+}
+
+/// Create a new named temporary file with the provided suffix (such as a file extension)
+/// in the system temp location, owned by this instance of Broker.
+pub fn named_tempfile_with_suffix(suffix: &str) -> io::Result<NamedTempFile> {
+    Builder::new().prefix(&prefix()).suffix(suffix).tempfile()
+}
+
+/// Create temporary files holding secrets in the `secrets` directory under the data root,
+/// for every such file created after this is called.
+///
+/// There's one such directory for the process, so installing one under a different data root is an error.
+pub fn install_secrets_root(data_root: &Path) -> Result<(), Report<Conflict>> {
+    SECRETS_DIR.install("secrets directory", data_root.join("secrets"))
+}
+
+/// Create a new named temporary file to hold a secret, such as an SSH key, owned by this instance of Broker.
+///
+/// The secret must be written in plain text, since the program it's for (like `ssh`) reads the file itself.
+/// To limit who can read it, the file is only readable by the user running Broker,
+/// and is created in the directory installed with [`install_secrets_root`], which only that user can access;
+/// if none was installed, it's created in the system temp location.
+pub fn secret_tempfile() -> io::Result<NamedTempFile> {
+    match SECRETS_DIR.get() {
+        Some(dir) => secret_tempfile_in(dir),
+        None => named_tempfile(),
+    }
+}
+
+/// Create a new named temporary file to hold a secret inside `dir`, creating `dir` if it doesn't exist.
+fn secret_tempfile_in(dir: &Path) -> io::Result<NamedTempFile> {
+    fs::create_dir_all(dir)?;
+    restrict(dir, 0o700)?;
+    let file = Builder::new().prefix(&prefix()).tempfile_in(dir)?;
+    restrict(file.path(), 0o600)?;
+    Ok(file)
+}
+
+/// Set the permissions of the path to the mode.
+#[cfg(target_family = "unix")]
+fn restrict(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+/// Files are private to the user who created them on other platforms, unless inherited permissions allow otherwise.
+#[cfg(not(target_family = "unix"))]
+fn restrict(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// The result of pruning orphaned temporary items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct Summary {
+    /// The number of items removed.
+    removed: usize,
+
+    /// The amount of space reclaimed by removing items.
+    reclaimed: ByteSize,
+}
+
+/// Remove temporary items in the system temp location, and temporary files holding secrets,
+/// that were created by an instance of Broker which is no longer running.
+///
+/// Failing to remove an individual item is not an error; it's logged and retried next time.
+#[tracing::instrument]
+pub fn prune_orphaned(now: SystemTime) -> io::Result<Summary> {
+    let temp = env::temp_dir();
+
+    // Markers are in the system temp location, so secrets are pruned first, while the markers of their owners remain.
+    let mut summary = Summary::default();
+    if let Some(dir) = SECRETS_DIR.get().filter(|dir| dir.is_dir()) {
+        summary = prune_orphaned_with(dir, &temp, now)?;
+    }
+    let items = prune_orphaned_with(&temp, &temp, now)?;
+    summary.removed += items.removed;
+    summary.reclaimed = ByteSize::b(summary.reclaimed.as_u64() + items.reclaimed.as_u64());
+    Ok(summary)
+}
+
+/// Remove temporary items inside `dir` that were created by an instance of Broker
+/// which is no longer running, according to the markers inside `dir`.
+///
+/// Generally, prefer [`prune_orphaned`]; this is mainly useful for testing.
+#[tracing::instrument]
+pub fn prune_orphaned_in(dir: &Path, now: SystemTime) -> io::Result<Summary> {
+    prune_orphaned_with(dir, dir, now)
+}
+
+/// Remove temporary items inside `dir` that were created by an instance of Broker
+/// which is no longer running, according to the markers inside `markers`.
+fn prune_orphaned_with(dir: &Path, markers: &Path, now: SystemTime) -> io::Result<Summary> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let owner = Owner::parse(&entry.file_name().to_string_lossy())?;
+            Some((entry, owner))
+        })
+        .collect::<Vec<_>>();
+
+    // Markers are removed last, so that the other items of their owners are still found to be orphaned.
+    entries.sort_by_key(|(entry, _)| entry.file_name().to_string_lossy().ends_with(MARKER_SUFFIX));
+
+    let mut summary = Summary::default();
+    for (entry, owner) in entries {
+        let path = entry.path();
+        let modified = entry
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .unwrap_or(now);
+        if !owner.is_orphaned(markers, modified, now) {
+            continue;
+        }
+
+        let size = disk_usage(&path);
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+
+        match removed {
+            Ok(_) => {
+                debug!(path = %path.display(), ?owner, %size, "Removed orphaned temporary item");
+                summary.removed += 1;
+                summary.reclaimed = ByteSize::b(summary.reclaimed.as_u64() + size.as_u64());
+            }
+            Err(err) => {
+                warn!(path = %path.display(), %err, "Unable to remove orphaned temporary item");
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// The owner of a temporary item, as encoded in its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Owner {
+    pid: u32,
+    instance: String,
+}
+
+impl Owner {
+    /// Parse the owner from the name of a temporary item, like `fossa-broker-1234-abcd1234-XXXXXX`.
+    fn parse(name: &str) -> Option<Self> {
+        let rest = name.strip_prefix(PREFIX)?;
+        let mut parts = rest.splitn(3, '-');
+        let pid = parts.next()?.parse().ok()?;
+        let instance = parts.next()?.to_string();
+        Some(Self { pid, instance })
+    }
+
+    /// The marker of the owner inside `dir`.
+    fn marker(&self, dir: &Path) -> PathBuf {
+        dir.join(format!(
+            "{PREFIX}{}-{}-{MARKER_SUFFIX}",
+            self.pid, self.instance
+        ))
+    }
+
+    fn is_orphaned(&self, markers: &Path, modified: SystemTime, now: SystemTime) -> bool {
+        if self.instance == *INSTANCE {
+            return false;
+        }
+
+        match instance_running(&self.marker(markers)) {
+            Some(running) => !running,
+            None => now.duration_since(modified).unwrap_or_default() > ORPHAN_AGE,
+        }
+    }
+}
+
+/// Report whether the instance which locked the marker is still running, if this can be determined.
+fn instance_running(marker: &Path) -> Option<bool> {
+    let file = File::open(marker).ok()?;
+    match file.try_lock_shared() {
+        Ok(_) => {
+            if let Err(err) = file.unlock() {
+                debug!(path = %marker.display(), %err, "Unable to unlock temporary item marker");
+            }
+            Some(false)
+        }
+        Err(err) if err.raw_os_error() == fs2::lock_contended_error().raw_os_error() => Some(true),
+        Err(_) => None,
+    }
+}
+
+/// Compute the total size of the files at the path.
+fn disk_usage(path: &Path) -> ByteSize {
+    let bytes = WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len())
+        .sum();
+    ByteSize::b(bytes)
+}
+
+/// The prefix for temporary items owned by this instance.
+fn prefix() -> String {
+    Lazy::force(&MARKER);
+    format!("{PREFIX}{}-{}-", std::process::id(), *INSTANCE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_owner() {
+        let owner = Owner::parse("fossa-broker-1234-abcd1234-XyZ123").expect("must parse");
+        assert_eq!(owner.pid, 1234);
+        assert_eq!(owner.instance, "abcd1234");
+        assert_eq!(Owner::parse("fossa-broker-"), None);
+        assert_eq!(Owner::parse("some-other-dir"), None);
+    }
+
+    #[test]
+    fn current_instance_is_not_orphaned() {
+        let owner = Owner::parse(&prefix()).expect("must parse");
+
+        let later = SystemTime::now() + ORPHAN_AGE * 2;
+        assert!(!owner.is_orphaned(&env::temp_dir(), SystemTime::now(), later));
+    }
+
+    #[test]
+    fn running_instances_are_not_orphaned() {
+        let tmp = TempDir::new().expect("must create tempdir");
+
+        // Every instance in a container is usually process 1, so only the marker tells them apart.
+        let item = tmp.path().join(format!("{PREFIX}1-11111111-abc"));
+        fs::create_dir_all(&item).expect("must create item");
+        let owner = Owner::parse(&item.file_name().expect("must have name").to_string_lossy())
+            .expect("must parse");
+        let marker = File::create(owner.marker(tmp.path())).expect("must create marker");
+        marker.lock_exclusive().expect("must lock marker");
+
+        let later = SystemTime::now() + ORPHAN_AGE * 2;
+        let summary = prune_orphaned_in(tmp.path(), later).expect("must prune");
+        assert_eq!(summary.removed(), 0);
+        assert!(item.exists());
+
+        // Once the instance stops, the lock on its marker is released.
+        drop(marker);
+        let summary = prune_orphaned_in(tmp.path(), SystemTime::now()).expect("must prune");
+        assert_eq!(summary.removed(), 2);
+        assert!(!item.exists());
+        assert!(!owner.marker(tmp.path()).exists());
+    }
+
+    #[test]
+    fn prunes_other_instances() {
+        let tmp = TempDir::new().expect("must create tempdir");
+
+        // Use a process ID that can't be running, and a timestamp far enough in the future
+        // that this test passes regardless of whether process liveness can be determined.
+        let orphan = tmp
+            .path()
+            .join(format!("{PREFIX}{}-00000000-abc", u32::MAX));
+        fs::create_dir_all(&orphan).expect("must create orphan");
+        fs::write(orphan.join("file"), [0u8; 10]).expect("must write file");
+
+        let unrelated = tmp.path().join("unrelated");
+        fs::create_dir_all(&unrelated).expect("must create unrelated");
+
+        let later = SystemTime::now() + ORPHAN_AGE * 2;
+        let summary = prune_orphaned_in(tmp.path(), later).expect("must prune");
+        assert_eq!(summary.removed(), 1);
+        assert_eq!(summary.reclaimed(), ByteSize::b(10));
+        assert!(!orphan.exists());
+        assert!(unrelated.exists());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn secrets_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = TempDir::new().expect("must create tempdir");
+        let dir = tmp.path().join("secrets");
+        let file = secret_tempfile_in(&dir).expect("must create secret file");
+        assert_eq!(file.path().parent(), Some(dir.as_path()));
+
+        let mode = |path: &Path| {
+            fs::metadata(path)
+                .expect("must read metadata")
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(file.path()), 0o600);
+    }
+}
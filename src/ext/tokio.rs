@@ -0,0 +1,82 @@
+//! Extensions to `tokio`.
+
+use std::{future::Future, sync::Arc, time::Duration};
+
+use tokio::sync::watch;
+
+/// Signals long running tasks to stop.
+///
+/// Clones share the same state, so cancelling any clone cancels all of them.
+/// Tasks check for cancellation cooperatively, usually at the points at which they'd otherwise wait.
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl CancellationToken {
+    /// Create a token which hasn't been cancelled.
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+
+    /// Cancel the token, stopping anything waiting on it.
+    pub fn cancel(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Wait until the token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.sender.subscribe();
+        // The sender is held by `self`, so this only returns once the token is cancelled.
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+
+    /// Run the future to completion, unless the token is cancelled first.
+    /// Returns `None` if the token was cancelled, in which case the future is dropped.
+    pub async fn run_until_cancelled<F: Future>(&self, future: F) -> Option<F::Output> {
+        tokio::select! {
+            biased;
+            _ = self.cancelled() => None,
+            output = future => Some(output),
+        }
+    }
+
+    /// Sleep for the duration, unless the token is cancelled first.
+    /// Returns `false` if the token was cancelled.
+    pub async fn sleep(&self, duration: Duration) -> bool {
+        self.run_until_cancelled(tokio::time::sleep(duration))
+            .await
+            .is_some()
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stops_waiting_when_cancelled() {
+        let token = CancellationToken::new();
+        let handle = token.clone();
+        assert!(!token.is_cancelled());
+
+        tokio::spawn(async move { handle.cancel() });
+        assert!(!token.sleep(Duration::from_secs(60 * 60)).await);
+        assert!(token.is_cancelled());
+        assert_eq!(token.run_until_cancelled(async { 1 }).await, None);
+    }
+}
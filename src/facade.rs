@@ -0,0 +1,297 @@
+//! A small, stable API for driving Broker from other Rust programs.
+//!
+//! The rest of this library may make breaking changes on any release,
+//! but this module follows semantic versioning:
+//! items in it are only removed or changed in a breaking way when the major version of Broker changes.
+//! To make that possible, nothing in this module exposes types from the rest of the library;
+//! everything it accepts or returns is defined here, and new fields or variants may be added in minor releases.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use broker::facade::{Broker, CancellationToken, Options, Selection};
+//!
+//! # async fn example() -> Result<(), error_stack::Report<broker::facade::Error>> {
+//! let options = Options::builder()
+//!     .config_path("/home/me/.config/fossa/broker/config.yml")
+//!     .database_path("/home/me/.config/fossa/broker/db.sqlite")
+//!     .data_root("/home/me/.config/fossa/broker")
+//!     .build();
+//! let broker = Broker::load(options).await?;
+//!
+//! // Scan the integrations in the "payments" group once.
+//! broker.scan(Selection::default().group("payments")).await?;
+//!
+//! // Run until cancelled.
+//! let token = CancellationToken::new();
+//! let handle = token.clone();
+//! tokio::spawn(async move {
+//!     tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+//!     handle.cancel();
+//! });
+//! broker.run(token).await?;
+//!
+//! for integration in broker.status().await?.integrations() {
+//!     println!("{}: {} recent scan(s)", integration.remote(), integration.recent_scans().len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{path::PathBuf, time::Duration};
+
+use error_stack::{Report, ResultExt};
+use getset::{CopyGetters, Getters};
+use typed_builder::TypedBuilder;
+
+use crate::{
+    config::{self, Config},
+    db::{self, Database, PolicyStatus},
+    ext::error_stack::DescribeContext,
+    AppContext,
+};
+
+/// The number of recent scans reported for each integration by [`Broker::status`].
+const RECENT_SCANS: u32 = 10;
+
+/// Errors encountered driving Broker.
+///
+/// Variants may be added in minor releases.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The config file couldn't be loaded.
+    #[error("load config file")]
+    LoadConfig,
+
+    /// The settings in the config file couldn't be applied.
+    #[error("apply config file")]
+    ApplyConfig,
+
+    /// The database couldn't be opened.
+    #[error("connect to database")]
+    ConnectDatabase,
+
+    /// Scanning integrations failed.
+    #[error("scan integrations")]
+    Scan,
+
+    /// Running Broker failed.
+    #[error("run Broker")]
+    Run,
+
+    /// The status of integrations couldn't be determined.
+    #[error("query status")]
+    Status,
+}
+
+/// The locations from which Broker loads its config and state.
+///
+/// Unlike the `broker` binary, these are never discovered; each must be provided.
+#[derive(Debug, Clone, PartialEq, Eq, TypedBuilder)]
+pub struct Options {
+    /// The path to the Broker config file.
+    #[builder(setter(into))]
+    config_path: PathBuf,
+
+    /// The path to the Broker database file, which is created if it doesn't exist.
+    #[builder(setter(into))]
+    database_path: PathBuf,
+
+    /// The root data directory, in which Broker stores working state.
+    #[builder(setter(into))]
+    data_root: PathBuf,
+}
+
+/// Selects the integrations scanned by [`Broker::scan`].
+///
+/// By default every integration is selected, and only references that changed since they were last scanned are scanned.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    group: Option<String>,
+    integration: Option<String>,
+    all: bool,
+}
+
+impl Selection {
+    /// Only scan integrations in the named group.
+    pub fn group(mut self, group: impl Into<String>) -> Self {
+        self.group = Some(group.into());
+        self
+    }
+
+    /// Only scan the integration with this remote, as it is written in the config file.
+    pub fn integration(mut self, remote: impl Into<String>) -> Self {
+        self.integration = Some(remote.into());
+        self
+    }
+
+    /// Scan every reference, even those which haven't changed since they were last scanned.
+    pub fn all(mut self) -> Self {
+        self.all = true;
+        self
+    }
+}
+
+/// Signals [`Broker::run`] to stop.
+///
+/// Clones share the same state, so a clone can be handed to another task and cancelled from there.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(crate::ext::tokio::CancellationToken);
+
+impl CancellationToken {
+    /// Create a token which hasn't been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the token, stopping anything waiting on it.
+    pub fn cancel(&self) {
+        self.0.cancel();
+    }
+
+    /// Whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.is_cancelled()
+    }
+
+    /// Wait until the token is cancelled.
+    pub async fn cancelled(&self) {
+        self.0.cancelled().await;
+    }
+}
+
+/// The status of each configured integration, as reported by [`Broker::status`].
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct Status {
+    /// The status of each integration, in the order they are configured.
+    integrations: Vec<IntegrationStatus>,
+}
+
+/// The status of a single integration.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct IntegrationStatus {
+    /// The remote of the integration, as it is written in the config file.
+    remote: String,
+
+    /// The group to which the integration belongs, if any.
+    group: Option<String>,
+
+    /// The most recent scans of the integration, newest first.
+    recent_scans: Vec<Scan>,
+}
+
+/// A completed scan.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct Scan {
+    /// The ID of the scan.
+    #[getset(get = "pub")]
+    id: String,
+
+    /// How long the reference took to clone.
+    #[getset(get_copy = "pub")]
+    clone_duration: Duration,
+
+    /// How long the reference took to analyze.
+    #[getset(get_copy = "pub")]
+    analyze_duration: Duration,
+
+    /// Whether the uploaded scan passed its check for issues in FOSSA, if it was checked.
+    #[getset(get_copy = "pub")]
+    policy: Option<PolicyStatus>,
+}
+
+/// A loaded instance of Broker.
+#[derive(Debug, Clone)]
+pub struct Broker {
+    config: Config,
+    database_path: PathBuf,
+    context: AppContext,
+}
+
+impl Broker {
+    /// Load and validate the config file, then apply its settings the same way the `broker` binary does.
+    ///
+    /// Some settings are shared by the whole process: the crypto mode, git backend, bandwidth limit,
+    /// and the directory for temporary files holding secrets under the data root.
+    /// Every instance loaded in a process must use the same values for them;
+    /// loading one that doesn't fails with [`Error::ApplyConfig`].
+    pub async fn load(options: Options) -> Result<Self, Report<Error>> {
+        let config = Config::load(&options.config_path)
+            .await
+            .change_context(Error::LoadConfig)
+            .describe_lazy(|| format!("config file: '{}'", options.config_path.display()))?;
+        let context = AppContext::new(options.data_root).change_context(Error::ApplyConfig)?;
+        let context = config::apply(&context, &config).change_context(Error::ApplyConfig)?;
+        Ok(Self {
+            config,
+            database_path: options.database_path,
+            context,
+        })
+    }
+
+    /// Poll the selected integrations once, scanning and uploading each reference that needs it.
+    pub async fn scan(&self, selection: Selection) -> Result<(), Report<Error>> {
+        let db = self.connect().await?;
+        crate::cmd::scan::main(
+            &self.context,
+            self.config.clone(),
+            db,
+            selection.group.as_deref(),
+            selection.integration.as_deref(),
+            selection.all,
+        )
+        .await
+        .change_context(Error::Scan)
+    }
+
+    /// Run Broker until the token is cancelled, the same as `broker run`.
+    ///
+    /// Once cancelled, Broker stops at the next point at which it would otherwise wait;
+    /// references that were being scanned are scanned again the next time Broker runs.
+    pub async fn run(&self, token: CancellationToken) -> Result<(), Report<Error>> {
+        let db = self.connect().await?;
+        crate::cmd::run::main(&self.context, self.config.clone(), db, token.0)
+            .await
+            .change_context(Error::Run)
+    }
+
+    /// Report the status of each configured integration.
+    pub async fn status(&self) -> Result<Status, Report<Error>> {
+        let db = self.connect().await?;
+        let mut integrations = Vec::new();
+        for integration in self.config.integrations().iter() {
+            let recent_scans = db
+                .recent_scans(
+                    &integration.namespace(),
+                    &integration.repository(),
+                    RECENT_SCANS,
+                )
+                .await
+                .change_context(Error::Status)?
+                .into_iter()
+                .map(|scan| Scan {
+                    id: scan.scan_id().clone(),
+                    clone_duration: scan.clone_duration(),
+                    analyze_duration: scan.analyze_duration(),
+                    policy: scan.policy(),
+                })
+                .collect();
+            integrations.push(IntegrationStatus {
+                remote: integration.remote().to_string(),
+                group: integration.group().clone(),
+                recent_scans,
+            });
+        }
+        Ok(Status { integrations })
+    }
+
+    async fn connect(&self) -> Result<impl Database, Report<Error>> {
+        db::connect_sqlite(&self.database_path, *self.config.database())
+            .await
+            .change_context(Error::ConnectDatabase)
+            .describe_lazy(|| format!("database file: '{}'", self.database_path.display()))
+    }
+}
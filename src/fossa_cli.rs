@@ -11,18 +11,34 @@ use serde_json::Value;
 use std::fmt::Debug;
 use std::io::{Cursor, Read};
 use std::path::{Path, PathBuf};
-use tempfile::tempdir;
+use std::time::Duration;
 use tokio::fs;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tracing::{debug, warn};
+use url::Url;
 
-use crate::ext::command::{Command, CommandDescriber, OutputProvider};
+use crate::api::http::client::{Client, Purpose};
+use crate::api::remote::{CliEnv, CliOptions};
+use crate::doc::code::{ErrorCode, HasErrorCode};
+use crate::ext::command::{self, Command, CommandDescriber, OutputProvider};
 use crate::ext::error_stack::{DescribeContext, ErrorHelper, IntoContext};
-use crate::ext::io::{spawn_blocking, spawn_blocking_wrap};
+use crate::ext::io::{self, spawn_blocking, spawn_blocking_wrap};
 use crate::ext::result::DiscardResult;
 use crate::ext::result::{WrapErr, WrapOk};
+use crate::ext::sha2;
+use crate::ext::tempfile::tempdir;
 use crate::ext::tracing::span_record;
-use crate::{debug, AppContext};
+use crate::{
+    bandwidth, debug,
+    encryption::{self, Key},
+    AppContext,
+};
+
+/// The number of times a download of FOSSA CLI is attempted (resuming where the last attempt left off) before giving up.
+const DOWNLOAD_ATTEMPTS: u32 = 5;
+
+/// How long to wait before resuming an interrupted download; this is multiplied by the number of attempts so far.
+const DOWNLOAD_RETRY_DELAY: Duration = Duration::from_secs(2);
 
 /// Errors while downloading fossa-cli
 #[derive(Debug, thiserror::Error)]
@@ -65,6 +81,15 @@ pub enum Error {
     #[error("download FOSSA CLI from github")]
     Download,
 
+    /// Once FOSSA CLI is downloaded, Broker verifies it against the checksum published with the release
+    /// before extracting it, so that a corrupted or tampered download is never installed.
+    #[error("verify FOSSA CLI download against its published checksum")]
+    Verify,
+
+    /// Downloaded FOSSA CLI archives are cached in the data root, so that they don't have to be downloaded again.
+    #[error("manage FOSSA CLI download cache at '{}'", .0.display())]
+    Cache(PathBuf),
+
     /// Once FOSSA CLI is downloaded, Broker must extract it from an archive into a tmpfile
     #[error("extract FOSSA CLI archive")]
     Extract,
@@ -88,6 +113,27 @@ pub enum Error {
     ParseOutput(String),
 }
 
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::FindVersion => ErrorCode::new(2001),
+            Self::CreateTempDir(..) => ErrorCode::new(2002),
+            Self::Execution(..) => ErrorCode::new(2003),
+            Self::ParseRedirect(..) => ErrorCode::new(2004),
+            Self::ParseVersion => ErrorCode::new(2005),
+            Self::VersionOutputFormat => ErrorCode::new(2006),
+            Self::DeterminedTagFormat(..) => ErrorCode::new(2007),
+            Self::Download => ErrorCode::new(2008),
+            Self::Verify => ErrorCode::new(2009),
+            Self::Cache(..) => ErrorCode::new(2010),
+            Self::Extract => ErrorCode::new(2011),
+            Self::FinalCopy(..) => ErrorCode::new(2012),
+            Self::ReadOutput => ErrorCode::new(2013),
+            Self::ParseOutput(..) => ErrorCode::new(2014),
+        }
+    }
+}
+
 impl Error {
     fn create_temp_dir() -> Self {
         Self::CreateTempDir(std::env::temp_dir())
@@ -98,6 +144,65 @@ impl Error {
     }
 }
 
+/// Errors that are possibly surfaced during validation of config values.
+#[derive(Debug, thiserror::Error)]
+pub enum ValidationError {
+    /// The download base URL must be a valid HTTP or HTTPS URL.
+    #[error("invalid download base url")]
+    DownloadBaseUrl,
+}
+
+impl HasErrorCode for ValidationError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::DownloadBaseUrl => ErrorCode::new(2051),
+        }
+    }
+}
+
+/// The location from which FOSSA CLI releases are downloaded by default.
+const GITHUB_RELEASES: &str = "https://github.com/fossas/fossa-cli/releases";
+
+/// Validated config values for downloading FOSSA CLI.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Config {
+    /// The location of a mirror of the FOSSA CLI releases to use instead of GitHub.
+    download_base_url: Option<Url>,
+}
+
+impl Config {
+    /// Create the config, validating the download base URL if one is provided.
+    pub fn new(download_base_url: Option<String>) -> Result<Self, ValidationError> {
+        let Some(url) = download_base_url else {
+            return Ok(Self::default());
+        };
+
+        match Url::parse(&url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(Self {
+                download_base_url: Some(parsed),
+            }),
+            _ => report!(ValidationError::DownloadBaseUrl)
+                .wrap_err()
+                .help("the download base url must be a full http or https url, such as 'https://artifactory.internal/fossa-cli'")
+                .describe_lazy(|| format!("provided value: {url}")),
+        }
+    }
+
+    /// The location of a mirror of the FOSSA CLI releases, if one is configured.
+    pub fn download_base_url(&self) -> Option<&Url> {
+        self.download_base_url.as_ref()
+    }
+
+    /// The location from which releases are downloaded,
+    /// which has the same layout as the releases on GitHub.
+    fn releases(&self) -> String {
+        match &self.download_base_url {
+            Some(url) => url.as_str().trim_end_matches('/').to_string(),
+            None => GITHUB_RELEASES.to_string(),
+        }
+    }
+}
+
 /// Which version of the fossa-cli you want to download.
 /// Currently, this is always the latest version
 #[derive(Debug, Clone)]
@@ -125,8 +230,10 @@ impl From<AnalysisResult> for SourceUnits {
 /// FOSSA CLI outputs "source units".
 ///
 /// Each source unit is a dependency graph in a specific format.
-/// Broker doesn't actually inspect these units, it just passes them through.
-#[derive(Debug, derive_more::Display)]
+/// Broker doesn't actually inspect these units, it just passes them through;
+/// the only exceptions are checking them for secrets (see [`crate::secrets`])
+/// and checking their size (see [`crate::size_limits`]) before they're uploaded.
+#[derive(Debug, Clone, derive_more::Display, derive_more::From)]
 pub struct SourceUnits(Value);
 
 impl SourceUnits {
@@ -134,6 +241,11 @@ impl SourceUnits {
     pub fn is_empty(&self) -> bool {
         self.0.as_array().map(|arr| arr.is_empty()).unwrap_or(true)
     }
+
+    /// The source units as FOSSA CLI rendered them.
+    pub fn as_json(&self) -> &Value {
+        &self.0
+    }
 }
 
 impl<'de> Deserialize<'de> for SourceUnits {
@@ -155,7 +267,7 @@ impl Serialize for SourceUnits {
 }
 
 /// The FOSSA CLI version.
-#[derive(Debug, Clone, derive_more::Display, PartialEq, Eq)]
+#[derive(Debug, Clone, derive_more::Display, derive_more::From, PartialEq, Eq)]
 pub struct Version(semver::Version);
 
 impl Version {
@@ -200,6 +312,7 @@ impl Serialize for Version {
 pub struct Location {
     cli: PathBuf,
     artifacts: debug::Root,
+    encryption: Option<Key>,
 }
 
 impl Location {
@@ -212,9 +325,16 @@ impl Location {
         Self {
             cli: path,
             artifacts: artifact_root.to_owned(),
+            encryption: None,
         }
     }
 
+    /// Encrypt the debug bundles stored for each scan with the key, if one is provided.
+    pub fn with_encryption(mut self, key: Option<Key>) -> Self {
+        self.encryption = key;
+        self
+    }
+
     /// Report the version of FOSSA CLI.
     #[tracing::instrument]
     pub async fn version(&self) -> Result<Version, Error> {
@@ -225,8 +345,17 @@ impl Location {
     ///
     /// FOSSA CLI log output is streamed into the traces for this function as `trace` logs.
     /// It also automatically places the debug bundle in the appropriate location for the scan.
+    ///
+    /// The provided environment variables are set for FOSSA CLI; secret values are redacted from its output.
+    /// The provided options are passed to FOSSA CLI as arguments.
     #[tracing::instrument]
-    pub async fn analyze(&self, scan_id: &str, project: &Path) -> Result<SourceUnits, Error> {
+    pub async fn analyze(
+        &self,
+        scan_id: &str,
+        project: &Path,
+        env: &[CliEnv],
+        options: &CliOptions,
+    ) -> Result<SourceUnits, Error> {
         let tmp = tempdir().context_lazy(Error::create_temp_dir)?;
 
         // Set the CLI to run in the temporary directory so that it creates the debug bundle there,
@@ -249,7 +378,12 @@ impl Location {
             .arg_plain("--debug")
             .arg_plain("--output")
             .arg_plain("--static-only-analysis")
-            .arg_plain(project.to_string_lossy());
+            .args(options.args().into_iter().map(command::Value::new_plain))
+            .arg_path(io::long_path(project))
+            .envs(
+                env.iter()
+                    .map(|var| (var.name().clone(), var.value().to_command_value())),
+            );
         let mut stream = cmd.stream().context_lazy(|| Error::running_cli(&cmd))?;
         let redacter = stream.redacter();
 
@@ -309,7 +443,8 @@ impl Location {
 
         // Copy the debug bundle to the correct location.
         // Don't error the process if this fails, as it's not critical to the scan process.
-        // We're copying instead of moving because on Linux, it's likely these are at different mount points.
+        // We're copying instead of moving because on Linux, it's likely these are at different mount points,
+        // and because the copy is encrypted if an encryption key is configured.
         let debug_bundle = tmp.path().join("fossa.debug.json.gz");
         let destination = self.artifacts.debug_bundle(scan_id);
         if let Err(err) = fs::create_dir_all(self.artifacts.as_path()).await {
@@ -319,7 +454,7 @@ impl Location {
             );
         }
 
-        match fs::copy(&debug_bundle, &destination).await {
+        match store_debug_bundle(&debug_bundle, &destination, self.encryption.as_ref()).await {
             Ok(_) => debug!("stored FOSSA CLI debug bundle at {destination:?}"),
             Err(err) => {
                 warn!("failed to store FOSSA CLI debug bundle at {destination:?}: {err:#}")
@@ -334,6 +469,18 @@ impl Location {
     }
 }
 
+/// Copy the debug bundle written by FOSSA CLI to the destination, encrypting it if a key is provided.
+async fn store_debug_bundle(
+    source: &Path,
+    destination: &Path,
+    key: Option<&Key>,
+) -> std::io::Result<()> {
+    let content = fs::read(source).await?;
+    let content = encryption::seal(key, content)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, format!("{err:#}")))?;
+    fs::write(destination, content).await
+}
+
 /// Find the location of the FOSSA CLI, downloading it if it doesn't exist or is outdated.
 /// If it is downloaded, it is placed in the data root of the provided [`AppContext`].
 ///
@@ -344,6 +491,7 @@ impl Location {
 #[tracing::instrument]
 pub async fn find_or_download(
     ctx: &AppContext,
+    config: &Config,
     artifact_root: &debug::Root,
     desired_version: DesiredVersion,
 ) -> Result<Location, Error> {
@@ -365,12 +513,13 @@ pub async fn find_or_download(
 
     // If the CLI isn't already local, download it.
     let Some(current_path) = current_path else {
-        return download(ctx, artifact_root, desired_version).await;
+        return download(ctx, config, artifact_root, desired_version).await;
     };
 
     // Now we know the CLI exists locally, check if it matches the desired version.
     // If so, use its path. If not, download the desired version and use it.
-    let resolved_version = resolve_version(&desired_version).await?;
+    let client = ctx.http().get(Purpose::FossaCli);
+    let resolved_version = resolve_version(client, config, &desired_version).await?;
     match local_version(&current_path).await {
         Ok(local_version) if local_version.to_string() == resolved_version => {
             debug!(
@@ -387,14 +536,14 @@ pub async fn find_or_download(
                 local_version,
                 resolved_version,
             );
-            download(ctx, artifact_root, desired_version).await
+            download(ctx, config, artifact_root, desired_version).await
         }
         Err(err) => {
             debug!(
                 "Error while getting version from local fossa-cli at {}: {err:#}. Downloading new version",
                 current_path.display()
             );
-            download(ctx, artifact_root, desired_version).await
+            download(ctx, config, artifact_root, desired_version).await
         }
     }
 }
@@ -408,18 +557,24 @@ pub async fn find_or_download(
 #[tracing::instrument]
 pub async fn download(
     ctx: &AppContext,
+    config: &Config,
     artifact_root: &debug::Root,
     desired_version: DesiredVersion,
 ) -> Result<Location, Error> {
-    let resolved_version = resolve_version(&desired_version).await?;
-    let path = download_tag(ctx, &resolved_version).await?;
+    let client = ctx.http().get(Purpose::FossaCli);
+    let resolved_version = resolve_version(client, config, &desired_version).await?;
+    let path = download_tag(ctx, client, config, &resolved_version).await?;
     Location::new(path, artifact_root).wrap_ok()
 }
 
 /// Resolve a [`DesiredVersion`] to a concrete version.
-async fn resolve_version(desired_version: &DesiredVersion) -> Result<String, Error> {
+async fn resolve_version(
+    client: &Client,
+    config: &Config,
+    desired_version: &DesiredVersion,
+) -> Result<String, Error> {
     match desired_version {
-        DesiredVersion::Latest => latest_release_version().await,
+        DesiredVersion::Latest => latest_release_version_from(client, config.releases()).await,
     }
 }
 
@@ -474,29 +629,60 @@ fn command_name() -> &'static str {
 }
 
 /// Get the version of the latest release on GitHub.
-#[tracing::instrument]
-#[cached(time = 3600, sync_writes = true, result = true)]
-pub async fn latest_release_version() -> Result<String, Error> {
-    let client = reqwest::Client::new();
+pub async fn latest_release_version(client: &Client) -> Result<String, Error> {
+    latest_release_version_from(client, GITHUB_RELEASES.to_string()).await
+}
+
+/// Get the version of the latest release from `releases`, which has the same layout as the releases on GitHub.
+///
+/// GitHub redirects the 'latest' pseudo-tag to the tag of the latest release;
+/// mirrors which can't redirect may instead respond to it with the tag (like `v3.7.2`) as plain text.
+#[tracing::instrument(skip(client))]
+#[cached(
+    time = 3600,
+    sync_writes = true,
+    result = true,
+    key = "String",
+    convert = r#"{ releases.clone() }"#
+)]
+async fn latest_release_version_from(client: &Client, releases: String) -> Result<String, Error> {
     // This will follow the redirect, so latest_release_response.url().path() will be something like "/fossas/fossa-cli/releases/tag/v3.7.2"
     let latest_release_response = client
-        .get("https://github.com/fossas/fossa-cli/releases/latest")
-        .header(reqwest::header::ACCEPT, "application/json")
-        .send()
+        .send(
+            client
+                .get(format!("{releases}/latest"))
+                .header(reqwest::header::ACCEPT, "application/json"),
+        )
         .await
+        .and_then(|response| response.error_for_status())
         .context(Error::FindVersion)
-        .describe("uses Github's 'latest' pseudo-tag to determine the latest release")?;
-    let path = latest_release_response.url().path();
+        .describe_lazy(|| {
+            format!("uses the 'latest' pseudo-tag at '{releases}' to determine the latest release")
+        })?;
+    let path = latest_release_response.url().path().to_string();
 
     let tag = path
         .rsplit('/')
         .next()
-        .ok_or_else(|| Error::ParseRedirect(String::from(path)))
+        .ok_or_else(|| Error::ParseRedirect(path.clone()))
         .context(Error::FindVersion)
-        .describe("uses the 'latest' pseudo-tag on Github to determine the tag representing the latest release")?;
+        .describe(
+            "uses the 'latest' pseudo-tag to determine the tag representing the latest release",
+        )?;
+
+    let tag = if tag.starts_with('v') {
+        tag.to_string()
+    } else {
+        let body = latest_release_response
+            .text()
+            .await
+            .context(Error::FindVersion)
+            .describe("the 'latest' pseudo-tag wasn't redirected, so its content is read as the tag of the latest release")?;
+        body.trim().to_string()
+    };
 
     if !tag.starts_with('v') {
-        return Error::DeterminedTagFormat(String::from(tag))
+        return Error::DeterminedTagFormat(tag)
             .wrap_err()
             .context(Error::FindVersion);
     }
@@ -504,49 +690,209 @@ pub async fn latest_release_version() -> Result<String, Error> {
 }
 
 /// Download the CLI into the config_dir
-#[tracing::instrument]
-async fn download_tag(ctx: &AppContext, version: &str) -> Result<PathBuf, Error> {
-    let content = download_from_github(version).await?;
+#[tracing::instrument(skip(client))]
+async fn download_tag(
+    ctx: &AppContext,
+    client: &Client,
+    config: &Config,
+    version: &str,
+) -> Result<PathBuf, Error> {
+    let download_url = download_url(&config.releases(), version);
+    let checksum = download_from_github(client, &format!("{download_url}.sha256")).await?;
+    let checksum = sha2::parse_sha256(checksum.get_ref())
+        .change_context(Error::Verify)
+        .describe_lazy(|| format!("parse checksum published at '{download_url}.sha256'"))?;
+
+    let cache = ctx.data_root().join("cache").join("fossa-cli");
+    let archive = fetch_archive(client, &cache, &download_url, &checksum).await?;
 
     let final_path = ctx.data_root().join(command_name());
-    spawn_blocking(move || unzip_zip(content, final_path))
+    spawn_blocking(move || unzip_zip(archive, final_path))
         .await
         .change_context(Error::Download)
 }
 
+/// Fetch the archive at `download_url` into the cache directory, returning its path.
+///
+/// If the archive was already downloaded and matches the checksum, it's reused.
+/// Otherwise it's downloaded into a partial file, which is resumed if the connection fails
+/// (including across restarts of Broker), and moved into place once it's complete and verified.
+#[tracing::instrument(skip(client))]
+async fn fetch_archive(
+    client: &Client,
+    cache: &Path,
+    download_url: &str,
+    checksum: &str,
+) -> Result<PathBuf, Error> {
+    let name = download_url.rsplit('/').next().unwrap_or("fossa.zip");
+    let archive = cache.join(name);
+    if let Ok(content) = fs::read(&archive).await {
+        if sha2::verify_sha256(&content, checksum).is_ok() {
+            debug!(archive = %archive.display(), "Reusing cached FOSSA CLI archive");
+            return archive.wrap_ok();
+        }
+        warn!(archive = %archive.display(), "Cached FOSSA CLI archive doesn't match its checksum; downloading it again");
+    }
+
+    fs::create_dir_all(cache)
+        .await
+        .context_lazy(|| Error::Cache(cache.to_path_buf()))?;
+
+    let partial = cache.join(format!("{name}.partial"));
+    let mut attempt = 1;
+    while let Err(err) = resume_download(client, download_url, &partial).await {
+        if attempt >= DOWNLOAD_ATTEMPTS {
+            return Err(err);
+        }
+        warn!(
+            attempt,
+            "Downloading FOSSA CLI was interrupted, resuming: {err:#}"
+        );
+        tokio::time::sleep(DOWNLOAD_RETRY_DELAY * attempt).await;
+        attempt += 1;
+    }
+
+    let content = fs::read(&partial)
+        .await
+        .context_lazy(|| Error::Cache(partial.clone()))?;
+    if let Err(err) = sha2::verify_sha256(&content, checksum) {
+        // Start over next time, since the partial download can't be trusted.
+        let _ = fs::remove_file(&partial).await;
+        return Err(err).change_context(Error::Verify).help_lazy(|| formatdoc!{"
+            The download from '{download_url}' doesn't match the checksum published with the release.
+            Try again; if this persists, this may be an issue with a proxy on the local network.
+            "}
+        );
+    }
+
+    fs::rename(&partial, &archive)
+        .await
+        .context_lazy(|| Error::Cache(archive.clone()))?;
+    prune_cache(cache, &archive).await;
+    archive.wrap_ok()
+}
+
+/// Download `download_url` into `partial`, continuing from the end of `partial` if it already has content.
+async fn resume_download(client: &Client, download_url: &str, partial: &Path) -> Result<(), Error> {
+    let offset = fs::metadata(partial)
+        .await
+        .map(|meta| meta.len())
+        .unwrap_or_default();
+
+    let mut request = client.get(download_url);
+    if offset > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={offset}-"));
+    }
+
+    let help = || {
+        formatdoc! {"
+        Try downloading FOSSA CLI from '{download_url}' to determine if this is an issue with the local network.
+        You also may be able to work around this issue by using the installation script for FOSSA CLI,
+        located at https://github.com/fossas/fossa-cli#installation
+        "}
+    };
+
+    let response = client
+        .send(request)
+        .await
+        .context(Error::Download)
+        .help_lazy(help)?;
+
+    // The partial file is already complete; it was interrupted before it could be moved into place.
+    if offset > 0 && response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        return Ok(());
+    }
+
+    let mut response = response
+        .error_for_status()
+        .context(Error::Download)
+        .help_lazy(help)?;
+
+    // Servers which don't support range requests send the whole file instead.
+    let resumed = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    debug!(offset, resumed, "Downloading FOSSA CLI");
+    let mut options = fs::OpenOptions::new();
+    if resumed {
+        options.append(true);
+    } else {
+        options.write(true).truncate(true);
+    }
+    let mut file = options
+        .create(true)
+        .open(partial)
+        .await
+        .context_lazy(|| Error::Cache(partial.to_path_buf()))?;
+
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .context(Error::Download)
+        .help_lazy(help)?
+    {
+        bandwidth::consume(chunk.len()).await;
+        file.write_all(&chunk)
+            .await
+            .context_lazy(|| Error::Cache(partial.to_path_buf()))?;
+    }
+    file.flush()
+        .await
+        .context_lazy(|| Error::Cache(partial.to_path_buf()))
+}
+
+/// Remove everything in the cache other than the archive that was just downloaded,
+/// so that archives for older versions don't accumulate.
+///
+/// Failing to remove an item is not an error; it's logged and retried next time.
+async fn prune_cache(cache: &Path, keep: &Path) {
+    let Ok(mut entries) = fs::read_dir(cache).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if path == keep {
+            continue;
+        }
+        match fs::remove_file(&path).await {
+            Ok(_) => debug!(path = %path.display(), "Removed cached FOSSA CLI archive"),
+            Err(err) => {
+                warn!(path = %path.display(), %err, "Unable to remove cached FOSSA CLI archive")
+            }
+        }
+    }
+}
+
 // currently supported os/arch combos:
 // darwin/amd64
 // linux/amd64
 // windows/amd64
 //
 // We only support "amd64" right now, so no need to look at target_arch
+// Mirrors configured with `download_base_url` replace "https://github.com/fossas/fossa-cli/releases" in these URLs.
 // Example URLs:
 // https://github.com/fossas/fossa-cli/releases/download/v3.7.2/fossa_3.7.2_windows_amd64.zip
 // https://github.com/fossas/fossa-cli/releases/download/v3.7.2/fossa_3.7.2_darwin_amd64.zip
 // https://github.com/fossas/fossa-cli/releases/download/v3.7.2/fossa_3.7.2_linux_amd64.zip
 #[cfg(target_os = "windows")]
-fn download_url(version: &str) -> String {
-    format!("https://github.com/fossas/fossa-cli/releases/download/v{version}/fossa_{version}_windows_amd64.zip")
+fn download_url(releases: &str, version: &str) -> String {
+    format!("{releases}/download/v{version}/fossa_{version}_windows_amd64.zip")
 }
 
 #[cfg(target_os = "macos")]
-fn download_url(version: &str) -> String {
-    format!("https://github.com/fossas/fossa-cli/releases/download/v{version}/fossa_{version}_darwin_amd64.zip")
+fn download_url(releases: &str, version: &str) -> String {
+    format!("{releases}/download/v{version}/fossa_{version}_darwin_amd64.zip")
 }
 
 #[cfg(target_os = "linux")]
-fn download_url(version: &str) -> String {
-    format!("https://github.com/fossas/fossa-cli/releases/download/v{version}/fossa_{version}_linux_amd64.zip")
+fn download_url(releases: &str, version: &str) -> String {
+    format!("{releases}/download/v{version}/fossa_{version}_linux_amd64.zip")
 }
 
-#[tracing::instrument]
-async fn download_from_github(version: &str) -> Result<Cursor<Bytes>, Error> {
-    let download_url = download_url(version);
-    let client = reqwest::Client::new();
+#[tracing::instrument(skip(client))]
+async fn download_from_github(client: &Client, download_url: &str) -> Result<Cursor<Bytes>, Error> {
     let response = client
-        .get(&download_url)
-        .send()
+        .send(client.get(download_url))
         .await
+        .and_then(|response| response.error_for_status())
         .into_report()
         .change_context(Error::Download)
         .help_lazy(|| formatdoc!{"
@@ -571,8 +917,20 @@ async fn download_from_github(version: &str) -> Result<Cursor<Bytes>, Error> {
     Ok(content)
 }
 
-#[tracing::instrument(skip(content))]
-fn unzip_zip(content: Cursor<Bytes>, final_path: PathBuf) -> Result<PathBuf, Error> {
+/// Extract the CLI from the archive into `final_path`.
+///
+/// The CLI is extracted next to `final_path` and then moved into place,
+/// so that an interrupted extraction never leaves a partially written CLI behind.
+#[tracing::instrument]
+fn unzip_zip(archive: PathBuf, final_path: PathBuf) -> Result<PathBuf, Error> {
+    let content = std::fs::File::open(&archive)
+        .context(Error::Extract)
+        .describe_lazy(|| {
+            format!(
+                "opening downloaded fossa release at '{}'",
+                archive.display()
+            )
+        })?;
     let mut archive = zip::ZipArchive::new(content)
         .context(Error::Extract)
         .describe("extracting zip file from downloaded fossa release")?;
@@ -585,8 +943,12 @@ fn unzip_zip(content: Cursor<Bytes>, final_path: PathBuf) -> Result<PathBuf, Err
         }
     };
 
-    write_zip_to_final_file(zip_file, &final_path)
-        .change_context(Error::Extract)
+    let mut staged = final_path.clone().into_os_string();
+    staged.push(".new");
+    let staged = PathBuf::from(staged);
+    write_zip_to_final_file(zip_file, &staged).change_context(Error::Extract)?;
+    std::fs::rename(&staged, &final_path)
+        .context_lazy(|| Error::FinalCopy(final_path.to_string_lossy().to_string()))
         .map(|_| final_path)
 }
 
@@ -0,0 +1,353 @@
+//! Hooks run user-provided commands at points in the lifecycle of each scan.
+//!
+//! This allows custom workflows, like scanning clones for viruses or copying scan results elsewhere,
+//! without patching Broker. Details about the scan are provided to each hook in environment variables,
+//! and hooks are run like any other command Broker runs: their output is redacted before it's logged or reported.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use derive_new::new;
+use error_stack::{report, Report, ResultExt};
+use getset::{CopyGetters, Getters};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::{
+    api::remote::Reference,
+    ext::{
+        command::{Command, OutputProvider, Value},
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        result::{WrapErr, WrapOk},
+    },
+};
+
+/// Errors encountered running hooks.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The hook could not be run.
+    #[error("run {point} hook '{command}'")]
+    RunCommand {
+        /// The point in the scan at which the hook was run.
+        point: Point,
+
+        /// The command that could not be run.
+        command: String,
+    },
+
+    /// The hook exited unsuccessfully.
+    #[error("{point} hook '{command}' exited with status {status}")]
+    CommandFailed {
+        /// The point in the scan at which the hook was run.
+        point: Point,
+
+        /// The command that failed.
+        command: String,
+
+        /// The exit status of the command.
+        status: i32,
+    },
+}
+
+/// Errors that are possibly surfaced during validation of config values.
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    /// The command for a hook is empty.
+    #[error("validate hook command")]
+    Command,
+
+    /// The timeout for a hook is not a valid duration.
+    #[error("validate hook timeout")]
+    Timeout,
+}
+
+/// The points in the lifecycle of a scan at which hooks are run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Point {
+    /// The reference was cloned, and hasn't been analyzed yet.
+    PostClone,
+
+    /// The reference was analyzed, and the results haven't been uploaded yet.
+    PostScan,
+
+    /// The results were uploaded to FOSSA.
+    PostUpload,
+}
+
+/// Validated config values for a single hook.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters, new)]
+pub struct Hook {
+    /// The command to run.
+    #[getset(get = "pub")]
+    command: String,
+
+    /// Arguments to provide to the command.
+    #[getset(get = "pub")]
+    args: Vec<String>,
+
+    /// If the hook runs longer than this, it is killed.
+    #[getset(get_copy = "pub")]
+    timeout: Duration,
+
+    /// Whether the scan fails if the hook fails.
+    /// Hooks which aren't required are logged when they fail, and the scan continues.
+    #[getset(get_copy = "pub")]
+    required: bool,
+}
+
+impl Hook {
+    /// The default timeout.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+    /// Validate the config values for a hook.
+    pub fn validate(
+        command: String,
+        args: Vec<String>,
+        timeout: Option<String>,
+        required: bool,
+    ) -> Result<Self, Report<ValidationError>> {
+        if command.trim().is_empty() {
+            return report!(ValidationError::Command)
+                .wrap_err()
+                .help("provide the path to the program to run, and any arguments in 'args'");
+        }
+
+        let timeout = match timeout {
+            None => Self::DEFAULT_TIMEOUT,
+            Some(timeout) => humantime::parse_duration(&timeout)
+                .context(ValidationError::Timeout)
+                .describe_lazy(|| format!("provided value: '{timeout}'"))
+                .help("provide a duration like '10m' or '30s'")?,
+        };
+        Self::new(command, args, timeout, required).wrap_ok()
+    }
+}
+
+/// Validated config values for hooks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Getters, new)]
+#[getset(get = "pub")]
+pub struct Config {
+    /// Hooks run after each reference is cloned.
+    post_clone: Vec<Hook>,
+
+    /// Hooks run after each reference is analyzed.
+    post_scan: Vec<Hook>,
+
+    /// Hooks run after each scan is uploaded.
+    post_upload: Vec<Hook>,
+}
+
+impl Config {
+    /// The hooks run at the point, in the order they're run.
+    pub fn hooks(&self, point: Point) -> &[Hook] {
+        match point {
+            Point::PostClone => &self.post_clone,
+            Point::PostScan => &self.post_scan,
+            Point::PostUpload => &self.post_upload,
+        }
+    }
+}
+
+/// Details about the scan, provided to hooks in environment variables.
+#[derive(Debug, Clone)]
+pub struct Context {
+    scan_id: String,
+    integration: String,
+    reference: String,
+    revision: String,
+    path: Option<PathBuf>,
+    scan_results: Option<PathBuf>,
+    locator: Option<String>,
+}
+
+impl Context {
+    /// The name of the variable set to the point at which the hook is run.
+    pub const VAR_HOOK: &'static str = "BROKER_HOOK";
+
+    /// The name of the variable set to the ID of the scan.
+    pub const VAR_SCAN_ID: &'static str = "BROKER_SCAN_ID";
+
+    /// The name of the variable set to the remote of the integration.
+    pub const VAR_INTEGRATION: &'static str = "BROKER_INTEGRATION";
+
+    /// The name of the variable set to the name of the reference.
+    pub const VAR_REFERENCE: &'static str = "BROKER_REFERENCE";
+
+    /// The name of the variable set to the revision of the reference.
+    pub const VAR_REVISION: &'static str = "BROKER_REVISION";
+
+    /// The name of the variable set to the directory into which the reference was cloned.
+    pub const VAR_PATH: &'static str = "BROKER_PATH";
+
+    /// The name of the variable set to the file containing the results of the scan.
+    pub const VAR_SCAN_RESULTS: &'static str = "BROKER_SCAN_RESULTS";
+
+    /// The name of the variable set to the locator of the uploaded project revision.
+    pub const VAR_LOCATOR: &'static str = "BROKER_LOCATOR";
+
+    /// Describe a scan of the reference for an integration.
+    pub fn new(scan_id: impl Display, integration: impl Display, reference: &Reference) -> Self {
+        Self {
+            scan_id: scan_id.to_string(),
+            integration: integration.to_string(),
+            reference: reference.name().to_string(),
+            revision: reference.revision().to_string(),
+            path: None,
+            scan_results: None,
+            locator: None,
+        }
+    }
+
+    /// The directory into which the reference was cloned.
+    pub fn path(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+
+    /// The file containing the results of the scan.
+    pub fn scan_results(mut self, path: &Path) -> Self {
+        self.scan_results = Some(path.to_path_buf());
+        self
+    }
+
+    /// The locator of the uploaded project revision.
+    pub fn locator(mut self, locator: impl Display) -> Self {
+        self.locator = Some(locator.to_string());
+        self
+    }
+
+    /// The environment variables provided to hooks run at the point.
+    fn envs(&self, point: Point) -> Vec<(&'static str, Value)> {
+        let mut envs = vec![
+            (Self::VAR_HOOK, Value::new_plain(point.to_string())),
+            (Self::VAR_SCAN_ID, Value::new_plain(&self.scan_id)),
+            (Self::VAR_INTEGRATION, Value::new_plain(&self.integration)),
+            (Self::VAR_REFERENCE, Value::new_plain(&self.reference)),
+            (Self::VAR_REVISION, Value::new_plain(&self.revision)),
+        ];
+        if let Some(path) = &self.path {
+            envs.push((Self::VAR_PATH, Value::new_path(path)));
+        }
+        if let Some(path) = &self.scan_results {
+            envs.push((Self::VAR_SCAN_RESULTS, Value::new_path(path)));
+        }
+        if let Some(locator) = &self.locator {
+            envs.push((Self::VAR_LOCATOR, Value::new_plain(locator)));
+        }
+        envs
+    }
+}
+
+/// Run the hooks configured for the point, in order.
+///
+/// Hooks which fail are logged; if a required hook fails, the remaining hooks aren't run and its failure is returned.
+#[tracing::instrument(skip(config, context))]
+pub async fn run(config: &Config, point: Point, context: &Context) -> Result<(), Report<Error>> {
+    for hook in config.hooks(point) {
+        match execute(hook, point, context).await {
+            Ok(()) => debug!("Ran {point} hook '{}'", hook.command()),
+            Err(err) if hook.required() => return Err(err),
+            Err(err) => warn!("Unable to run {point} hook: {err:#}"),
+        }
+    }
+    Ok(())
+}
+
+async fn execute(hook: &Hook, point: Point, context: &Context) -> Result<(), Report<Error>> {
+    info!("Running {point} hook '{}'", hook.command());
+    let mut command = Command::new(hook.command())
+        .args(hook.args().iter().map(Value::new_plain))
+        .envs(context.envs(point))
+        .timeout(hook.timeout());
+    if let Some(path) = &context.path {
+        command = command.current_dir(path);
+    }
+
+    let output = command
+        .output_traced()
+        .await
+        .context_lazy(|| Error::RunCommand {
+            point,
+            command: hook.command().clone(),
+        })
+        .help("ensure the hook command exists and is executable")?;
+
+    if !output.status().success() {
+        let stderr = output.stderr_string_lossy();
+        return report!(Error::CommandFailed {
+            point,
+            command: hook.command().clone(),
+            status: output.exit_code(),
+        })
+        .wrap_err()
+        .describe_lazy(|| format!("stderr: {}", stderr.trim()));
+    }
+    Ok(())
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use crate::api::remote::git;
+
+    use super::*;
+
+    fn hook(script: &str, required: bool) -> Hook {
+        Hook::new(
+            String::from("sh"),
+            vec![String::from("-c"), String::from(script)],
+            Hook::DEFAULT_TIMEOUT,
+            required,
+        )
+    }
+
+    fn context() -> Context {
+        let reference = Reference::Git(git::Reference::new_branch(
+            String::from("main"),
+            String::from("abcd1234"),
+        ));
+        Context::new("scan-1", "github.com/fossas/broker", &reference)
+    }
+
+    #[tokio::test]
+    async fn provides_scan_details() {
+        let root = tempfile::tempdir().expect("must create temp dir");
+        let out = root.path().join("out");
+        let script = format!(
+            "echo \"$BROKER_HOOK $BROKER_SCAN_ID $BROKER_REFERENCE $BROKER_REVISION $BROKER_LOCATOR\" > '{}'",
+            out.display()
+        );
+        let config = Config::new(Vec::new(), Vec::new(), vec![hook(&script, true)]);
+
+        let context = context().locator("custom+1/broker$abcd1234");
+        run(&config, Point::PostUpload, &context)
+            .await
+            .expect("must run hook");
+
+        let written = std::fs::read_to_string(out).expect("must read hook output");
+        assert_eq!(
+            written.trim(),
+            "post_upload scan-1 main abcd1234 custom+1/broker$abcd1234"
+        );
+    }
+
+    #[tokio::test]
+    async fn only_required_hooks_fail() {
+        let config = Config::new(vec![hook("exit 3", false)], Vec::new(), Vec::new());
+        run(&config, Point::PostClone, &context())
+            .await
+            .expect("optional hooks must not fail");
+
+        let config = Config::new(vec![hook("exit 3", true)], Vec::new(), Vec::new());
+        let err = run(&config, Point::PostClone, &context())
+            .await
+            .expect_err("required hooks must fail");
+        assert!(matches!(
+            err.current_context(),
+            Error::CommandFailed { status: 3, .. }
+        ));
+    }
+}
@@ -0,0 +1,162 @@
+//! Reads secrets from the keyring of the operating system, so they don't have to be written in the config file.
+//!
+//! Secrets are looked up by the name of the service under which they were stored,
+//! using the tools that ship with each platform instead of linking against its keyring:
+//! - macOS: the login keychain, with `security`.
+//! - Windows: the Credential Manager, with `powershell`.
+//! - Linux and other platforms: the Secret Service (such as GNOME Keyring or KWallet), with `secret-tool`.
+
+use std::time::Duration;
+
+use error_stack::{bail, report, Report};
+
+use crate::{
+    doc::code::{ErrorCode, HasErrorCode},
+    ext::{
+        command::{Command, OutputProvider},
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        result::WrapErr,
+        secrecy::ComparableSecretString,
+    },
+};
+
+/// If reading from the keyring takes longer than this, for example because it's waiting to be unlocked, it is stopped.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Errors encountered reading secrets from the keyring.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The tool used to read the keyring could not be run.
+    #[error("run '{0}' to read the keyring")]
+    RunCommand(&'static str),
+
+    /// The keyring has no secret stored under the service, or it couldn't be read.
+    #[error("read secret '{0}' from the keyring")]
+    NotFound(String),
+
+    /// The secret stored under the service is empty.
+    #[error("secret '{0}' in the keyring is empty")]
+    Empty(String),
+}
+
+impl HasErrorCode for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::RunCommand(..) => ErrorCode::new(4601),
+            Self::NotFound(..) => ErrorCode::new(4602),
+            Self::Empty(..) => ErrorCode::new(4603),
+        }
+    }
+}
+
+/// Read the secret stored in the keyring under the service.
+///
+/// The output of the tool used to read the keyring is never included in errors, since it may contain the secret.
+#[tracing::instrument]
+pub async fn read(service: &str) -> Result<ComparableSecretString, Report<Error>> {
+    let (program, command) = command(service);
+    let output = command
+        .timeout(TIMEOUT)
+        .output()
+        .await
+        .context(Error::RunCommand(program))
+        .help_lazy(|| format!("ensure that '{program}' is installed and in the PATH"))?;
+
+    if !output.status().success() {
+        return report!(Error::NotFound(service.to_string()))
+            .wrap_err()
+            .describe_lazy(|| format!("'{program}' exited with status {}", output.exit_code()))
+            .help_lazy(|| {
+                format!(
+                    "store the secret in the keyring first: {}",
+                    store_hint(service)
+                )
+            });
+    }
+
+    // Only trailing newlines are removed; the secret is otherwise used exactly as stored.
+    let secret = output
+        .stdout_string_lossy()
+        .trim_end_matches(['\r', '\n'])
+        .to_string();
+    if secret.is_empty() {
+        bail!(Error::Empty(service.to_string()));
+    }
+    Ok(ComparableSecretString::from(secret))
+}
+
+/// The command which prints the secret stored under the service, and the name of the program it runs.
+#[cfg(target_os = "macos")]
+fn command(service: &str) -> (&'static str, Command) {
+    let command = Command::new("security")
+        .arg_plain("find-generic-password")
+        .arg_plain("-s")
+        .arg_plain(service)
+        .arg_plain("-w");
+    ("security", command)
+}
+
+/// The command which prints the secret stored under the service, and the name of the program it runs.
+#[cfg(target_os = "windows")]
+fn command(service: &str) -> (&'static str, Command) {
+    // The Credential Manager has no command line tool that prints secrets, so read it with the Win32 API.
+    // The service is provided in the environment so that it's never interpreted as part of the script.
+    const SCRIPT: &str = indoc::indoc! {r#"
+        Add-Type -TypeDefinition @'
+        using System;
+        using System.Runtime.InteropServices;
+        public static class BrokerKeyring {
+            [StructLayout(LayoutKind.Sequential, CharSet = CharSet.Unicode)]
+            struct Credential {
+                public int Flags; public int Type; public string TargetName; public string Comment;
+                public long LastWritten; public int CredentialBlobSize; public IntPtr CredentialBlob;
+                public int Persist; public int AttributeCount; public IntPtr Attributes;
+                public string TargetAlias; public string UserName;
+            }
+            [DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+            static extern bool CredReadW(string target, int type, int flags, out IntPtr credential);
+            [DllImport("advapi32.dll")]
+            static extern void CredFree(IntPtr credential);
+            public static string Read(string target) {
+                IntPtr pointer;
+                if (!CredReadW(target, 1, 0, out pointer)) { return null; }
+                try {
+                    var credential = (Credential)Marshal.PtrToStructure(pointer, typeof(Credential));
+                    return Marshal.PtrToStringUni(credential.CredentialBlob, credential.CredentialBlobSize / 2);
+                } finally { CredFree(pointer); }
+            }
+        }
+        '@
+        $secret = [BrokerKeyring]::Read($env:BROKER_KEYRING_SERVICE)
+        if ($secret -eq $null) { exit 1 }
+        [Console]::Out.Write($secret)
+    "#};
+    let command = Command::new("powershell")
+        .arg_plain("-NoProfile")
+        .arg_plain("-NonInteractive")
+        .arg_plain("-Command")
+        .arg_plain(SCRIPT)
+        .env_plain("BROKER_KEYRING_SERVICE", service);
+    ("powershell", command)
+}
+
+/// The command which prints the secret stored under the service, and the name of the program it runs.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn command(service: &str) -> (&'static str, Command) {
+    let command = Command::new("secret-tool")
+        .arg_plain("lookup")
+        .arg_plain("service")
+        .arg_plain(service);
+    ("secret-tool", command)
+}
+
+/// How to store a secret under the service on this platform.
+fn store_hint(service: &str) -> String {
+    if cfg!(target_os = "macos") {
+        format!("security add-generic-password -a broker -s '{service}' -w")
+    } else if cfg!(target_os = "windows") {
+        format!("cmdkey /generic:{service} /user:broker /pass")
+    } else {
+        format!("secret-tool store --label='{service}' service '{service}'")
+    }
+}
@@ -4,21 +4,38 @@
 //! While it is possible to import this library from another Rust program, this library
 //! may make major breaking changes on _any_ release, as it is not considered part of the API contract
 //! for Broker (which is distributed to end users in binary form only).
+//!
+//! The exception is [`facade`], a small API for driving Broker programmatically
+//! which follows semantic versioning.
 
 #![deny(clippy::unwrap_used)]
 #![deny(unsafe_code)]
 #![deny(missing_docs)]
 #![warn(rust_2018_idioms)]
 
+pub mod admin;
 pub mod api;
+pub mod audit;
+pub mod bandwidth;
+pub mod clock;
 pub mod cmd;
 pub mod config;
+pub mod crypto;
 pub mod db;
 pub mod debug;
+pub mod disk;
 pub mod doc;
+pub mod encryption;
 pub mod ext;
+pub mod facade;
 pub mod fossa_cli;
+pub mod hooks;
+pub mod keyring;
+pub mod locale;
+pub mod notify;
 pub mod queue;
+pub mod secrets;
+pub mod size_limits;
 
 /// Get the path to a subdirectory of the data root for the current module
 /// with the given context.
@@ -37,7 +54,7 @@ pub mod queue;
 ///
 /// # let tmp = tempfile::tempdir().expect("must create tempdir");
 /// # let root = tmp.path().to_path_buf();
-/// # let ctx = broker::AppContext::new(root);
+/// # let ctx = broker::AppContext::new(root).expect("must create context");
 /// # my_module::my_function(&ctx);
 /// ```
 #[macro_export]
@@ -53,8 +70,14 @@ pub use ctx::AppContext;
 mod ctx {
     use std::path::PathBuf;
 
+    use error_stack::Report;
     use getset::Getters;
 
+    use crate::{
+        api::http::client::{self, Clients},
+        clock::Clock,
+    };
+
     /// Context that many parts of the program need to know about, arranged into a single type for dependency injection.
     ///
     /// This type should be added to sparingly; definitely prefer to pass in args to functions over using context
@@ -72,13 +95,59 @@ mod ctx {
         /// to play it maximally safe and avoid collisions,
         /// consider using a subdirectory with the [`data_dir`] function.
         data_root: PathBuf,
+
+        /// The source of time for long running tasks.
+        ///
+        /// This is the system clock, unless replaced with [`AppContext::with_clock`]
+        /// so that tests can control the passage of time.
+        clock: Clock,
+
+        /// The shared HTTP clients for outbound requests.
+        ///
+        /// These use the default connection pool settings, unless replaced with [`AppContext::with_http`]
+        /// once the config file is loaded.
+        http: Clients,
+
+        /// The profile in use, if one was selected with [`AppContext::with_profile`].
+        ///
+        /// A profile's data root is a subdirectory of the data root it was selected from,
+        /// and its config file and database are only read from its data root.
+        profile: Option<String>,
     }
 
     impl AppContext {
-        /// Create a new context.
-        pub fn new(data_root: PathBuf) -> Self {
+        /// Create a new context, with HTTP clients using the default connection pool settings.
+        ///
+        /// Fails if the HTTP clients can't be constructed.
+        pub fn new(data_root: PathBuf) -> Result<Self, Report<client::Error>> {
             // Note: if we get too many things in here, switch to builder pattern via `typed_builder`.
-            Self { data_root }
+            Ok(Self {
+                data_root,
+                clock: Clock::system(),
+                http: Clients::new(client::Config::default())?,
+                profile: None,
+            })
+        }
+
+        /// Select the profile with the provided name, whose data root is the `profiles/<name>` subdirectory of this one.
+        ///
+        /// The name is expected to be validated already; see [`crate::config::RawRunArgs`].
+        pub fn with_profile(mut self, profile: String) -> Self {
+            self.data_root = self.data_root.join("profiles").join(&profile);
+            self.profile = Some(profile);
+            self
+        }
+
+        /// Use the provided clock instead of the system clock.
+        pub fn with_clock(mut self, clock: Clock) -> Self {
+            self.clock = clock;
+            self
+        }
+
+        /// Use the provided HTTP clients instead of clients with the default settings.
+        pub fn with_http(mut self, http: Clients) -> Self {
+            self.http = http;
+            self
         }
 
         /// Get the path to a subdirectory of the data root for a given module name.
@@ -100,7 +169,7 @@ mod ctx {
         ///
         /// # let tmp = tempfile::tempdir().expect("must create tempdir");
         /// # let root = tmp.path().to_path_buf();
-        /// # let ctx = broker::AppContext::new(root);
+        /// # let ctx = broker::AppContext::new(root).expect("must create context");
         /// # my_module::my_function(&ctx);
         /// ```
         #[track_caller]
@@ -124,7 +193,7 @@ mod tests {
         #[test]
         fn creates_data_subdir() {
             let tmp = tempdir().expect("must create tempdir");
-            let ctx = AppContext::new(tmp.path().to_path_buf());
+            let ctx = AppContext::new(tmp.path().to_path_buf()).expect("must create context");
             let subdir = ctx.data_dir(module_path!());
 
             subdir
@@ -137,7 +206,7 @@ mod tests {
     #[test]
     fn creates_data_subdir() {
         let tmp = tempdir().expect("must create tempdir");
-        let ctx = AppContext::new(tmp.path().to_path_buf());
+        let ctx = AppContext::new(tmp.path().to_path_buf()).expect("must create context");
         let subdir = ctx.data_dir(module_path!());
 
         subdir
@@ -145,4 +214,18 @@ mod tests {
             .expect("must be a subdirectory");
         assert_eq!(subdir, tmp.path().join("broker-tests"));
     }
+
+    #[test]
+    fn profile_has_own_data_root() {
+        let tmp = tempdir().expect("must create tempdir");
+        let ctx = AppContext::new(tmp.path().to_path_buf())
+            .expect("must create context")
+            .with_profile(String::from("staging"));
+
+        assert_eq!(ctx.profile().as_deref(), Some("staging"));
+        assert_eq!(
+            ctx.data_root(),
+            &tmp.path().join("profiles").join("staging")
+        );
+    }
 }
@@ -0,0 +1,392 @@
+//! Localization of the messages Broker shows to users.
+//!
+//! The remediation text of `broker fix`, the output and help of `broker init`, and the descriptions of subcommands
+//! are looked up in a message catalog for the active [`Locale`].
+//! Catalogs are YAML files in `src/locale` which map message keys to templates, with named placeholders like `{remote}`
+//! filled in when the message is rendered; messages missing from a catalog fall back to English.
+//!
+//! ```
+//! use broker::locale::Message;
+//!
+//! let title = broker::msg!(Message::FixDiagnosingFossa);
+//! let collected = broker::msg!(Message::FixBundleCollected, location = "/tmp/bundle.tar.gz");
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::{Display, Write},
+};
+
+use error_stack::Report;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoStaticStr};
+use tracing::warn;
+
+use crate::ext::once_cell::{Conflict, InstallOnce};
+
+/// The environment variables which select the language of messages, in order of precedence.
+const LOCALE_VARIABLES: &[&str] = &["LC_ALL", "LC_MESSAGES", "LANG"];
+
+/// The prefix of the catalog keys for subcommand descriptions.
+const CLI_PREFIX: &str = "cli.";
+
+/// A language Broker's messages are available in.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Default,
+    Deserialize,
+    Serialize,
+    strum::Display,
+    EnumIter,
+)]
+pub enum Locale {
+    /// English, the language Broker is written in.
+    #[default]
+    #[serde(rename = "en")]
+    #[strum(serialize = "en")]
+    English,
+
+    /// Japanese.
+    #[serde(rename = "ja")]
+    #[strum(serialize = "ja")]
+    Japanese,
+}
+
+impl Locale {
+    /// Detect the locale from the environment: the first of `LC_ALL`, `LC_MESSAGES`, and `LANG` which is set,
+    /// as POSIX systems do. Unset and unsupported locales are English.
+    pub fn detect() -> Self {
+        LOCALE_VARIABLES
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.trim().is_empty())
+            .map(|tag| Self::from_tag(&tag))
+            .unwrap_or_default()
+    }
+
+    /// The locale for a POSIX locale name or language tag, such as `ja_JP.UTF-8` or `ja-JP`.
+    /// Unsupported locales are English.
+    pub fn from_tag(tag: &str) -> Self {
+        let language = tag
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "ja" => Self::Japanese,
+            _ => Self::English,
+        }
+    }
+
+    /// The catalog of messages in the language.
+    fn catalog(self) -> &'static HashMap<String, String> {
+        match self {
+            Self::English => &ENGLISH,
+            Self::Japanese => &JAPANESE,
+        }
+    }
+}
+
+static ENGLISH: Lazy<HashMap<String, String>> =
+    Lazy::new(|| parse_catalog(Locale::English, include_str!("locale/en.yml")));
+static JAPANESE: Lazy<HashMap<String, String>> =
+    Lazy::new(|| parse_catalog(Locale::Japanese, include_str!("locale/ja.yml")));
+
+/// Parse a catalog. Catalogs are embedded in Broker and tested, so failing to parse one is a bug;
+/// rather than failing the command, the messages fall back to English (or to their keys, for English).
+fn parse_catalog(locale: Locale, content: &str) -> HashMap<String, String> {
+    serde_yaml::from_str(content).unwrap_or_else(|err| {
+        warn!("Unable to parse the '{locale}' message catalog: {err}");
+        HashMap::new()
+    })
+}
+
+/// The locale installed with [`install`].
+static LOCALE: OnceCell<Locale> = OnceCell::new();
+
+/// Show messages in the provided locale for the rest of the process.
+///
+/// Fails if messages are already shown in a different locale.
+pub fn install(locale: Locale) -> Result<(), Report<Conflict>> {
+    LOCALE.install("locale", locale)
+}
+
+/// The locale messages are shown in: the one installed with [`install`], or the one detected from the environment.
+pub fn current() -> Locale {
+    LOCALE.get().copied().unwrap_or_else(Locale::detect)
+}
+
+/// The localized description of a subcommand, if the current locale translates it.
+///
+/// Subcommands are named by their path, such as `fix` or `db reset`.
+/// English descriptions are the doc comments of the subcommands, so they aren't in the catalog.
+pub fn cli_description(path: &str) -> Option<&'static str> {
+    let key = format!("{CLI_PREFIX}{}", path.replace(' ', "."));
+    current().catalog().get(&key).map(String::as_str)
+}
+
+/// A message shown to users.
+///
+/// Each message is keyed in the catalogs by its name in snake case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum Message {
+    /// `broker init` left an existing config file as it was.
+    InitConfigExists,
+    /// `broker init` created a config file.
+    InitConfigCreated,
+    /// Why `broker init` creates the data root.
+    InitCreateDataRootDescription,
+    /// How to resolve failing to create the data root.
+    InitCreateDataRootHelp,
+    /// How to resolve failing to write the config file.
+    InitWriteConfigHelp,
+    /// `broker init` wrote example service definitions and CI workflows.
+    InitExamplesCreated,
+
+    /// The title of the checks of the connection to FOSSA.
+    FixDiagnosingFossa,
+    /// Checking the FOSSA API without authentication.
+    FixCheckFossaNoAuth,
+    /// Checking the FOSSA API with authentication.
+    FixCheckFossaAuth,
+    /// The title of the checks of the integrations.
+    FixDiagnosingIntegrations,
+    /// The title of the problems found checking integrations.
+    FixIntegrationErrors,
+    /// The title of the problems found checking the connection to FOSSA.
+    FixFossaErrors,
+    /// The title of collecting the debug bundle.
+    FixCollectingBundle,
+    /// The debug bundle wasn't collected because collection is disabled.
+    FixBundleDisabled,
+    /// The debug bundle wasn't collected because no problems were found.
+    FixBundleNotNeeded,
+    /// Where the debug bundle was collected.
+    FixBundleCollected,
+    /// Asks users to include the debug bundle in support requests.
+    FixBundleInclude,
+    /// The title of uploading the debug bundle.
+    FixUploadingBundle,
+    /// The reference of the uploaded debug bundle.
+    FixBundleUploaded,
+    /// Asks users to include the debug bundle reference in support requests.
+    FixBundleReferenceInclude,
+
+    /// Checking the connection to FOSSA failed.
+    FixErrorFossaConnection,
+    /// Building the URL of the FOSSA API failed.
+    FixErrorFossaUrl,
+    /// Building an example command failed.
+    FixErrorExampleCommand,
+    /// Cloning a reference failed.
+    FixErrorCloneReference,
+    /// Downloading FOSSA CLI failed.
+    FixErrorDownloadCli,
+    /// Generating the debug bundle failed.
+    FixErrorGenerateBundle,
+    /// Uploading the debug bundle failed.
+    FixErrorUploadBundle,
+
+    /// Connecting to a git remote failed.
+    FixConnectionError,
+    /// How to resolve failing to connect to any git remote.
+    FixConnectionShared,
+    /// How to debug connecting with an SSH key file.
+    FixConnectionSshKeyFile,
+    /// How to debug connecting with an SSH key.
+    FixConnectionSshKeyValue,
+    /// How to debug connecting with HTTP basic authentication.
+    FixConnectionHttpBasic,
+    /// How to debug connecting with an HTTP header.
+    FixConnectionHttpHeader,
+    /// How to debug connecting with a credential command.
+    FixConnectionHttpCommand,
+    /// How to debug connecting to AWS CodeCommit.
+    FixConnectionCodeCommit,
+    /// The AWS profile used to connect to AWS CodeCommit.
+    FixConnectionCodeCommitProfile,
+    /// The default AWS credentials used to connect to AWS CodeCommit.
+    FixConnectionCodeCommitDefaultCredentials,
+    /// How to debug connecting without authentication.
+    FixConnectionHttpNoAuth,
+    /// How to enter the passphrase of an SSH key when debugging.
+    FixConnectionSshPassphrase,
+    /// Connecting to an integration other than git failed.
+    FixConnectionOther,
+
+    /// How to debug failing to scan a reference.
+    FixScanError,
+    /// How to resolve failing to download FOSSA CLI.
+    FixDownloadCliError,
+    /// Cloning a reference failed, and why.
+    FixCloneReferenceError,
+
+    /// Requesting the FOSSA API without authentication.
+    FixFossaGetNoAuth,
+    /// Requesting the FOSSA API with authentication.
+    FixFossaGetAuth,
+    /// FOSSA rejected the API key.
+    FixFossaUnauthorized,
+    /// FOSSA responded with an error status.
+    FixFossaStatus,
+    /// Requesting the FOSSA API timed out.
+    FixFossaTimeout,
+    /// Requesting the FOSSA API failed.
+    FixFossaOther,
+    /// How to debug failing to request the FOSSA API.
+    FixFossaExplanation,
+}
+
+impl Message {
+    /// The key of the message in the catalogs.
+    pub fn key(self) -> &'static str {
+        self.into()
+    }
+
+    /// Render the message in the current locale, filling in its placeholders from the arguments.
+    ///
+    /// Placeholders without an argument are left as they are; prefer the [`msg`](crate::msg) macro to calling this directly.
+    pub fn render(self, args: &[(&str, &dyn Display)]) -> String {
+        render(self.template(current()), args)
+    }
+
+    /// The template of the message in the locale, falling back to English.
+    fn template(self, locale: Locale) -> &'static str {
+        locale
+            .catalog()
+            .get(self.key())
+            .or_else(|| Locale::English.catalog().get(self.key()))
+            .map(String::as_str)
+            .unwrap_or_else(|| self.key())
+    }
+}
+
+/// Render a message in the current locale, filling in its placeholders like `format!`.
+///
+/// ```
+/// use broker::locale::Message;
+///
+/// let rendered = broker::msg!(Message::FixErrorFossaUrl, remote = "https://app.fossa.com", path = "/api");
+/// assert!(rendered.contains("https://app.fossa.com"));
+/// ```
+#[macro_export]
+macro_rules! msg {
+    ($message:expr) => {
+        $message.render(&[])
+    };
+    ($message:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $message.render(&[$((stringify!($name), &$value as &dyn std::fmt::Display)),+])
+    };
+}
+
+/// Fill in the `{name}` placeholders in the template.
+///
+/// The template is scanned once, so placeholders in the arguments aren't filled in.
+fn render(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let arg = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            args.iter()
+                .find(|(arg, _)| *arg == name)
+                .map(|(_, value)| (end, value))
+        });
+        match arg {
+            Some((end, value)) => {
+                let _ = write!(rendered, "{value}");
+                rest = &after[end + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = after;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    /// The names of the placeholders in the template.
+    fn placeholders(template: &str) -> BTreeSet<&str> {
+        template
+            .split('{')
+            .skip(1)
+            .filter_map(|part| part.split_once('}'))
+            .map(|(name, _)| name)
+            .filter(|name| {
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+            })
+            .collect()
+    }
+
+    #[test]
+    fn catalogs_are_complete() {
+        for locale in Locale::iter() {
+            let catalog = locale.catalog();
+            for message in Message::iter() {
+                let template = catalog
+                    .get(message.key())
+                    .unwrap_or_else(|| panic!("'{locale}' must translate '{}'", message.key()));
+                let english = message.template(Locale::English);
+                assert_eq!(
+                    placeholders(template),
+                    placeholders(english),
+                    "'{locale}' must use the same placeholders as English for '{}'",
+                    message.key()
+                );
+            }
+
+            let known = Message::iter().map(Message::key).collect::<BTreeSet<_>>();
+            for key in catalog.keys() {
+                assert!(
+                    known.contains(key.as_str()) || key.starts_with(CLI_PREFIX),
+                    "'{locale}' translates unknown message '{key}'"
+                );
+            }
+        }
+        assert!(
+            !Locale::English
+                .catalog()
+                .keys()
+                .any(|key| key.starts_with(CLI_PREFIX)),
+            "English subcommand descriptions are their doc comments"
+        );
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let rendered = render(
+            "clone {remote} at {branch}, {missing} {",
+            &[("remote", &"{branch}"), ("branch", &"main")],
+        );
+        assert_eq!(rendered, "clone {branch} at main, {missing} {");
+    }
+
+    #[test]
+    fn detects_locale_from_tag() {
+        assert_eq!(Locale::from_tag("ja_JP.UTF-8"), Locale::Japanese);
+        assert_eq!(Locale::from_tag("ja-JP"), Locale::Japanese);
+        assert_eq!(Locale::from_tag("JA"), Locale::Japanese);
+        assert_eq!(Locale::from_tag("en_US.UTF-8"), Locale::English);
+        assert_eq!(Locale::from_tag("C"), Locale::English);
+        assert_eq!(Locale::from_tag("fr_FR"), Locale::English);
+    }
+}
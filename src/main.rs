@@ -6,16 +6,17 @@
 #![warn(rust_2018_idioms)]
 
 use atty::Stream;
-use broker::api::remote::RemoteProvider;
 use broker::db;
 use broker::doc::crate_version;
 use broker::ext::error_stack::IntoContext;
+use broker::ext::tokio::CancellationToken;
+use broker::locale;
 use broker::{config, ext::error_stack::ErrorHelper};
 use broker::{
     doc,
-    ext::error_stack::{DescribeContext, ErrorDocReference, FatalErrorReport},
+    ext::error_stack::{DescribeContext, ErrorCodeReference, ErrorDocReference, FatalErrorReport},
 };
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use error_stack::{fmt::ColorMode, Report, Result, ResultExt};
 use tap::TapFallible;
 use tracing::debug;
@@ -56,12 +57,100 @@ enum Commands {
     /// Automatically detect problems with Broker and fix them.
     Fix(config::RawFixArgs),
 
+    /// Check that the host meets Broker's prerequisites, such as git, disk space, and network access.
+    Doctor(config::RawRunArgs),
+
     /// Run Broker with the current config.
     Run(config::RawRunArgs),
 
-    /// Attempt to do a git clone.
-    #[clap(hide = true)]
-    Clone(config::RawRunArgs),
+    /// Poll integrations once, scan the references that changed, then exit.
+    Scan(config::RawScanArgs),
+
+    /// Scan the most recent historical tags of an integration, then exit.
+    Backfill(config::RawBackfillArgs),
+
+    /// Replay recorded polls and FOSSA CLI output through the pipeline against a mock FOSSA endpoint, then exit.
+    Simulate(config::RawSimulateArgs),
+
+    /// Show how far along Broker is in scanning the references enqueued for each integration.
+    Status(config::RawRunArgs),
+
+    /// Watch integrations, queues, running jobs, and recent errors in a live terminal UI.
+    Monitor(config::RawRunArgs),
+
+    /// Update Broker to the latest release.
+    Update(config::RawUpdateArgs),
+
+    /// Inspect the Broker configuration.
+    #[clap(subcommand)]
+    Config(ConfigCommands),
+
+    /// Manage the Broker database.
+    #[clap(subcommand)]
+    Db(DbCommands),
+
+    /// Inspect the jobs enqueued by 'broker run'.
+    #[clap(subcommand)]
+    Queue(QueueCommands),
+
+    /// Pause an integration, or every integration with '--all', so that 'broker run' skips it until it's resumed.
+    Pause(config::RawPauseArgs),
+
+    /// Resume an integration, or every integration with '--all', after it was paused.
+    Resume(config::RawPauseArgs),
+
+    /// Report on the scans Broker performed and the issues FOSSA found in them.
+    #[clap(subcommand)]
+    Report(ReportCommands),
+
+    /// Diagnose problems with integrations.
+    #[clap(subcommand)]
+    Debug(DebugCommands),
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommands {
+    /// Show the config file, or with '--effective' the configuration as Broker resolved it.
+    Show(config::RawConfigShowArgs),
+
+    /// Check the config file for settings which are valid, but unused or ineffective.
+    Validate(config::RawRunArgs),
+}
+
+#[derive(Debug, Subcommand)]
+enum DbCommands {
+    /// Clear the stored state for an integration (or one of its references),
+    /// so that it is scanned again on the next poll.
+    Reset(config::RawDbResetArgs),
+
+    /// Move the stored state for a remote to a new remote, for example after the repository was renamed,
+    /// so that its references aren't scanned again.
+    MigrateRemote(config::RawDbMigrateRemoteArgs),
+
+    /// Show the database's schema version, the Broker version which claimed it, and its size.
+    Info(config::RawRunArgs),
+}
+
+#[derive(Debug, Subcommand)]
+enum QueueCommands {
+    /// List the jobs enqueued by 'broker run', and the scans waiting to be uploaded again.
+    Ls(config::RawRunArgs),
+
+    /// Drop a job so that it isn't worked on again, for example one that keeps crashing Broker.
+    Drop(config::RawQueueDropArgs),
+}
+
+#[derive(Debug, Subcommand)]
+enum DebugCommands {
+    /// Clone a branch or tag of an integration, printing each git command as it runs,
+    /// and keep the clone on disk for inspection.
+    Clone(config::RawDebugCloneArgs),
+}
+
+#[derive(Debug, Subcommand)]
+enum ReportCommands {
+    /// Summarize the repositories imported, revisions scanned, and issues found by severity over a period.
+    Summary(config::RawReportSummaryArgs),
 }
 
 #[tokio::main]
@@ -75,13 +164,30 @@ async fn main() -> Result<(), Error> {
     }
 
     // Subcommand routing.
-    let Opts { command } = Opts::parse();
+    let Opts { command } = parse_opts();
     let subcommand = || async {
         match command {
             Commands::Init(args) => main_init(args).await,
             Commands::Fix(args) => main_fix(args).await,
+            Commands::Doctor(args) => main_doctor(args).await,
             Commands::Run(args) => main_run(args).await,
-            Commands::Clone(args) => main_clone(args).await,
+            Commands::Scan(args) => main_scan(args).await,
+            Commands::Backfill(args) => main_backfill(args).await,
+            Commands::Simulate(args) => main_simulate(args).await,
+            Commands::Status(args) => main_status(args).await,
+            Commands::Monitor(args) => main_monitor(args).await,
+            Commands::Update(args) => main_update(args).await,
+            Commands::Config(ConfigCommands::Show(args)) => main_config_show(args).await,
+            Commands::Config(ConfigCommands::Validate(args)) => main_config_validate(args).await,
+            Commands::Db(DbCommands::Reset(args)) => main_db_reset(args).await,
+            Commands::Db(DbCommands::MigrateRemote(args)) => main_db_migrate_remote(args).await,
+            Commands::Db(DbCommands::Info(args)) => main_db_info(args).await,
+            Commands::Queue(QueueCommands::Ls(args)) => main_queue_ls(args).await,
+            Commands::Queue(QueueCommands::Drop(args)) => main_queue_drop(args).await,
+            Commands::Pause(args) => main_pause(args).await,
+            Commands::Resume(args) => main_resume(args).await,
+            Commands::Report(ReportCommands::Summary(args)) => main_report_summary(args).await,
+            Commands::Debug(DebugCommands::Clone(args)) => main_debug_clone(args).await,
         }
     };
 
@@ -111,17 +217,47 @@ async fn main() -> Result<(), Error> {
         }
     }
     // Decorate any error message with top level diagnostics and debugging help.
+    .error_codes()
     .request_support()
     .describe_lazy(|| format!("broker version: {version}"))
 }
 
+/// Parse the arguments, with the descriptions of subcommands in the current locale.
+fn parse_opts() -> Opts {
+    let command = localize_descriptions(Opts::command(), "");
+    Opts::from_arg_matches(&command.get_matches()).unwrap_or_else(|err| err.exit())
+}
+
+/// Replace the descriptions of the subcommands with their translations, if the current locale has them.
+fn localize_descriptions(command: clap::Command, parent: &str) -> clap::Command {
+    let names = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect::<Vec<_>>();
+    names.into_iter().fold(command, |command, name| {
+        let path = format!("{parent}{name}");
+        command.mut_subcommand(&name, |subcommand| {
+            let subcommand = localize_descriptions(subcommand, &format!("{path} "));
+            match locale::cli_description(&path) {
+                Some(about) => subcommand.about(about).long_about(None),
+                None => subcommand,
+            }
+        })
+    })
+}
+
 /// Initialize Broker configuration.
 async fn main_init(args: config::RawInitArgs) -> Result<(), Error> {
+    let examples = args.examples();
     let ctx = args
         .validate()
         .await
         .change_context(Error::DetermineEffectiveConfig)?;
-    broker::cmd::init::main(ctx.data_root()).change_context(Error::Runtime)
+    broker::cmd::init::main(ctx.data_root()).change_context(Error::Runtime)?;
+    if examples {
+        broker::cmd::init::examples(ctx.data_root()).change_context(Error::Runtime)?;
+    }
+    Ok(())
 }
 
 /// Automatically detect problems with Broker and fix them.
@@ -137,22 +273,49 @@ async fn main_fix(args: config::RawFixArgs) -> Result<(), Error> {
         .change_context(Error::DetermineEffectiveConfig)
         .documentation_lazy(doc::link::config_file_reference)?;
     debug!("Loaded {conf:?}");
+    if let Some(configured) = conf.locale() {
+        locale::install(*configured).change_context(Error::InternalSetup)?;
+    }
 
     let _tracing_guard = conf
         .debug()
-        .run_tracing_sink()
+        .run_tracing_sink(conf.secrets())
         .change_context(Error::InternalSetup)?;
 
+    let ctx =
+        config::apply(args.runtime().context(), &conf).change_context(Error::InternalSetup)?;
     broker::cmd::fix::main(
-        args.runtime().context(),
+        &ctx,
         &conf,
+        args.runtime().database_path().path(),
         &broker::cmd::fix::StdoutLogger,
         args.export_bundle(),
+        args.upload_bundle(),
+        args.scan_check(),
     )
     .await
     .change_context(Error::Runtime)
 }
 
+/// Check that the host meets Broker's prerequisites.
+async fn main_doctor(args: config::RawRunArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(&args)
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+    debug!("Loaded {conf:?}");
+
+    let ctx = config::apply(args.context(), &conf).change_context(Error::InternalSetup)?;
+    broker::cmd::doctor::main(&ctx, &conf, &broker::cmd::fix::StdoutLogger)
+        .await
+        .change_context(Error::Runtime)
+}
+
 /// Run Broker with the current config.
 async fn main_run(args: config::RawRunArgs) -> Result<(), Error> {
     let args = args.validate()
@@ -168,50 +331,385 @@ async fn main_run(args: config::RawRunArgs) -> Result<(), Error> {
 
     let _tracing_guard = conf
         .debug()
-        .run_tracing_sink()
+        .run_tracing_sink(conf.secrets())
         .change_context(Error::InternalSetup)?;
 
-    let db = db::connect_sqlite(args.database_path().path())
+    let db = db::connect_sqlite(args.database_path().path(), *conf.database())
         .await
         .change_context(Error::InternalSetup)?;
 
-    broker::cmd::run::main(args.context(), conf, db)
+    // The process exits on ctrl+c, so there's nothing to cancel the workers in the meantime.
+    let cancel = CancellationToken::new();
+    let ctx = config::apply(args.context(), &conf).change_context(Error::InternalSetup)?;
+    broker::cmd::run::main(&ctx, conf, db, cancel)
         .await
         .change_context(Error::Runtime)
 }
 
-/// Workflow:
-/// 1. get a list of remotes
-/// 2. For each remote, clone it into a directory and check out the tag or branch
-async fn main_clone(args: config::RawRunArgs) -> Result<(), Error> {
+/// Poll integrations once, scan the references that changed, then exit.
+async fn main_scan(args: config::RawScanArgs) -> Result<(), Error> {
     let args = args.validate()
         .await
         .change_context(Error::DetermineEffectiveConfig)
         .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
 
-    let conf = config::load(&args)
+    let conf = config::load(args.runtime())
         .await
         .change_context(Error::DetermineEffectiveConfig)
         .documentation_lazy(doc::link::config_file_reference)?;
+    debug!("Loaded {conf:?}");
 
     let _tracing_guard = conf
         .debug()
-        .run_tracing_sink()
+        .run_tracing_sink(conf.secrets())
         .change_context(Error::InternalSetup)?;
 
-    let integration = &conf.integrations().as_ref()[0];
-    let mut references = integration.references()
+    let db = db::connect_sqlite(args.runtime().database_path().path(), *conf.database())
+        .await
+        .change_context(Error::InternalSetup)?;
+
+    let ctx =
+        config::apply(args.runtime().context(), &conf).change_context(Error::InternalSetup)?;
+    broker::cmd::scan::main(
+        &ctx,
+        conf,
+        db,
+        args.group().as_deref(),
+        args.integration().as_deref(),
+        args.all(),
+    )
+    .await
+    .change_context(Error::Runtime)
+}
+
+/// Scan the most recent historical tags of an integration, then exit.
+async fn main_backfill(args: config::RawBackfillArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(args.runtime())
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+    debug!("Loaded {conf:?}");
+
+    let _tracing_guard = conf
+        .debug()
+        .run_tracing_sink(conf.secrets())
+        .change_context(Error::InternalSetup)?;
+
+    let db = db::connect_sqlite(args.runtime().database_path().path(), *conf.database())
+        .await
+        .change_context(Error::InternalSetup)?;
+
+    let ctx =
+        config::apply(args.runtime().context(), &conf).change_context(Error::InternalSetup)?;
+    broker::cmd::backfill::main(
+        &ctx,
+        conf,
+        db,
+        args.integration(),
+        args.tags_matching().as_ref(),
+        args.max(),
+    )
+    .await
+    .change_context(Error::Runtime)
+}
+
+/// Replay recorded polls and FOSSA CLI output through the pipeline against a mock FOSSA endpoint, then exit.
+async fn main_simulate(args: config::RawSimulateArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(args.runtime())
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+    debug!("Loaded {conf:?}");
+
+    let _tracing_guard = conf
+        .debug()
+        .run_tracing_sink(conf.secrets())
+        .change_context(Error::InternalSetup)?;
+
+    let ctx =
+        config::apply(args.runtime().context(), &conf).change_context(Error::InternalSetup)?;
+    broker::cmd::simulate::main(&ctx, conf, args.fixtures())
         .await
         .change_context(Error::Runtime)
+}
+
+/// Check for a newer release of Broker, and install it unless only checking.
+async fn main_update(args: config::RawUpdateArgs) -> Result<(), Error> {
+    let args = args
+        .validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+    broker::cmd::update::main(args.context(), args.check())
+        .await
+        .change_context(Error::Runtime)
+}
+
+/// Show the config file, or the effective configuration.
+async fn main_config_show(args: config::RawConfigShowArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
         .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
 
-    // clone the first 5 references that need to be scanned
-    references.truncate(5);
-    for reference in references {
-        integration
-            .clone_reference(&reference)
-            .await
-            .change_context(Error::Runtime)?;
+    let conf = config::load(args.runtime())
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+
+    if args.effective() {
+        broker::cmd::config::show_effective(&conf, args.format())
+    } else {
+        broker::cmd::config::show(args.runtime().config_path().path()).await
     }
+    .change_context(Error::Runtime)
+}
+
+/// Check the config file for settings which are likely unintended.
+async fn main_config_validate(args: config::RawRunArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(&args)
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+
+    broker::cmd::config::validate(&conf, args.database_path().path()).await;
     Ok(())
 }
+
+/// Clear the stored state for an integration so that it is scanned again.
+async fn main_db_reset(args: config::RawDbResetArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(args.runtime())
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+
+    let db = db::connect_sqlite(args.runtime().database_path().path(), *conf.database())
+        .await
+        .change_context(Error::InternalSetup)?;
+
+    broker::cmd::db::reset(&conf, &db, args.integration(), args.reference().as_deref())
+        .await
+        .change_context(Error::Runtime)
+}
+
+/// Move the stored state for a renamed remote so that it isn't scanned again.
+async fn main_db_migrate_remote(args: config::RawDbMigrateRemoteArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(args.runtime())
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+
+    let db = db::connect_sqlite(args.runtime().database_path().path(), *conf.database())
+        .await
+        .change_context(Error::InternalSetup)?;
+
+    broker::cmd::db::migrate_remote(&conf, &db, args.old(), args.new())
+        .await
+        .change_context(Error::Runtime)
+}
+
+/// Show progress through the backlog of each integration.
+async fn main_status(args: config::RawRunArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(&args)
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+
+    broker::cmd::status::main(&conf, args.database_path().path())
+        .await
+        .change_context(Error::Runtime)
+}
+
+/// Watch the state of Broker in a live terminal UI.
+async fn main_monitor(args: config::RawRunArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(&args)
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+
+    broker::cmd::monitor::main(&conf, args.database_path().path())
+        .await
+        .change_context(Error::Runtime)
+}
+
+/// Show information about the database without migrating or claiming it.
+async fn main_db_info(args: config::RawRunArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    broker::cmd::db::info(args.database_path().path())
+        .await
+        .change_context(Error::Runtime)
+}
+
+/// List the jobs enqueued by `broker run`.
+async fn main_queue_ls(args: config::RawRunArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(&args)
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+
+    broker::cmd::queue::list(&conf, args.database_path().path())
+        .await
+        .change_context(Error::Runtime)
+}
+
+/// Drop a job enqueued by `broker run`.
+async fn main_queue_drop(args: config::RawQueueDropArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(args.runtime())
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+
+    let db = db::connect_sqlite(args.runtime().database_path().path(), *conf.database())
+        .await
+        .change_context(Error::InternalSetup)?;
+
+    broker::cmd::queue::drop_job(args.runtime().context(), &conf, &db, args.id())
+        .await
+        .change_context(Error::Runtime)
+}
+
+/// Pause integrations so that `broker run` skips them.
+async fn main_pause(args: config::RawPauseArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(args.runtime())
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+
+    let db = db::connect_sqlite(args.runtime().database_path().path(), *conf.database())
+        .await
+        .change_context(Error::InternalSetup)?;
+
+    broker::cmd::pause::pause(&conf, &db, args.integration().as_deref())
+        .await
+        .change_context(Error::Runtime)
+}
+
+/// Resume integrations that were paused.
+async fn main_resume(args: config::RawPauseArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(args.runtime())
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+
+    let db = db::connect_sqlite(args.runtime().database_path().path(), *conf.database())
+        .await
+        .change_context(Error::InternalSetup)?;
+
+    broker::cmd::pause::resume(&conf, &db, args.integration().as_deref())
+        .await
+        .change_context(Error::Runtime)
+}
+
+/// Summarize the scans recorded over a period and the issues FOSSA found in them.
+async fn main_report_summary(args: config::RawReportSummaryArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(args.runtime())
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+
+    let ctx =
+        config::apply(args.runtime().context(), &conf).change_context(Error::InternalSetup)?;
+    broker::cmd::report::summary(
+        &ctx,
+        &conf,
+        args.runtime().database_path().path(),
+        args.since(),
+        args.format(),
+    )
+    .await
+    .change_context(Error::Runtime)
+}
+
+/// Clone a reference of an integration and keep it on disk, printing the git commands used.
+async fn main_debug_clone(args: config::RawDebugCloneArgs) -> Result<(), Error> {
+    let args = args.validate()
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .help("try running Broker with the '--help' argument to see available options and usage suggestions")?;
+
+    let conf = config::load(args.runtime())
+        .await
+        .change_context(Error::DetermineEffectiveConfig)
+        .documentation_lazy(doc::link::config_file_reference)?;
+    debug!("Loaded {conf:?}");
+
+    let _tracing_guard = conf
+        .debug()
+        .run_tracing_sink(conf.secrets())
+        .change_context(Error::InternalSetup)?;
+
+    // The clone uses the configured git backend and bandwidth limit, which this installs.
+    config::apply(args.runtime().context(), &conf).change_context(Error::InternalSetup)?;
+    broker::cmd::debug::clone(
+        &conf,
+        args.integration(),
+        args.reference().as_deref(),
+        args.dest().as_deref(),
+    )
+    .await
+    .change_context(Error::Runtime)
+}
@@ -0,0 +1,413 @@
+//! Notifications sent to external services when imports fail.
+//!
+//! Without notifications, failures are only visible in Broker's logs,
+//! so broken imports can otherwise go unnoticed for a long time.
+//! Each configured sink receives the events it is subscribed to;
+//! failing to deliver a notification is logged but otherwise does not interrupt Broker.
+//!
+//! Webhooks are sent a notification for each event as it happens,
+//! while SMTP sinks collect events and send them periodically as a digest e-mail.
+
+use std::{fmt::Display, time::Duration};
+
+use derive_new::new;
+use error_stack::{report, Report};
+use futures::future::join_all;
+use getset::Getters;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    api::http::client::Client,
+    ext::{error_stack::ErrorHelper, result::WrapErr},
+};
+
+pub mod smtp;
+pub mod webhook;
+
+/// Errors that are possibly surfaced during validation of config values.
+#[derive(Debug, thiserror::Error)]
+pub enum ValidationError {
+    /// The URL for a webhook must be a valid URL.
+    #[error("webhook url is not valid")]
+    Url,
+
+    /// Message templates may only use known placeholders.
+    #[error("unknown placeholder in template: '{{{0}}}'")]
+    TemplatePlaceholder(String),
+
+    /// Digest intervals are parsed from a user-provided string.
+    #[error("validate digest interval")]
+    DigestInterval,
+
+    /// Digest intervals must be at least a certain minimum.
+    #[error("digest interval must be a minimum of {}", humantime::format_duration(smtp::MIN_DIGEST_INTERVAL).to_string())]
+    MinDigestInterval,
+
+    /// The SMTP host must be provided.
+    #[error("smtp host is empty")]
+    SmtpHost,
+
+    /// SMTP credentials must be provided together.
+    #[error("smtp username and password must be provided together")]
+    SmtpCredentials,
+
+    /// SMTP sinks must have at least one recipient.
+    #[error("smtp recipients are empty")]
+    SmtpRecipients,
+
+    /// E-mail addresses must be valid.
+    #[error("e-mail address is not valid: '{0}'")]
+    EmailAddress(String),
+}
+
+/// Validated config values for notifications.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Getters, new)]
+#[getset(get = "pub")]
+pub struct Config {
+    /// The configured notification sinks.
+    sinks: Vec<Sink>,
+}
+
+/// A destination for notifications.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sink {
+    /// Notifications are sent as HTTP requests to a webhook.
+    Webhook(webhook::Webhook),
+
+    /// Notifications are collected and periodically sent as a digest e-mail.
+    Smtp(smtp::Smtp),
+}
+
+/// The kinds of events for which notifications are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Kind {
+    /// Broker failed to poll an integration for references.
+    PollFailure,
+
+    /// Broker failed to clone or analyze a reference.
+    ScanFailure,
+
+    /// Broker failed to upload the results of a scan to FOSSA.
+    UploadFailure,
+
+    /// Cloning or analyzing a reference took much longer than the recent average for the integration.
+    SlowScan,
+
+    /// A location Broker writes to has less free space than the configured minimum, so new scans are paused.
+    LowDiskSpace,
+
+    /// FOSSA found issues in an uploaded scan, or couldn't check it for issues.
+    PolicyFailure,
+
+    /// A branch was rewritten, for example by a force push: it no longer descends from the commit last scanned.
+    ReferenceRewritten,
+
+    /// Secrets were found in the results of a scan before they were uploaded.
+    SecretsFound,
+
+    /// A scan was skipped because it exceeded a size limit.
+    ScanTooLarge,
+}
+
+impl Kind {
+    /// Every kind of event.
+    pub const ALL: [Kind; 9] = [
+        Kind::PollFailure,
+        Kind::ScanFailure,
+        Kind::UploadFailure,
+        Kind::SlowScan,
+        Kind::LowDiskSpace,
+        Kind::PolicyFailure,
+        Kind::ReferenceRewritten,
+        Kind::SecretsFound,
+        Kind::ScanTooLarge,
+    ];
+
+    /// A short human readable description of the event.
+    fn description(self) -> &'static str {
+        match self {
+            Kind::PollFailure => "poll failed",
+            Kind::ScanFailure => "scan failed",
+            Kind::UploadFailure => "upload failed",
+            Kind::SlowScan => "scan was slow",
+            Kind::LowDiskSpace => "is low on disk space",
+            Kind::PolicyFailure => "failed policy check",
+            Kind::ReferenceRewritten => "branch was rewritten",
+            Kind::SecretsFound => "scan contained secrets",
+            Kind::ScanTooLarge => "skipped oversized scan",
+        }
+    }
+}
+
+/// The kinds of events to which a sink is subscribed.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Subscription {
+    /// The sink receives every kind of event.
+    #[default]
+    All,
+
+    /// The sink receives only the listed kinds of event.
+    Only(Vec<Kind>),
+}
+
+impl Subscription {
+    /// Whether the subscription includes the kind of event.
+    pub fn accepts(&self, kind: Kind) -> bool {
+        match self {
+            Subscription::All => true,
+            Subscription::Only(kinds) => kinds.contains(&kind),
+        }
+    }
+
+    /// The kinds of event included in the subscription.
+    pub fn kinds(&self) -> Vec<Kind> {
+        match self {
+            Subscription::All => Kind::ALL.to_vec(),
+            Subscription::Only(kinds) => kinds.clone(),
+        }
+    }
+}
+
+impl From<Vec<Kind>> for Subscription {
+    fn from(kinds: Vec<Kind>) -> Self {
+        if kinds.is_empty() {
+            Self::All
+        } else {
+            Self::Only(kinds)
+        }
+    }
+}
+
+/// A message template, in which placeholders such as `{integration}` are replaced with the details of the event.
+///
+/// The available placeholders are `{kind}`, `{integration}`, `{reference}`, `{scan_id}`, and `{error}`.
+/// Placeholders for details which don't apply to the event (for example, `{reference}` for a poll failure)
+/// are replaced with an empty string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(String);
+
+static PLACEHOLDER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{(\w+)\}").expect("invariant: placeholder pattern must be valid"));
+
+const PLACEHOLDERS: [&str; 5] = ["kind", "integration", "reference", "scan_id", "error"];
+
+impl Template {
+    /// The template as written in the config file.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Render the template for the event.
+    pub fn render(&self, event: &Event) -> String {
+        PLACEHOLDER
+            .replace_all(&self.0, |caps: &regex::Captures<'_>| match &caps[1] {
+                "kind" => event.kind.to_string(),
+                "integration" => event.integration.clone(),
+                "reference" => event.reference.clone().unwrap_or_default(),
+                "scan_id" => event.scan_id.clone().unwrap_or_default(),
+                "error" => event.error.clone(),
+                other => format!("{{{other}}}"),
+            })
+            .into_owned()
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = Report<ValidationError>;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if let Some(unknown) = PLACEHOLDER
+            .captures_iter(&value)
+            .map(|caps| caps[1].to_string())
+            .find(|name| !PLACEHOLDERS.contains(&name.as_str()))
+        {
+            return report!(ValidationError::TemplatePlaceholder(unknown))
+                .wrap_err()
+                .help("available placeholders are: {kind}, {integration}, {reference}, {scan_id}, {error}");
+        }
+        Ok(Self(value))
+    }
+}
+
+/// An event about which to notify.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Event {
+    kind: Kind,
+    integration: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scan_id: Option<String>,
+
+    error: String,
+}
+
+impl Event {
+    /// Create an event for an integration.
+    ///
+    /// `error` is a summary of the problem, which is shown to the people receiving the notification.
+    pub fn new(kind: Kind, integration: impl Display, error: impl Display) -> Self {
+        Self {
+            kind,
+            integration: integration.to_string(),
+            reference: None,
+            scan_id: None,
+            error: error.to_string(),
+        }
+    }
+
+    /// The reference to which the event relates.
+    pub fn reference(mut self, reference: impl Display) -> Self {
+        self.reference = Some(reference.to_string());
+        self
+    }
+
+    /// The scan to which the event relates.
+    pub fn scan_id(mut self, scan_id: impl Display) -> Self {
+        self.scan_id = Some(scan_id.to_string());
+        self
+    }
+
+    /// The kind of event.
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    /// Render the message for the event, using the template if one is provided.
+    pub fn message(&self, template: Option<&Template>) -> String {
+        if let Some(template) = template {
+            return template.render(self);
+        }
+
+        let description = self.kind.description();
+        match &self.reference {
+            Some(reference) => format!(
+                "Broker {description} for '{}' at '{reference}': {}",
+                self.integration, self.error
+            ),
+            None => format!(
+                "Broker {description} for '{}': {}",
+                self.integration, self.error
+            ),
+        }
+    }
+}
+
+/// Sends notifications to the configured sinks.
+#[derive(Debug)]
+pub struct Notifier {
+    webhooks: Vec<webhook::Webhook>,
+    digests: Vec<smtp::Digest>,
+    client: Client,
+}
+
+impl Notifier {
+    /// How long to wait for a sink to accept a notification.
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Create a notifier for the configured sinks, which delivers notifications to webhooks with the provided client.
+    pub fn new(config: Config, client: Client) -> Self {
+        let mut webhooks = Vec::new();
+        let mut digests = Vec::new();
+        for sink in config.sinks {
+            match sink {
+                Sink::Webhook(webhook) => webhooks.push(webhook),
+                Sink::Smtp(smtp) => digests.push(smtp::Digest::new(smtp)),
+            }
+        }
+
+        Self {
+            webhooks,
+            digests,
+            client,
+        }
+    }
+
+    /// Send the event to each sink subscribed to it.
+    /// For sinks that send digests, the event is included in the next digest.
+    ///
+    /// Failing to deliver a notification is logged, but is otherwise not an error.
+    pub async fn notify(&self, event: Event) {
+        for digest in &self.digests {
+            digest.push(&event);
+        }
+
+        for webhook in &self.webhooks {
+            if !webhook.events().accepts(event.kind()) {
+                continue;
+            }
+            if let Err(err) = webhook.send(&self.client, &event).await {
+                warn!(kind = %event.kind(), "Unable to send notification: {err:#?}");
+            }
+        }
+    }
+
+    /// Periodically send digests for the sinks which collect events, for as long as Broker runs.
+    pub async fn run_digests(&self) {
+        join_all(self.digests.iter().map(smtp::Digest::run)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_template() {
+        let template = Template::try_from(String::from(
+            "{kind}: {integration} {reference} ({scan_id}) {error}",
+        ))
+        .expect("must be valid");
+
+        let event = Event::new(
+            Kind::ScanFailure,
+            "git@github.com:fossas/broker.git",
+            "boom",
+        )
+        .reference("main")
+        .scan_id("1234");
+        assert_eq!(
+            template.render(&event),
+            "scan_failure: git@github.com:fossas/broker.git main (1234) boom"
+        );
+
+        let event = Event::new(
+            Kind::PollFailure,
+            "git@github.com:fossas/broker.git",
+            "boom",
+        );
+        assert_eq!(
+            template.render(&event),
+            "poll_failure: git@github.com:fossas/broker.git  () boom"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_placeholder() {
+        Template::try_from(String::from("{integration} {branch}")).expect_err("must be invalid");
+    }
+
+    #[test]
+    fn default_message() {
+        let event = Event::new(Kind::UploadFailure, "example.com/repo", "boom").reference("main");
+        assert_eq!(
+            event.message(None),
+            "Broker upload failed for 'example.com/repo' at 'main': boom"
+        );
+    }
+
+    #[test]
+    fn subscription() {
+        assert!(Subscription::from(vec![]).accepts(Kind::PollFailure));
+        let only = Subscription::from(vec![Kind::ScanFailure]);
+        assert!(only.accepts(Kind::ScanFailure));
+        assert!(!only.accepts(Kind::PollFailure));
+    }
+}
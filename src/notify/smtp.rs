@@ -0,0 +1,510 @@
+//! Notifications delivered as digest e-mails over SMTP.
+//!
+//! Rather than sending an e-mail for every failure, events are collected
+//! and sent together as a single digest once per configured interval.
+//! If a digest can't be sent, its events are kept and included in the next digest.
+
+use std::{
+    sync::{Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use error_stack::{ensure, report, Report};
+use getset::{CopyGetters, Getters};
+use humantime::parse_duration;
+use itertools::Itertools;
+use lettre::{
+    message::Mailbox,
+    transport::smtp::{
+        authentication::Credentials,
+        client::{self, TlsParameters},
+    },
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::ext::{
+    error_stack::{DescribeContext, ErrorHelper, IntoContext},
+    result::{WrapErr, WrapOk},
+    secrecy::ComparableSecretString,
+};
+
+use super::{Event, Subscription, Template, ValidationError};
+
+/// Errors encountered delivering notifications over SMTP.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The e-mail could not be constructed.
+    #[error("build digest e-mail")]
+    Build,
+
+    /// The connection to the SMTP server could not be configured.
+    #[error("configure connection to SMTP server")]
+    Connect,
+
+    /// The SMTP server did not accept the e-mail.
+    #[error("send digest e-mail")]
+    Send,
+}
+
+/// How the connection to the SMTP server is secured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tls {
+    /// Connect in plain text, then upgrade the connection with `STARTTLS`.
+    #[default]
+    Starttls,
+
+    /// Connect over TLS.
+    Tls,
+
+    /// Don't secure the connection. Only appropriate for a relay on a trusted network.
+    None,
+}
+
+impl Tls {
+    /// The conventional port for the connection type.
+    pub fn default_port(self) -> u16 {
+        match self {
+            Tls::Starttls => 587,
+            Tls::Tls => 465,
+            Tls::None => 25,
+        }
+    }
+}
+
+/// The minimum interval between digests.
+pub const MIN_DIGEST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often digests are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DigestInterval(Duration);
+
+impl DigestInterval {
+    /// The interval used if none is configured.
+    pub const DEFAULT: Self = Self(Duration::from_secs(60 * 60));
+
+    /// Convert the interval to a duration.
+    pub fn as_duration(&self) -> Duration {
+        self.0
+    }
+}
+
+impl Default for DigestInterval {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl TryFrom<String> for DigestInterval {
+    type Error = Report<ValidationError>;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let parsed = parse_duration(&value)
+            .context(ValidationError::DigestInterval)
+            .describe_lazy(|| format!("provided value: {value}"))?;
+
+        ensure!(
+            parsed >= MIN_DIGEST_INTERVAL,
+            ValidationError::MinDigestInterval
+        );
+        Self(parsed).wrap_ok()
+    }
+}
+
+/// Credentials used to authenticate to the SMTP server.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct Auth {
+    /// The username.
+    username: String,
+
+    /// The password.
+    password: ComparableSecretString,
+}
+
+/// An SMTP server to which digest e-mails are sent.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct Smtp {
+    /// The host name of the SMTP server.
+    #[getset(get = "pub")]
+    host: String,
+
+    /// The port of the SMTP server.
+    #[getset(get_copy = "pub")]
+    port: u16,
+
+    /// How the connection to the SMTP server is secured.
+    #[getset(get_copy = "pub")]
+    tls: Tls,
+
+    /// Credentials for the SMTP server, if it requires authentication.
+    #[getset(get = "pub")]
+    auth: Option<Auth>,
+
+    /// The sender of the e-mail.
+    #[getset(get = "pub")]
+    from: Mailbox,
+
+    /// The recipients of the e-mail.
+    #[getset(get = "pub")]
+    to: Vec<Mailbox>,
+
+    /// How often digests are sent.
+    #[getset(get_copy = "pub")]
+    interval: DigestInterval,
+
+    /// The events included in the digest.
+    #[getset(get = "pub")]
+    events: Subscription,
+
+    /// The template for each event in the digest; if not provided, a default message is used.
+    #[getset(get = "pub")]
+    template: Option<Template>,
+}
+
+impl Smtp {
+    /// Create an SMTP sink, validating the addresses and credentials.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        host: String,
+        port: Option<u16>,
+        tls: Tls,
+        username: Option<String>,
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+        interval: DigestInterval,
+        events: Subscription,
+        template: Option<Template>,
+    ) -> Result<Self, Report<ValidationError>> {
+        ensure!(!host.is_empty(), ValidationError::SmtpHost);
+
+        let auth = match (username, password) {
+            (Some(username), Some(password)) => Some(Auth {
+                username,
+                password: ComparableSecretString::from(password),
+            }),
+            (None, None) => None,
+            _ => {
+                return report!(ValidationError::SmtpCredentials)
+                    .wrap_err()
+                    .help("provide both 'username' and 'password', or neither")
+            }
+        };
+
+        let from = parse_mailbox(from)?;
+        if to.is_empty() {
+            return report!(ValidationError::SmtpRecipients)
+                .wrap_err()
+                .help("provide at least one address in 'to'");
+        }
+        let to = to
+            .into_iter()
+            .map(parse_mailbox)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            host,
+            port: port.unwrap_or_else(|| tls.default_port()),
+            tls,
+            auth,
+            from,
+            to,
+            interval,
+            events,
+            template,
+        })
+    }
+
+    /// Build the digest e-mail for the events.
+    fn message(&self, digest: &Pending) -> Result<Message, Report<Error>> {
+        let subject = format!(
+            "Broker: {} import failures since the last digest",
+            digest.count()
+        );
+        let builder = Message::builder().from(self.from.clone()).subject(subject);
+        self.to
+            .iter()
+            .cloned()
+            .fold(builder, |builder, to| builder.to(to))
+            .body(self.body(digest))
+            .context(Error::Build)
+    }
+
+    /// The plain text body of the digest e-mail, listing each event.
+    fn body(&self, digest: &Pending) -> String {
+        let count = digest.count();
+        let mut body = format!("Broker encountered {count} failures since the last digest:\n\n");
+        for (at, event) in &digest.events {
+            let at = humantime::format_rfc3339_seconds(*at);
+            let message = event.message(self.template.as_ref());
+            body.push_str(&format!("- {at}: {message}\n"));
+        }
+        if digest.omitted > 0 {
+            body.push_str(&format!(
+                "- {} older failures were omitted; see Broker's logs for details.\n",
+                digest.omitted
+            ));
+        }
+        body
+    }
+
+    /// Send the digest e-mail for the events.
+    async fn send(&self, digest: &Pending) -> Result<(), Report<Error>> {
+        let message = self.message(digest)?;
+        let builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.host);
+        let builder = match self.tls {
+            Tls::Starttls => builder.tls(client::Tls::Required(
+                tls_parameters(&self.host).context(Error::Connect)?,
+            )),
+            Tls::Tls => builder.tls(client::Tls::Wrapper(
+                tls_parameters(&self.host).context(Error::Connect)?,
+            )),
+            Tls::None => builder,
+        };
+        let builder = builder.port(self.port).timeout(Some(Self::TIMEOUT));
+        let builder = match &self.auth {
+            Some(auth) => builder.credentials(Credentials::new(
+                auth.username.clone(),
+                auth.password.expose_secret().to_string(),
+            )),
+            None => builder,
+        };
+
+        builder
+            .build()
+            .send(message)
+            .await
+            .context(Error::Send)
+            .describe_lazy(|| format!("sending to '{}:{}'", self.host, self.port))
+            .help("ensure the SMTP server settings are correct and that the server accepts e-mail from Broker")
+            .map(|_| ())
+    }
+
+    /// How long to wait for the SMTP server to respond.
+    const TIMEOUT: Duration = Duration::from_secs(60);
+}
+
+/// The parameters for connecting to the host over TLS with [`crate::crypto::TLS_PROVIDER`].
+#[cfg(not(feature = "fips"))]
+fn tls_parameters(host: &str) -> Result<TlsParameters, lettre::transport::smtp::Error> {
+    TlsParameters::builder(host.to_string()).build_rustls()
+}
+
+/// The parameters for connecting to the host over TLS with [`crate::crypto::TLS_PROVIDER`].
+#[cfg(feature = "fips")]
+fn tls_parameters(host: &str) -> Result<TlsParameters, lettre::transport::smtp::Error> {
+    TlsParameters::builder(host.to_string()).build_native()
+}
+
+fn parse_mailbox(address: String) -> Result<Mailbox, Report<ValidationError>> {
+    address
+        .parse::<Mailbox>()
+        .context_lazy(|| ValidationError::EmailAddress(address.clone()))
+        .help("addresses may be plain, like 'team@example.com', or named, like 'Team <team@example.com>'")
+}
+
+/// The maximum number of events kept for the next digest.
+/// If more failures than this occur, only the most recent are listed.
+const MAX_DIGEST_EVENTS: usize = 1000;
+
+/// Events waiting to be sent in the next digest.
+#[derive(Debug, Default)]
+struct Pending {
+    events: Vec<(SystemTime, Event)>,
+    omitted: usize,
+}
+
+impl Pending {
+    fn push(&mut self, at: SystemTime, event: Event) {
+        self.events.push((at, event));
+        if self.events.len() > MAX_DIGEST_EVENTS {
+            let excess = self.events.len() - MAX_DIGEST_EVENTS;
+            self.events.drain(..excess);
+            self.omitted += excess;
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.events.is_empty() && self.omitted == 0
+    }
+
+    fn count(&self) -> usize {
+        self.events.len() + self.omitted
+    }
+
+    /// Put events from a digest which failed to send back, ahead of any events collected since.
+    fn restore(&mut self, earlier: Pending) {
+        let later = std::mem::replace(self, earlier);
+        self.omitted += later.omitted;
+        for (at, event) in later.events {
+            self.push(at, event);
+        }
+    }
+}
+
+/// Collects events for an SMTP sink and periodically sends them as a digest.
+#[derive(Debug)]
+pub struct Digest {
+    smtp: Smtp,
+    pending: Mutex<Pending>,
+}
+
+impl Digest {
+    /// Create a digest for the SMTP sink.
+    pub fn new(smtp: Smtp) -> Self {
+        Self {
+            smtp,
+            pending: Mutex::new(Pending::default()),
+        }
+    }
+
+    /// Add the event to the next digest, if the sink is subscribed to it.
+    pub fn push(&self, event: &Event) {
+        if self.smtp.events.accepts(event.kind()) {
+            self.pending
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(SystemTime::now(), event.clone());
+        }
+    }
+
+    /// Send a digest every interval, for as long as Broker runs.
+    pub async fn run(&self) {
+        let interval = self.smtp.interval.as_duration();
+        loop {
+            tokio::time::sleep(interval).await;
+            self.flush().await;
+        }
+    }
+
+    /// Send a digest of the pending events, if there are any.
+    async fn flush(&self) {
+        let digest = {
+            let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+            std::mem::take(&mut *pending)
+        };
+        if digest.is_empty() {
+            return;
+        }
+
+        let count = digest.count();
+        match self.smtp.send(&digest).await {
+            Ok(_) => info!(
+                "Sent digest of {count} failures to {}",
+                self.smtp.to.iter().join(", ")
+            ),
+            Err(err) => {
+                warn!(
+                    "Unable to send digest of {count} failures, retrying next interval: {err:#?}"
+                );
+                self.pending
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .restore(digest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::notify::Kind;
+
+    fn smtp() -> Smtp {
+        Smtp::new(
+            String::from("smtp.example.com"),
+            None,
+            Tls::Starttls,
+            Some(String::from("broker")),
+            Some(String::from("hunter2")),
+            String::from("Broker <broker@example.com>"),
+            vec![String::from("team@example.com")],
+            DigestInterval::DEFAULT,
+            Subscription::All,
+            None,
+        )
+        .expect("must be valid")
+    }
+
+    #[test]
+    fn validates_settings() {
+        let smtp = smtp();
+        assert_eq!(smtp.port(), 587);
+
+        Smtp::new(
+            String::from("smtp.example.com"),
+            None,
+            Tls::Starttls,
+            Some(String::from("broker")),
+            None,
+            String::from("broker@example.com"),
+            vec![String::from("team@example.com")],
+            DigestInterval::DEFAULT,
+            Subscription::All,
+            None,
+        )
+        .expect_err("must require both username and password");
+
+        Smtp::new(
+            String::from("smtp.example.com"),
+            None,
+            Tls::Starttls,
+            None,
+            None,
+            String::from("not an address"),
+            vec![String::from("team@example.com")],
+            DigestInterval::DEFAULT,
+            Subscription::All,
+            None,
+        )
+        .expect_err("must require a valid address");
+    }
+
+    #[test]
+    fn validates_interval() {
+        let interval = DigestInterval::try_from(String::from("30m")).expect("must be valid");
+        assert_eq!(interval.as_duration(), Duration::from_secs(30 * 60));
+        DigestInterval::try_from(String::from("10s")).expect_err("must be above minimum");
+    }
+
+    #[test]
+    fn keeps_most_recent_events() {
+        let mut pending = Pending::default();
+        for i in 0..MAX_DIGEST_EVENTS + 5 {
+            let event = Event::new(Kind::ScanFailure, "example.com/repo", i);
+            pending.push(SystemTime::UNIX_EPOCH, event);
+        }
+        assert_eq!(pending.events.len(), MAX_DIGEST_EVENTS);
+        assert_eq!(pending.omitted, 5);
+
+        let (_, first) = &pending.events[0];
+        assert_eq!(first, &Event::new(Kind::ScanFailure, "example.com/repo", 5));
+    }
+
+    #[test]
+    fn builds_digest() {
+        let mut pending = Pending::default();
+        let event = Event::new(Kind::UploadFailure, "example.com/repo", "boom").reference("main");
+        pending.push(SystemTime::UNIX_EPOCH, event);
+
+        let smtp = smtp();
+        assert_eq!(
+            smtp.body(&pending),
+            indoc! {"
+            Broker encountered 1 failures since the last digest:
+
+            - 1970-01-01T00:00:00Z: Broker upload failed for 'example.com/repo' at 'main': boom
+            "}
+        );
+
+        let message = smtp.message(&pending).expect("must build");
+        let formatted = String::from_utf8(message.formatted()).expect("must be utf8");
+        assert!(formatted.contains("Subject: Broker: 1 import failures since the last digest"));
+    }
+}
@@ -0,0 +1,166 @@
+//! Notifications delivered to webhooks.
+
+use error_stack::{report, Report};
+use getset::Getters;
+use serde_json::json;
+
+use crate::{
+    api::http::client::Client,
+    ext::{
+        error_stack::{DescribeContext, ErrorHelper, IntoContext},
+        result::WrapErr,
+        secrecy::ComparableSecretString,
+    },
+};
+
+use super::{Event, Notifier, Subscription, Template, ValidationError};
+
+/// Errors encountered delivering notifications to a webhook.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The request to the webhook failed.
+    #[error("send request to webhook")]
+    Send,
+
+    /// The webhook responded with an unsuccessful status.
+    #[error("webhook responded with status {0}")]
+    Status(reqwest::StatusCode),
+}
+
+/// The format of the request body sent to the webhook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The event is sent as a JSON object, along with the rendered message.
+    Json,
+
+    /// The rendered message is sent in the form expected by Slack incoming webhooks.
+    Slack,
+}
+
+/// A webhook to which notifications are delivered.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct Webhook {
+    /// The URL to which notifications are sent.
+    ///
+    /// Webhook URLs (such as Slack incoming webhooks) often embed a secret, so they are redacted.
+    url: ComparableSecretString,
+
+    /// The format of the request body.
+    format: Format,
+
+    /// The events sent to the webhook.
+    events: Subscription,
+
+    /// The template for the message; if not provided, a default message is used.
+    template: Option<Template>,
+}
+
+impl Webhook {
+    /// Create a webhook, validating that the URL is valid.
+    pub fn new(
+        url: String,
+        format: Format,
+        events: Subscription,
+        template: Option<Template>,
+    ) -> Result<Self, Report<ValidationError>> {
+        if url::Url::parse(&url).is_err() {
+            return report!(ValidationError::Url)
+                .wrap_err()
+                .help("the webhook url must be a full url, such as 'https://hooks.slack.com/services/...'");
+        }
+
+        Ok(Self {
+            url: ComparableSecretString::from(url),
+            format,
+            events,
+            template,
+        })
+    }
+
+    /// The request body for the event.
+    fn body(&self, event: &Event) -> serde_json::Value {
+        let message = event.message(self.template.as_ref());
+        match self.format {
+            Format::Json => json!({ "event": event, "message": message }),
+            Format::Slack => json!({ "text": message }),
+        }
+    }
+
+    /// Deliver the event to the webhook.
+    pub async fn send(&self, client: &Client, event: &Event) -> Result<(), Report<Error>> {
+        // Errors from `reqwest` include the URL, which is secret; strip it.
+        let request = client
+            .post(self.url.expose_secret())
+            .timeout(Notifier::TIMEOUT)
+            .json(&self.body(event));
+        let response = client
+            .send(request)
+            .await
+            .map_err(|err| err.without_url())
+            .context(Error::Send)
+            .describe_lazy(|| format!("sending '{}' notification", event.kind()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return report!(Error::Status(status))
+                .wrap_err()
+                .help("ensure the webhook url is correct and that the service accepts requests from Broker");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::Kind;
+
+    #[test]
+    fn formats_body() {
+        let event = Event::new(Kind::ScanFailure, "example.com/repo", "boom").reference("main");
+        let webhook = Webhook::new(
+            String::from("https://example.com/hook"),
+            Format::Slack,
+            Subscription::All,
+            None,
+        )
+        .expect("must be valid");
+        assert_eq!(
+            webhook.body(&event),
+            json!({ "text": "Broker scan failed for 'example.com/repo' at 'main': boom" })
+        );
+
+        let webhook = Webhook::new(
+            String::from("https://example.com/hook"),
+            Format::Json,
+            Subscription::All,
+            None,
+        )
+        .expect("must be valid");
+        assert_eq!(
+            webhook.body(&event),
+            json!({
+                "event": {
+                    "kind": "scan_failure",
+                    "integration": "example.com/repo",
+                    "reference": "main",
+                    "error": "boom",
+                },
+                "message": "Broker scan failed for 'example.com/repo' at 'main': boom",
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_url() {
+        Webhook::new(
+            String::from("not a url"),
+            Format::Json,
+            Subscription::All,
+            None,
+        )
+        .expect_err("must be invalid");
+    }
+}
@@ -1,9 +1,26 @@
 //! Async work queue implementation.
+//!
+//! Items are delivered at least once: each item received from the queue is held by a [`Guard`],
+//! which must be committed once the item is handled. If the guard is dropped without being committed,
+//! for example because the worker handling it failed or panicked, the item is redelivered
+//! so that another receiver can handle it, up to [`MAX_DELIVERIES`] times.
+//!
+//! Redelivered items were already admitted by the queue's limit when they were sent,
+//! so they're never dropped for lack of room: if senders filled the queue in the meantime,
+//! the item is held aside and delivered before the items in the queue.
 
-use std::{fmt::Debug, marker::PhantomData};
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    marker::PhantomData,
+    ops::Deref,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
 
 use error_stack::Report;
 use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Notify;
+use tracing::warn;
 
 use crate::ext::error_stack::IntoContext;
 
@@ -24,10 +41,65 @@ pub enum Error {
 /// The default limit for a queue.
 pub const DEFAULT_LIMIT: usize = 1000;
 
+/// The number of times an item is delivered before it's discarded, if it's never committed.
+pub const MAX_DELIVERIES: u32 = 3;
+
 /// A queue implementation specialized to the type of data being sent through it.
 pub struct Queue<T> {
     t: PhantomData<T>,
-    internal: deadqueue::limited::Queue<Vec<u8>>,
+    internal: Internal,
+}
+
+/// The items in a queue, regardless of their type.
+struct Internal {
+    /// Items waiting to be delivered, limited to the size of the queue.
+    items: deadqueue::limited::Queue<Envelope>,
+
+    /// Items to redeliver which didn't fit in `items`; these are delivered first.
+    overflow: Mutex<VecDeque<Envelope>>,
+
+    /// Notified when an item is added to `overflow`.
+    overflowed: Notify,
+}
+
+impl Internal {
+    fn overflow(&self) -> MutexGuard<'_, VecDeque<Envelope>> {
+        self.overflow.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Take the next item, waiting until one is available.
+    async fn pop(&self) -> Envelope {
+        loop {
+            if let Some(envelope) = self.try_pop() {
+                return envelope;
+            }
+            tokio::select! {
+                envelope = self.items.pop() => return envelope,
+                // The notification is kept if nothing is waiting yet, so an item added to the overflow
+                // after checking it above still wakes this up.
+                _ = self.overflowed.notified() => {}
+            }
+        }
+    }
+
+    /// Take the next item if one is available, without waiting.
+    fn try_pop(&self) -> Option<Envelope> {
+        self.overflow().pop_front().or_else(|| self.items.try_pop())
+    }
+
+    /// Put the item back to be delivered again, even if the queue is full.
+    fn redeliver(&self, envelope: Envelope) {
+        if let Err(envelope) = self.items.try_push(envelope) {
+            self.overflow().push_back(envelope);
+            self.overflowed.notify_one();
+        }
+    }
+}
+
+/// An item in the queue, along with how many times it has been delivered.
+struct Envelope {
+    data: Vec<u8>,
+    deliveries: u32,
 }
 
 impl<T> Queue<T>
@@ -40,7 +112,11 @@ where
     pub fn new(size: usize) -> Self {
         Self {
             t: PhantomData,
-            internal: deadqueue::limited::Queue::new(size),
+            internal: Internal {
+                items: deadqueue::limited::Queue::new(size),
+                overflow: Mutex::default(),
+                overflowed: Notify::new(),
+            },
         }
     }
 }
@@ -60,8 +136,14 @@ where
 {
     /// Sends an item into the queue.
     pub async fn send(&self, item: &T) -> Result<(), Report<Error>> {
-        let encoded = serde_json::to_vec(item).context(Error::Serialize)?;
-        self.internal.push(encoded.to_vec()).await;
+        let data = serde_json::to_vec(item).context(Error::Serialize)?;
+        self.internal
+            .items
+            .push(Envelope {
+                data,
+                deliveries: 0,
+            })
+            .await;
         Ok(())
     }
 }
@@ -71,9 +153,114 @@ where
     T: DeserializeOwned,
 {
     /// Retrieves an element from the queue.
-    pub async fn recv(&self) -> Result<T, Report<Error>> {
-        let data = self.internal.pop().await;
-        serde_json::from_slice(&data).context(Error::Deserialize)
+    ///
+    /// The element is redelivered unless the returned guard is committed.
+    pub async fn recv(&self) -> Result<Guard<'_, T>, Report<Error>> {
+        let envelope = self.internal.pop().await;
+        self.deliver(envelope)
+    }
+
+    /// Retrieves an element from the queue if one is available, without waiting.
+    ///
+    /// The element is redelivered unless the returned guard is committed.
+    pub fn try_recv(&self) -> Option<Result<Guard<'_, T>, Report<Error>>> {
+        self.internal
+            .try_pop()
+            .map(|envelope| self.deliver(envelope))
+    }
+
+    /// Items which can't be deserialized are never going to be handled, so they aren't redelivered.
+    fn deliver(&self, mut envelope: Envelope) -> Result<Guard<'_, T>, Report<Error>> {
+        let item = serde_json::from_slice(&envelope.data).context(Error::Deserialize)?;
+        envelope.deliveries += 1;
+        Ok(Guard {
+            item,
+            receipt: Receipt {
+                envelope: Some(envelope),
+                queue: &self.internal,
+                notify: None,
+                item: std::any::type_name::<T>(),
+            },
+        })
+    }
+}
+
+/// An item received from a [`Queue`], which is redelivered unless it's committed.
+pub struct Guard<'a, T> {
+    item: T,
+    receipt: Receipt<'a>,
+}
+
+impl<'a, T> Guard<'a, T> {
+    /// How many times the item has been delivered, including this delivery.
+    pub fn deliveries(&self) -> u32 {
+        self.receipt
+            .envelope
+            .as_ref()
+            .map(|envelope| envelope.deliveries)
+            .unwrap_or_default()
+    }
+
+    /// Whether the item is discarded rather than redelivered if this delivery isn't committed.
+    pub fn is_last_delivery(&self) -> bool {
+        self.deliveries() >= MAX_DELIVERIES
+    }
+
+    /// Mark the item as handled, so that it isn't redelivered.
+    pub fn commit(self) -> T {
+        let Self { item, mut receipt } = self;
+        receipt.envelope = None;
+        item
+    }
+
+    /// Notify the waiters on `notify` if the item is redelivered.
+    pub(crate) fn notifying(mut self, notify: &'a Notify) -> Self {
+        self.receipt.notify = Some(notify);
+        self
+    }
+}
+
+impl<T> Deref for Guard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.item
+    }
+}
+
+impl<T: Debug> Debug for Guard<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Guard")
+            .field("item", &self.item)
+            .field("deliveries", &self.deliveries())
+            .finish()
+    }
+}
+
+/// Redelivers the item when dropped, unless it was committed.
+struct Receipt<'a> {
+    envelope: Option<Envelope>,
+    queue: &'a Internal,
+    notify: Option<&'a Notify>,
+    item: &'static str,
+}
+
+impl Drop for Receipt<'_> {
+    fn drop(&mut self) {
+        let Some(envelope) = self.envelope.take() else {
+            return;
+        };
+
+        let item = self.item;
+        if envelope.deliveries >= MAX_DELIVERIES {
+            warn!("Discarding {item} after {} deliveries", envelope.deliveries);
+            return;
+        }
+
+        self.queue.redeliver(envelope);
+        if let Some(notify) = self.notify {
+            notify.notify_waiters();
+        }
     }
 }
 
@@ -0,0 +1,21 @@
+//! Guardrails on the size of what Broker scans and uploads.
+//!
+//! Repositories sometimes accidentally contain huge files, like data dumps,
+//! which make them very large to clone and can make the results of analyzing them very large too.
+//! Rather than letting one such repository fill the disk or exhaust memory on every poll,
+//! scans which exceed a limit are skipped until the reference changes.
+
+use bytesize::ByteSize;
+use derive_new::new;
+use getset::CopyGetters;
+
+/// Validated config values for the size guardrails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, CopyGetters, new)]
+#[getset(get_copy = "pub")]
+pub struct Config {
+    /// References are skipped if their clone is larger than this, if set.
+    max_clone_size: Option<ByteSize>,
+
+    /// Scans aren't uploaded if their results are larger than this, if set.
+    max_upload_size: Option<ByteSize>,
+}
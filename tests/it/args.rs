@@ -11,7 +11,12 @@ use crate::assert_error_stack_snapshot;
 use test_strategy::proptest;
 
 pub fn raw_base_args(config: &str, db: &str) -> RawRunArgs {
-    RawRunArgs::new(Some(String::from(config)), Some(String::from(db)), None)
+    RawRunArgs::new(
+        Some(String::from(config)),
+        Some(String::from(db)),
+        None,
+        None,
+    )
 }
 
 #[tokio::test]
@@ -35,16 +40,44 @@ async fn validates_args() {
 
 #[tokio::test]
 async fn validates_init_args() {
-    let base = RawInitArgs::new(Some(PathBuf::from("some/path")));
+    let base = RawInitArgs::new(Some(PathBuf::from("some/path")), None, false);
     let ctx = base.validate().await.expect("valid args");
     assert_eq!(ctx.data_root(), &PathBuf::from("some/path"));
 }
 
+#[tokio::test]
+async fn validates_profile_args() {
+    let base = RawInitArgs::new(
+        Some(PathBuf::from("some/path")),
+        Some(String::from("staging")),
+        false,
+    );
+    let ctx = base.validate().await.expect("valid args");
+    assert_eq!(ctx.profile().as_deref(), Some("staging"));
+    assert_eq!(
+        ctx.data_root(),
+        &PathBuf::from("some/path").join("profiles").join("staging")
+    );
+
+    let base = RawInitArgs::new(
+        Some(PathBuf::from("some/path")),
+        Some(String::from("../staging")),
+        false,
+    );
+    let err = base.validate().await.expect_err("must reject profile");
+    assert_eq!(err.current_context().to_string(), "validate profile name");
+}
+
 #[tokio::test]
 async fn infers_db_path() {
     std::env::set_var(broker::config::DISABLE_FILE_DISCOVERY_VAR, "1");
 
-    let base = RawRunArgs::new(Some(String::from("testdata/config/basic.yml")), None, None);
+    let base = RawRunArgs::new(
+        Some(String::from("testdata/config/basic.yml")),
+        None,
+        None,
+        None,
+    );
     let validated = base.validate().await;
     let validated = validated.expect("args must have passed validation");
     assert_eq!(
@@ -63,7 +96,7 @@ async fn infers_db_path() {
 async fn infers_db_path_failing_config() {
     std::env::set_var(broker::config::DISABLE_FILE_DISCOVERY_VAR, "1");
 
-    let base = RawRunArgs::new(Some(String::from("")), None, None);
+    let base = RawRunArgs::new(Some(String::from("")), None, None, None);
     let validated = base.clone().validate().await;
     let err = validated.expect_err("must have errored");
     assert_error_stack_snapshot!(&base, err);
@@ -0,0 +1,43 @@
+use std::time::Instant;
+
+use broker::{
+    audit::{Action, Event, Log},
+    debug::Root,
+};
+
+#[tokio::test]
+async fn records_actions_as_jsonl() {
+    let tmp = tempfile::tempdir().expect("must create tempdir");
+    let log = Log::new(&Root::new(tmp.path().to_path_buf()));
+
+    let success: Result<(), String> = Ok(());
+    let event = Event::new(Action::Upload, "https://github.com/fossas/broker.git")
+        .reference("main")
+        .scan_id("1234")
+        .locator("custom+1/broker$abcd");
+    log.record(event, Instant::now(), &success).await;
+
+    let failure: Result<(), String> = Err(String::from("clone failed"));
+    let event = Event::new(Action::Clone, "https://github.com/fossas/broker.git");
+    log.record(event, Instant::now(), &failure).await;
+
+    let content = std::fs::read_to_string(log.path()).expect("must read audit log");
+    let records = content
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).expect("must parse record"))
+        .collect::<Vec<_>>();
+    assert_eq!(records.len(), 2);
+
+    assert_eq!(records[0]["action"], "upload");
+    assert_eq!(records[0]["outcome"], "success");
+    assert_eq!(records[0]["locator"], "custom+1/broker$abcd");
+    assert_eq!(records[0]["reference"], "main");
+    assert!(records[0]["timestamp"].is_string());
+    assert!(records[0]["duration_ms"].is_number());
+    assert!(records[0].get("error").is_none());
+
+    assert_eq!(records[1]["action"], "clone");
+    assert_eq!(records[1]["outcome"], "failure");
+    assert_eq!(records[1]["error"], "clone failed");
+    assert!(records[1].get("reference").is_none());
+}
@@ -1 +1,2 @@
+mod memory;
 mod sqlite;
@@ -0,0 +1,426 @@
+//! The in-memory database mirrors the sqlite implementation,
+//! so these tests cover the same behavior as a subset of the sqlite tests.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use semver::Version;
+
+use broker::{
+    cmd,
+    db::{
+        self, memory, Connectivity, Coordinate, Database, JobStage, Namespace, PauseScope,
+        PendingUpload, ProjectMapping, QueuedJob, ScanRecord,
+    },
+    doc::crate_version,
+};
+
+use crate::load_config;
+
+fn coordinate(repository: &str, reference: &str) -> Coordinate {
+    Coordinate::new(
+        Namespace::Git,
+        repository.to_string(),
+        reference.to_string(),
+    )
+}
+
+#[tokio::test]
+async fn claims_current_version() {
+    let db = memory::Database::new();
+    db.claim_broker_version()
+        .await
+        .expect("must claim current version");
+
+    let version = db
+        .broker_version()
+        .await
+        .expect("must get version")
+        .expect("must have a version set");
+    assert_eq!(&version, crate_version());
+}
+
+#[tokio::test]
+async fn claim_older_version_fails() {
+    let db = memory::Database::with_broker_version(Version::new(999, 0, 0));
+    let err = db
+        .claim_broker_version()
+        .await
+        .expect_err("must fail to claim version");
+    assert!(matches!(err.current_context(), db::Error::BrokerOutdated));
+}
+
+#[tokio::test]
+async fn roundtrip_state() {
+    let db = memory::Database::new();
+    let coordinate = coordinate("some repo", "some reference");
+
+    let state = db.state(&coordinate).await.expect("must get state");
+    assert!(state.is_none(), "db state was unset, so must be none");
+
+    db.set_state(&coordinate, b"some state", &true)
+        .await
+        .expect("must set state");
+    let state = db.state(&coordinate).await.expect("must get state");
+    assert_eq!(state, Some(b"some state".to_vec()));
+
+    // Clones share the same storage.
+    let clone = db.clone();
+    clone
+        .delete_states("some repo", true)
+        .await
+        .expect("must delete states");
+    let state = db.state(&coordinate).await.expect("must get state");
+    assert!(state.is_none(), "db state was removed");
+}
+
+#[tokio::test]
+async fn roundtrip_pending_upload() {
+    let db = memory::Database::new();
+    let coordinate = coordinate("some repo", "some reference");
+    let failed_at = UNIX_EPOCH + Duration::from_secs(1_000);
+    let upload = PendingUpload::new(
+        String::from("some scan"),
+        coordinate.clone(),
+        1,
+        failed_at,
+        failed_at + Duration::from_secs(60),
+    );
+    db.set_pending_upload(&upload)
+        .await
+        .expect("must set pending upload");
+
+    let due = db
+        .due_pending_uploads(&Namespace::Git, "some repo", failed_at)
+        .await
+        .expect("must get due uploads");
+    assert!(due.is_empty(), "upload isn't due until its next attempt");
+
+    let due = db
+        .due_pending_uploads(
+            &Namespace::Git,
+            "some repo",
+            failed_at + Duration::from_secs(60),
+        )
+        .await
+        .expect("must get due uploads");
+    assert_eq!(due, vec![upload]);
+
+    db.delete_pending_upload("some scan")
+        .await
+        .expect("must delete pending upload");
+    let found = db
+        .pending_upload(&coordinate, "some scan")
+        .await
+        .expect("must get pending upload");
+    assert!(found.is_none(), "pending upload was deleted");
+}
+
+#[tokio::test]
+async fn rename_repository_moves_states() {
+    let db = memory::Database::new();
+    let legacy = "git@github.com:fossas/broker.git";
+    let canonical = "github.com/fossas/broker";
+
+    for reference in ["moved", "conflicting"] {
+        db.set_state(&coordinate(legacy, reference), b"legacy", &true)
+            .await
+            .expect("must set state");
+    }
+    db.set_state(&coordinate(canonical, "conflicting"), b"canonical", &true)
+        .await
+        .expect("must set state");
+
+    let moved = db
+        .rename_repository(&Namespace::Git, legacy, canonical)
+        .await
+        .expect("must rename repository");
+    assert_eq!(moved, 1);
+
+    let states = db
+        .states_for(&[
+            coordinate(canonical, "moved"),
+            coordinate(canonical, "conflicting"),
+            coordinate(legacy, "moved"),
+            coordinate(legacy, "conflicting"),
+        ])
+        .await
+        .expect("must get states");
+    assert_eq!(
+        states,
+        vec![
+            Some(b"legacy".to_vec()),
+            Some(b"canonical".to_vec()),
+            None,
+            None
+        ]
+    );
+}
+
+#[tokio::test]
+async fn rename_repository_moves_repository_data() {
+    let db = memory::Database::new();
+    let namespace = Namespace::Git;
+    let legacy = "git@github.com:fossas/broker.git";
+    let canonical = "github.com/fossas/broker";
+    let job = |repository: &str| {
+        QueuedJob::new(
+            String::from("some scan"),
+            JobStage::Scan,
+            Coordinate::new(
+                namespace.clone(),
+                repository.to_string(),
+                String::from("main"),
+            ),
+            String::from("main"),
+            b"state".to_vec(),
+            true,
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            None,
+        )
+    };
+
+    let mapping = ProjectMapping::new(String::from("custom+1/broker"), None);
+    db.set_project_mapping(&namespace, legacy, &mapping)
+        .await
+        .expect("must set project mapping");
+    db.set_queued_job(&job(legacy))
+        .await
+        .expect("must set queued job");
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let expires_at = now + Duration::from_secs(60);
+    let leased = db
+        .acquire_scan_lease(job(legacy).coordinate(), "first", now, expires_at)
+        .await
+        .expect("must acquire lease");
+    assert!(leased, "lease is free");
+    db.pause(
+        &PauseScope::Repository(namespace.clone(), legacy.to_string()),
+        now,
+    )
+    .await
+    .expect("must pause");
+    db.record_connectivity(&namespace, legacy, true, now)
+        .await
+        .expect("must record connectivity");
+
+    db.rename_repository(&namespace, legacy, canonical)
+        .await
+        .expect("must rename repository");
+
+    let found = db
+        .project_mapping(&namespace, canonical)
+        .await
+        .expect("must get project mapping");
+    assert_eq!(found, Some(mapping));
+    let found = db
+        .project_mapping(&namespace, legacy)
+        .await
+        .expect("must get project mapping");
+    assert_eq!(found, None);
+
+    let jobs = db.queued_jobs().await.expect("must get queued jobs");
+    assert_eq!(jobs, vec![job(canonical)]);
+
+    let leased = db
+        .acquire_scan_lease(job(canonical).coordinate(), "second", now, expires_at)
+        .await
+        .expect("must acquire lease");
+    assert!(!leased, "lease moved to the new name");
+
+    let paused = db
+        .is_paused(&namespace, canonical)
+        .await
+        .expect("must check pause");
+    assert!(paused, "pause moved to the new name");
+    let paused = db
+        .is_paused(&namespace, legacy)
+        .await
+        .expect("must check pause");
+    assert!(!paused, "pause no longer applies to the old name");
+
+    let found = db
+        .connectivity(&namespace, canonical)
+        .await
+        .expect("must get connectivity");
+    assert_eq!(found, Some(Connectivity::new(true, now)));
+}
+
+#[tokio::test]
+async fn tracks_backlog_progress() {
+    let db = memory::Database::new();
+    let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+
+    db.enqueue_backlog(&Namespace::Git, "some repo", 2, at(10))
+        .await
+        .expect("must enqueue backlog");
+    db.progress_backlog(&Namespace::Git, "some repo", at(20))
+        .await
+        .expect("must progress backlog");
+    let backlog = db
+        .backlog(&Namespace::Git, "some repo")
+        .await
+        .expect("must get backlog")
+        .expect("must have a backlog");
+    assert_eq!((backlog.total(), backlog.completed()), (2, 1));
+
+    // Abandoned backlogs are complete, so the next enqueue starts a new backlog.
+    assert_eq!(db.abandon_backlogs().await.expect("must abandon"), 1);
+    db.enqueue_backlog(&Namespace::Git, "some repo", 3, at(30))
+        .await
+        .expect("must enqueue backlog");
+    let backlog = db
+        .backlog(&Namespace::Git, "some repo")
+        .await
+        .expect("must get backlog")
+        .expect("must have a backlog");
+    assert_eq!((backlog.total(), backlog.completed()), (3, 0));
+    assert_eq!(backlog.started_at(), at(30));
+}
+
+#[tokio::test]
+async fn scan_lease_excludes_other_holders() {
+    let db = memory::Database::new();
+    let coordinate = coordinate("some repo", "some reference");
+    let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+    let acquire = |holder: &'static str, now: u64| {
+        db.acquire_scan_lease(&coordinate, holder, at(now), at(now + 300))
+    };
+
+    assert!(acquire("first", 0).await.expect("must acquire lease"));
+    assert!(acquire("first", 60).await.expect("must renew lease"));
+    assert!(
+        !acquire("second", 120).await.expect("must try lease"),
+        "lease is held by another holder until it expires"
+    );
+    assert!(
+        acquire("second", 360).await.expect("must acquire lease"),
+        "lease expired"
+    );
+
+    // Only the holder can release the lease.
+    db.release_scan_lease(&coordinate, "first")
+        .await
+        .expect("must release lease");
+    assert!(!acquire("first", 400).await.expect("must try lease"));
+    db.release_scan_lease(&coordinate, "second")
+        .await
+        .expect("must release lease");
+    assert!(acquire("first", 400).await.expect("must acquire lease"));
+}
+
+#[tokio::test]
+async fn pauses_integrations() {
+    let db = memory::Database::new();
+    let at = UNIX_EPOCH + Duration::from_secs(10);
+    let paused = |repository: &'static str| db.is_paused(&Namespace::Git, repository);
+    let scope = PauseScope::Repository(Namespace::Git, String::from("some repo"));
+
+    db.pause(&scope, at).await.expect("must pause");
+    assert!(paused("some repo").await.expect("must check pause"));
+    assert!(!paused("other repo").await.expect("must check pause"));
+
+    // Pausing every integration applies to each of them until every integration is resumed.
+    db.pause(&PauseScope::All, at).await.expect("must pause");
+    assert_eq!(db.resume(&scope).await.expect("must resume"), 1);
+    assert!(paused("some repo").await.expect("must check pause"));
+    assert!(paused("other repo").await.expect("must check pause"));
+
+    db.pause(&scope, at).await.expect("must pause");
+    assert_eq!(db.resume(&PauseScope::All).await.expect("must resume"), 2);
+    assert!(!paused("some repo").await.expect("must check pause"));
+}
+
+#[tokio::test]
+async fn migrates_renamed_remotes() {
+    let (_, conf) = load_config!(
+        "testdata/config/basic-aliases.yml",
+        "testdata/database/empty.sqlite"
+    )
+    .await;
+    let db = memory::Database::new();
+    for repository in [
+        "github.com/fossas-legacy/broker",
+        "https://github.com/fossas-renamed/broker.git",
+    ] {
+        db.set_state(&coordinate(repository, "main"), b"state", &true)
+            .await
+            .expect("must set state");
+    }
+
+    // State for aliases is moved automatically.
+    cmd::db::canonicalize_repositories(&db, conf.integrations())
+        .await
+        .expect("must canonicalize repositories");
+    let states = db
+        .states_for(&[
+            coordinate("github.com/fossas/broker", "main"),
+            coordinate("github.com/fossas-legacy/broker", "main"),
+        ])
+        .await
+        .expect("must get states");
+    assert_eq!(states, vec![Some(b"state".to_vec()), None]);
+
+    // Other remotes are moved on request.
+    db.delete_state(&coordinate("github.com/fossas/broker", "main"))
+        .await
+        .expect("must delete state");
+    cmd::db::migrate_remote(
+        &conf,
+        &db,
+        "https://github.com/fossas-renamed/broker.git",
+        "git@github.com:fossas/broker.git",
+    )
+    .await
+    .expect("must migrate remote");
+    let states = db
+        .states_for(&[
+            coordinate("github.com/fossas/broker", "main"),
+            coordinate("https://github.com/fossas-renamed/broker.git", "main"),
+        ])
+        .await
+        .expect("must get states");
+    assert_eq!(states, vec![Some(b"state".to_vec()), None]);
+}
+
+#[tokio::test]
+async fn records_scan_locators() {
+    let db = memory::Database::new();
+
+    let coordinate = Coordinate::new(
+        Namespace::Git,
+        String::from("some repo"),
+        String::from("git:branch:main@abcd"),
+    );
+    let scan = |id: &str| ScanRecord::new(id.to_string(), Duration::ZERO, Duration::ZERO);
+    for id in ["first", "second"] {
+        db.record_scan(&coordinate, &scan(id))
+            .await
+            .expect("must record scan");
+    }
+    db.set_scan_locator("first", "custom+1/some-repo$abcd")
+        .await
+        .expect("must set scan locator");
+
+    let scans = db
+        .scans_since(&Namespace::Git, "some repo", UNIX_EPOCH)
+        .await
+        .expect("must read scans");
+    let recorded = scans
+        .iter()
+        .map(|scan| (scan.record().scan_id().as_str(), scan.locator().as_deref()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        recorded,
+        vec![("second", None), ("first", Some("custom+1/some-repo$abcd"))]
+    );
+    assert!(scans
+        .iter()
+        .all(|scan| scan.revision() == "git:branch:main@abcd"));
+
+    let later = SystemTime::now() + Duration::from_secs(60 * 60);
+    let scans = db
+        .scans_since(&Namespace::Git, "some repo", later)
+        .await
+        .expect("must read scans");
+    assert!(scans.is_empty(), "scans before the period are excluded");
+}
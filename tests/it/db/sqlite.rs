@@ -1,9 +1,14 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use semver::Version;
 use sqlx::{query, Connection};
 use tempfile::tempdir;
 
 use broker::{
-    db::{connect_sqlite, Coordinate, Database},
+    db::{
+        connect_sqlite, Connectivity, Coordinate, Database, JobStage, Namespace, PauseScope,
+        PendingUpload, ProjectMapping, QueuedJob, ScanRecord,
+    },
     doc::{crate_name, crate_version},
 };
 
@@ -17,11 +22,15 @@ macro_rules! temp_db {
     () => {{
         let tmp = tempdir().expect("must create temporary directory");
         let path = tmp.path().join("test.db");
-        let db = connect_sqlite(&path).await.expect("must create db");
+        let db = connect_sqlite(&path, Default::default())
+            .await
+            .expect("must create db");
         (tmp, db, path)
     }};
     ($path:expr) => {{
-        connect_sqlite($path).await.expect("must create db")
+        connect_sqlite($path, Default::default())
+            .await
+            .expect("must create db")
     }};
 }
 
@@ -80,8 +89,8 @@ async fn claims_current_version() {
 
 #[tokio::test]
 async fn claim_older_version_fails() {
-    let version = crate_version();
-    let newer = Version::new(version.major + 1, version.minor, version.patch).to_string();
+    // A fixed version keeps the snapshot stable across releases.
+    let newer = Version::new(999, 0, 0).to_string();
 
     // Need a bare connection since setting an arbitrary version is private.
     let (_tmp, mut db, path) = raw_temp_db!(with_migrations);
@@ -93,12 +102,68 @@ async fn claim_older_version_fails() {
     db.close().await.expect("must close db");
 
     // Now open the actual DB interface at this path and try to claim the current version.
-    let err = connect_sqlite(&path)
+    let err = connect_sqlite(&path, Default::default())
         .await
         .expect_err("must fail to claim version");
     assert_error_stack_snapshot!(&path, err);
 }
 
+#[tokio::test]
+async fn newer_migrations_report_outdated_version() {
+    let newer = Version::new(999, 0, 0).to_string();
+
+    // Simulate a newer Broker which has applied a migration this version doesn't know about.
+    let (_tmp, mut db, path) = raw_temp_db!(with_migrations);
+    let name = crate_name();
+    query!("insert into broker_version values (?, ?)", name, newer)
+        .execute(&mut db)
+        .await
+        .expect("must set initial broker version");
+    sqlx::query("insert into _sqlx_migrations (version, description, success, checksum, execution_time) values (99991231000000, 'from the future', true, x'00', 0)")
+        .execute(&mut db)
+        .await
+        .expect("must record future migration");
+    db.close().await.expect("must close db");
+
+    let err = connect_sqlite(&path, Default::default())
+        .await
+        .expect_err("must fail to connect");
+    let outdated = err.frames().any(|frame| {
+        matches!(
+            frame.downcast_ref::<broker::db::Error>(),
+            Some(broker::db::Error::BrokerOutdated)
+        )
+    });
+    assert!(outdated, "must report outdated version: {err:?}");
+}
+
+#[tokio::test]
+async fn inspects_database() {
+    let (_tmp, db, path) = temp_db!();
+    drop(db);
+
+    let info = broker::db::inspect_sqlite(&path)
+        .await
+        .expect("must inspect db");
+    assert!(
+        info.schema_version().is_some(),
+        "migrations must be applied"
+    );
+    assert_eq!(info.broker_version().as_ref(), Some(crate_version()));
+    assert!(info.size() > 0, "db must have a size");
+}
+
+#[tokio::test]
+async fn inspect_missing_database_fails() {
+    let tmp = tempdir().expect("must create temporary directory");
+    let path = tmp.path().join("missing.db");
+
+    broker::db::inspect_sqlite(&path)
+        .await
+        .expect_err("must fail to inspect missing db");
+    assert!(!path.exists(), "inspecting must not create the db");
+}
+
 #[tokio::test]
 async fn gets_initial_version() {
     let (_tmp, db, _path) = temp_db!();
@@ -272,3 +337,369 @@ async fn remove_all_tag_states() {
     assert_eq!(state_after_delete, state);
     assert!(state2_after_delete.is_none(), "db state2 was removed");
 }
+
+#[tokio::test]
+async fn roundtrip_pending_upload() {
+    let (_tmp, db, _path) = temp_db!();
+
+    let coordinate = Coordinate::new(
+        broker::db::Namespace::Git,
+        String::from("some repo"),
+        String::from("some reference"),
+    );
+    let failed_at = UNIX_EPOCH + Duration::from_secs(1_000);
+    let upload = PendingUpload::new(
+        String::from("some scan"),
+        coordinate.clone(),
+        1,
+        failed_at,
+        failed_at + Duration::from_secs(60),
+    );
+    db.set_pending_upload(&upload)
+        .await
+        .expect("must set pending upload");
+
+    let found = db
+        .pending_upload(&coordinate, "some scan")
+        .await
+        .expect("must get pending upload");
+    assert_eq!(found, Some(upload.clone()));
+
+    let due = db
+        .due_pending_uploads(&broker::db::Namespace::Git, "some repo", failed_at)
+        .await
+        .expect("must get due uploads");
+    assert!(due.is_empty(), "upload isn't due until its next attempt");
+
+    let due = db
+        .due_pending_uploads(
+            &broker::db::Namespace::Git,
+            "some repo",
+            failed_at + Duration::from_secs(60),
+        )
+        .await
+        .expect("must get due uploads");
+    assert_eq!(due, vec![upload]);
+
+    db.delete_pending_upload("some scan")
+        .await
+        .expect("must delete pending upload");
+    let found = db
+        .pending_upload(&coordinate, "some scan")
+        .await
+        .expect("must get pending upload");
+    assert!(found.is_none(), "pending upload was deleted");
+}
+
+#[tokio::test]
+async fn rename_repository_moves_states() {
+    let (_tmp, db, _path) = temp_db!();
+
+    let namespace = broker::db::Namespace::Git;
+    let legacy = "git@github.com:fossas/broker.git";
+    let canonical = "github.com/fossas/broker";
+    let coordinate = |repository: &str, reference: &str| {
+        Coordinate::new(
+            namespace.clone(),
+            repository.to_string(),
+            reference.to_string(),
+        )
+    };
+
+    for reference in ["moved", "conflicting"] {
+        db.set_state(&coordinate(legacy, reference), b"legacy", &true)
+            .await
+            .expect("must set state");
+    }
+    db.set_state(&coordinate(canonical, "conflicting"), b"canonical", &true)
+        .await
+        .expect("must set state");
+
+    let moved = db
+        .rename_repository(&namespace, legacy, canonical)
+        .await
+        .expect("must rename repository");
+    assert_eq!(moved, 1);
+
+    let states = db
+        .states_for(&[
+            coordinate(canonical, "moved"),
+            coordinate(canonical, "conflicting"),
+            coordinate(legacy, "moved"),
+            coordinate(legacy, "conflicting"),
+        ])
+        .await
+        .expect("must get states");
+    assert_eq!(
+        states,
+        vec![
+            Some(b"legacy".to_vec()),
+            Some(b"canonical".to_vec()),
+            None,
+            None
+        ]
+    );
+}
+
+#[tokio::test]
+async fn rename_repository_moves_repository_data() {
+    let (_tmp, db, _path) = temp_db!();
+    let namespace = Namespace::Git;
+    let legacy = "git@github.com:fossas/broker.git";
+    let canonical = "github.com/fossas/broker";
+    let job = |repository: &str| {
+        QueuedJob::new(
+            String::from("some scan"),
+            JobStage::Scan,
+            Coordinate::new(
+                namespace.clone(),
+                repository.to_string(),
+                String::from("main"),
+            ),
+            String::from("main"),
+            b"state".to_vec(),
+            true,
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            None,
+        )
+    };
+
+    let mapping = ProjectMapping::new(String::from("custom+1/broker"), None);
+    db.set_project_mapping(&namespace, legacy, &mapping)
+        .await
+        .expect("must set project mapping");
+    db.set_queued_job(&job(legacy))
+        .await
+        .expect("must set queued job");
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let expires_at = now + Duration::from_secs(60);
+    let leased = db
+        .acquire_scan_lease(job(legacy).coordinate(), "first", now, expires_at)
+        .await
+        .expect("must acquire lease");
+    assert!(leased, "lease is free");
+    db.pause(
+        &PauseScope::Repository(namespace.clone(), legacy.to_string()),
+        now,
+    )
+    .await
+    .expect("must pause");
+    db.record_connectivity(&namespace, legacy, true, now)
+        .await
+        .expect("must record connectivity");
+
+    db.rename_repository(&namespace, legacy, canonical)
+        .await
+        .expect("must rename repository");
+
+    let found = db
+        .project_mapping(&namespace, canonical)
+        .await
+        .expect("must get project mapping");
+    assert_eq!(found, Some(mapping));
+    let found = db
+        .project_mapping(&namespace, legacy)
+        .await
+        .expect("must get project mapping");
+    assert_eq!(found, None);
+
+    let jobs = db.queued_jobs().await.expect("must get queued jobs");
+    assert_eq!(jobs, vec![job(canonical)]);
+
+    let leased = db
+        .acquire_scan_lease(job(canonical).coordinate(), "second", now, expires_at)
+        .await
+        .expect("must acquire lease");
+    assert!(!leased, "lease moved to the new name");
+
+    let paused = db
+        .is_paused(&namespace, canonical)
+        .await
+        .expect("must check pause");
+    assert!(paused, "pause moved to the new name");
+    let paused = db
+        .is_paused(&namespace, legacy)
+        .await
+        .expect("must check pause");
+    assert!(!paused, "pause no longer applies to the old name");
+
+    let found = db
+        .connectivity(&namespace, canonical)
+        .await
+        .expect("must get connectivity");
+    assert_eq!(found, Some(Connectivity::new(true, now)));
+}
+
+#[tokio::test]
+async fn roundtrip_project_mapping() {
+    let (_tmp, db, _path) = temp_db!();
+
+    let namespace = broker::db::Namespace::Git;
+    let repository = "github.com/fossas/broker";
+    let found = db
+        .project_mapping(&namespace, repository)
+        .await
+        .expect("must get project mapping");
+    assert!(
+        found.is_none(),
+        "project mapping was unset, so must be none"
+    );
+
+    for mapping in [
+        ProjectMapping::new(String::from(repository), None),
+        ProjectMapping::new(
+            String::from("git@github.com:fossas/broker.git"),
+            Some(String::from("Broker")),
+        ),
+    ] {
+        db.set_project_mapping(&namespace, repository, &mapping)
+            .await
+            .expect("must set project mapping");
+        let found = db
+            .project_mapping(&namespace, repository)
+            .await
+            .expect("must get project mapping");
+        assert_eq!(found, Some(mapping));
+    }
+}
+
+#[tokio::test]
+async fn scan_lease_excludes_other_holders() {
+    let (_tmp, db, _path) = temp_db!();
+
+    let coordinate = Coordinate::new(
+        broker::db::Namespace::Git,
+        String::from("some repo"),
+        String::from("some reference"),
+    );
+    let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+    let acquire = |holder: &'static str, now: u64| {
+        db.acquire_scan_lease(&coordinate, holder, at(now), at(now + 300))
+    };
+
+    assert!(acquire("first", 0).await.expect("must acquire lease"));
+    assert!(acquire("first", 60).await.expect("must renew lease"));
+    assert!(
+        !acquire("second", 120).await.expect("must try lease"),
+        "lease is held by another holder until it expires"
+    );
+    assert!(
+        acquire("second", 360).await.expect("must acquire lease"),
+        "lease expired"
+    );
+
+    // Only the holder can release the lease.
+    db.release_scan_lease(&coordinate, "first")
+        .await
+        .expect("must release lease");
+    assert!(!acquire("first", 400).await.expect("must try lease"));
+    db.release_scan_lease(&coordinate, "second")
+        .await
+        .expect("must release lease");
+    assert!(acquire("first", 400).await.expect("must acquire lease"));
+}
+
+#[tokio::test]
+async fn pauses_integrations() {
+    let (_tmp, db, _path) = temp_db!();
+
+    let at = UNIX_EPOCH + Duration::from_secs(10);
+    let paused = |repository: &'static str| db.is_paused(&Namespace::Git, repository);
+    let scope = PauseScope::Repository(Namespace::Git, String::from("some repo"));
+
+    db.pause(&scope, at).await.expect("must pause");
+    assert!(paused("some repo").await.expect("must check pause"));
+    assert!(!paused("other repo").await.expect("must check pause"));
+
+    // Pausing every integration applies to each of them until every integration is resumed.
+    db.pause(&PauseScope::All, at).await.expect("must pause");
+    assert_eq!(db.resume(&scope).await.expect("must resume"), 1);
+    assert!(paused("some repo").await.expect("must check pause"));
+    assert!(paused("other repo").await.expect("must check pause"));
+
+    db.pause(&scope, at).await.expect("must pause");
+    assert_eq!(db.resume(&PauseScope::All).await.expect("must resume"), 2);
+    assert!(!paused("some repo").await.expect("must check pause"));
+}
+
+#[tokio::test]
+async fn records_scan_locators() {
+    let (_tmp, db, _path) = temp_db!();
+
+    let coordinate = Coordinate::new(
+        Namespace::Git,
+        String::from("some repo"),
+        String::from("git:branch:main@abcd"),
+    );
+    let scan = |id: &str| ScanRecord::new(id.to_string(), Duration::ZERO, Duration::ZERO);
+    for id in ["first", "second"] {
+        db.record_scan(&coordinate, &scan(id))
+            .await
+            .expect("must record scan");
+    }
+    db.set_scan_locator("first", "custom+1/some-repo$abcd")
+        .await
+        .expect("must set scan locator");
+
+    let scans = db
+        .scans_since(&Namespace::Git, "some repo", UNIX_EPOCH)
+        .await
+        .expect("must read scans");
+    let recorded = scans
+        .iter()
+        .map(|scan| (scan.record().scan_id().as_str(), scan.locator().as_deref()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        recorded,
+        vec![("second", None), ("first", Some("custom+1/some-repo$abcd"))]
+    );
+    assert!(scans
+        .iter()
+        .all(|scan| scan.revision() == "git:branch:main@abcd"));
+
+    let later = SystemTime::now() + Duration::from_secs(60 * 60);
+    let scans = db
+        .scans_since(&Namespace::Git, "some repo", later)
+        .await
+        .expect("must read scans");
+    assert!(scans.is_empty(), "scans before the period are excluded");
+}
+
+#[tokio::test]
+async fn retries_while_another_connection_writes() {
+    let tmp = tempdir().expect("must create temporary directory");
+    let path = tmp.path().join("test.db");
+    let config = broker::db::Config::new(Duration::from_millis(10), Duration::ZERO);
+    let db = connect_sqlite(&path, config).await.expect("must create db");
+
+    // Another connection holds the write lock for much longer than the busy timeout.
+    let options = sqlx::sqlite::SqliteConnectOptions::new().filename(&path);
+    let mut other = sqlx::SqliteConnection::connect_with(&options)
+        .await
+        .expect("must connect to db");
+    sqlx::query("begin immediate")
+        .execute(&mut other)
+        .await
+        .expect("must lock db");
+    let release = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        sqlx::query("commit")
+            .execute(&mut other)
+            .await
+            .expect("must unlock db");
+    });
+
+    let coordinate = Coordinate::new(
+        broker::db::Namespace::Git,
+        String::from("github.com/fossas/broker"),
+        String::from("main"),
+    );
+    db.set_state(&coordinate, b"state", &true)
+        .await
+        .expect("must set state once the lock is released");
+    release.await.expect("must release lock");
+
+    db.checkpoint().await.expect("must checkpoint");
+    let state = db.state(&coordinate).await.expect("must get state");
+    assert_eq!(state, Some(b"state".to_vec()));
+}
@@ -1,6 +1,12 @@
 //! Tests for debugging functionality.
 
-use broker::debug::ArtifactRetentionCount;
+use std::time::{Duration, SystemTime};
+
+use broker::debug::{
+    retention::{enforce_cli_bundles, CliBundleRetention},
+    ArtifactRetentionCount, Root,
+};
+use bytesize::ByteSize;
 use proptest::{prop_assert, prop_assert_eq};
 use test_strategy::proptest;
 
@@ -31,3 +37,41 @@ fn validate_artifact_retention_count_min() {
 fn validate_artifact_retention_count_default() {
     assert_eq!(ArtifactRetentionCount::default(), 7);
 }
+
+#[test]
+fn enforce_cli_bundle_retention_max_size() {
+    let tmp = tempfile::tempdir().expect("must create tempdir");
+    let root = Root::new(tmp.path().to_path_buf());
+    for scan_id in ["a", "b", "c"] {
+        std::fs::write(root.debug_bundle(scan_id), [0u8; 10]).expect("must write bundle");
+    }
+    std::fs::write(tmp.path().join("unrelated.txt"), [0u8; 100]).expect("must write file");
+
+    let retention =
+        CliBundleRetention::new(ArtifactRetentionCount::default(), Some(ByteSize::b(25)));
+    let summary = enforce_cli_bundles(&root, retention, SystemTime::now()).expect("must enforce");
+    assert_eq!(summary.removed(), 1);
+    assert_eq!(summary.reclaimed(), ByteSize::b(10));
+
+    let remaining = std::fs::read_dir(tmp.path()).expect("must list").count();
+    assert_eq!(
+        remaining, 3,
+        "two bundles and the unrelated file must remain"
+    );
+}
+
+#[test]
+fn enforce_cli_bundle_retention_max_age() {
+    let tmp = tempfile::tempdir().expect("must create tempdir");
+    let root = Root::new(tmp.path().to_path_buf());
+    for scan_id in ["a", "b"] {
+        std::fs::write(root.debug_bundle(scan_id), [0u8; 10]).expect("must write bundle");
+    }
+
+    let retention = CliBundleRetention::new(ArtifactRetentionCount::default(), None);
+    let later = SystemTime::now() + Duration::from_secs(8 * 24 * 60 * 60);
+    let summary = enforce_cli_bundles(&root, retention, later).expect("must enforce");
+    assert_eq!(summary.removed(), 2);
+    assert!(!root.debug_bundle("a").exists());
+    assert!(!root.debug_bundle("b").exists());
+}
@@ -0,0 +1,104 @@
+use broker::facade::{Broker, CancellationToken, Options, Selection};
+use once_cell::sync::Lazy;
+use tempfile::TempDir;
+
+use crate::guard_integration_test;
+
+/// The data root for every instance loaded in these tests.
+///
+/// Loading an instance installs the secrets directory under its data root for the whole process,
+/// and every test in this binary shares a process, so instances must share a data root too.
+static DATA_ROOT: Lazy<TempDir> = Lazy::new(|| tempfile::tempdir().expect("must create tempdir"));
+
+#[tokio::test]
+async fn reports_status_of_configured_integrations() {
+    let tmp = tempfile::tempdir().expect("must create tempdir");
+    let options = Options::builder()
+        .config_path("testdata/config/basic-groups.yml")
+        .database_path(tmp.path().join("db.sqlite"))
+        .data_root(DATA_ROOT.path())
+        .build();
+
+    let broker = Broker::load(options).await.expect("must load");
+    let status = broker.status().await.expect("must report status");
+
+    assert!(!status.integrations().is_empty());
+    for integration in status.integrations() {
+        assert!(integration.recent_scans().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn cancellation_token_is_shared_by_clones() {
+    let token = CancellationToken::new();
+    let handle = token.clone();
+    assert!(!token.is_cancelled());
+
+    tokio::spawn(async move { handle.cancel() });
+    token.cancelled().await;
+    assert!(token.is_cancelled());
+}
+
+#[tokio::test]
+async fn seals_cached_source_units_with_configured_key() {
+    guard_integration_test!();
+
+    let tmp = tempfile::tempdir().expect("must create tempdir");
+    let fossa_key = std::env::var("FOSSA_API_KEY").expect("test");
+    std::env::set_var(
+        "BROKER_TEST_FACADE_ENCRYPTION_KEY",
+        "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=",
+    );
+
+    let debug = tmp.path().join("debug");
+    let content = indoc::formatdoc! {r#"
+    fossa_endpoint: https://app.fossa.com
+    fossa_integration_key: {fossa_key}
+    version: 1
+    debugging:
+      location: {debug:?}
+      retention:
+        days: 1
+    encryption:
+      key:
+        env: BROKER_TEST_FACADE_ENCRYPTION_KEY
+    integrations:
+      - type: git
+        poll_interval: 1h
+        remote: https://github.com/fossas/broker-test-example.git
+        import_branches: true
+        watched_branches:
+          - main
+        auth:
+          type: none
+          transport: http
+    "#};
+    let config_path = tmp.path().join("config.yml");
+    std::fs::write(&config_path, content).expect("must write config file");
+
+    let options = Options::builder()
+        .config_path(config_path)
+        .database_path(tmp.path().join("db.sqlite"))
+        .data_root(DATA_ROOT.path())
+        .build();
+    let broker = Broker::load(options).await.expect("must load");
+    broker.scan(Selection::default()).await.expect("must scan");
+
+    let cache = DATA_ROOT
+        .path()
+        .join("broker-cmd-run")
+        .join("analysis-cache");
+    let entries = std::fs::read_dir(&cache)
+        .expect("must cache source units")
+        .map(|entry| entry.expect("must read entry").path())
+        .collect::<Vec<_>>();
+    assert!(!entries.is_empty(), "must cache source units");
+    for entry in entries {
+        let content = std::fs::read(&entry).expect("must read cached source units");
+        assert!(
+            broker::encryption::is_sealed(&content),
+            "'{}' must be encrypted",
+            entry.display()
+        );
+    }
+}
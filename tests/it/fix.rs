@@ -1,4 +1,4 @@
-use std::sync::RwLock;
+use std::{path::Path, sync::RwLock};
 
 use crate::{
     guard_integration_test,
@@ -6,8 +6,12 @@ use crate::{
     load_config, set_snapshot_vars, temp_config,
 };
 use broker::{
-    cmd::fix::Logger,
-    debug::{bundler::TarGz, Bundle, BundleExport},
+    cmd::fix::{Logger, ScanCheck},
+    debug::{
+        bundler::TarGz,
+        snapshot::{self, Snapshot},
+        Bundle, BundleExport, BundleUpload,
+    },
 };
 use insta::assert_snapshot;
 
@@ -67,9 +71,17 @@ async fn with_successful_http_no_auth_integration() {
     .await;
 
     let logger = TestLogger::new();
-    broker::cmd::fix::main(&ctx, &conf, &logger, BundleExport::Disable)
-        .await
-        .expect("should run fix");
+    broker::cmd::fix::main(
+        &ctx,
+        &conf,
+        Path::new("testdata/database/empty.sqlite"),
+        &logger,
+        BundleExport::Disable,
+        BundleUpload::Disable,
+        ScanCheck::Representative,
+    )
+    .await
+    .expect("should run fix");
 
     insta::with_settings!(
         { filters => fix_output_filters() },
@@ -92,9 +104,17 @@ async fn with_failing_http_no_auth_integration_scan_unix() {
     .await;
 
     let logger = TestLogger::new();
-    broker::cmd::fix::main(&ctx, &conf, &logger, BundleExport::Disable)
-        .await
-        .expect("should run fix");
+    broker::cmd::fix::main(
+        &ctx,
+        &conf,
+        Path::new("testdata/database/empty.sqlite"),
+        &logger,
+        BundleExport::Disable,
+        BundleUpload::Disable,
+        ScanCheck::Representative,
+    )
+    .await
+    .expect("should run fix");
 
     insta::with_settings!(
         { filters => fix_output_filters() },
@@ -117,9 +137,17 @@ async fn with_failing_http_no_auth_integration_scan_windows() {
     .await;
 
     let logger = TestLogger::new();
-    broker::cmd::fix::main(&ctx, &conf, &logger, BundleExport::Disable)
-        .await
-        .expect("should run fix");
+    broker::cmd::fix::main(
+        &ctx,
+        &conf,
+        Path::new("testdata/database/empty.sqlite"),
+        &logger,
+        BundleExport::Disable,
+        BundleUpload::Disable,
+        ScanCheck::Representative,
+    )
+    .await
+    .expect("should run fix");
 
     insta::with_settings!(
         { filters => fix_output_filters() },
@@ -141,9 +169,17 @@ async fn with_failing_http_no_auth_download_cli() {
     .await;
 
     let logger = TestLogger::new();
-    broker::cmd::fix::main(&ctx, &conf, &logger, BundleExport::Disable)
-        .await
-        .expect("should run fix");
+    broker::cmd::fix::main(
+        &ctx,
+        &conf,
+        Path::new("testdata/database/empty.sqlite"),
+        &logger,
+        BundleExport::Disable,
+        BundleUpload::Disable,
+        ScanCheck::Representative,
+    )
+    .await
+    .expect("should run fix");
 
     insta::with_settings!(
         { filters => fix_output_filters() },
@@ -164,9 +200,17 @@ async fn with_failing_http_basic_auth_integration() {
     )
     .await;
     let logger = TestLogger::new();
-    broker::cmd::fix::main(&ctx, &conf, &logger, BundleExport::Disable)
-        .await
-        .expect("should run fix");
+    broker::cmd::fix::main(
+        &ctx,
+        &conf,
+        Path::new("testdata/database/empty.sqlite"),
+        &logger,
+        BundleExport::Disable,
+        BundleUpload::Disable,
+        ScanCheck::Representative,
+    )
+    .await
+    .expect("should run fix");
 
     insta::with_settings!(
         { filters => fix_output_filters() },
@@ -188,9 +232,17 @@ async fn with_failing_http_no_auth_integration() {
     .await;
 
     let logger = TestLogger::new();
-    broker::cmd::fix::main(&ctx, &conf, &logger, BundleExport::Disable)
-        .await
-        .expect("should run fix");
+    broker::cmd::fix::main(
+        &ctx,
+        &conf,
+        Path::new("testdata/database/empty.sqlite"),
+        &logger,
+        BundleExport::Disable,
+        BundleUpload::Disable,
+        ScanCheck::Representative,
+    )
+    .await
+    .expect("should run fix");
 
     insta::with_settings!(
         { filters => fix_output_filters() },
@@ -210,9 +262,20 @@ async fn generates_debug_bundle() {
 
     let bundle_target = tmp.path().join("fossa.broker.debug.tar.gz");
     let bundler = TarGz::new().expect("must create bundler");
-    let bundle =
-        Bundle::collect(conf.debug(), bundler, bundle_target).expect("must collect debug bundle");
+    let bundle = Bundle::collect(
+        conf.debug(),
+        conf.encryption().as_ref(),
+        bundler,
+        bundle_target,
+        &Snapshot::default(),
+    )
+    .expect("must collect debug bundle");
 
     let unpacked = expand_debug_bundle(bundle.location());
     assert_equal_contents("testdata/fossa.broker.debug/bundled", unpacked.path());
+
+    let snapshot = std::fs::read(unpacked.path().join(snapshot::FILE_NAME))
+        .expect("must bundle runtime snapshot");
+    let snapshot = serde_json::from_slice::<Snapshot>(&snapshot).expect("must parse snapshot");
+    assert_eq!(snapshot, Snapshot::default());
 }
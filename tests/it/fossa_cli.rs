@@ -1,6 +1,9 @@
 use std::path::PathBuf;
 
-use broker::fossa_cli::{self, DesiredVersion, Location};
+use broker::{
+    api::{http::client::Purpose, remote::CliOptions},
+    fossa_cli::{self, DesiredVersion, Location},
+};
 use tracing_test::traced_test;
 use uuid::Uuid;
 
@@ -13,14 +16,22 @@ async fn downloads_latest_cli() {
     let (_tmp, config, ctx) = temp_config!(load);
 
     println!("Downloading CLI");
-    let location = fossa_cli::download(&ctx, config.debug().location(), DesiredVersion::Latest)
-        .await
-        .expect("must download CLI");
+    let location = fossa_cli::download(
+        &ctx,
+        config.fossa_cli(),
+        config.debug().location(),
+        DesiredVersion::Latest,
+    )
+    .await
+    .expect("must download CLI");
 
     println!("Checking versions");
-    let (downloaded, latest) =
-        tokio::try_join!(location.version(), fossa_cli::latest_release_version())
-            .expect("must fetch version information");
+    let client = ctx.http().get(Purpose::FossaCli);
+    let (downloaded, latest) = tokio::try_join!(
+        location.version(),
+        fossa_cli::latest_release_version(client)
+    )
+    .expect("must fetch version information");
 
     assert_eq!(
         downloaded.to_string(),
@@ -29,6 +40,45 @@ async fn downloads_latest_cli() {
     );
 }
 
+#[tokio::test]
+async fn caches_downloaded_cli() {
+    guard_integration_test!();
+
+    let (_tmp, config, ctx) = temp_config!(load);
+    let cache = ctx.data_root().join("cache").join("fossa-cli");
+
+    println!("Downloading CLI");
+    fossa_cli::download(
+        &ctx,
+        config.fossa_cli(),
+        config.debug().location(),
+        DesiredVersion::Latest,
+    )
+    .await
+    .expect("must download CLI");
+
+    let cached = std::fs::read_dir(&cache)
+        .expect("must have created cache")
+        .filter_map(Result::ok)
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(cached.len(), 1, "must cache only the archive: {cached:?}");
+    assert!(
+        cached[0].ends_with(".zip"),
+        "must cache the archive: {cached:?}"
+    );
+
+    println!("Downloading CLI again from cache");
+    fossa_cli::download(
+        &ctx,
+        config.fossa_cli(),
+        config.debug().location(),
+        DesiredVersion::Latest,
+    )
+    .await
+    .expect("must download CLI from cache");
+}
+
 #[tokio::test]
 #[traced_test]
 async fn analyze_runs() {
@@ -41,14 +91,19 @@ async fn analyze_runs() {
         .join("fossa-analyze");
 
     println!("Downloading CLI");
-    let location = fossa_cli::download(&ctx, config.debug().location(), DesiredVersion::Latest)
-        .await
-        .expect("must download CLI");
+    let location = fossa_cli::download(
+        &ctx,
+        config.fossa_cli(),
+        config.debug().location(),
+        DesiredVersion::Latest,
+    )
+    .await
+    .expect("must download CLI");
 
     // Scan our vendored node project to speed up tests.
     println!("Analyzing '{}' with scan id '{scan_id}'", project.display());
     let source_units = location
-        .analyze(&scan_id, &project)
+        .analyze(&scan_id, &project, &[], &CliOptions::default())
         .await
         .expect("must analyze");
 
@@ -81,14 +136,19 @@ async fn analyze_fails() {
         .join("fossa-analyze-does-not-exist");
 
     println!("Downloading CLI");
-    let location = fossa_cli::download(&ctx, config.debug().location(), DesiredVersion::Latest)
-        .await
-        .expect("must download CLI");
+    let location = fossa_cli::download(
+        &ctx,
+        config.fossa_cli(),
+        config.debug().location(),
+        DesiredVersion::Latest,
+    )
+    .await
+    .expect("must download CLI");
 
     // Scan our path that does not exist.
     println!("Analyzing '{}' with scan id '{scan_id}'", project.display());
     let err = location
-        .analyze(&scan_id, &project)
+        .analyze(&scan_id, &project, &[], &CliOptions::default())
         .await
         .expect_err("must fail to analyze");
 
@@ -114,14 +174,19 @@ async fn analyze_fails_dynamic() {
     let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
 
     println!("Downloading CLI");
-    let location = fossa_cli::download(&ctx, config.debug().location(), DesiredVersion::Latest)
-        .await
-        .expect("must download CLI");
+    let location = fossa_cli::download(
+        &ctx,
+        config.fossa_cli(),
+        config.debug().location(),
+        DesiredVersion::Latest,
+    )
+    .await
+    .expect("must download CLI");
 
     // Scan our project.
     println!("Analyzing '{}' with scan id '{scan_id}'", project.display());
     let analysis_results = location
-        .analyze(&scan_id, &project)
+        .analyze(&scan_id, &project, &[], &CliOptions::default())
         .await
         .expect("Must successfully run");
 
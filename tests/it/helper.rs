@@ -14,6 +14,7 @@ use tempfile::TempDir;
 
 pub mod duration;
 pub mod gen;
+pub mod git_server;
 
 /// Create a context in a temporary directory.
 #[macro_export]
@@ -21,7 +22,8 @@ macro_rules! temp_ctx {
     () => {{
         let tmp = tempfile::tempdir().expect("must create tempdir");
         let root = tmp.path().to_path_buf();
-        (tmp, broker::AppContext::new(root))
+        let ctx = broker::AppContext::new(root).expect("must create context");
+        (tmp, ctx)
     }};
 }
 
@@ -69,6 +71,7 @@ macro_rules! temp_config {
             Some(config_file_path.to_string_lossy().to_string()),
             None, // Infer the DB path to be a sibling of the config file.
             Some(tmp.path().to_path_buf()),
+            None,
         );
 
         let args = raw_args.validate().await.expect("must have validated");
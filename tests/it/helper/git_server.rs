@@ -0,0 +1,342 @@
+//! An in-process git server speaking the smart HTTP protocol, serving fixture repositories.
+//!
+//! Tests of the git transports, of redaction, and of the scan pipeline use this server
+//! so that they don't depend on the network or on the state of repositories on github.com.
+//!
+//! Requests are answered by `git http-backend`, the same CGI program that git hosts use,
+//! so the `git` executable must be in the `PATH` to use it.
+//! The server only supports fetching; pushing to fixture repositories isn't supported.
+//!
+//! ```ignore
+//! let server = GitServer::start(Auth::None).await;
+//! let repo = server.repository("example");
+//! let commit = repo.commit("initial commit");
+//! let transport = Transport::new_http(Remote::new(server.url("example")), None);
+//! ```
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use base64::{engine::general_purpose, Engine};
+use tempfile::TempDir;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    process::Command,
+    task::JoinHandle,
+};
+
+/// The authentication the server requires for every request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Auth {
+    /// Requests are accepted without authentication.
+    None,
+
+    /// Requests must authenticate with HTTP Basic, using this username and password.
+    Basic {
+        /// The required username.
+        username: String,
+
+        /// The required password.
+        password: String,
+    },
+
+    /// Requests must provide this token in an `Authorization: Bearer` header.
+    Bearer(String),
+}
+
+impl Auth {
+    /// Whether the value of the `Authorization` header of a request satisfies the auth.
+    fn accepts(&self, header: Option<&str>) -> bool {
+        match self {
+            Auth::None => true,
+            Auth::Basic { username, password } => {
+                let encoded = general_purpose::STANDARD.encode(format!("{username}:{password}"));
+                header.map(str::trim) == Some(format!("Basic {encoded}").as_str())
+            }
+            Auth::Bearer(token) => {
+                header.map(str::trim) == Some(format!("Bearer {token}").as_str())
+            }
+        }
+    }
+}
+
+/// A git HTTP server serving fixture repositories from a temporary directory.
+///
+/// The server stops, and its repositories are removed, when it's dropped.
+pub struct GitServer {
+    root: TempDir,
+    address: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl GitServer {
+    /// Start serving on a random port of the loopback interface, requiring the provided auth.
+    pub async fn start(auth: Auth) -> Self {
+        let root = tempfile::tempdir().expect("must create temp dir");
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("must bind git server");
+        let address = listener.local_addr().expect("must read git server address");
+
+        let project_root = root.path().to_path_buf();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let project_root = project_root.clone();
+                let auth = auth.clone();
+                tokio::spawn(async move {
+                    // Failures are seen by the client as a broken connection, which tests report.
+                    let _ = serve(stream, &project_root, &auth).await;
+                });
+            }
+        });
+
+        Self {
+            root,
+            address,
+            task,
+        }
+    }
+
+    /// The URL from which the named repository is cloned.
+    pub fn url(&self, name: &str) -> String {
+        format!("http://{}/{name}", self.address)
+    }
+
+    /// Create an empty repository with the provided name, served at [`GitServer::url`].
+    pub fn repository(&self, name: &str) -> Fixture {
+        let path = self.root.path().join(name);
+        std::fs::create_dir_all(&path).expect("must create fixture repository");
+        let fixture = Fixture { path };
+        fixture.git(&["-c", "init.defaultBranch=main", "init", "--quiet"]);
+        fixture
+    }
+}
+
+impl Drop for GitServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A repository served by a [`GitServer`].
+///
+/// Commits are authored by the same fixed identity so that tests don't depend on the git config of the host.
+pub struct Fixture {
+    path: PathBuf,
+}
+
+impl Fixture {
+    /// Write a file relative to the root of the repository, to be included in the next commit.
+    pub fn write(&self, path: &str, content: &str) -> &Self {
+        let path = self.path.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).expect("must create fixture directory");
+        }
+        std::fs::write(path, content).expect("must write fixture file");
+        self
+    }
+
+    /// Commit all changes to the current branch, returning the ID of the commit.
+    pub fn commit(&self, message: &str) -> String {
+        self.git(&["add", "--all"]);
+        self.git(&["commit", "--quiet", "--allow-empty", "-m", message]);
+        self.git(&["rev-parse", "HEAD"])
+    }
+
+    /// Create a branch at the current commit and switch to it.
+    pub fn branch(&self, name: &str) -> &Self {
+        self.git(&["checkout", "--quiet", "-b", name]);
+        self
+    }
+
+    /// Switch to an existing branch.
+    pub fn checkout(&self, name: &str) -> &Self {
+        self.git(&["checkout", "--quiet", name]);
+        self
+    }
+
+    /// Create a lightweight tag at the current commit.
+    pub fn tag(&self, name: &str) -> &Self {
+        self.git(&["tag", name]);
+        self
+    }
+
+    /// Create an annotated tag at the current commit, returning the ID of the tag object.
+    pub fn annotated_tag(&self, name: &str, message: &str) -> String {
+        self.git(&["tag", "-a", name, "-m", message]);
+        self.git(&["rev-parse", name])
+    }
+
+    /// Run git in the repository, returning its trimmed stdout.
+    #[track_caller]
+    pub fn git(&self, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .args(["-c", "commit.gpgsign=false", "-c", "tag.gpgsign=false"])
+            .args(args)
+            .current_dir(&self.path)
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .output()
+            .expect("must run git");
+        assert!(
+            output.status.success(),
+            "git {args:?} must succeed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+}
+
+/// Answer the single request made on the connection.
+async fn serve(stream: TcpStream, project_root: &Path, auth: &Auth) -> std::io::Result<()> {
+    let mut stream = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(candidate, _)| candidate == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    if !auth.accepts(header("authorization")) {
+        let response = "HTTP/1.1 401 Unauthorized\r\n\
+            WWW-Authenticate: Basic realm=\"fixtures\"\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\r\n";
+        return stream.get_mut().write_all(response.as_bytes()).await;
+    }
+
+    if header("expect").map(|value| value.eq_ignore_ascii_case("100-continue")) == Some(true) {
+        let response = "HTTP/1.1 100 Continue\r\n\r\n";
+        stream.get_mut().write_all(response.as_bytes()).await?;
+    }
+
+    let body = if header("transfer-encoding") == Some("chunked") {
+        read_chunked(&mut stream).await?
+    } else {
+        let length = header("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).await?;
+        body
+    };
+
+    let mut backend = Command::new("git")
+        .arg("http-backend")
+        .env("GIT_PROJECT_ROOT", project_root)
+        .env("GIT_HTTP_EXPORT_ALL", "1")
+        .env("REQUEST_METHOD", &method)
+        .env("PATH_INFO", path)
+        .env("QUERY_STRING", query)
+        .env("CONTENT_TYPE", header("content-type").unwrap_or_default())
+        .env("CONTENT_LENGTH", body.len().to_string())
+        .env("GIT_PROTOCOL", header("git-protocol").unwrap_or_default())
+        .env(
+            "HTTP_CONTENT_ENCODING",
+            header("content-encoding").unwrap_or_default(),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // Write the body while the output is read, so that neither side blocks on a full pipe.
+    let mut stdin = backend.stdin.take().expect("stdin must be piped");
+    let input = tokio::spawn(async move { stdin.write_all(&body).await });
+    let output = backend.wait_with_output().await?;
+    let _ = input.await;
+
+    let response = cgi_response(&output.stdout);
+    stream.get_mut().write_all(&response).await?;
+    stream.get_mut().shutdown().await
+}
+
+/// Read a body sent with `Transfer-Encoding: chunked`, which git uses for large requests.
+async fn read_chunked(stream: &mut BufReader<TcpStream>) -> std::io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let mut size = String::new();
+        stream.read_line(&mut size).await?;
+        let size = size.trim().split(';').next().unwrap_or_default();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+        let mut chunk = vec![0; size + 2];
+        stream.read_exact(&mut chunk).await?;
+        if size == 0 {
+            return Ok(body);
+        }
+        body.extend_from_slice(&chunk[..size]);
+    }
+}
+
+/// Convert the output of a CGI program into an HTTP response.
+///
+/// The CGI `Status` header becomes the status of the response, which is otherwise `200 OK`.
+fn cgi_response(output: &[u8]) -> Vec<u8> {
+    let split = output
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|at| (at, at + 4))
+        .or_else(|| {
+            output
+                .windows(2)
+                .position(|window| window == b"\n\n")
+                .map(|at| (at, at + 2))
+        });
+    let (headers, body) = match split {
+        Some((end, start)) => (String::from_utf8_lossy(&output[..end]), &output[start..]),
+        None => (Default::default(), output),
+    };
+
+    let mut status = String::from("200 OK");
+    let mut response_headers = String::new();
+    for line in headers.lines() {
+        match line.split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("status") => {
+                status = value.trim().to_string();
+            }
+            Some(_) => {
+                response_headers.push_str(line.trim_end());
+                response_headers.push_str("\r\n");
+            }
+            None => {}
+        }
+    }
+
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\n{response_headers}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
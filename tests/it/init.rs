@@ -45,3 +45,33 @@ async fn when_config_files_exist_only_overwrites_example() {
         .starts_with("# This config file is read whenever broker starts, and contains all of the information that broker needs in order to work.")
     );
 }
+
+#[tokio::test]
+async fn writes_examples_for_data_root() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let tmpdir = PathBuf::from(tmpdir.path());
+    let examples = broker::cmd::init::examples(&tmpdir).expect("should write examples");
+    assert_eq!(examples, tmpdir.join("examples"));
+
+    let config = tmpdir.join("config.yml");
+    for name in [
+        "broker.service",
+        "broker-service.xml",
+        "docker-compose.yml",
+        "github-actions.yml",
+    ] {
+        let example = fs::read_to_string(examples.join(name)).expect("should read example");
+        assert!(
+            example.contains(&tmpdir.display().to_string()),
+            "{name} should reference the data root"
+        );
+        assert!(
+            !example.contains("{data_root}"),
+            "{name} should be rendered"
+        );
+        assert!(!example.contains("{broker}"), "{name} should be rendered");
+    }
+
+    let unit = fs::read_to_string(examples.join("broker.service")).expect("should read unit");
+    assert!(unit.contains(&format!("--config-file-path \"{}\"", config.display())));
+}
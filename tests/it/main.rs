@@ -14,10 +14,12 @@ mod helper;
 
 mod api_code;
 mod args;
+mod audit;
 mod binary;
 mod config;
 mod db;
 mod debug;
+mod facade;
 mod fix;
 mod fossa_cli;
 mod init;
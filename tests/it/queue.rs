@@ -1,4 +1,4 @@
-use broker::queue::Queue;
+use broker::queue::{Queue, MAX_DELIVERIES};
 
 #[tokio::test]
 async fn echo() {
@@ -15,7 +15,7 @@ async fn echo() {
     // Receive the messages
     let mut messages = Vec::new();
     for i in 0..3 {
-        let msg = queue.recv().await.expect("must receive");
+        let msg = queue.recv().await.expect("must receive").commit();
         println!("rx {i}: '{msg}'");
         messages.push(msg);
     }
@@ -30,3 +30,51 @@ async fn echo() {
         ]
     );
 }
+
+#[tokio::test]
+async fn redelivers_until_committed() {
+    let queue = Queue::default();
+    queue.send(&String::from("msg")).await.expect("must send");
+
+    // Dropping a guard without committing it redelivers the item, up to the maximum number of deliveries.
+    for delivery in 1..=MAX_DELIVERIES {
+        let msg = queue.recv().await.expect("must receive");
+        assert_eq!(*msg, "msg");
+        assert_eq!(msg.deliveries(), delivery);
+        assert_eq!(msg.is_last_delivery(), delivery == MAX_DELIVERIES);
+    }
+    assert!(
+        queue.try_recv().is_none(),
+        "must discard after max deliveries"
+    );
+
+    // Committed items aren't redelivered.
+    queue.send(&String::from("msg")).await.expect("must send");
+    let msg = queue.recv().await.expect("must receive");
+    assert_eq!(msg.commit(), "msg");
+    assert!(
+        queue.try_recv().is_none(),
+        "must not redeliver committed items"
+    );
+}
+
+#[tokio::test]
+async fn redelivers_when_full() {
+    let queue = Queue::new(1);
+    queue.send(&String::from("first")).await.expect("must send");
+    let first = queue.recv().await.expect("must receive");
+
+    // Another item fills the queue before the first is put back, so the first is held aside and delivered first.
+    queue
+        .send(&String::from("second"))
+        .await
+        .expect("must send");
+    drop(first);
+
+    let first = queue.recv().await.expect("must receive");
+    assert_eq!(*first, "first");
+    assert_eq!(first.deliveries(), 2);
+    first.commit();
+    let second = queue.recv().await.expect("must receive");
+    assert_eq!(second.commit(), "second");
+}
@@ -1,7 +1,9 @@
 //! Tests for git remotes
+use crate::helper::git_server::{Auth, GitServer};
 use crate::{assert_error_stack_snapshot, guard_integration_test, load_config};
-use broker::api::remote::{Reference, RemoteProvider};
+use broker::api::remote::{git::transport::Transport, Reference, Remote, RemoteProvider, ScanMode};
 
+use broker::api::http;
 use broker::ext::secrecy::REDACTION_LITERAL;
 use broker::{self, api::remote::git};
 
@@ -26,6 +28,30 @@ async fn references_on_public_repo_with_no_auth() {
     assert!(!references.is_empty());
 }
 
+#[tokio::test]
+async fn scanned_references_on_public_repo_with_no_auth() {
+    guard_integration_test!();
+
+    let (_, conf) = load_config!(
+        "testdata/config/fossa-one-http-no-auth.yml",
+        "testdata/database/empty.sqlite"
+    )
+    .await;
+
+    let mut integrations = conf.integrations().as_ref().iter();
+    let integration = integrations
+        .next()
+        .expect("no integration loaded from config");
+    let references = integration
+        .scanned_references()
+        .await
+        .expect("must list scanned references on a public repo");
+    assert!(!references.is_empty());
+    assert!(references
+        .iter()
+        .all(|reference| integration.scans_reference(reference.branch())));
+}
+
 #[tokio::test]
 async fn references_on_private_repo_with_no_auth() {
     guard_integration_test!();
@@ -64,16 +90,68 @@ async fn clone_public_repo_with_no_auth() {
         .next()
         .expect("no integration loaded from config");
 
-    let reference = Reference::Git(git::Reference::new_tag(
-        "master".to_string(),
-        "onetwothree".to_string(),
-    ));
+    // Clones are verified against the commit listed for the reference, so it must be real.
+    let reference = integration
+        .references()
+        .await
+        .expect("must list references on a public repo")
+        .into_iter()
+        .next()
+        .expect("public repo must have references");
     integration
         .clone_reference(&reference)
         .await
         .expect("no path returned from clone_branch_or_tag on a public repo!");
 }
 
+#[tokio::test]
+async fn clone_manifests_public_repo_with_no_auth() {
+    guard_integration_test!();
+
+    let (_, conf) = load_config!(
+        "testdata/config/fossa-one-http-no-auth.yml",
+        "testdata/database/empty.sqlite"
+    )
+    .await;
+
+    let mut integrations = conf.integrations().as_ref().iter();
+    let integration = integrations
+        .next()
+        .expect("no integration loaded from config");
+
+    // Clones are verified against the commit listed for the reference, so it must be real.
+    let reference = integration
+        .references()
+        .await
+        .expect("must list references on a public repo")
+        .into_iter()
+        .next()
+        .expect("public repo must have references");
+    let cloned = integration
+        .protocol()
+        .clone_manifests(&reference)
+        .await
+        .expect("no path returned from clone_manifests on a public repo!");
+
+    let manifests = ScanMode::MANIFESTS
+        .iter()
+        .map(|pattern| glob::Pattern::new(pattern).expect("manifest pattern must be valid"))
+        .collect::<Vec<_>>();
+    let files = walkdir::WalkDir::new(cloned.path())
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file());
+    for file in files {
+        let name = file.file_name().to_string_lossy();
+        assert!(
+            manifests.iter().any(|pattern| pattern.matches(&name)),
+            "'{}' must not be checked out",
+            file.path().display()
+        );
+    }
+}
+
 #[tokio::test]
 async fn clone_private_repo_with_no_auth() {
     guard_integration_test!();
@@ -185,3 +263,218 @@ async fn redacts_auth_http_header() {
     // Finally, snapshot for stability.
     assert_error_stack_snapshot!(&context, err);
 }
+
+#[tokio::test]
+async fn references_over_http_with_no_auth() {
+    let server = GitServer::start(Auth::None).await;
+    let repo = server.repository("fixture");
+    let main = repo.commit("one");
+    repo.tag("v1");
+    let feature = repo.branch("feature").commit("two");
+    repo.annotated_tag("v2", "two");
+    repo.checkout("main");
+
+    let transport = Transport::new_http(Remote::new(server.url("fixture")), None);
+    let references = git::repository::list_references(&transport)
+        .await
+        .expect("must list references");
+
+    // Annotated tags are listed with the commit they point to.
+    for expected in [
+        git::Reference::new_branch("main".to_string(), main.clone()),
+        git::Reference::new_branch("feature".to_string(), feature.clone()),
+        git::Reference::new_tag("v1".to_string(), main),
+        git::Reference::new_tag("v2".to_string(), feature),
+    ] {
+        assert!(
+            references.contains(&expected),
+            "references {references:?} must contain {expected:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn clone_over_http_with_basic_auth() {
+    let server = GitServer::start(Auth::Basic {
+        username: "some_user".to_string(),
+        password: "some_password".to_string(),
+    })
+    .await;
+    let repo = server.repository("fixture");
+    let commit = repo.write("README.md", "fixture").commit("one");
+
+    let auth = http::Auth::new_basic("some_user".to_string().into(), "some_password".into());
+    let transport = Transport::new_http(Remote::new(server.url("fixture")), Some(auth));
+    let reference = git::Reference::new_branch("main".to_string(), commit);
+    let cloned = git::repository::clone_reference(&transport, &reference)
+        .await
+        .expect("must clone reference");
+
+    let readme =
+        std::fs::read_to_string(cloned.path().join("README.md")).expect("must read file in clone");
+    assert_eq!(readme, "fixture");
+}
+
+#[tokio::test]
+async fn clone_over_http_with_header_auth() {
+    let server = GitServer::start(Auth::Bearer("some_token".to_string())).await;
+    let repo = server.repository("fixture");
+    let commit = repo.write("README.md", "fixture").commit("one");
+
+    let auth = http::Auth::new_header("Authorization: Bearer some_token".into());
+    let transport = Transport::new_http(Remote::new(server.url("fixture")), Some(auth));
+    let reference = git::Reference::new_branch("main".to_string(), commit);
+    let cloned = git::repository::clone_reference(&transport, &reference)
+        .await
+        .expect("must clone reference");
+
+    assert!(cloned.path().join("README.md").exists());
+}
+
+#[tokio::test]
+async fn redacts_rejected_auth_over_http() {
+    let server = GitServer::start(Auth::Basic {
+        username: "some_user".to_string(),
+        password: "correct_password".to_string(),
+    })
+    .await;
+    let repo = server.repository("fixture");
+    let commit = repo.commit("one");
+
+    let auth = http::Auth::new_basic("some_user".to_string().into(), "some_password".into());
+    let transport = Transport::new_http(Remote::new(server.url("fixture")), Some(auth));
+    let reference = git::Reference::new_branch("main".to_string(), commit);
+    let err = git::repository::clone_reference(&transport, &reference)
+        .await
+        .expect_err("must reject the wrong password");
+
+    // Ensure it doesn't contain our auth values, in plain text or as sent in the header.
+    let printed = format!("{err:#}");
+    for secret in ["some_password", "c29tZV91c2VyOnNvbWVfcGFzc3dvcmQ="] {
+        assert!(
+            !printed.contains(secret),
+            "error '{printed}' must not contain auth"
+        );
+    }
+
+    // Ensure it tried to print them but they were redacted.
+    assert!(
+        printed.contains(REDACTION_LITERAL),
+        "error '{printed}' must have redacted auth"
+    );
+}
+
+#[tokio::test]
+async fn contributors_from_recent_commits() {
+    let root = tempfile::tempdir().expect("must create temp dir");
+    let run = |args: &[&str], email: &str| {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(root.path())
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", email)
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", email)
+            .status()
+            .expect("must run git");
+        assert!(status.success(), "git {args:?} must succeed");
+    };
+
+    run(&["init", "--quiet"], "");
+    run(
+        &["commit", "--allow-empty", "-m", "one"],
+        "jessica@example.com",
+    );
+    run(
+        &["commit", "--allow-empty", "-m", "two"],
+        "Zach@Example.com",
+    );
+    run(
+        &["commit", "--allow-empty", "-m", "three"],
+        "jessica@example.com",
+    );
+
+    let contributors = git::repository::contributors(root.path())
+        .await
+        .expect("must list contributors");
+    let emails = contributors.as_ref().keys().collect::<Vec<_>>();
+    assert_eq!(emails, vec!["jessica@example.com", "zach@example.com"]);
+}
+
+#[tokio::test]
+async fn verifies_cloned_head() {
+    let root = tempfile::tempdir().expect("must create temp dir");
+    let run = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(root.path())
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .output()
+            .expect("must run git");
+        assert!(output.status.success(), "git {args:?} must succeed");
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+
+    run(&["init", "--quiet"]);
+    run(&["commit", "--allow-empty", "-m", "one"]);
+    let one = run(&["rev-parse", "HEAD"]);
+    run(&["commit", "--allow-empty", "-m", "two"]);
+    run(&["tag", "-a", "v1", "-m", "v1"]);
+    let head = run(&["rev-parse", "HEAD"]);
+    let tag = run(&["rev-parse", "v1"]);
+
+    let branch = git::Reference::new_branch("main".to_string(), head);
+    git::repository::verify_head(root.path(), &branch)
+        .await
+        .expect("must accept the listed commit");
+
+    // Annotated tags may be listed with the ID of the tag object.
+    let annotated = git::Reference::new_tag("v1".to_string(), tag);
+    git::repository::verify_head(root.path(), &annotated)
+        .await
+        .expect("must accept the tag object of the listed commit");
+
+    let stale = git::Reference::new_branch("main".to_string(), one);
+    let err = git::repository::verify_head(root.path(), &stale)
+        .await
+        .expect_err("must reject a different commit");
+    assert!(matches!(
+        err.current_context(),
+        git::repository::Error::UnexpectedHead { .. }
+    ));
+}
+
+#[test]
+fn peels_annotated_tags() {
+    let output = [
+        "9e9834e875bcc07745495b05fe7e73d85d8962b9\tHEAD",
+        "9e9834e875bcc07745495b05fe7e73d85d8962b9\trefs/heads/main",
+        "b72eb52c09df108c81e755bc3a083ce56d7e4197\trefs/tags/v0.0.1",
+        "ffb878b5eb456e7e1725606192765dcb6c7e78b8\trefs/tags/v0.0.1^{}",
+        "dc575604056303cb16131c1e468077392470b1a6\trefs/tags/v0.0.2",
+    ]
+    .join("\n");
+
+    let references = git::repository::references_from_ls_remote(output);
+    assert_eq!(
+        references,
+        vec![
+            git::Reference::new_branch(
+                "main".to_string(),
+                "9e9834e875bcc07745495b05fe7e73d85d8962b9".to_string()
+            ),
+            git::Reference::Tag {
+                name: "v0.0.1".to_string(),
+                commit: "ffb878b5eb456e7e1725606192765dcb6c7e78b8".to_string(),
+                object: Some("b72eb52c09df108c81e755bc3a083ce56d7e4197".to_string()),
+            },
+            git::Reference::new_tag(
+                "v0.0.2".to_string(),
+                "dc575604056303cb16131c1e468077392470b1a6".to_string()
+            ),
+        ]
+    );
+}